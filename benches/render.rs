@@ -143,12 +143,201 @@ fn bench_pdf_creation(c: &mut Criterion) {
     });
 }
 
+/// Benchmark loading a large photo that is drawn small: a full decode to
+/// learn the size plus a second full decode to embed it, against a header
+/// probe plus one DCT-scaled decode
+fn bench_large_image(c: &mut Criterion) {
+    use image::codecs::jpeg::{JpegDecoder, JpegEncoder};
+    use std::io::Cursor;
+
+    let (width, height) = (4000, 3000);
+    let pixels = image::RgbImage::from_fn(width, height, |x, y| image::Rgb([(x % 256) as u8, (y % 256) as u8, 128]));
+    let mut jpeg = Vec::new();
+    JpegEncoder::new_with_quality(&mut jpeg, 85)
+        .encode(pixels.as_raw(), width, height, image::ColorType::Rgb8)
+        .unwrap();
+    // Three inches wide at 300 DPI
+    let (target_w, target_h) = (900, 675);
+
+    let mut group = c.benchmark_group("large_jpeg_12mp");
+    group.sample_size(10);
+    group.bench_function("decode_twice", |b| {
+        b.iter(|| {
+            let probe = image::load_from_memory(black_box(&jpeg)).unwrap();
+            black_box((probe.width(), probe.height()));
+            let img = image::load_from_memory(black_box(&jpeg)).unwrap();
+            black_box(img.resize(target_w, target_h, image::imageops::FilterType::Lanczos3))
+        })
+    });
+    group.bench_function("probe_and_scaled_decode", |b| {
+        b.iter(|| {
+            let dims = image::io::Reader::new(Cursor::new(black_box(&jpeg)))
+                .with_guessed_format()
+                .unwrap()
+                .into_dimensions()
+                .unwrap();
+            black_box(dims);
+            let mut decoder = JpegDecoder::new(Cursor::new(black_box(&jpeg))).unwrap();
+            decoder.scale(target_w as u16, target_h as u16).unwrap();
+            let img = image::DynamicImage::from_decoder(decoder).unwrap();
+            black_box(img.resize(target_w, target_h, image::imageops::FilterType::Lanczos3))
+        })
+    });
+    group.finish();
+}
+
+/// Benchmark 1,000 label pages drawing the same 20 strings: resolving,
+/// measuring and encoding every draw, against a memo keyed by string
+fn bench_repeated_strings(c: &mut Criterion) {
+    use std::collections::HashMap;
+
+    // Mirror LoadedFont: cmap and advance widths in hash maps
+    let data = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/IBMPlexSans-Regular.otf")).unwrap();
+    let face = ttf_parser::Face::parse(&data, 0).unwrap();
+    let mut cmap: HashMap<char, u16> = HashMap::new();
+    let mut widths: HashMap<u16, u16> = HashMap::new();
+    for ch in (' '..='~').chain('\u{a0}'..='\u{17f}') {
+        if let Some(gid) = face.glyph_index(ch) {
+            cmap.insert(ch, gid.0);
+            widths.insert(gid.0, face.glyph_hor_advance(gid).unwrap_or(0));
+        }
+    }
+    let units_per_em = face.units_per_em() as f32;
+    let strings: Vec<String> = (0..20).map(|i| format!("NET WT {} oz — BATCH {:04} · Acme Foods Ltd., Springfield", i, i * 37)).collect();
+    let shape = |text: &str| -> (Vec<u8>, f32) {
+        let mut bytes = Vec::with_capacity(text.len() * 2);
+        let mut width = 0.0;
+        for ch in text.chars() {
+            let Some(&gid) = cmap.get(&ch) else { continue };
+            bytes.extend_from_slice(&gid.to_be_bytes());
+            width += widths.get(&gid).copied().unwrap_or(0) as f32 * 10.0 / units_per_em;
+        }
+        (bytes, width)
+    };
+
+    let mut group = c.benchmark_group("label_strings_1000_pages");
+    group.sample_size(10);
+    group.bench_function("shape_every_draw", |b| {
+        b.iter(|| {
+            for _ in 0..1000 {
+                for s in &strings {
+                    black_box(shape(black_box(s)));
+                }
+            }
+        })
+    });
+    group.bench_function("memoized", |b| {
+        b.iter(|| {
+            let mut memo: HashMap<String, (Vec<u8>, f32)> = HashMap::new();
+            for _ in 0..1000 {
+                for s in &strings {
+                    if let Some(shaped) = memo.get(black_box(s.as_str())) {
+                        black_box(shaped);
+                        continue;
+                    }
+                    let shaped = shape(s);
+                    black_box(&shaped);
+                    memo.insert(s.clone(), shaped);
+                }
+            }
+        })
+    });
+    group.finish();
+}
+
+/// Benchmark the first pass over 2,000 pages of 50 text elements:
+/// registering every char of every draw under freshly allocated alias keys,
+/// against registering each distinct string once with an ASCII glyph table
+fn bench_glyph_collection(c: &mut Criterion) {
+    use std::collections::{BTreeMap, HashMap, HashSet};
+
+    // Mirror FontEmbedder: used glyph ids and the char -> glyph map
+    #[derive(Default)]
+    struct Embedder {
+        used: HashSet<u16>,
+        chars: BTreeMap<char, u16>,
+        ascii: Option<[Option<u16>; 128]>,
+    }
+    impl Embedder {
+        fn use_glyph(&mut self, ch: char, gid: u16) {
+            if let Some(seen) = self.ascii.as_mut().and_then(|ascii| ascii.get_mut(ch as usize)) {
+                if *seen == Some(gid) {
+                    return;
+                }
+                *seen = Some(gid);
+            }
+            self.used.insert(gid);
+            self.chars.insert(ch, gid);
+        }
+    }
+
+    let data = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/IBMPlexSans-Regular.otf")).unwrap();
+    let face = ttf_parser::Face::parse(&data, 0).unwrap();
+    let words = ["NET WT", "BATCH", "Acme Foods Ltd.", "Springfield", "Total", "Invoice", "Qty", "Price"];
+    let pages: Vec<Vec<String>> = (0..2000)
+        .map(|p| {
+            (0..50)
+                .map(|i| if i % 5 == 0 { format!("Page {} line {}", p, i) } else { format!("{} {}", words[i % 8], i) })
+                .collect()
+        })
+        .collect();
+    // Resolution is memoized per string either way; only registration differs
+    let mut resolved: HashMap<&str, Vec<(char, u16)>> = HashMap::new();
+    for text in pages.iter().flatten() {
+        resolved.entry(text.as_str()).or_insert_with(|| {
+            text.chars().filter_map(|ch| face.glyph_index(ch).map(|gid| (ch, gid.0))).collect()
+        });
+    }
+
+    let mut group = c.benchmark_group("glyph_collection_2000_pages");
+    group.sample_size(10);
+    group.bench_function("register_every_draw", |b| {
+        b.iter(|| {
+            let mut alias_to_ps: HashMap<String, String> = HashMap::new();
+            let mut embedders: HashMap<String, Embedder> = HashMap::new();
+            for text in pages.iter().flatten() {
+                for &(ch, gid) in &resolved[text.as_str()] {
+                    alias_to_ps.entry("body".to_string()).or_insert_with(|| "IBMPlexSans".to_string());
+                    embedders.entry("body".to_string()).or_default().use_glyph(ch, gid);
+                }
+            }
+            black_box((alias_to_ps, embedders))
+        })
+    });
+    group.bench_function("register_unique_strings", |b| {
+        b.iter(|| {
+            let mut alias_to_ps: HashMap<String, String> = HashMap::new();
+            let mut embedders: HashMap<String, Embedder> = HashMap::new();
+            let mut registered: HashSet<*const Vec<(char, u16)>> = HashSet::new();
+            for text in pages.iter().flatten() {
+                let chars = &resolved[text.as_str()];
+                if !registered.insert(chars) {
+                    continue;
+                }
+                if !embedders.contains_key("body") {
+                    alias_to_ps.insert("body".to_string(), "IBMPlexSans".to_string());
+                    embedders.insert("body".to_string(), Embedder { ascii: Some([None; 128]), ..Default::default() });
+                }
+                let embedder = embedders.get_mut("body").unwrap();
+                for &(ch, gid) in chars {
+                    embedder.use_glyph(ch, gid);
+                }
+            }
+            black_box((alias_to_ps, embedders))
+        })
+    });
+    group.finish();
+}
+
 criterion_group!(
     benches,
     bench_color_operations,
     bench_compression,
     bench_content_stream,
     bench_pdf_creation,
+    bench_large_image,
+    bench_repeated_strings,
+    bench_glyph_collection,
 );
 
 criterion_main!(benches);
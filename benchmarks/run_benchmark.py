@@ -54,6 +54,19 @@ def get_font_path() -> Optional[str]:
     return None
 
 
+def get_large_jpeg() -> Optional[bytes]:
+    """Encode a 12 MP JPEG with Pillow, if it is installed."""
+    try:
+        from io import BytesIO as _BytesIO
+        from PIL import Image
+    except ImportError:
+        return None
+    img = Image.linear_gradient("L").resize((4000, 3000)).convert("RGB")
+    buf = _BytesIO()
+    img.save(buf, format="JPEG", quality=85)
+    return buf.getvalue()
+
+
 def benchmark(func: Callable, iterations: int = 10) -> Tuple[float, float, float]:
     """Run benchmark and return (min, avg, max) times in ms."""
     times = []
@@ -169,6 +182,37 @@ def rupdf_multi_page(font_path: str) -> bytes:
     return rupdf.render_pdf(doc)
 
 
+LABEL_STRINGS = [f"NET WT {i} oz - BATCH {i * 37:04d}" for i in range(20)]
+
+
+def rupdf_label_run(font_path: str) -> bytes:
+    """Benchmark: 1,000 label pages repeating the same 20 strings."""
+    elements = [
+        {"type": "text", "x": 10, "y": 12 + i * 10, "text": s, "font": "f", "size": 8}
+        for i, s in enumerate(LABEL_STRINGS)
+    ]
+    pages = [{"size": (288, 216), "elements": elements} for _ in range(1000)]
+    doc = {
+        "pages": pages,
+        "resources": {"fonts": {"f": {"path": font_path}}},
+    }
+    return rupdf.render_pdf(doc)
+
+
+def rupdf_large_image(jpeg: bytes) -> bytes:
+    """Benchmark: 12 MP photo drawn three inches wide."""
+    doc = {
+        "pages": [{
+            "size": (612, 792),
+            "elements": [
+                {"type": "image", "x": 72, "y": 72, "w": 216, "image_ref": "photo"},
+            ],
+        }],
+        "resources": {"images": {"photo": {"bytes": jpeg}}},
+    }
+    return rupdf.render_pdf(doc)
+
+
 # ============================================================================
 # ReportLab benchmarks (for comparison)
 # ============================================================================
@@ -248,7 +292,13 @@ def run_benchmarks(iterations: int = 10):
         ("rupdf: graphics heavy (100 rects)", lambda: rupdf_graphics_heavy()),
         ("rupdf: mixed content", lambda: rupdf_mixed_content(font_path)),
         ("rupdf: multi-page (10 pages)", lambda: rupdf_multi_page(font_path)),
+        ("rupdf: label run (1000 pages)", lambda: rupdf_label_run(font_path)),
     ]
+    jpeg = get_large_jpeg()
+    if jpeg is not None:
+        benchmarks.append(("rupdf: large image (12 MP JPEG)", lambda: rupdf_large_image(jpeg)))
+    else:
+        print("Note: Pillow not installed, skipping the large image benchmark.\n")
 
     for name, func in benchmarks:
         min_t, avg_t, max_t = benchmark(func, iterations)
@@ -2,9 +2,37 @@
 
 from importlib.metadata import PackageNotFoundError, version as _pkg_version
 
-from rupdf._rupdf import render_pdf, RupdfError
+from rupdf._rupdf import (
+    render_pdf,
+    render_pdf_with_index,
+    validate_document,
+    render_layout_fingerprint,
+    rasterize_page,
+    preflight,
+    inspect_font,
+    font_covers,
+    measure_barcode,
+    extract_content,
+    PdfRenderJob,
+    RupdfError,
+    RupdfWarning,
+)
 
-__all__ = ["render_pdf", "RupdfError"]
+__all__ = [
+    "render_pdf",
+    "render_pdf_with_index",
+    "validate_document",
+    "render_layout_fingerprint",
+    "rasterize_page",
+    "preflight",
+    "inspect_font",
+    "font_covers",
+    "measure_barcode",
+    "extract_content",
+    "PdfRenderJob",
+    "RupdfError",
+    "RupdfWarning",
+]
 
 try:
     __version__ = _pkg_version("rupdf")
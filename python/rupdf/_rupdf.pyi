@@ -1,10 +1,45 @@
 """Type stubs for rupdf._rupdf native module."""
 
-from typing import Dict, List, Literal, Tuple, TypedDict, Union
+from typing import Any, Dict, List, Literal, Optional, Sequence, Tuple, TypedDict, Union, overload
 
 # Type aliases for colors and coordinates
-Color = Tuple[int, int, int, int]  # RGBA (0-255 each)
-Size = Tuple[float, float]  # (width, height) in points
+RGBA = Tuple[int, int, int, int]  # 0-255 each
+RGB = Tuple[int, int, int]  # 0-255 each, alpha 255
+CMYKA = Tuple[float, float, float, float, int]  # c, m, y, k 0-1; alpha 0-255
+
+class CMYKColor(TypedDict, total=False):
+    cmyk: Tuple[float, float, float, float]  # required, 0-1 each
+    a: int  # 0-255, default 255
+
+class SpotColor(TypedDict, total=False):
+    spot: str  # ink name, required
+    alt: RGBA  # required; the color viewers show at full strength
+    tint: float  # 0-1, default 1.0
+
+# Strings are "#RRGGBB", "#RRGGBBAA", a CSS color name or a palette name
+Color = Union[RGBA, RGB, str, CMYKA, CMYKColor, SpotColor]
+class RelativeLength(TypedDict, total=False):
+    """Fraction of the page or its content area; set exactly one of x, y, w, h."""
+
+    rel: Literal["page", "content"]  # default "content"
+    x: float  # position from the area's left edge
+    y: float  # position from the area's top edge
+    w: float  # share of the area's width
+    h: float  # share of the area's height
+
+# A number in the document's `units`, or a string with a unit suffix
+# ("25mm", "1in", "12pt", "300px"). Element lengths may also be relative
+# to their page: a keyword ("margin_left", "content_width", ...), a
+# percentage of the page ("50%") or a RelativeLength.
+Length = Union[float, str, RelativeLength]
+Size = Tuple[Length, Length]  # (width, height)
+
+
+class PageMargins(TypedDict, total=False):
+    top: Length  # default 0
+    right: Length  # default 0
+    bottom: Length  # default 0
+    left: Length  # default 0
 
 # Alignment types
 HAlign = Literal["left", "center", "right"]
@@ -12,92 +47,458 @@ VAlign = Literal["top", "center", "bottom"]
 VerticalAnchor = Literal["baseline", "capline", "center"]
 TextAlignY = Literal["top", "capline", "center", "baseline", "bottom"]
 
+# Stroke styles
+LineCap = Literal["butt", "round", "square"]
+LineJoin = Literal["miter", "round", "bevel"]
+
 
 MissingGlyphPolicy = Literal["drop", "raise"]
 
 
 class TextElement(TypedDict, total=False):
     type: Literal["text"]
-    x: float
-    y: float
-    text: str
+    id: str  # labels the element in errors and warnings; never drawn
+    artifact: bool  # decorative; marked so assistive technology skips it
+    reading_order: int  # order among the page's tagged content; not with artifact
+    z: int  # paint order among siblings, default 0; equal z keeps list order
+    visible: bool  # False skips the element when rendering; default True
+    x: Length
+    y: Length
+    text: Union[str, NumberText]
     font: str
+    # Instead of font: a Document.font_families entry and the face to use.
+    family: str
+    weight: Literal["regular", "bold"]  # default "regular"
+    style: Literal["normal", "italic"]  # default "normal"
     # Aliases of additional fonts in `resources.fonts`, tried in order for
     # characters absent from the primary font's cmap. Primary font drives
     # baseline / line-height / metrics; fallbacks contribute only glyphs.
     font_fallback: List[str]
     # Behavior when no font in the chain covers a character. Default "drop".
     missing_glyph_policy: MissingGlyphPolicy
-    size: float
+    # Alias of a color bitmap (sbix/CBDT) font for emoji the chain has no
+    # outline for; they are drawn inline as images.
+    emoji_font: str
+    # Text substituted for emoji that no font can draw.
+    emoji_replacement: str
+    size: Length
     color: Color
     align: HAlign
     vertical_anchor: VerticalAnchor
+    # Fill behind the glyphs, sized from the measured text and font metrics.
+    highlight_color: Color
+    highlight_padding: Length  # default 1pt
+    # Case change at render time; "smallcaps" draws lowercase letters as
+    # capitals at 75% size.
+    transform: Literal["none", "uppercase", "lowercase", "capitalize", "smallcaps"]
+    # Stretch or squeeze the line to an exact width.
+    fit_width: TextFit
+    # Hold the line to at most this width, about the align anchor; not
+    # combined with fit_width.
+    max_width: Length
+    # What happens past max_width: "clip" (default) cuts the glyphs off,
+    # "ellipsis" drops characters for a trailing "…", "shrink" lowers the
+    # size down to min_size (default size / 2) and clips beyond that.
+    overflow: Literal["clip", "ellipsis", "shrink"]
+    min_size: Length
+    # Shear in degrees about the anchor point on the baseline, highlight
+    # included; positive skew_x slants forward, positive skew_y raises the
+    # right side. Between -90 and 90.
+    skew_x: float
+    skew_y: float
+    # Counter-clockwise turn in degrees about the (x, y) anchor, highlight
+    # and max_width clip included. Applied after skew.
+    rotation: float
+    # Fill glyph outlines instead of showing the font, which then isn't
+    # embedded for this element. The text can't be selected or searched.
+    outline: bool
+    # Parse **bold** and {name}...{/name} spans against Document.styles.
+    markup: bool
+
+
+class FontFamily(TypedDict, total=False):
+    """Font aliases of a family's faces; see README "Font families"."""
+
+    regular: str
+    bold: str
+    italic: str
+    bold_italic: str
+
+
+class TextStyle(TypedDict, total=False):
+    """Named style for markup spans; see README "Inline markup"."""
+
+    font: str  # alias in resources.fonts; the chain's fallbacks still apply
+    color: Color  # alpha ignored; the element's applies
+
+
+class NumberFormatSpec(TypedDict, total=False):
+    """How a number is written; see README "Formatted numbers"."""
+
+    # Separators for a convention such as "en", "de_DE" or "fr"; the
+    # explicit keys below override it.
+    locale: str
+    decimals: int  # fixed places, rounded half away from zero; default: as given
+    thousands: str  # default: none
+    decimal: str  # default "."
+    prefix: str
+    suffix: str
+    negative: Literal["minus", "parentheses"]  # default "minus"
+
+
+class NumberText(TypedDict, total=False):
+    number: Union[int, float, str]  # also decimal.Decimal
+    format: NumberFormatSpec
+
+
+class TextFit(TypedDict, total=False):
+    """Target width for a text element; see README "Fit width"."""
+
+    width: Length  # required
+    # "tracking" (default) adds letter spacing; "scale" scales glyphs
+    # horizontally.
+    mode: Literal["tracking", "scale"]
+    # Limits on the fitted width as a ratio of the natural width.
+    min: float  # default 0.5
+    max: float  # default 2.0
+
+
+class TextSpan(TypedDict):
+    """Plain text span of rich text."""
+
+    text: str
+
+
+class InlineImageSpan(TypedDict, total=False):
+    """Image flowed with rich text, wrapped like a word."""
+
+    image_ref: str  # required
+    h: Length  # required; width follows the image's aspect ratio
+    baseline_shift: Length  # default 0; raises the image above the baseline
 
 
 class TextBoxElement(TypedDict, total=False):
     """Multi-line text with word wrapping within a fixed box."""
 
     type: Literal["textbox"]
-    x: float
-    y: float
-    w: float
-    h: float
-    text: str
+    id: str
+    artifact: bool
+    reading_order: int
+    z: int
+    visible: bool
+    x: Length
+    y: Length
+    w: Length
+    h: Length
+    text: Union[str, List[Union[str, TextSpan, InlineImageSpan]]]
     font: str
-    # See TextElement for fallback / policy semantics.
+    # See TextElement for family, fallback and policy semantics.
+    family: str
+    weight: Literal["regular", "bold"]
+    style: Literal["normal", "italic"]
     font_fallback: List[str]
     missing_glyph_policy: MissingGlyphPolicy
-    size: float
-    line_height: float  # defaults to size * 1.2
+    emoji_font: str
+    emoji_replacement: str
+    size: Length
+    line_height: Length  # defaults to size * line_height_ratio
+    line_height_ratio: float  # default 1.2; ignored when line_height is set
     color: Color
+    columns: int  # default 1; lines fill each column before the next
+    column_gap: Length  # default 12pt
+    balance: bool  # spread lines evenly across columns
     box_align_x: HAlign  # positions box relative to (x, y)
     box_align_y: VAlign  # positions box relative to (x, y)
     text_align_x: HAlign  # positions text within box
     text_align_y: TextAlignY  # positions text within box
+    markup: bool  # see TextElement
+
+
+class FillPattern(TypedDict, total=False):
+    style: Literal["diagonal", "cross", "horizontal", "vertical", "dots"]  # default: "diagonal"
+    spacing: Length  # between lines or dot centers, default 4pt
+    line_width: Length  # or dot diameter, default 0.5pt
+    color: Color  # alpha ignored; default black
 
 
 class RectElement(TypedDict, total=False):
     type: Literal["rect"]
-    x: float
-    y: float
-    w: float
-    h: float
-    stroke: float
+    id: str
+    artifact: bool
+    reading_order: int
+    z: int
+    visible: bool
+    x: Length
+    y: Length
+    w: Length
+    h: Length
+    stroke: Union[Length, Literal["hairline"]]  # 0 = no stroke
     stroke_color: Color
     fill_color: Color
-    corner_radius: float
+    fill_pattern: FillPattern  # hatching over fill_color
+    gradient: GradientSpec  # painted over fill_color, inside the outline
+    # one radius, or (top_left, top_right, bottom_right, bottom_left)
+    corner_radius: Union[Length, Tuple[Length, Length, Length, Length]]
+    corner_style: Literal["circular", "continuous"]  # default: "circular"
+    sides: List[Literal["top", "right", "bottom", "left"]]  # default: all
+    # dash/gap lengths (empty = solid), or a preset scaled to the stroke
+    dash: Union[List[Length], Literal["dashed", "dotted"]]
+    dash_phase: Length
+    miter_limit: float  # >= 1
+    cap: LineCap  # default "butt"; "square" where sides leaves edges open
+    join: LineJoin  # default "miter"
+    stroke_align: Literal["center", "inside", "outside"]  # default: "center"
+    skew_x: float  # degrees about the center, as for text
+    skew_y: float
+    rotation: float  # degrees clockwise, applied after skew
+    # "center" (default) or an (x, y) point from the rect's top-left corner
+    rotation_origin: Union[Literal["center"], Tuple[Union[float, str], Union[float, str]]]
+
+
+class CheckElement(TypedDict, total=False):
+    """Checkbox drawn from vector paths: a square plus a tick or cross."""
+
+    type: Literal["check", "checkbox"]
+    id: str
+    artifact: bool
+    reading_order: int
+    z: int
+    visible: bool
+    x: Length
+    y: Length
+    size: Length
+    state: Literal["unchecked", "checked", "crossed"]  # default "unchecked"
+    stroke: Length  # box border, default 1pt
+    stroke_color: Color
+    fill_color: Color
+    corner_radius: Length
+    mark_color: Color  # defaults to stroke_color
+    mark_stroke: Length  # defaults to size * 0.12
+
+
+class NoteElement(TypedDict, total=False):
+    """Sticky-note comment, written as a Text annotation with a popup."""
+
+    type: Literal["note"]
+    id: str
+    artifact: bool
+    reading_order: int
+    z: int
+    visible: bool
+    x: Length  # top left corner of the 20pt icon
+    y: Length
+    contents: str  # required
+    title: str  # author, shown in the popup title bar
+    color: Color  # default (255, 235, 0, 255)
+    icon: Literal["note", "comment", "help", "insert", "key", "paragraph", "new_paragraph"]
+    open: bool  # show the popup when the document opens
+
+
+class SignatureLineElement(TypedDict, total=False):
+    """Rule with an optional "X" above it and caption rows beneath."""
+
+    type: Literal["signature_line"]
+    id: str
+    artifact: bool
+    reading_order: int
+    z: int
+    visible: bool
+    x: Length  # left end of the rule
+    y: Length  # center line of the rule
+    w: Length
+    font: str  # required
+    label: str  # first caption row, e.g. the printed name
+    sublabels: List[str]  # next row, in equal columns across the width
+    size: Length  # default 9pt
+    color: Color  # rule and text, default black
+    stroke: Length  # rule thickness, default 0.75pt
+    mark: bool  # draw "X" above the left end, default False
 
 
 class LineElement(TypedDict, total=False):
     type: Literal["line"]
-    x1: float
-    y1: float
-    x2: float
-    y2: float
-    stroke: float
+    id: str
+    artifact: bool
+    reading_order: int
+    z: int
+    visible: bool
+    x1: Length
+    y1: Length
+    x2: Length
+    y2: Length
+    stroke: Union[Length, Literal["hairline"]]  # 0 = no stroke
+    color: Color
+    # dash/gap lengths (empty = solid), or a preset scaled to the stroke
+    dash: Union[List[Length], Literal["dashed", "dotted"]]
+    dash_phase: Length
+    cap: LineCap  # default "butt"; "round" for dotted dashes
+    join: LineJoin
+    miter_limit: float  # >= 1
+
+
+class PolygonElement(TypedDict, total=False):
+    type: Literal["polygon"]
+    id: str
+    artifact: bool
+    reading_order: int
+    z: int
+    visible: bool
+    points: List[Tuple[Length, Length]]  # (x, y) vertices, at least 3
+    stroke: Union[Length, Literal["hairline"]]  # default 1; 0 = no stroke
+    stroke_color: Color
+    fill_color: Color  # omitted = no fill
+    close: bool  # stroke back to the first point (default True)
+    fill_rule: Literal["nonzero", "evenodd"]  # default "nonzero"
+    cap: LineCap  # ends of an open polygon; default "butt"
+    join: LineJoin  # default "miter"
+    miter_limit: float  # >= 1
+
+
+class LeaderElement(TypedDict, total=False):
+    type: Literal["leader"]
+    id: str
+    artifact: bool
+    reading_order: int
+    z: int
+    visible: bool
+    x1: Length
+    x2: Length
+    y: Length  # center line, or with font the text baseline
+    style: Literal["dots", "dashes", "solid"]
+    dot_size: Length  # default: the font's period height, else 1pt
+    gap: Length  # default: twice dot_size
+    color: Color
+    font: str
+    size: Length  # required with font
+
+
+class ProgressRingElement(TypedDict, total=False):
+    type: Literal["progress_ring"]
+    id: str
+    artifact: bool
+    reading_order: int
+    z: int
+    visible: bool
+    cx: Length
+    cy: Length
+    radius: Length  # to the middle of the stroke
+    thickness: Length
+    value: float  # 0.0-1.0
+    start_angle: float  # degrees clockwise from 12 o'clock, default 0
+    track_color: Color  # default: no track
     color: Color
 
 
 class ImageElement(TypedDict, total=False):
     type: Literal["image"]
-    x: float
-    y: float
-    w: float
-    h: float
+    id: str
+    artifact: bool
+    reading_order: int
+    z: int
+    visible: bool
+    x: Length
+    y: Length
+    w: Length
+    h: Length
     image_ref: str
     align: HAlign
+    fade: "ImageFade"
+    # Clockwise turn in degrees about the anchor: x (per align) on the top
+    # edge, or on the bottom edge with origin "bottom_left"
+    rotation: float
+    # Corners the image's top-left, top-right and bottom-left land on
+    # (bottom-left, bottom-right and top-left with origin "bottom_left"),
+    # replacing x, y, w, h, align and rotation
+    quad: Tuple[Tuple[Length, Length], Tuple[Length, Length], Tuple[Length, Length]]
+    # Shape fitted to the display box that the image is clipped to: a
+    # circle, an ellipse, or rounded corners given as for a rect's
+    # corner_radius
+    clip: Union[Literal["circle", "ellipse"], Length, Tuple[Length, Length, Length, Length]]
+    # Outline stroked over the image along its display box or clip shape;
+    # 0 (the default) draws none
+    border_width: Length
+    border_color: Color
+
+
+class ImagePlaceholderElement(TypedDict, total=False):
+    """Crossed box with a caption, placed like an ImageElement."""
+
+    type: Literal["image_placeholder"]
+    id: str
+    artifact: bool
+    reading_order: int
+    z: int
+    visible: bool
+    x: Length
+    y: Length
+    # Both, or one of them with aspect
+    w: Length
+    h: Length
+    aspect: float  # width / height
+    align: HAlign
+    font: str  # required
+    label: str
+    note: str  # smaller second caption line
+    size: Length  # default 9pt
+    color: Color  # outline, cross and caption, default gray
+    fill_color: Color  # default light gray
+
+
+class GridElement(TypedDict, total=False):
+    """Layout grid across the whole page, heavier every `major_every`
+    cells, with major lines labeled when `font` is given."""
+
+    type: Literal["grid"]
+    id: str
+    artifact: bool
+    reading_order: int
+    z: int  # negative to draw under the page's other elements
+    visible: bool
+    spacing: Length  # required; at most 10,000 lines a page
+    major_every: int  # default 5
+    color: Color  # default light blue
+    stroke: Length  # default 0.25
+    major_stroke: Length  # default 0.75
+    font: str  # labels are drawn only with a font
+    size: Length  # label size, default 6pt
+    label_color: Color  # default dark gray
+
+
+class ImageFade(TypedDict, total=False):
+    """Fade to transparent toward `edge`; `start`/`end` are fractions of
+    the placed image measured from the opposite side."""
+
+    edge: Literal["top", "bottom", "left", "right"]  # default "bottom"
+    start: float  # default 0.5
+    end: float  # default 1.0
 
 
 class BarcodeElement(TypedDict, total=False):
     type: Literal["barcode", "barcode128"]
-    x: float
-    y: float
-    w: float
-    h: float
+    id: str
+    artifact: bool
+    reading_order: int
+    z: int
+    visible: bool
+    x: Length
+    y: Length
+    w: Length
+    h: Length
     value: str
     human_readable: bool
+    # Font for the human-readable text. Default "mono", or the document's
+    # only font when it has no "mono"; checked when the document is parsed.
     font: str
-    font_size: float
+    font_size: Length
+    # Human-readable text wider than w: "shrink" (default) lowers the size
+    # down to text_min_size (default font_size / 2) and clips beyond that,
+    # "ellipsis" drops characters for a trailing "…", "clip" clips to w.
+    text_overflow: Literal["clip", "ellipsis", "shrink"]
+    text_min_size: Length
+    color: Color
+    # Clockwise quarter turn about the top-left corner (bottom-left with
+    # origin "bottom_left"); w and h are measured before turning.
+    rotation: Literal[0, 90, 180, 270]
 
 
 class GS1_128Element(TypedDict, total=False):
@@ -109,22 +510,74 @@ class GS1_128Element(TypedDict, total=False):
     """
 
     type: Literal["gs1_128", "gs1-128", "gs1"]
-    x: float
-    y: float
-    w: float
-    h: float
+    id: str
+    artifact: bool
+    reading_order: int
+    z: int
+    visible: bool
+    x: Length
+    y: Length
+    w: Length
+    h: Length
     value: str
     human_readable: bool
+    # As for BarcodeElement
     font: str
-    font_size: float
+    font_size: Length
+    text_overflow: Literal["clip", "ellipsis", "shrink"]
+    text_min_size: Length
+    color: Color
+    rotation: Literal[0, 90, 180, 270]
+
+
+class VCardValue(TypedDict, total=False):
+    """Contact card QR value, serialized as vCard 3.0."""
+
+    kind: Literal["vcard"]
+    name: str  # required; formatted name
+    family_name: str
+    given_name: str
+    org: str
+    title: str
+    phone: Union[str, List[str]]
+    email: Union[str, List[str]]
+    url: str
+    note: str
+
+
+class WifiValue(TypedDict, total=False):
+    """Network join QR value, serialized as a WIFI: payload."""
+
+    kind: Literal["wifi"]
+    ssid: str  # required
+    password: str  # required unless security is "nopass"
+    security: Literal["WPA", "WPA2", "WPA3", "SAE", "WEP", "nopass"]  # default "WPA" with a password, else "nopass"
+    hidden: bool  # default False
 
 
 class QRCodeElement(TypedDict, total=False):
+    """QR code.
+
+    `value` may be `str` (encoded as UTF-8), `bytes` (encoded verbatim in
+    byte mode), or a VCardValue / WifiValue dict, which is serialized to the
+    standard payload with its special characters escaped. Payloads over the symbol capacity raise RupdfError naming the
+    payload length and the maximum for the chosen `error_correction` level.
+    """
+
     type: Literal["qrcode", "qr"]
-    x: float
-    y: float
-    size: float  # QR codes are square
-    value: str
+    id: str
+    artifact: bool
+    reading_order: int
+    z: int
+    visible: bool
+    x: Length
+    y: Length
+    size: Length  # QR codes are square
+    value: Union[str, bytes, VCardValue, WifiValue]
+    error_correction: Literal["L", "M", "Q", "H"]  # default "M"
+    version: int  # 1-40; a minimum when auto_version is true (default)
+    auto_version: bool  # False: payload must fit `version` exactly
+    min_module_size: Length  # reject symbols whose modules would be smaller
     color: Color  # foreground (dark modules)
     background: Color  # background (light modules)
 
@@ -146,36 +599,245 @@ class DataMatrixElement(TypedDict, total=False):
     """
 
     type: Literal["datamatrix", "gs1_datamatrix", "gs1-datamatrix"]
-    x: float
-    y: float
-    size: float
+    id: str
+    artifact: bool
+    reading_order: int
+    z: int
+    visible: bool
+    x: Length
+    y: Length
+    size: Length
     value: str
     shape: Literal["any", "square", "rectangular"]
     color: Color  # foreground (dark modules)
     background: Color  # background (light modules)
 
 
+class TableElement(TypedDict, total=False):
+    """Table of wrapped text cells.
+
+    Rows that don't fit below `y` (within `max_height`, or above the page's
+    bottom margin, which is `continued_y` when the page sets none) flow onto
+    continuation pages inserted after the table's page, headed by the
+    `header_rows` and `continued_caption`. Continuation pages keep
+    `continued_y` clear at both top and bottom. Rows are never split; a row
+    too tall for a page raises RupdfError naming its index. A table inside a
+    group or fragment is never split and raises RupdfError if it doesn't fit.
+    """
+
+    type: Literal["table"]
+    id: str
+    artifact: bool
+    reading_order: int
+    z: int
+    visible: bool
+    x: Length
+    y: Length
+    column_widths: List[Length]
+    rows: List[List[str]]  # one string per column in every row
+    header_rows: int  # default 0
+    font: str
+    header_font: str  # defaults to font
+    font_fallback: List[str]
+    missing_glyph_policy: MissingGlyphPolicy
+    size: Length
+    line_height: Length  # defaults to size * line_height_ratio
+    line_height_ratio: float  # default 1.2
+    color: Color
+    padding: Length  # default 4pt
+    border: Length  # default 0.5pt; 0 draws no borders
+    border_color: Color
+    header_fill: Color
+    max_height: Length
+    continued_y: Length  # table top on continuation pages; defaults to y
+    continued_caption: str
+
+
+class GroupElement(TypedDict, total=False):
+    """Child elements drawn together, in local coordinates.
+
+    The children's origin lands at (`x`, `y`), turned clockwise by
+    `rotation` degrees and scaled by `scale`; with the defaults they use
+    page coordinates. Nested groups compose their transforms.
+
+    With `transparency_group` the children composite as one unit (a PDF
+    transparency group) drawn at `opacity`, so overlaps inside the group
+    don't stack. Any `opacity` below 1 makes the group a transparency
+    group.
+    """
+
+    type: Literal["group"]
+    id: str
+    artifact: bool
+    reading_order: int
+    z: int
+    visible: bool
+    elements: List["Element"]
+    x: Length  # default 0
+    y: Length  # default 0
+    rotation: float  # clockwise degrees, default 0
+    scale: float  # > 0, default 1
+    transparency_group: bool  # default False
+    opacity: float  # 0.0-1.0, default 1.0
+
+
+class FragmentElement(TypedDict, total=False):
+    """A placement of a named entry in the document's `fragments`.
+
+    The fragment's elements are laid out in local coordinates; its origin
+    lands at (`x`, `y`) and everything is scaled by `scale`.
+    """
+
+    type: Literal["fragment"]
+    id: str
+    artifact: bool
+    reading_order: int
+    z: int
+    visible: bool
+    fragment_ref: str
+    x: Length
+    y: Length
+    scale: float  # default 1.0
+
+
+class RawResource(TypedDict, total=False):
+    """What a `raw_resources` name refers to: exactly one key."""
+
+    font: str   # resources.fonts name, for Tf
+    image: str  # resources.images name at its natural size, for Do
+    alpha: int  # 0-255 opacity, snapped to the nearest state, for gs
+
+
+class RawContentElement(TypedDict, total=False):
+    """Content stream operators written as given; needs allow_raw=True.
+
+    Coordinates are PDF user space: points from the page's bottom-left.
+    Text strings are 2-byte glyph ids in the Tf font.
+    """
+
+    type: Literal["raw_content"]
+    id: str
+    artifact: bool
+    reading_order: int
+    z: int
+    visible: bool
+    operators: str
+    raw_resources: Dict[str, RawResource]  # names the operators may use
+
+
 Element = Union[
     TextElement,
     TextBoxElement,
     RectElement,
     LineElement,
+    PolygonElement,
+    LeaderElement,
+    ProgressRingElement,
     ImageElement,
+    ImagePlaceholderElement,
+    GridElement,
     BarcodeElement,
     GS1_128Element,
     QRCodeElement,
     DataMatrixElement,
+    TableElement,
+    CheckElement,
+    NoteElement,
+    SignatureLineElement,
+    GroupElement,
+    FragmentElement,
+    RawContentElement,
 ]
 
 
 class FontResource(TypedDict, total=False):
     path: str
     bytes: bytes
+    url: str  # fetched only with allow_remote
+    embed: bool  # False is an error with a PDF/A output_intent
+    # Characters to measure the cap height from when the OS/2 table has
+    # none, first present wins; default "H0水म"
+    cap_height_glyphs: str
+
+
+class FontInfo(TypedDict):
+    postscript_name: str
+    # Metrics in font units
+    units_per_em: int
+    ascender: int
+    descender: int  # negative below the baseline
+    cap_height: int
+    x_height: int
+    # Where each height came from; the *_glyph keys name the measured
+    # character when the source is "glyph"
+    cap_height_source: Literal["os2", "glyph", "estimate"]
+    cap_height_glyph: Optional[str]
+    x_height_source: Literal["os2", "glyph", "estimate"]
+    x_height_glyph: Optional[str]
+    num_glyphs: int
+    outlines: Literal["cff", "truetype"]
+
+
+class BarcodeMeasurement(TypedDict):
+    modules: int  # symbol width, quiet zones excluded
+    quiet_zone_modules: int  # needed on each side
+    width: float  # points at module_width
+    min_width: float  # width plus both quiet zones
+    checksum_added: bool
+    human_readable: str
+
+
+class ExtractedText(TypedDict):
+    text: str  # decoded through the font's ToUnicode map
+    font: str  # BaseFont name
+    size: float
+    x: float  # start of the baseline, from the page's top-left
+    y: float
+    width: float  # advance, spacing included
+    color: List[float]  # fill color components, 0-1; empty for a pattern
+
+
+class ExtractedRect(TypedDict):
+    x: float
+    y: float
+    w: float
+    h: float
+    fill: Optional[List[float]]  # None when not filled
+    stroke: Optional[List[float]]  # None when not stroked
+
+
+class ExtractedLine(TypedDict):
+    x1: float
+    y1: float
+    x2: float
+    y2: float
+    width: float
+    color: List[float]
+
+
+class ExtractedPage(TypedDict):
+    width: float
+    height: float
+    text: List[ExtractedText]  # one per text-showing operator
+    rects: List[ExtractedRect]
+    lines: List[ExtractedLine]
+
+
+class ExtractedContent(TypedDict):
+    pages: List[ExtractedPage]
+
+
+class BarcodeOptions(TypedDict, total=False):
+    module_width: Length  # default 1pt
 
 
 class ImageResource(TypedDict, total=False):
     path: str
     bytes: bytes
+    url: str  # fetched only with allow_remote
+    svg: str  # SVG source text, loaded as SVG without format sniffing
+    frame: int  # animated GIF frame to draw, default 0
+    profile: Literal["embed", "srgb"]  # the PNG/JPEG's own ICC profile; default "embed"
 
 
 class Resources(TypedDict, total=False):
@@ -183,6 +845,27 @@ class Resources(TypedDict, total=False):
     images: Dict[str, ImageResource]
 
 
+class XYZView(TypedDict, total=False):
+    x: Length  # default 0
+    y: Length  # default 0 (top of the page)
+    zoom: float  # 1.0 = 100%; omit to keep the viewer's zoom
+
+
+class OpenAction(TypedDict, total=False):
+    # Index in the output, counting page repeats and table continuations.
+    page: int  # default 0
+    view: Union[Literal["fit", "fit_width"], XYZView]  # default "fit"
+
+
+class OutputIntent(TypedDict, total=False):
+    path: str  # ICC profile; path or bytes
+    bytes: bytes
+    condition: str  # required OutputConditionIdentifier, e.g. "FOGRA39"
+    info: str
+    subtype: Literal["pdfa", "pdfx"]  # default "pdfa"
+    tag_images: bool  # raster images ICCBased on the profile (RGB only)
+
+
 class Metadata(TypedDict, total=False):
     title: str
     author: str
@@ -191,38 +874,301 @@ class Metadata(TypedDict, total=False):
     creation_date: str
 
 
+class GradientSpec(TypedDict, total=False):
+    type: Literal["linear", "radial"]  # default "linear"
+    angle: float  # linear only; degrees, 0 = left to right, 90 = top to bottom
+    center: Tuple[Union[float, str], Union[float, str]]  # radial only; from the top-left, default the middle
+    radius: Union[float, str]  # radial only; default reaches the farthest corner
+    stops: List[Tuple[float, Color]]  # (offset 0-1, color), ascending
+
+
+class GradientBackground(TypedDict):
+    gradient: GradientSpec
+
+
+class ImageBackground(TypedDict, total=False):
+    image_ref: str
+    fit: Literal["cover", "contain", "stretch"]  # default "cover"
+
+
+PageSizePreset = Literal[
+    "A3", "A4", "A5", "A6", "B5", "C4", "C5", "DL",
+    "letter", "legal", "tabloid", "executive",
+]  # matched case-insensitively
+
+
+Origin = Literal["top_left", "bottom_left"]
+
+
 class Page(TypedDict, total=False):
-    size: Size
-    background: Color
+    size: Union[Size, PageSizePreset, str]
+    landscape: bool  # swap to width > height
+    # Content area inset for page-relative element lengths; one length
+    # sets every side.
+    margins: Union[Length, PageMargins]
+    background: Union[Color, GradientBackground, ImageBackground]
+    # Emit this page n times in a row, sharing one content stream.
+    repeat: int  # default 1
     elements: List[Element]
+    origin: Origin  # must match the document's
+    # Any value, echoed for each page it produces by render_pdf_with_index
+    tag: Any
+
+
+class RepeatOptions(TypedDict, total=False):
+    """Extra keys accepted on repeating elements."""
+
+    every_page: bool  # on a page 0 element: repeat it on every page
+    only_pages: List[int]  # page indices to draw on (default: all)
+    except_pages: List[int]  # page indices to skip
+    under_content: bool  # draw beneath the page's elements (default: over)
 
 
 class Document(TypedDict, total=False):
     metadata: Metadata
+    # Elements (plus RepeatOptions keys) drawn on every page
+    repeating_elements: List[Dict[str, Any]]
+    # Named lists of elements like repeating_elements, drawn only when
+    # render_pdf(include_layers=[...]) names them
+    layers: Dict[str, List[Dict[str, Any]]]
+    # Unit for bare numeric lengths: "pt" (default), "mm", "cm", "in",
+    # "px" (96 dpi) or "px@<dpi>".
+    units: str
+    # Where y is measured from (default "top_left"). With "bottom_left",
+    # y increases upward and boxes are placed by their bottom-left corner.
+    origin: Origin
+    # Values for keys an element omits. Flat keys apply to every element
+    # type; a nested dict under a type name ("text", "rect", ...) applies
+    # to that type only and takes precedence over flat keys.
+    defaults: Dict[str, Any]
+    # Reject unknown keys anywhere in the document (default False).
+    strict_keys: bool
+    # Convert drawn text to Unicode NFC while parsing (default True).
+    normalize_text: bool
+    # One element drawn on every page: an element dict plus "opacity",
+    # "rotation" (counter-clockwise degrees), "placement" ("center" or
+    # (x, y) fractions of the page size) and "behind".
+    watermark: Dict[str, Any]
+    # Elements drawn on every page, offset from the top or bottom edge: a
+    # list, or a dict of "elements", "offset" and "skip_first". "{page}"
+    # and "{pages}" in their text become the page number and count, with
+    # an optional offset and format: "{page-3}", "{page:roman}",
+    # "{page:Roman}", "{page:alpha}", "{page:Alpha}" or "{page-3:roman}".
+    header: Union[List[Dict[str, Any]], Dict[str, Any]]
+    footer: Union[List[Dict[str, Any]], Dict[str, Any]]
+    # Colors by name, usable wherever a color is taken.
+    palette: Dict[str, Color]
+    # Styles for markup text by name; "bold" is the one **...** uses.
+    styles: Dict[str, TextStyle]
+    # Faces of each family by name, for text elements set by family.
+    font_families: Dict[str, FontFamily]
+    # Named element lists in local coordinates, placed by "fragment"
+    # elements. A fragment may place those defined before it.
+    fragments: Dict[str, List["Element"]]
     pages: List[Page]
     resources: Resources
+    output_intent: OutputIntent
+    open_action: OpenAction
+
+
+class ParseErrorEntry(TypedDict):
+    page: Optional[int]     # None for fragments and repeating_elements
+    element: Optional[int]  # index in its page or in repeating_elements
+    id: Optional[str]       # the element's id
+    message: str
 
 
 class RupdfError(Exception):
     """Error raised by rupdf operations."""
 
+    # Set when parse errors are collected (validate_document, or
+    # render_pdf(collect_errors=True)): every one found, in document order
+    errors: List[ParseErrorEntry]
+
+
+class RupdfWarning(UserWarning):
+    """Non-fatal problem found while rendering (emitted via `warnings`)."""
+
     ...
 
 
-def render_pdf(document: Document, *, compress: bool = True) -> bytes:
+BoundsCheck = Literal["off", "warn", "error"]
+
+
+class RemoteOptions(TypedDict, total=False):
+    timeout: float  # seconds per request, default 10
+    max_bytes: int  # largest response accepted, default 20 MiB
+
+
+AllowRemote = Union[bool, RemoteOptions]
+
+
+class ValidationWarning(TypedDict):
+    code: str  # e.g. "out_of_bounds", "duplicate_id"
+    page: Optional[int]
+    element: Optional[int]
+    id: Optional[str]  # the element's id
+    message: str
+
+
+class FontStats(TypedDict):
+    postscript_name: str
+    glyphs: int     # glyphs in the subset
+    embedded: bool  # False for fonts loaded with embed: False
+    bytes: int      # compressed font program; 0 when not embedded
+
+
+class ImageStats(TypedDict):
+    name: str  # resources.images name
+    encoding: Literal["jpeg", "flate", "svg"]
+    width: Optional[int]   # pixels as embedded; None for SVG
+    height: Optional[int]
+    dpi: Optional[float]   # at the drawn size; None for SVG and emoji
+    bytes: int
+    profile: Optional[Literal["embed", "srgb"]]  # None without an own ICC profile
+
+
+class RenderStats(TypedDict):
+    """What a PDF holds, from render_pdf(return_stats=True)."""
+    pages: int          # page objects, each repeat included
+    bytes: int          # the whole file
+    content_bytes: int  # page, group and fragment content streams
+    warnings: int       # RupdfWarnings emitted
+    fonts: Dict[str, FontStats]  # by resources.fonts name
+    images: List[ImageStats]     # one per XObject: per drawn size for rasters
+
+
+class PageIndexEntry(TypedDict):
+    """Where one page of the PDF was written, from render_pdf_with_index."""
+    tag: Any             # its page dict's tag, or None
+    page: int            # index in the PDF from 0, each repeat included
+    object: int          # object number of the page dictionary
+    content_object: int  # object number of its content stream
+    content_offset: int  # byte offset of the content stream object
+
+
+class PreflightFont(TypedDict):
+    ok: bool
+    error: Optional[str]  # why it failed to load; None when ok
+    referenced: bool      # drawn by a visible element
+    # The rest are None when the font failed
+    postscript_name: Optional[str]
+    num_glyphs: Optional[int]
+    embedded: Optional[bool]
+    missing: Optional[List[str]]  # sample characters without a glyph
+
+
+class PreflightSize(TypedDict):
+    width: float   # drawn size in points
+    height: float
+    pixel_width: Optional[int]   # as embedded at 300 DPI; None for SVG
+    pixel_height: Optional[int]
+    dpi: Optional[float]
+
+
+class PreflightImage(TypedDict):
+    ok: bool
+    error: Optional[str]  # why it failed to load or decode; None when ok
+    referenced: bool
+    # The rest are None when the image failed
+    kind: Optional[Literal["raster", "svg"]]
+    width: Optional[float]   # pixels, or points for SVG
+    height: Optional[float]
+    sizes: Optional[List[PreflightSize]]  # one per size drawn
+    unsupported: Optional[List[str]]      # SVG features drawn differently or not at all
+
+
+class PreflightReport(TypedDict):
+    """What preflight found."""
+    ok: bool  # every referenced resource loaded and none is missing
+    fonts: Dict[str, PreflightFont]    # every resources.fonts entry
+    images: Dict[str, PreflightImage]  # every resources.images entry
+    missing_fonts: List[str]   # drawn but not declared
+    missing_images: List[str]
+
+
+@overload
+def render_pdf(
+    document: Document,
+    *,
+    compress: bool = True,
+    bounds_check: BoundsCheck = "off",
+    bounds_margin: float = 0.0,
+    allow_remote: AllowRemote = False,
+    return_stats: Literal[False] = False,
+    collect_errors: bool = False,
+    allow_raw: bool = False,
+    include_layers: Sequence[str] = (),
+    debug_uncompressed: bool = False,
+) -> bytes: ...
+
+
+@overload
+def render_pdf(
+    document: Document,
+    *,
+    compress: bool = True,
+    bounds_check: BoundsCheck = "off",
+    bounds_margin: float = 0.0,
+    allow_remote: AllowRemote = False,
+    return_stats: Literal[True],
+    collect_errors: bool = False,
+    allow_raw: bool = False,
+    include_layers: Sequence[str] = (),
+    debug_uncompressed: bool = False,
+) -> Tuple[bytes, RenderStats]: ...
+
+
+def render_pdf(
+    document: Document,
+    *,
+    compress: bool = True,
+    bounds_check: BoundsCheck = "off",
+    bounds_margin: float = 0.0,
+    allow_remote: AllowRemote = False,
+    return_stats: bool = False,
+    collect_errors: bool = False,
+    allow_raw: bool = False,
+    include_layers: Sequence[str] = (),
+    debug_uncompressed: bool = False,
+) -> Union[bytes, Tuple[bytes, RenderStats]]:
     """
     Render a document to PDF bytes.
 
     Args:
         document: Document specification with pages, elements, and resources.
         compress: Whether to compress the PDF content streams (default: True).
+        bounds_check: Report elements extending past the page: "off"
+            (default), "warn" (emit RupdfWarning) or "error" (raise).
+        bounds_margin: Inset from the page edges for bounds_check, in the
+            document's units.
+        allow_remote: Fetch "url" font and image sources before loading
+            them: True, or RemoteOptions to change the limits. Without it
+            URL sources raise RupdfError. Fetching needs rupdf built with
+            the "remote" feature.
+        return_stats: Also return a RenderStats dict (default: False).
+        collect_errors: Keep parsing past elements that fail and raise them
+            all at once, listed in RupdfError.errors (default: False).
+        allow_raw: Render raw_content elements (default: False, which
+            raises RupdfError for any in the document).
+        include_layers: Names of document layers to draw (default: none).
+            Layers left out load no fonts or images and add nothing to
+            the PDF.
+        debug_uncompressed: Deflate nothing, font programs, SVG forms and
+            color glyph bitmaps included, overriding compress (default:
+            False). Meant for golden-file diffs: a document always renders
+            to the same bytes.
 
     Returns:
-        PDF file contents as bytes.
+        PDF file contents as bytes; with return_stats, a (bytes, RenderStats)
+        tuple.
 
     Raises:
         RupdfError: If rendering fails (missing fonts, invalid elements, etc.)
 
+    The GIL is released after the document is parsed, and concurrent calls
+    share no state, so documents can be rendered from several threads.
+
     Example:
         >>> doc = {
         ...     "pages": [{"size": (612, 792), "elements": []}],
@@ -233,3 +1179,254 @@ def render_pdf(document: Document, *, compress: bool = True) -> bytes:
         b'%PDF-'
     """
     ...
+
+
+@overload
+def render_pdf_with_index(
+    document: Document,
+    *,
+    compress: bool = True,
+    bounds_check: BoundsCheck = "off",
+    bounds_margin: float = 0.0,
+    allow_remote: AllowRemote = False,
+    return_stats: Literal[False] = False,
+    collect_errors: bool = False,
+    allow_raw: bool = False,
+    include_layers: Sequence[str] = (),
+    debug_uncompressed: bool = False,
+) -> Tuple[bytes, List[PageIndexEntry]]: ...
+
+
+@overload
+def render_pdf_with_index(
+    document: Document,
+    *,
+    compress: bool = True,
+    bounds_check: BoundsCheck = "off",
+    bounds_margin: float = 0.0,
+    allow_remote: AllowRemote = False,
+    return_stats: Literal[True],
+    collect_errors: bool = False,
+    allow_raw: bool = False,
+    include_layers: Sequence[str] = (),
+    debug_uncompressed: bool = False,
+) -> Tuple[bytes, List[PageIndexEntry], RenderStats]: ...
+
+
+def render_pdf_with_index(
+    document: Document,
+    *,
+    compress: bool = True,
+    bounds_check: BoundsCheck = "off",
+    bounds_margin: float = 0.0,
+    allow_remote: AllowRemote = False,
+    return_stats: bool = False,
+    collect_errors: bool = False,
+    allow_raw: bool = False,
+    include_layers: Sequence[str] = (),
+    debug_uncompressed: bool = False,
+) -> Union[Tuple[bytes, List[PageIndexEntry]], Tuple[bytes, List[PageIndexEntry], RenderStats]]:
+    """
+    Render a document to PDF bytes and say where each page was written.
+
+    Each page dict may carry a "tag" of any value. The index has one entry
+    per page of the PDF, in order, with its page dict's tag. A table's
+    continuation pages and a repeated page's copies share their page's
+    tag. Use it to split a merged batch PDF by record.
+
+    Args:
+        document: As for render_pdf.
+        compress, bounds_check, bounds_margin, allow_remote, return_stats,
+        collect_errors, allow_raw, include_layers, debug_uncompressed: As for
+            render_pdf.
+
+    Returns:
+        The PDF bytes and a PageIndexEntry per page; with return_stats, the
+        RenderStats too.
+
+    Raises:
+        RupdfError: If rendering fails.
+    """
+    ...
+
+
+class PageStatus(TypedDict):
+    """Progress yielded by PdfRenderJob after each entry of pages."""
+    index: int   # Index into the document's pages
+    copies: int  # Page objects written for it (its repeat)
+    total: int   # Number of entries in pages
+
+
+class PdfRenderJob:
+    """
+    A document being rendered one page at a time.
+
+    Creating the job parses the document, loads its resources and runs the
+    checks, raising or warning exactly like render_pdf. Pages are then
+    rendered on a worker thread as the job is iterated: each step yields a
+    PageStatus once that page's content and page objects are written.
+
+    Example:
+        >>> job = PdfRenderJob(doc)
+        >>> for status in job:
+        ...     progress.update(status["index"] + 1, status["total"])
+        >>> pdf = job.result()
+    """
+
+    def __init__(
+        self,
+        document: Document,
+        *,
+        compress: bool = True,
+        bounds_check: BoundsCheck = "off",
+        bounds_margin: float = 0.0,
+        allow_remote: AllowRemote = False,
+        allow_raw: bool = False,
+        include_layers: Sequence[str] = (),
+        debug_uncompressed: bool = False,
+    ) -> None: ...
+
+    def __iter__(self) -> "PdfRenderJob": ...
+
+    def __next__(self) -> PageStatus:
+        """Render the next page. Raises RupdfError if it fails."""
+        ...
+
+    def result(self) -> bytes:
+        """
+        Render any remaining pages and return the PDF bytes. Later calls
+        return the same bytes.
+
+        Raises:
+            RupdfError: If a page failed to render.
+        """
+        ...
+
+
+def validate_document(
+    document: Document,
+    *,
+    bounds_check: BoundsCheck = "warn",
+    bounds_margin: float = 0.0,
+    allow_remote: AllowRemote = False,
+    allow_raw: bool = False,
+    include_layers: Sequence[str] = (),
+) -> List[ValidationWarning]:
+    """
+    Check a document without rendering it.
+
+    Parses the document and loads its resources, so anything render_pdf
+    would reject raises RupdfError here too. Parse errors are collected as
+    with render_pdf(collect_errors=True): all of them are raised together
+    and listed in RupdfError.errors.
+
+    Returns:
+        One dict per warning, with page and element indices.
+    """
+    ...
+
+
+def render_layout_fingerprint(
+    document: Document,
+    *,
+    allow_remote: AllowRemote = False,
+    allow_raw: bool = False,
+    include_layers: Sequence[str] = (),
+) -> str:
+    """
+    Hash the layout a document renders to, as 16 hex digits.
+
+    Covers everything the pages draw, positions and colors included, but
+    not metadata or embedded font and image data, so it is stable across
+    runs. No PDF is kept.
+    """
+
+def rasterize_page(
+    document: Document,
+    page: int = 0,
+    dpi: float = 72.0,
+    *,
+    allow_remote: AllowRemote = False,
+    allow_raw: bool = False,
+    include_layers: Sequence[str] = (),
+) -> bytes:
+    """
+    Render one page of a document to PNG, painted from the PDF render_pdf
+    writes so it shows the same layout.
+
+    Raises ValueError if dpi isn't positive, RupdfError if the page would
+    be more than 16384 x 16384 pixels and IndexError if the output has no
+    such page. Image fades and annotations aren't drawn.
+    """
+    ...
+
+
+def preflight(
+    document: Document,
+    samples: Sequence[str] = (),
+    *,
+    allow_remote: AllowRemote = False,
+    allow_raw: bool = False,
+) -> PreflightReport:
+    """
+    Check a document's fonts and images before rendering it.
+
+    Every declared resource is loaded as render_pdf loads it, carrying on
+    past failures, and raster images are decoded in full; fonts are
+    checked for the characters of `samples`. Nothing is rendered.
+
+    Raises:
+        RupdfError: If the document itself is invalid.
+    """
+    ...
+
+
+def inspect_font(font: FontResource) -> FontInfo:
+    """
+    Report the metrics rupdf reads from a font.
+
+    Args:
+        font: A font resource with "path" or "bytes", as in resources.fonts.
+
+    Raises:
+        RupdfError: If the font can't be read or parsed.
+    """
+    ...
+
+
+def font_covers(font: FontResource, text: str) -> List[str]:
+    """
+    List the characters of `text` the font has no glyph for, each once in
+    order of first use. The text is NFC-normalized first, as document text
+    is by default; control characters and variation selectors are ignored.
+
+    Raises:
+        RupdfError: If the font can't be read or parsed.
+    """
+    ...
+
+
+def measure_barcode(
+    value: str,
+    symbology: Literal["code128", "gs1_128"] = "code128",
+    options: Optional[BarcodeOptions] = None,
+) -> BarcodeMeasurement:
+    """
+    Measure a Code 128 or GS1-128 symbol without rendering it. The value is
+    encoded exactly as a barcode element's is.
+
+    Raises:
+        RupdfError: If the value can't be encoded.
+    """
+    ...
+
+
+def extract_content(pdf: bytes) -> ExtractedContent:
+    """
+    Read back the text, rects and lines a PDF from render_pdf draws, for
+    tests and QA. Only rupdf's own output is understood, compressed or not.
+
+    Raises:
+        RupdfError: If the bytes aren't a PDF this function can read.
+    """
+    ...
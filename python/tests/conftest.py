@@ -3,9 +3,10 @@
 import json
 import os
 from pathlib import Path
-from typing import Any, Dict, Optional
+from typing import Any, Callable, Dict, Optional
 
 import pytest
+import rupdf
 
 # Paths
 PROJECT_ROOT = Path(__file__).parent.parent.parent
@@ -23,6 +24,12 @@ FONT_PATHS = {
 # Test assets
 SVG_PATH = ASSETS_DIR / "test-svg.svg"
 PNG_PATH = ASSETS_DIR / "test-png.png"
+GRAY16_PNG_PATH = ASSETS_DIR / "test-gray16.png"
+RGB16_PNG_PATH = ASSETS_DIR / "test-rgb16.png"
+P3_PNG_PATH = ASSETS_DIR / "test-p3.png"
+GIF_PATH = ASSETS_DIR / "test-anim.gif"
+EMOJI_FONT_PATH = ASSETS_DIR / "TestEmoji-sbix.ttf"
+CJK_FONT_PATH = ASSETS_DIR / "TestCJK.ttf"
 
 
 def get_available_font() -> Optional[str]:
@@ -57,6 +64,22 @@ def svg_path() -> str:
     return str(SVG_PATH)
 
 
+@pytest.fixture
+def emoji_font_path() -> str:
+    """Get the color bitmap test font path, skip if not available."""
+    if not EMOJI_FONT_PATH.exists():
+        pytest.skip("Emoji test font not available")
+    return str(EMOJI_FONT_PATH)
+
+
+@pytest.fixture
+def cjk_font_path() -> str:
+    """Get the CJK-only test font path, skip if not available."""
+    if not CJK_FONT_PATH.exists():
+        pytest.skip("CJK test font not available")
+    return str(CJK_FONT_PATH)
+
+
 @pytest.fixture
 def png_path() -> str:
     """Get PNG test file path, skip if not available."""
@@ -65,6 +88,38 @@ def png_path() -> str:
     return str(PNG_PATH)
 
 
+@pytest.fixture
+def gray16_png_path() -> str:
+    """Get 16-bit grayscale PNG test file path, skip if not available."""
+    if not GRAY16_PNG_PATH.exists():
+        pytest.skip("16-bit PNG test file not available")
+    return str(GRAY16_PNG_PATH)
+
+
+@pytest.fixture
+def rgb16_png_path() -> str:
+    """Get 16-bit RGB PNG test file path, skip if not available."""
+    if not RGB16_PNG_PATH.exists():
+        pytest.skip("16-bit PNG test file not available")
+    return str(RGB16_PNG_PATH)
+
+
+@pytest.fixture
+def p3_png_path() -> str:
+    """Get the Display P3 tagged PNG test file path (16x16), skip if not available."""
+    if not P3_PNG_PATH.exists():
+        pytest.skip("ICC-tagged PNG test file not available")
+    return str(P3_PNG_PATH)
+
+
+@pytest.fixture
+def gif_path() -> str:
+    """Get animated GIF test file path (3 frames, 40x20), skip if not available."""
+    if not GIF_PATH.exists():
+        pytest.skip("GIF test file not available")
+    return str(GIF_PATH)
+
+
 @pytest.fixture
 def svg_bytes(svg_path: str) -> bytes:
     """Get SVG data as bytes."""
@@ -168,3 +223,100 @@ def text_doc(font_path: str) -> Dict[str, Any]:
         }],
         "resources": {"fonts": {"default": {"path": font_path}}},
     }
+
+
+def page_doc(
+    *elements: Dict[str, Any],
+    size=(612, 792),
+    page: Optional[Dict[str, Any]] = None,
+    fonts: Optional[Dict[str, Any]] = None,
+    images: Optional[Dict[str, Any]] = None,
+    **document: Any,
+) -> Dict[str, Any]:
+    """One-page document drawing `elements`.
+
+    `page` adds keys to the page dict, `fonts` and `images` become the
+    resources, and any other keyword is a document key.
+    """
+    doc = {"pages": [{"size": size, "elements": list(elements), **(page or {})}], **document}
+    if fonts is not None or images is not None:
+        doc["resources"] = {"fonts": fonts or {}, "images": images or {}}
+    return doc
+
+
+def render_uncompressed(doc: Dict[str, Any], **kwargs: Any) -> bytes:
+    """Render with compress=False so content streams can be searched."""
+    return rupdf.render_pdf(doc, compress=False, **kwargs)
+
+
+@pytest.fixture
+def font_doc(font_path: str) -> Callable[..., Dict[str, Any]]:
+    """page_doc with the test font registered as "f"."""
+    def build(*elements: Dict[str, Any], fonts: Optional[Dict[str, Any]] = None, **kwargs: Any) -> Dict[str, Any]:
+        return page_doc(*elements, fonts={"f": {"path": font_path}, **(fonts or {})}, **kwargs)
+    return build
+
+
+def woff_from_sfnt(sfnt: bytes) -> bytes:
+    """Wrap an OpenType font in WOFF 1.0, zlib-packing each table that shrinks."""
+    import struct
+    import zlib
+
+    num_tables = struct.unpack(">H", sfnt[4:6])[0]
+    data_start = 44 + 20 * num_tables
+    directory, body = b"", b""
+    for i in range(num_tables):
+        tag, checksum, offset, length = struct.unpack(">4sIII", sfnt[12 + 16 * i:28 + 16 * i])
+        table = sfnt[offset:offset + length]
+        packed = zlib.compress(table)
+        if len(packed) >= length:
+            packed = table
+        directory += struct.pack(">4sIIII", tag, data_start + len(body), len(packed), length, checksum)
+        body += packed + b"\0" * (-len(packed) % 4)
+    header = struct.pack(">4s4sIH", b"wOFF", sfnt[:4], data_start + len(body), num_tables)
+    return header.ljust(44, b"\0") + directory + body
+
+
+def woff2_from_sfnt(sfnt: bytes) -> bytes:
+    """Wrap an OpenType font in WOFF 2.0 with no table transforms.
+
+    The tables go in Brotli stored (uncompressed) meta-blocks, since the
+    standard library has no Brotli encoder.
+    """
+    import struct
+
+    num_tables = struct.unpack(">H", sfnt[4:6])[0]
+    directory, tables = b"", b""
+    for i in range(num_tables):
+        tag, _, offset, length = struct.unpack(">4sIII", sfnt[12 + 16 * i:28 + 16 * i])
+        # Flag 0x3F: the tag follows rather than a known-tag index
+        directory += b"\x3f" + tag
+        # UIntBase128 length: 7 bits per byte, most significant first
+        value, groups = length, [length & 0x7F]
+        while value > 0x7F:
+            value >>= 7
+            groups.append(value & 0x7F | 0x80)
+        directory += bytes(reversed(groups))
+        tables += sfnt[offset:offset + length]
+    # WBITS 16 (one 0 bit), then per block ISLAST=0, four nibbles of
+    # MLEN-1 and ISUNCOMPRESSED=1, padded to a byte; a last empty block ends it
+    stream, first = b"", True
+    for start in range(0, len(tables), 0x10000):
+        chunk = tables[start:start + 0x10000]
+        bits = ((len(chunk) - 1) << 3 | 1 << 19) << (1 if first else 0)
+        stream += bits.to_bytes(3, "little") + chunk
+        first = False
+    stream += b"\x03"
+    header_len = 48 + len(directory)
+    header = struct.pack(
+        ">4s4sIHHII", b"wOF2", sfnt[:4], header_len + len(stream), num_tables, 0, len(sfnt), len(stream)
+    )
+    return header.ljust(48, b"\0") + directory + stream
+
+
+def icc_header(space: bytes) -> bytes:
+    """Bare ICC header: enough for rupdf's profile checks."""
+    data = bytearray(128)
+    data[16:20] = space
+    data[36:40] = b"acsp"
+    return bytes(data)
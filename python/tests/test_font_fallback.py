@@ -185,3 +185,30 @@ class TestFallbackEmbedsBothFonts:
         assert b"IBMPlexSans" in pdf
         # Mono shouldn't be embedded since no char resolved to it.
         assert b"IBMPlexMono" not in pdf
+
+
+class TestUnicodeNormalization:
+    """Text is NFC-normalized while parsing; variation selectors never go missing."""
+
+    def _render(self, text, **doc_options):
+        doc = _doc([
+            {"type": "text", "x": 50, "y": 100, "text": text, "font": "sans", "size": 12,
+             "missing_glyph_policy": "raise"},
+            {"type": "textbox", "x": 50, "y": 200, "w": 300, "h": 200, "text": text,
+             "font": "sans", "size": 12, "missing_glyph_policy": "raise"},
+        ], with_mono=False)
+        doc.update(doc_options)
+        return rupdf.render_pdf(doc, compress=False)
+
+    def test_decomposed_matches_precomposed(self):
+        assert self._render("Cafe\u0301 cre\u0300me") == self._render("Caf\u00e9 cr\u00e8me")
+
+    def test_normalize_text_false_keeps_codepoints(self):
+        assert self._render("Cafe\u0301", normalize_text=False) != self._render("Cafe\u0301")
+
+    def test_variation_selectors_are_ignored(self):
+        assert self._render("A\ufe0fB\ufe0e") == self._render("AB")
+
+    def test_font_covers_normalizes(self):
+        _check_assets()
+        assert rupdf.font_covers({"path": str(SANS_PATH)}, "e\u0301\ufe0f") == []
@@ -4,9 +4,14 @@ Tests the Python-Rust interface: argument parsing, error handling,
 return types, and edge cases.
 """
 
+import re
+import struct
+
 import pytest
 import rupdf
 
+from .conftest import icc_header, page_doc, render_uncompressed, woff2_from_sfnt, woff_from_sfnt
+
 
 class TestRenderPdfSignature:
     """Test render_pdf function signature and argument handling."""
@@ -179,7 +184,7 @@ class TestColorParsing:
             "pages": [{
                 "size": (612, 792),
                 "elements": [
-                    {"type": "text", "x": 72, "y": 72, "text": "Test", "font": "f", "size": 12, "color": (255, 0, 0)}
+                    {"type": "text", "x": 72, "y": 72, "text": "Test", "font": "f", "size": 12, "color": (255, 0)}
                 ]
             }],
             "resources": {"fonts": {"f": {"path": font_path}}},
@@ -209,3 +214,2000 @@ class TestErrorType:
             rupdf.render_pdf(doc)
         # Error should mention the missing font
         assert "missing" in str(exc_info.value).lower() or "font" in str(exc_info.value).lower()
+
+
+class TestQrCodeParsing:
+    """Test QR payload handling at the interface boundary."""
+
+    QR = {"type": "qrcode", "x": 72, "y": 72, "size": 100, "value": "HELLO"}
+
+    def test_bytes_payload(self):
+        """Binary QR payloads should be accepted without a str round-trip."""
+        result = rupdf.render_pdf(page_doc(dict(self.QR, value=bytes(range(256)))))
+        assert result[:5] == b"%PDF-"
+
+    def test_oversized_payload_reports_capacity(self):
+        """Overflow errors should name the payload length and the limit."""
+        with pytest.raises(rupdf.RupdfError) as exc_info:
+            rupdf.render_pdf(page_doc(dict(self.QR, value=b"x" * 3000, error_correction="M")))
+        message = str(exc_info.value)
+        assert "3000 bytes" in message
+        assert "2331" in message
+
+    def test_invalid_version_raises(self):
+        """Versions outside 1-40 should be rejected at parse time."""
+        for version in (0, 41, 300, -1):
+            with pytest.raises(rupdf.RupdfError, match="QR version must be between 1 and 40"):
+                rupdf.render_pdf(page_doc(dict(self.QR, version=version)))
+
+    def test_vcard_value(self):
+        value = {"kind": "vcard", "name": "Jane Doe", "org": "Acme, Inc.", "phone": ["+1 555 0100", "+1 555 0101"]}
+        assert rupdf.render_pdf(page_doc(dict(self.QR, value=value)))[:5] == b"%PDF-"
+
+    def test_wifi_value(self):
+        value = {"kind": "wifi", "ssid": "Office", "password": "s3cret;!", "security": "WPA2", "hidden": True}
+        assert rupdf.render_pdf(page_doc(dict(self.QR, value=value)))[:5] == b"%PDF-"
+
+    def test_unknown_value_kind(self):
+        with pytest.raises(rupdf.RupdfError, match="Invalid QR value kind: 'mecard'"):
+            rupdf.render_pdf(page_doc(dict(self.QR, value={"kind": "mecard", "name": "Jane"})))
+
+    def test_value_missing_required_field(self):
+        with pytest.raises(rupdf.RupdfError, match="QR wifi value is missing required field 'ssid'"):
+            rupdf.render_pdf(page_doc(dict(self.QR, value={"kind": "wifi", "password": "x"})))
+
+    def test_password_required_for_wpa(self):
+        value = {"kind": "wifi", "ssid": "Office", "security": "WPA"}
+        with pytest.raises(rupdf.RupdfError, match="missing required field 'password'"):
+            rupdf.render_pdf(page_doc(dict(self.QR, value=value)))
+
+    def test_unknown_value_field(self):
+        value = {"kind": "vcard", "name": "Jane", "phone_number": "1"}
+        with pytest.raises(rupdf.RupdfError, match="Unknown field 'phone_number' in QR vcard value"):
+            rupdf.render_pdf(page_doc(dict(self.QR, value=value)))
+
+
+class TestPageBackground:
+    """Test gradient and image page background parsing."""
+
+    def test_gradient_needs_two_stops(self):
+        doc = page_doc(page={"background": {"gradient": {"stops": [(0.0, (0, 0, 0, 255))]}}})
+        with pytest.raises(rupdf.RupdfError):
+            rupdf.render_pdf(doc)
+
+    def test_gradient_alpha_warns(self):
+        stops = [(0.0, (255, 255, 255, 0)), (1.0, (0, 0, 128, 255))]
+        doc = page_doc(page={"background": {"gradient": {"stops": stops}}})
+        with pytest.warns(rupdf.RupdfWarning, match="Page 0: background gradient stop 0 alpha 0 is ignored"):
+            rupdf.render_pdf(doc)
+
+    def test_gradient_offsets_must_ascend(self):
+        stops = [(0.6, (0, 0, 0, 255)), (0.4, (255, 255, 255, 255))]
+        with pytest.raises(rupdf.RupdfError):
+            rupdf.render_pdf(page_doc(page={"background": {"gradient": {"stops": stops}}}))
+
+    def test_invalid_fit_raises(self, png_path):
+        doc = page_doc(page={"background": {"image_ref": "bg", "fit": "tile"}}, images={"bg": {"path": png_path}})
+        with pytest.raises(rupdf.RupdfError):
+            rupdf.render_pdf(doc)
+
+
+class TestDefaults:
+    """Test document-level default styles."""
+
+    TEXT = {"type": "text", "x": 72, "y": 72, "text": "Hello"}
+
+    def test_per_type_defaults_fill_missing_keys(self, font_doc):
+        doc = font_doc(self.TEXT, defaults={"text": {"font": "f", "size": 10}})
+        assert rupdf.render_pdf(doc)[:5] == b"%PDF-"
+
+    def test_flat_defaults_apply_to_all_types(self, font_doc):
+        box = {"type": "textbox", "x": 72, "y": 72, "w": 200, "h": 50, "text": "Hi"}
+        doc = font_doc(box, defaults={"font": "f", "size": 10, "color": (51, 51, 51, 255)})
+        assert rupdf.render_pdf(doc)[:5] == b"%PDF-"
+
+    def test_element_values_win(self, font_doc):
+        """An explicit font overrides a default naming a missing font."""
+        doc = font_doc(dict(self.TEXT, font="f"), defaults={"text": {"font": "missing", "size": 10}})
+        assert rupdf.render_pdf(doc)[:5] == b"%PDF-"
+
+    def test_invalid_default_names_its_section(self, font_doc):
+        doc = font_doc(self.TEXT, defaults={"text": {"font": "f", "size": "big"}})
+        with pytest.raises(rupdf.RupdfError) as exc_info:
+            rupdf.render_pdf(doc)
+        message = str(exc_info.value)
+        assert "Element 0" in message
+        assert "defaults.text" in message
+
+    def test_type_section_must_be_dict(self, font_doc):
+        doc = font_doc(dict(self.TEXT, font="f", size=10), defaults={"text": "f"})
+        with pytest.raises(rupdf.RupdfError, match="defaults.text"):
+            rupdf.render_pdf(doc)
+
+    def test_missing_default_font_says_so(self, font_doc):
+        doc = font_doc(self.TEXT, defaults={"text": {"font": "nope", "size": 10}})
+        with pytest.raises(rupdf.RupdfError, match=r"Missing font: 'nope' \(from defaults\)"):
+            rupdf.render_pdf(doc)
+
+        # Named by the element itself, it is the element's mistake
+        doc = font_doc(dict(self.TEXT, font="nope"), defaults={"text": {"size": 10}})
+        with pytest.raises(rupdf.RupdfError) as exc_info:
+            rupdf.render_pdf(doc)
+        assert str(exc_info.value).endswith("Missing font: 'nope'")
+
+
+class TestUnits:
+    """Test document units and suffixed lengths."""
+
+    def test_element_lengths_accept_suffixes(self):
+        rect = {"type": "rect", "x": "10mm", "y": 10, "w": "2cm", "h": "0.5in", "stroke": "0.5pt"}
+        assert rupdf.render_pdf(page_doc(rect))[:5] == b"%PDF-"
+
+    def test_invalid_units_raises(self):
+        with pytest.raises(rupdf.RupdfError, match="Invalid units"):
+            rupdf.render_pdf({"units": "furlong", "pages": []})
+
+    def test_invalid_length_raises(self):
+        rect = {"type": "rect", "x": "10 furlongs", "y": 10, "w": 20, "h": 20}
+        with pytest.raises(rupdf.RupdfError, match="Invalid length"):
+            rupdf.render_pdf(page_doc(rect))
+
+    def test_unknown_page_size_preset_lists_supported(self):
+        with pytest.raises(rupdf.RupdfError) as exc_info:
+            rupdf.render_pdf({"pages": [{"size": "A9"}]})
+        message = str(exc_info.value)
+        assert "A9" in message
+        assert "letter" in message
+
+
+class TestPageMargins:
+    """Test page margins and page-relative element lengths."""
+
+    def test_unknown_keyword_names_element(self):
+        rect = {"type": "rect", "id": "rule", "x": "margin_lft", "y": 0, "w": 10, "h": 10}
+        with pytest.raises(rupdf.RupdfError, match="Element 0 \\(id 'rule'\\): .*Unknown length keyword 'margin_lft'"):
+            rupdf.render_pdf(page_doc(rect, page={"margins": "1in"}))
+
+    def test_repeating_elements_cannot_be_page_relative(self):
+        rect = {"type": "rect", "x": "margin_left", "y": 0, "w": 10, "h": 10}
+        doc = {"pages": [{"size": (600, 800)}], "repeating_elements": [rect]}
+        with pytest.raises(rupdf.RupdfError, match="only page elements"):
+            rupdf.render_pdf(doc)
+
+    def test_margins_must_leave_content(self):
+        rect = {"type": "rect", "x": 0, "y": 0, "w": 1, "h": 1}
+        with pytest.raises(rupdf.RupdfError, match="leave no content area"):
+            rupdf.render_pdf(page_doc(rect, size=(600, 800), page={"margins": {"left": 300, "right": 300}}))
+
+
+class TestRepeatedPages:
+    """Test the page repeat count."""
+
+    def test_zero_rejected(self):
+        with pytest.raises(rupdf.RupdfError, match="repeat"):
+            rupdf.render_pdf(page_doc(page={"repeat": 0}))
+
+
+class TestOpenAction:
+    """Test open_action parsing."""
+
+    PAGES = [{"size": (612, 792)}, {"size": (300, 400)}]
+
+    def test_counts_repeated_pages(self):
+        pages = [dict(self.PAGES[0], repeat=3), self.PAGES[1]]
+        rupdf.render_pdf({"pages": pages, "open_action": {"page": 3}})
+        with pytest.raises(rupdf.RupdfError, match="out of range"):
+            rupdf.render_pdf({"pages": self.PAGES, "open_action": {"page": 2}})
+
+    def test_invalid_view_rejected(self):
+        with pytest.raises(rupdf.RupdfError, match="view"):
+            rupdf.render_pdf({"pages": self.PAGES, "open_action": {"view": "zoomed"}})
+
+    def test_nonpositive_zoom_rejected(self):
+        with pytest.raises(rupdf.RupdfError, match="zoom"):
+            rupdf.render_pdf({"pages": self.PAGES, "open_action": {"view": {"zoom": 0}}})
+
+
+class TestNoteParsing:
+    """Test sticky-note element parsing."""
+
+    def test_contents_required(self):
+        with pytest.raises(rupdf.RupdfError, match="contents"):
+            rupdf.render_pdf(page_doc({"type": "note", "x": 0, "y": 0}))
+
+    def test_invalid_icon(self):
+        note = {"type": "note", "x": 100, "y": 100, "contents": "Looks off", "icon": "smiley"}
+        with pytest.raises(rupdf.RupdfError, match="icon"):
+            rupdf.render_pdf(page_doc(note))
+
+
+class TestBoundsCheck:
+    """Test out-of-bounds element detection."""
+
+    def _rect(self, x, y, w=100, h=50):
+        return {"type": "rect", "x": x, "y": y, "w": w, "h": h, "stroke": 0}
+
+    def test_off_by_default(self):
+        import warnings
+
+        with warnings.catch_warnings():
+            warnings.simplefilter("error")
+            rupdf.render_pdf(page_doc(self._rect(580, 72)))
+
+    def test_warn_mode_emits_rupdf_warning(self):
+        with pytest.warns(rupdf.RupdfWarning, match="Page 0, element 1: rect extends past the right edge"):
+            rupdf.render_pdf(page_doc(self._rect(72, 72), self._rect(580, 72)), bounds_check="warn")
+
+    def test_error_mode_raises(self):
+        with pytest.raises(rupdf.RupdfError, match="out of bounds"):
+            rupdf.render_pdf(page_doc(self._rect(-10, 72)), bounds_check="error")
+
+    def test_margin_shrinks_area(self):
+        doc = page_doc(self._rect(10, 72))
+        assert rupdf.validate_document(doc) == []
+        [warning] = rupdf.validate_document(doc, bounds_margin=36)
+        assert warning["code"] == "out_of_bounds"
+        assert (warning["page"], warning["element"]) == (0, 0)
+        assert "left edge by 26.0 pt" in warning["message"]
+
+    def test_margin_in_document_units(self):
+        doc = dict(page_doc(self._rect(10, 72)), units="mm")
+        [warning] = rupdf.validate_document(doc, bounds_margin=20)
+        assert "left edge by 28.3 pt" in warning["message"]
+        assert rupdf.validate_document(doc, bounds_margin=3) == []
+
+    def test_text_is_measured(self, font_doc):
+        text = {"type": "text", "x": 600, "y": 72, "text": "Overflowing", "font": "f", "size": 12}
+        [warning] = rupdf.validate_document(font_doc(text))
+        assert "right edge" in warning["message"]
+
+    def test_invalid_mode_raises(self):
+        with pytest.raises((rupdf.RupdfError, ValueError)):
+            rupdf.render_pdf(page_doc(), bounds_check="loud")
+
+
+class TestRepeatingElementParsing:
+    """Test repeating element parsing."""
+
+    RULE = {"type": "line", "x1": 36, "y1": 756, "x2": 576, "y2": 756, "stroke": 0.25}
+
+    def test_every_page_rejected_after_page_zero(self):
+        pages = [{"size": (612, 792)}, {"size": (612, 792), "elements": [dict(self.RULE, every_page=True)]}]
+        with pytest.raises(rupdf.RupdfError, match="only allowed on page 0"):
+            rupdf.render_pdf({"pages": pages})
+
+    def test_invalid_repeating_element_is_labelled(self):
+        doc = page_doc(repeating_elements=[{"type": "line"}])
+        with pytest.raises(rupdf.RupdfError, match="repeating_elements"):
+            rupdf.render_pdf(doc)
+
+    def test_bad_watermark_placement_raises(self):
+        mark = {"type": "rect", "x": -50, "y": -10, "w": 100, "h": 20, "placement": "top"}
+        with pytest.raises(rupdf.RupdfError, match="watermark"):
+            rupdf.render_pdf(page_doc(watermark=mark))
+
+
+class TestHeaderFooterParsing:
+    """Test header and footer parsing."""
+
+    def test_unknown_region_key_rejected(self):
+        doc = {"strict_keys": True, "header": {"elements": [], "ofset": 10}, "pages": []}
+        with pytest.raises(rupdf.RupdfError, match="did you mean 'offset'"):
+            rupdf.render_pdf(doc)
+
+    def test_unknown_page_number_format_names_element_and_token(self, font_path):
+        text = {"type": "text", "x": 36, "y": 0, "text": "Page {page:greek}", "font": "f", "size": 9}
+        doc = page_doc(resources={"fonts": {"f": {"path": font_path}}}, footer=[text])
+        with pytest.raises(rupdf.RupdfError, match=r"Repeating element 0.*\{page:greek\}"):
+            rupdf.render_pdf(doc)
+
+
+class TestLayers:
+    """Test named layer selection."""
+
+    PERFORATION = {"type": "line", "x1": 0, "y1": 396, "x2": 612, "y2": 396, "stroke": 0.3}
+
+    def test_unknown_layer(self):
+        doc = page_doc(layers={"proof_only": [self.PERFORATION]})
+        with pytest.raises(rupdf.RupdfError, match="Unknown layer 'proof'; the document has 'proof_only'"):
+            rupdf.render_pdf(doc, include_layers=["proof"])
+
+    def test_left_out_layer_is_still_checked(self):
+        doc = page_doc(layers={"proof_only": [{"type": "line"}]})
+        with pytest.raises(rupdf.RupdfError, match="layers.proof_only: .*Element 0: .*'x1'"):
+            rupdf.render_pdf(doc)
+
+    def test_render_job_and_validation(self):
+        doc = {
+            "pages": [{"size": (612, 792)}, {"size": (612, 792)}],
+            "layers": {"proof_only": [dict(self.PERFORATION, x2=700)]},
+        }
+        assert rupdf.validate_document(doc) == []
+        warnings = rupdf.validate_document(doc, include_layers=["proof_only"])
+        assert [w["code"] for w in warnings] == ["out_of_bounds", "out_of_bounds"]
+        job = rupdf.PdfRenderJob(doc, compress=False, include_layers=["proof_only"])
+        assert job.result().count(b"0.3 w") == 2
+
+    def test_fingerprint_and_rasterize_select_layers(self):
+        doc = page_doc(layers={"proof_only": [self.PERFORATION]})
+        plain = rupdf.render_layout_fingerprint(doc)
+        assert rupdf.render_layout_fingerprint(doc, include_layers=["proof_only"]) != plain
+        assert rupdf.rasterize_page(doc, dpi=18, include_layers=["proof_only"]) != rupdf.rasterize_page(doc, dpi=18)
+        for function in (rupdf.render_layout_fingerprint, rupdf.rasterize_page):
+            with pytest.raises(rupdf.RupdfError, match="Unknown layer 'proof'"):
+                function(doc, include_layers=["proof"])
+
+
+class TestUnembeddedFonts:
+    """Test fonts referenced by name instead of embedded."""
+
+    TEXT = {"type": "text", "x": 72, "y": 72, "text": "Hello", "font": "f", "size": 12}
+
+    def test_tradeoff_is_reported(self, font_path):
+        [warning] = rupdf.validate_document(page_doc(self.TEXT, fonts={"f": {"path": font_path, "embed": False}}))
+        assert warning["code"] == "font_not_embedded"
+        assert "'f'" in warning["message"]
+
+    def test_rejected_with_pdfa_output_intent(self, font_path):
+        doc = page_doc(
+            self.TEXT,
+            fonts={"f": {"path": font_path, "embed": False}},
+            output_intent={"bytes": icc_header(b"RGB "), "condition": "sRGB"},
+        )
+        with pytest.raises(rupdf.RupdfError, match="PDF/A output_intent requires every font to be embedded"):
+            rupdf.render_pdf(doc)
+
+    def test_embed_must_be_bool(self, font_path):
+        with pytest.raises(rupdf.RupdfError, match="bool"):
+            rupdf.render_pdf(page_doc(self.TEXT, fonts={"f": {"path": font_path, "embed": "no"}}))
+
+
+class TestWoffFonts:
+    """Test WOFF font source parsing."""
+
+    TEXT = {"type": "text", "x": 72, "y": 72, "text": "Hello", "font": "f", "size": 12}
+
+    def test_truncated_woff_raises(self, font_bytes):
+        woff = woff_from_sfnt(font_bytes)
+        with pytest.raises(rupdf.RupdfError, match="WOFF"):
+            rupdf.render_pdf(page_doc(self.TEXT, fonts={"f": {"bytes": woff[: len(woff) // 2]}}))
+
+    def test_truncated_woff2_raises(self, font_bytes):
+        woff2 = woff2_from_sfnt(font_bytes)
+        with pytest.raises(rupdf.RupdfError, match="WOFF2 length mismatch"):
+            rupdf.render_pdf(page_doc(self.TEXT, fonts={"f": {"bytes": woff2[: len(woff2) // 2]}}))
+
+
+class TestEmoji:
+    """Test color emoji fallback options."""
+
+    TEXT = {"type": "text", "x": 72, "y": 72, "text": "A\U0001F600B", "font": "f", "size": 12}
+
+    def test_replacement(self, font_doc):
+        doc = font_doc(dict(self.TEXT, emoji_replacement="?", missing_glyph_policy="raise"))
+        assert rupdf.render_pdf(doc)[:5] == b"%PDF-"
+
+    def test_uncovered_emoji_follows_policy(self, font_doc):
+        with pytest.raises(rupdf.RupdfError):
+            rupdf.render_pdf(font_doc(dict(self.TEXT, missing_glyph_policy="raise")))
+
+    def test_unknown_emoji_font_raises(self, font_doc):
+        with pytest.raises(rupdf.RupdfError, match="missing"):
+            rupdf.render_pdf(font_doc(dict(self.TEXT, text="A", emoji_font="missing")))
+
+
+class TestTextHighlight:
+    """Test text highlight bounds."""
+
+    def test_padding_counts_toward_bounds(self, font_doc):
+        text = {"type": "text", "x": 0, "y": 72, "text": "Total", "font": "f", "size": 12,
+                "highlight_color": (255, 235, 0, 255), "highlight_padding": 4}
+        [warning] = rupdf.validate_document(font_doc(text))
+        assert "left edge by 4.0 pt" in warning["message"]
+
+
+class TestTextTransform:
+    """Test case transforms on text elements."""
+
+    TEXT = {"type": "text", "x": 300, "y": 72, "text": "Hello world", "font": "f", "size": 20}
+
+    def test_smallcaps_narrower_than_uppercase(self, font_doc):
+        def left_edge(transform):
+            text = dict(self.TEXT, x=0, text="Hello", align="center", transform=transform)
+            [warning] = rupdf.validate_document(font_doc(text))
+            return float(warning["message"].split("left edge by ")[1].split(" pt")[0])
+
+        assert left_edge("smallcaps") < left_edge("uppercase")
+
+    def test_invalid_transform_rejected(self, font_doc):
+        with pytest.raises(rupdf.RupdfError, match="transform"):
+            rupdf.render_pdf(font_doc(dict(self.TEXT, transform="shout")))
+
+
+class TestTextFitWidth:
+    """Test fit_width parsing and clamping."""
+
+    TEXT = {"type": "text", "x": 400, "y": 72, "text": "Hello", "font": "f", "size": 20, "align": "right"}
+
+    def test_clamp_warns(self, font_doc):
+        [warning] = rupdf.validate_document(font_doc(dict(self.TEXT, fit_width={"width": 500})))
+        assert warning["code"] == "fit_width_clamped"
+        assert warning["page"] == 0
+
+    def test_fit_within_range_is_quiet(self, font_doc):
+        assert rupdf.validate_document(font_doc(dict(self.TEXT, fit_width={"width": 80}))) == []
+
+    def test_invalid_range_rejected(self, font_doc):
+        with pytest.raises(rupdf.RupdfError, match="min"):
+            rupdf.render_pdf(font_doc(dict(self.TEXT, fit_width={"width": 80, "min": 1.5})))
+
+    def test_invalid_mode_rejected(self, font_doc):
+        with pytest.raises(rupdf.RupdfError, match="mode"):
+            rupdf.render_pdf(font_doc(dict(self.TEXT, fit_width={"width": 80, "mode": "squash"})))
+
+
+class TestFontFamilies:
+    """Test font family parsing."""
+
+    TEXT = {"type": "text", "x": 72, "y": 72, "text": "Hello", "size": 12, "family": "body"}
+    FAMILIES = {"body": {"regular": "regular", "bold": "bold"}}
+
+    def test_missing_face(self, font_path):
+        doc = page_doc(dict(self.TEXT, weight="bold", style="italic"),
+                       fonts={"regular": {"path": font_path}}, font_families=self.FAMILIES)
+        with pytest.raises(rupdf.RupdfError, match="Element 0: .*font family 'body' has no bold_italic face"):
+            rupdf.render_pdf(doc)
+
+    def test_unknown_family(self, font_path):
+        doc = page_doc(dict(self.TEXT, family="head"), fonts={"regular": {"path": font_path}}, font_families=self.FAMILIES)
+        with pytest.raises(rupdf.RupdfError, match="unknown font family 'head'"):
+            rupdf.render_pdf(doc)
+
+    def test_weight_needs_family(self, font_path):
+        text = dict(self.TEXT, family=None, font="regular", weight="bold")
+        doc = page_doc(text, fonts={"regular": {"path": font_path}}, font_families=self.FAMILIES)
+        with pytest.raises(rupdf.RupdfError, match="weight and style need a family"):
+            rupdf.render_pdf(doc)
+
+    def test_invalid_weight(self, font_path):
+        doc = page_doc(dict(self.TEXT, weight="heavy"), fonts={"regular": {"path": font_path}}, font_families=self.FAMILIES)
+        with pytest.raises(rupdf.RupdfError, match="Invalid weight: 'heavy'"):
+            rupdf.render_pdf(doc)
+
+    def test_empty_family(self, font_path):
+        doc = page_doc(self.TEXT, fonts={"regular": {"path": font_path}}, font_families={"body": {}})
+        with pytest.raises(rupdf.RupdfError, match="font_families.body: .*a family needs at least one"):
+            rupdf.render_pdf(doc)
+
+
+class TestTextMaxWidth:
+    """Test max_width parsing."""
+
+    TEXT = {"type": "text", "x": 300, "y": 72, "text": "Bartholomew Higginbottom", "font": "f", "size": 20,
+            "align": "center", "max_width": 100}
+
+    def test_invalid_overflow_rejected(self, font_doc):
+        with pytest.raises(rupdf.RupdfError, match="Invalid overflow: 'wrap'"):
+            rupdf.render_pdf(font_doc(dict(self.TEXT, overflow="wrap")))
+
+    def test_invalid_min_size_rejected(self, font_doc):
+        with pytest.raises(rupdf.RupdfError, match="min_size must be positive and at most size 20"):
+            rupdf.render_pdf(font_doc(dict(self.TEXT, overflow="shrink", min_size=30)))
+
+    def test_fit_width_conflicts(self, font_doc):
+        with pytest.raises(rupdf.RupdfError, match="max_width can't be combined with fit_width"):
+            rupdf.render_pdf(font_doc(dict(self.TEXT, fit_width={"width": 80})))
+
+
+class TestTextBoxColumns:
+    """Test textbox column parsing."""
+
+    BOX = {"type": "textbox", "x": 72, "y": 72, "w": 300, "h": 60, "text": "lorem ipsum", "font": "f", "size": 10}
+
+    def test_zero_columns_rejected(self, font_doc):
+        with pytest.raises(rupdf.RupdfError, match="columns"):
+            rupdf.render_pdf(font_doc(dict(self.BOX, columns=0)))
+
+    def test_gap_wider_than_box_rejected(self, font_doc):
+        with pytest.raises(rupdf.RupdfError, match="column_gap"):
+            rupdf.render_pdf(font_doc(dict(self.BOX, columns=3, column_gap=200)))
+
+    def test_single_column_ignores_gap(self, font_doc):
+        assert rupdf.render_pdf(font_doc(dict(self.BOX, w=0))).startswith(b"%PDF")
+        assert rupdf.render_pdf(font_doc(dict(self.BOX, w=100, column_gap=200))).startswith(b"%PDF")
+
+
+class TestTable:
+    """Test table parsing and layout errors."""
+
+    TABLE = {"type": "table", "x": 72, "y": 72, "column_widths": [200, 60], "header_rows": 1,
+             "font": "f", "size": 10, "line_height": 12}
+
+    def test_oversized_row_names_index(self, font_doc):
+        rows = [["Item", "Qty"], ["Part 0", "0"], ["word " * 200, "1"], ["Part 2", "2"]]
+        with pytest.raises(rupdf.RupdfError, match="table row 2"):
+            rupdf.render_pdf(font_doc(dict(self.TABLE, rows=rows, max_height=100)))
+
+    def test_overflowing_table_in_group_rejected(self, font_doc):
+        rows = [["Item", "Qty"]] + [[f"Part {i}", str(i)] for i in range(60)]
+        group = {"type": "group", "elements": [dict(self.TABLE, rows=rows, max_height=400)]}
+        with pytest.raises(rupdf.RupdfError, match="inside a group or fragment can't continue"):
+            rupdf.render_pdf(font_doc(group))
+
+    def test_ragged_row_rejected(self, font_doc):
+        rows = [["Item", "Qty"], ["Part 0", "0", "extra"], ["Part 1", "1"]]
+        with pytest.raises(rupdf.RupdfError, match="row 1 has 3 cells"):
+            rupdf.render_pdf(font_doc(dict(self.TABLE, rows=rows)))
+
+
+class TestRectSides:
+    """Test partial rect border parsing."""
+
+    RECT = {"type": "rect", "x": 100, "y": 100, "w": 200, "h": 50, "stroke": 1}
+
+    def test_rounded_corners_rejected(self):
+        with pytest.raises(rupdf.RupdfError, match="corner_radius"):
+            rupdf.render_pdf(page_doc(dict(self.RECT, sides=["top"], corner_radius=4)))
+
+    def test_unknown_side_rejected(self):
+        with pytest.raises(rupdf.RupdfError, match="Invalid side"):
+            rupdf.render_pdf(page_doc(dict(self.RECT, sides=["middle"])))
+
+
+class TestFillPattern:
+    """Test fill_pattern parsing."""
+
+    RECT = {"type": "rect", "x": 100, "y": 100, "w": 200, "h": 50, "stroke": 0}
+
+    def test_invalid_style(self):
+        with pytest.raises(rupdf.RupdfError, match="Invalid fill_pattern style"):
+            rupdf.render_pdf(page_doc(dict(self.RECT, fill_pattern={"style": "zigzag"})))
+
+    def test_spacing_must_be_positive(self):
+        with pytest.raises(rupdf.RupdfError, match="fill_pattern spacing must be positive"):
+            rupdf.render_pdf(page_doc(dict(self.RECT, fill_pattern={"spacing": 0})))
+
+
+class TestRectGradient:
+    """Test gradient parsing."""
+
+    RECT = {"type": "rect", "x": 100, "y": 100, "w": 200, "h": 50, "stroke": 0}
+    STOPS = [(0.0, (255, 0, 0, 255)), (1.0, (0, 0, 255, 255))]
+
+    def test_offset_outside_range(self):
+        gradient = {"stops": [(0.0, (0, 0, 0, 255)), (1.5, (255, 255, 255, 255))]}
+        with pytest.raises(rupdf.RupdfError, match="Element 0: .*offset 1.5 is outside 0..1"):
+            rupdf.render_pdf(page_doc(dict(self.RECT, gradient=gradient)))
+
+    def test_offsets_must_ascend(self):
+        gradient = {"stops": [(0.6, (0, 0, 0, 255)), (0.4, (255, 255, 255, 255))]}
+        with pytest.raises(rupdf.RupdfError, match="less than the previous offset"):
+            rupdf.render_pdf(page_doc(dict(self.RECT, gradient=gradient)))
+
+    def test_radial_radius_must_be_positive(self):
+        gradient = {"type": "radial", "radius": 0, "stops": self.STOPS}
+        with pytest.raises(rupdf.RupdfError, match="radius must be positive"):
+            rupdf.render_pdf(page_doc(dict(self.RECT, gradient=gradient)))
+
+    def test_stop_alpha_warns(self):
+        gradient = {"type": "radial", "stops": [(0.0, (255, 0, 0, 255)), (1.0, (0, 0, 255, 128))]}
+        with pytest.warns(rupdf.RupdfWarning, match="rect gradient stop 1 alpha 128 is ignored"):
+            rupdf.render_pdf(page_doc(dict(self.RECT, gradient=gradient)))
+
+    def test_invalid_type(self):
+        with pytest.raises(rupdf.RupdfError, match="Must be 'linear' or 'radial'"):
+            rupdf.render_pdf(page_doc(dict(self.RECT, gradient={"type": "conic", "stops": self.STOPS})))
+
+
+class TestCornerStyle:
+    """Test corner_radius and corner_style parsing."""
+
+    RECT = {"type": "rect", "x": 100, "y": 100, "w": 200, "h": 100, "stroke": 1}
+
+    def test_invalid_style_rejected(self):
+        with pytest.raises(rupdf.RupdfError, match="Invalid corner_style"):
+            rupdf.render_pdf(page_doc(dict(self.RECT, corner_radius=10, corner_style="squircle")))
+
+    def test_per_corner_radii_need_four(self):
+        with pytest.raises(rupdf.RupdfError, match="corner_radius must be a length or a"):
+            rupdf.render_pdf(page_doc(dict(self.RECT, corner_radius=(10, 10))))
+
+    def test_negative_corner_clamped(self):
+        with pytest.warns(rupdf.RupdfWarning, match="rect corner_radius bottom_left -5 is negative; using 0"):
+            rupdf.render_pdf(page_doc(dict(self.RECT, corner_radius=(10, 10, 10, -5))))
+
+
+class TestImagePlaceholder:
+    """Test image_placeholder parsing and bounds."""
+
+    PLACEHOLDER = {"type": "image_placeholder", "x": 300, "y": 100, "font": "f", "label": "Photo"}
+
+    def test_needs_size(self, font_doc):
+        with pytest.raises(rupdf.RupdfError, match="Element 0: image_placeholder needs w and h"):
+            rupdf.render_pdf(font_doc(dict(self.PLACEHOLDER, w=200)))
+        with pytest.raises(rupdf.RupdfError, match="aspect must be positive"):
+            rupdf.render_pdf(font_doc(dict(self.PLACEHOLDER, w=200, aspect=0)))
+
+    def test_box_is_checked_against_page(self, font_doc):
+        [warning] = rupdf.validate_document(font_doc(dict(self.PLACEHOLDER, w=400, h=100)))
+        assert "right edge by 88.0 pt" in warning["message"]
+
+
+class TestGrid:
+    """Test grid parsing."""
+
+    GRID = {"type": "grid", "spacing": 72, "major_every": 2}
+
+    def test_spacing_must_be_positive(self):
+        with pytest.raises(rupdf.RupdfError, match="grid spacing must be positive"):
+            rupdf.render_pdf(page_doc(dict(self.GRID, spacing=0)))
+
+    def test_line_count_is_capped(self):
+        with pytest.raises(rupdf.RupdfError, match="more than 10000; use a larger spacing"):
+            rupdf.render_pdf(page_doc(dict(self.GRID, spacing=0.0001)))
+
+
+class TestSignatureLine:
+    """Test signature_line parsing and bounds."""
+
+    def test_requires_font(self):
+        with pytest.raises(rupdf.RupdfError, match="font"):
+            rupdf.render_pdf(page_doc({"type": "signature_line", "x": 72, "y": 100, "w": 200}))
+
+    def test_within_bounds(self, font_doc):
+        line = {"type": "signature_line", "x": 72, "y": 780, "w": 200, "font": "f", "label": "Name"}
+        warnings = rupdf.validate_document(font_doc(line))
+        assert [w["code"] for w in warnings] == ["out_of_bounds"]
+
+
+class TestVisible:
+    """Test hidden elements in validation."""
+
+    RECT = {"type": "rect", "x": 700, "y": 0, "w": 10, "h": 10}
+
+    def test_hidden_elements_keep_indices(self):
+        doc = page_doc(dict(self.RECT, x=0, visible=False), self.RECT)
+        warnings = rupdf.validate_document(doc, bounds_check="warn")
+        assert [(w["code"], w["element"]) for w in warnings] == [("out_of_bounds", 1)]
+
+    def test_hidden_out_of_bounds_not_reported(self):
+        assert rupdf.validate_document(page_doc(dict(self.RECT, visible=False)), bounds_check="warn") == []
+
+
+class TestNumberText:
+    """Test number text parsing."""
+
+    TEXT = {"type": "text", "x": 72, "y": 100, "font": "f", "size": 12}
+
+    def test_not_a_number(self, font_doc):
+        with pytest.raises(rupdf.RupdfError, match="Element 0: .*text number 'inf' is not a finite number"):
+            rupdf.render_pdf(font_doc(dict(self.TEXT, text={"number": float("inf")})))
+
+    def test_bool_rejected(self, font_doc):
+        with pytest.raises(rupdf.RupdfError, match="bool"):
+            rupdf.render_pdf(font_doc(dict(self.TEXT, text={"number": True})))
+
+    def test_unknown_locale(self, font_doc):
+        with pytest.raises(rupdf.RupdfError, match="Unknown locale: 'xx'"):
+            rupdf.render_pdf(font_doc(dict(self.TEXT, text={"number": 1, "format": {"locale": "xx"}})))
+
+    def test_invalid_negative(self, font_doc):
+        with pytest.raises(rupdf.RupdfError, match="Invalid negative"):
+            rupdf.render_pdf(font_doc(dict(self.TEXT, text={"number": 1, "format": {"negative": "red"}})))
+
+
+class TestPaintOrder:
+    """Test z parsing."""
+
+    RECT = {"type": "rect", "x": 1, "y": 100, "w": 10, "h": 10, "stroke": 0}
+
+    def test_errors_name_list_index(self):
+        doc = page_doc(self.RECT, {"type": "image", "x": 0, "y": 0, "image_ref": "missing", "z": -5})
+        with pytest.raises(rupdf.RupdfError, match="element 1"):
+            rupdf.render_pdf(doc)
+
+    def test_z_must_be_int(self):
+        with pytest.raises(rupdf.RupdfError):
+            rupdf.render_pdf(page_doc(dict(self.RECT, z="top")))
+
+
+class TestSkew:
+    """Test skew parsing and bounds."""
+
+    RECT = {"type": "rect", "x": 100, "y": 100, "w": 200, "h": 50}
+
+    def test_angle_must_be_under_a_quarter_turn(self):
+        with pytest.raises(rupdf.RupdfError, match="skew_x must be between -90 and 90"):
+            rupdf.render_pdf(page_doc(dict(self.RECT, skew_x=90)))
+
+    def test_bounds_measure_the_sheared_box(self):
+        doc = page_doc(dict(self.RECT, x=0, w=100, h=100, skew_x=30))
+        warnings = rupdf.validate_document(doc, bounds_check="warn")
+        assert [w["code"] for w in warnings] == ["out_of_bounds"]
+        assert "left edge" in warnings[0]["message"]
+
+
+class TestRectRotation:
+    """Test rect rotation parsing and bounds."""
+
+    RECT = {"type": "rect", "x": 100, "y": 100, "w": 200, "h": 100, "stroke": 1}
+
+    def test_invalid_origin(self):
+        with pytest.raises(rupdf.RupdfError, match="rotation_origin must be 'center' or an"):
+            rupdf.render_pdf(page_doc(dict(self.RECT, rotation=10, rotation_origin="corner")))
+
+    def test_bounds_measure_the_turned_box(self):
+        # 200 x 20 at the top-right, turned upright about its center,
+        # pokes above the page but stays inside its right edge
+        rect = dict(self.RECT, x=400, y=10, w=200, h=20, stroke=0, rotation=90)
+        warnings = rupdf.validate_document(page_doc(rect), bounds_check="warn")
+        assert [w["code"] for w in warnings] == ["out_of_bounds"]
+        assert "top edge" in warnings[0]["message"]
+        assert "right edge" not in warnings[0]["message"]
+
+
+class TestTextRotation:
+    """Test text rotation bounds."""
+
+    def test_bounds_measure_the_turned_box(self, font_doc):
+        # Running up the page from near the top, the line leaves through
+        # the top edge
+        text = {"type": "text", "x": 300, "y": 20, "text": "A spine label", "font": "f", "size": 12, "rotation": 90}
+        warnings = rupdf.validate_document(font_doc(text), bounds_check="warn")
+        assert [w["code"] for w in warnings] == ["out_of_bounds"]
+        assert "top edge" in warnings[0]["message"]
+
+
+class TestTextOutline:
+    """Test outlined text reporting."""
+
+    def test_validate_reports_outlined_text(self, font_doc):
+        text = {"type": "text", "id": "title", "x": 300, "y": 200, "text": "Hi", "font": "f", "size": 24,
+                "outline": True}
+        warnings = rupdf.validate_document(font_doc(text))
+        assert [w["code"] for w in warnings] == ["outlined_text"]
+        assert warnings[0]["id"] == "title"
+        assert "can't be selected or searched" in warnings[0]["message"]
+
+
+class TestLeader:
+    """Test leader parsing."""
+
+    LEADER = {"type": "leader", "x1": 72, "x2": 172, "y": 100}
+
+    def test_font_requires_size(self, font_doc):
+        with pytest.raises(rupdf.RupdfError, match="size"):
+            rupdf.render_pdf(font_doc(dict(self.LEADER, font="f")))
+
+    def test_reversed_ends_rejected(self):
+        with pytest.raises(rupdf.RupdfError, match="x2"):
+            rupdf.render_pdf(page_doc(dict(self.LEADER, x1=200)))
+
+    def test_invalid_style(self):
+        with pytest.raises(rupdf.RupdfError, match="Invalid leader style"):
+            rupdf.render_pdf(page_doc(dict(self.LEADER, style="wavy")))
+
+    def test_missing_font(self):
+        with pytest.raises(rupdf.RupdfError, match="nope"):
+            rupdf.render_pdf(page_doc(dict(self.LEADER, font="nope", size=10)))
+
+
+class TestProgressRing:
+    """Test progress_ring parsing."""
+
+    RING = {"type": "progress_ring", "cx": 100, "cy": 100, "radius": 50, "thickness": 8, "value": 0.25}
+
+    def test_value_out_of_range(self):
+        with pytest.raises(rupdf.RupdfError, match="between 0 and 1"):
+            rupdf.render_pdf(page_doc(dict(self.RING, value=1.5)))
+
+    def test_thickness_must_be_positive(self):
+        with pytest.raises(rupdf.RupdfError, match="thickness must be positive"):
+            rupdf.render_pdf(page_doc(dict(self.RING, thickness=0)))
+
+
+class TestElementIds:
+    """Test element ids in errors and warnings."""
+
+    RECT = {"type": "rect", "x": 72, "y": 72, "w": 50, "h": 50}
+
+    def test_parse_error_names_id(self):
+        with pytest.raises(rupdf.RupdfError, match=r"Element 0 \(id 'totals.box'\)"):
+            rupdf.render_pdf(page_doc(dict(self.RECT, id="totals.box", w="wide")))
+
+    def test_id_must_be_string(self):
+        with pytest.raises(rupdf.RupdfError, match="Element 0"):
+            rupdf.render_pdf(page_doc(dict(self.RECT, id=17)))
+
+    def test_render_error_names_id(self, font_doc):
+        text = {"type": "text", "id": "greeting", "x": 72, "y": 72, "text": "Hi \U0001F600",
+                "font": "f", "size": 12, "missing_glyph_policy": "raise"}
+        with pytest.raises(rupdf.RupdfError, match=r"Page 0, element 1 \(id 'greeting'\): Missing glyph"):
+            rupdf.render_pdf(font_doc(self.RECT, text))
+
+    def test_bounds_warning_carries_id(self):
+        [warning] = rupdf.validate_document(page_doc(self.RECT, dict(self.RECT, x=580, id="stamp")))
+        assert warning["id"] == "stamp"
+        assert warning["message"].startswith("Page 0, element 1 (id 'stamp'): rect extends")
+
+    def test_bounds_error_names_id(self):
+        with pytest.raises(rupdf.RupdfError, match=r"element 0 \(id 'stamp'\)"):
+            rupdf.render_pdf(page_doc(dict(self.RECT, x=-10, id="stamp")), bounds_check="error")
+
+    def test_duplicate_ids_flagged_by_validate(self):
+        group = {"type": "group", "elements": [dict(self.RECT, x=200, id="box")]}
+        doc = page_doc(dict(self.RECT, id="box"), dict(self.RECT, x=140, id="other"), group)
+        [warning] = rupdf.validate_document(doc)
+        assert warning["code"] == "duplicate_id"
+        assert (warning["page"], warning["element"], warning["id"]) == (0, 2, "box")
+        assert "first used by page 0, element 0" in warning["message"]
+
+    def test_duplicate_ids_allowed_when_rendering(self):
+        import warnings
+
+        doc = page_doc(dict(self.RECT, id="box"), dict(self.RECT, x=140, id="box"))
+        with warnings.catch_warnings():
+            warnings.simplefilter("error")
+            assert rupdf.render_pdf(doc)[:5] == b"%PDF-"
+
+
+class TestStrokeStyle:
+    """Test stroke style parsing."""
+
+    LINE = {"type": "line", "x1": 0, "y1": 10, "x2": 100, "y2": 10}
+    RECT = {"type": "rect", "x": 10, "y": 10, "w": 50, "h": 50}
+
+    def test_miter_limit_below_one_rejected(self):
+        with pytest.raises(rupdf.RupdfError, match="miter_limit must be at least 1"):
+            rupdf.render_pdf(page_doc(dict(self.RECT, miter_limit=0.5)))
+
+    def test_negative_dash_rejected(self):
+        with pytest.raises(rupdf.RupdfError, match="dash"):
+            rupdf.render_pdf(page_doc(dict(self.LINE, dash=[3, -1])))
+
+    def test_unknown_preset_rejected(self):
+        with pytest.raises(rupdf.RupdfError, match="Invalid dash: 'dashdot'"):
+            rupdf.render_pdf(page_doc(dict(self.LINE, dash="dashdot")))
+
+    def test_invalid_cap_and_join(self):
+        with pytest.raises(rupdf.RupdfError, match="Invalid cap: 'flat'. Must be 'butt', 'round' or 'square'"):
+            rupdf.render_pdf(page_doc(dict(self.LINE, cap="flat")))
+        with pytest.raises(rupdf.RupdfError, match="Invalid join: 'sharp'. Must be 'miter', 'round' or 'bevel'"):
+            rupdf.render_pdf(page_doc(dict(self.RECT, join="sharp")))
+
+
+class TestStrokeAlign:
+    """Test stroke_align parsing and bounds."""
+
+    RECT = {"type": "rect", "x": 0, "y": 0, "w": 200, "h": 50, "stroke": 4}
+
+    def test_inside_stroke_stays_within_bounds(self):
+        assert rupdf.validate_document(page_doc(dict(self.RECT, stroke_align="inside"))) == []
+        [warning] = rupdf.validate_document(page_doc(self.RECT))
+        assert "left edge by 2.0 pt" in warning["message"]
+
+    def test_invalid_value_rejected(self):
+        with pytest.raises(rupdf.RupdfError, match="stroke_align"):
+            rupdf.render_pdf(page_doc(dict(self.RECT, stroke_align="middle")))
+
+
+class TestHairline:
+    """Test stroke keyword parsing."""
+
+    def test_other_strings_rejected(self):
+        line = {"type": "line", "x1": 72, "y1": 72, "x2": 200, "y2": 72, "stroke": "thin"}
+        with pytest.raises(rupdf.RupdfError, match="thin"):
+            rupdf.render_pdf(page_doc(line))
+
+
+class TestArtifacts:
+    """Test artifact parsing."""
+
+    def test_artifact_with_reading_order_rejected(self):
+        rect = {"type": "rect", "x": 0, "y": 0, "w": 10, "h": 10, "artifact": True, "reading_order": 1}
+        with pytest.raises(rupdf.RupdfError, match="reading_order can't be set on an artifact"):
+            rupdf.render_pdf(page_doc(rect))
+
+
+class TestInlineImages:
+    """Test textbox span parsing."""
+
+    TEXTBOX = {"type": "textbox", "x": 72, "y": 72, "w": 200, "h": 100, "font": "f", "size": 12}
+
+    def test_height_required(self, font_doc, png_path):
+        doc = font_doc(dict(self.TEXTBOX, text=["a", {"image_ref": "logo"}]), images={"logo": {"path": png_path}})
+        with pytest.raises(rupdf.RupdfError, match="text span 1: .*Missing required key: 'h'"):
+            rupdf.render_pdf(doc)
+
+    def test_bad_span_rejected(self, font_doc):
+        with pytest.raises(rupdf.RupdfError, match="text span 0: must be a string or a dict"):
+            rupdf.render_pdf(font_doc(dict(self.TEXTBOX, text=[3])))
+
+    def test_unknown_image(self, font_doc, png_path):
+        doc = font_doc(dict(self.TEXTBOX, text=[{"image_ref": "missing", "h": 10}]), images={"logo": {"path": png_path}})
+        with pytest.raises(rupdf.RupdfError, match="missing"):
+            rupdf.render_pdf(doc)
+
+
+class TestCheck:
+    """Test checkbox parsing."""
+
+    def test_invalid_state_rejected(self):
+        with pytest.raises(rupdf.RupdfError, match="state"):
+            rupdf.render_pdf(page_doc({"type": "check", "x": 100, "y": 100, "size": 10, "state": "maybe"}))
+
+
+class TestGroup:
+    """Test group parsing and bounds."""
+
+    RECT = {"type": "rect", "x": 100, "y": 100, "w": 50, "h": 50, "stroke": 0}
+
+    def test_child_errors_name_the_group(self):
+        group = {"type": "group", "elements": [self.RECT, {"type": "rect", "x": 1}]}
+        with pytest.raises(rupdf.RupdfError, match="Element 0: Element 1"):
+            rupdf.render_pdf(page_doc(group))
+
+    def test_transformed_bounds(self):
+        # Turned a quarter about (600, 100), the rects hang off the page's right edge
+        group = {"type": "group", "x": 600, "y": 100, "rotation": -90, "elements": [self.RECT, dict(self.RECT, x=125)]}
+        with pytest.raises(rupdf.RupdfError, match="out of bounds"):
+            rupdf.render_pdf(page_doc(group), bounds_check="error")
+
+    def test_scale_must_be_positive(self):
+        with pytest.raises(rupdf.RupdfError, match="scale must be positive"):
+            rupdf.render_pdf(page_doc({"type": "group", "scale": 0, "elements": [self.RECT]}))
+
+
+class TestFragment:
+    """Test fragment parsing."""
+
+    BOX = [{"type": "rect", "x": 0, "y": 0, "w": 50, "h": 20, "stroke": 0}]
+    PLACE = {"type": "fragment", "fragment_ref": "box", "x": 0, "y": 0}
+
+    def test_later_fragment_not_visible(self):
+        doc = page_doc(fragments={"pair": [self.PLACE], "box": self.BOX})
+        with pytest.raises(rupdf.RupdfError, match="fragments.pair: .*unknown fragment 'box'"):
+            rupdf.render_pdf(doc)
+
+    def test_unknown_fragment(self):
+        doc = page_doc(dict(self.PLACE, fragment_ref="nope"), fragments={"box": self.BOX})
+        with pytest.raises(rupdf.RupdfError, match="unknown fragment 'nope'"):
+            rupdf.render_pdf(doc)
+
+    def test_notes_rejected(self):
+        doc = page_doc(fragments={"box": [{"type": "note", "x": 0, "y": 0, "contents": "hi"}]})
+        with pytest.raises(rupdf.RupdfError, match="notes can't be used in fragments"):
+            rupdf.render_pdf(doc)
+
+    def test_scale_must_be_positive(self):
+        with pytest.raises(rupdf.RupdfError, match="scale must be positive"):
+            rupdf.render_pdf(page_doc(dict(self.PLACE, scale=0), fragments={"box": self.BOX}))
+
+    def test_bounds_check_uses_placement(self):
+        doc = page_doc(dict(self.PLACE, x=700, y=100), fragments={"box": self.BOX})
+        with pytest.raises(rupdf.RupdfError, match="outside"):
+            rupdf.render_pdf(doc, bounds_check="error")
+
+
+class TestImageProbing:
+    """Test that raster images are sized from headers and decoded once."""
+
+    IMAGE = {"type": "image", "x": 72, "y": 72, "w": 100, "image_ref": "img"}
+
+    def test_damaged_pixels_fail_at_render(self, png_bytes):
+        doc = page_doc(self.IMAGE, images={"img": {"bytes": png_bytes[: len(png_bytes) // 2]}})
+        rupdf.validate_document(doc)
+        with pytest.raises(rupdf.RupdfError, match="img"):
+            rupdf.render_pdf(doc)
+
+    def test_bad_header_fails_at_load(self):
+        with pytest.raises(rupdf.RupdfError, match="img"):
+            rupdf.validate_document(page_doc(self.IMAGE, images={"img": {"bytes": b"not an image"}}))
+
+
+class TestGifFrames:
+    """Test GIF frame parsing."""
+
+    IMAGE = {"type": "image", "x": 72, "y": 72, "image_ref": "anim"}
+
+    def test_frame_out_of_range(self, gif_path):
+        with pytest.raises(rupdf.RupdfError, match="has 3 frames"):
+            rupdf.validate_document(page_doc(self.IMAGE, images={"anim": {"path": gif_path, "frame": 3}}))
+
+    def test_frame_on_png(self, png_path):
+        with pytest.raises(rupdf.RupdfError, match="only GIF"):
+            rupdf.validate_document(page_doc(self.IMAGE, images={"anim": {"path": png_path, "frame": 1}}))
+
+
+class TestRemoteResources:
+    """Test that URL sources are never fetched without allow_remote."""
+
+    URL = "http://127.0.0.1:9/logo.png"
+    IMAGE = {"type": "image", "x": 72, "y": 72, "w": 100, "image_ref": "logo"}
+
+    def test_url_needs_allow_remote(self):
+        doc = page_doc(self.IMAGE, images={"logo": {"url": self.URL}})
+        with pytest.raises(rupdf.RupdfError, match="allow_remote=True"):
+            rupdf.render_pdf(doc)
+        with pytest.raises(rupdf.RupdfError, match="allow_remote=True"):
+            rupdf.validate_document(doc)
+
+    def test_font_url_needs_allow_remote(self):
+        with pytest.raises(rupdf.RupdfError, match="127.0.0.1"):
+            rupdf.inspect_font({"url": self.URL})
+
+    def test_url_with_path_rejected(self, png_path):
+        doc = page_doc(self.IMAGE, images={"logo": {"url": self.URL, "path": png_path}})
+        with pytest.raises(rupdf.RupdfError, match="only one is allowed"):
+            rupdf.render_pdf(doc)
+
+    def test_fetch_failure_names_url(self):
+        # Either the build lacks the feature or nothing listens on port 9
+        doc = page_doc(self.IMAGE, images={"logo": {"url": self.URL}})
+        with pytest.raises(rupdf.RupdfError, match="Failed to fetch 'http://127.0.0.1:9/logo.png'"):
+            rupdf.render_pdf(doc, allow_remote={"timeout": 2})
+
+    def test_invalid_timeout(self):
+        doc = page_doc(self.IMAGE, images={"logo": {"url": self.URL}})
+        with pytest.raises(ValueError, match="timeout"):
+            rupdf.render_pdf(doc, allow_remote={"timeout": 0})
+
+
+class TestImageFade:
+    """Test image fade parsing."""
+
+    def test_invalid_range_rejected(self, png_path):
+        image = {"type": "image", "x": 100, "y": 100, "w": 200, "h": 100, "image_ref": "hero",
+                 "fade": {"start": 0.8, "end": 0.2}}
+        with pytest.raises(rupdf.RupdfError, match="start"):
+            rupdf.render_pdf(page_doc(image, images={"hero": {"path": png_path}}))
+
+
+class TestImageRotation:
+    """Test image rotation bounds."""
+
+    def test_bounds_measure_the_turned_box(self, png_path):
+        # Turned about its top-left corner, the image swings left of x
+        image = {"type": "image", "x": 50, "y": 100, "w": 200, "h": 100, "image_ref": "logo", "rotation": 90}
+        warnings = rupdf.validate_document(page_doc(image, images={"logo": {"path": png_path}}), bounds_check="warn")
+        assert [w["code"] for w in warnings] == ["out_of_bounds"]
+        assert "left edge" in warnings[0]["message"]
+
+
+class TestImageQuad:
+    """Test quad parsing and bounds."""
+
+    IMAGE = {"type": "image", "image_ref": "logo"}
+
+    def test_collinear_corners_rejected(self, png_path):
+        doc = page_doc(dict(self.IMAGE, quad=[(0, 0), (100, 100), (50, 50)]), images={"logo": {"path": png_path}})
+        with pytest.raises(rupdf.RupdfError, match="collinear"):
+            rupdf.render_pdf(doc)
+
+    def test_needs_three_corners(self, png_path):
+        doc = page_doc(dict(self.IMAGE, quad=[(0, 0), (100, 0)]), images={"logo": {"path": png_path}})
+        with pytest.raises(rupdf.RupdfError, match="3 corners"):
+            rupdf.render_pdf(doc)
+
+    def test_bounds_measure_the_corners(self, png_path):
+        doc = page_doc(dict(self.IMAGE, quad=[(500, 100), (700, 100), (500, 200)]), images={"logo": {"path": png_path}})
+        warnings = rupdf.validate_document(doc, bounds_check="warn")
+        assert [w["code"] for w in warnings] == ["out_of_bounds"]
+
+
+class TestImageClip:
+    """Test image clip parsing."""
+
+    def test_unknown_shape_rejected(self, png_path):
+        image = {"type": "image", "image_ref": "logo", "x": 100, "y": 100, "w": 200, "h": 100, "clip": "square"}
+        with pytest.raises(rupdf.RupdfError, match="clip must be"):
+            rupdf.render_pdf(page_doc(image, images={"logo": {"path": png_path}}))
+
+
+class TestSpotColors:
+    """Test spot color parsing."""
+
+    RECT = {"type": "rect", "x": 100, "y": 100, "w": 50, "h": 50}
+
+    def test_tint_out_of_range_rejected(self):
+        fill = {"spot": "PANTONE 213 C", "alt": (230, 0, 126, 255), "tint": 1.5}
+        with pytest.raises(rupdf.RupdfError, match="tint"):
+            rupdf.render_pdf(page_doc(dict(self.RECT, fill_color=fill)))
+
+    def test_alt_required(self):
+        with pytest.raises(rupdf.RupdfError, match="alt"):
+            rupdf.render_pdf(page_doc(dict(self.RECT, fill_color={"spot": "Gold"})))
+
+
+class TestCMYKColors:
+    """Test CMYK color parsing."""
+
+    RECT = {"type": "rect", "x": 100, "y": 100, "w": 50, "h": 50, "stroke": 1}
+
+    def test_out_of_range_rejected(self):
+        with pytest.raises(rupdf.RupdfError, match="between 0 and 1"):
+            rupdf.render_pdf(page_doc(dict(self.RECT, fill_color=(0, 50, 100, 0, 255))))
+
+    def test_wrong_arity_rejected(self):
+        with pytest.raises(rupdf.RupdfError, match="5-tuple"):
+            rupdf.render_pdf(page_doc(dict(self.RECT, fill_color=(0, 0))))
+
+
+class TestOutputIntent:
+    """Test output intent parsing."""
+
+    def test_condition_required(self):
+        with pytest.raises(rupdf.RupdfError, match="output_intent"):
+            rupdf.render_pdf(page_doc(output_intent={"bytes": icc_header(b"RGB ")}))
+
+    def test_invalid_profile_rejected(self):
+        with pytest.raises(rupdf.RupdfError, match="not an ICC profile"):
+            rupdf.render_pdf(page_doc(output_intent={"bytes": b"not a profile", "condition": "x"}))
+
+    def test_unsupported_color_space_rejected(self):
+        with pytest.raises(rupdf.RupdfError, match="Lab"):
+            rupdf.render_pdf(page_doc(output_intent={"bytes": icc_header(b"Lab "), "condition": "x"}))
+
+
+class TestInspectFont:
+    """Test font metrics inspection and coverage checks."""
+
+    def test_inspect_reports_metrics(self, font_path):
+        info = rupdf.inspect_font({"path": font_path})
+        assert info["postscript_name"] == "IBMPlexSans"
+        assert info["units_per_em"] == 1000
+        assert info["ascender"] > 0 > info["descender"]
+        assert 0 < info["cap_height"] < info["ascender"]
+        assert info["num_glyphs"] > 100
+        assert info["outlines"] == "cff"
+
+    def test_inspect_accepts_bytes(self, font_path, font_bytes):
+        assert rupdf.inspect_font({"bytes": font_bytes}) == rupdf.inspect_font({"path": font_path})
+
+    def test_truetype_outlines(self, emoji_font_path):
+        assert rupdf.inspect_font({"path": emoji_font_path})["outlines"] == "truetype"
+
+    def test_heights_from_os2(self, font_path):
+        info = rupdf.inspect_font({"path": font_path})
+        assert 0 < info["x_height"] < info["cap_height"]
+        assert (info["cap_height_source"], info["cap_height_glyph"]) == ("os2", None)
+        assert info["x_height_source"] == "os2"
+
+    def test_cjk_cap_height_measured_from_ideograph(self, cjk_font_path):
+        info = rupdf.inspect_font({"path": cjk_font_path})
+        assert (info["cap_height"], info["cap_height_source"], info["cap_height_glyph"]) == (840, "glyph", "\u6c34")
+        assert (info["x_height_source"], info["x_height_glyph"]) == ("estimate", None)
+
+    def test_cap_height_glyphs(self, cjk_font_path):
+        info = rupdf.inspect_font({"path": cjk_font_path, "cap_height_glyphs": "\u5b57"})
+        assert (info["cap_height"], info["cap_height_glyph"]) == (830, "\u5b57")
+
+    def test_covers_lists_missing_once(self, font_path):
+        assert rupdf.font_covers({"path": font_path}, "Hi \u4e2d\n\u4e2d!") == ["\u4e2d"]
+
+    def test_covers_everything(self, font_path):
+        assert rupdf.font_covers({"path": font_path}, "Hello, world") == []
+
+    def test_invalid_font_raises(self):
+        with pytest.raises(rupdf.RupdfError):
+            rupdf.inspect_font({"bytes": b"not a font"})
+
+    def test_missing_source_raises(self):
+        with pytest.raises(rupdf.RupdfError, match="'path', 'bytes' or 'url'"):
+            rupdf.font_covers({}, "x")
+
+
+class TestExtractContent:
+    """Test reading rendered output back."""
+
+    def test_centered_text_midpoint_is_x(self, font_doc):
+        text = {"type": "text", "x": 300, "y": 72, "text": "Invoice 2024", "font": "f", "size": 20,
+                "align": "center", "color": (255, 0, 0, 255)}
+        for compress in (False, True):
+            [page] = rupdf.extract_content(rupdf.render_pdf(font_doc(text), compress=compress))["pages"]
+            [title] = page["text"]
+            assert title["text"] == "Invoice 2024"
+            assert title["size"] == 20
+            assert title["y"] == pytest.approx(72)
+            assert title["x"] + title["width"] / 2 == pytest.approx(300, abs=0.01)
+            assert title["color"] == [1, 0, 0]
+
+    def test_rects_and_lines_in_document_coordinates(self):
+        doc = page_doc(
+            {"type": "rect", "x": 10, "y": 20, "w": 50, "h": 30, "stroke": 0, "fill_color": (0, 0, 255, 255)},
+            {"type": "line", "x1": 0, "y1": 100, "x2": 200, "y2": 100, "stroke": 2},
+        )
+        [page] = rupdf.extract_content(rupdf.render_pdf(doc))["pages"]
+        assert page["width"] == 612 and page["height"] == 792
+        [rect] = page["rects"]
+        assert (rect["x"], rect["y"], rect["w"], rect["h"]) == (10, 20, 50, 30)
+        assert rect["fill"] == [0, 0, 1] and rect["stroke"] is None
+        [line] = page["lines"]
+        assert (line["x1"], line["y1"], line["x2"], line["y2"], line["width"]) == (0, 100, 200, 100, 2)
+
+    def test_wrapped_lines_and_groups(self, font_doc):
+        doc = font_doc(
+            {"type": "textbox", "x": 72, "y": 72, "w": 80, "h": 100, "text": "one two three four",
+             "font": "f", "size": 12, "text_align_y": "top"},
+            {"type": "group", "transparency_group": True, "opacity": 0.5, "elements": [
+                {"type": "text", "x": 72, "y": 300, "text": "inside", "font": "f", "size": 12},
+            ]},
+        )
+        [page] = rupdf.extract_content(rupdf.render_pdf(doc))["pages"]
+        texts = [t["text"] for t in page["text"]]
+        assert texts[-1] == "inside"
+        assert " ".join(texts[:-1]) == "one two three four"
+        assert len({t["y"] for t in page["text"][:-1]}) == len(texts) - 1
+
+    def test_rejects_other_bytes(self):
+        with pytest.raises(rupdf.RupdfError, match="can't read PDF"):
+            rupdf.extract_content(b"not a pdf")
+
+
+class TestMeasureBarcode:
+    """Test barcode measurement without rendering."""
+
+    def test_code128_modules(self):
+        m = rupdf.measure_barcode("ABC")
+        # start, 3 data, check: 11 modules each, plus a 13-module stop
+        assert m["modules"] == 68
+        assert m["width"] == 68
+        assert m["quiet_zone_modules"] == 10
+        assert m["checksum_added"] is True
+        assert m["human_readable"] == "ABC"
+
+    def test_module_width(self):
+        m = rupdf.measure_barcode("ABC", "code128", {"module_width": 0.5})
+        assert m["width"] == 34
+        assert m["min_width"] == 44
+
+    def test_module_width_units(self):
+        m = rupdf.measure_barcode("ABC", options={"module_width": "1in"})
+        assert m["width"] == pytest.approx(68 * 72)
+
+    def test_gs1_human_readable(self):
+        m = rupdf.measure_barcode("(01)12345678901234(10)AB1", "gs1_128")
+        assert m["human_readable"] == "(01)12345678901234(10)AB1"
+        # start, FNC1, 21 data characters and check, then the stop
+        assert m["modules"] == 24 * 11 + 13
+
+    def test_invalid_value_raises(self):
+        with pytest.raises(rupdf.RupdfError):
+            rupdf.measure_barcode("(01)123", "gs1_128")
+
+    def test_invalid_symbology_raises(self):
+        with pytest.raises(ValueError, match="Invalid symbology"):
+            rupdf.measure_barcode("ABC", "ean13")
+
+
+class TestBarcodeFont:
+    """Test the font behind a barcode's human-readable text."""
+
+    BARCODE = {"type": "barcode", "x": 72, "y": 72, "w": 200, "h": 60, "value": "ABC-123", "human_readable": True}
+
+    def _doc(self, fonts, **barcode):
+        # On the second page, so errors name it
+        return {
+            "pages": [{"size": (612, 792)}, {"size": (612, 792), "elements": [dict(self.BARCODE, **barcode)]}],
+            "resources": {"fonts": fonts},
+        }
+
+    def test_implicit_mono_is_named_at_parse_time(self, font_path):
+        doc = self._doc({"a": {"path": font_path}, "b": {"path": font_path}})
+        with pytest.raises(rupdf.RupdfError, match=r"Page 1: .*Element 0: .*defaulted to 'mono'.*registered: a, b"):
+            rupdf.validate_document(doc)
+
+    def test_explicit_font_must_exist(self):
+        with pytest.raises(rupdf.RupdfError, match="needs font 'ocr', .*none are registered"):
+            rupdf.render_pdf(self._doc({}, font="ocr"))
+
+    def test_mono_wins_over_other_fonts(self, font_path):
+        doc = self._doc({"body": {"path": font_path}, "mono": {"path": font_path}})
+        assert rupdf.validate_document(doc) == []
+
+    def test_font_only_checked_for_human_readable(self):
+        rupdf.render_pdf(self._doc({}, human_readable=False, font="ocr"))
+
+    def test_non_positive_module_width_raises(self):
+        with pytest.raises(ValueError, match="module_width"):
+            rupdf.measure_barcode("ABC", options={"module_width": 0})
+
+
+class TestBarcodeRotation:
+    """Test barcode rotation parsing and bounds."""
+
+    BARCODE = {"type": "barcode", "x": 100, "y": 100, "w": 200, "h": 60, "value": "RUPDF-1"}
+
+    def test_only_quarter_turns(self):
+        with pytest.raises(rupdf.RupdfError, match="barcode rotation must be 0, 90, 180 or 270, got 45"):
+            rupdf.render_pdf(page_doc(dict(self.BARCODE, rotation=45)))
+
+    def test_bounds_measure_the_turned_box(self):
+        warnings = rupdf.validate_document(page_doc(dict(self.BARCODE, x=50, rotation=90)), bounds_check="warn")
+        assert [w["code"] for w in warnings] == ["out_of_bounds"]
+        assert "left edge by 10.0 pt" in warnings[0]["message"]
+
+
+class TestBarcodeTextOverflow:
+    """Test barcode text overflow parsing."""
+
+    BARCODE = {"type": "barcode", "x": 100, "y": 100, "w": 120, "h": 60, "value": "RUPDF-LONG-VALUE-123",
+               "human_readable": True, "font": "mono", "font_size": 14}
+
+    def test_text_min_size_above_font_size_raises(self, font_path):
+        doc = page_doc(dict(self.BARCODE, text_min_size=20), fonts={"mono": {"path": font_path}})
+        with pytest.raises(rupdf.RupdfError, match="text_min_size must be positive and at most font_size 14"):
+            rupdf.render_pdf(doc)
+
+    def test_invalid_text_overflow_raises(self, font_path):
+        doc = page_doc(dict(self.BARCODE, text_overflow="wrap"), fonts={"mono": {"path": font_path}})
+        with pytest.raises(rupdf.RupdfError, match="Invalid overflow: 'wrap'"):
+            rupdf.render_pdf(doc)
+
+
+class TestRenderJob:
+    """Test PdfRenderJob page-by-page rendering."""
+
+    def _doc(self, font_path, pages=3):
+        return {
+            "pages": [
+                {"size": (300, 300), "elements": [
+                    {"type": "text", "x": 20, "y": 40, "text": f"Page {i}", "font": "f", "size": 12},
+                ]}
+                for i in range(pages)
+            ],
+            "resources": {"fonts": {"f": {"path": font_path}}},
+        }
+
+    def test_iteration_yields_each_page(self, font_path):
+        doc = self._doc(font_path)
+        doc["pages"][1]["repeat"] = 2
+        job = rupdf.PdfRenderJob(doc, compress=False)
+        statuses = list(job)
+        assert statuses == [
+            {"index": 0, "copies": 1, "total": 3},
+            {"index": 1, "copies": 2, "total": 3},
+            {"index": 2, "copies": 1, "total": 3},
+        ]
+        pdf = job.result()
+        assert pdf.startswith(b"%PDF-")
+        assert b"/Count 4" in pdf
+        assert job.result() == pdf
+
+    def test_result_without_iterating(self, font_path):
+        job = rupdf.PdfRenderJob(self._doc(font_path), compress=False)
+        pdf = job.result()
+        assert b"/Count 3" in pdf
+        assert list(job) == []
+
+    def test_partial_iteration_then_result(self, font_path):
+        job = rupdf.PdfRenderJob(self._doc(font_path, pages=5))
+        assert next(job)["index"] == 0
+        assert rupdf.PdfRenderJob is type(job)
+        assert job.result().startswith(b"%PDF-")
+
+    def test_invalid_document_raises_on_creation(self, font_path):
+        doc = self._doc(font_path)
+        doc["pages"][0]["elements"].append({"type": "circle"})
+        with pytest.raises(rupdf.RupdfError, match="circle"):
+            rupdf.PdfRenderJob(doc)
+
+    def test_page_error_raises_from_iteration(self, font_path, png_bytes):
+        doc = self._doc(font_path)
+        doc["resources"]["images"] = {"img": {"bytes": png_bytes[: len(png_bytes) // 2]}}
+        doc["pages"][2]["elements"].append(
+            {"type": "image", "x": 10, "y": 10, "w": 50, "image_ref": "img"}
+        )
+        job = rupdf.PdfRenderJob(doc)
+        # Images are written before the pages, so the first step fails
+        with pytest.raises(rupdf.RupdfError, match="img"):
+            next(job)
+        with pytest.raises(rupdf.RupdfError, match="img"):
+            job.result()
+
+    def test_dropping_a_running_job(self, font_path):
+        job = rupdf.PdfRenderJob(self._doc(font_path, pages=10))
+        next(job)
+        del job
+
+
+class TestConcurrentRendering:
+    """Test render_pdf from many threads at once."""
+
+    def _doc(self, i, font_path):
+        return {
+            "metadata": {"title": f"Document {i}"},
+            "pages": [{"size": (300, 300), "repeat": i + 1, "elements": [
+                {"type": "text", "x": 20, "y": 40, "text": "Hello", "font": "f", "size": 10 + i},
+            ]}],
+            "resources": {"fonts": {"f": {"path": font_path}}},
+        }
+
+    def test_threads_get_their_own_output(self, font_path):
+        from concurrent.futures import ThreadPoolExecutor
+
+        docs = [self._doc(i, font_path) for i in range(16)]
+        with ThreadPoolExecutor(max_workers=8) as pool:
+            pdfs = list(pool.map(lambda doc: rupdf.render_pdf(doc, compress=False), docs))
+        for i, pdf in enumerate(pdfs):
+            assert f"(Document {i})".encode() in pdf
+            assert f"/Count {i + 1}".encode() in pdf
+            assert f" {10 + i} Tf".encode() in pdf
+
+    def test_errors_stay_with_their_call(self, font_path):
+        from concurrent.futures import ThreadPoolExecutor
+
+        def render(i):
+            doc = self._doc(i, font_path)
+            if i % 2:
+                doc["pages"][0]["elements"][0]["font"] = "missing"
+            try:
+                rupdf.render_pdf(doc)
+                return None
+            except rupdf.RupdfError as e:
+                return str(e)
+
+        with ThreadPoolExecutor(max_workers=8) as pool:
+            results = list(pool.map(render, range(16)))
+        assert all((result is None) == (i % 2 == 0) for i, result in enumerate(results))
+
+
+class TestMarkup:
+    """Test markup parsing."""
+
+    TEXTBOX = {"type": "textbox", "x": 72, "y": 72, "w": 300, "h": 100, "font": "f", "size": 12, "markup": True}
+    STYLES = {"bold": {"font": "f"}, "red": {"color": (255, 0, 0, 255)}}
+
+    def test_unbalanced_markup_names_offset(self, font_doc):
+        with pytest.raises(rupdf.RupdfError, match=r"Element 0: .*markup: unclosed '\*\*' at offset 5"):
+            rupdf.render_pdf(font_doc(dict(self.TEXTBOX, text="Pick **4 of"), styles=self.STYLES))
+        with pytest.raises(rupdf.RupdfError, match=r"'\{/red\}' at offset 3 closes nothing"):
+            rupdf.render_pdf(font_doc(dict(self.TEXTBOX, text="SKU{/red}"), styles=self.STYLES))
+
+    def test_unknown_style(self, font_doc):
+        with pytest.raises(rupdf.RupdfError, match="unknown style 'blue' at offset 0"):
+            rupdf.render_pdf(font_doc(dict(self.TEXTBOX, text="{blue}x{/blue}"), styles=self.STYLES))
+
+    def test_style_needs_font_or_color(self, font_doc):
+        with pytest.raises(rupdf.RupdfError, match="styles.red: .*a style needs a font or a color"):
+            rupdf.render_pdf(font_doc(dict(self.TEXTBOX, text="x"), styles={"red": {}}))
+
+
+class TestStrictKeys:
+    """Test rejecting unknown keys with strict_keys."""
+
+    TEXT = {"type": "text", "x": 72, "y": 72, "text": "Hello", "font": "f", "size": 12}
+
+    def test_valid_document_passes(self, font_doc):
+        doc = font_doc(self.TEXT, strict_keys=True, metadata={"title": "T"},
+                       defaults={"text": {"size": 10}, "color": (0, 0, 0, 255)})
+        assert rupdf.render_pdf(doc).startswith(b"%PDF")
+
+    def test_element_typo_suggests_key(self, font_doc):
+        doc = font_doc(dict(self.TEXT, colour=(255, 0, 0)), strict_keys=True)
+        with pytest.raises(rupdf.RupdfError, match="Element 0: .*unknown key 'colour' for text elements; did you mean 'color'"):
+            rupdf.render_pdf(doc)
+
+    def test_barcode_typo(self):
+        barcode = {"type": "barcode", "x": 72, "y": 72, "w": 200, "h": 50, "value": "ABC", "front_size": 8}
+        with pytest.raises(rupdf.RupdfError, match="did you mean 'font_size'"):
+            rupdf.render_pdf(page_doc(barcode, strict_keys=True))
+
+    def test_key_of_another_type(self):
+        rect = {"type": "rect", "x": 72, "y": 72, "w": 10, "h": 10, "font": "f"}
+        with pytest.raises(rupdf.RupdfError, match="unknown key 'font' for rect elements"):
+            rupdf.render_pdf(page_doc(rect, strict_keys=True))
+
+    def test_group_children_are_checked(self):
+        group = {"type": "group", "elements": [{"type": "rect", "x": 72, "y": 72, "w": 10, "h": 10, "fil_color": (0, 0, 0)}]}
+        with pytest.raises(rupdf.RupdfError, match="did you mean 'fill_color'"):
+            rupdf.render_pdf(page_doc(group, strict_keys=True))
+
+    def test_page_typo(self):
+        doc = page_doc(page={"backgroud": (255, 255, 255)}, strict_keys=True)
+        with pytest.raises(rupdf.RupdfError, match="Page 0: .*unknown key 'backgroud' for pages; did you mean 'background'"):
+            rupdf.render_pdf(doc)
+
+    def test_document_typo(self):
+        with pytest.raises(rupdf.RupdfError, match="unknown key 'metdata' for the document; did you mean 'metadata'"):
+            rupdf.render_pdf(page_doc(strict_keys=True, metdata={"title": "T"}))
+
+    def test_metadata_and_resources(self, font_path):
+        with pytest.raises(rupdf.RupdfError, match="unknown key 'titel' for metadata; did you mean 'title'"):
+            rupdf.render_pdf(page_doc(strict_keys=True, metadata={"titel": "T"}))
+        with pytest.raises(rupdf.RupdfError, match="unknown key 'pth' for font 'f'; did you mean 'path'"):
+            rupdf.render_pdf(page_doc(strict_keys=True, fonts={"f": {"pth": font_path}}))
+
+    def test_defaults_are_checked(self):
+        with pytest.raises(rupdf.RupdfError, match="defaults.text: .*unknown key 'sise'"):
+            rupdf.render_pdf(page_doc(strict_keys=True, defaults={"text": {"sise": 10}}))
+        with pytest.raises(rupdf.RupdfError, match="defaults: unknown key 'sise' for any element type"):
+            rupdf.render_pdf(page_doc(strict_keys=True, defaults={"sise": 10}))
+
+    def test_unknown_keys_ignored_without_strict(self, font_doc):
+        doc = font_doc(dict(self.TEXT, colour=(255, 0, 0)), strict_keys=False, metdata={})
+        assert rupdf.render_pdf(doc).startswith(b"%PDF")
+
+
+class TestSvgSources:
+    """Test SVG source parsing."""
+
+    SVG = '<svg xmlns="http://www.w3.org/2000/svg" width="40" height="20"><rect width="40" height="20"/></svg>'
+    IMAGE = {"type": "image", "x": 72, "y": 72, "w": 40, "h": 20, "image_ref": "chart"}
+
+    def test_one_source_only(self):
+        doc = page_doc(self.IMAGE, images={"chart": {"svg": self.SVG, "bytes": b"x"}})
+        with pytest.raises(rupdf.RupdfError, match="more than one of 'path', 'bytes', 'url' and 'svg'"):
+            rupdf.render_pdf(doc)
+
+    def test_invalid_svg_string(self):
+        with pytest.raises(rupdf.RupdfError, match="Failed to parse SVG"):
+            rupdf.render_pdf(page_doc(self.IMAGE, images={"chart": {"svg": "<svg"}}))
+
+    def test_undecodable_bytes_name_both_readings(self):
+        with pytest.raises(rupdf.RupdfError, match=r"as a raster image \(.*\) or as SVG \("):
+            rupdf.render_pdf(page_doc(self.IMAGE, images={"chart": {"bytes": b"<html></html>"}}))
+
+
+class TestColorStrings:
+    """Test color string parsing."""
+
+    RECT = {"type": "rect", "x": 72, "y": 72, "w": 100, "h": 50, "stroke": 0}
+
+    def test_unknown_palette_name_lists_palette(self):
+        doc = page_doc(dict(self.RECT, fill_color="brand_primry"), palette={"brand_secondary": "red", "brand_primary": "blue"})
+        with pytest.raises(rupdf.RupdfError, match="Unknown color 'brand_primry'. Palette colors: brand_primary, brand_secondary"):
+            rupdf.render_pdf(doc)
+
+    def test_invalid_hex(self):
+        with pytest.raises(rupdf.RupdfError, match="Invalid hex color '#12345'"):
+            rupdf.render_pdf(page_doc(dict(self.RECT, fill_color="#12345")))
+
+    def test_invalid_palette_entry(self):
+        with pytest.raises(rupdf.RupdfError, match="palette.brand: .*Unknown color 'bleu'"):
+            rupdf.render_pdf(page_doc(dict(self.RECT, fill_color="brand"), palette={"brand": "bleu"}))
+
+
+class TestRenderStats:
+    """Test render_pdf(return_stats=True)."""
+
+    ELEMENTS = [
+        {"type": "text", "x": 72, "y": 72, "text": "Hello", "font": "f", "size": 12},
+        {"type": "image", "x": 72, "y": 100, "w": 50, "h": 50, "image_ref": "logo"},
+        {"type": "image", "x": 72, "y": 200, "w": 100, "h": 100, "image_ref": "logo"},
+    ]
+
+    def test_default_returns_bytes(self, font_doc, png_path):
+        doc = font_doc(*self.ELEMENTS, images={"logo": {"path": png_path}}, page={"repeat": 2})
+        assert isinstance(rupdf.render_pdf(doc), bytes)
+
+    def test_stats(self, font_doc, png_path):
+        doc = font_doc(*self.ELEMENTS, images={"logo": {"path": png_path}}, page={"repeat": 2})
+        pdf, stats = rupdf.render_pdf(doc, return_stats=True)
+        assert pdf[:5] == b"%PDF-"
+        assert stats["pages"] == 2
+        assert stats["bytes"] == len(pdf)
+        assert 0 < stats["content_bytes"] < len(pdf)
+        assert stats["warnings"] == 0
+
+        font = stats["fonts"]["f"]
+        assert font["embedded"]
+        assert font["glyphs"] >= 4  # H, e, l, o
+        assert 0 < font["bytes"] < len(pdf)
+
+        images = stats["images"]
+        assert [image["name"] for image in images] == ["logo", "logo"]
+        for image in images:
+            assert image["encoding"] == "jpeg"
+            assert image["width"] > 0 and image["height"] > 0
+            assert image["dpi"] <= 310
+            assert image["bytes"] > 0
+
+    def test_svg_has_no_pixels(self, svg_path):
+        image = {"type": "image", "x": 72, "y": 72, "w": 100, "h": 100, "image_ref": "chart"}
+        _, stats = rupdf.render_pdf(page_doc(image, images={"chart": {"path": svg_path}}), return_stats=True)
+        (image,) = stats["images"]
+        assert image["encoding"] == "svg"
+        assert image["width"] is None and image["dpi"] is None
+
+    def test_warnings_counted(self):
+        rect = {"type": "rect", "x": 600, "y": 72, "w": 100, "h": 50}
+        with pytest.warns(rupdf.RupdfWarning):
+            _, stats = rupdf.render_pdf(page_doc(rect), bounds_check="warn", return_stats=True)
+        assert stats["warnings"] == 1
+
+
+class TestPageIndex:
+    """Test render_pdf_with_index."""
+
+    def _doc(self, **first):
+        pages = [
+            dict({"size": (612, 792), "tag": {"customer": 7}, "repeat": 2, "elements": []}, **first),
+            {"size": (612, 792), "tag": "B-2", "elements": []},
+            {"size": (612, 792), "elements": []},
+        ]
+        return {"pages": pages}
+
+    def test_tags_follow_pages(self):
+        doc = self._doc()
+        pdf, index = rupdf.render_pdf_with_index(doc)
+        assert [entry["tag"] for entry in index] == [{"customer": 7}, {"customer": 7}, "B-2", None]
+        assert index[0]["tag"] is doc["pages"][0]["tag"]
+        assert [entry["page"] for entry in index] == [0, 1, 2, 3]
+
+    def test_offsets_point_at_content_streams(self):
+        pdf, index = rupdf.render_pdf_with_index(self._doc())
+        for entry in index:
+            header = b"%d 0 obj" % entry["content_object"]
+            assert pdf[entry["content_offset"]:].startswith(header)
+            assert b"\n%d 0 obj\n<<\n  /Type /Page\n" % entry["object"] in pdf
+        # Copies of a repeated page share their content stream
+        assert index[0]["content_object"] == index[1]["content_object"]
+        assert index[0]["object"] != index[1]["object"]
+
+    def test_continuation_pages_share_the_tag(self, font_doc):
+        rows = [["Row %d" % i] for i in range(200)]
+        table = {"type": "table", "x": 72, "y": 72, "rows": rows, "column_widths": [200], "font": "f", "size": 10}
+        _, index = rupdf.render_pdf_with_index(font_doc(table, page={"tag": "long"}))
+        assert len(index) > 1
+        assert all(entry["tag"] == "long" for entry in index)
+
+    def test_takes_render_pdf_arguments(self):
+        pdf, index, stats = rupdf.render_pdf_with_index(
+            self._doc(), return_stats=True, debug_uncompressed=True)
+        assert stats["pages"] == len(index) == 4
+        assert b"/FlateDecode" not in pdf
+
+        doc = self._doc(elements=[{"type": "rect", "x": 600, "y": 72, "w": 100, "h": 50}])
+        with pytest.raises(rupdf.RupdfError, match="out of bounds"):
+            rupdf.render_pdf_with_index(doc, bounds_check="error")
+
+
+class TestPolygon:
+    """Test polygon parsing and bounds."""
+
+    POLYGON = {"type": "polygon", "points": [(100, 100), (200, 100), (150, 50)]}
+
+    def test_too_few_points(self):
+        with pytest.raises(rupdf.RupdfError, match="Element 0: polygon needs at least 3 points, got 2"):
+            rupdf.render_pdf(page_doc(dict(self.POLYGON, points=[(0, 0), (10, 10)])))
+
+    def test_invalid_fill_rule(self):
+        with pytest.raises(rupdf.RupdfError, match="Invalid fill_rule: 'odd'"):
+            rupdf.render_pdf(page_doc(dict(self.POLYGON, fill_rule="odd")))
+
+    def test_bounds(self):
+        warnings = rupdf.validate_document(page_doc(dict(self.POLYGON, points=[(500, 100), (700, 100), (600, 50)])))
+        assert [w["code"] for w in warnings] == ["out_of_bounds"]
+
+
+class TestCollectErrors:
+    """Test collecting every parse error before failing."""
+
+    def _doc(self):
+        return {
+            "fragments": {"broken": {"width": 10, "height": 10, "elements": [{"type": "rect"}]}},
+            "pages": [
+                {"size": (612, 792), "elements": [
+                    {"type": "rect", "x": 0, "y": 0, "w": 10, "h": 10},
+                    {"type": "polygon", "id": "tri", "points": [(0, 0), (1, 1)]},
+                    {"type": "rect", "x": 0, "y": 0},
+                ]},
+                {"size": (-1, 792)},
+                {"size": (612, 792), "elements": ["not a dict"]},
+            ],
+            "repeating_elements": [{"type": "hexagon"}],
+        }
+
+    def test_fail_fast_by_default(self):
+        with pytest.raises(rupdf.RupdfError) as exc_info:
+            rupdf.render_pdf(self._doc())
+        assert "fragments.broken" in str(exc_info.value)
+        assert not hasattr(exc_info.value, "errors")
+
+    def test_render_collects_every_error(self):
+        with pytest.raises(rupdf.RupdfError, match="^6 errors in the document:") as exc_info:
+            rupdf.render_pdf(self._doc(), collect_errors=True)
+        errors = exc_info.value.errors
+        assert [(e["page"], e["element"]) for e in errors] == [
+            (None, None), (0, 1), (0, 2), (1, None), (2, 0), (None, 0),
+        ]
+        assert errors[1]["id"] == "tri"
+        assert errors[1]["message"].startswith("Page 0: ")
+        assert "polygon needs at least 3 points" in errors[1]["message"]
+        assert "Invalid page size" in errors[3]["message"]
+        assert "Element 0 must be a dict" in errors[4]["message"]
+        assert "repeating_elements: Unknown element type" in errors[5]["message"]
+
+    def test_validate_collects(self):
+        with pytest.raises(rupdf.RupdfError) as exc_info:
+            rupdf.validate_document(self._doc())
+        assert len(exc_info.value.errors) == 6
+
+    def test_single_error_keeps_its_message(self):
+        with pytest.raises(rupdf.RupdfError, match="^Page 0: .*Element 0") as exc_info:
+            rupdf.validate_document(page_doc({"type": "rect", "x": 0, "y": 0}))
+        assert len(exc_info.value.errors) == 1
+
+    def test_valid_document(self):
+        doc = page_doc({"type": "rect", "x": 0, "y": 0, "w": 1, "h": 1})
+        assert rupdf.render_pdf(doc, collect_errors=True).startswith(b"%PDF")
+
+
+class TestRawContent:
+    """Test raw_content parsing and the allow_raw flag."""
+
+    RAW = {"type": "raw_content"}
+
+    def test_needs_allow_raw(self):
+        doc = page_doc(dict(self.RAW, operators="0 0 m 100 100 l S"))
+        with pytest.raises(rupdf.RupdfError, match="only rendered with allow_raw=True"):
+            rupdf.render_pdf(doc)
+        with pytest.raises(rupdf.RupdfError, match="allow_raw"):
+            rupdf.validate_document(doc)
+        with pytest.raises(rupdf.RupdfError, match="allow_raw"):
+            rupdf.PdfRenderJob(doc)
+        assert rupdf.validate_document(doc, allow_raw=True) == []
+
+    def test_names_must_be_declared(self):
+        with pytest.raises(rupdf.RupdfError, match="'/Half' is not declared"):
+            rupdf.render_pdf(page_doc(dict(self.RAW, operators="/Half gs")), allow_raw=True)
+        raw = dict(self.RAW, operators="/Half gs", raw_resources={"Half": {"image": "logo"}})
+        with pytest.raises(rupdf.RupdfError, match="must be an alpha resource"):
+            rupdf.render_pdf(page_doc(raw), allow_raw=True)
+        raw = dict(self.RAW, operators="/Half gs 0 0 m 100 100 l S", raw_resources={"Half": {"alpha": 127}})
+        assert b"/A127 gs" in render_uncompressed(page_doc(raw), allow_raw=True)
+
+    def test_operators_outside_allowlist(self):
+        with pytest.raises(rupdf.RupdfError, match="operator 'BI' is not allowed"):
+            rupdf.render_pdf(page_doc(dict(self.RAW, operators="BI /W 1 /H 1 ID")), allow_raw=True)
+        with pytest.raises(rupdf.RupdfError, match="'q' without a matching 'Q'"):
+            rupdf.render_pdf(page_doc(dict(self.RAW, operators="q 1 w")), allow_raw=True)
+
+    def test_unknown_key_is_strict(self):
+        doc = page_doc(dict(self.RAW, operators="0 0 m", operator="S"), strict_keys=True)
+        with pytest.raises(rupdf.RupdfError, match="unknown key 'operator' for raw_content elements; did you mean 'operators'"):
+            rupdf.render_pdf(doc, allow_raw=True)
+
+    def test_text_uses_glyph_ids(self, font_path):
+        fonts = {"sans": {"path": font_path}}
+        raw = dict(self.RAW, operators="BT /F 12 Tf 72 72 Td <0024> Tj ET", raw_resources={"F": {"font": "sans"}})
+        assert b"<0024> Tj" in render_uncompressed(page_doc(raw, fonts=fonts), allow_raw=True)
+        raw = dict(raw, operators="BT /F 12 Tf <FFFF> Tj ET")
+        with pytest.raises(rupdf.RupdfError, match="glyph 65535 is not in font 'sans'"):
+            rupdf.render_pdf(page_doc(raw, fonts=fonts), allow_raw=True)
+
+
+class TestOrigin:
+    """Test origin parsing."""
+
+    def test_mixed_origins_rejected(self):
+        doc = page_doc(page={"origin": "top_left"}, origin="bottom_left")
+        with pytest.raises(rupdf.RupdfError, match="origin 'top_left' differs from the document's 'bottom_left'"):
+            rupdf.render_pdf(doc)
+        # Restating the document's origin is fine, strict keys included
+        doc["pages"][0]["origin"] = "bottom_left"
+        assert rupdf.render_pdf(dict(doc, strict_keys=True)).startswith(b"%PDF")
+
+    def test_invalid_origin(self):
+        with pytest.raises(rupdf.RupdfError, match="Invalid origin: 'center'"):
+            rupdf.render_pdf(page_doc(origin="center"))
+
+
+class TestNumericSanitation:
+    """Test non-finite numbers are rejected and out-of-range ones clamped."""
+
+    def test_nan_names_element_and_field(self):
+        doc = page_doc({"type": "rect", "x": 72, "y": 72, "w": float("nan"), "h": 10, "id": "box"})
+        with pytest.raises(rupdf.RupdfError, match=r"^Page 0: Element 0 \(id 'box'\): rect w must be a finite number, got NaN$"):
+            rupdf.render_pdf(doc)
+
+    def test_every_non_finite_number_is_raised(self):
+        doc = page_doc(
+            {"type": "line", "x1": float("inf"), "y1": 0, "x2": 10, "y2": 0},
+            {"type": "group", "elements": [{"type": "text", "x": 0, "y": float("-inf"), "text": "x", "font": "f", "size": 12}]},
+        )
+        with pytest.raises(rupdf.RupdfError, match="^2 errors in the document:") as exc_info:
+            rupdf.validate_document(doc)
+        errors = exc_info.value.errors
+        assert [(e["page"], e["element"]) for e in errors] == [(0, 0), (0, 1)]
+        assert errors[1]["message"].endswith("text y must be a finite number, got -inf")
+
+    def test_negative_stroke_clamped_with_warning(self):
+        doc = page_doc({"type": "line", "x1": 72, "y1": 72, "x2": 144, "y2": 72, "stroke": -3})
+        [warning] = rupdf.validate_document(doc)
+        assert warning["code"] == "clamped"
+        assert warning["message"] == "Page 0, element 0: line stroke -3 is negative; using 0"
+        with pytest.warns(rupdf.RupdfWarning, match="line stroke -3 is negative"):
+            pdf = render_uncompressed(doc)
+        # Drawn as a zero-width line, which is not drawn at all
+        assert b"144 720 l" not in pdf
+
+    def test_corner_radius_clamped_to_half_the_shorter_side(self):
+        doc = page_doc({"type": "rect", "x": 72, "y": 72, "w": 100, "h": 40, "corner_radius": 50})
+        [warning] = rupdf.validate_document(doc)
+        assert "rect corner_radius 50 is more than half the shorter side (20); using 20" in warning["message"]
+
+    def test_in_range_values_pass_quietly(self):
+        doc = page_doc({"type": "rect", "x": 72, "y": 72, "w": 100, "h": 40, "corner_radius": 20, "stroke": 0})
+        assert rupdf.validate_document(doc) == []
+
+
+class TestImageProfiles:
+    """Test image profile parsing."""
+
+    def test_invalid_profile(self, p3_png_path):
+        image = {"type": "image", "x": 0, "y": 100, "w": 16, "h": 16, "image_ref": "photo"}
+        doc = page_doc(image, images={"photo": {"path": p3_png_path, "profile": "p3"}})
+        with pytest.raises(rupdf.RupdfError, match="Invalid image profile: 'p3'. Must be 'embed' or 'srgb'"):
+            rupdf.render_pdf(doc)
+
+
+class TestPreflight:
+    """Test checking resources without rendering."""
+
+    TEXT = {"type": "text", "x": 72, "y": 72, "text": "Hi", "size": 12}
+    IMAGE = {"type": "image", "x": 72, "y": 72, "w": 72}
+
+    def test_reports_fonts_and_coverage(self, font_path):
+        doc = page_doc(dict(self.TEXT, font="sans"), fonts={"sans": {"path": font_path}, "spare": {"path": font_path}})
+        report = rupdf.preflight(doc, ["Grüße", "東京"])
+        assert report["ok"]
+        sans = report["fonts"]["sans"]
+        assert sans["ok"] and sans["error"] is None and sans["referenced"]
+        assert sans["postscript_name"] == "IBMPlexSans"
+        assert sans["missing"] == ["東", "京"]
+        assert not report["fonts"]["spare"]["referenced"]
+
+    def test_collects_every_failure(self):
+        doc = page_doc(
+            dict(self.TEXT, font="broken"),
+            dict(self.TEXT, y=96, font="ghost"),
+            dict(self.IMAGE, y=120, image_ref="nothing"),
+            fonts={"broken": {"bytes": b"not a font"}},
+            images={"gone": {"path": "/no/such/image.png"}},
+        )
+        report = rupdf.preflight(doc)
+        assert not report["ok"]
+        broken = report["fonts"]["broken"]
+        assert not broken["ok"] and broken["postscript_name"] is None
+        assert "broken" in broken["error"]
+        assert "Failed to read file" in report["images"]["gone"]["error"]
+        assert report["missing_fonts"] == ["ghost"]
+        assert report["missing_images"] == ["nothing"]
+
+    def test_unreferenced_failures_keep_ok(self):
+        report = rupdf.preflight(page_doc(fonts={"broken": {"bytes": b"not a font"}}))
+        assert report["ok"]
+        assert not report["fonts"]["broken"]["ok"]
+
+    def test_hidden_elements_reference_nothing(self):
+        doc = page_doc(dict(self.TEXT, font="ghost", visible=False))
+        assert rupdf.preflight(doc)["missing_fonts"] == []
+
+    def test_image_sizes_at_300_dpi(self, png_path):
+        doc = page_doc(
+            dict(self.IMAGE, h=72, image_ref="logo"),
+            dict(self.IMAGE, y=200, h=72, image_ref="logo"),
+            dict(self.IMAGE, y=300, w=360, h=360, image_ref="logo"),
+            images={"logo": {"path": png_path}},
+        )
+        logo = rupdf.preflight(doc)["images"]["logo"]
+        assert logo["ok"] and logo["kind"] == "raster"
+        assert (logo["width"], logo["height"]) == (1280, 1280)
+        # An inch square at 300 DPI; five inches is past the source's pixels
+        assert [(s["width"], s["pixel_width"], s["dpi"]) for s in logo["sizes"]] == [
+            (72, 300, 300),
+            (360, 1280, 256),
+        ]
+
+    def test_truncated_raster_fails_to_decode(self, rgb16_png_path):
+        with open(rgb16_png_path, "rb") as f:
+            data = f.read()
+        doc = page_doc(dict(self.IMAGE, image_ref="photo"), images={"photo": {"bytes": data[: len(data) // 2]}})
+        report = rupdf.preflight(doc)
+        assert not report["ok"]
+        assert "Failed to decode" in report["images"]["photo"]["error"]
+
+    def test_svg_unsupported_features(self):
+        svg = (
+            '<svg xmlns="http://www.w3.org/2000/svg" width="40" height="20">'
+            '<rect width="40" height="20" fill="red" fill-opacity="0.5" stroke="blue" stroke-dasharray="2 2"/>'
+            "</svg>"
+        )
+        doc = page_doc(dict(self.IMAGE, w=80, image_ref="chart"), images={"chart": {"svg": svg}})
+        chart = rupdf.preflight(doc)["images"]["chart"]
+        assert chart["kind"] == "svg"
+        assert chart["unsupported"] == ["fill opacity", "stroke dash"]
+        assert chart["sizes"] == [
+            {"width": 80, "height": 40, "pixel_width": None, "pixel_height": None, "dpi": None}
+        ]
+
+    def test_writes_no_pdf(self, font_path):
+        assert isinstance(rupdf.preflight(page_doc(fonts={"sans": {"path": font_path}})), dict)
+
+
+class TestLayoutFingerprint:
+    """Test hashing the layout without keeping a PDF."""
+
+    TEXTBOX = {"type": "textbox", "x": 72, "y": 72, "w": 120, "h": 100, "text": "Pick four cases from bay seven",
+               "font": "f", "size": 12}
+    BARCODE = {"type": "barcode", "x": 72, "y": 200, "w": 200, "h": 40, "value": "SKU-1234"}
+
+    def test_stable_hex(self, font_doc):
+        fingerprint = rupdf.render_layout_fingerprint(font_doc(self.TEXTBOX, self.BARCODE))
+        assert re.fullmatch(r"[0-9a-f]{16}", fingerprint)
+        assert rupdf.render_layout_fingerprint(font_doc(self.TEXTBOX, self.BARCODE)) == fingerprint
+
+    def test_ignores_metadata(self, font_doc):
+        plain = rupdf.render_layout_fingerprint(font_doc(self.TEXTBOX, self.BARCODE))
+        dated = font_doc(self.TEXTBOX, self.BARCODE, metadata={"title": "Picking", "creation_date": "2026-01-02"})
+        assert rupdf.render_layout_fingerprint(dated) == plain
+
+    def test_tracks_layout(self, font_doc):
+        def fingerprint(textbox, barcode):
+            return rupdf.render_layout_fingerprint(font_doc(textbox, barcode))
+
+        plain = fingerprint(self.TEXTBOX, self.BARCODE)
+        assert fingerprint(dict(self.TEXTBOX, w=160), self.BARCODE) != plain
+        assert fingerprint(dict(self.TEXTBOX, text="Pick five cases from bay seven"), self.BARCODE) != plain
+        assert fingerprint(self.TEXTBOX, dict(self.BARCODE, value="SKU-1235")) != plain
+
+    def test_ignores_image_data(self, png_path, rgb16_png_path):
+        image = {"type": "image", "x": 72, "y": 72, "w": 100, "h": 100, "image_ref": "photo"}
+
+        def fingerprint(path):
+            return rupdf.render_layout_fingerprint(page_doc(image, images={"photo": {"path": path}}))
+
+        assert fingerprint(png_path) == fingerprint(rgb16_png_path)
+
+
+class TestRasterizePage:
+    """Test rendering a page to PNG through the same layout as the PDF."""
+
+    def _doc(self, font_path, color="#cc0000", repeat=1):
+        return {
+            "pages": [
+                {"size": (200, 100), "repeat": repeat, "elements": [
+                    {"type": "rect", "x": 10, "y": 10, "w": 80, "h": 40, "fill_color": color},
+                    {"type": "text", "x": 10, "y": 80, "text": "Bay 7", "font": "sans", "size": 12},
+                ]},
+                {"size": (100, 300), "elements": []},
+            ],
+            "resources": {"fonts": {"sans": {"path": font_path}}},
+        }
+
+    @staticmethod
+    def _size(png):
+        assert png[:8] == b"\x89PNG\r\n\x1a\n"
+        return struct.unpack(">II", png[16:24])
+
+    def test_png_sized_by_dpi(self, font_path):
+        assert self._size(rupdf.rasterize_page(self._doc(font_path))) == (200, 100)
+        assert self._size(rupdf.rasterize_page(self._doc(font_path), dpi=144)) == (400, 200)
+        assert self._size(rupdf.rasterize_page(self._doc(font_path), page=1, dpi=36)) == (50, 150)
+
+    def test_pixels_follow_content(self, font_path):
+        red = rupdf.rasterize_page(self._doc(font_path))
+        assert rupdf.rasterize_page(self._doc(font_path)) == red
+        assert rupdf.rasterize_page(self._doc(font_path, color="#0000cc")) != red
+
+    def test_copies_count_as_pages(self, font_path):
+        doc = self._doc(font_path, repeat=2)
+        assert self._size(rupdf.rasterize_page(doc, page=2)) == (100, 300)
+        with pytest.raises(IndexError, match="Page 3 is out of range; the document has 3 pages"):
+            rupdf.rasterize_page(doc, page=3)
+
+    def test_dpi_must_be_positive(self, font_path):
+        with pytest.raises(ValueError, match="Invalid dpi"):
+            rupdf.rasterize_page(self._doc(font_path), dpi=0)
+
+    def test_huge_dpi_raises(self, font_path):
+        with pytest.raises(rupdf.RupdfError, match="too large to rasterize"):
+            rupdf.rasterize_page(self._doc(font_path), dpi=1e6)
+
+    def test_thumbnail_places_as_image(self, font_path):
+        thumbnail = rupdf.rasterize_page(self._doc(font_path), dpi=36)
+        image = {"type": "image", "x": 72, "y": 72, "w": 100, "image_ref": "original"}
+        pdf = rupdf.render_pdf(page_doc(image, images={"original": {"bytes": thumbnail}}))
+        assert pdf.startswith(b"%PDF")
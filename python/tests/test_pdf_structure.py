@@ -1,7 +1,8 @@
 """PDF structure and validity tests.
 
-Tests that generated PDFs have correct structure without
-comparing exact byte sequences.
+Tests that generated PDFs have correct structure, and that each
+element and resource draws what it should, without comparing exact
+byte sequences.
 """
 
 import re
@@ -9,7 +10,7 @@ import re
 import pytest
 import rupdf
 
-from .conftest import inject_font_resources
+from .conftest import icc_header, inject_font_resources, page_doc, render_uncompressed, woff2_from_sfnt, woff_from_sfnt
 
 
 class TestPdfHeader:
@@ -188,6 +189,30 @@ class TestCompression:
         uncompressed = rupdf.render_pdf(doc, compress=False)
         assert len(compressed) <= len(uncompressed)
 
+    def test_debug_uncompressed_deflates_nothing(self, font_path, svg_path):
+        """debug_uncompressed leaves font programs and SVG forms plain too."""
+        doc = {
+            "pages": [{
+                "size": (612, 792),
+                "elements": [
+                    {"type": "text", "x": 72, "y": 72, "text": "Golden", "font": "f", "size": 12},
+                    {"type": "image", "x": 72, "y": 100, "w": 50, "h": 50, "image_ref": "logo"},
+                ]
+            }],
+            "resources": {"fonts": {"f": {"path": font_path}}, "images": {"logo": {"path": svg_path}}},
+        }
+        assert b"FlateDecode" in rupdf.render_pdf(doc, compress=False)
+        assert b"FlateDecode" not in rupdf.render_pdf(doc, debug_uncompressed=True)
+        job = rupdf.PdfRenderJob(doc, debug_uncompressed=True)
+        assert b"FlateDecode" not in job.result()
+
+    def test_debug_uncompressed_renders_are_identical(self, multi_page_fixture, font_path):
+        """Two renders of the same document give the same bytes."""
+        doc = inject_font_resources(multi_page_fixture, font_path)
+        first = rupdf.render_pdf(doc, debug_uncompressed=True)
+        assert rupdf.render_pdf(doc, debug_uncompressed=True) == first
+        assert rupdf.render_pdf(doc) == rupdf.render_pdf(doc)
+
 
 class TestBarcodes:
     """Test barcode generation in PDFs."""
@@ -474,3 +499,1875 @@ class TestPageDimensions:
         }
         pdf = rupdf.render_pdf(doc)
         assert b"/MediaBox [0 0 400 600]" in pdf or b"/MediaBox[0 0 400 600]" in pdf
+
+
+def mediabox(pdf):
+    """The first page's MediaBox as numbers."""
+    start = pdf.index(b"/MediaBox [")
+    return [float(v) for v in pdf[start + 11:pdf.index(b"]", start)].split()]
+
+
+class TestQrCode:
+    """Test QR symbols in PDFs."""
+
+    def test_wifi_dict_matches_hand_escaped_string(self):
+        """The dict should draw the same symbol as the escaped payload."""
+        qr = {"type": "qrcode", "x": 72, "y": 72, "size": 100}
+        structured = render_uncompressed(page_doc(dict(qr, value={"kind": "wifi", "ssid": "a;b", "password": "p:w"})))
+        manual = render_uncompressed(page_doc(dict(qr, value="WIFI:T:WPA;S:a\\;b;P:p\\:w;;")))
+        assert structured.count(b" re") == manual.count(b" re")
+
+
+class TestPageBackground:
+    """Test gradient and image page backgrounds."""
+
+    STOPS = [(0.0, (255, 255, 255, 255)), (1.0, (0, 0, 128, 255))]
+
+    def test_gradient_background(self):
+        doc = page_doc(page={"background": {"gradient": {"angle": 90, "stops": self.STOPS}}})
+        assert b"/ShadingType 2" in render_uncompressed(doc)
+
+    def test_radial_background(self):
+        radial = {"gradient": {"type": "radial", "center": (100, 92), "radius": 300, "stops": self.STOPS}}
+        result = render_uncompressed(page_doc(page={"background": radial}))
+        assert b"/ShadingType 3" in result
+        assert b"/Coords [100 700 0 100 700 300]" in result
+
+    def test_radial_background_bottom_left_origin(self):
+        radial = {"gradient": {"type": "radial", "center": (100, 92), "radius": 300, "stops": self.STOPS}}
+        doc = page_doc(page={"background": radial}, origin="bottom_left")
+        assert b"/Coords [100 92 0 100 92 300]" in render_uncompressed(doc)
+
+    def test_image_background(self, png_path):
+        doc = page_doc(page={"background": {"image_ref": "bg", "fit": "contain"}}, images={"bg": {"path": png_path}})
+        assert b"/Subtype /Image" in render_uncompressed(doc)
+
+
+class TestPageSizes:
+    """Test page sizes in units and by name."""
+
+    def test_mm_page_size(self):
+        doc = {"units": "mm", "pages": [{"size": (25.4, 50.8), "elements": []}]}
+        assert mediabox(render_uncompressed(doc))[2:] == [72, 144]
+
+    def test_suffix_overrides_document_units(self):
+        doc = {"units": "mm", "pages": [{"size": ("1in", "2in"), "elements": []}]}
+        assert mediabox(render_uncompressed(doc))[2:] == [72, 144]
+
+    def test_px_at_dpi(self):
+        doc = {"units": "px@300", "pages": [{"size": (300, 600), "elements": []}]}
+        assert mediabox(render_uncompressed(doc))[2:] == [72, 144]
+
+    def test_a4(self):
+        _, _, w, h = mediabox(render_uncompressed(page_doc(size="A4")))
+        assert w == pytest.approx(595.276, abs=0.01)
+        assert h == pytest.approx(841.89, abs=0.01)
+
+    def test_letter_landscape(self):
+        doc = page_doc(size="letter", page={"landscape": True})
+        assert mediabox(render_uncompressed(doc))[2:] == [792, 612]
+
+    def test_case_insensitive(self):
+        assert mediabox(render_uncompressed(page_doc(size="Legal")))[2:] == [612, 1008]
+
+    def test_tuple_still_works(self):
+        assert mediabox(render_uncompressed(page_doc(size=(300, 400))))[2:] == [300, 400]
+
+
+class TestPageMargins:
+    """Test page margins and page-relative element lengths."""
+
+    MARGINS = {"top": 50, "right": 40, "bottom": 60, "left": 30}
+
+    def test_keywords_fractions_and_percentages(self):
+        rect = {"type": "rect", "x": "margin_left", "y": {"rel": "content", "y": 0.5}, "w": "content_width", "h": "10%"}
+        pdf = render_uncompressed(page_doc(rect, size=(600, 800), page={"margins": self.MARGINS}))
+        assert b"\n30 325 530 80 re\n" in pdf
+
+    def test_page_relative_fraction(self):
+        rect = {"type": "rect", "x": {"rel": "page", "x": 0.25}, "y": 0, "w": {"rel": "page", "w": 0.5}, "h": 10}
+        pdf = render_uncompressed(page_doc(rect, size=(600, 800), page={"margins": self.MARGINS}))
+        assert b"\n150 790 300 10 re\n" in pdf
+
+    def test_single_length_sets_every_side(self):
+        line = {"type": "line", "x1": "margin_left", "y1": "margin_bottom", "x2": "margin_right", "y2": "margin_bottom"}
+        pdf = render_uncompressed(page_doc(line, size=(600, 800), page={"margins": "1in"}))
+        assert b"\n72 72 m\n528 72 l\n" in pdf
+
+
+class TestRepeatedPages:
+    """Test pages emitted several times from one content stream."""
+
+    RECT = {"type": "rect", "x": 72, "y": 72, "w": 100, "h": 50, "stroke": 1}
+
+    def _render(self, repeat):
+        return render_uncompressed(page_doc(self.RECT, size=(300, 300), page={"repeat": repeat}))
+
+    def test_page_count(self):
+        pdf = self._render(5)
+        assert b"/Count 5" in pdf
+        assert pdf.count(b"/Type /Page\n") == 5
+
+    def test_content_written_once(self):
+        assert self._render(50).count(b" re\n") == self._render(1).count(b" re\n")
+
+    def test_copies_stay_small(self):
+        # Each extra copy costs a page dict and an xref entry, not the content
+        per_copy = (len(self._render(1001)) - len(self._render(1))) / 1000
+        assert per_copy < 200
+
+
+class TestOpenAction:
+    """Test the document open action."""
+
+    PAGES = [{"size": (612, 792)}, {"size": (300, 400)}]
+
+    def test_fit_by_default(self):
+        pdf = render_uncompressed({"pages": self.PAGES, "open_action": {"page": 1}})
+        assert b"/OpenAction [" in pdf
+        assert b" /Fit]" in pdf
+
+    def test_fit_width_uses_page_top(self):
+        pdf = render_uncompressed({"pages": self.PAGES, "open_action": {"page": 1, "view": "fit_width"}})
+        assert b" /FitH 400]" in pdf
+
+    def test_xyz_flips_y(self):
+        action = {"page": 0, "view": {"x": 10, "y": 100, "zoom": 2}}
+        assert b" /XYZ 10 692 2]" in render_uncompressed({"pages": self.PAGES, "open_action": action})
+
+
+class TestNote:
+    """Test sticky-note annotations."""
+
+    NOTE = {"type": "note", "x": 100, "y": 100, "contents": "Looks off"}
+
+    def test_text_annotation_with_popup(self):
+        pdf = render_uncompressed(page_doc(self.NOTE))
+        assert b"/Subtype /Text" in pdf
+        assert b"/Subtype /Popup" in pdf
+        assert b"/Contents (Looks off)" in pdf
+        assert b"/Annots [" in pdf
+        assert b"/Open false" in pdf
+
+    def test_options(self):
+        note = dict(self.NOTE, title="Ana", icon="help", open=True, color=(0, 0, 255, 255))
+        pdf = render_uncompressed(page_doc(note))
+        assert b"/T (Ana)" in pdf
+        assert b"/Name /Help" in pdf
+        assert b"/Open true" in pdf
+        assert b"/C [0 0 1]" in pdf
+
+    def test_popup_flips_near_right_edge(self):
+        assert b"/Rect [360 572 560 692]" in render_uncompressed(page_doc(dict(self.NOTE, x=560)))
+
+    def test_not_drawn_into_content(self):
+        blank = render_uncompressed(page_doc())
+        pdf = render_uncompressed(page_doc(self.NOTE))
+        content = lambda doc: doc[doc.index(b"stream\n"):doc.index(b"endstream")]
+        assert content(pdf) == content(blank)
+
+
+class TestRepeatingElements:
+    """Test elements repeated across pages."""
+
+    RULE = {"type": "line", "x1": 36, "y1": 756, "x2": 576, "y2": 756, "stroke": 0.25}
+    PAGES = [{"size": (612, 792)}, {"size": (612, 792)}, {"size": (612, 792)}]
+
+    def test_repeating_elements_list(self):
+        doc = {"pages": self.PAGES, "repeating_elements": [dict(self.RULE, except_pages=[2])]}
+        assert render_uncompressed(doc).count(b"0.25 w") == 2
+
+    def test_every_page_on_page_zero(self):
+        pages = [dict(self.PAGES[0], elements=[dict(self.RULE, every_page=True)]), *self.PAGES[1:]]
+        assert render_uncompressed({"pages": pages}).count(b"0.25 w") == 3
+
+    def test_only_pages(self):
+        doc = {"pages": self.PAGES, "repeating_elements": [dict(self.RULE, only_pages=[1])]}
+        assert render_uncompressed(doc).count(b"0.25 w") == 1
+
+
+class TestWatermark:
+    """Test the document watermark."""
+
+    MARK = {"type": "rect", "x": -50, "y": -10, "w": 100, "h": 20, "fill_color": (200, 0, 0, 255)}
+
+    def _render(self, **options):
+        pages = [
+            {"size": (612, 792), "elements": [{"type": "line", "x1": 0, "y1": 0, "x2": 10, "y2": 0}]},
+            {"size": (792, 612), "elements": []},
+        ]
+        return render_uncompressed({"pages": pages, "watermark": dict(self.MARK, **options)})
+
+    def test_drawn_on_every_page(self):
+        pdf = self._render()
+        assert b"-50 782 100 20 re" in pdf
+        assert b"-50 602 100 20 re" in pdf
+
+    def test_placed_per_page_size(self):
+        pdf = self._render(rotation=90)
+        # Turned about the center of each page, in its own size
+        assert b"0 1 -1 0 1098 396 cm" in pdf
+        assert b"0 1 -1 0 1008 306 cm" in pdf
+
+    def test_opacity_uses_a_transparency_group(self):
+        pdf = self._render(opacity=0.5)
+        assert pdf.count(b"/A128 gs") == 2
+        assert b"/Group" in pdf
+
+    def test_behind_draws_before_content(self):
+        pdf = self._render(behind=True)
+        assert pdf.index(b"-50 782 100 20 re") < pdf.index(b"0 792 m\n")
+
+
+class TestHeaderFooter:
+    """Test headers and footers drawn on every page."""
+
+    def _pages(self, font_path, **regions):
+        doc = {
+            "pages": [{"size": (612, 792), "repeat": 2}, {"size": (792, 612)}],
+            "resources": {"fonts": {"f": {"path": font_path}}},
+            **regions,
+        }
+        pages = rupdf.extract_content(rupdf.render_pdf(doc))["pages"]
+        return [[(t["text"], round(t["y"])) for t in page["text"]] for page in pages]
+
+    def _text(self, text):
+        return {"type": "text", "x": 36, "y": 0, "text": text, "font": "f", "size": 9}
+
+    def test_footer_numbers_each_page(self, font_path):
+        footer = {"elements": [self._text("Page {page} of {pages}")], "offset": 36}
+        assert self._pages(font_path, footer=footer) == [
+            [("Page 1 of 3", 756)],
+            [("Page 2 of 3", 756)],
+            [("Page 3 of 3", 576)],
+        ]
+
+    def test_number_formats_and_offsets(self, font_path):
+        footer = [self._text("{page:roman}/{page:Roman}/{page:alpha}/{page+25:Alpha}/{page-1}/{pages-1:Roman}")]
+        assert [page[0][0] for page in self._pages(font_path, footer=footer)] == [
+            "i/I/a/Z/0/II",
+            "ii/II/b/AA/1/II",
+            "iii/III/c/AB/2/II",
+        ]
+
+    def test_offset_below_one_writes_nothing_in_roman(self, font_path):
+        footer = [self._text("[{page-2:roman}]")]
+        assert [page[0][0] for page in self._pages(font_path, footer=footer)] == ["[]", "[]", "[i]"]
+
+    def test_header_list_and_skip_first(self, font_path):
+        header = {"elements": [self._text("Report")], "offset": 24, "skip_first": True}
+        assert self._pages(font_path, header=header) == [[], [], [("Report", 24)]]
+        assert self._pages(font_path, header=[self._text("Report")])[0] == [("Report", 0)]
+
+
+class TestLayers:
+    """Test named layers selected at render time."""
+
+    PERFORATION = {"type": "line", "x1": 0, "y1": 396, "x2": 612, "y2": 396, "stroke": 0.3}
+    BODY = {"type": "rect", "x": 72, "y": 72, "w": 100, "h": 50, "stroke": 0.7}
+
+    def _doc(self, font_path, **layers):
+        return {
+            "pages": [{"size": (612, 792), "elements": [self.BODY]}, {"size": (612, 792)}],
+            "layers": layers,
+            "resources": {"fonts": {"f": {"path": font_path}}},
+        }
+
+    def test_left_out_by_default(self, font_path):
+        label = {"type": "text", "x": 36, "y": 20, "text": "PROOF", "font": "f", "size": 8}
+        doc = self._doc(font_path, proof_only=[self.PERFORATION, label])
+        pdf, stats = render_uncompressed(doc, return_stats=True)
+        assert b"0.3 w" not in pdf
+        assert stats["fonts"] == {}
+        assert len(pdf) == len(render_uncompressed(dict(doc, layers={})))
+
+    def test_included_on_every_page(self, font_path):
+        doc = self._doc(font_path, proof_only=[self.PERFORATION])
+        assert render_uncompressed(doc, include_layers=["proof_only"]).count(b"0.3 w") == 2
+
+    def test_page_options_and_stacking(self, font_path):
+        under = dict(self.PERFORATION, under_content=True, only_pages=[0])
+        doc = self._doc(font_path, proof_only=[under])
+        doc["repeating_elements"] = [dict(self.PERFORATION, stroke=0.4, under_content=True)]
+        pdf = render_uncompressed(doc, include_layers=["proof_only"])
+        assert pdf.count(b"0.3 w") == 1
+        # Beneath the repeating elements, which are beneath the page's own
+        assert pdf.index(b"0.3 w") < pdf.index(b"0.4 w") < pdf.index(b"0.7 w")
+
+
+class TestUnembeddedFonts:
+    """Test fonts referenced by name instead of embedded."""
+
+    def test_font_program_is_skipped(self, font_path):
+        text = {"type": "text", "x": 72, "y": 72, "text": "Hello", "font": "f", "size": 12}
+        with pytest.warns(rupdf.RupdfWarning):
+            referenced = render_uncompressed(page_doc(text, fonts={"f": {"path": font_path, "embed": False}}))
+        embedded = render_uncompressed(page_doc(text, fonts={"f": {"path": font_path, "embed": True}}))
+        assert b"/FontFile2" not in referenced
+        assert b"/FontFile2" in embedded
+        assert len(referenced) < len(embedded)
+
+
+class TestWoffFonts:
+    """Test WOFF font sources."""
+
+    def test_woff_renders_like_sfnt(self, font_bytes):
+        text = {"type": "text", "x": 72, "y": 72, "text": "Hello", "font": "f", "size": 12}
+        from_woff = render_uncompressed(page_doc(text, fonts={"f": {"bytes": woff_from_sfnt(font_bytes)}}))
+        from_sfnt = render_uncompressed(page_doc(text, fonts={"f": {"bytes": font_bytes}}))
+        assert b"/FontFile2" in from_woff
+        assert len(from_woff) == len(from_sfnt)
+
+    def test_woff2_renders_like_sfnt(self, font_bytes):
+        text = {"type": "text", "x": 72, "y": 72, "text": "Hello", "font": "f", "size": 12}
+        from_woff2 = render_uncompressed(page_doc(text, fonts={"f": {"bytes": woff2_from_sfnt(font_bytes)}}))
+        from_sfnt = render_uncompressed(page_doc(text, fonts={"f": {"bytes": font_bytes}}))
+        assert len(from_woff2) == len(from_sfnt)
+
+
+class TestEmoji:
+    """Test color emoji fallback."""
+
+    def test_emoji_font_draws_inline_images(self, font_doc, emoji_font_path):
+        text = {"type": "text", "x": 72, "y": 72, "text": "Hi \U0001F600!", "font": "f", "size": 12,
+                "emoji_font": "color", "missing_glyph_policy": "raise"}
+        pdf = render_uncompressed(font_doc(text, fonts={"color": {"path": emoji_font_path}}))
+        assert b"/emoji_color_1 Do" in pdf
+        assert b"/SMask" in pdf
+
+    def test_textbox_accepts_emoji_font(self, font_doc, emoji_font_path):
+        textbox = {
+            "type": "textbox", "x": 72, "y": 72, "w": 200, "h": 100, "font": "f", "size": 12,
+            "text": "one ❤️ two", "emoji_font": "color",
+        }
+        pdf = render_uncompressed(font_doc(textbox, fonts={"color": {"path": emoji_font_path}}))
+        assert b"/emoji_color_2 Do" in pdf
+
+
+class TestTextHighlight:
+    """Test highlight fills behind text."""
+
+    TEXT = {"type": "text", "x": 300, "y": 72, "text": "Total", "font": "f", "size": 12}
+
+    def test_highlight_precedes_text(self, font_doc):
+        pdf = render_uncompressed(font_doc(dict(self.TEXT, highlight_color=(255, 235, 0, 128))))
+        assert b"/A127 gs" in pdf
+        assert pdf.index(b" re\nf") < pdf.index(b"BT")
+
+    def test_no_highlight_by_default(self, font_doc):
+        assert b" re\nf" not in render_uncompressed(font_doc(self.TEXT))
+
+
+class TestTextTransform:
+    """Test case transforms on text elements."""
+
+    TEXT = {"type": "text", "x": 300, "y": 72, "text": "Hello world", "font": "f", "size": 20}
+
+    def test_uppercase_subsets_capitals(self, font_doc):
+        pdf = render_uncompressed(font_doc(dict(self.TEXT, transform="uppercase")))
+        assert b"<0045>" in pdf  # E
+        assert b"<0065>" not in pdf  # e
+
+    def test_smallcaps_switches_size(self, font_doc):
+        pdf = render_uncompressed(font_doc(dict(self.TEXT, transform="smallcaps")))
+        assert b" 20 Tf" in pdf
+        assert b" 15 Tf" in pdf
+
+
+class TestTextFitWidth:
+    """Test fitting text to an exact width."""
+
+    TEXT = {"type": "text", "x": 400, "y": 72, "text": "Hello", "font": "f", "size": 20, "align": "right"}
+
+    def test_tracking_sets_char_spacing(self, font_doc):
+        pdf = render_uncompressed(font_doc(dict(self.TEXT, fit_width={"width": 80})))
+        assert b" Tc\n" in pdf
+        assert b" Tz\n" not in pdf
+        assert b"\n320 720 Td\n" in pdf
+
+    def test_scale_sets_horizontal_scaling(self, font_doc):
+        pdf = render_uncompressed(font_doc(dict(self.TEXT, fit_width={"width": 80, "mode": "scale"})))
+        assert b" Tz\n" in pdf
+        assert b" Tc\n" not in pdf
+
+
+class TestFontFamilies:
+    """Test picking fonts by family, weight and style."""
+
+    TEXT = {"type": "text", "x": 72, "y": 72, "text": "Hello", "size": 12, "family": "body"}
+
+    @pytest.fixture
+    def family_doc(self, font_path):
+        fonts = {"regular": {"path": font_path}, "bold": {"path": font_path.replace("Regular", "Bold")}}
+        families = {"body": {"regular": "regular", "bold": "bold"}}
+        return lambda *elements: page_doc(*elements, fonts=fonts, font_families=families)
+
+    def test_regular_by_default(self, family_doc):
+        assert b"Bold" not in render_uncompressed(family_doc(self.TEXT))
+
+    def test_weight_picks_bold_face(self, family_doc):
+        for extra in ({}, {"type": "textbox", "w": 200, "h": 50}):
+            pdf = render_uncompressed(family_doc(dict(self.TEXT, weight="bold", **extra)))
+            assert b"/IBMPlexSans-Bold 12 Tf" in pdf
+
+    def test_markup_bold_uses_family(self, family_doc):
+        pdf = render_uncompressed(family_doc(dict(self.TEXT, text="a **b**", markup=True)))
+        assert b"/IBMPlexSans-Bold 12 Tf" in pdf
+
+
+class TestTextMaxWidth:
+    """Test holding a single text element to a maximum width."""
+
+    TEXT = {"type": "text", "x": 300, "y": 72, "text": "Bartholomew Higginbottom", "font": "f", "size": 20,
+            "align": "center", "max_width": 100}
+
+    def test_clip_is_centered_on_x(self, font_doc):
+        assert b"250 0 100 792 re\nW\n" in render_uncompressed(font_doc(self.TEXT))
+
+    def test_short_text_is_not_clipped(self, font_doc):
+        assert b" re\nW\n" not in render_uncompressed(font_doc(dict(self.TEXT, text="Bart")))
+
+    def test_ellipsis_truncates(self, font_doc):
+        pdf = render_uncompressed(font_doc(dict(self.TEXT, overflow="ellipsis")))
+        assert b" re\nW\n" not in pdf
+        assert b"<2026>" in pdf
+
+    def test_shrink_scales_size(self, font_doc):
+        pdf = render_uncompressed(font_doc(dict(self.TEXT, overflow="shrink", min_size=6)))
+        assert b" 20 Tf" not in pdf
+        assert b" re\nW\n" not in pdf
+
+    def test_shrink_clips_below_min_size(self, font_doc):
+        pdf = render_uncompressed(font_doc(dict(self.TEXT, overflow="shrink", min_size=18)))
+        assert b" 18 Tf" in pdf
+        assert b"250 0 100 792 re\nW\n" in pdf
+
+
+def line_origins(pdf):
+    """Absolute (x, y) of each text line start, replaying Td moves."""
+    x = y = 0.0
+    origins = []
+    for line in pdf.split(b"\n"):
+        if line.endswith(b" Td"):
+            dx, dy = map(float, line.split()[:2])
+            x, y = x + dx, y + dy
+            origins.append((round(x, 2), round(y, 2)))
+    return origins
+
+
+def page_count(pdf):
+    return int(pdf.split(b"/Count ")[1].split()[0])
+
+
+class TestTextBoxColumns:
+    """Test multi-column text flow."""
+
+    BOX = {"type": "textbox", "x": 72, "y": 72, "w": 300, "h": 60,
+           "text": " ".join(["lorem ipsum dolor sit amet"] * 12), "font": "f", "size": 10, "line_height": 12}
+
+    def test_lines_continue_in_next_column(self, font_doc):
+        pdf = render_uncompressed(font_doc(dict(self.BOX, columns=2, column_gap=20)))
+        assert sorted({x for x, _ in line_origins(pdf)}) == [72.0, 232.0]
+
+    def test_balance_evens_out_columns(self, font_doc):
+        box = dict(self.BOX, columns=2, column_gap=20, balance=True, w=60, h=500, text="a b c d e")
+        origins = line_origins(render_uncompressed(font_doc(box)))
+        left = [o for o in origins if o[0] == 72.0]
+        right = [o for o in origins if o[0] != 72.0]
+        assert len(left) - len(right) in (0, 1)
+        assert right, "second column is used"
+
+
+class TestTable:
+    """Test table layout and pagination."""
+
+    TABLE = {"type": "table", "x": 72, "y": 72, "column_widths": [200, 60], "header_rows": 1,
+             "font": "f", "size": 10, "line_height": 12}
+
+    def _table(self, rows=60, **options):
+        body = [[f"Part {i}", str(i)] for i in range(rows)]
+        return dict(self.TABLE, rows=[["Item", "Qty"]] + body, **options)
+
+    def test_short_table_stays_on_page(self, font_doc):
+        assert page_count(render_uncompressed(font_doc(self._table(rows=5)))) == 1
+
+    def test_long_table_adds_pages(self, font_doc):
+        # 20pt rows: header + 19 body rows per 400pt page
+        assert page_count(render_uncompressed(font_doc(self._table(max_height=400)))) == 4
+
+    def test_header_repeats_on_continuations(self, font_doc):
+        pdf = render_uncompressed(font_doc(self._table(max_height=400, header_fill=(204, 204, 204, 255))))
+        assert pdf.count(b"0.8 0.8 0.8 rg") == 2 * page_count(pdf)
+
+    def test_default_break_at_page_bottom(self, font_doc):
+        # 792 - 72 = 720pt: 35 body rows after the header
+        assert page_count(render_uncompressed(font_doc(self._table(rows=35)))) == 1
+        assert page_count(render_uncompressed(font_doc(self._table(rows=36)))) == 2
+
+    def test_break_at_bottom_margin(self, font_doc):
+        # 792 - 72 - 36 = 684pt: 33 body rows after the header
+        margins = {"margins": {"bottom": 36}}
+        assert page_count(render_uncompressed(font_doc(self._table(rows=33), page=margins))) == 1
+        assert page_count(render_uncompressed(font_doc(self._table(rows=34), page=margins))) == 2
+
+    def test_continued_y_mirrors_as_bottom_margin(self, font_doc):
+        # 792 - 2 * 72 = 648pt per page: 31 body rows after the header
+        assert page_count(render_uncompressed(font_doc(self._table(rows=31, continued_y=72)))) == 1
+        assert page_count(render_uncompressed(font_doc(self._table(rows=32, continued_y=72)))) == 2
+
+    def test_table_in_lower_half_stays_on_page(self, font_doc):
+        pdf = render_uncompressed(font_doc(self._table(rows=5, y=450)))
+        assert page_count(pdf) == 1
+        assert pdf.count(b" re\nS") == 2 * 6
+
+    def test_continuation_pages_follow_source_page(self, font_doc):
+        doc = font_doc(self._table(max_height=400))
+        doc["pages"].append({"size": (300, 300), "elements": []})
+        boxes = render_uncompressed(doc).split(b"/MediaBox [")[1:]
+        sizes = [b.split(b"]")[0].split()[2:] for b in boxes]
+        assert sizes[-1] == [b"300", b"300"]
+        assert sizes[:-1] == [[b"612", b"792"]] * 4
+
+
+class TestRectSides:
+    """Test partial rect borders."""
+
+    RECT = {"type": "rect", "x": 100, "y": 100, "w": 200, "h": 50, "stroke": 1}
+
+    def test_only_listed_sides_stroked(self):
+        pdf = render_uncompressed(page_doc(dict(self.RECT, sides=["top", "bottom"])))
+        assert b"100 692 m\n300 692 l" in pdf
+        assert b"300 642 m\n100 642 l" in pdf
+        assert pdf.count(b" m\n") == 2
+
+    def test_fill_covers_full_area(self):
+        pdf = render_uncompressed(page_doc(dict(self.RECT, sides=["left"], fill_color=(240, 240, 240, 255))))
+        assert b"100 642 200 50 re\nf" in pdf
+
+    def test_no_sides_no_stroke_segments(self):
+        assert b" m\n" not in render_uncompressed(page_doc(dict(self.RECT, sides=[])))
+
+    def test_default_strokes_outline(self):
+        assert b"100 642 200 50 re\nS" in render_uncompressed(page_doc(self.RECT))
+
+
+class TestFillPattern:
+    """Test hatch fills on rects."""
+
+    RECT = {"type": "rect", "x": 100, "y": 100, "w": 200, "h": 50, "stroke": 0}
+
+    def _doc(self, *patterns, **rect):
+        return page_doc(*[dict(self.RECT, y=100 + i * 60, fill_pattern=p, **rect) for i, p in enumerate(patterns)])
+
+    def test_tiling_pattern(self):
+        pdf = render_uncompressed(self._doc({"style": "horizontal", "spacing": 6, "line_width": 1}))
+        assert b"/PatternType 1" in pdf
+        assert b"/XStep 6" in pdf
+        assert b"0 3 m\n6 3 l" in pdf
+        assert b"/Pattern cs\n/Hatch" in pdf
+        assert b"100 642 200 50 re\nf" in pdf
+
+    def test_same_pattern_written_once(self):
+        pattern = {"style": "cross", "spacing": 4}
+        pdf = render_uncompressed(self._doc(pattern, pattern, {"style": "dots"}))
+        assert pdf.count(b"/PatternType 1") == 2
+        assert pdf.count(b"/Pattern cs") == 3
+
+    def test_over_fill_color(self):
+        pdf = render_uncompressed(self._doc({}, fill_color=(255, 255, 0, 255)))
+        assert pdf.index(b"1 1 0 rg") < pdf.index(b"/Pattern cs")
+
+    def test_spot_color(self):
+        pdf = render_uncompressed(self._doc({"color": {"spot": "PANTONE 213 C", "alt": (230, 0, 126, 255)}}))
+        assert b"/Separation /PANTONE#20213#20C" in pdf
+        assert b"/ColorSpace <<\n      /Spot" in pdf
+
+
+class TestRectGradient:
+    """Test linear and radial gradient fills on rects."""
+
+    RECT = {"type": "rect", "x": 100, "y": 100, "w": 200, "h": 50, "stroke": 0}
+    STOPS = [(0.0, (255, 0, 0, 255)), (1.0, (0, 0, 255, 255))]
+
+    def _doc(self, *gradients, **rect):
+        return page_doc(*[dict(self.RECT, y=100 + i * 60, gradient=g, **rect) for i, g in enumerate(gradients)])
+
+    def test_axial_shading(self):
+        pdf = render_uncompressed(self._doc({"type": "linear", "angle": 45, "stops": self.STOPS}))
+        assert b"/ShadingType 2" in pdf
+        assert b"100 642 200 50 re\nW\nn" in pdf
+        assert b"/Sh0 sh" in pdf
+
+    def test_same_gradient_and_size_written_once(self):
+        gradient = {"stops": self.STOPS}
+        pdf = render_uncompressed(self._doc(gradient, gradient, {"angle": 90, "stops": self.STOPS}))
+        assert pdf.count(b"/ShadingType 2") == 2
+        assert pdf.count(b" sh\n") == 3
+
+    def test_over_fill_color(self):
+        pdf = render_uncompressed(self._doc({"stops": self.STOPS}, fill_color=(255, 255, 0, 255)))
+        assert pdf.index(b"1 1 0 rg") < pdf.index(b"/Sh0 sh")
+
+    def test_clipped_to_rounded_corners(self):
+        pdf = render_uncompressed(self._doc({"stops": self.STOPS}, corner_radius=10))
+        clip = pdf.index(b"W\nn")
+        assert b"110 692 m\n290 692 l\n295.523 692 300 687.523 300 682 c" in pdf[:clip]
+
+    def test_radial_shading(self):
+        pdf = render_uncompressed(self._doc({"type": "radial", "center": (50, 10), "radius": 80, "stops": self.STOPS}))
+        assert b"/ShadingType 3" in pdf
+        assert b"/Coords [50 40 0 50 40 80]" in pdf
+
+    def test_radial_defaults_to_farthest_corner(self):
+        pdf = render_uncompressed(self._doc({"type": "radial", "stops": [(0, "#ffffff"), (1, "#000000")]}, w=80, h=60))
+        assert b"/Coords [40 30 0 40 30 50]" in pdf
+
+    def test_radial_center_is_element_local(self):
+        gradient = {"type": "radial", "center": (20, 20), "stops": self.STOPS}
+        pdf = render_uncompressed(self._doc(gradient, gradient))
+        assert pdf.count(b"/ShadingType 3") == 1
+        assert pdf.count(b" sh\n") == 2
+
+    def test_radial_lengths_follow_units(self):
+        gradient = {"type": "radial", "center": (25, 20), "radius": "1in", "stops": self.STOPS}
+        rect = dict(self.RECT, x=10, y=10, w=50, h=40, gradient=gradient)
+        pdf = render_uncompressed(page_doc(rect, size=(210, 297), units="mm"))
+        assert b"/Coords [70.86614 56.692917 0 70.86614 56.692917 72]" in pdf
+
+
+class TestCornerStyle:
+    """Test circular and continuous rounded corners."""
+
+    RECT = {"type": "rect", "x": 100, "y": 100, "w": 200, "h": 100, "stroke": 1}
+
+    def test_circular_is_default(self):
+        path = b"110 692 m\n290 692 l\n295.523 692 300 687.523 300 682 c"
+        assert path in render_uncompressed(page_doc(dict(self.RECT, corner_radius=10)))
+        assert path in render_uncompressed(page_doc(dict(self.RECT, corner_radius=10, corner_style="circular")))
+
+    def test_continuous_curve_starts_further_out(self):
+        pdf = render_uncompressed(page_doc(dict(self.RECT, corner_radius=25, corner_style="continuous")))
+        assert b"132 692 m\n268 692 l\n289.5 692 300 681.5 300 660 c" in pdf
+
+    def test_continuous_clamped_to_short_side(self):
+        with pytest.warns(rupdf.RupdfWarning, match="corner_radius 500 is more than half the shorter side"):
+            pdf = render_uncompressed(page_doc(dict(self.RECT, corner_radius=500, corner_style="continuous")))
+        assert b"150 692 m\n250 692 l" in pdf
+
+    def test_from_defaults(self):
+        rect = {"type": "rect", "x": 100, "y": 100, "w": 200, "h": 100, "corner_radius": 25}
+        doc = page_doc(rect, defaults={"rect": {"corner_style": "continuous"}})
+        assert b"132 692 m" in render_uncompressed(doc)
+
+    def test_per_corner_radii(self):
+        pdf = render_uncompressed(page_doc(dict(self.RECT, corner_radius=(20, 20, 0, 0), fill_color=(0, 0, 255, 255))))
+        path = (
+            b"120 692 m\n280 692 l\n291.046 692 300 683.046 300 672 c\n"
+            b"300 592 l\n100 592 l\n100 672 l\n100 683.046 108.954 692 120 692 c\nh"
+        )
+        # The fill and the stroke follow the same outline
+        assert pdf.count(path) == 2
+
+    def test_per_corner_radii_scaled_to_fit(self):
+        with pytest.warns(rupdf.RupdfWarning, match=r"corner_radius \(150, 150, 0, 0\) adds up to more than a side; using \(100, 100, 0, 0\)"):
+            pdf = render_uncompressed(page_doc(dict(self.RECT, corner_radius=(150, 150, 0, 0))))
+        assert b"200 692 m\n200 692 l" in pdf
+
+    def test_per_corner_radii_take_units(self):
+        pdf = render_uncompressed(page_doc(dict(self.RECT, corner_radius=("20pt", 0, "0mm", 0))))
+        assert b"120 692 m\n300 692 l\n300 592 l" in pdf
+
+
+class TestImagePlaceholder:
+    """Test the image_placeholder element."""
+
+    PLACEHOLDER = {"type": "image_placeholder", "x": 300, "y": 100, "font": "f", "label": "Photo"}
+
+    def test_box_and_cross(self, font_doc):
+        pdf = render_uncompressed(font_doc(dict(self.PLACEHOLDER, w=200, h=100)))
+        assert b"300 692 m\n500 592 l" in pdf
+        assert b"300 592 m\n500 692 l" in pdf
+        assert pdf.count(b"\nBT\n") == 1
+
+    def test_aligns_like_an_image(self, font_doc):
+        pdf = render_uncompressed(font_doc(dict(self.PLACEHOLDER, w=200, h=100, align="center")))
+        assert b"200 692 m\n400 592 l" in pdf
+
+    def test_aspect_gives_missing_side(self, font_doc):
+        pdf = render_uncompressed(font_doc(dict(self.PLACEHOLDER, w=200, aspect=2)))
+        assert b"300 692 m\n500 592 l" in pdf
+        pdf = render_uncompressed(font_doc(dict(self.PLACEHOLDER, h=100, aspect=2, align="right")))
+        assert b"100 692 m\n300 592 l" in pdf
+
+    def test_note_adds_caption_row(self, font_doc):
+        pdf = render_uncompressed(font_doc(dict(self.PLACEHOLDER, w=200, h=100, note="16:9")))
+        assert pdf.count(b"\nBT\n") == 2
+
+
+class TestGrid:
+    """Test the grid element."""
+
+    GRID = {"type": "grid", "spacing": 72, "major_every": 2}
+
+    def test_lines_cover_the_page(self, font_doc):
+        pdf = render_uncompressed(font_doc(self.GRID))
+        assert b"72 792 m\n72 0 l" in pdf
+        assert b"0 648 m\n612 648 l" in pdf
+        assert b"\nBT\n" not in pdf
+
+    def test_labels_need_a_font(self, font_doc):
+        assert render_uncompressed(font_doc(dict(self.GRID, font="f"))).count(b"\nBT\n") == 9
+
+    def test_z_orders_against_content(self):
+        line = {"type": "line", "x1": 10, "y1": 10, "x2": 20, "y2": 10, "color": (255, 0, 0, 255)}
+        grid = dict(self.GRID, color=(0, 0, 255, 255))
+        under = render_uncompressed(page_doc(line, dict(grid, z=-1)))
+        assert under.index(b"0 0 1 RG") < under.index(b"1 0 0 RG")
+        over = render_uncompressed(page_doc(line, grid))
+        assert over.index(b"0 0 1 RG") > over.index(b"1 0 0 RG")
+
+    def test_bottom_left_rows_count_from_the_bottom(self):
+        pdf = render_uncompressed(page_doc(dict(self.GRID, spacing=100), origin="bottom_left"))
+        assert b"0 100 m\n612 100 l" in pdf
+        assert b"0 692 m\n612 692 l" not in pdf
+
+
+class TestSignatureLine:
+    """Test the signature_line element."""
+
+    LINE = {"type": "signature_line", "x": 72, "y": 100, "w": 200, "font": "f", "stroke": 1}
+
+    def test_rule_only(self, font_doc):
+        pdf = render_uncompressed(font_doc(self.LINE))
+        assert b"72 692 m\n272 692 l" in pdf
+        assert b"\nBT\n" not in pdf
+
+    def test_label_sublabels_and_mark(self, font_doc):
+        pdf = render_uncompressed(font_doc(dict(self.LINE, label="Jane Doe", sublabels=["Title", "Date"], mark=True)))
+        assert pdf.count(b"\nBT\n") == 4
+        assert b"\n172 " in pdf  # second sublabel starts halfway along
+
+    def test_font_from_defaults(self, font_doc):
+        line = {"type": "signature_line", "x": 72, "y": 100, "w": 200}
+        doc = font_doc(line, defaults={"signature_line": {"font": "f", "label": "Name"}})
+        assert render_uncompressed(doc).count(b"\nBT\n") == 1
+
+
+class TestVisible:
+    """Test hiding elements with visible: False."""
+
+    TEXT = {"type": "text", "x": 72, "y": 100, "text": "Placeholder", "font": "f", "size": 12}
+    RECT = {"type": "rect", "x": 72, "y": 72, "w": 10, "h": 10}
+
+    def test_hidden_text_embeds_no_font(self, font_doc):
+        assert b"/FontFile" in render_uncompressed(font_doc(self.TEXT))
+        hidden = render_uncompressed(font_doc(dict(self.TEXT, visible=False)))
+        assert b"\nBT\n" not in hidden
+        assert b"/FontFile" not in hidden
+
+    def test_hidden_image_not_embedded(self, png_path):
+        image = {"type": "image", "x": 72, "y": 72, "w": 50, "image_ref": "logo", "visible": False}
+        pdf = render_uncompressed(page_doc(image, images={"logo": {"path": png_path}}))
+        assert b"/Subtype /Image" not in pdf
+
+    def test_hidden_group_hides_children(self):
+        group = {"type": "group", "visible": False, "elements": [self.RECT]}
+        assert b" re\n" not in render_uncompressed(page_doc(group))
+
+    def test_from_defaults(self):
+        doc = page_doc(self.RECT, defaults={"rect": {"visible": False}})
+        assert b" re\n" not in render_uncompressed(doc)
+
+
+class TestNumberText:
+    """Test formatted number content in text elements."""
+
+    TEXT = {"type": "text", "x": 72, "y": 100, "font": "f", "size": 12}
+
+    def _same(self, font_doc, number, fmt, expected):
+        formatted = render_uncompressed(font_doc(dict(self.TEXT, text={"number": number, "format": fmt})))
+        plain = render_uncompressed(font_doc(dict(self.TEXT, text=expected)))
+        # PDF output isn't byte-stable, so compare the drawn strings
+        shown = lambda pdf: re.findall(rb"Tf\n(.*?) Tj", pdf, re.S)
+        assert len(shown(formatted)) == 1
+        assert shown(formatted) == shown(plain)
+
+    def test_currency(self, font_doc):
+        fmt = {"decimals": 2, "thousands": ",", "decimal": ".", "prefix": "$"}
+        self._same(font_doc, 1234567.5, fmt, "$1,234,567.50")
+
+    def test_parentheses(self, font_doc):
+        self._same(font_doc, -42, {"decimals": 2, "prefix": "$", "negative": "parentheses"}, "($42.00)")
+
+    def test_locale(self, font_doc):
+        self._same(font_doc, 1234.5, {"locale": "de_DE", "decimals": 2, "suffix": " EUR"}, "1.234,50 EUR")
+
+    def test_locale_overridden(self, font_doc):
+        self._same(font_doc, 1234.5, {"locale": "de", "thousands": ""}, "1234,5")
+
+    def test_decimal_and_string(self, font_doc):
+        import decimal
+        self._same(font_doc, decimal.Decimal("2.675"), {"decimals": 2}, "2.68")
+        self._same(font_doc, "1E+3", {"thousands": ","}, "1,000")
+
+    def test_without_format(self, font_doc):
+        self._same(font_doc, 0.1 + 0.2, {}, "0.30000000000000004")
+
+
+class TestPaintOrder:
+    """Test z ordering of elements."""
+
+    RECT = {"type": "rect", "y": 100, "w": 10, "h": 10, "stroke": 0, "fill_color": (0, 0, 0, 255)}
+
+    def _painted_in_order(self, pdf, *xs):
+        positions = [pdf.index(b"\n%d 682 10 10 re" % x) for x in xs]
+        return positions == sorted(positions)
+
+    def test_sorted_by_z(self):
+        rects = [dict(self.RECT, x=1, z=10), dict(self.RECT, x=2), dict(self.RECT, x=3, z=-1), dict(self.RECT, x=4)]
+        assert self._painted_in_order(render_uncompressed(page_doc(*rects)), 3, 2, 4, 1)
+
+    def test_without_z_unchanged(self):
+        rects = [dict(self.RECT, x=x) for x in (1, 2, 3)]
+        assert self._painted_in_order(render_uncompressed(page_doc(*rects)), 1, 2, 3)
+
+    def test_group_children(self):
+        group = {"type": "group", "elements": [dict(self.RECT, x=1, z=1), dict(self.RECT, x=2)]}
+        assert self._painted_in_order(render_uncompressed(page_doc(group)), 2, 1)
+
+    def test_repeating_layers_sort_within(self):
+        doc = page_doc(
+            dict(self.RECT, x=3, z=100),
+            repeating_elements=[dict(self.RECT, x=1, z=5), dict(self.RECT, x=2)],
+        )
+        assert self._painted_in_order(render_uncompressed(doc), 3, 2, 1)
+
+
+class TestSkew:
+    """Test skew_x / skew_y on text and rect elements."""
+
+    RECT = {"type": "rect", "x": 100, "y": 100, "w": 200, "h": 50}
+
+    def test_rect_shears_about_its_center(self):
+        pdf = render_uncompressed(page_doc(dict(self.RECT, fill_color=(0, 0, 0, 255), skew_x=45)))
+        assert b"1 0 1 1 -667 0 cm" in pdf
+
+    def test_text_shears_about_its_anchor(self, font_doc):
+        text = {"type": "text", "x": 300, "y": 200, "text": "Hi", "font": "f", "size": 12, "skew_y": 45}
+        assert b"1 1 0 1 0 -300 cm" in render_uncompressed(font_doc(text))
+
+    def test_no_skew_no_transform(self):
+        assert b" cm\n" not in render_uncompressed(page_doc(self.RECT))
+
+
+class TestRectRotation:
+    """Test rotation and rotation_origin on rects."""
+
+    RECT = {"type": "rect", "x": 100, "y": 100, "w": 200, "h": 100, "stroke": 1}
+
+    def test_turns_about_center(self):
+        pdf = render_uncompressed(page_doc(dict(self.RECT, rotation=90, fill_color=(255, 0, 0, 255))))
+        assert b"q\n0 -1 1 0 -442 842 cm\n" in pdf
+        # One transform covers the fill and the stroke
+        assert pdf.count(b" cm\n") == 1
+
+    def test_explicit_origin_from_top_left(self):
+        pdf = render_uncompressed(page_doc(dict(self.RECT, rotation=15, rotation_origin=(0, 0))))
+        assert b"0.9659258 -0.25881904 0.25881904 0.9659258 -175.69537 49.461243 cm" in pdf
+
+    def test_bottom_left_origin_measures_up(self):
+        rect = dict(self.RECT, y=592, rotation=15, rotation_origin=(0, 100))
+        assert b"-175.69537 49.461243 cm" in render_uncompressed(page_doc(rect, origin="bottom_left"))
+
+    def test_before_skew(self):
+        pdf = render_uncompressed(page_doc(dict(self.RECT, rotation=90, skew_x=45)))
+        assert pdf.index(b"0 -1 1 0 -442 842 cm") < pdf.index(b"1 0 1 1 -642 0 cm")
+
+    def test_no_rotation_no_transform(self):
+        assert b" cm\n" not in render_uncompressed(page_doc(dict(self.RECT, rotation=0)))
+
+
+class TestTextRotation:
+    """Test rotation on text elements."""
+
+    TEXT = {"type": "text", "x": 300, "y": 200, "text": "Hi", "font": "f", "size": 12}
+
+    def test_turns_counter_clockwise_about_anchor(self, font_doc):
+        assert b"q\n0 1 -1 0 892 292 cm\n" in render_uncompressed(font_doc(dict(self.TEXT, rotation=90)))
+
+    def test_other_angles(self, font_doc):
+        turned = lambda rotation: render_uncompressed(font_doc(dict(self.TEXT, rotation=rotation)))
+        assert b"q\n-1 0 0 -1 600 1184 cm\n" in turned(180)
+        assert b"q\n0 -1 1 0 -292 892 cm\n" in turned(270)
+        assert b"0.70710677 0.70710677 -0.70710677 0.70710677" in turned(45)
+
+    def test_centered_text_stays_on_its_anchor(self, font_doc):
+        plain = render_uncompressed(font_doc(dict(self.TEXT, align="center")))
+        turned = render_uncompressed(font_doc(dict(self.TEXT, align="center", rotation=30)))
+        td = re.search(rb"([\d.]+) 592 Td", plain).group(0)
+        assert td in turned
+
+    def test_before_skew(self, font_doc):
+        pdf = render_uncompressed(font_doc(dict(self.TEXT, rotation=90, skew_y=45)))
+        assert pdf.index(b"0 1 -1 0 892 292 cm") < pdf.index(b"1 1 0 1 0 -300 cm")
+
+    def test_no_rotation_no_transform(self, font_doc):
+        assert b" cm\n" not in render_uncompressed(font_doc(dict(self.TEXT, rotation=0)))
+
+
+class TestTextOutline:
+    """Test text drawn as glyph outlines."""
+
+    TEXT = {"type": "text", "x": 300, "y": 200, "text": "Hi", "font": "f", "size": 24, "outline": True}
+
+    def _render(self, doc):
+        with pytest.warns(rupdf.RupdfWarning, match="drawn as glyph outlines"):
+            return render_uncompressed(doc)
+
+    def test_no_font_is_embedded(self, font_doc):
+        pdf = self._render(font_doc(self.TEXT))
+        assert b"/FontFile" not in pdf
+        assert b"/Font" not in pdf
+        assert b" Tf" not in pdf
+        assert b" m\n" in pdf and b"\nf\n" in pdf
+
+    def test_filled_in_the_text_color(self, font_doc):
+        assert b"0 0 1 rg\n" in self._render(font_doc(dict(self.TEXT, color=(0, 0, 255, 255))))
+
+    def test_alignment_moves_the_paths(self, font_doc):
+        def first_x(pdf):
+            return float(re.search(rb"([\d.]+) [\d.]+ m\n", pdf).group(1))
+
+        left = first_x(self._render(font_doc(self.TEXT)))
+        right = first_x(self._render(font_doc(dict(self.TEXT, align="right"))))
+        assert left > 300
+        assert right < 300
+
+    def test_plain_text_still_embeds_the_font(self, font_doc):
+        plain = {"type": "text", "x": 72, "y": 100, "text": "Shown", "font": "f", "size": 12}
+        pdf = self._render(font_doc(self.TEXT, plain))
+        assert pdf.count(b" Tf") == 1
+        assert b"/FontFile" in pdf
+
+
+class TestLeader:
+    """Test the leader element."""
+
+    LEADER = {"type": "leader", "x1": 72, "x2": 172, "y": 100}
+
+    def test_dots(self):
+        pdf = render_uncompressed(page_doc(self.LEADER))
+        assert b"1 J" in pdf
+        assert b"[0 3] 0 d" in pdf
+        assert b"72.5 692 m" in pdf
+
+    def test_dashes_and_solid(self):
+        assert b"[3 2] 0 d" in render_uncompressed(page_doc(dict(self.LEADER, style="dashes")))
+        solid = render_uncompressed(page_doc(dict(self.LEADER, style="solid", dot_size=0.5)))
+        assert b"0.5 w" in solid
+        assert b"72 692 m\n172 692 l" in solid
+
+    def test_gap(self):
+        assert b"[0 5] 0 d" in render_uncompressed(page_doc(dict(self.LEADER, dot_size=2, gap=3)))
+
+    def test_font_baseline(self, font_doc):
+        pdf = render_uncompressed(font_doc(dict(self.LEADER, font="f", size=10)))
+        assert b"1.29 w" in pdf
+        assert b" 692.525 m" in pdf
+
+    def test_too_short_draws_nothing(self):
+        assert b" m\n" not in render_uncompressed(page_doc(dict(self.LEADER, x2=72.5)))
+
+
+class TestProgressRing:
+    """Test the progress_ring element."""
+
+    RING = {"type": "progress_ring", "cx": 100, "cy": 100, "radius": 50,
+            "thickness": 8, "value": 0.25, "track_color": (224, 224, 224, 255)}
+
+    def test_quarter(self):
+        pdf = render_uncompressed(page_doc(self.RING))
+        assert b"8 w" in pdf
+        assert b"1 J\n100 742 m\n" in pdf
+        assert b" 150 692 c\nS" in pdf
+        assert pdf.count(b" c\n") == 5
+
+    def test_start_angle(self):
+        assert b"1 J\n150 692 m\n" in render_uncompressed(page_doc(dict(self.RING, start_angle=90)))
+
+    def test_zero_and_full(self):
+        assert render_uncompressed(page_doc(dict(self.RING, value=0))).count(b" c\n") == 4
+        full = render_uncompressed(page_doc(dict(self.RING, value=1)))
+        assert full.count(b" c\n") == 8
+        assert b"1 J" not in full
+
+    def test_no_track(self):
+        assert render_uncompressed(page_doc(dict(self.RING, track_color=None, value=1))).count(b" c\n") == 4
+
+
+class TestElementIds:
+    """Test element ids in the output."""
+
+    def test_id_does_not_change_output(self):
+        rect = {"type": "rect", "x": 72, "y": 72, "w": 50, "h": 50}
+        plain = render_uncompressed(page_doc(rect))
+        tagged = render_uncompressed(page_doc(dict(rect, id="box")))
+        assert len(plain) == len(tagged)
+
+
+class TestStrokeStyle:
+    """Test dash patterns, caps, joins and miter limits."""
+
+    LINE = {"type": "line", "x1": 0, "y1": 10, "x2": 100, "y2": 10}
+    RECT = {"type": "rect", "x": 10, "y": 10, "w": 50, "h": 50}
+
+    def test_dash_with_phase(self):
+        assert b"[6 3] 2 d" in render_uncompressed(page_doc(dict(self.LINE, dash=[6, 3], dash_phase=2)))
+
+    def test_miter_limit(self):
+        assert b"1.5 M" in render_uncompressed(page_doc(dict(self.RECT, miter_limit=1.5)))
+
+    def test_document_wide_through_defaults(self):
+        pdf = render_uncompressed(page_doc(self.LINE, self.RECT, defaults={"dash": [1, 1], "dash_phase": 0.5}))
+        assert pdf.count(b"[1 1] 0.5 d") == 2
+
+    def test_empty_dash_is_solid(self):
+        assert b" d\n" not in render_uncompressed(page_doc(dict(self.LINE, dash=[])))
+
+    def test_presets_scale_with_stroke(self):
+        pdf = render_uncompressed(page_doc(dict(self.LINE, stroke=2, dash="dashed"), dict(self.RECT, stroke=0.5, dash="dotted")))
+        assert b"[6 4] 0 d\n" in pdf
+        # Round-capped zero-length dashes: dots half a point across
+        assert b"[0 1] 0 d\n1 J\n" in pdf
+
+    def test_dotted_rect_sides_keep_round_caps(self):
+        pdf = render_uncompressed(page_doc(dict(self.RECT, sides=["top"], dash="dotted")))
+        assert b"1 J\n" in pdf
+        assert b"2 J\n" not in pdf
+
+    def test_hairline_presets(self):
+        pdf = render_uncompressed(page_doc(dict(self.LINE, stroke="hairline", dash="dotted", dash_phase=0.5)))
+        assert b"[1 1] 0.5 d\n" in pdf
+        assert b"1 J" not in pdf
+
+    def test_line_cap_and_join(self):
+        pdf = render_uncompressed(page_doc(dict(self.LINE, stroke=6, cap="round", join="bevel", miter_limit=2)))
+        assert b"6 w\n1 J\n2 j\n2 M\n0 782 m\n" in pdf
+        assert b"2 J\n" in render_uncompressed(page_doc(dict(self.LINE, cap="square")))
+
+    def test_polyline_round_joins(self):
+        polyline = {"type": "polygon", "points": [(0, 0), (50, 40), (100, 0)], "close": False, "stroke": 8,
+                    "cap": "round", "join": "round"}
+        assert b"8 w\n1 J\n1 j\n" in render_uncompressed(page_doc(polyline))
+
+    def test_rect_join(self):
+        pdf = render_uncompressed(page_doc(dict(self.RECT, stroke=4, join="round")))
+        assert b"1 j\n" in pdf
+        assert b" J\n" not in pdf
+
+    def test_explicit_cap_wins(self):
+        # Over a dotted dash's round caps, and the projecting caps of sides
+        assert b"1 J\n2 J\n" in render_uncompressed(page_doc(dict(self.LINE, dash="dotted", cap="square")))
+        pdf = render_uncompressed(page_doc(dict(self.RECT, sides=["top", "left"], cap="butt")))
+        assert b"0 J\n" in pdf
+        assert b"2 J\n" not in pdf
+
+    def test_defaults_by_default(self):
+        pdf = render_uncompressed(page_doc(self.LINE, self.RECT))
+        assert b" J\n" not in pdf
+        assert b" j\n" not in pdf
+
+
+class TestStrokeAlign:
+    """Test inside and outside rect strokes."""
+
+    RECT = {"type": "rect", "x": 100, "y": 100, "w": 200, "h": 50, "stroke": 4, "fill_color": (0, 0, 255, 255)}
+
+    def test_inside_insets_stroke_path(self):
+        pdf = render_uncompressed(page_doc(dict(self.RECT, stroke_align="inside")))
+        assert b"100 642 200 50 re\nf" in pdf
+        assert b"102 644 196 46 re\nS" in pdf
+
+    def test_outside_outsets_stroke_path(self):
+        assert b"98 640 204 54 re\nS" in render_uncompressed(page_doc(dict(self.RECT, stroke_align="outside")))
+
+
+class TestHairline:
+    """Test "hairline" strokes and zero-width strokes."""
+
+    LINE = {"type": "line", "x1": 72, "y1": 72, "x2": 200, "y2": 72}
+    RECT = {"type": "rect", "x": 100, "y": 100, "w": 200, "h": 50}
+
+    def test_hairline_is_width_zero(self):
+        pdf = render_uncompressed(page_doc(dict(self.LINE, stroke="hairline"), dict(self.RECT, stroke="hairline")))
+        # Set once and used by both strokes
+        assert pdf.count(b"\n0 w\n") == 1
+        assert pdf.count(b"\nS\n") == 2
+
+    def test_zero_is_no_stroke(self):
+        pdf = render_uncompressed(page_doc(dict(self.LINE, stroke=0), dict(self.RECT, stroke=0)))
+        assert b" w\n" not in pdf
+        assert b"\nS\n" not in pdf
+
+    def test_hairline_through_defaults(self):
+        assert b"\n0 w\n" in render_uncompressed(page_doc(self.LINE, defaults={"stroke": "hairline"}))
+
+
+class TestArtifacts:
+    """Test artifact marking and reading_order."""
+
+    RECT = {"type": "rect", "x": 72, "y": 72, "w": 100, "h": 50}
+
+    def test_artifact_wrapped_in_marked_content(self):
+        rule = {"type": "line", "x1": 72, "y1": 700, "x2": 540, "y2": 700, "artifact": True}
+        pdf = render_uncompressed(page_doc(rule, self.RECT))
+        assert pdf.count(b"/Artifact BMC") == 1
+        assert pdf.count(b"EMC") == 1
+
+    def test_artifact_through_defaults(self):
+        assert b"/Artifact BMC" in render_uncompressed(page_doc(self.RECT, defaults={"rect": {"artifact": True}}))
+
+    def test_reading_order_accepted(self):
+        footer = dict(self.RECT, y=740, h=20, reading_order=2)
+        body = dict(self.RECT, h=20, reading_order=1)
+        assert b"/Artifact" not in render_uncompressed(page_doc(footer, body))
+
+
+class TestInlineImages:
+    """Test image spans flowed with textbox text."""
+
+    TEXTBOX = {"type": "textbox", "x": 72, "y": 72, "w": 200, "h": 100, "font": "f", "size": 12}
+
+    def test_image_span_drawn_at_display_size(self, font_doc, png_path):
+        text = ["Made by ", {"image_ref": "logo", "h": 10}, " and ", {"image_ref": "logo", "h": 10, "baseline_shift": -2}]
+        pdf = render_uncompressed(font_doc(dict(self.TEXTBOX, text=text), images={"logo": {"path": png_path}}))
+        assert pdf.count(b"/logo_10x10 Do") == 2
+        assert pdf.count(b"/Subtype /Image") == 1
+
+    def test_text_spans_match_plain_text(self, font_doc):
+        plain = render_uncompressed(font_doc(dict(self.TEXTBOX, text="Hello world")))
+        spans = render_uncompressed(font_doc(dict(self.TEXTBOX, text=["Hello ", {"text": "world"}])))
+        assert plain.count(b"\nBT\n") == spans.count(b"\nBT\n") == 1
+        assert b" Do" not in spans
+
+
+class TestCheck:
+    """Test checkbox elements."""
+
+    CHECK = {"type": "check", "x": 100, "y": 100, "size": 10}
+
+    def test_unchecked_draws_box_only(self):
+        pdf = render_uncompressed(page_doc(self.CHECK))
+        assert b"100 682 10 10 re" in pdf
+        assert b" l\n" not in pdf
+
+    def test_checked_draws_tick(self):
+        pdf = render_uncompressed(page_doc(dict(self.CHECK, state="checked", mark_color=(255, 0, 0, 255))))
+        assert b"1 0 0 RG" in pdf
+        assert pdf.count(b" m\n") == 1
+
+    def test_crossed_draws_two_strokes(self):
+        assert render_uncompressed(page_doc(dict(self.CHECK, state="crossed"))).count(b" m\n") == 2
+
+
+class TestGroup:
+    """Test group elements and transparency groups."""
+
+    RECTS = [
+        {"type": "rect", "x": 100, "y": 100, "w": 50, "h": 50, "stroke": 0,
+         "fill_color": (255, 0, 0, 255)},
+        {"type": "rect", "x": 125, "y": 100, "w": 50, "h": 50, "stroke": 0,
+         "fill_color": (0, 0, 255, 255)},
+    ]
+
+    def _group(self, **options):
+        return dict({"type": "group", "elements": self.RECTS}, **options)
+
+    def test_plain_group_draws_children(self):
+        pdf = render_uncompressed(page_doc(self._group()))
+        assert pdf.count(b" re\nf") == 2
+        assert b"/Subtype /Form" not in pdf
+
+    def test_transparency_group_uses_form_xobject(self):
+        pdf = render_uncompressed(page_doc(self._group(transparency_group=True, opacity=0.25)))
+        assert b"/Subtype /Form" in pdf
+        assert b"/S /Transparency" in pdf
+        assert b"/A64 gs\n/G0 Do" in pdf
+
+    def test_nested_groups(self):
+        inner = self._group(transparency_group=True, opacity=0.5)
+        pdf = render_uncompressed(page_doc(self._group(transparency_group=True, elements=[inner])))
+        assert b"/G0 Do" in pdf and b"/G1 Do" in pdf
+
+    def test_repeated_group_shares_one_form(self):
+        doc = {
+            "pages": [{"size": (612, 792)}, {"size": (612, 792)}],
+            "repeating_elements": [self._group(transparency_group=True, opacity=0.5)],
+        }
+        assert render_uncompressed(doc).count(b"/Subtype /Form") == 1
+
+    def test_opacity_alone_uses_a_transparency_group(self):
+        pdf = render_uncompressed(page_doc(self._group(opacity=0.5)))
+        assert b"/S /Transparency" in pdf
+        assert b"/A128 gs\n/G0 Do" in pdf
+
+    def test_transform_wraps_children(self):
+        pdf = render_uncompressed(page_doc(self._group(x=200, y=300, rotation=90)))
+        assert b"q\n0 -1 1 0 -592 492 cm\n" in pdf
+        assert pdf.count(b" re\nf") == 2
+
+    def test_nested_transforms_compose(self):
+        inner = self._group(x=10, scale=2)
+        pdf = render_uncompressed(page_doc(self._group(x=200, y=300, rotation=90, elements=[inner])))
+        assert pdf.index(b"0 -1 1 0 -592 492 cm") < pdf.index(b"2 0 0 2 10 -792 cm")
+
+
+class TestFragment:
+    """Test document fragments and fragment elements."""
+
+    BOX = [{"type": "rect", "x": 0, "y": 0, "w": 50, "h": 20, "stroke": 0,
+            "fill_color": (255, 0, 0, 255)}]
+    PLACE = {"type": "fragment", "fragment_ref": "box"}
+
+    def test_single_placement_is_inline(self):
+        pdf = render_uncompressed(page_doc(dict(self.PLACE, x=72, y=100), fragments={"box": self.BOX}))
+        assert b"/Subtype /Form" not in pdf
+        assert b"1 0 0 1 72 -100 cm" in pdf
+        assert pdf.count(b" re\nf") == 1
+
+    def test_repeated_placements_share_a_form(self):
+        placements = [dict(self.PLACE, x=72, y=100), dict(self.PLACE, x=72, y=200), dict(self.PLACE, x=72, y=300, scale=2)]
+        pdf = render_uncompressed(page_doc(*placements, fragments={"box": self.BOX}))
+        assert pdf.count(b"/Subtype /Form") == 1
+        assert pdf.count(b"/F0 Do") == 3
+        assert pdf.count(b" re\nf") == 1
+        assert b"2 0 0 2 72 -1092 cm" in pdf
+
+    def test_repeating_element_shares_a_form(self):
+        doc = {
+            "fragments": {"box": self.BOX},
+            "repeating_elements": [dict(self.PLACE, x=72, y=100)],
+            "pages": [{"size": (612, 792)}, {"size": (612, 792)}],
+        }
+        assert render_uncompressed(doc).count(b"/F0 Do") == 2
+
+    def test_nested_fragments(self):
+        fragments = {"box": self.BOX, "pair": [dict(self.PLACE, x=0, y=0), dict(self.PLACE, x=60, y=0)]}
+        pdf = render_uncompressed(page_doc(dict(self.PLACE, fragment_ref="pair", x=72, y=72), fragments=fragments))
+        assert pdf.count(b"/F0 Do") == 2
+        assert pdf.count(b" re\nf") == 1
+
+
+class TestImageProbing:
+    """Test that probed raster images still embed."""
+
+    def test_png_renders(self, png_bytes):
+        image = {"type": "image", "x": 72, "y": 72, "w": 100, "image_ref": "img"}
+        assert b"/Subtype /Image" in rupdf.render_pdf(page_doc(image, images={"img": {"bytes": png_bytes}}))
+
+
+class TestHighBitDepthImages:
+    """Test that 16-bit rasters are reduced to 8 bits for embedding."""
+
+    IMAGE = {"type": "image", "x": 72, "y": 72, "w": 256, "image_ref": "ramp"}
+
+    def test_gray16_renders(self, gray16_png_path):
+        pdf = render_uncompressed(page_doc(self.IMAGE, images={"ramp": {"path": gray16_png_path}}))
+        assert b"/Subtype /Image" in pdf
+        assert b"/BitsPerComponent 8" in pdf
+
+    def test_rgb16_renders(self, rgb16_png_path):
+        pdf = render_uncompressed(page_doc(self.IMAGE, images={"ramp": {"path": rgb16_png_path}}))
+        assert b"/Subtype /Image" in pdf
+        assert b"/BitsPerComponent 8" in pdf
+
+
+class TestGifFrames:
+    """Test frame selection for animated GIFs."""
+
+    IMAGE = {"type": "image", "x": 72, "y": 72, "image_ref": "anim"}
+
+    def test_first_frame_by_default(self, gif_path):
+        assert b"/Width 40" in render_uncompressed(page_doc(self.IMAGE, images={"anim": {"path": gif_path}}))
+
+    def test_later_frame_has_canvas_size(self, gif_path):
+        pdf = render_uncompressed(page_doc(self.IMAGE, images={"anim": {"path": gif_path, "frame": 2}}))
+        assert b"/Width 40" in pdf
+        assert b"/Height 20" in pdf
+
+
+class TestImageFade:
+    """Test image fade soft masks."""
+
+    IMAGE = {"type": "image", "x": 100, "y": 100, "w": 200, "h": 100, "image_ref": "hero"}
+
+    def test_fade_writes_luminosity_mask(self, png_path):
+        pdf = render_uncompressed(page_doc(dict(self.IMAGE, fade={"edge": "bottom"}), images={"hero": {"path": png_path}}))
+        assert b"/S /Luminosity" in pdf
+        assert b"/BBox [0 0 200 100]" in pdf
+
+    def test_mask_follows_alignment(self, png_path):
+        image = dict(self.IMAGE, align="right", fade={"edge": "left", "start": 0.0, "end": 0.3})
+        assert b"1 0 0 1 -100 592 cm" in render_uncompressed(page_doc(image, images={"hero": {"path": png_path}}))
+
+    def test_no_fade_no_mask(self, png_path):
+        assert b"/SMask" not in render_uncompressed(page_doc(self.IMAGE, images={"hero": {"path": png_path}}))
+
+    def test_shared_mask_for_same_size(self, png_path):
+        images = [dict(self.IMAGE, x=x, w=50, h=50, fade={"edge": "right"}) for x in (100, 200)]
+        pdf = render_uncompressed(page_doc(*images, images={"hero": {"path": png_path}}))
+        assert pdf.count(b"/S /Luminosity") == 1
+
+
+class TestImageRotation:
+    """Test rotation on image elements."""
+
+    IMAGE = {"type": "image", "x": 100, "y": 100, "w": 200, "h": 100, "image_ref": "logo"}
+
+    def test_raster_turns_about_anchor(self, png_path):
+        pdf = render_uncompressed(page_doc(dict(self.IMAGE, rotation=90), images={"logo": {"path": png_path}}))
+        assert b"q\n0 -1 1 0 -592 792 cm\n200 0 0 100 100 592 cm\n" in pdf
+
+    def test_svg_turns_alike(self, svg_path):
+        pdf = render_uncompressed(page_doc(dict(self.IMAGE, rotation=90), images={"logo": {"path": svg_path}}))
+        assert b"q\n0 -1 1 0 -592 792 cm\n" in pdf
+
+    def test_anchor_follows_alignment(self, png_path):
+        image = dict(self.IMAGE, rotation=90, align="center")
+        pdf = render_uncompressed(page_doc(image, images={"logo": {"path": png_path}}))
+        assert b"q\n0 -1 1 0 -592 792 cm\n200 0 0 100 0 592 cm\n" in pdf
+
+    def test_raster_size_ignores_angle(self, png_path):
+        plain = render_uncompressed(page_doc(self.IMAGE, images={"logo": {"path": png_path}}))
+        turned = render_uncompressed(page_doc(dict(self.IMAGE, rotation=37), images={"logo": {"path": png_path}}))
+        width = re.compile(rb"/Width (\d+)")
+        assert width.findall(turned) == width.findall(plain)
+
+    def test_bottom_left_origin_turns_about_bottom_anchor(self, png_path):
+        image = dict(self.IMAGE, y=592, rotation=90)
+        pdf = render_uncompressed(page_doc(image, images={"logo": {"path": png_path}}, origin="bottom_left"))
+        # Placed at (200, 492) and turned, the bottom-left corner lands
+        # back on the anchor at (100, 592)
+        assert b"q\n0 -1 1 0 -392 792 cm\n200 0 0 100 200 492 cm\n" in pdf
+
+
+class TestImageQuad:
+    """Test quad placement on image elements."""
+
+    IMAGE = {"type": "image", "image_ref": "logo", "quad": [(100, 100), (260, 220), (160, 180)]}
+
+    def test_maps_image_onto_corners(self, png_path):
+        pdf = render_uncompressed(page_doc(self.IMAGE, images={"logo": {"path": png_path}}))
+        assert b"q\n0.8 -0.6 -0.6 0.8 160 612 cm\n200 0 0 100 0 0 cm\n" in pdf
+
+    def test_bottom_left_origin(self, png_path):
+        image = dict(self.IMAGE, quad=[(160, 612), (320, 492), (100, 692)])
+        pdf = render_uncompressed(page_doc(image, images={"logo": {"path": png_path}}, origin="bottom_left"))
+        assert b"0.8 -0.6 -0.6 0.8 160 612 cm\n" in pdf
+
+
+class TestImageClip:
+    """Test clipping image elements to a shape."""
+
+    IMAGE = {"type": "image", "image_ref": "logo", "x": 100, "y": 100, "w": 200, "h": 100}
+
+    def test_circle_centered_in_box(self, png_path):
+        pdf = render_uncompressed(page_doc(dict(self.IMAGE, clip="circle"), images={"logo": {"path": png_path}}))
+        assert b"q\n250 642 m\n" in pdf
+        assert b"h\nW\nn\n200 0 0 100 100 592 cm\n" in pdf
+
+    def test_ellipse_reaches_the_sides(self, png_path):
+        pdf = render_uncompressed(page_doc(dict(self.IMAGE, clip="ellipse"), images={"logo": {"path": png_path}}))
+        assert b"q\n300 642 m\n" in pdf
+
+    def test_corner_radius(self, png_path):
+        clipped = lambda clip: render_uncompressed(page_doc(dict(self.IMAGE, clip=clip), images={"logo": {"path": png_path}}))
+        assert b"q\n110 692 m\n290 692 l\n" in clipped(10)
+        assert b"q\n100 692 m\n290 692 l\n" in clipped((0, 10, 10, 10))
+
+
+class TestImageBorder:
+    """Test borders stroked around image elements."""
+
+    IMAGE = {"type": "image", "image_ref": "logo", "x": 200, "y": 100, "w": 200, "h": 100, "align": "center"}
+
+    def test_border_over_aligned_box(self, png_path):
+        image = dict(self.IMAGE, border_width=2, border_color=(255, 0, 0, 255))
+        pdf = render_uncompressed(page_doc(image, images={"logo": {"path": png_path}}))
+        assert b" Do\nQ\n1 0 0 RG\n2 w\n100 592 200 100 re\nS\n" in pdf
+
+    def test_border_follows_clip(self, png_path):
+        pdf = render_uncompressed(page_doc(dict(self.IMAGE, clip="circle", border_width=2), images={"logo": {"path": png_path}}))
+        drawn = pdf.index(b" Do\nQ\n")
+        assert b"2 w\n250 642 m\n" in pdf[drawn:]
+
+    def test_translucent_border_uses_alpha_state(self, png_path):
+        image = dict(self.IMAGE, border_width=1, border_color=(0, 0, 0, 127))
+        assert b"Q\n/A127 gs\n" in render_uncompressed(page_doc(image, images={"logo": {"path": png_path}}))
+
+    def test_no_border_by_default(self, png_path):
+        assert b" re\nS\n" not in render_uncompressed(page_doc(self.IMAGE, images={"logo": {"path": png_path}}))
+
+
+class TestSpotColors:
+    """Test spot (Separation) colors."""
+
+    SPOT = {"spot": "PANTONE 213 C", "alt": (230, 0, 126, 255)}
+    RECT = {"type": "rect", "x": 100, "y": 100, "w": 50, "h": 50}
+
+    def test_spot_fill_uses_separation(self):
+        pdf = render_uncompressed(page_doc(dict(self.RECT, fill_color=dict(self.SPOT, tint=0.25))))
+        assert b"/Separation /PANTONE#20213#20C /DeviceRGB" in pdf
+        assert b"0.25 scn" in pdf
+
+    def test_ink_written_once(self):
+        pdf = render_uncompressed(page_doc(dict(self.RECT, fill_color=self.SPOT), dict(self.RECT, fill_color=dict(self.SPOT, tint=0.5))))
+        assert pdf.count(b"/Separation") == 1
+
+    def test_spot_page_background(self):
+        pdf = render_uncompressed(page_doc(page={"background": self.SPOT}))
+        assert b"/Separation" in pdf
+        assert b"1 scn" in pdf
+
+    def test_process_colors_unchanged(self):
+        assert b"/Separation" not in render_uncompressed(page_doc(dict(self.RECT, fill_color=(255, 0, 0, 255))))
+
+
+class TestCMYKColors:
+    """Test CMYK color input."""
+
+    RECT = {"type": "rect", "x": 100, "y": 100, "w": 50, "h": 50, "stroke": 1, "stroke_color": (0, 0, 0, 255)}
+
+    def test_five_tuple_fills_with_k(self):
+        assert b"0 0.6 1 0 k" in render_uncompressed(page_doc(dict(self.RECT, fill_color=(0.0, 0.6, 1.0, 0.0, 255))))
+
+    def test_dict_form_strokes_with_upper_k(self):
+        assert b"0 0 0 1 K" in render_uncompressed(page_doc(dict(self.RECT, stroke_color={"cmyk": (0, 0, 0, 1)})))
+
+    def test_mixed_with_rgb(self):
+        line = {"type": "line", "x1": 0, "y1": 0, "x2": 100, "y2": 100, "color": (255, 0, 0, 255)}
+        pdf = render_uncompressed(page_doc(dict(self.RECT, fill_color=(0.0, 0.0, 0.0, 1.0, 255)), line))
+        assert b"0 0 0 1 k" in pdf
+        assert b"1 0 0 RG" in pdf
+
+    def test_barcode_color(self):
+        barcode = {"type": "barcode", "x": 72, "y": 72, "w": 200, "h": 60, "value": "ABC-123", "color": (0, 0, 0, 1, 255)}
+        assert b"0 0 0 1 k" in render_uncompressed(page_doc(barcode))
+
+    def test_page_background(self):
+        assert b"0.1 0 0 0 k" in render_uncompressed(page_doc(page={"background": {"cmyk": (0.1, 0.0, 0.0, 0.0)}}))
+
+    def test_translucent_cmyk_uses_alpha_state(self):
+        assert b"/A127 gs" in render_uncompressed(page_doc(dict(self.RECT, fill_color=(1.0, 0.0, 0.0, 0.0, 127))))
+
+
+class TestOutputIntent:
+    """Test ICC output intents."""
+
+    def test_catalog_references_output_intent(self):
+        pdf = render_uncompressed(page_doc(output_intent={"bytes": icc_header(b"CMYK"), "condition": "FOGRA39"}))
+        assert b"/OutputIntents [" in pdf
+        assert b"/S /GTS_PDFA1" in pdf
+        assert b"/OutputConditionIdentifier (FOGRA39)" in pdf
+        assert b"/N 4" in pdf
+
+    def test_pdfx_subtype(self):
+        intent = {"bytes": icc_header(b"RGB "), "condition": "sRGB", "subtype": "pdfx"}
+        pdf = render_uncompressed(page_doc(output_intent=intent))
+        assert b"/S /GTS_PDFX" in pdf
+        assert b"/N 3" in pdf
+
+    def test_tag_images(self, png_path):
+        image = {"type": "image", "x": 100, "y": 100, "w": 50, "h": 50, "image_ref": "img"}
+        intent = {"bytes": icc_header(b"RGB "), "condition": "sRGB", "tag_images": True}
+        pdf = render_uncompressed(page_doc(image, images={"img": {"path": png_path}}, output_intent=intent))
+        assert b"/ICCBased" in pdf
+
+    def test_no_output_intent_by_default(self):
+        assert b"/OutputIntents" not in render_uncompressed(page_doc())
+
+
+class TestCjkCapline:
+    """Test capline placement with a font that has no Latin glyphs."""
+
+    TEXT = {"type": "text", "x": 72, "y": 100, "text": "水字", "font": "cjk", "size": 40}
+
+    def test_text_hangs_from_ideograph_tops(self, cjk_font_path):
+        text = dict(self.TEXT, vertical_anchor="capline")
+        pdf = render_uncompressed(page_doc(text, fonts={"cjk": {"path": cjk_font_path}}))
+        # 水 rises 840 units, so the baseline sits 33.6 pt below y
+        assert b"72 658.4 Td" in pdf
+
+    def test_textbox_capline(self, cjk_font_path):
+        textbox = dict(self.TEXT, type="textbox", w=200, h=100, text_align_y="capline")
+        assert b"658.4 Td" in render_uncompressed(page_doc(textbox, fonts={"cjk": {"path": cjk_font_path}}))
+
+    def test_descriptor_cap_height(self, cjk_font_path):
+        text = dict(self.TEXT, text="水")
+        assert b"/CapHeight 840" in render_uncompressed(page_doc(text, fonts={"cjk": {"path": cjk_font_path}}))
+
+
+class TestBarcodeFont:
+    """Test the font behind a barcode's human-readable text."""
+
+    def test_only_font_stands_in_for_mono(self, font_path):
+        barcode = {"type": "barcode", "x": 72, "y": 72, "w": 200, "h": 60, "value": "ABC-123", "human_readable": True}
+        pdf = render_uncompressed(page_doc(barcode, fonts={"body": {"path": font_path}}))
+        assert b" Tj\n" in pdf or b"] TJ\n" in pdf
+
+
+class TestBarcodeRotation:
+    """Test rotation on barcodes."""
+
+    BARCODE = {"type": "barcode", "x": 100, "y": 100, "w": 200, "h": 60, "value": "RUPDF-1"}
+
+    def test_turns_about_top_left(self):
+        pdf = render_uncompressed(page_doc(dict(self.BARCODE, rotation=90)))
+        assert b"q\n0 -1 1 0 -592 792 cm\n0 0 0 rg\n100 632 " in pdf
+
+    def test_text_turns_with_the_bars(self, font_path):
+        barcode = dict(self.BARCODE, rotation=270, human_readable=True, font="mono")
+        pdf = render_uncompressed(page_doc(barcode, fonts={"mono": {"path": font_path}}))
+        # One save/restore wraps both the bars and the caption
+        stream = pdf[pdf.index(b"stream\nq\n0 1 -1 0 792 592 cm\n"):]
+        assert stream.index(b" Tj\n") < stream.index(b"ET\nQ\n")
+
+    def test_turned_copies_share_a_form(self):
+        pdf = render_uncompressed(page_doc(dict(self.BARCODE, rotation=90), dict(self.BARCODE, x=400)))
+        assert pdf.count(b"/Subtype /Form") == 1
+        assert b"q\n0 -1 1 0 100 692 cm\n/S0 Do\nQ" in pdf
+        assert b"q\n1 0 0 1 400 692 cm\n/S0 Do\nQ" in pdf
+
+    def test_bottom_left_origin_turns_about_bottom_anchor(self):
+        pdf = render_uncompressed(page_doc(dict(self.BARCODE, y=592, rotation=90), origin="bottom_left"))
+        # Placed at (160, 592) and turned, the bottom-left corner lands
+        # back on the anchor at (100, 592)
+        assert b"q\n0 -1 1 0 -432 752 cm\n0 0 0 rg\n160 532 " in pdf
+
+
+class TestBarcodeTextOverflow:
+    """Test human-readable barcode text wider than the barcode."""
+
+    BARCODE = {"type": "barcode", "x": 100, "y": 100, "w": 120, "h": 60, "value": "RUPDF-LONG-VALUE-123",
+               "human_readable": True, "font": "mono", "font_size": 14}
+
+    @pytest.fixture
+    def render(self, font_path):
+        def render(**barcode):
+            pdf = render_uncompressed(page_doc(dict(self.BARCODE, **barcode), fonts={"mono": {"path": font_path}}))
+            size = float(re.search(rb" ([\d.]+) Tf\n", pdf).group(1))
+            glyphs = len(re.search(rb"\((.*)\) Tj\n", pdf).group(1).replace(b"\\", b""))
+            return pdf, size, glyphs
+        return render
+
+    def test_shrinks_by_default(self, render):
+        pdf, size, _ = render()
+        assert 7 < size < 14
+        assert b"W\nn\n" not in pdf
+
+    def test_shrinking_stops_at_text_min_size(self, render):
+        pdf, size, _ = render(text_min_size=12)
+        assert size == 12
+        # What still overshoots is clipped to the barcode width
+        assert b"100 " in pdf and b" 120 " in pdf and b"W\nn\n" in pdf
+
+    def test_ellipsis_keeps_the_size(self, render):
+        pdf, size, glyphs = render(text_overflow="ellipsis")
+        _, _, whole = render(text_overflow="clip")
+        assert size == 14
+        assert glyphs < whole
+        assert b"W\nn\n" not in pdf
+
+    def test_clip(self, render):
+        pdf, size, _ = render(text_overflow="clip")
+        assert size == 14
+        assert re.search(rb"\n100 [\d.]+ 120 [\d.]+ re\nW\nn\nBT\n", pdf)
+
+    def test_text_that_fits_is_untouched(self, render):
+        pdf, size, _ = render(value="AB-1")
+        assert size == 14
+        assert b"W\nn\n" not in pdf
+
+
+class TestMarkup:
+    """Test **bold** and {name} spans in markup text."""
+
+    TEXT = {"type": "text", "x": 72, "y": 72, "font": "f", "size": 12}
+
+    def test_spans_set_font_and_color(self, font_doc, font_path):
+        styles = {"bold": {"font": "b"}, "red": {"color": (255, 0, 0, 255)}}
+        bold = {"b": {"path": font_path.replace("Regular", "Bold")}}
+        for extra in ({}, {"type": "textbox", "w": 300, "h": 100}):
+            element = dict(self.TEXT, text="Pick **4** of {red}SKU 1234{/red}", markup=True, **extra)
+            pdf = render_uncompressed(font_doc(element, fonts=bold, styles=styles))
+            assert b"/IBMPlexSans-Bold 12 Tf" in pdf
+            assert pdf.count(b"1 0 0 rg") == 1
+
+    def test_without_markup_tags_are_text(self, font_doc):
+        assert b"Bold" not in render_uncompressed(font_doc(dict(self.TEXT, text="**4**")))
+
+
+class TestSvgSources:
+    """Test SVG images given as source strings, and SVG detection."""
+
+    SVG = '<svg xmlns="http://www.w3.org/2000/svg" width="40" height="20"><rect width="40" height="20"/></svg>'
+    IMAGE = {"type": "image", "x": 72, "y": 72, "w": 40, "h": 20, "image_ref": "chart"}
+
+    def test_svg_string(self):
+        pdf = render_uncompressed(page_doc(self.IMAGE, images={"chart": {"svg": self.SVG}}))
+        assert b"/Subtype /Form" in pdf
+        assert b"/Subtype /Image" not in pdf
+
+    def test_bytes_with_bom_and_prolog(self):
+        source = '﻿\n<?xml version="1.0"?>\n<!-- chart -->\n<!DOCTYPE svg PUBLIC "-//W3C//DTD SVG 1.1//EN" "x.dtd">\n' + self.SVG
+        pdf = render_uncompressed(page_doc(self.IMAGE, images={"chart": {"bytes": source.encode("utf-8")}}))
+        assert b"/Subtype /Form" in pdf
+
+
+class TestColorStrings:
+    """Test hex, named, 3-tuple and palette colors."""
+
+    RECT = {"type": "rect", "x": 72, "y": 72, "w": 100, "h": 50, "stroke": 0}
+
+    def _render(self, color, **document):
+        return render_uncompressed(page_doc(dict(self.RECT, fill_color=color), **document))
+
+    def _fill(self, color):
+        return [line for line in self._render(color).split(b"\n") if line.endswith((b" rg", b" gs"))]
+
+    def test_hex(self):
+        assert b"1 0.5019608 0 rg" in self._render("#FF8000")
+
+    def test_hex_with_alpha(self):
+        assert self._fill("#FF800080") != self._fill("#FF8000")
+        assert self._fill("#FF800080") == self._fill((255, 128, 0, 128))
+
+    def test_named_and_three_tuple(self):
+        assert b"0 0 0.5019608 rg" in self._render("navy")
+        assert self._fill((0, 0, 128)) == self._fill("navy")
+
+    def test_tuple_unchanged(self):
+        assert self._fill((255, 128, 0, 255)) == self._fill("#ff8000")
+
+    def test_palette(self):
+        assert b"0 0 1 rg" in self._render("brand_primary", palette={"brand_primary": "#0000FF"})
+
+    def test_palette_in_backgrounds_and_defaults(self):
+        doc = page_doc(
+            {"type": "rect", "x": 72, "y": 72, "w": 100, "h": 50, "stroke": 0},
+            page={"background": "paper"},
+            palette={"paper": (250, 250, 240), "ink": "#00FF00"},
+            defaults={"rect": {"fill_color": "ink"}},
+        )
+        pdf = render_uncompressed(doc)
+        assert b"0 1 0 rg" in pdf
+        assert b"0.98039216 0.98039216 0.9411765 rg" in pdf
+
+
+class TestPolygon:
+    """Test polygon elements."""
+
+    POLYGON = {"type": "polygon", "points": [(100, 100), (200, 100), (150, 50)]}
+
+    def test_stroked_and_filled(self):
+        pdf = render_uncompressed(page_doc(dict(self.POLYGON, fill_color=(255, 0, 0, 255))))
+        assert pdf.count(b"100 692 m\n200 692 l\n150 742 l\nh\n") == 2
+        assert b"\nf\n" in pdf
+        assert b"\nS\n" in pdf
+
+    def test_open_stroke(self):
+        assert b"150 742 l\nS\n" in render_uncompressed(page_doc(dict(self.POLYGON, close=False)))
+
+    def test_evenodd(self):
+        pdf = render_uncompressed(page_doc(dict(self.POLYGON, fill_color="red", stroke=0, fill_rule="evenodd")))
+        assert b"\nf*\n" in pdf
+        assert b"\nS\n" not in pdf
+
+    def test_units(self):
+        polygon = {"type": "polygon", "points": [(0, 0), ("72pt", 0), (0, 10)]}
+        assert b"72 792 l" in render_uncompressed(page_doc(polygon, size=("612pt", "792pt"), units="mm"))
+
+
+class TestSharedSymbols:
+    """Test that repeated barcodes and QR codes are drawn once."""
+
+    QR = {"type": "qrcode", "x": 500, "y": 720, "size": 50, "value": "https://example.com/support"}
+
+    def test_repeating_qr_is_one_form(self):
+        doc = {"pages": [{"size": (612, 792)} for _ in range(5)], "repeating_elements": [self.QR]}
+        pdf = render_uncompressed(doc)
+        assert pdf.count(b"/Subtype /Form") == 1
+        assert pdf.count(b"q\n1 0 0 1 500 72 cm\n/S0 Do\nQ\n") == 5
+
+    def test_single_use_is_inline(self):
+        assert b"/Subtype /Form" not in render_uncompressed(page_doc(self.QR))
+
+    def test_different_styling_is_not_shared(self, font_path):
+        barcode = {"type": "barcode", "x": 72, "y": 72, "w": 200, "h": 60, "value": "RUPDF-1",
+                   "human_readable": True, "font": "mono", "font_size": 10}
+        doc = page_doc(
+            barcode, dict(barcode, y=200), dict(barcode, y=300, color=(255, 0, 0, 255)),
+            fonts={"mono": {"path": font_path}},
+        )
+        pdf = render_uncompressed(doc)
+        assert pdf.count(b"/Subtype /Form") == 1
+        assert pdf.count(b"/S0 Do") == 2
+        # The form carries the human-readable text with its font
+        assert pdf.count(b" Tj\n") + pdf.count(b"] TJ\n") == 2
+
+
+class TestRawContent:
+    """Test raw_content elements."""
+
+    def test_written_as_given(self):
+        raw = {"type": "raw_content", "operators": "1 0 0 rg 10 10 50 50 re f"}
+        pdf = render_uncompressed(page_doc(raw), allow_raw=True)
+        assert b"1 0 0 rg\n10 10 50 50 re\nf\n" in pdf
+
+
+class TestOrigin:
+    """Test origin="bottom_left" against the same layout in top-left coordinates."""
+
+    H = 792
+
+    def _pdf(self, elements, origin="top_left", font_path=None, png_path=None, **document):
+        fonts = {"f": {"path": font_path}} if font_path else {}
+        images = {"logo": {"path": png_path}} if png_path else {}
+        return render_uncompressed(page_doc(*elements, size=(612, self.H), fonts=fonts, images=images,
+                                            origin=origin, **document))
+
+    @staticmethod
+    def _streams(pdf):
+        # Object numbering can differ between renders; the streams can't
+        return sorted(re.findall(rb"stream\n(.*?)\nendstream", pdf, re.S))
+
+    def _same(self, top_left, bottom_left, **kwargs):
+        expected = self._streams(self._pdf([top_left], **kwargs))
+        assert self._streams(self._pdf([bottom_left], origin="bottom_left", **kwargs)) == expected
+
+    def test_points_flip(self, font_path):
+        H = self.H
+        text = {"type": "text", "x": 72, "y": 100, "text": "Hi", "font": "f", "size": 12}
+        self._same(text, dict(text, y=H - 100), font_path=font_path)
+        capline = dict(text, vertical_anchor="capline")
+        self._same(capline, dict(capline, y=H - 100), font_path=font_path)
+        line = {"type": "line", "x1": 72, "y1": 100, "x2": 300, "y2": 250}
+        self._same(line, dict(line, y1=H - 100, y2=H - 250))
+        polygon = {"type": "polygon", "points": [(72, 100), (150, 100), (110, 160)]}
+        self._same(polygon, dict(polygon, points=[(72, H - 100), (150, H - 100), (110, H - 160)]))
+        leader = {"type": "leader", "x1": 72, "x2": 300, "y": 100}
+        self._same(leader, dict(leader, y=H - 100))
+        ring = {"type": "progress_ring", "cx": 100, "cy": 100, "radius": 20, "thickness": 4, "value": 0.3}
+        self._same(ring, dict(ring, cy=H - 100))
+        signature = {"type": "signature_line", "x": 72, "y": 100, "w": 200, "label": "Name", "font": "f", "size": 9}
+        self._same(signature, dict(signature, y=H - 100), font_path=font_path)
+
+    def test_boxes_are_placed_by_bottom_left_corner(self, font_path):
+        H = self.H
+        rect = {"type": "rect", "x": 72, "y": 100, "w": 200, "h": 50, "fill_color": (0, 0, 255, 255)}
+        self._same(rect, dict(rect, y=H - 150))
+        barcode = {"type": "barcode", "x": 72, "y": 100, "w": 200, "h": 60, "value": "RUPDF"}
+        self._same(barcode, dict(barcode, y=H - 160))
+        qr = {"type": "qrcode", "x": 72, "y": 100, "size": 80, "value": "hello"}
+        self._same(qr, dict(qr, y=H - 180))
+        matrix = {"type": "datamatrix", "x": 72, "y": 100, "size": 40, "value": "hello"}
+        self._same(matrix, dict(matrix, y=H - 140))
+        check = {"type": "check", "x": 72, "y": 100, "size": 12, "state": "checked"}
+        self._same(check, dict(check, y=H - 112))
+        note = {"type": "note", "x": 72, "y": 100, "contents": "Check this"}
+        self._same(note, dict(note, y=H - 120))
+        placeholder = {"type": "image_placeholder", "x": 72, "y": 100, "w": 120, "h": 80, "label": "Photo", "font": "f", "size": 9}
+        self._same(placeholder, dict(placeholder, y=H - 180), font_path=font_path)
+
+    def test_images(self, png_path):
+        H = self.H
+        sized = {"type": "image", "x": 72, "y": 100, "w": 100, "h": 50, "image_ref": "logo"}
+        self._same(sized, dict(sized, y=H - 150), png_path=png_path)
+        # The height follows from the aspect ratio; the test image is square
+        by_width = {"type": "image", "x": 72, "y": 100, "w": 100, "image_ref": "logo"}
+        self._same(by_width, dict(by_width, y=H - 200), png_path=png_path)
+
+    def test_textbox_defaults_to_its_bottom_edge(self, font_path):
+        H = self.H
+        box = {"type": "textbox", "x": 72, "y": 100, "w": 200, "h": 50, "text": "Wrapped text", "font": "f", "size": 10}
+        self._same(box, dict(box, y=H - 150), font_path=font_path)
+        centered = dict(box, box_align_y="center", y=125)
+        self._same(centered, dict(centered, y=H - 125), font_path=font_path)
+
+    def test_table_is_placed_by_its_top(self, font_path):
+        table = {"type": "table", "x": 72, "y": 100, "column_widths": [100, 100],
+                 "rows": [["a", "b"], ["c", "d"]], "font": "f", "size": 10}
+        self._same(table, dict(table, y=self.H - 100), font_path=font_path)
+
+    def test_groups_and_fragments(self):
+        H = self.H
+        rect = {"type": "rect", "x": 72, "y": 100, "w": 20, "h": 10}
+        self._same({"type": "group", "elements": [rect]}, {"type": "group", "elements": [dict(rect, y=H - 110)]})
+        place = {"type": "fragment", "fragment_ref": "box", "x": 72, "y": 300, "scale": 2}
+        top_left = self._pdf([place], fragments={"box": [dict(rect, x=0, y=10)]})
+        # Fragment contents are measured up from the placement
+        bottom_left = self._pdf([dict(place, y=H - 300)], origin="bottom_left",
+                                fragments={"box": [dict(rect, x=0, y=-20)]})
+        assert self._streams(top_left) == self._streams(bottom_left)
+
+    def test_raw_content_is_unchanged(self):
+        raw = {"type": "raw_content", "operators": "10 10 50 50 re f"}
+        top_left = render_uncompressed(page_doc(raw), allow_raw=True)
+        bottom_left = render_uncompressed(page_doc(raw, origin="bottom_left"), allow_raw=True)
+        assert self._streams(top_left) == self._streams(bottom_left)
+
+    def test_page_relative_lengths(self):
+        # Margin keywords name the same edges; fractional positions are
+        # measured from the origin
+        margins = {"margins": {"top": 50, "bottom": 80}}
+        line = {"type": "line", "x1": 0, "x2": 612, "y1": "margin_top", "y2": {"rel": "content", "y": 0}}
+        top_left = render_uncompressed(page_doc(line, page=margins))
+        bottom_left = render_uncompressed(page_doc(line, page=margins, origin="bottom_left"))
+        assert b"0 742 m\n612 742 l\n" in top_left
+        assert b"0 742 m\n612 80 l\n" in bottom_left
+
+    def test_repeating_elements_follow_each_page_bottom(self):
+        footer = {"type": "rect", "x": 72, "y": 20, "w": 100, "h": 10}
+        doc = {
+            "origin": "bottom_left",
+            "pages": [{"size": (612, 792)}, {"size": (612, 500)}],
+            "repeating_elements": [footer],
+        }
+        assert render_uncompressed(doc).count(b"72 20 100 10 re\n") == 2
+
+    def test_open_action_point(self):
+        action = {"view": {"x": 0, "y": 700}}
+        assert b"/XYZ 0 700" in render_uncompressed(page_doc(open_action=action, origin="bottom_left"))
+        assert b"/XYZ 0 92" in render_uncompressed(page_doc(open_action=action))
+
+
+class TestImageProfiles:
+    """Test raster images carrying their own ICC profile."""
+
+    def _render(self, images):
+        elements = [
+            {"type": "image", "x": 100 * i, "y": 100, "w": 16, "h": 16, "image_ref": name}
+            for i, name in enumerate(images)
+        ]
+        return render_uncompressed(page_doc(*elements, images=images), return_stats=True)
+
+    def test_profile_embedded_by_default(self, p3_png_path):
+        pdf, stats = self._render({"photo": {"path": p3_png_path}})
+        assert b"/ColorSpace [/ICCBased" in pdf
+        assert [image["profile"] for image in stats["images"]] == ["embed"]
+
+    def test_identical_profiles_embedded_once(self, p3_png_path):
+        pdf, _ = self._render({"a": {"path": p3_png_path}, "b": {"path": p3_png_path}})
+        assert pdf.count(b"/ColorSpace [/ICCBased") == 2
+        assert pdf.count(b"/N 3") == 1
+
+    def test_srgb_converts_pixels(self, p3_png_path):
+        pdf, stats = self._render({"photo": {"path": p3_png_path, "profile": "srgb"}})
+        assert b"/ICCBased" not in pdf
+        assert b"/ColorSpace /DeviceRGB" in pdf
+        assert [image["profile"] for image in stats["images"]] == ["srgb"]
+
+    def test_untagged_images_unchanged(self, rgb16_png_path):
+        pdf, stats = self._render({"photo": {"path": rgb16_png_path, "profile": "srgb"}})
+        assert b"/ColorSpace /DeviceRGB" in pdf
+        assert [image["profile"] for image in stats["images"]] == [None]
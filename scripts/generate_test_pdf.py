@@ -17,6 +17,7 @@ Examples:
 """
 
 import argparse
+import math
 import sys
 from pathlib import Path
 
@@ -355,6 +356,48 @@ def build_document(fonts: dict, images: dict) -> dict:
         "size": 9,
         "color": (0, 0, 0, 255),
     })
+
+    # Dash presets, beside the solid lines
+    for row, (dash, stroke) in enumerate([("dashed", 1), ("dotted", 1.5)]):
+        line_y = y - 30 + row * 15
+        elements_page1.append({
+            "type": "line",
+            "x1": MARGIN + 280,
+            "y1": line_y,
+            "x2": MARGIN + 430,
+            "y2": line_y,
+            "stroke": stroke,
+            "dash": dash,
+        })
+        elements_page1.append({
+            "type": "text",
+            "x": MARGIN + 440,
+            "y": line_y + 3,
+            "text": f'dash: "{dash}"',
+            "font": default_font,
+            "size": 9,
+            "color": (0, 0, 0, 255),
+        })
+
+    # A thick open polygon with round caps and joins, as a connector
+    elements_page1.append({
+        "type": "polygon",
+        "points": [(MARGIN + 280 + i * 50, y + (15 if i % 2 else -3)) for i in range(4)],
+        "close": False,
+        "stroke": 4,
+        "stroke_color": (0, 140, 140, 255),
+        "cap": "round",
+        "join": "round",
+    })
+    elements_page1.append({
+        "type": "text",
+        "x": MARGIN + 440,
+        "y": y + 9,
+        "text": 'cap/join: "round"',
+        "font": default_font,
+        "size": 9,
+        "color": (0, 0, 0, 255),
+    })
     y += 45
 
     # --- BARCODE SECTION ---
@@ -621,6 +664,17 @@ def build_document(fonts: dict, images: dict) -> dict:
                 "h": 150,
                 "image_ref": "raster",
             })
+
+            # Faded toward the bottom edge
+            elements_page2.append({
+                "type": "image",
+                "x": MARGIN + 360,
+                "y": y2,
+                "w": 100,
+                "h": 150,
+                "image_ref": "raster",
+                "fade": {"edge": "bottom", "start": 0.4},
+            })
             y2 += 170
     else:
         elements_page2.append({
@@ -634,6 +688,437 @@ def build_document(fonts: dict, images: dict) -> dict:
         })
         y2 += 30
 
+    # --- INLINE SVG SECTION ---
+    # SVG source given as a string, as a chart service would return it
+    images = {**images, "inline_chart": {"svg": (
+        '<svg xmlns="http://www.w3.org/2000/svg" width="120" height="60">'
+        '<rect x="5" y="30" width="20" height="30" fill="#4a7ab5"/>'
+        '<rect x="35" y="15" width="20" height="45" fill="#4a7ab5"/>'
+        '<rect x="65" y="5" width="20" height="55" fill="#4a7ab5"/>'
+        '<rect x="95" y="40" width="20" height="20" fill="#4a7ab5"/>'
+        '</svg>'
+    )}}
+    elements_page2.append({
+        "type": "text",
+        "x": MARGIN,
+        "y": y2,
+        "text": "Inline SVG source:",
+        "font": default_font,
+        "size": 12,
+        "color": (0, 0, 0, 255),
+    })
+    y2 += 20
+    elements_page2.append({
+        "type": "image",
+        "x": MARGIN,
+        "y": y2,
+        "w": 120,
+        "h": 60,
+        "image_ref": "inline_chart",
+    })
+    y2 += 80
+
+    # --- COLOR STRINGS SECTION ---
+    elements_page2.append({
+        "type": "text",
+        "x": MARGIN,
+        "y": y2,
+        "text": "Palette, hex, CSS name and 3-tuple colors:",
+        "font": default_font,
+        "size": 12,
+        "color": "brand_primary",
+    })
+    y2 += 20
+    for i, color in enumerate(["brand_primary", "brand_accent", "#E07B3980", "teal", (120, 120, 120)]):
+        elements_page2.append({
+            "type": "rect",
+            "x": MARGIN + i * 60,
+            "y": y2,
+            "w": 50,
+            "h": 30,
+            "stroke": 0,
+            "fill_color": color,
+        })
+    y2 += 50
+
+    # --- CHECK SECTION ---
+    elements_page2.append({
+        "type": "text",
+        "x": MARGIN,
+        "y": y2,
+        "text": "Check Elements",
+        "font": default_font,
+        "size": 16,
+        "color": (0, 0, 128, 255),
+    })
+    y2 += 20
+
+    for i, state in enumerate(["unchecked", "checked", "crossed"]):
+        elements_page2.append({
+            "type": "check",
+            "x": MARGIN + i * 100,
+            "y": y2,
+            "size": 12,
+            "state": state,
+            "mark_color": (0, 100, 0, 255) if state == "checked" else (180, 0, 0, 255),
+        })
+        elements_page2.append({
+            "type": "text",
+            "x": MARGIN + i * 100 + 18,
+            "y": y2 + 10,
+            "text": state,
+            "font": default_font,
+            "size": 10,
+            "color": (0, 0, 0, 255),
+        })
+    elements_page2.append({
+        "type": "note",
+        "x": MARGIN + 320,
+        "y": y2 - 4,
+        "contents": "Notes are annotations: open this one to read it.",
+        "title": "rupdf",
+    })
+    y2 += 30
+
+    # --- SIGNATURE LINE SECTION ---
+    elements_page2.append({
+        "type": "text",
+        "x": MARGIN,
+        "y": y2,
+        "text": "Signature Lines",
+        "font": default_font,
+        "size": 16,
+        "color": (0, 0, 128, 255),
+    })
+    y2 += 40
+
+    for i, (name, mark) in enumerate([("Jane Doe", True), ("John Roe", False)]):
+        elements_page2.append({
+            "type": "signature_line",
+            "x": MARGIN + i * 250,
+            "y": y2,
+            "w": 220,
+            "font": default_font,
+            "label": name,
+            "sublabels": ["Title", "Date"],
+            "mark": mark,
+        })
+    y2 += 45
+
+    # --- IMAGE PLACEHOLDER SECTION ---
+    elements_page2.append({
+        "type": "text",
+        "x": MARGIN,
+        "y": y2,
+        "text": "Image Placeholders",
+        "font": default_font,
+        "size": 16,
+        "color": (0, 0, 128, 255),
+    })
+    y2 += 15
+
+    elements_page2.append({
+        "type": "image_placeholder",
+        "x": MARGIN,
+        "y": y2,
+        "h": 60,
+        "aspect": 16 / 9,
+        "font": default_font,
+        "label": "Hero photo",
+        "note": "16:9",
+    })
+    elements_page2.append({
+        "type": "image_placeholder",
+        "x": MARGIN + 140,
+        "y": y2,
+        "w": 60,
+        "h": 60,
+        "font": default_font,
+        "label": "Logo",
+    })
+    y2 += 75
+
+    # === PAGE 2 (CONTINUED) ===
+    # The remaining sections don't fit; they continue on a page of their own
+    elements_page2_first = elements_page2
+    elements_page2 = [{
+        "type": "text",
+        "x": W / 2,
+        "y": MARGIN,
+        "text": "Page 2 (continued)",
+        "font": default_font,
+        "size": 24,
+        "color": (0, 0, 0, 255),
+        "align": "center",
+    }]
+    y2 = MARGIN + 50
+
+    # --- LEADER SECTION ---
+    elements_page2.append({
+        "type": "text",
+        "x": MARGIN,
+        "y": y2,
+        "text": "Leaders",
+        "font": default_font,
+        "size": 16,
+        "color": (0, 0, 128, 255),
+    })
+    y2 += 25
+
+    for label, page, style in [("Introduction", "1", "dots"), ("Getting started", "4", "dots"),
+                               ("Reference", "12", "dashes"), ("Index", "40", "solid")]:
+        label_w = len(label) * 5.5
+        elements_page2.append({
+            "type": "text",
+            "x": MARGIN,
+            "y": y2,
+            "text": label,
+            "font": default_font,
+            "size": 11,
+        })
+        elements_page2.append({
+            "type": "leader",
+            "x1": MARGIN + label_w + 4,
+            "x2": MARGIN + 280,
+            "y": y2,
+            "style": style,
+            "font": default_font,
+            "size": 11,
+        })
+        elements_page2.append({
+            "type": "text",
+            "x": MARGIN + 300,
+            "y": y2,
+            "text": page,
+            "font": default_font,
+            "size": 11,
+            "align": "right",
+        })
+        y2 += 16
+    y2 += 10
+
+    # --- PROGRESS RING SECTION ---
+    elements_page2.append({
+        "type": "text",
+        "x": MARGIN,
+        "y": y2,
+        "text": "Progress Rings (0, 0.3, 0.72, 1)",
+        "font": default_font,
+        "size": 16,
+        "color": (0, 0, 128, 255),
+    })
+    y2 += 20
+
+    for i, value in enumerate([0.0, 0.3, 0.72, 1.0]):
+        elements_page2.append({
+            "type": "progress_ring",
+            "cx": MARGIN + 30 + i * 80,
+            "cy": y2 + 30,
+            "radius": 24,
+            "thickness": 8,
+            "value": value,
+            "track_color": (224, 224, 224, 255),
+            "color": (0, 120, 200, 255),
+        })
+    y2 += 75
+
+    # --- HATCH FILL SECTION ---
+    elements_page2.append({
+        "type": "text",
+        "x": MARGIN,
+        "y": y2,
+        "text": "Hatch Fills",
+        "font": default_font,
+        "size": 16,
+        "color": (0, 0, 128, 255),
+    })
+    y2 += 10
+
+    for i, style in enumerate(["diagonal", "cross", "horizontal", "vertical", "dots"]):
+        elements_page2.append({
+            "type": "rect",
+            "x": MARGIN + i * 90,
+            "y": y2,
+            "w": 80,
+            "h": 30,
+            "stroke": 1,
+            "fill_color": (255, 250, 230, 255),
+            "fill_pattern": {"style": style, "spacing": 5, "line_width": 0.75 if style != "dots" else 1.5,
+                             "color": (120, 60, 0, 255)},
+        })
+    y2 += 45
+
+    # --- MARKUP SECTION ---
+    elements_page2.append({
+        "type": "text",
+        "x": MARGIN,
+        "y": y2,
+        "text": "Inline Markup",
+        "font": default_font,
+        "size": 16,
+        "color": (0, 0, 128, 255),
+    })
+    y2 += 20
+
+    elements_page2.append({
+        "type": "textbox",
+        "x": MARGIN,
+        "y": y2,
+        "w": W - 2 * MARGIN,
+        "h": 30,
+        "text": "Pick **4 cases** of {red}SKU 1234{/red} from bay 7, then {warn}**check** the seal{/warn}.",
+        "font": default_font,
+        "size": 11,
+        "text_align_y": "top",
+        "markup": True,
+    })
+    y2 += 35
+
+    elements_page2.append({
+        "type": "text",
+        "x": MARGIN,
+        "y": y2,
+        "text": "Set by font family: sans, weight bold",
+        "family": "sans",
+        "weight": "bold",
+        "size": 11,
+    })
+    y2 += 20
+
+    # --- MAX WIDTH SECTION ---
+    elements_page2.append({
+        "type": "text",
+        "x": MARGIN,
+        "y": y2,
+        "text": "Max Width (clip, ellipsis, shrink)",
+        "font": default_font,
+        "size": 16,
+        "color": (0, 0, 128, 255),
+    })
+    y2 += 20
+
+    column = (W - 2 * MARGIN) / 3
+    for i, overflow in enumerate(["clip", "ellipsis", "shrink"]):
+        left = MARGIN + i * column
+        elements_page2.append({
+            "type": "rect", "x": left + 10, "y": y2 - 12, "w": column - 20, "h": 16,
+            "stroke": 0.5, "stroke_color": (180, 180, 180, 255),
+        })
+        elements_page2.append({
+            "type": "text",
+            "x": left + column / 2,
+            "y": y2,
+            "text": "Bartholomew Higginbottom III",
+            "font": default_font,
+            "size": 12,
+            "align": "center",
+            "max_width": column - 20,
+            "overflow": overflow,
+            "min_size": 8,
+        })
+    y2 += 25
+
+    # --- GROUP SECTION ---
+    elements_page2.append({
+        "type": "text",
+        "x": MARGIN,
+        "y": y2,
+        "text": "Transparency Group (overlap stays uniform)",
+        "font": default_font,
+        "size": 16,
+        "color": (0, 0, 128, 255),
+    })
+    y2 += 20
+
+    elements_page2.append({
+        "type": "group",
+        "transparency_group": True,
+        "opacity": 0.5,
+        "elements": [
+            {"type": "rect", "x": MARGIN + i * 30, "y": y2, "w": 60, "h": 40,
+             "stroke": 0, "fill_color": (0, 90, 200, 255)}
+            for i in range(3)
+        ],
+    })
+    y2 += 60
+
+    # --- FRAGMENT SECTION ---
+    elements_page2.append({
+        "type": "text",
+        "x": MARGIN,
+        "y": y2,
+        "text": "Fragments (one stamp, three placements)",
+        "font": default_font,
+        "size": 16,
+        "color": (0, 0, 128, 255),
+    })
+    y2 += 15
+
+    for i, scale in enumerate([1.0, 0.75, 0.5]):
+        elements_page2.append({
+            "type": "fragment",
+            "fragment_ref": "stamp",
+            "x": MARGIN + i * 130,
+            "y": y2,
+            "scale": scale,
+        })
+    y2 += 50
+
+    # --- POLYGON SECTION ---
+    elements_page2.append({
+        "type": "text",
+        "x": MARGIN,
+        "y": y2,
+        "text": "Polygons (triangle, chevron, open, even-odd star)",
+        "font": default_font,
+        "size": 16,
+        "color": (0, 0, 128, 255),
+    })
+    y2 += 10
+
+    star = [(30 + 25 * math.sin(k * 4 * math.pi / 5), 25 - 25 * math.cos(k * 4 * math.pi / 5)) for k in range(5)]
+    shapes = [
+        {"points": [(0, 50), (50, 50), (25, 0)], "fill_color": (255, 200, 0, 255)},
+        {"points": [(0, 0), (35, 0), (55, 25), (35, 50), (0, 50), (20, 25)], "fill_color": (0, 120, 200, 255), "stroke": 0},
+        {"points": [(0, 50), (15, 0), (30, 50), (45, 0), (60, 50)], "close": False, "stroke": 2},
+        {"points": star, "fill_color": (200, 40, 40, 255), "fill_rule": "evenodd", "stroke": 0.5},
+    ]
+    for i, shape in enumerate(shapes):
+        dx = MARGIN + i * 90
+        elements_page2.append({
+            "type": "polygon",
+            **shape,
+            "points": [(dx + x, y2 + y) for x, y in shape["points"]],
+        })
+    y2 += 60
+
+    # --- RAW CONTENT SECTION ---
+    elements_page2.append({
+        "type": "text",
+        "x": MARGIN,
+        "y": y2,
+        "text": "Raw content (Bezier curves, needs allow_raw)",
+        "font": default_font,
+        "size": 16,
+        "color": (0, 0, 128, 255),
+    })
+    y2 += 10
+
+    # Raw operators use PDF space: origin bottom-left, y up
+    base = H - (y2 + 40)
+    elements_page2.append({
+        "type": "raw_content",
+        "operators": (
+            f"/Half gs 0.2 0.5 0.8 rg {MARGIN} {base} m "
+            f"{MARGIN + 60} {base + 80} {MARGIN + 120} {base - 40} {MARGIN + 180} {base} c "
+            f"{MARGIN + 180} {base - 20} l {MARGIN} {base - 20} l h f "
+            f"/Opaque gs 1.5 w 0.8 0.2 0.2 RG {MARGIN + 220} {base - 20} m "
+            f"{MARGIN + 260} {base + 60} {MARGIN + 300} {base - 60} {MARGIN + 340} {base + 20} c S"
+        ),
+        "raw_resources": {"Half": {"alpha": 127}, "Opaque": {"alpha": 255}},
+    })
+    y2 += 60
+
     # Footer on page 2
     elements_page2.append({
         "type": "text",
@@ -646,23 +1131,88 @@ def build_document(fonts: dict, images: dict) -> dict:
         "align": "center",
     })
 
+    # === PAGE 3 ===
+    # A table long enough to continue onto an inserted page
+    elements_page3 = [{
+        "type": "text",
+        "x": W / 2,
+        "y": MARGIN,
+        "text": "Page 4 - Table",
+        "font": default_font,
+        "size": 24,
+        "color": (0, 0, 0, 255),
+        "align": "center",
+    }, {
+        "type": "table",
+        "x": MARGIN,
+        "y": MARGIN + 30,
+        "column_widths": [60, 260, 80, 68],
+        "rows": [["#", "Description", "Qty", "Amount"]] + [
+            [str(i + 1), f"Line item {i + 1}" + (" with a longer description that wraps" if i % 7 == 3 else ""),
+             str(i % 5 + 1), f"${(i % 5 + 1) * 3.25:.2f}"]
+            for i in range(45)
+        ],
+        "header_rows": 1,
+        "font": default_font,
+        "size": 10,
+        "header_fill": (220, 225, 235, 255),
+        "border_color": (160, 160, 160, 255),
+        "continued_y": MARGIN,
+        "continued_caption": "Table (continued)",
+    }]
+
     return {
         "metadata": {
             "title": "rupdf All Elements Test",
             "author": "rupdf test suite",
             "subject": "Comprehensive test of all PDF element types",
         },
+        # Every key below must be one rupdf reads
+        "strict_keys": True,
+        "palette": {"brand_primary": "#1E6FD9", "brand_accent": (224, 123, 57)},
+        "font_families": {
+            "sans": {"regular": default_font, "bold": "sans-bold" if "sans-bold" in fonts else default_font},
+        },
+        "styles": {
+            "bold": {"font": "sans-bold" if "sans-bold" in fonts else default_font},
+            "red": {"color": (200, 0, 0, 255)},
+            "warn": {"color": (200, 120, 0, 255)},
+        },
+        "fragments": {
+            "stamp": [
+                {"type": "rect", "x": 0, "y": 0, "w": 110, "h": 40, "stroke": 2,
+                 "stroke_color": (180, 0, 0, 255), "corner_radius": 6},
+                {"type": "text", "x": 55, "y": 26, "text": "APPROVED", "font": default_font,
+                 "size": 14, "align": "center", "color": (180, 0, 0, 255)},
+            ],
+        },
+        # The same QR code in every page's corner is written once and
+        # placed by reference
+        "repeating_elements": [
+            {"type": "qrcode", "x": W - MARGIN - 30, "y": H - 40, "size": 30,
+             "value": "https://github.com/stringking/rupdf"},
+        ],
         "pages": [
             {
                 "size": (W, H),
                 "background": (255, 255, 255, 255),
                 "elements": elements_page1,
             },
+            {
+                "size": (W, H),
+                "background": (255, 255, 255, 255),
+                "elements": elements_page2_first,
+            },
             {
                 "size": (W, H),
                 "background": (255, 255, 255, 255),
                 "elements": elements_page2,
             },
+            {
+                "size": (W, H),
+                "background": (255, 255, 255, 255),
+                "elements": elements_page3,
+            },
         ],
         "resources": {
             "fonts": fonts,
@@ -697,6 +1247,11 @@ Examples:
         metavar="PATH",
         help="Path to a raster image (PNG/JPEG/WebP) to include",
     )
+    parser.add_argument(
+        "--stats",
+        action="store_true",
+        help="Print the size of each embedded font and image",
+    )
 
     args = parser.parse_args()
 
@@ -726,7 +1281,20 @@ Examples:
     doc = build_document(fonts, images)
 
     print(f"Rendering PDF with {len(doc['pages'])} pages...")
-    pdf_bytes = rupdf.render_pdf(doc, compress=True)
+    try:
+        pdf_bytes, stats = rupdf.render_pdf(doc, compress=True, return_stats=True, collect_errors=True, allow_raw=True)
+    except rupdf.RupdfError as e:
+        # Report every bad element at once rather than one per run
+        for error in getattr(e, "errors", [{"message": str(e)}]):
+            print(f"Error: {error['message']}")
+        sys.exit(1)
+    if args.stats:
+        print(f"  {stats['pages']} pages, {stats['content_bytes']:,} bytes of content streams")
+        for name, font in stats["fonts"].items():
+            print(f"  font {name}: {font['glyphs']} glyphs, {font['bytes']:,} bytes")
+        for image in stats["images"]:
+            dpi = f", {image['dpi']:.0f} DPI" if image["dpi"] else ""
+            print(f"  image {image['name']}: {image['encoding']}{dpi}, {image['bytes']:,} bytes")
 
     print(f"Writing {len(pdf_bytes):,} bytes to {args.output}")
     with open(args.output, "wb") as f:
@@ -0,0 +1,97 @@
+"""Generate assets/TestCJK.ttf, a tiny CJK-only outline font for tests.
+
+The font maps 水 and 字 to square TrueType outlines in the ideographic
+box and has no Latin glyphs and no OS/2 table, so its cap height has to
+be measured from an ideograph. Rerun after changing the glyph set:
+
+    python scripts/make_test_cjk_font.py
+"""
+
+import struct
+from pathlib import Path
+
+UPEM = 1000
+ADVANCE = 1000
+ASCENDER = 880
+DESCENDER = -120
+NAME = "TestCJK"
+# (codepoint, (x_min, y_min, x_max, y_max)) per glyph; glyph 0 is .notdef
+GLYPHS = [(0x6C34, (60, -60, 940, 840)), (0x5B57, (80, -50, 920, 830))]
+
+
+def glyph(box):
+    """A simple glyph: one clockwise rectangular contour of on-curve points."""
+    x_min, y_min, x_max, y_max = box
+    points = [(x_min, y_min), (x_min, y_max), (x_max, y_max), (x_max, y_min)]
+    xs = [b - a for a, b in zip([0] + [x for x, _ in points], [x for x, _ in points])]
+    ys = [b - a for a, b in zip([0] + [y for _, y in points], [y for _, y in points])]
+    data = struct.pack(">hhhhhHH", 1, x_min, y_min, x_max, y_max, len(points) - 1, 0)
+    data += bytes([0x01] * len(points))
+    data += b"".join(struct.pack(">h", d) for d in xs + ys)
+    return data.ljust((len(data) + 3) // 4 * 4, b"\0")
+
+
+def tables():
+    num_glyphs = len(GLYPHS) + 1
+    glyphs = [b""] + [glyph(box) for _, box in GLYPHS]
+    loca, pos = b"", 0
+    for data in glyphs:
+        loca += struct.pack(">I", pos)
+        pos += len(data)
+    loca += struct.pack(">I", pos)
+    glyf = b"".join(glyphs)
+
+    x_min = min(box[0] for _, box in GLYPHS)
+    y_min = min(box[1] for _, box in GLYPHS)
+    x_max = max(box[2] for _, box in GLYPHS)
+    y_max = max(box[3] for _, box in GLYPHS)
+    head = struct.pack(
+        ">HHIIIHHqqhhhhHHhhh",
+        1, 0, 0x10000, 0, 0x5F0F3CF5, 0, UPEM, 0, 0,
+        x_min, y_min, x_max, y_max, 0, 8, 2, 1, 0,
+    )
+    hhea = struct.pack(
+        ">HHhhhHhhhhhhhhhhhH",
+        1, 0, ASCENDER, DESCENDER, 0, ADVANCE, 0, 0, x_max, 1, 0, 0, 0, 0, 0, 0, 0, num_glyphs,
+    )
+    maxp = struct.pack(">IHHHHHHHHHHHHHH", 0x10000, num_glyphs, 4, 1, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0)
+    hmtx = b"".join(struct.pack(">Hh", ADVANCE, 0) for _ in range(num_glyphs))
+
+    groups = sorted((cp, gid + 1) for gid, (cp, _) in enumerate(GLYPHS))
+    sub = struct.pack(">HHIII", 12, 0, 16 + 12 * len(groups), 0, len(groups))
+    sub += b"".join(struct.pack(">III", cp, cp, gid) for cp, gid in groups)
+    cmap = struct.pack(">HHHHI", 0, 1, 3, 10, 12) + sub
+
+    strings = [(1, NAME), (6, NAME)]
+    records, storage = b"", b""
+    for name_id, text in strings:
+        encoded = text.encode("utf-16-be")
+        records += struct.pack(">HHHHHH", 3, 1, 0x409, name_id, len(encoded), len(storage))
+        storage += encoded
+    name = struct.pack(">HHH", 0, len(strings), 6 + 12 * len(strings)) + records + storage
+
+    post = struct.pack(">IIhhIIIII", 0x30000, 0, -100, 50, 0, 0, 0, 0, 0)
+
+    return {
+        b"cmap": cmap, b"glyf": glyf, b"head": head, b"hhea": hhea, b"hmtx": hmtx,
+        b"loca": loca, b"maxp": maxp, b"name": name, b"post": post,
+    }
+
+
+def sfnt(tables):
+    tags = sorted(tables)
+    out = struct.pack(">IHHHH", 0x00010000, len(tags), 128, 3, len(tags) * 16 - 128)
+    offset = 12 + 16 * len(tags)
+    body = b""
+    for tag in tags:
+        data = tables[tag]
+        checksum = sum(struct.unpack(">%dI" % ((len(data) + 3) // 4), data.ljust((len(data) + 3) // 4 * 4, b"\0"))) & 0xFFFFFFFF
+        out += struct.pack(">4sIII", tag, checksum, offset + len(body), len(data))
+        body += data.ljust((len(data) + 3) // 4 * 4, b"\0")
+    return out + body
+
+
+if __name__ == "__main__":
+    path = Path(__file__).resolve().parent.parent / "assets" / "TestCJK.ttf"
+    path.write_bytes(sfnt(tables()))
+    print(f"wrote {path}")
@@ -0,0 +1,84 @@
+"""Generate assets/TestEmoji-sbix.ttf, a tiny color bitmap font for tests.
+
+The font has no outlines: U+1F600 and U+2764 map to glyphs that exist only
+as 64 ppem PNG images in an `sbix` table, like Apple Color Emoji. Rerun
+after changing the glyph set:
+
+    python scripts/make_test_emoji_font.py
+"""
+
+import struct
+import zlib
+from pathlib import Path
+
+UPEM = 1000
+PPEM = 64
+ADVANCE = 1100
+# (codepoint, RGBA fill) per glyph; glyph 0 is .notdef
+GLYPHS = [(0x1F600, (255, 204, 0, 255)), (0x2764, (220, 20, 60, 255))]
+
+
+def png(size, rgba):
+    """A size x size PNG: a filled disc on a transparent background."""
+    rows = b""
+    r = size / 2
+    for y in range(size):
+        row = b"\0"
+        for x in range(size):
+            inside = (x + 0.5 - r) ** 2 + (y + 0.5 - r) ** 2 <= r * r
+            row += bytes(rgba) if inside else b"\0\0\0\0"
+        rows += row
+
+    def chunk(tag, data):
+        body = tag + data
+        return struct.pack(">I", len(data)) + body + struct.pack(">I", zlib.crc32(body))
+
+    ihdr = struct.pack(">IIBBBBB", size, size, 8, 6, 0, 0, 0)
+    return b"\x89PNG\r\n\x1a\n" + chunk(b"IHDR", ihdr) + chunk(b"IDAT", zlib.compress(rows)) + chunk(b"IEND", b"")
+
+
+def tables():
+    num_glyphs = len(GLYPHS) + 1
+    head = struct.pack(
+        ">HHIIIHHqqhhhhHHhhh",
+        1, 0, 0x10000, 0, 0x5F0F3CF5, 0, UPEM, 0, 0,
+        0, -200, ADVANCE, 800, 0, 8, 2, 0, 0,
+    )
+    hhea = struct.pack(">HHhhhHhhhhhhhhhhhH", 1, 0, 800, -200, 0, ADVANCE, 0, 0, ADVANCE, 1, 0, 0, 0, 0, 0, 0, 0, num_glyphs)
+    maxp = struct.pack(">IH", 0x5000, num_glyphs)
+    hmtx = b"".join(struct.pack(">Hh", ADVANCE, 0) for _ in range(num_glyphs))
+
+    groups = sorted((cp, gid + 1) for gid, (cp, _) in enumerate(GLYPHS))
+    sub = struct.pack(">HHIII", 12, 0, 16 + 12 * len(groups), 0, len(groups))
+    sub += b"".join(struct.pack(">III", cp, cp, gid) for cp, gid in groups)
+    cmap = struct.pack(">HHHHI", 0, 1, 3, 10, 12) + sub
+
+    images = [b""] + [b"\0\0\0\0png " + png(PPEM, rgba) for _, rgba in GLYPHS]
+    offsets, pos = [], 4 + 4 * (num_glyphs + 1)
+    for data in images:
+        offsets.append(pos)
+        pos += len(data)
+    offsets.append(pos)
+    strike = struct.pack(">HH", PPEM, 72) + b"".join(struct.pack(">I", o) for o in offsets) + b"".join(images)
+    sbix = struct.pack(">HHII", 1, 1, 1, 12) + strike
+
+    return {b"cmap": cmap, b"head": head, b"hhea": hhea, b"hmtx": hmtx, b"maxp": maxp, b"sbix": sbix}
+
+
+def sfnt(tables):
+    tags = sorted(tables)
+    out = struct.pack(">IHHHH", 0x00010000, len(tags), 64, 2, len(tags) * 16 - 64)
+    offset = 12 + 16 * len(tags)
+    body = b""
+    for tag in tags:
+        data = tables[tag]
+        checksum = sum(struct.unpack(">%dI" % ((len(data) + 3) // 4), data.ljust((len(data) + 3) // 4 * 4, b"\0"))) & 0xFFFFFFFF
+        out += struct.pack(">4sIII", tag, checksum, offset + len(body), len(data))
+        body += data.ljust((len(data) + 3) // 4 * 4, b"\0")
+    return out + body
+
+
+if __name__ == "__main__":
+    path = Path(__file__).resolve().parent.parent / "assets" / "TestEmoji-sbix.ttf"
+    path.write_bytes(sfnt(tables()))
+    print(f"wrote {path}")
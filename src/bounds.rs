@@ -0,0 +1,439 @@
+//! Out-of-bounds element detection.
+//!
+//! Computes each element's bounding box in page coordinates (top-left
+//! origin, points) and compares it against the page MediaBox inset by an
+//! optional margin. Viewers silently clip anything outside the page, so
+//! this is the only place such layout bugs surface before printing.
+
+use crate::elements::{fit, leader, overflow, table};
+use crate::error::{Result, RupdfError};
+use crate::pdf::PdfGenerator;
+use crate::resources::{LoadedFont, LoadedResources};
+use crate::runs;
+use crate::types::*;
+use crate::units::Length;
+use crate::warnings::{Warning, Warnings};
+use pyo3::prelude::*;
+use pyo3::Borrowed;
+
+/// How to report elements that extend past the page
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BoundsCheck {
+    #[default]
+    Off,
+    Warn,
+    Error,
+}
+
+impl<'py> FromPyObject<'_, 'py> for BoundsCheck {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        let s: String = ob.extract()?;
+        match s.as_str() {
+            "off" => Ok(BoundsCheck::Off),
+            "warn" => Ok(BoundsCheck::Warn),
+            "error" => Ok(BoundsCheck::Error),
+            _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Invalid bounds_check: '{}'. Must be 'off', 'warn', or 'error'",
+                s
+            ))),
+        }
+    }
+}
+
+/// Axis-aligned box in page coordinates (y grows downward)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BBox {
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+}
+
+impl BBox {
+    fn new(x: f32, y: f32, w: f32, h: f32) -> Self {
+        Self {
+            left: x.min(x + w),
+            top: y.min(y + h),
+            right: x.max(x + w),
+            bottom: y.max(y + h),
+        }
+    }
+
+    fn outset(self, d: f32) -> Self {
+        Self {
+            left: self.left - d,
+            top: self.top - d,
+            right: self.right + d,
+            bottom: self.bottom + d,
+        }
+    }
+
+    /// This box, given in a fragment's local coordinates, on the page
+    /// where `placement` puts the fragment's origin.
+    fn placed(self, placement: &FragmentElement) -> Self {
+        let (x, y, s) = (placement.x, placement.y, placement.scale);
+        Self {
+            left: x + self.left * s,
+            top: y + self.top * s,
+            right: x + self.right * s,
+            bottom: y + self.bottom * s,
+        }
+    }
+
+    /// The box around this one sheared about `origin`: the extent of the
+    /// parallelogram its corners become.
+    fn skewed(self, skew: Skew, origin: (f32, f32)) -> Self {
+        if skew.is_identity() {
+            return self;
+        }
+        self.mapped(|corner| skew.apply(corner, origin))
+    }
+
+    /// The box around this one turned `degrees` clockwise about `pivot`
+    fn turned(self, degrees: f32, pivot: (f32, f32)) -> Self {
+        if degrees == 0.0 {
+            return self;
+        }
+        self.mapped(|corner| rotate_point(degrees, corner, pivot))
+    }
+
+    /// The box around this one's corners moved by `f`, which keeps
+    /// straight lines straight
+    fn mapped(self, f: impl Fn((f32, f32)) -> (f32, f32)) -> Self {
+        let corners = [
+            (self.left, self.top),
+            (self.right, self.top),
+            (self.right, self.bottom),
+            (self.left, self.bottom),
+        ]
+        .map(f);
+        Self::around(corners)
+    }
+
+    /// The box around four corners
+    fn around(corners: [(f32, f32); 4]) -> Self {
+        let (xs, ys) = (corners.map(|(x, _)| x), corners.map(|(_, y)| y));
+        Self {
+            left: xs.into_iter().fold(f32::INFINITY, f32::min),
+            top: ys.into_iter().fold(f32::INFINITY, f32::min),
+            right: xs.into_iter().fold(f32::NEG_INFINITY, f32::max),
+            bottom: ys.into_iter().fold(f32::NEG_INFINITY, f32::max),
+        }
+    }
+}
+
+/// Overhang below which an element is considered inside the page, to
+/// absorb float rounding from unit conversion and font metrics.
+const TOLERANCE: f32 = 0.01;
+
+type TextChain<'a> = (Vec<&'a LoadedFont>, Vec<&'a str>, Vec<Option<usize>>, runs::EmojiFallback<'a>);
+
+/// The element's font chain as the writer builds it: fonts set by markup
+/// styles go last, with their chain indices (see `runs::resolve_styled`).
+pub(crate) fn font_chain<'a>(resources: &'a LoadedResources, t: &'a TextElement) -> Result<TextChain<'a>> {
+    let mut fonts = vec![resources.get_font(&t.font)?];
+    let mut names = vec![t.font.as_str()];
+    for alias in t.font_fallback.iter().chain(&t.emoji.font) {
+        fonts.push(resources.get_font(alias)?);
+        names.push(alias.as_str());
+    }
+    let emoji = runs::EmojiFallback {
+        font: t.emoji.font.as_ref().map(|_| fonts.len() - 1),
+        replacement: t.emoji.replacement.as_deref(),
+    };
+    let mut style_fonts = Vec::with_capacity(t.styles.len());
+    for style in &t.styles {
+        let Some(alias) = &style.font else {
+            style_fonts.push(None);
+            continue;
+        };
+        let index = match names.iter().position(|n| n == alias) {
+            Some(index) => index,
+            None => {
+                fonts.push(resources.get_font(alias)?);
+                names.push(alias.as_str());
+                names.len() - 1
+            }
+        };
+        style_fonts.push(Some(index));
+    }
+    Ok((fonts, names, style_fonts, emoji))
+}
+
+/// Union of the bounding boxes of `elements`, or `None` if none draws.
+fn union_bbox(elements: &[Element], resources: &LoadedResources) -> Result<Option<BBox>> {
+    let mut union: Option<BBox> = None;
+    for child in elements {
+        if let Some(b) = element_bbox(child, resources)? {
+            union = Some(match union {
+                Some(u) => BBox {
+                    left: u.left.min(b.left),
+                    top: u.top.min(b.top),
+                    right: u.right.max(b.right),
+                    bottom: u.bottom.max(b.bottom),
+                },
+                None => b,
+            });
+        }
+    }
+    Ok(union)
+}
+
+/// Bounding box of an element's ink, or `None` if it draws nothing.
+pub fn element_bbox(element: &Element, resources: &LoadedResources) -> Result<Option<BBox>> {
+    if !element.visible() {
+        return Ok(None);
+    }
+    let bbox = match element {
+        Element::Text(t) => {
+            let constrained = overflow::constrain(t, resources)?;
+            let t = constrained.as_ref().unwrap_or(t);
+            let (fonts, names, style_fonts, emoji) = font_chain(resources, t)?;
+            let pieces = runs::resolve_pieces(&t.pieces(), &fonts, &names, &style_fonts, t.missing_glyph_policy, &emoji)?;
+            if pieces.iter().all(|(resolved, _)| resolved.is_empty()) {
+                return Ok(None);
+            }
+            let mut width: f32 = pieces.iter().map(|(resolved, size)| runs::measure(resolved, &fonts, *size)).sum();
+            if let Some(target) = &t.fit_width {
+                width = fit::fit(target, width, fit::glyph_count(pieces.iter().map(|(chars, _)| chars.as_slice()))).width;
+            }
+            let primary = fonts[0];
+            let cap = primary.cap_height_pts(t.size);
+            let baseline = match t.vertical_anchor {
+                VerticalAnchor::Baseline => t.y,
+                VerticalAnchor::Capline => t.y + cap,
+                VerticalAnchor::Center => t.y + cap / 2.0,
+            };
+            let left = match t.align {
+                TextAlign::Left => t.x,
+                TextAlign::Center => t.x - width / 2.0,
+                TextAlign::Right => t.x - width,
+            };
+            let bbox = BBox {
+                left,
+                top: baseline - primary.ascender_pts(t.size),
+                right: left + width,
+                bottom: baseline + primary.descender_pts(t.size).abs(),
+            };
+            let bbox = match t.highlight_color {
+                Some(_) => bbox.outset(t.highlight_padding),
+                None => bbox,
+            };
+            let bbox = bbox.skewed(t.skew, (t.x, baseline));
+            let bbox = match overflow::clip(t, width) {
+                Some((left, right)) => BBox { left: bbox.left.max(left), right: bbox.right.min(right), ..bbox },
+                None => bbox,
+            };
+            // Text turns counter-clockwise
+            bbox.turned(-t.rotation, (t.x, t.y))
+        }
+        Element::TextBox(tb) => {
+            let left = match tb.box_align_x {
+                BoxAlignX::Left => tb.x,
+                BoxAlignX::Center => tb.x - tb.w / 2.0,
+                BoxAlignX::Right => tb.x - tb.w,
+            };
+            let top = match tb.box_align_y {
+                BoxAlignY::Top => tb.y,
+                BoxAlignY::Center => tb.y - tb.h / 2.0,
+                BoxAlignY::Bottom => tb.y - tb.h,
+            };
+            BBox::new(left, top, tb.w, tb.h)
+        }
+        Element::Rect(r) => {
+            let center = (r.x + r.w / 2.0, r.y + r.h / 2.0);
+            let pivot = r.rotation_pivot();
+            BBox::new(r.x, r.y, r.w, r.h)
+                .outset(r.stroke_overhang())
+                .mapped(|corner| rotate_point(r.rotation, r.skew.apply(corner, center), pivot))
+        }
+        Element::Line(l) => BBox::new(l.x1, l.y1, l.x2 - l.x1, l.y2 - l.y1).outset(l.stroke / 2.0),
+        Element::Polygon(p) => {
+            let (xs, ys): (Vec<f32>, Vec<f32>) = p.points.iter().copied().unzip();
+            let min = |v: &[f32]| v.iter().copied().fold(f32::INFINITY, f32::min);
+            let max = |v: &[f32]| v.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+            BBox { left: min(&xs), top: min(&ys), right: max(&xs), bottom: max(&ys) }.outset(p.stroke / 2.0)
+        }
+        Element::Leader(l) => match leader::layout(l, resources)? {
+            Some(s) => {
+                let caps = if s.round_caps { s.width / 2.0 } else { 0.0 };
+                BBox::new(s.start - caps, s.y - s.width / 2.0, s.end - s.start + 2.0 * caps, s.width)
+            }
+            None => return Ok(None),
+        },
+        // The whole circle, whether or not the track is drawn
+        Element::ProgressRing(r) => {
+            let outer = r.radius + r.thickness / 2.0;
+            BBox::new(r.cx - outer, r.cy - outer, 2.0 * outer, 2.0 * outer)
+        }
+        Element::Image(ImageElement { quad: Some(quad), .. }) => BBox::around(quad.corners()),
+        Element::Image(img) => {
+            let (src_w, src_h) = resources.get_image(&img.image_ref)?.dimensions();
+            let (w, h) = PdfGenerator::compute_image_dimensions(src_w, src_h, img.w, img.h);
+            let left = match img.align {
+                TextAlign::Left => img.x,
+                TextAlign::Center => img.x - w / 2.0,
+                TextAlign::Right => img.x - w,
+            };
+            BBox::new(left, img.y, w, h).turned(img.rotation, (img.x, img.y))
+        }
+        Element::Barcode(b) => BBox::new(b.x, b.y, b.w, b.h).turned(b.rotation, (b.x, b.y)),
+        Element::QRCode(qr) => BBox::new(qr.x, qr.y, qr.size, qr.size),
+        Element::DataMatrix(dm) => BBox::new(dm.x, dm.y, dm.size, dm.size),
+        Element::Check(c) => BBox::new(c.x, c.y, c.size, c.size).outset(c.stroke.max(0.0) / 2.0),
+        Element::Note(n) => BBox::new(n.x, n.y, NoteElement::ICON_SIZE, NoteElement::ICON_SIZE),
+        Element::Group(g) => return Ok(union_bbox(&g.elements, resources)?.map(|b| b.mapped(|corner| g.place(corner)))),
+        Element::Fragment(f) => return Ok(union_bbox(&f.fragment.elements, resources)?.map(|b| b.placed(f))),
+        Element::SignatureLine(s) => return union_bbox(&s.parts(), resources),
+        // The box alone: the captions are centered in it and may overhang
+        // a narrow box without marking anything on the page edge
+        Element::ImagePlaceholder(p) => BBox::new(p.left(), p.y, p.w, p.h),
+        // A layout aid drawn edge to edge, so never out of bounds
+        Element::Grid(_) => return Ok(None),
+        // The operators aren't interpreted, so their extent isn't known
+        Element::RawContent(_) => return Ok(None),
+        Element::Table(t) => {
+            if t.rows.is_empty() {
+                return Ok(None);
+            }
+            let h = table::height(t, resources)?;
+            BBox::new(t.x, t.y, t.width(), h).outset(t.border / 2.0)
+        }
+    };
+    Ok(Some(bbox))
+}
+
+/// Describe how `bbox` violates `area`, or `None` if it fits.
+fn describe_violation(kind: &str, bbox: BBox, area: BBox) -> Option<String> {
+    if bbox.right <= area.left
+        || bbox.left >= area.right
+        || bbox.bottom <= area.top
+        || bbox.top >= area.bottom
+    {
+        return Some(format!("{} lies entirely outside the page area", kind));
+    }
+    let overhangs = [
+        ("left", area.left - bbox.left),
+        ("top", area.top - bbox.top),
+        ("right", bbox.right - area.right),
+        ("bottom", bbox.bottom - area.bottom),
+    ];
+    let edges: Vec<String> = overhangs
+        .iter()
+        .filter(|(_, by)| *by > TOLERANCE)
+        .map(|(edge, by)| format!("{} edge by {:.1} pt", edge, by))
+        .collect();
+    if edges.is_empty() {
+        None
+    } else {
+        Some(format!("{} extends past the {}", kind, edges.join(", ")))
+    }
+}
+
+/// Check every element against its page, inset by `margin` in the
+/// document's units. In `Warn` mode violations are pushed to `warnings`;
+/// in `Error` mode the first one is returned as an error.
+pub fn check(
+    doc: &Document,
+    resources: &LoadedResources,
+    mode: BoundsCheck,
+    margin: f32,
+    warnings: &mut Warnings,
+) -> Result<()> {
+    if mode == BoundsCheck::Off {
+        return Ok(());
+    }
+    let margin = doc.units.to_points(Length { value: margin, unit: None });
+    for (page_index, page) in doc.pages.iter().enumerate() {
+        let area = BBox {
+            left: margin,
+            top: margin,
+            right: page.width - margin,
+            bottom: page.height - margin,
+        };
+        // Page elements carry their index; repeating elements are named in
+        // the message instead since they belong to no page's element list.
+        let page_elements = page.elements.iter().enumerate().map(|(i, e)| (e, Some(i), None));
+        let repeating = doc.repeating.iter().enumerate()
+            .filter(|(_, r)| r.pages.includes(page_index))
+            .map(|(i, r)| (&r.element, None, Some(i)));
+        for (element, element_index, repeating_index) in page_elements.chain(repeating) {
+            let bbox = element_bbox(element, resources).map_err(|e| match (element_index, repeating_index) {
+                (Some(i), _) => e.in_element(format!("Page {}, element {}", page_index, element.label(i))),
+                (_, Some(i)) => e.in_element(format!("Page {}, repeating element {}", page_index, element.label(i))),
+                _ => e,
+            })?;
+            let Some(bbox) = bbox else {
+                continue;
+            };
+            let Some(mut message) = describe_violation(element.type_name(), bbox, area) else {
+                continue;
+            };
+            if let Some(i) = repeating_index {
+                message = format!("repeating element {}: {}", element.label(i), message);
+            }
+            let warning = Warning {
+                code: "out_of_bounds",
+                page: Some(page_index),
+                element: element_index,
+                id: element.id().map(str::to_string),
+                message,
+            };
+            match mode {
+                BoundsCheck::Error => return Err(RupdfError::OutOfBounds(warning.to_string())),
+                _ => warnings.push(warning),
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: BBox = BBox { left: 0.0, top: 0.0, right: 612.0, bottom: 792.0 };
+
+    #[test]
+    fn inside_element_passes() {
+        assert_eq!(describe_violation("rect", BBox::new(72.0, 72.0, 100.0, 50.0), PAGE), None);
+    }
+
+    #[test]
+    fn overhang_reports_edges() {
+        let msg = describe_violation("rect", BBox::new(600.0, -5.0, 20.0, 50.0), PAGE).unwrap();
+        assert_eq!(msg, "rect extends past the top edge by 5.0 pt, right edge by 8.0 pt");
+    }
+
+    #[test]
+    fn fully_outside_element_is_called_out() {
+        let msg = describe_violation("image", BBox::new(700.0, 10.0, 20.0, 20.0), PAGE).unwrap();
+        assert!(msg.contains("entirely outside"), "{}", msg);
+    }
+
+    #[test]
+    fn negative_sizes_normalize() {
+        let b = BBox::new(100.0, 100.0, -50.0, -20.0);
+        assert_eq!(b, BBox { left: 50.0, top: 80.0, right: 100.0, bottom: 100.0 });
+    }
+
+    #[test]
+    fn skew_widens_to_the_parallelogram() {
+        // 45 degrees forward about the center: the top edge moves right by
+        // half the height and the bottom edge left
+        let b = BBox::new(0.0, 0.0, 100.0, 100.0).skewed(Skew { x: 45.0, y: 0.0 }, (50.0, 50.0));
+        assert!((b.left + 50.0).abs() < 1e-3 && (b.right - 150.0).abs() < 1e-3, "{:?}", b);
+        assert_eq!((b.top, b.bottom), (0.0, 100.0));
+    }
+
+    #[test]
+    fn rotation_turns_the_corners() {
+        // A quarter turn about the top-left corner swings the box to its left
+        let b = BBox::new(0.0, 0.0, 100.0, 40.0).mapped(|c| rotate_point(90.0, c, (0.0, 0.0)));
+        assert_eq!((b.left, b.top, b.right, b.bottom), (-40.0, 0.0, 0.0, 100.0));
+        // 45 degrees about the center of a square widens it to the diagonal
+        let b = BBox::new(0.0, 0.0, 100.0, 100.0).mapped(|c| rotate_point(45.0, c, (50.0, 50.0)));
+        assert!((b.left - (50.0 - 50.0 * 2f32.sqrt())).abs() < 1e-3, "{:?}", b);
+    }
+}
@@ -0,0 +1,56 @@
+//! Code 128 and GS1-128 encoding, shared by rendering and
+//! `measure_barcode` so a measured symbol is exactly the one drawn.
+
+use crate::error::{Result, RupdfError};
+use crate::types::BarcodeKind;
+use rubar_core::{encode_code128, gs1, Code128Symbol, LinearGeometry};
+
+/// Light modules required on each side of a Code 128 symbol
+pub const QUIET_ZONE_MODULES: u32 = 10;
+
+/// A value encoded as bars
+pub struct EncodedBarcode {
+    pub geometry: LinearGeometry,
+    /// Text printed under the bars: the value, or for GS1-128 its
+    /// parenthesized form
+    pub human_readable: String,
+}
+
+/// Encode `value` as a `kind` symbol. Every Code 128 symbol ends with a
+/// modulo 103 check character, added here.
+pub fn encode(kind: BarcodeKind, value: &str) -> Result<EncodedBarcode> {
+    let invalid = |reason: String| RupdfError::InvalidBarcode { value: value.to_string(), reason };
+    let (symbols, human_readable) = match kind {
+        // Plain Code 128 with Code-B start: a single Data symbol is enough
+        // — rubar_core::encode_code128 auto-inserts Code-B when no start
+        // symbol is specified.
+        BarcodeKind::Code128 => (vec![Code128Symbol::Data(value.to_string())], value.to_string()),
+        BarcodeKind::Gs1_128 => {
+            let fields = gs1::parse(value).map_err(|e| invalid(e.to_string()))?;
+            (gs1::to_symbols(&fields), gs1::format_human_readable(&fields))
+        }
+    };
+    let geometry = encode_code128(&symbols).map_err(|e| invalid(e.to_string()))?;
+    Ok(EncodedBarcode { geometry, human_readable })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code128_counts_start_data_check_and_stop() {
+        // Start B, three data characters and the check character are 11
+        // modules each; the stop pattern is 13
+        let encoded = encode(BarcodeKind::Code128, "ABC").unwrap();
+        assert_eq!(encoded.geometry.total_modules, 5 * 11 + 13);
+        assert_eq!(encoded.human_readable, "ABC");
+    }
+
+    #[test]
+    fn gs1_reads_as_parenthesized_fields() {
+        let encoded = encode(BarcodeKind::Gs1_128, "(10)ABC").unwrap();
+        assert_eq!(encoded.human_readable, "(10)ABC");
+        assert!(encode(BarcodeKind::Gs1_128, "10ABC").is_err());
+    }
+}
@@ -0,0 +1,186 @@
+//! Fitting single-line text to an exact width.
+//!
+//! A text with `fit_width` is measured at its natural width, then stretched
+//! or squeezed to the target with letter spacing (`Tc`) or horizontal
+//! scaling (`Tz`). The fitted width is clamped to `min`..`max` times the
+//! natural width so absurd inputs stay readable; when that happens the text
+//! falls short of (or overshoots) its target and `check` reports it.
+
+use crate::bounds;
+use crate::error::Result;
+use crate::resources::LoadedResources;
+use crate::runs::{self, ResolvedChar, Segment};
+use crate::types::{Document, Element, FitMode, TextElement, TextFit};
+use crate::warnings::{Warning, Warnings};
+
+/// Ratio slack below which a fit counts as exact
+const TOLERANCE: f32 = 1e-4;
+
+/// Text state that makes a line span its fitted width
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fitted {
+    /// Drawn width, in points
+    pub width: f32,
+    /// Extra space after each glyph (`Tc`), in points
+    pub char_spacing: f32,
+    /// Horizontal scale (`Tz`), 1.0 = unscaled
+    pub scale: f32,
+    /// The target lay outside `min`..`max` of the natural width
+    pub clamped: bool,
+}
+
+impl Fitted {
+    /// Move a piece's segments to where they land under this fit and
+    /// return the piece's fitted advance.
+    pub fn place(&self, segments: &mut [Segment], natural: f32) -> f32 {
+        let mut shown = 0;
+        for segment in segments {
+            match segment {
+                Segment::Image { x, .. } | Segment::Inline { x, .. } => *x = *x * self.scale + shown as f32 * self.char_spacing,
+                Segment::Text { x, run } => {
+                    *x = *x * self.scale + shown as f32 * self.char_spacing;
+                    shown += run.glyphs.len();
+                }
+            }
+        }
+        natural * self.scale + shown as f32 * self.char_spacing
+    }
+}
+
+/// Glyphs shown as text, each followed by `Tc`. Color glyph images and
+/// control characters don't count.
+pub fn glyph_count<'c>(pieces: impl IntoIterator<Item = &'c [ResolvedChar]>) -> usize {
+    pieces
+        .into_iter()
+        .flatten()
+        .filter(|c| c.glyph.is_some() && !c.image)
+        .count()
+}
+
+/// Fit a line `natural` points wide with `glyphs` glyphs to `fit.width`.
+/// Tracking spreads the difference over the gaps between glyphs, so the
+/// last glyph ends exactly at the target.
+pub fn fit(fit: &TextFit, natural: f32, glyphs: usize) -> Fitted {
+    let gaps = glyphs.saturating_sub(1);
+    if natural <= 0.0 {
+        return Fitted { width: natural, char_spacing: 0.0, scale: 1.0, clamped: false };
+    }
+    let wanted = fit.width / natural;
+    if fit.mode == FitMode::Tracking && gaps == 0 {
+        // A lone glyph has nowhere to put spacing
+        return Fitted { width: natural, char_spacing: 0.0, scale: 1.0, clamped: (wanted - 1.0).abs() > TOLERANCE };
+    }
+    let ratio = wanted.clamp(fit.min, fit.max);
+    let width = natural * ratio;
+    let (char_spacing, scale) = match fit.mode {
+        FitMode::Tracking => ((width - natural) / gaps as f32, 1.0),
+        FitMode::Scale => (0.0, ratio),
+    };
+    Fitted { width, char_spacing, scale, clamped: (wanted - ratio).abs() > TOLERANCE }
+}
+
+/// Natural width and glyph count of a text element's line.
+pub fn measure(t: &TextElement, resources: &LoadedResources) -> Result<(f32, usize)> {
+    let (fonts, names, style_fonts, emoji) = bounds::font_chain(resources, t)?;
+    let pieces = runs::resolve_pieces(&t.pieces(), &fonts, &names, &style_fonts, t.missing_glyph_policy, &emoji)?;
+    let width = pieces.iter().map(|(chars, size)| runs::measure(chars, &fonts, *size)).sum();
+    Ok((width, glyph_count(pieces.iter().map(|(chars, _)| chars.as_slice()))))
+}
+
+fn check_element(
+    element: &Element,
+    resources: &LoadedResources,
+    location: (Option<usize>, Option<usize>, Option<&str>),
+    warnings: &mut Warnings,
+) -> Result<()> {
+    if !element.visible() {
+        return Ok(());
+    }
+    match element {
+        Element::Text(t) => {
+            let Some(target) = &t.fit_width else { return Ok(()) };
+            let (natural, glyphs) = measure(t, resources)?;
+            let fitted = fit(target, natural, glyphs);
+            if fitted.clamped {
+                warnings.push(Warning {
+                    code: "fit_width_clamped",
+                    page: location.0,
+                    element: location.1,
+                    id: location.2.map(str::to_string),
+                    message: format!(
+                        "text fit to {:.1} pt drawn {:.1} pt wide: its natural width {:.1} pt can only be scaled by {}..{}",
+                        target.width, fitted.width, natural, target.min, target.max
+                    ),
+                });
+            }
+        }
+        Element::Group(g) => {
+            for child in &g.elements {
+                check_element(child, resources, location, warnings)?;
+            }
+        }
+        Element::Fragment(f) => {
+            for child in &f.fragment.elements {
+                check_element(child, resources, location, warnings)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Warn for every `fit_width` text whose target is out of its clamp range.
+pub fn check(doc: &Document, resources: &LoadedResources, warnings: &mut Warnings) -> Result<()> {
+    for (page_index, page) in doc.pages.iter().enumerate() {
+        for (element_index, element) in page.elements.iter().enumerate() {
+            check_element(element, resources, (Some(page_index), Some(element_index), element.id()), warnings)
+                .map_err(|e| e.in_element(format!("Page {}, element {}", page_index, element.label(element_index))))?;
+        }
+    }
+    for (i, repeating) in doc.repeating.iter().enumerate() {
+        let element = &repeating.element;
+        check_element(element, resources, (None, None, element.id()), warnings)
+            .map_err(|e| e.in_element(format!("Repeating element {}", element.label(i))))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(width: f32, mode: FitMode) -> TextFit {
+        TextFit { width, mode, min: 0.5, max: 2.0 }
+    }
+
+    #[test]
+    fn tracking_spreads_difference_over_gaps() {
+        let f = fit(&target(120.0, FitMode::Tracking), 100.0, 5);
+        assert!((f.width - 120.0).abs() < 1e-3);
+        assert!((f.char_spacing - 5.0).abs() < 1e-3);
+        assert_eq!(f.scale, 1.0);
+        assert!(!f.clamped);
+    }
+
+    #[test]
+    fn scale_condenses() {
+        let f = fit(&target(80.0, FitMode::Scale), 100.0, 5);
+        assert_eq!(f.width, 80.0);
+        assert_eq!(f.scale, 0.8);
+        assert_eq!(f.char_spacing, 0.0);
+    }
+
+    #[test]
+    fn extreme_targets_clamp() {
+        let f = fit(&target(500.0, FitMode::Scale), 100.0, 5);
+        assert_eq!(f.width, 200.0);
+        assert!(f.clamped);
+    }
+
+    #[test]
+    fn single_glyph_cannot_track() {
+        let f = fit(&target(50.0, FitMode::Tracking), 10.0, 1);
+        assert_eq!(f.width, 10.0);
+        assert!(f.clamped);
+    }
+}
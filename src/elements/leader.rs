@@ -0,0 +1,120 @@
+//! Leader geometry: where a `leader` element's marks go.
+//!
+//! Marks are drawn as one stroked line: dots are zero-length dashes with
+//! round caps, dashes a plain dash pattern. The run is anchored at `x2`, so
+//! leaders ending at the same x (a TOC's page-number column) line their
+//! dots up from row to row whatever the label width.
+
+use crate::error::Result;
+use crate::resources::LoadedResources;
+use crate::types::{LeaderElement, LeaderStyle};
+
+/// Dot size without a font to take it from, in points
+pub const DEFAULT_DOT_SIZE: f32 = 1.0;
+/// Default gap between marks, in multiples of the dot size
+pub const DEFAULT_GAP: f32 = 2.0;
+/// Dash length, in multiples of the dot size
+pub const DASH_LENGTH: f32 = 3.0;
+
+/// A leader's stroke, in top-left coordinates
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LeaderStroke {
+    /// Center line of the marks
+    pub y: f32,
+    /// Line width: the dot diameter or the dash and rule thickness
+    pub width: f32,
+    /// Ends of the stroked line
+    pub start: f32,
+    pub end: f32,
+    /// None for a solid rule
+    pub dash: Option<Dash>,
+    pub round_caps: bool,
+}
+
+/// Lay out the leader's marks. None when not even one mark fits.
+pub fn layout(leader: &LeaderElement, resources: &LoadedResources) -> Result<Option<LeaderStroke>> {
+    let (y, width) = match &leader.font {
+        Some(font) => {
+            // Rest the marks where the font's period sits, sized like it
+            let font = resources.get_font(font)?;
+            let period = font
+                .glyph_id_opt('.')
+                .and_then(|gid| font.glyph_extent_pts(gid, leader.size));
+            let bottom = period.map_or(0.0, |(bottom, _)| bottom);
+            let width = leader
+                .dot_size
+                .or(period.map(|(bottom, top)| top - bottom).filter(|&h| h > 0.0))
+                .unwrap_or(DEFAULT_DOT_SIZE);
+            (leader.y - bottom - width / 2.0, width)
+        }
+        None => (leader.y, leader.dot_size.unwrap_or(DEFAULT_DOT_SIZE)),
+    };
+    let gap = leader.gap.unwrap_or(width * DEFAULT_GAP);
+    let Some((start, end, dash)) = marks(leader.style, leader.x1, leader.x2, width, gap) else {
+        return Ok(None);
+    };
+    Ok(Some(LeaderStroke { y, width, start, end, dash, round_caps: leader.style == LeaderStyle::Dots }))
+}
+
+/// Dash on and off lengths
+type Dash = (f32, f32);
+
+/// The stroked line's ends and dash pattern for marks `width` across
+/// between `x1` and `x2`.
+fn marks(style: LeaderStyle, x1: f32, x2: f32, width: f32, gap: f32) -> Option<(f32, f32, Option<Dash>)> {
+    // Whole marks only, allowing for rounding in the page coordinates
+    let count = |span: f32, mark: f32| {
+        let pitch = mark + gap;
+        (span >= mark - 1e-3).then(|| ((span - mark + 1e-3) / pitch).floor() + 1.0)
+    };
+    let span = x2 - x1;
+    match style {
+        LeaderStyle::Solid => (span > 0.0).then_some((x1, x2, None)),
+        LeaderStyle::Dots => {
+            // Round caps reach half a dot past each dash, so the line runs
+            // between the first and last dot centers
+            let n = count(span, width)?;
+            let pitch = width + gap;
+            let end = x2 - width / 2.0;
+            Some((end - (n - 1.0) * pitch, end, Some((0.0, pitch))))
+        }
+        LeaderStyle::Dashes => {
+            let dash = width * DASH_LENGTH;
+            let n = count(span, dash)?;
+            Some((x2 - n * dash - (n - 1.0) * gap, x2, Some((dash, gap))))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dots_anchor_at_the_right_end() {
+        // 1pt dots on a 3pt pitch: 10pt holds 4, the last centered 0.5 in from x2
+        let (start, end, dash) = marks(LeaderStyle::Dots, 100.0, 110.0, 1.0, 2.0).unwrap();
+        assert_eq!(end, 109.5);
+        assert_eq!(start, 109.5 - 9.0);
+        assert_eq!(dash, Some((0.0, 3.0)));
+        // A shorter leader ending at the same x keeps the same columns
+        let (start, ..) = marks(LeaderStyle::Dots, 104.0, 110.0, 1.0, 2.0).unwrap();
+        assert_eq!(start, 109.5 - 3.0);
+    }
+
+    #[test]
+    fn dashes_are_whole() {
+        // 3pt dashes, 2pt gaps: 2 fit in 10pt with 2pt left over at x1
+        let (start, end, dash) = marks(LeaderStyle::Dashes, 0.0, 10.0, 1.0, 2.0).unwrap();
+        assert_eq!((start, end), (2.0, 10.0));
+        assert_eq!(dash, Some((3.0, 2.0)));
+    }
+
+    #[test]
+    fn too_short_draws_nothing() {
+        assert_eq!(marks(LeaderStyle::Dots, 0.0, 0.5, 1.0, 2.0), None);
+        assert_eq!(marks(LeaderStyle::Dashes, 0.0, 2.0, 1.0, 2.0), None);
+        assert_eq!(marks(LeaderStyle::Solid, 5.0, 5.0, 1.0, 2.0), None);
+        assert!(marks(LeaderStyle::Dots, 0.0, 1.0, 1.0, 2.0).is_some());
+    }
+}
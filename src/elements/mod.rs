@@ -1,7 +1,23 @@
 // Element rendering is handled directly in pdf/writer.rs
 // This module is reserved for future element-specific utilities.
 //
-// Barcode encoding (Code 128, GS1-128) lives in the `rubar-core` crate.
-// SVG rasterization stays here since it's PDF-specific.
+// Barcode encoding (Code 128, GS1-128) lives in the `rubar-core` crate;
+// `barcode` picks the symbols for each kind so rendering and measuring agree.
+// SVG rasterization stays here since it's PDF-specific. QR encoding drives
+// the `qrcode` crate directly for byte payloads and capacity reporting.
+// Table layout needs loaded fonts, so its pagination pass lives here too.
+// Text fitting (`fit_width`) is shared by the writer and the bounds check,
+// as is leader layout, which reads the period's height from the font.
+// So is `max_width` overflow, which measures text to trim or shrink it.
+// Progress ring arcs are plain geometry, split out to be tested alone.
+// Raw content operators are checked against an allowlist before writing.
 
+pub mod barcode;
+pub mod fit;
+pub mod leader;
+pub mod overflow;
+pub mod qr;
+pub mod raw;
+pub mod ring;
 pub mod svg;
+pub mod table;
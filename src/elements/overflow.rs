@@ -0,0 +1,208 @@
+//! Keeping single-line text within its `max_width`.
+//!
+//! `clip` draws the text whole and clips it to the width. `ellipsis` and
+//! `shrink` draw a narrower copy of the element instead: the text trimmed
+//! behind a "…", or the size reduced in proportion to the overshoot.
+//! Shrinking stops at `min_size`, and whatever still overshoots is
+//! clipped, so no overflow mode spills past the width. The width is laid
+//! out from the anchor as `align` places the text: for centered text it is
+//! centered on `x`. A barcode's human-readable line goes through the same
+//! modes within the barcode's width (`constrain_line`).
+
+use crate::elements::fit;
+use crate::error::Result;
+use crate::resources::LoadedResources;
+use crate::types::{MaxWidth, TextAlign, TextElement, TextOverflow};
+
+/// Slack before a line counts as wider than its `max_width`
+const SLACK: f32 = 0.01;
+
+const ELLIPSIS: char = '\u{2026}';
+
+/// Left and right edges of the room `max` gives `t`.
+fn span(t: &TextElement, max: &MaxWidth) -> (f32, f32) {
+    match t.align {
+        TextAlign::Left => (t.x, t.x + max.width),
+        TextAlign::Center => (t.x - max.width / 2.0, t.x + max.width / 2.0),
+        TextAlign::Right => (t.x - max.width, t.x),
+    }
+}
+
+/// Left and right edges to clip `t` to when drawn `width` points wide, if
+/// that overshoots its `max_width`.
+pub fn clip(t: &TextElement, width: f32) -> Option<(f32, f32)> {
+    let max = t.max_width.as_ref()?;
+    (width > max.width + SLACK).then(|| span(t, max))
+}
+
+/// `t` as drawn within its `max_width`: a trimmed or shrunk copy when
+/// `ellipsis` or `shrink` has to act, otherwise None.
+pub fn constrain(t: &TextElement, resources: &LoadedResources) -> Result<Option<TextElement>> {
+    let Some(max) = &t.max_width else { return Ok(None) };
+    if max.overflow == TextOverflow::Clip {
+        return Ok(None);
+    }
+    let (natural, _) = fit::measure(t, resources)?;
+    if natural <= max.width + SLACK {
+        return Ok(None);
+    }
+    let mut out = t.clone();
+    match max.overflow {
+        TextOverflow::Shrink => out.size = (t.size * max.width / natural).max(max.min_size),
+        TextOverflow::Ellipsis => {
+            let text = ellipsize(&t.text, |text| {
+                out.text = text.to_string();
+                Ok(fit::measure(&out, resources)?.0 <= max.width + SLACK)
+            })?;
+            out.text = text;
+        }
+        TextOverflow::Clip => unreachable!("clipped text is drawn as is"),
+    }
+    Ok(Some(out))
+}
+
+/// The longest prefix of `text` that `fits` with a trailing ellipsis, down
+/// to the ellipsis alone. The whole of `text` overshoots.
+fn ellipsize(text: &str, mut fits: impl FnMut(&str) -> Result<bool>) -> Result<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let trimmed = |n: usize| {
+        let mut text: String = chars[..n].iter().collect::<String>().trim_end().to_string();
+        text.push(ELLIPSIS);
+        text
+    };
+    let (mut fit, mut overshoots) = (0, chars.len());
+    while overshoots - fit > 1 {
+        let mid = (fit + overshoots) / 2;
+        if fits(&trimmed(mid))? {
+            fit = mid;
+        } else {
+            overshoots = mid;
+        }
+    }
+    Ok(trimmed(fit))
+}
+
+/// One centered line within `room` points, as a barcode draws its
+/// human-readable text: the text and size to set it at, and whether it
+/// still overshoots and has to be clipped to `room`. `measure` gives a
+/// line's width at a size.
+pub fn constrain_line(
+    text: &str,
+    size: f32,
+    room: f32,
+    overflow: TextOverflow,
+    min_size: f32,
+    measure: impl Fn(&str, f32) -> Result<f32>,
+) -> Result<(String, f32, bool)> {
+    let natural = measure(text, size)?;
+    if natural <= room + SLACK {
+        return Ok((text.to_string(), size, false));
+    }
+    let (text, size) = match overflow {
+        TextOverflow::Clip => (text.to_string(), size),
+        TextOverflow::Shrink => (text.to_string(), (size * room / natural).max(min_size)),
+        TextOverflow::Ellipsis => (ellipsize(text, |text| Ok(measure(text, size)? <= room + SLACK))?, size),
+    };
+    let overshoots = measure(&text, size)? > room + SLACK;
+    Ok((text, size, overshoots))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{FontResource, FontSource, Resources};
+
+    fn resources() -> LoadedResources {
+        let mut res = Resources::default();
+        res.fonts.insert(
+            "f".to_string(),
+            FontResource {
+                source: FontSource::Path(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/IBMPlexSans-Regular.otf").to_string()),
+                embed: true,
+                cap_height_glyphs: None,
+            },
+        );
+        LoadedResources::load(&res).unwrap()
+    }
+
+    fn text(s: &str, align: TextAlign, overflow: TextOverflow) -> TextElement {
+        TextElement {
+            id: None,
+            tagging: Default::default(),
+            z: 0,
+            visible: true,
+            x: 100.0,
+            y: 100.0,
+            text: s.to_string(),
+            font: "f".to_string(),
+            font_fallback: Vec::new(),
+            missing_glyph_policy: Default::default(),
+            emoji: Default::default(),
+            size: 10.0,
+            color: crate::types::Color::black(),
+            align,
+            vertical_anchor: Default::default(),
+            highlight_color: None,
+            highlight_padding: 0.0,
+            transform: Default::default(),
+            fit_width: None,
+            max_width: Some(MaxWidth { width: 40.0, overflow, min_size: 6.0 }),
+            skew: Default::default(),
+            rotation: 0.0,
+            outline: false,
+            styles: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn clip_span_follows_alignment() {
+        let t = text("x", TextAlign::Center, TextOverflow::Clip);
+        assert_eq!(clip(&t, 40.0), None);
+        assert_eq!(clip(&t, 60.0), Some((80.0, 120.0)));
+        assert_eq!(clip(&text("x", TextAlign::Right, TextOverflow::Clip), 60.0), Some((60.0, 100.0)));
+    }
+
+    #[test]
+    fn ellipsis_keeps_the_longest_prefix_that_fits() {
+        let res = resources();
+        let t = text("Bartholomew Higginbottom", TextAlign::Left, TextOverflow::Ellipsis);
+        let out = constrain(&t, &res).unwrap().unwrap();
+        assert!(fit::measure(&out, &res).unwrap().0 <= 40.0);
+        let kept = out.text.strip_suffix('\u{2026}').unwrap();
+        assert!(!kept.is_empty() && t.text.starts_with(kept));
+        // One more character would overshoot
+        let mut wider = out.clone();
+        wider.text = format!("{}\u{2026}", &t.text[..kept.len() + 1]);
+        assert!(fit::measure(&wider, &res).unwrap().0 > 40.0);
+
+        assert!(constrain(&text("Bart", TextAlign::Left, TextOverflow::Ellipsis), &res).unwrap().is_none());
+    }
+
+    #[test]
+    fn shrink_scales_to_fit_down_to_min_size() {
+        let res = resources();
+        let t = text("Bartholomew", TextAlign::Left, TextOverflow::Shrink);
+        let (natural, _) = fit::measure(&t, &res).unwrap();
+        let out = constrain(&t, &res).unwrap().unwrap();
+        assert!((out.size - 10.0 * 40.0 / natural).abs() < 1e-4);
+
+        let out = constrain(&text("Bartholomew Higginbottom", TextAlign::Left, TextOverflow::Shrink), &res).unwrap().unwrap();
+        assert_eq!(out.size, 6.0);
+    }
+
+    #[test]
+    fn constrain_line_fits_the_room() {
+        // Five points a character at size 10
+        let measure = |text: &str, size: f32| Ok(text.chars().count() as f32 * size / 2.0);
+        let line = |overflow, min_size| constrain_line("ABCDEFGHIJ", 10.0, 30.0, overflow, min_size, measure).unwrap();
+
+        assert_eq!(line(TextOverflow::Shrink, 5.0), ("ABCDEFGHIJ".to_string(), 6.0, false));
+        assert_eq!(line(TextOverflow::Shrink, 8.0), ("ABCDEFGHIJ".to_string(), 8.0, true));
+        assert_eq!(line(TextOverflow::Ellipsis, 5.0), ("ABCDE\u{2026}".to_string(), 10.0, false));
+        assert_eq!(line(TextOverflow::Clip, 5.0), ("ABCDEFGHIJ".to_string(), 10.0, true));
+        assert_eq!(
+            constrain_line("ABC", 10.0, 30.0, TextOverflow::Shrink, 5.0, measure).unwrap(),
+            ("ABC".to_string(), 10.0, false)
+        );
+    }
+}
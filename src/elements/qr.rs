@@ -0,0 +1,325 @@
+//! QR code encoding with capacity-aware error reporting.
+//!
+//! `rubar_core::encode_qr` only takes `&str` at error-correction level M and
+//! reports overflow as a bare "data too long". This module drives the
+//! `qrcode` crate directly so byte payloads, explicit EC levels and fixed
+//! versions are supported, and overflow errors say how far over the limit
+//! the payload is.
+
+use crate::error::{Result, RupdfError};
+use crate::types::{QRCodeElement, QrData, QrErrorCorrection, WifiSecurity};
+use qrcode::types::QrError;
+use qrcode::{EcLevel, QrCode, Version};
+use rubar_core::MatrixGeometry;
+
+impl From<QrErrorCorrection> for EcLevel {
+    fn from(ec: QrErrorCorrection) -> Self {
+        match ec {
+            QrErrorCorrection::Low => EcLevel::L,
+            QrErrorCorrection::Medium => EcLevel::M,
+            QrErrorCorrection::Quartile => EcLevel::Q,
+            QrErrorCorrection::High => EcLevel::H,
+        }
+    }
+}
+
+/// Escape a vCard 3.0 text value (RFC 2426 section 4).
+fn vcard_text(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '\\' | ';' | ',' => {
+                out.push('\\');
+                out.push(ch);
+            }
+            '\n' => out.push_str("\\n"),
+            '\r' => {}
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+/// Escape a `WIFI:` field value: `\`, `;`, `,`, `:` and `"` are
+/// backslash-escaped.
+fn wifi_text(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for ch in s.chars() {
+        if matches!(ch, '\\' | ';' | ',' | ':' | '"') {
+            out.push('\\');
+        }
+        out.push(ch);
+    }
+    out
+}
+
+impl QrData {
+    /// The standard text payload: a vCard 3.0 card with CRLF line ends, or
+    /// a `WIFI:T:...;S:...;P:...;;` string.
+    pub fn payload(&self) -> String {
+        match self {
+            QrData::VCard { name, family_name, given_name, org, title, phones, emails, url, note } => {
+                let mut lines = vec!["BEGIN:VCARD".to_string(), "VERSION:3.0".to_string()];
+                let n = match (family_name, given_name) {
+                    (None, None) => format!("{};;;;", vcard_text(name)),
+                    (family, given) => format!(
+                        "{};{};;;",
+                        vcard_text(family.as_deref().unwrap_or("")),
+                        vcard_text(given.as_deref().unwrap_or(""))
+                    ),
+                };
+                lines.push(format!("N:{}", n));
+                lines.push(format!("FN:{}", vcard_text(name)));
+                if let Some(org) = org {
+                    lines.push(format!("ORG:{}", vcard_text(org)));
+                }
+                if let Some(title) = title {
+                    lines.push(format!("TITLE:{}", vcard_text(title)));
+                }
+                for phone in phones {
+                    lines.push(format!("TEL:{}", phone.replace(['\r', '\n'], "")));
+                }
+                for email in emails {
+                    lines.push(format!("EMAIL;TYPE=INTERNET:{}", vcard_text(email)));
+                }
+                if let Some(url) = url {
+                    lines.push(format!("URL:{}", url.replace(['\r', '\n'], "")));
+                }
+                if let Some(note) = note {
+                    lines.push(format!("NOTE:{}", vcard_text(note)));
+                }
+                lines.push("END:VCARD".to_string());
+                lines.iter().map(|line| format!("{}\r\n", line)).collect()
+            }
+            QrData::Wifi { ssid, password, security, hidden } => {
+                let kind = match security {
+                    WifiSecurity::Wpa => "WPA",
+                    WifiSecurity::Wep => "WEP",
+                    WifiSecurity::Sae => "SAE",
+                    WifiSecurity::None => "nopass",
+                };
+                let mut out = format!("WIFI:T:{};S:{};", kind, wifi_text(ssid));
+                if let Some(password) = password {
+                    out.push_str(&format!("P:{};", wifi_text(password)));
+                }
+                if *hidden {
+                    out.push_str("H:true;");
+                }
+                out.push(';');
+                out
+            }
+        }
+    }
+}
+
+/// Version 40 capacity per encoding mode, indexed by EC level (L, M, Q, H).
+const NUMERIC_CAPACITY: [usize; 4] = [7089, 5596, 3993, 3057];
+const ALPHANUMERIC_CAPACITY: [usize; 4] = [4296, 3391, 2420, 1852];
+const BYTE_CAPACITY: [usize; 4] = [2953, 2331, 1663, 1273];
+
+fn is_alphanumeric(b: u8) -> bool {
+    b.is_ascii_digit() || b.is_ascii_uppercase() || b" $%*+-./:".contains(&b)
+}
+
+/// Largest payload that fits a version 40 symbol, in the densest mode the
+/// whole payload qualifies for. Returns (max length, unit name).
+fn capacity(data: &[u8], ec: QrErrorCorrection) -> (usize, &'static str) {
+    let level = match ec {
+        QrErrorCorrection::Low => 0,
+        QrErrorCorrection::Medium => 1,
+        QrErrorCorrection::Quartile => 2,
+        QrErrorCorrection::High => 3,
+    };
+    if data.iter().all(u8::is_ascii_digit) {
+        (NUMERIC_CAPACITY[level], "digits")
+    } else if data.iter().copied().all(is_alphanumeric) {
+        (ALPHANUMERIC_CAPACITY[level], "characters")
+    } else {
+        (BYTE_CAPACITY[level], "bytes")
+    }
+}
+
+fn encode_auto(qr: &QRCodeElement) -> Result<QrCode> {
+    let data = qr.value.as_bytes();
+    QrCode::with_error_correction_level(data, qr.error_correction.into()).map_err(|e| match e {
+        QrError::DataTooLong => {
+            let (max, unit) = capacity(data, qr.error_correction);
+            RupdfError::QrCapacityExceeded {
+                length: data.len(),
+                max,
+                unit,
+                level: qr.error_correction.letter(),
+            }
+        }
+        other => RupdfError::InvalidBarcode {
+            value: qr.value.display(),
+            reason: other.to_string(),
+        },
+    })
+}
+
+/// Encode a QR element's payload into module geometry.
+///
+/// `version` is honored exactly when `auto_version` is false; otherwise it
+/// is a minimum and the encoder grows the symbol as the payload requires.
+/// `min_module_size` is checked against the final symbol width.
+pub fn encode(qr: &QRCodeElement) -> Result<MatrixGeometry> {
+    let data = qr.value.as_bytes();
+    let code = match qr.version {
+        Some(v) => {
+            match QrCode::with_version(data, Version::Normal(v as i16), qr.error_correction.into()) {
+                Ok(code) => code,
+                Err(QrError::DataTooLong) if qr.auto_version => encode_auto(qr)?,
+                Err(QrError::DataTooLong) => {
+                    let needed = match encode_auto(qr)?.version() {
+                        Version::Normal(n) | Version::Micro(n) => n,
+                    };
+                    return Err(RupdfError::InvalidBarcode {
+                        value: qr.value.display(),
+                        reason: format!(
+                            "payload does not fit QR version {} at error correction level {}; \
+                             it needs version {} (set auto_version to allow this)",
+                            v,
+                            qr.error_correction.letter(),
+                            needed
+                        ),
+                    });
+                }
+                Err(e) => {
+                    return Err(RupdfError::InvalidBarcode {
+                        value: qr.value.display(),
+                        reason: e.to_string(),
+                    })
+                }
+            }
+        }
+        None => encode_auto(qr)?,
+    };
+
+    let width = code.width();
+    if let Some(min) = qr.min_module_size {
+        let module = qr.size / width as f32;
+        if module < min {
+            return Err(RupdfError::InvalidBarcode {
+                value: qr.value.display(),
+                reason: format!(
+                    "QR symbol needs {w}x{w} modules; at size {size} each module is {module:.3} pt, \
+                     below min_module_size {min}",
+                    w = width,
+                    size = qr.size,
+                ),
+            });
+        }
+    }
+
+    let colors = code.to_colors();
+    let modules = colors
+        .chunks(width)
+        .map(|row| row.iter().map(|&c| c == qrcode::Color::Dark).collect())
+        .collect();
+    Ok(MatrixGeometry {
+        modules,
+        width: width as u32,
+        height: width as u32,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Color, QrPayload, Tagging};
+
+    fn qr(value: QrPayload) -> QRCodeElement {
+        QRCodeElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 0.0,
+            y: 0.0,
+            size: 100.0,
+            value,
+            error_correction: QrErrorCorrection::Medium,
+            version: None,
+            auto_version: true,
+            min_module_size: None,
+            color: Color::black(),
+            background: Color::white(),
+        }
+    }
+
+    #[test]
+    fn encodes_text_payload() {
+        let geom = encode(&qr(QrPayload::Text("HELLO".to_string()))).unwrap();
+        assert_eq!(geom.width, 21);
+        assert_eq!(geom.modules.len(), 21);
+    }
+
+    #[test]
+    fn encodes_binary_payload() {
+        let geom = encode(&qr(QrPayload::Bytes(vec![0x00, 0xff, 0x80, 0x7f]))).unwrap();
+        assert!(geom.is_square());
+    }
+
+    #[test]
+    fn overflow_reports_length_and_capacity() {
+        let err = encode(&qr(QrPayload::Bytes(vec![b'x'; 3000]))).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "QR payload too long: 3000 bytes exceeds the maximum of 2331 bytes at error correction level M"
+        );
+    }
+
+    #[test]
+    fn fixed_version_grows_only_with_auto_version() {
+        let mut el = qr(QrPayload::Text("A".repeat(100)));
+        el.version = Some(1);
+        assert!(encode(&el).unwrap().width > 21);
+
+        el.auto_version = false;
+        let err = encode(&el).unwrap_err().to_string();
+        assert!(err.contains("does not fit QR version 1"), "{}", err);
+    }
+
+    #[test]
+    fn min_module_size_rejects_dense_symbols() {
+        let mut el = qr(QrPayload::Text("A".repeat(500)));
+        el.min_module_size = Some(2.0);
+        let err = encode(&el).unwrap_err().to_string();
+        assert!(err.contains("below min_module_size"), "{}", err);
+    }
+
+    #[test]
+    fn vcard_payload_escapes_text() {
+        let card = QrData::VCard {
+            name: "Doe, Jane".to_string(),
+            family_name: None,
+            given_name: None,
+            org: Some("Acme; Inc.".to_string()),
+            title: None,
+            phones: vec!["+1 555 0100".to_string()],
+            emails: vec!["jane@example.com".to_string()],
+            url: None,
+            note: Some("line one\nline two".to_string()),
+        };
+        assert_eq!(
+            card.payload(),
+            "BEGIN:VCARD\r\nVERSION:3.0\r\nN:Doe\\, Jane;;;;\r\nFN:Doe\\, Jane\r\nORG:Acme\\; Inc.\r\n\
+             TEL:+1 555 0100\r\nEMAIL;TYPE=INTERNET:jane@example.com\r\nNOTE:line one\\nline two\r\nEND:VCARD\r\n"
+        );
+    }
+
+    #[test]
+    fn wifi_payload_escapes_fields() {
+        let wifi = QrData::Wifi {
+            ssid: "Cafe \"Wi:Fi\"".to_string(),
+            password: Some("a;b,c\\d".to_string()),
+            security: WifiSecurity::Wpa,
+            hidden: true,
+        };
+        assert_eq!(wifi.payload(), r#"WIFI:T:WPA;S:Cafe \"Wi\:Fi\";P:a\;b\,c\\d;H:true;;"#);
+
+        let open = QrData::Wifi { ssid: "Guest".to_string(), password: None, security: WifiSecurity::None, hidden: false };
+        assert_eq!(open.payload(), "WIFI:T:nopass;S:Guest;;");
+    }
+}
@@ -0,0 +1,380 @@
+//! Checking for `raw_content` operators.
+//!
+//! Raw operators go into the content stream as written, so they are only
+//! read far enough to keep the page around them intact: every operator
+//! must be on an allowlist, every resource it names must be declared in
+//! `raw_resources`, and `q`/`Q` and `BT`/`ET` must balance. Operands are
+//! not otherwise checked. Declared names are swapped for the names rupdf
+//! gives those resources when the stream is written, and the glyphs shown
+//! in each declared font are added to its subset.
+
+use crate::error::{Result, RupdfError};
+use crate::types::RawResource;
+use std::ops::Range;
+
+/// Operators raw content may use, beyond those that name resources
+const ALLOWED: &[&str] = &[
+    // Graphics state
+    "q", "Q", "cm", "w", "J", "j", "M", "d", "ri", "i",
+    // Paths, painting and clipping
+    "m", "l", "c", "v", "y", "h", "re", "S", "s", "f", "F", "f*", "B", "B*", "b", "b*", "n", "W", "W*",
+    // Device colors
+    "G", "g", "RG", "rg", "K", "k", "SC", "sc", "SCN", "scn",
+    // Text
+    "BT", "ET", "Tc", "Tw", "Tz", "TL", "Ts", "Tr", "Td", "TD", "Tm", "T*", "Tj", "TJ", "'", "\"",
+];
+
+/// What a raw operator stream refers to, found by `check`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RawUses {
+    /// Name operands to replace when writing: the byte range of each
+    /// (slash included) and the index of its `raw_resources` entry
+    pub names: Vec<(Range<usize>, usize)>,
+    /// Glyph ids shown, by the `raw_resources` index of their font
+    pub glyphs: Vec<(usize, u16)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number,
+    Name(Range<usize>),
+    /// A literal or hex string's bytes
+    String(Vec<u8>),
+    ArrayStart,
+    ArrayEnd,
+    Keyword(Range<usize>),
+}
+
+fn invalid(message: String) -> RupdfError {
+    RupdfError::InvalidDocument(message)
+}
+
+fn is_space(b: u8) -> bool {
+    matches!(b, b' ' | b'\t' | b'\n' | b'\r' | b'\x0c' | b'\0')
+}
+
+fn is_delimiter(b: u8) -> bool {
+    matches!(b, b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%')
+}
+
+/// Read the literal string starting at `pos`, returning its bytes and the
+/// position after it
+fn literal_string(data: &[u8], mut pos: usize) -> Result<(Vec<u8>, usize)> {
+    let mut out = Vec::new();
+    let mut depth = 0;
+    while pos < data.len() {
+        let b = data[pos];
+        pos += 1;
+        match b {
+            b'(' => {
+                depth += 1;
+                if depth == 1 {
+                    continue;
+                }
+            }
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok((out, pos));
+                }
+            }
+            b'\\' => {
+                let Some(&escaped) = data.get(pos) else { break };
+                pos += 1;
+                match escaped {
+                    b'n' => out.push(b'\n'),
+                    b'r' => out.push(b'\r'),
+                    b't' => out.push(b'\t'),
+                    b'b' => out.push(0x08),
+                    b'f' => out.push(0x0c),
+                    b'0'..=b'7' => {
+                        let mut value = u32::from(escaped - b'0');
+                        for _ in 0..2 {
+                            match data.get(pos) {
+                                Some(&d @ b'0'..=b'7') => {
+                                    value = value * 8 + u32::from(d - b'0');
+                                    pos += 1;
+                                }
+                                _ => break,
+                            }
+                        }
+                        out.push(value as u8);
+                    }
+                    // A backslash before a line end continues the line
+                    b'\r' => {
+                        if data.get(pos) == Some(&b'\n') {
+                            pos += 1;
+                        }
+                    }
+                    b'\n' => {}
+                    other => out.push(other),
+                }
+                continue;
+            }
+            _ => {}
+        }
+        out.push(b);
+    }
+    Err(invalid("raw_content: unterminated string".to_string()))
+}
+
+/// Read the hex string starting at `pos`
+fn hex_string(data: &[u8], pos: usize) -> Result<(Vec<u8>, usize)> {
+    let end = data[pos..]
+        .iter()
+        .position(|&b| b == b'>')
+        .map(|n| pos + n)
+        .ok_or_else(|| invalid("raw_content: unterminated hex string".to_string()))?;
+    let mut digits = Vec::new();
+    for &b in &data[pos + 1..end] {
+        if is_space(b) {
+            continue;
+        }
+        let digit = (b as char)
+            .to_digit(16)
+            .ok_or_else(|| invalid(format!("raw_content: invalid hex digit '{}'", b as char)))?;
+        digits.push(digit as u8);
+    }
+    // An odd final digit is followed by an implied 0
+    if digits.len() % 2 == 1 {
+        digits.push(0);
+    }
+    Ok((digits.chunks(2).map(|pair| pair[0] << 4 | pair[1]).collect(), end + 1))
+}
+
+fn tokenize(data: &[u8]) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let b = data[pos];
+        if is_space(b) {
+            pos += 1;
+            continue;
+        }
+        let start = pos;
+        match b {
+            b'%' => {
+                while pos < data.len() && data[pos] != b'\n' && data[pos] != b'\r' {
+                    pos += 1;
+                }
+            }
+            b'(' => {
+                let (bytes, end) = literal_string(data, pos)?;
+                tokens.push(Token::String(bytes));
+                pos = end;
+            }
+            b'<' if data.get(pos + 1) == Some(&b'<') => {
+                return Err(invalid("raw_content: dictionaries are not allowed".to_string()));
+            }
+            b'<' => {
+                let (bytes, end) = hex_string(data, pos)?;
+                tokens.push(Token::String(bytes));
+                pos = end;
+            }
+            b'[' => {
+                tokens.push(Token::ArrayStart);
+                pos += 1;
+            }
+            b']' => {
+                tokens.push(Token::ArrayEnd);
+                pos += 1;
+            }
+            b')' | b'>' | b'{' | b'}' => {
+                return Err(invalid(format!("raw_content: unexpected '{}'", b as char)));
+            }
+            _ => {
+                pos += 1;
+                while pos < data.len() && !is_space(data[pos]) && !is_delimiter(data[pos]) {
+                    pos += 1;
+                }
+                let word = &data[start..pos];
+                tokens.push(if b == b'/' {
+                    Token::Name(start..pos)
+                } else if word[0].is_ascii_digit() || matches!(word[0], b'+' | b'-' | b'.') {
+                    Token::Number
+                } else {
+                    Token::Keyword(start..pos)
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Check `operators` against the allowlist and the declared `resources`,
+/// and find the names and glyphs to resolve when it is written.
+pub fn check(operators: &str, resources: &[(String, RawResource)]) -> Result<RawUses> {
+    let data = operators.as_bytes();
+    let mut uses = RawUses::default();
+    let mut operands: Vec<Token> = Vec::new();
+    let mut saves = 0usize;
+    let mut in_text = false;
+    // The `raw_resources` index of the font last selected with Tf
+    let mut font: Option<usize> = None;
+
+    for token in tokenize(data)? {
+        let Token::Keyword(range) = token else {
+            operands.push(token);
+            continue;
+        };
+        let op = &operators[range];
+        if matches!(op, "true" | "false" | "null") {
+            operands.push(Token::Number);
+            continue;
+        }
+        // The resource a name operand refers to, which must be of `kind`
+        let mut resource = |kind: &str, is_kind: fn(&RawResource) -> bool| -> Result<usize> {
+            let Some(Token::Name(range)) = operands.iter().find(|t| matches!(t, Token::Name(_))).cloned() else {
+                return Err(invalid(format!("raw_content: '{}' needs a resource name", op)));
+            };
+            let name = &operators[range.start + 1..range.end];
+            let index = resources
+                .iter()
+                .position(|(declared, _)| declared == name)
+                .ok_or_else(|| invalid(format!("raw_content: '/{}' is not declared in raw_resources", name)))?;
+            if !is_kind(&resources[index].1) {
+                return Err(invalid(format!("raw_content: '/{}' used with '{}' must be {} resource", name, op, kind)));
+            }
+            uses.names.push((range, index));
+            Ok(index)
+        };
+        match op {
+            "Tf" => font = Some(resource("a font", |r| matches!(r, RawResource::Font(_)))?),
+            "Do" => {
+                resource("an image", |r| matches!(r, RawResource::Image(_)))?;
+            }
+            "gs" => {
+                resource("an alpha", |r| matches!(r, RawResource::Alpha(_)))?;
+            }
+            "cs" | "CS" => {
+                let space = match operands.first() {
+                    Some(Token::Name(range)) => &operators[range.clone()],
+                    _ => "",
+                };
+                if !matches!(space, "/DeviceGray" | "/DeviceRGB" | "/DeviceCMYK") {
+                    return Err(invalid(format!(
+                        "raw_content: '{}' only takes /DeviceGray, /DeviceRGB or /DeviceCMYK", op
+                    )));
+                }
+            }
+            "SC" | "sc" | "SCN" | "scn" if operands.iter().any(|t| !matches!(t, Token::Number)) => {
+                return Err(invalid(format!("raw_content: '{}' only takes color components", op)));
+            }
+            "q" => saves += 1,
+            "Q" => {
+                saves = saves
+                    .checked_sub(1)
+                    .ok_or_else(|| invalid("raw_content: 'Q' without a matching 'q'".to_string()))?;
+            }
+            "BT" if in_text => return Err(invalid("raw_content: 'BT' inside a text object".to_string())),
+            "BT" => in_text = true,
+            "ET" if !in_text => return Err(invalid("raw_content: 'ET' without a matching 'BT'".to_string())),
+            "ET" => in_text = false,
+            "Tj" | "TJ" | "'" | "\"" => {
+                let font = font
+                    .ok_or_else(|| invalid(format!("raw_content: '{}' before a font is set with Tf", op)))?;
+                for token in &operands {
+                    if let Token::String(bytes) = token {
+                        if bytes.len() % 2 == 1 {
+                            return Err(invalid(
+                                "raw_content: text strings are 2-byte glyph ids, so need an even length".to_string(),
+                            ));
+                        }
+                        uses.glyphs.extend(bytes.chunks(2).map(|pair| (font, u16::from_be_bytes([pair[0], pair[1]]))));
+                    }
+                }
+            }
+            _ if ALLOWED.contains(&op) => {}
+            _ => return Err(invalid(format!("raw_content: operator '{}' is not allowed", op))),
+        }
+        operands.clear();
+    }
+
+    if !operands.is_empty() {
+        return Err(invalid("raw_content: operands after the last operator".to_string()));
+    }
+    if saves > 0 {
+        return Err(invalid("raw_content: 'q' without a matching 'Q'".to_string()));
+    }
+    if in_text {
+        return Err(invalid("raw_content: 'BT' without a matching 'ET'".to_string()));
+    }
+    uses.glyphs.sort_unstable();
+    uses.glyphs.dedup();
+    Ok(uses)
+}
+
+/// `operators` with each declared name replaced by `resolve`'s name for
+/// its resource
+pub fn resolve_names(operators: &str, uses: &RawUses, mut resolve: impl FnMut(usize) -> String) -> String {
+    let mut out = String::with_capacity(operators.len());
+    let mut copied = 0;
+    for (range, index) in &uses.names {
+        out.push_str(&operators[copied..range.start]);
+        out.push('/');
+        out.push_str(&resolve(*index));
+        copied = range.end;
+    }
+    out.push_str(&operators[copied..]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resources() -> Vec<(String, RawResource)> {
+        vec![
+            ("F1".to_string(), RawResource::Font("body".to_string())),
+            ("Logo".to_string(), RawResource::Image("logo".to_string())),
+            ("Half".to_string(), RawResource::Alpha(127)),
+        ]
+    }
+
+    fn error(operators: &str) -> String {
+        check(operators, &resources()).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_paths_and_colors_are_allowed() {
+        let uses = check("q 1 0 0 rg 0 0 m 10 10 l S /DeviceCMYK cs 0 1 0 0 sc Q", &resources()).unwrap();
+        assert_eq!(uses, RawUses::default());
+    }
+
+    #[test]
+    fn test_declared_names_are_resolved() {
+        let operators = "/Half gs q 50 0 0 50 0 0 cm /Logo Do Q";
+        let uses = check(operators, &resources()).unwrap();
+        let names = ["Body", "logo_50x50", "A127"];
+        assert_eq!(resolve_names(operators, &uses, |i| names[i].to_string()), "/A127 gs q 50 0 0 50 0 0 cm /logo_50x50 Do Q");
+    }
+
+    #[test]
+    fn test_text_glyphs_are_collected() {
+        let uses = check("BT /F1 12 Tf 10 10 Td <00240025> Tj [(\\000\\044) -50 <0026>] TJ ET", &resources()).unwrap();
+        assert_eq!(uses.glyphs, vec![(0, 0x24), (0, 0x25), (0, 0x26)]);
+        assert_eq!(uses.names.len(), 1);
+    }
+
+    #[test]
+    fn test_rejected_operators() {
+        assert!(error("0 0 m 10 10 l /Sh sh").contains("operator 'sh' is not allowed"));
+        assert!(error("BI /W 1 ID").contains("'BI' is not allowed"));
+        assert!(error("/Other Do").contains("'/Other' is not declared in raw_resources"));
+        assert!(error("/F1 Do").contains("must be an image resource"));
+        assert!(error("/P0 scn").contains("only takes color components"));
+        assert!(error("/Pattern cs").contains("only takes /DeviceGray"));
+        assert!(error("/Tag << /MCID 0 >> BDC").contains("dictionaries are not allowed"));
+    }
+
+    #[test]
+    fn test_nesting_must_balance() {
+        assert!(error("Q").contains("'Q' without a matching 'q'"));
+        assert!(error("q q Q").contains("'q' without a matching 'Q'"));
+        assert!(error("BT BT ET ET").contains("'BT' inside a text object"));
+        assert!(error("BT /F1 12 Tf").contains("'BT' without a matching 'ET'"));
+        assert!(error("BT (ab) Tj ET").contains("before a font is set"));
+        assert!(error("BT /F1 12 Tf <002400> Tj ET").contains("even length"));
+        assert!(error("0 0 10").contains("operands after the last operator"));
+    }
+}
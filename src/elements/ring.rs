@@ -0,0 +1,96 @@
+//! Progress ring geometry: circular arcs as cubic Béziers.
+//!
+//! Angles are in degrees clockwise from 12 o'clock, in top-left page
+//! coordinates. Arcs are split into pieces of at most 90°, each close to
+//! the true circle to within 0.03% of the radius; a full turn is four
+//! pieces and closes on itself, so it has no caps to overlap.
+
+use crate::types::ProgressRingElement;
+
+/// One cubic Bézier piece: start, two control points, end
+pub type Segment = [(f32, f32); 4];
+
+/// Stroked arcs for a ring: the track under the fill
+#[derive(Debug, Clone, PartialEq)]
+pub struct RingArcs {
+    /// The whole circle, when there is a track color
+    pub track: Option<Vec<Segment>>,
+    /// The filled part; None for a value of 0
+    pub fill: Option<Vec<Segment>>,
+    /// Whether the fill is a whole circle, closed rather than capped
+    pub fill_closed: bool,
+}
+
+pub fn arcs(ring: &ProgressRingElement) -> RingArcs {
+    let arc = |sweep: f32| arc_segments(ring.cx, ring.cy, ring.radius, ring.start_angle, sweep);
+    RingArcs {
+        track: ring.track_color.as_ref().map(|_| arc(360.0)),
+        fill: (ring.value > 0.0).then(|| arc(ring.value * 360.0)),
+        fill_closed: ring.value >= 1.0,
+    }
+}
+
+/// Point on the circle at `angle` degrees
+fn point(cx: f32, cy: f32, r: f32, angle: f32) -> (f32, f32) {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    (cx + r * sin, cy - r * cos)
+}
+
+/// Béziers tracing `sweep` degrees (0 < sweep <= 360) clockwise from `start`.
+pub fn arc_segments(cx: f32, cy: f32, r: f32, start: f32, sweep: f32) -> Vec<Segment> {
+    let n = (sweep / 90.0).ceil().max(1.0) as usize;
+    let step = sweep / n as f32;
+    // Control arm length for a piece spanning `step`
+    let k = r * 4.0 / 3.0 * (step.to_radians() / 4.0).tan();
+    (0..n)
+        .map(|i| {
+            let a0 = start + step * i as f32;
+            let a1 = a0 + step;
+            let p0 = point(cx, cy, r, a0);
+            let p3 = point(cx, cy, r, a1);
+            // Clockwise tangent at angle a is (cos a, sin a)
+            let (s0, c0) = a0.to_radians().sin_cos();
+            let (s1, c1) = a1.to_radians().sin_cos();
+            [p0, (p0.0 + k * c0, p0.1 + k * s0), (p3.0 - k * c1, p3.1 - k * s1), p3]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: (f32, f32), b: (f32, f32)) -> bool {
+        (a.0 - b.0).abs() < 1e-3 && (a.1 - b.1).abs() < 1e-3
+    }
+
+    #[test]
+    fn full_turn_is_four_quarters() {
+        let segs = arc_segments(0.0, 0.0, 10.0, 0.0, 360.0);
+        assert_eq!(segs.len(), 4);
+        assert!(close(segs[0][0], (0.0, -10.0)));
+        assert!(close(segs[0][3], (10.0, 0.0)));
+        assert!(close(segs[3][3], segs[0][0]));
+        // The standard quarter-circle arm, 0.5523 of the radius
+        assert!(close(segs[0][1], (5.523, -10.0)));
+    }
+
+    #[test]
+    fn pieces_never_exceed_a_quarter() {
+        assert_eq!(arc_segments(0.0, 0.0, 10.0, 0.0, 90.0).len(), 1);
+        assert_eq!(arc_segments(0.0, 0.0, 10.0, 0.0, 91.0).len(), 2);
+        assert_eq!(arc_segments(0.0, 0.0, 10.0, 0.0, 1.0).len(), 1);
+        let half = arc_segments(0.0, 0.0, 10.0, 0.0, 180.0);
+        assert!(close(half[1][3], (0.0, 10.0)));
+    }
+
+    #[test]
+    fn midpoint_stays_on_the_circle() {
+        let [p0, p1, p2, p3] = arc_segments(0.0, 0.0, 10.0, 30.0, 60.0)[0];
+        let mid = (
+            (p0.0 + 3.0 * p1.0 + 3.0 * p2.0 + p3.0) / 8.0,
+            (p0.1 + 3.0 * p1.1 + 3.0 * p2.1 + p3.1) / 8.0,
+        );
+        assert!(close(mid, point(0.0, 0.0, 10.0, 60.0)));
+    }
+}
@@ -9,13 +9,16 @@ fn warn_unsupported(feature: &str) {
     eprintln!("rupdf warning: SVG feature not supported: {}", feature);
 }
 
-/// Convert an SVG tree to a PDF Form XObject containing vector paths
+/// Convert an SVG tree to a PDF Form XObject containing vector paths,
+/// deflated unless `compress` is false. Returns the size of the written
+/// content stream.
 pub fn write_svg_form(
     pdf: &mut Pdf,
     form_ref: Ref,
     tree: &Tree,
     _name: &str,
-) -> Result<()> {
+    compress: bool,
+) -> Result<usize> {
     let size = tree.size;
     let width = size.width() as f32;
     let height = size.height() as f32;
@@ -24,15 +27,21 @@ pub fn write_svg_form(
     let content_data = render_svg_to_content(tree, height)?;
 
     // Compress the content
-    let compressed = miniz_oxide::deflate::compress_to_vec_zlib(&content_data, 6);
+    let data = if compress {
+        miniz_oxide::deflate::compress_to_vec_zlib(&content_data, 6)
+    } else {
+        content_data
+    };
 
     // Write as Form XObject
-    let mut form = pdf.form_xobject(form_ref, &compressed);
-    form.filter(pdf_writer::Filter::FlateDecode);
+    let mut form = pdf.form_xobject(form_ref, &data);
+    if compress {
+        form.filter(pdf_writer::Filter::FlateDecode);
+    }
     form.bbox(Rect::new(0.0, 0.0, width, height));
     form.finish();
 
-    Ok(())
+    Ok(data.len())
 }
 
 /// Render SVG tree to PDF content stream bytes
@@ -166,6 +175,53 @@ fn render_path(path: &usvg::Path, content: &mut Content, svg_height: f32) {
     }
 }
 
+/// The features of an SVG that `write_svg_form` drops or draws
+/// differently, each once, in the order first met. Gradients, patterns
+/// and images are skipped with a warning as they are drawn; the rest are
+/// drawn as if absent (opacity as opaque, dashes as solid lines).
+pub fn unsupported_features(tree: &Tree) -> Vec<&'static str> {
+    let paint = |paint: &Paint, kind: [&'static str; 3]| match paint {
+        Paint::Color(_) => None,
+        Paint::LinearGradient(_) => Some(kind[0]),
+        Paint::RadialGradient(_) => Some(kind[1]),
+        Paint::Pattern(_) => Some(kind[2]),
+    };
+    let mut features = Vec::new();
+    for node in tree.root.descendants() {
+        let found: Vec<Option<&'static str>> = match &*node.borrow() {
+            NodeKind::Group(group) => vec![
+                (group.opacity.get() < 1.0).then_some("group opacity"),
+                (group.blend_mode != usvg::BlendMode::Normal).then_some("blend mode"),
+                group.clip_path.is_some().then_some("clip path"),
+                group.mask.is_some().then_some("mask"),
+                (!group.filters.is_empty()).then_some("filter"),
+            ],
+            NodeKind::Path(path) => {
+                let mut found = Vec::new();
+                if let Some(fill) = &path.fill {
+                    found.push(paint(&fill.paint, ["linear gradient fill", "radial gradient fill", "pattern fill"]));
+                    found.push((fill.opacity.get() < 1.0).then_some("fill opacity"));
+                    found.push((fill.rule == usvg::FillRule::EvenOdd).then_some("even-odd fill rule"));
+                }
+                if let Some(stroke) = &path.stroke {
+                    found.push(paint(&stroke.paint, ["linear gradient stroke", "radial gradient stroke", "pattern stroke"]));
+                    found.push((stroke.opacity.get() < 1.0).then_some("stroke opacity"));
+                    found.push(stroke.dasharray.is_some().then_some("stroke dash"));
+                }
+                found
+            }
+            NodeKind::Image(_) => vec![Some("embedded image")],
+            NodeKind::Text(_) => vec![Some("text element")],
+        };
+        for feature in found.into_iter().flatten() {
+            if !features.contains(&feature) {
+                features.push(feature);
+            }
+        }
+    }
+    features
+}
+
 /// Apply a usvg transform to PDF content
 fn apply_transform(transform: &usvg::Transform, content: &mut Content) {
     // usvg transform is [a, b, c, d, e, f] which maps to:
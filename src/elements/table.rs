@@ -0,0 +1,381 @@
+//! Table layout and pagination.
+//!
+//! Cell text wraps to its column width, less padding, against the row's
+//! font chain, and a row is as tall as its tallest cell. Row heights depend
+//! on font metrics, so pagination runs once resources are loaded: a table
+//! that doesn't fit its page keeps the rows that do, and the rest move to a
+//! continuation page inserted right after it, headed by the header rows.
+//! Rows are never split between pages. A table inside a group or fragment
+//! is placed in its container's coordinates, so it can't continue onto
+//! another page; one that would need to is an error.
+
+use crate::error::{Result, RupdfError};
+use crate::resources::{LoadedFont, LoadedResources};
+use crate::runs::{self, EmojiFallback, ResolvedChar};
+use crate::types::{Document, Element, ElementLabel, Page, TableElement};
+
+/// Slack allowed when fitting rows, to absorb float rounding.
+const TOLERANCE: f32 = 0.01;
+
+/// Fonts and aliases for the cells of `row`: the row font plus fallbacks.
+pub fn row_chain<'a>(
+    table: &'a TableElement,
+    row: usize,
+    resources: &'a LoadedResources,
+) -> Result<(Vec<&'a LoadedFont>, Vec<&'a str>)> {
+    let primary = table.row_font(row);
+    let mut fonts = vec![resources.get_font(primary)?];
+    let mut names = vec![primary];
+    for alias in &table.font_fallback {
+        fonts.push(resources.get_font(alias)?);
+        names.push(alias.as_str());
+    }
+    Ok((fonts, names))
+}
+
+/// Wrapped lines of one cell.
+fn cell_lines(
+    table: &TableElement,
+    row: usize,
+    column: usize,
+    fonts: &[&LoadedFont],
+    names: &[&str],
+) -> Result<Vec<Vec<ResolvedChar>>> {
+    let width = table.column_widths[column] - 2.0 * table.padding;
+    runs::wrap(
+        &table.rows[row][column],
+        &[],
+        fonts,
+        names,
+        &[],
+        table.size,
+        width,
+        table.missing_glyph_policy,
+        &EmojiFallback::default(),
+    )
+}
+
+/// Offset of a cell's first baseline below the top of its row: the line's
+/// ascender-to-descender box is centered in the first line slot.
+pub fn first_baseline(table: &TableElement, primary: &LoadedFont) -> f32 {
+    let ascender = primary.ascender_pts(table.size);
+    let descender = primary.descender_pts(table.size).abs();
+    table.padding + (table.line_height + ascender - descender) / 2.0
+}
+
+/// One row's wrapped cells and the height they make it.
+pub struct RowLayout {
+    pub height: f32,
+    /// Wrapped lines of each cell, by column
+    pub cells: Vec<Vec<Vec<ResolvedChar>>>,
+}
+
+/// Wrap every cell of `table` and size its rows.
+pub fn layout(table: &TableElement, resources: &LoadedResources) -> Result<Vec<RowLayout>> {
+    (0..table.rows.len())
+        .map(|r| {
+            let (fonts, names) = row_chain(table, r, resources)?;
+            let cells = (0..table.column_widths.len())
+                .map(|c| cell_lines(table, r, c, &fonts, &names))
+                .collect::<Result<Vec<_>>>()?;
+            let lines = cells.iter().map(Vec::len).max().unwrap_or(0).max(1);
+            Ok(RowLayout { height: 2.0 * table.padding + lines as f32 * table.line_height, cells })
+        })
+        .collect()
+}
+
+/// Height of every row, in points.
+pub fn row_heights(table: &TableElement, resources: &LoadedResources) -> Result<Vec<f32>> {
+    Ok(layout(table, resources)?.iter().map(|row| row.height).collect())
+}
+
+/// Total height, including the continuation caption.
+pub fn height(table: &TableElement, resources: &LoadedResources) -> Result<f32> {
+    Ok(table.caption_height() + row_heights(table, resources)?.iter().sum::<f32>())
+}
+
+/// Space kept clear below the table on the page it starts on: the page's
+/// bottom margin, or `continued_y` when the page sets none.
+fn bottom_margin(table: &TableElement) -> f32 {
+    if table.page_margin_bottom > 0.0 {
+        table.page_margin_bottom
+    } else {
+        table.continued_y.unwrap_or(0.0)
+    }
+}
+
+/// Space kept clear below the table on continuation pages: `continued_y`,
+/// mirroring their top margin, or the first page's bottom margin.
+fn continued_bottom_margin(table: &TableElement) -> f32 {
+    table.continued_y.unwrap_or_else(|| bottom_margin(table))
+}
+
+/// Room for a table whose top is at `y` on a page `page_height` tall,
+/// keeping `bottom` clear below it.
+fn available(table: &TableElement, y: f32, bottom: f32, page_height: f32) -> f32 {
+    table.max_height.unwrap_or(page_height - y - bottom)
+}
+
+/// Fail if a body row could not fit even on a continuation page of its own.
+fn check_rows(table: &TableElement, heights: &[f32], page_height: f32) -> Result<()> {
+    let header: f32 = heights[..table.header_rows].iter().sum();
+    let caption = if table.continued_caption.is_some() { table.line_height } else { 0.0 };
+    let y = table.continued_y.unwrap_or(table.y);
+    let room = available(table, y, continued_bottom_margin(table), page_height) - caption - header;
+    for (r, &h) in heights.iter().enumerate().skip(table.header_rows) {
+        if h > room + TOLERANCE {
+            return Err(RupdfError::InvalidDocument(format!(
+                "table row {} is {:.1} pt tall but a page has room for {:.1} pt below the header",
+                r, h, room.max(0.0)
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Keep the rows of `table` that fit its page, with `bottom` clear below
+/// them, and return the rest as the next part, or `None` if the whole table
+/// fits.
+fn split(table: &mut TableElement, heights: &[f32], bottom: f32, page_height: f32) -> Option<TableElement> {
+    let header: f32 = heights[..table.header_rows].iter().sum();
+    let room = available(table, table.y, bottom, page_height) - table.caption_height() - header;
+    let mut used = 0.0;
+    let mut fit = table.header_rows;
+    while fit < heights.len() && used + heights[fit] <= room + TOLERANCE {
+        used += heights[fit];
+        fit += 1;
+    }
+    if fit == heights.len() {
+        return None;
+    }
+
+    let mut rest = table.clone();
+    rest.rows = table.rows[..table.header_rows].iter().chain(&table.rows[fit..]).cloned().collect();
+    rest.y = table.continued_y.unwrap_or(table.y);
+    if fit == table.header_rows {
+        // Not even one body row fits: start the whole table on the next
+        // page rather than leave its header stranded here.
+        rest.continuation = table.continuation;
+        table.rows.clear();
+        table.header_rows = 0;
+    } else {
+        rest.continuation = true;
+        table.rows.truncate(fit);
+    }
+    Some(rest)
+}
+
+/// Fail if a visible table among `elements`, which sit in a group or
+/// fragment, doesn't fit its page.
+fn check_nested(elements: &[Element], resources: &LoadedResources, page_height: f32) -> Result<()> {
+    for (index, element) in elements.iter().enumerate().filter(|(_, e)| e.visible()) {
+        let children = match element {
+            Element::Group(g) => &g.elements,
+            Element::Fragment(f) => &f.fragment.elements,
+            Element::Table(table) => {
+                let heights = row_heights(table, resources)?;
+                if split(&mut table.clone(), &heights, bottom_margin(table), page_height).is_some() {
+                    return Err(RupdfError::InvalidDocument(format!(
+                        "table {} doesn't fit on its page, and a table inside a group or fragment \
+                         can't continue onto another; place it directly on the page",
+                        element.label(index)
+                    )));
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        check_nested(children, resources, page_height)?;
+    }
+    Ok(())
+}
+
+/// Split every page table that overflows its page, inserting continuation
+/// pages (same size and background) after the page it starts on.
+pub fn paginate(doc: &mut Document, resources: &LoadedResources) -> Result<()> {
+    let pages = std::mem::take(&mut doc.pages);
+    for (page_index, mut page) in pages.into_iter().enumerate() {
+        let mut parts = Vec::new();
+        for (element_index, element) in page.elements.iter_mut().enumerate() {
+            if let (Element::Group(_) | Element::Fragment(_), true) = (&*element, element.visible()) {
+                check_nested(std::slice::from_ref(&*element), resources, page.height).map_err(|e| {
+                    RupdfError::InvalidDocument(format!(
+                        "Page {}, element {}: {}",
+                        page_index,
+                        element.label(element_index),
+                        e
+                    ))
+                })?;
+                continue;
+            }
+            let Element::Table(table) = element else { continue };
+            // A hidden table adds no continuation pages
+            if !table.visible {
+                continue;
+            }
+            let label = ElementLabel { index: element_index, id: table.id.as_deref() };
+            let heights = row_heights(table, resources)
+                .map_err(|e| e.in_element(format!("Page {}, element {}", page_index, label)))?;
+            check_rows(table, &heights, page.height).map_err(|e| {
+                RupdfError::InvalidDocument(format!("Page {}, element {}: {}", page_index, label, e))
+            })?;
+            let continued_bottom = continued_bottom_margin(table);
+            let mut next = split(table, &heights, bottom_margin(table), page.height);
+            while let Some(mut part) = next {
+                let heights = row_heights(&part, resources)?;
+                next = split(&mut part, &heights, continued_bottom, page.height);
+                parts.push(part);
+            }
+        }
+        if page.repeat > 1 && !parts.is_empty() {
+            return Err(RupdfError::InvalidDocument(format!(
+                "Page {}: repeat can't be used on a page whose table continues onto further pages",
+                page_index
+            )));
+        }
+        let (width, height, background, source) = (page.width, page.height, page.background.clone(), page.source);
+        doc.pages.push(page);
+        doc.pages.extend(parts.into_iter().map(|part| Page {
+            width,
+            height,
+            background: background.clone(),
+            repeat: 1,
+            elements: vec![Element::Table(part)],
+            source,
+        }));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Color, MissingGlyphPolicy, PageBackground, Resources, Tagging};
+    use crate::units::{Origin, Units};
+
+    fn table(rows: usize, header_rows: usize) -> TableElement {
+        TableElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 50.0,
+            y: 50.0,
+            column_widths: vec![100.0, 100.0],
+            rows: (0..rows).map(|r| vec![format!("r{}", r), "x".to_string()]).collect(),
+            header_rows,
+            font: "missing".to_string(),
+            header_font: None,
+            font_fallback: Vec::new(),
+            missing_glyph_policy: MissingGlyphPolicy::Drop,
+            size: 10.0,
+            line_height: 12.0,
+            color: Color::black(),
+            padding: 4.0,
+            border: 0.5,
+            border_color: Color::black(),
+            header_fill: None,
+            max_height: Some(100.0),
+            continued_y: None,
+            page_margin_bottom: 0.0,
+            continued_caption: None,
+            continuation: false,
+        }
+    }
+
+    #[test]
+    fn split_keeps_whole_rows_and_repeats_header() {
+        // Header plus four 20 pt rows fit in 100 pt; the rest continue.
+        let mut t = table(10, 1);
+        let rest = split(&mut t, &[20.0; 10], 0.0, 792.0).unwrap();
+        assert_eq!(t.rows.len(), 5);
+        assert_eq!(rest.rows.len(), 6);
+        assert_eq!(rest.rows[0][0], "r0");
+        assert_eq!(rest.rows[1][0], "r5");
+        assert!(rest.continuation);
+    }
+
+    #[test]
+    fn split_returns_none_when_table_fits() {
+        let mut t = table(5, 1);
+        assert!(split(&mut t, &[20.0; 5], 0.0, 792.0).is_none());
+        assert_eq!(t.rows.len(), 5);
+    }
+
+    #[test]
+    fn caption_takes_room_on_continuations() {
+        let mut t = table(10, 1);
+        t.continued_caption = Some("(continued)".to_string());
+        t.continuation = true;
+        // 100 - 12 (caption) - 20 (header) leaves room for three rows
+        split(&mut t, &[20.0; 10], 0.0, 792.0).unwrap();
+        assert_eq!(t.rows.len(), 4);
+    }
+
+    #[test]
+    fn table_moves_whole_when_no_row_fits() {
+        let mut t = table(3, 1);
+        let rest = split(&mut t, &[20.0, 90.0, 20.0], 0.0, 792.0).unwrap();
+        assert!(t.rows.is_empty());
+        assert_eq!(rest.rows.len(), 3);
+        assert!(!rest.continuation);
+    }
+
+    #[test]
+    fn oversized_row_names_its_index() {
+        let t = table(3, 1);
+        let err = check_rows(&t, &[20.0, 20.0, 200.0], 792.0).unwrap_err().to_string();
+        assert!(err.contains("table row 2"), "{}", err);
+    }
+
+    #[test]
+    fn default_room_runs_to_bottom_margin() {
+        let mut t = table(1, 0);
+        t.max_height = None;
+        assert_eq!(available(&t, 50.0, bottom_margin(&t), 792.0), 742.0);
+        t.continued_y = Some(72.0);
+        assert_eq!(available(&t, 50.0, bottom_margin(&t), 792.0), 670.0);
+        t.page_margin_bottom = 36.0;
+        assert_eq!(available(&t, 50.0, bottom_margin(&t), 792.0), 706.0);
+        // Continuation pages mirror continued_y
+        assert_eq!(available(&t, 72.0, continued_bottom_margin(&t), 792.0), 648.0);
+    }
+
+    #[test]
+    fn table_in_lower_half_keeps_its_rows() {
+        let mut t = table(5, 1);
+        t.max_height = None;
+        t.y = 450.0;
+        check_rows(&t, &[20.0; 5], 792.0).unwrap();
+        let bottom = bottom_margin(&t);
+        assert!(split(&mut t, &[20.0; 5], bottom, 792.0).is_none());
+        assert_eq!(t.rows.len(), 5);
+    }
+
+    #[test]
+    fn paginate_without_tables_leaves_pages_alone() {
+        let mut doc = Document {
+            metadata: Default::default(),
+            pages: vec![Page {
+                width: 612.0,
+                height: 792.0,
+                background: PageBackground::Color(Color::white()),
+                repeat: 1,
+                elements: Vec::new(),
+                source: 0,
+            }],
+            repeating: Vec::new(),
+            layers: Vec::new(),
+            open_action: None,
+            origin: Origin::TopLeft,
+            units: Units::default(),
+            watermark: None,
+            header: None,
+            footer: None,
+            resources: Resources::default(),
+        };
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        paginate(&mut doc, &resources).unwrap();
+        assert_eq!(doc.pages.len(), 1);
+    }
+}
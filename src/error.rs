@@ -1,5 +1,7 @@
 use pyo3::exceptions::PyException;
 use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+use std::fmt;
 use thiserror::Error;
 
 /// All errors raised by rupdf
@@ -8,10 +10,14 @@ pub enum RupdfError {
     #[error("Missing font: '{0}'")]
     MissingFont(String),
 
+    /// A missing font that elements only inherit from `defaults`
+    #[error("Missing font: '{0}' (from defaults)")]
+    MissingDefaultFont(String),
+
     #[error("Missing image: '{0}'")]
     MissingImage(String),
 
-    #[error("Missing glyph '{glyph}' in font '{font}'")]
+    #[error("Missing glyph '{glyph}' in font '{font}'{}", combining_hint(.glyph))]
     MissingGlyph { glyph: char, font: String },
 
     #[error("Invalid font data for '{0}': {1}")]
@@ -26,6 +32,12 @@ pub enum RupdfError {
     #[error("Invalid barcode value '{value}': {reason}")]
     InvalidBarcode { value: String, reason: String },
 
+    #[error("QR payload too long: {length} {unit} exceeds the maximum of {max} {unit} at error correction level {level}")]
+    QrCapacityExceeded { length: usize, max: usize, unit: &'static str, level: char },
+
+    #[error("Element out of bounds: {0}")]
+    OutOfBounds(String),
+
     #[error("Unknown element type: '{0}'")]
     UnknownElementType(String),
 
@@ -40,13 +52,106 @@ pub enum RupdfError {
 
     #[error("PDF generation error: {0}")]
     PdfError(String),
+
+    /// An error raised while drawing one element, located by page and
+    /// element (with its id, when it has one)
+    #[error("{location}: {source}")]
+    InElement { location: String, source: Box<RupdfError> },
+
+    /// Every error found by a collecting parse (`collect_errors`), in
+    /// document order
+    #[error("{}", ParseError::summary(.0))]
+    Multiple(Vec<ParseError>),
+}
+
+/// One error found by a collecting parse, located by page and element
+/// index where it has one. Errors in `repeating_elements` have an element
+/// but no page.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub page: Option<usize>,
+    pub element: Option<usize>,
+    /// The element's `id`, if it has one
+    pub id: Option<String>,
+    /// The error, prefixed with its page where it has one
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl ParseError {
+    fn summary(errors: &[ParseError]) -> String {
+        match errors {
+            [error] => error.to_string(),
+            _ => {
+                let mut out = format!("{} errors in the document:", errors.len());
+                for error in errors {
+                    out.push_str("\n  ");
+                    out.push_str(&error.to_string());
+                }
+                out
+            }
+        }
+    }
+
+    pub fn to_py<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        dict.set_item("page", self.page)?;
+        dict.set_item("element", self.element)?;
+        dict.set_item("id", &self.id)?;
+        dict.set_item("message", &self.message)?;
+        Ok(dict)
+    }
+}
+
+impl RupdfError {
+    /// Prefix with the element the error came from, e.g. "Page 2, element
+    /// 5 (id 'total')". An error already located keeps its location.
+    pub fn in_element(self, location: impl std::fmt::Display) -> Self {
+        match self {
+            RupdfError::InElement { .. } => self,
+            _ => RupdfError::InElement { location: location.to_string(), source: Box::new(self) },
+        }
+    }
+}
+
+/// Why a missing combining mark wasn't drawn from a precomposed character
+fn combining_hint(glyph: &char) -> String {
+    if !unicode_normalization::char::is_combining_mark(*glyph) {
+        return String::new();
+    }
+    format!(
+        " (U+{:04X} is a combining mark with no precomposed form for the character before it; \
+         use a font that has the mark, or check normalize_text is on)",
+        *glyph as u32
+    )
 }
 
 pyo3::create_exception!(rupdf, PyRupdfError, PyException);
 
 impl From<RupdfError> for PyErr {
     fn from(err: RupdfError) -> PyErr {
-        PyRupdfError::new_err(err.to_string())
+        PyErr::from(&err)
+    }
+}
+
+impl From<&RupdfError> for PyErr {
+    fn from(err: &RupdfError) -> PyErr {
+        let py_err = PyRupdfError::new_err(err.to_string());
+        if let RupdfError::Multiple(errors) = err {
+            // The entries go on the exception as `errors`, one dict each
+            Python::attach(|py| {
+                let entries = errors.iter().map(|e| e.to_py(py)).collect::<PyResult<Vec<_>>>();
+                if let Ok(list) = entries.and_then(|entries| PyList::new(py, entries)) {
+                    let _ = py_err.value(py).setattr("errors", list);
+                }
+            });
+        }
+        py_err
     }
 }
 
@@ -68,6 +173,12 @@ mod tests {
         assert_eq!(err.to_string(), "Missing glyph '中' in font 'Arial'");
     }
 
+    #[test]
+    fn test_error_display_missing_combining_mark() {
+        let err = RupdfError::MissingGlyph { glyph: '\u{301}', font: "Arial".to_string() };
+        assert!(err.to_string().contains("U+0301 is a combining mark"), "{}", err);
+    }
+
     #[test]
     fn test_error_display_invalid_page_size() {
         let err = RupdfError::InvalidPageSize { width: -10.0, height: 792.0 };
@@ -83,9 +194,34 @@ mod tests {
         assert!(err.to_string().contains("null bytes not allowed"));
     }
 
+    #[test]
+    fn test_error_display_in_element() {
+        let err = RupdfError::MissingGlyph { glyph: '中', font: "Arial".to_string() }
+            .in_element("Page 0, element 3 (id 'total')")
+            .in_element("Page 0, element 1");
+        assert_eq!(err.to_string(), "Page 0, element 3 (id 'total'): Missing glyph '中' in font 'Arial'");
+    }
+
     #[test]
     fn test_error_display_unknown_element() {
         let err = RupdfError::UnknownElementType("circle".to_string());
         assert_eq!(err.to_string(), "Unknown element type: 'circle'");
     }
+
+    #[test]
+    fn test_error_display_multiple() {
+        let error = |page, element, message: &str| ParseError {
+            page,
+            element,
+            id: None,
+            message: message.to_string(),
+        };
+        let one = RupdfError::Multiple(vec![error(Some(1), Some(0), "Page 1: Element 0: bad")]);
+        assert_eq!(one.to_string(), "Page 1: Element 0: bad");
+        let two = RupdfError::Multiple(vec![
+            error(Some(0), Some(2), "Page 0: Element 2: bad"),
+            error(None, None, "fragments.logo: bad"),
+        ]);
+        assert_eq!(two.to_string(), "2 errors in the document:\n  Page 0: Element 2: bad\n  fragments.logo: bad");
+    }
 }
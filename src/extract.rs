@@ -0,0 +1,926 @@
+//! Reading rupdf's own output back, for tests and QA.
+//!
+//! `extract` is not a general PDF reader. It understands what this crate
+//! writes: a classic cross-reference layout, direct stream lengths,
+//! unfiltered or Flate-compressed streams, and Type0 fonts with
+//! Identity-H encoding and a ToUnicode CMap. From each page's content,
+//! including the Form XObjects it draws, it collects the text shown with
+//! its position, font and Unicode, and the rects and straight lines
+//! painted. Clipping paths are left out.
+//!
+//! Positions are in the document's own coordinates: points from the
+//! page's top-left corner, y down.
+
+use crate::error::{Result, RupdfError};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+use std::collections::HashMap;
+
+/// Text shown by one text-showing operator
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextShow {
+    pub text: String,
+    /// The font's BaseFont name
+    pub font: String,
+    pub size: f32,
+    /// Start of the baseline
+    pub x: f32,
+    pub y: f32,
+    /// Horizontal advance of the shown glyphs, spacing included
+    pub width: f32,
+    /// Fill color components at the time, empty for a pattern
+    pub color: Vec<f32>,
+}
+
+/// A painted rect, as its bounding box on the page
+#[derive(Debug, Clone, PartialEq)]
+pub struct RectPaint {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+    /// Fill color components if filled, empty for a pattern
+    pub fill: Option<Vec<f32>>,
+    /// Stroke color components if stroked
+    pub stroke: Option<Vec<f32>>,
+}
+
+/// A stroked straight segment
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinePaint {
+    pub x1: f32,
+    pub y1: f32,
+    pub x2: f32,
+    pub y2: f32,
+    pub width: f32,
+    pub color: Vec<f32>,
+}
+
+/// What one page draws
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PageContent {
+    pub width: f32,
+    pub height: f32,
+    pub text: Vec<TextShow>,
+    pub rects: Vec<RectPaint>,
+    pub lines: Vec<LinePaint>,
+}
+
+/// Read the pages of a PDF written by rupdf.
+pub fn extract(pdf: &[u8]) -> Result<Vec<PageContent>> {
+    let reader = Reader::parse(pdf)?;
+    reader.pages()?.into_iter().map(|page| reader.page_content(page)).collect()
+}
+
+pub(crate) fn err(message: impl std::fmt::Display) -> RupdfError {
+    RupdfError::PdfError(format!("can't read PDF: {}", message))
+}
+
+/// A PDF object. Numbers are kept as f32, which covers everything rupdf
+/// writes.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Obj {
+    Null,
+    Bool(bool),
+    Num(f32),
+    Name(String),
+    Str(Vec<u8>),
+    Array(Vec<Obj>),
+    Dict(HashMap<String, Obj>),
+    Ref(u32),
+}
+
+impl Obj {
+    pub(crate) fn get(&self, key: &str) -> Option<&Obj> {
+        match self {
+            Obj::Dict(dict) => dict.get(key),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_name(&self) -> Option<&str> {
+        match self {
+            Obj::Name(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_num(&self) -> Option<f32> {
+        match self {
+            Obj::Num(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Token {
+    Num(f32),
+    Name(String),
+    Str(Vec<u8>),
+    ArrayOpen,
+    ArrayClose,
+    DictOpen,
+    DictClose,
+    /// A keyword or content stream operator
+    Word(String),
+}
+
+fn is_delimiter(b: u8) -> bool {
+    matches!(b, b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%')
+}
+
+pub(crate) struct Lexer<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Lexer<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn skip_space(&mut self) {
+        while let Some(&b) = self.data.get(self.pos) {
+            if b == b'%' {
+                while self.data.get(self.pos).is_some_and(|&b| b != b'\n' && b != b'\r') {
+                    self.pos += 1;
+                }
+            } else if b.is_ascii_whitespace() || b == 0 {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn regular(&mut self) -> &'a [u8] {
+        let start = self.pos;
+        while self.data.get(self.pos).is_some_and(|&b| !b.is_ascii_whitespace() && b != 0 && !is_delimiter(b)) {
+            self.pos += 1;
+        }
+        &self.data[start..self.pos]
+    }
+
+    pub(crate) fn next_token(&mut self) -> Result<Option<Token>> {
+        self.skip_space();
+        let Some(&b) = self.data.get(self.pos) else {
+            return Ok(None);
+        };
+        let token = match b {
+            b'/' => {
+                self.pos += 1;
+                let raw = self.regular();
+                let mut name = Vec::with_capacity(raw.len());
+                let mut i = 0;
+                while i < raw.len() {
+                    match (raw[i], raw.get(i + 1..i + 3)) {
+                        (b'#', Some(hex)) => {
+                            let hex = std::str::from_utf8(hex).ok().and_then(|h| u8::from_str_radix(h, 16).ok());
+                            name.push(hex.ok_or_else(|| err("bad name escape"))?);
+                            i += 3;
+                        }
+                        (c, _) => {
+                            name.push(c);
+                            i += 1;
+                        }
+                    }
+                }
+                Token::Name(String::from_utf8_lossy(&name).into_owned())
+            }
+            b'(' => Token::Str(self.literal_string()?),
+            b'<' if self.data.get(self.pos + 1) == Some(&b'<') => {
+                self.pos += 2;
+                Token::DictOpen
+            }
+            b'<' => {
+                self.pos += 1;
+                let end = self.data[self.pos..].iter().position(|&b| b == b'>').ok_or_else(|| err("unterminated hex string"))?;
+                let mut digits: Vec<u8> = self.data[self.pos..self.pos + end]
+                    .iter()
+                    .filter(|b| b.is_ascii_hexdigit())
+                    .copied()
+                    .collect();
+                if digits.len() % 2 == 1 {
+                    digits.push(b'0');
+                }
+                self.pos += end + 1;
+                let bytes = digits
+                    .chunks(2)
+                    .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap_or("00"), 16).unwrap_or(0))
+                    .collect();
+                Token::Str(bytes)
+            }
+            b'>' if self.data.get(self.pos + 1) == Some(&b'>') => {
+                self.pos += 2;
+                Token::DictClose
+            }
+            b'[' => {
+                self.pos += 1;
+                Token::ArrayOpen
+            }
+            b']' => {
+                self.pos += 1;
+                Token::ArrayClose
+            }
+            _ if is_delimiter(b) => return Err(err(format!("unexpected '{}' at byte {}", b as char, self.pos))),
+            _ => {
+                let raw = self.regular();
+                let word = String::from_utf8_lossy(raw).into_owned();
+                match word.parse::<f32>() {
+                    Ok(n) if raw[0].is_ascii_digit() || matches!(raw[0], b'+' | b'-' | b'.') => Token::Num(n),
+                    _ => Token::Word(word),
+                }
+            }
+        };
+        Ok(Some(token))
+    }
+
+    fn literal_string(&mut self) -> Result<Vec<u8>> {
+        self.pos += 1;
+        let mut out = Vec::new();
+        let mut depth = 0;
+        loop {
+            let &b = self.data.get(self.pos).ok_or_else(|| err("unterminated string"))?;
+            self.pos += 1;
+            match b {
+                b'(' => {
+                    depth += 1;
+                    out.push(b);
+                }
+                b')' if depth == 0 => return Ok(out),
+                b')' => {
+                    depth -= 1;
+                    out.push(b);
+                }
+                b'\\' => {
+                    let &e = self.data.get(self.pos).ok_or_else(|| err("unterminated string"))?;
+                    self.pos += 1;
+                    match e {
+                        b'n' => out.push(b'\n'),
+                        b'r' => out.push(b'\r'),
+                        b't' => out.push(b'\t'),
+                        b'b' => out.push(8),
+                        b'f' => out.push(12),
+                        b'\r' => {
+                            if self.data.get(self.pos) == Some(&b'\n') {
+                                self.pos += 1;
+                            }
+                        }
+                        b'\n' => {}
+                        b'0'..=b'7' => {
+                            let mut value = (e - b'0') as u32;
+                            for _ in 0..2 {
+                                match self.data.get(self.pos) {
+                                    Some(&d @ b'0'..=b'7') => {
+                                        value = value * 8 + (d - b'0') as u32;
+                                        self.pos += 1;
+                                    }
+                                    _ => break,
+                                }
+                            }
+                            out.push(value as u8);
+                        }
+                        other => out.push(other),
+                    }
+                }
+                _ => out.push(b),
+            }
+        }
+    }
+
+    /// Parse the object `first` starts. With `refs`, `n g R` reads as a
+    /// reference; content streams have none.
+    pub(crate) fn value(&mut self, first: Token, refs: bool) -> Result<Obj> {
+        Ok(match first {
+            Token::Num(n) => {
+                if refs {
+                    let save = self.pos;
+                    if let (Some(Token::Num(_)), Some(Token::Word(r))) = (self.next_token()?, self.next_token()?) {
+                        if r == "R" {
+                            return Ok(Obj::Ref(n as u32));
+                        }
+                    }
+                    self.pos = save;
+                }
+                Obj::Num(n)
+            }
+            Token::Name(name) => Obj::Name(name),
+            Token::Str(bytes) => Obj::Str(bytes),
+            Token::ArrayOpen => {
+                let mut items = Vec::new();
+                loop {
+                    match self.next_token()?.ok_or_else(|| err("unterminated array"))? {
+                        Token::ArrayClose => break,
+                        token => items.push(self.value(token, refs)?),
+                    }
+                }
+                Obj::Array(items)
+            }
+            Token::DictOpen => {
+                let mut dict = HashMap::new();
+                loop {
+                    match self.next_token()?.ok_or_else(|| err("unterminated dictionary"))? {
+                        Token::DictClose => break,
+                        Token::Name(key) => {
+                            let token = self.next_token()?.ok_or_else(|| err("unterminated dictionary"))?;
+                            dict.insert(key, self.value(token, refs)?);
+                        }
+                        other => return Err(err(format!("dictionary key expected, got {:?}", other))),
+                    }
+                }
+                Obj::Dict(dict)
+            }
+            Token::Word(word) => match word.as_str() {
+                "true" => Obj::Bool(true),
+                "false" => Obj::Bool(false),
+                "null" => Obj::Null,
+                _ => return Err(err(format!("unexpected '{}'", word))),
+            },
+            other => return Err(err(format!("unexpected {:?}", other))),
+        })
+    }
+}
+
+pub(crate) struct Object {
+    pub(crate) value: Obj,
+    pub(crate) stream: Option<Vec<u8>>,
+}
+
+pub(crate) struct Reader {
+    pub(crate) objects: HashMap<u32, Object>,
+}
+
+/// A font's widths and Unicode mapping, by two-byte code
+pub(crate) struct Font {
+    pub(crate) name: String,
+    pub(crate) widths: HashMap<u16, f32>,
+    pub(crate) default_width: f32,
+    unicode: HashMap<u16, String>,
+}
+
+pub(crate) type Matrix = [f32; 6];
+
+pub(crate) const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+/// `a` then `b`: the matrix of applying `a` first
+pub(crate) fn concat(a: &Matrix, b: &Matrix) -> Matrix {
+    [
+        a[0] * b[0] + a[1] * b[2],
+        a[0] * b[1] + a[1] * b[3],
+        a[2] * b[0] + a[3] * b[2],
+        a[2] * b[1] + a[3] * b[3],
+        a[4] * b[0] + a[5] * b[2] + b[4],
+        a[4] * b[1] + a[5] * b[3] + b[5],
+    ]
+}
+
+pub(crate) fn apply(m: &Matrix, x: f32, y: f32) -> (f32, f32) {
+    (m[0] * x + m[2] * y + m[4], m[1] * x + m[3] * y + m[5])
+}
+
+#[derive(Clone)]
+struct GraphicsState {
+    ctm: Matrix,
+    fill: Vec<f32>,
+    stroke: Vec<f32>,
+    line_width: f32,
+}
+
+enum Segment {
+    Rect(f32, f32, f32, f32),
+    Line(f32, f32, f32, f32),
+}
+
+/// Interpreter state for one page, Form XObjects included
+struct Painter<'r> {
+    reader: &'r Reader,
+    fonts: HashMap<u32, Font>,
+    /// Page top and left, to turn PDF coordinates into the document's
+    top: f32,
+    left: f32,
+    out: PageContent,
+}
+
+impl Reader {
+    pub(crate) fn parse(data: &[u8]) -> Result<Self> {
+        let mut lexer = Lexer::new(data);
+        let mut objects = HashMap::new();
+        while let Some(token) = lexer.next_token()? {
+            let id = match token {
+                Token::Num(id) => id as u32,
+                Token::Word(word) if word == "xref" => break,
+                other => return Err(err(format!("object expected, got {:?}", other))),
+            };
+            match (lexer.next_token()?, lexer.next_token()?) {
+                (Some(Token::Num(_)), Some(Token::Word(obj))) if obj == "obj" => {}
+                _ => return Err(err(format!("malformed object {}", id))),
+            }
+            let first = lexer.next_token()?.ok_or_else(|| err(format!("object {} is empty", id)))?;
+            let value = lexer.value(first, true)?;
+            lexer.skip_space();
+            let mut stream = None;
+            if data[lexer.pos..].starts_with(b"stream") {
+                lexer.pos += b"stream".len();
+                if data[lexer.pos..].starts_with(b"\r\n") {
+                    lexer.pos += 2;
+                } else if data.get(lexer.pos) == Some(&b'\n') {
+                    lexer.pos += 1;
+                }
+                let length = value
+                    .get("Length")
+                    .and_then(Obj::as_num)
+                    .ok_or_else(|| err(format!("stream {} has no direct /Length", id)))? as usize;
+                let bytes = data.get(lexer.pos..lexer.pos + length).ok_or_else(|| err(format!("stream {} is cut short", id)))?;
+                stream = Some(bytes.to_vec());
+                lexer.pos += length;
+                match lexer.next_token()? {
+                    Some(Token::Word(word)) if word == "endstream" => {}
+                    _ => return Err(err(format!("stream {} has no endstream", id))),
+                }
+            }
+            match lexer.next_token()? {
+                Some(Token::Word(word)) if word == "endobj" => {}
+                _ => return Err(err(format!("object {} has no endobj", id))),
+            }
+            objects.insert(id, Object { value, stream });
+        }
+        Ok(Self { objects })
+    }
+
+    pub(crate) fn resolve<'a>(&'a self, obj: &'a Obj) -> Result<&'a Obj> {
+        match obj {
+            Obj::Ref(id) => self.objects.get(id).map(|o| &o.value).ok_or_else(|| err(format!("missing object {}", id))),
+            _ => Ok(obj),
+        }
+    }
+
+    /// A stream's data, decompressed
+    pub(crate) fn stream(&self, obj: &Obj) -> Result<Vec<u8>> {
+        let Obj::Ref(id) = obj else {
+            return Err(err("stream expected"));
+        };
+        let object = self.objects.get(id).ok_or_else(|| err(format!("missing object {}", id)))?;
+        let data = object.stream.as_ref().ok_or_else(|| err(format!("object {} is not a stream", id)))?;
+        match object.value.get("Filter").and_then(Obj::as_name) {
+            None => Ok(data.clone()),
+            Some("FlateDecode") => miniz_oxide::inflate::decompress_to_vec_zlib(data)
+                .map_err(|e| err(format!("stream {}: {:?}", id, e))),
+            Some(other) => Err(err(format!("stream {} has unsupported filter {}", id, other))),
+        }
+    }
+
+    /// The page dictionaries, in order
+    pub(crate) fn pages(&self) -> Result<Vec<&Obj>> {
+        let catalog = self
+            .objects
+            .values()
+            .find(|o| o.value.get("Type").and_then(Obj::as_name) == Some("Catalog"))
+            .ok_or_else(|| err("no catalog"))?;
+        let mut pages = Vec::new();
+        self.collect_pages(catalog.value.get("Pages").ok_or_else(|| err("catalog has no /Pages"))?, &mut pages)?;
+        Ok(pages)
+    }
+
+    /// A page's /MediaBox as left, bottom, right, top
+    pub(crate) fn media_box(&self, page: &Obj) -> Result<[f32; 4]> {
+        let media_box: Vec<f32> = match page.get("MediaBox").map(|b| self.resolve(b)).transpose()? {
+            Some(Obj::Array(items)) => items.iter().filter_map(Obj::as_num).collect(),
+            _ => return Err(err("page has no /MediaBox")),
+        };
+        media_box[..].try_into().map_err(|_| err("malformed /MediaBox"))
+    }
+
+    /// The page dictionaries under a page tree node, in order
+    fn collect_pages<'a>(&'a self, node: &'a Obj, pages: &mut Vec<&'a Obj>) -> Result<()> {
+        let dict = self.resolve(node)?;
+        match dict.get("Type").and_then(Obj::as_name) {
+            Some("Page") => pages.push(dict),
+            _ => {
+                if let Some(Obj::Array(kids)) = dict.get("Kids").map(|k| self.resolve(k)).transpose()? {
+                    for kid in kids {
+                        self.collect_pages(kid, pages)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn page_content(&self, page: &Obj) -> Result<PageContent> {
+        let [left, bottom, right, top] = self.media_box(page)?;
+        let mut painter = Painter {
+            reader: self,
+            fonts: HashMap::new(),
+            top,
+            left,
+            out: PageContent { width: right - left, height: top - bottom, ..PageContent::default() },
+        };
+        let resources = match page.get("Resources") {
+            Some(resources) => self.resolve(resources)?.clone(),
+            None => Obj::Null,
+        };
+        let state = GraphicsState { ctm: IDENTITY, fill: vec![0.0], stroke: vec![0.0], line_width: 1.0 };
+        let contents: Vec<&Obj> = match page.get("Contents") {
+            Some(Obj::Array(streams)) => streams.iter().collect(),
+            Some(stream) => vec![stream],
+            None => Vec::new(),
+        };
+        for stream in contents {
+            painter.run(&self.stream(stream)?, &resources, state.clone())?;
+        }
+        Ok(painter.out)
+    }
+
+    pub(crate) fn load_font(&self, obj: &Obj) -> Result<Font> {
+        let dict = self.resolve(obj)?;
+        let name = dict.get("BaseFont").and_then(Obj::as_name).unwrap_or_default().to_string();
+        let mut font = Font { name, widths: HashMap::new(), default_width: 1000.0, unicode: HashMap::new() };
+        if let Some(Obj::Array(descendants)) = dict.get("DescendantFonts").map(|d| self.resolve(d)).transpose()? {
+            if let Some(cid_font) = descendants.first() {
+                let cid_font = self.resolve(cid_font)?;
+                if let Some(dw) = cid_font.get("DW").and_then(Obj::as_num) {
+                    font.default_width = dw;
+                }
+                if let Some(Obj::Array(w)) = cid_font.get("W").map(|w| self.resolve(w)).transpose()? {
+                    let mut i = 0;
+                    while i < w.len() {
+                        match (w[i].as_num(), w.get(i + 1), w.get(i + 2).and_then(Obj::as_num)) {
+                            (Some(first), Some(Obj::Array(widths)), _) => {
+                                for (k, width) in widths.iter().filter_map(Obj::as_num).enumerate() {
+                                    font.widths.insert(first as u16 + k as u16, width);
+                                }
+                                i += 2;
+                            }
+                            (Some(first), Some(Obj::Num(last)), Some(width)) => {
+                                for code in first as u16..=*last as u16 {
+                                    font.widths.insert(code, width);
+                                }
+                                i += 3;
+                            }
+                            _ => return Err(err(format!("malformed /W in font {}", font.name))),
+                        }
+                    }
+                }
+            }
+        }
+        if let Some(cmap) = dict.get("ToUnicode") {
+            font.unicode = parse_to_unicode(&self.stream(cmap)?)?;
+        }
+        Ok(font)
+    }
+}
+
+pub(crate) fn code(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0, |code, &b| (code << 8) | b as u16)
+}
+
+fn utf16(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes.chunks(2).map(code).collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// The `bfchar` and `bfrange` mappings of a ToUnicode CMap
+fn parse_to_unicode(data: &[u8]) -> Result<HashMap<u16, String>> {
+    let mut lexer = Lexer::new(data);
+    let mut map = HashMap::new();
+    let mut operands = Vec::new();
+    while let Some(token) = lexer.next_token()? {
+        match token {
+            Token::Word(word) if word == "endbfchar" => {
+                for pair in operands.chunks(2) {
+                    if let [Obj::Str(src), Obj::Str(dst)] = pair {
+                        map.insert(code(src), utf16(dst));
+                    }
+                }
+                operands.clear();
+            }
+            Token::Word(word) if word == "endbfrange" => {
+                for range in operands.chunks(3) {
+                    match range {
+                        [Obj::Str(lo), Obj::Str(hi), Obj::Str(dst)] => {
+                            let base = code(dst);
+                            let prefix = &dst[..dst.len().saturating_sub(2)];
+                            for (k, src) in (code(lo)..=code(hi)).enumerate() {
+                                let mut unit = prefix.to_vec();
+                                unit.extend_from_slice(&(base + k as u16).to_be_bytes());
+                                map.insert(src, utf16(&unit));
+                            }
+                        }
+                        [Obj::Str(lo), Obj::Str(_), Obj::Array(dsts)] => {
+                            for (k, dst) in dsts.iter().enumerate() {
+                                if let Obj::Str(dst) = dst {
+                                    map.insert(code(lo) + k as u16, utf16(dst));
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                operands.clear();
+            }
+            Token::Word(_) => operands.clear(),
+            token => operands.push(lexer.value(token, false)?),
+        }
+    }
+    Ok(map)
+}
+
+impl Painter<'_> {
+    fn to_page(&self, ctm: &Matrix, x: f32, y: f32) -> (f32, f32) {
+        let (x, y) = apply(ctm, x, y);
+        (x - self.left, self.top - y)
+    }
+
+    fn font(&mut self, resources: &Obj, name: &str) -> Result<u32> {
+        let fonts = match resources.get("Font") {
+            Some(fonts) => self.reader.resolve(fonts)?,
+            None => return Err(err(format!("no font resources for /{}", name))),
+        };
+        let Some(Obj::Ref(id)) = fonts.get(name) else {
+            return Err(err(format!("unknown font /{}", name)));
+        };
+        if !self.fonts.contains_key(id) {
+            let font = self.reader.load_font(&Obj::Ref(*id))?;
+            self.fonts.insert(*id, font);
+        }
+        Ok(*id)
+    }
+
+    /// Interpret a content stream drawn with `resources` from `state`.
+    fn run(&mut self, data: &[u8], resources: &Obj, state: GraphicsState) -> Result<()> {
+        let mut lexer = Lexer::new(data);
+        let mut operands: Vec<Obj> = Vec::new();
+        let mut stack: Vec<GraphicsState> = Vec::new();
+        let mut gs = state;
+        let mut path: Vec<Segment> = Vec::new();
+        let (mut current, mut start) = ((0.0, 0.0), (0.0, 0.0));
+        // Text state
+        let (mut tm, mut tlm) = (IDENTITY, IDENTITY);
+        let mut font: Option<u32> = None;
+        let (mut size, mut char_spacing, mut word_spacing, mut scaling, mut leading, mut rise) =
+            (0.0, 0.0, 0.0, 100.0, 0.0, 0.0);
+        while let Some(token) = lexer.next_token()? {
+            let op = match token {
+                Token::Word(op) => op,
+                token => {
+                    operands.push(lexer.value(token, false)?);
+                    continue;
+                }
+            };
+            let nums: Vec<f32> = operands.iter().filter_map(Obj::as_num).collect();
+            let num = |i: usize| nums.get(i).copied().unwrap_or(0.0);
+            match op.as_str() {
+                "q" => stack.push(gs.clone()),
+                "Q" => gs = stack.pop().ok_or_else(|| err("unbalanced Q"))?,
+                "cm" if nums.len() == 6 => gs.ctm = concat(&[num(0), num(1), num(2), num(3), num(4), num(5)], &gs.ctm),
+                "w" => gs.line_width = num(0),
+                "g" | "rg" | "k" | "sc" | "scn" => gs.fill = nums.clone(),
+                "G" | "RG" | "K" | "SC" | "SCN" => gs.stroke = nums.clone(),
+                "cs" => gs.fill = vec![0.0],
+                "CS" => gs.stroke = vec![0.0],
+                "re" => {
+                    path.push(Segment::Rect(num(0), num(1), num(2), num(3)));
+                    current = (num(0), num(1));
+                    start = current;
+                }
+                "m" => {
+                    current = (num(0), num(1));
+                    start = current;
+                }
+                "l" => {
+                    path.push(Segment::Line(current.0, current.1, num(0), num(1)));
+                    current = (num(0), num(1));
+                }
+                "c" => current = (num(4), num(5)),
+                "v" | "y" => current = (num(2), num(3)),
+                "h" => {
+                    if current != start {
+                        path.push(Segment::Line(current.0, current.1, start.0, start.1));
+                    }
+                    current = start;
+                }
+                "S" | "s" | "f" | "F" | "f*" | "B" | "B*" | "b" | "b*" | "n" => {
+                    if matches!(op.as_str(), "s" | "b" | "b*") && current != start {
+                        path.push(Segment::Line(current.0, current.1, start.0, start.1));
+                    }
+                    let fill = matches!(op.as_str(), "f" | "F" | "f*" | "B" | "B*" | "b" | "b*");
+                    let stroke = matches!(op.as_str(), "S" | "s" | "B" | "B*" | "b" | "b*");
+                    for segment in path.drain(..) {
+                        self.paint(segment, &gs, fill, stroke);
+                    }
+                }
+                "BT" => {
+                    tm = IDENTITY;
+                    tlm = IDENTITY;
+                }
+                "Tf" => {
+                    if let Some(name) = operands.first().and_then(Obj::as_name) {
+                        font = Some(self.font(resources, name)?);
+                    }
+                    size = num(0);
+                }
+                "Td" | "TD" => {
+                    if op == "TD" {
+                        leading = -num(1);
+                    }
+                    tlm = concat(&[1.0, 0.0, 0.0, 1.0, num(0), num(1)], &tlm);
+                    tm = tlm;
+                }
+                "T*" => {
+                    tlm = concat(&[1.0, 0.0, 0.0, 1.0, 0.0, -leading], &tlm);
+                    tm = tlm;
+                }
+                "Tm" if nums.len() == 6 => {
+                    tlm = [num(0), num(1), num(2), num(3), num(4), num(5)];
+                    tm = tlm;
+                }
+                "Tc" => char_spacing = num(0),
+                "Tw" => word_spacing = num(0),
+                "Tz" => scaling = num(0),
+                "TL" => leading = num(0),
+                "Ts" => rise = num(0),
+                "Tj" | "TJ" => {
+                    let Some(font_id) = font else {
+                        return Err(err(format!("{} without a font", op)));
+                    };
+                    let items: Vec<Obj> = match operands.first() {
+                        Some(Obj::Array(items)) => items.clone(),
+                        Some(item) => vec![item.clone()],
+                        None => Vec::new(),
+                    };
+                    let start_tm = concat(&[1.0, 0.0, 0.0, 1.0, 0.0, rise], &tm);
+                    let (x, y) = self.to_page(&concat(&start_tm, &gs.ctm), 0.0, 0.0);
+                    let font = &self.fonts[&font_id];
+                    let mut text = String::new();
+                    let mut advance = 0.0;
+                    for item in items {
+                        match item {
+                            Obj::Str(bytes) => {
+                                for pair in bytes.chunks(2) {
+                                    let code = code(pair);
+                                    text.push_str(font.unicode.get(&code).map_or("\u{FFFD}", String::as_str));
+                                    let width = font.widths.get(&code).copied().unwrap_or(font.default_width);
+                                    let spacing = if pair.len() == 1 && pair[0] == b' ' { word_spacing } else { 0.0 };
+                                    advance += (width / 1000.0 * size + char_spacing + spacing) * scaling / 100.0;
+                                }
+                            }
+                            Obj::Num(adjust) => advance -= adjust / 1000.0 * size * scaling / 100.0,
+                            _ => {}
+                        }
+                    }
+                    tm = concat(&[1.0, 0.0, 0.0, 1.0, advance, 0.0], &tm);
+                    let end_tm = concat(&[1.0, 0.0, 0.0, 1.0, 0.0, rise], &tm);
+                    let (end_x, _) = self.to_page(&concat(&end_tm, &gs.ctm), 0.0, 0.0);
+                    self.out.text.push(TextShow {
+                        text,
+                        font: font.name.clone(),
+                        size,
+                        x,
+                        y,
+                        width: end_x - x,
+                        color: gs.fill.clone(),
+                    });
+                }
+                "Do" => {
+                    let name = operands.first().and_then(Obj::as_name).unwrap_or_default();
+                    let xobject = resources
+                        .get("XObject")
+                        .map(|x| self.reader.resolve(x))
+                        .transpose()?
+                        .and_then(|x| x.get(name))
+                        .ok_or_else(|| err(format!("unknown XObject /{}", name)))?;
+                    let form = self.reader.resolve(xobject)?;
+                    if form.get("Subtype").and_then(Obj::as_name) == Some("Form") {
+                        let mut inner = gs.clone();
+                        if let Some(Obj::Array(m)) = form.get("Matrix") {
+                            let m: Vec<f32> = m.iter().filter_map(Obj::as_num).collect();
+                            if let [a, b, c, d, e, f] = m[..] {
+                                inner.ctm = concat(&[a, b, c, d, e, f], &gs.ctm);
+                            }
+                        }
+                        let form_resources = match form.get("Resources") {
+                            Some(r) => self.reader.resolve(r)?.clone(),
+                            None => resources.clone(),
+                        };
+                        let data = self.reader.stream(xobject)?;
+                        self.run(&data, &form_resources, inner)?;
+                    }
+                }
+                _ => {}
+            }
+            operands.clear();
+        }
+        Ok(())
+    }
+
+    fn paint(&mut self, segment: Segment, gs: &GraphicsState, fill: bool, stroke: bool) {
+        match segment {
+            Segment::Rect(x, y, w, h) => {
+                let corners = [(x, y), (x + w, y), (x, y + h), (x + w, y + h)].map(|(x, y)| self.to_page(&gs.ctm, x, y));
+                let left = corners.iter().map(|c| c.0).fold(f32::INFINITY, f32::min);
+                let right = corners.iter().map(|c| c.0).fold(f32::NEG_INFINITY, f32::max);
+                let top = corners.iter().map(|c| c.1).fold(f32::INFINITY, f32::min);
+                let bottom = corners.iter().map(|c| c.1).fold(f32::NEG_INFINITY, f32::max);
+                if fill || stroke {
+                    self.out.rects.push(RectPaint {
+                        x: left,
+                        y: top,
+                        w: right - left,
+                        h: bottom - top,
+                        fill: fill.then(|| gs.fill.clone()),
+                        stroke: stroke.then(|| gs.stroke.clone()),
+                    });
+                }
+            }
+            Segment::Line(x1, y1, x2, y2) if stroke => {
+                let (x1, y1) = self.to_page(&gs.ctm, x1, y1);
+                let (x2, y2) = self.to_page(&gs.ctm, x2, y2);
+                let scale = (gs.ctm[0] * gs.ctm[3] - gs.ctm[1] * gs.ctm[2]).abs().sqrt();
+                self.out.lines.push(LinePaint { x1, y1, x2, y2, width: gs.line_width * scale, color: gs.stroke.clone() });
+            }
+            Segment::Line(..) => {}
+        }
+    }
+}
+
+/// The pages as a Python dict; see `extract_content` in lib.rs.
+pub fn to_py<'py>(py: Python<'py>, pages: &[PageContent]) -> PyResult<Bound<'py, PyDict>> {
+    let list = PyList::empty(py);
+    for page in pages {
+        let dict = PyDict::new(py);
+        dict.set_item("width", page.width)?;
+        dict.set_item("height", page.height)?;
+        let text = PyList::empty(py);
+        for show in &page.text {
+            let item = PyDict::new(py);
+            item.set_item("text", &show.text)?;
+            item.set_item("font", &show.font)?;
+            item.set_item("size", show.size)?;
+            item.set_item("x", show.x)?;
+            item.set_item("y", show.y)?;
+            item.set_item("width", show.width)?;
+            item.set_item("color", show.color.clone())?;
+            text.append(item)?;
+        }
+        dict.set_item("text", text)?;
+        let rects = PyList::empty(py);
+        for rect in &page.rects {
+            let item = PyDict::new(py);
+            item.set_item("x", rect.x)?;
+            item.set_item("y", rect.y)?;
+            item.set_item("w", rect.w)?;
+            item.set_item("h", rect.h)?;
+            item.set_item("fill", rect.fill.clone())?;
+            item.set_item("stroke", rect.stroke.clone())?;
+            rects.append(item)?;
+        }
+        dict.set_item("rects", rects)?;
+        let lines = PyList::empty(py);
+        for line in &page.lines {
+            let item = PyDict::new(py);
+            item.set_item("x1", line.x1)?;
+            item.set_item("y1", line.y1)?;
+            item.set_item("x2", line.x2)?;
+            item.set_item("y2", line.y2)?;
+            item.set_item("width", line.width)?;
+            item.set_item("color", line.color.clone())?;
+            lines.append(item)?;
+        }
+        dict.set_item("lines", lines)?;
+        list.append(dict)?;
+    }
+    let out = PyDict::new(py);
+    out.set_item("pages", list)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn literal_strings_unescape() {
+        let mut lexer = Lexer::new(br"(a\(b\) (c) \101\n)");
+        assert_eq!(lexer.next_token().unwrap(), Some(Token::Str(b"a(b) (c) A\n".to_vec())));
+    }
+
+    #[test]
+    fn objects_resolve_references() {
+        let mut lexer = Lexer::new(b"<< /Kids [3 0 R 4 0 R] /Count 2 >>");
+        let first = lexer.next_token().unwrap().unwrap();
+        let dict = lexer.value(first, true).unwrap();
+        assert_eq!(dict.get("Kids"), Some(&Obj::Array(vec![Obj::Ref(3), Obj::Ref(4)])));
+        assert_eq!(dict.get("Count"), Some(&Obj::Num(2.0)));
+    }
+
+    #[test]
+    fn to_unicode_ranges_expand() {
+        let cmap = b"2 beginbfchar <0001> <0041> <0002> <D83DDE00> endbfchar \
+                     1 beginbfrange <0010> <0012> <0061> endbfrange";
+        let map = parse_to_unicode(cmap).unwrap();
+        assert_eq!(map[&1], "A");
+        assert_eq!(map[&2], "\u{1F600}");
+        assert_eq!(map[&0x12], "c");
+    }
+}
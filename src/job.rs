@@ -0,0 +1,181 @@
+//! Page-by-page rendering for Python.
+//!
+//! A `PdfRenderJob` renders its document on a worker thread. After each
+//! page is written the worker hands its progress over a rendezvous channel
+//! and waits, so pages are produced as the caller iterates rather than all
+//! at once. Dropping the job mid-way closes the channel, which stops the
+//! worker at its next page.
+
+use crate::bounds::BoundsCheck;
+use crate::error::{Result, RupdfError};
+use crate::pdf::{PageProgress, PdfGenerator};
+use crate::remote::RemoteAccess;
+use crate::types::Document;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+
+enum Job {
+    Running {
+        progress: Receiver<PageProgress>,
+        worker: JoinHandle<Result<Vec<u8>>>,
+    },
+    /// The PDF bytes, or the error that stopped the render, shared so it
+    /// can be raised again
+    Done(std::result::Result<Vec<u8>, Arc<RupdfError>>),
+}
+
+impl Job {
+    /// Wait for the next page; `None` once the render has ended.
+    fn next_page(&mut self) -> Option<PageProgress> {
+        let Job::Running { progress, .. } = self else { return None };
+        match progress.recv() {
+            Ok(page) => Some(page),
+            Err(_) => {
+                self.join();
+                None
+            }
+        }
+    }
+
+    /// Let the worker run to the end and keep its outcome.
+    fn join(&mut self) {
+        if matches!(self, Job::Done(_)) {
+            return;
+        }
+        let Job::Running { progress, worker } = std::mem::replace(self, Job::Done(Ok(Vec::new()))) else {
+            unreachable!("checked above")
+        };
+        while progress.recv().is_ok() {}
+        let outcome = match worker.join() {
+            Ok(result) => result,
+            Err(_) => Err(RupdfError::PdfError("render worker panicked".to_string())),
+        };
+        *self = Job::Done(outcome.map_err(Arc::new));
+    }
+}
+
+/// A document being rendered one page at a time
+///
+/// Iterating yields a dict per entry of the document's pages once its
+/// content and page objects are written: "index" (into pages), "copies"
+/// (its repeat) and "total" (the number of pages entries). result()
+/// renders whatever is left and returns the PDF bytes.
+///
+/// Args:
+///     document: A dictionary containing the document structure with pages and elements
+///     compress: Whether to compress the output (default: True)
+///     bounds_check: "off" (default), "warn" or "error" for elements that
+///         extend past the page
+///     bounds_margin: Inset from the page edges used by bounds_check, in
+///         the document's units
+///     allow_remote: Fetch "url" sources, as for render_pdf
+///     allow_raw: Render raw_content elements, as for render_pdf
+///     include_layers: Layers to draw, as for render_pdf
+///     debug_uncompressed: Deflate nothing, as for render_pdf
+///
+/// Raises:
+///     RupdfError: If the document is invalid (when the job is created), or
+///         if a page fails to render (from iteration or result())
+///
+/// Warns:
+///     RupdfWarning: For each non-fatal problem found, when the job is created
+#[pyclass(module = "rupdf._rupdf")]
+pub struct PdfRenderJob {
+    job: Mutex<Job>,
+}
+
+#[pymethods]
+impl PdfRenderJob {
+    #[new]
+    #[pyo3(signature = (document, compress = true, bounds_check = BoundsCheck::Off, bounds_margin = 0.0, allow_remote = RemoteAccess::Off, allow_raw = false, include_layers = Vec::new(), debug_uncompressed = false))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        py: Python<'_>,
+        document: &Bound<'_, PyDict>,
+        compress: bool,
+        bounds_check: BoundsCheck,
+        bounds_margin: f32,
+        allow_remote: RemoteAccess,
+        allow_raw: bool,
+        include_layers: Vec<String>,
+        debug_uncompressed: bool,
+    ) -> PyResult<Self> {
+        let mut doc = Document::from_py(document).map_err(PyErr::from)?;
+        doc.select_layers(&include_layers).map_err(PyErr::from)?;
+        doc.check_raw_allowed(allow_raw).map_err(PyErr::from)?;
+        let (resources, warnings) = py
+            .detach(|| crate::prepare(&mut doc, bounds_check, bounds_margin, allow_remote))
+            .map_err(PyErr::from)?;
+        warnings.emit(py)?;
+
+        let (sender, progress) = mpsc::sync_channel(0);
+        let worker = thread::spawn(move || {
+            PdfGenerator::new(&doc, &resources, compress).debug_uncompressed(debug_uncompressed).generate_with(|page| {
+                sender
+                    .send(page)
+                    .map_err(|_| RupdfError::PdfError("render job was dropped".to_string()))
+            })
+        });
+        Ok(Self { job: Mutex::new(Job::Running { progress, worker }) })
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__<'py>(&self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyDict>>> {
+        let next = py.detach(|| {
+            let mut job = self.lock()?;
+            match job.next_page() {
+                Some(page) => Ok(Some(page)),
+                None => match &*job {
+                    Job::Done(Err(err)) => Err(err.clone()),
+                    _ => Ok(None),
+                },
+            }
+        });
+        match next.map_err(|err| PyErr::from(&*err))? {
+            Some(page) => {
+                let status = PyDict::new(py);
+                status.set_item("index", page.index)?;
+                status.set_item("copies", page.copies)?;
+                status.set_item("total", page.total)?;
+                Ok(Some(status))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Finish rendering and return the PDF bytes
+    ///
+    /// Pages not yet iterated are rendered first. May be called more than
+    /// once; later calls return the same bytes.
+    ///
+    /// Raises:
+    ///     RupdfError: If a page failed to render
+    fn result<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        let outcome = py.detach(|| {
+            let mut job = self.lock()?;
+            job.join();
+            match &*job {
+                Job::Done(Ok(bytes)) => Ok(bytes.clone()),
+                Job::Done(Err(err)) => Err(err.clone()),
+                Job::Running { .. } => unreachable!("join leaves the job done"),
+            }
+        });
+        let bytes = outcome.map_err(|err| PyErr::from(&*err))?;
+        Ok(PyBytes::new(py, &bytes))
+    }
+}
+
+impl PdfRenderJob {
+    /// The job's state; an error once a thread panicked holding it
+    fn lock(&self) -> std::result::Result<MutexGuard<'_, Job>, Arc<RupdfError>> {
+        self.job.lock().map_err(|_| {
+            Arc::new(RupdfError::PdfError("render job failed earlier and can't be resumed".to_string()))
+        })
+    }
+}
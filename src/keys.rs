@@ -0,0 +1,243 @@
+//! The keys each part of a document accepts, for `strict_keys`.
+//!
+//! Element lists are kept honest by `ElementFields`, which asserts in
+//! debug builds that every key an element parser reads is listed here for
+//! its type. A key listed but never read only loosens strict checking.
+
+use crate::error::{Result, RupdfError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+/// Keys every element accepts: its type, id, tagging and paint options,
+/// and the repeat options read for page 0 and repeating elements
+const COMMON: &[&str] = &[
+    "type", "id", "artifact", "reading_order", "z", "visible",
+    "every_page", "only_pages", "except_pages", "under_content",
+];
+
+const TEXT_FONT: &[&str] = &[
+    "font", "family", "weight", "style", "font_fallback", "missing_glyph_policy",
+    "emoji_font", "emoji_replacement", "markup",
+];
+
+const TEXT: &[&str] = &[
+    "x", "y", "text", "size", "color", "align", "vertical_anchor", "highlight_color",
+    "highlight_padding", "transform", "fit_width", "max_width", "overflow", "min_size",
+    "skew_x", "skew_y", "rotation", "outline",
+];
+
+const TEXTBOX: &[&str] = &[
+    "x", "y", "w", "h", "text", "size", "line_height", "line_height_ratio", "color",
+    "columns", "column_gap", "balance", "box_align_x", "box_align_y", "text_align_x",
+    "text_align_y",
+];
+
+const RECT: &[&str] = &[
+    "x", "y", "w", "h", "stroke", "stroke_color", "fill_color", "fill_pattern", "gradient", "rotation", "rotation_origin",
+    "corner_radius", "corner_style", "sides", "dash", "dash_phase", "miter_limit",
+    "stroke_align", "skew_x", "skew_y", "cap", "join",
+];
+
+const LINE: &[&str] = &[
+    "x1", "y1", "x2", "y2", "stroke", "color", "dash", "dash_phase", "cap", "join", "miter_limit",
+];
+
+const POLYGON: &[&str] = &[
+    "points", "stroke", "stroke_color", "fill_color", "close", "fill_rule", "cap", "join",
+    "miter_limit",
+];
+
+const LEADER: &[&str] = &["x1", "x2", "y", "style", "font", "size", "color", "gap", "dot_size"];
+
+const PROGRESS_RING: &[&str] = &[
+    "cx", "cy", "radius", "thickness", "value", "start_angle", "track_color", "color",
+];
+
+const IMAGE: &[&str] = &[
+    "x", "y", "w", "h", "image_ref", "align", "fade", "rotation", "quad", "clip", "border_width",
+    "border_color",
+];
+
+const BARCODE: &[&str] = &[
+    "x", "y", "w", "h", "value", "human_readable", "font", "font_size", "text_overflow",
+    "text_min_size", "color", "rotation",
+];
+
+const QRCODE: &[&str] = &[
+    "x", "y", "size", "value", "color", "background", "error_correction", "version",
+    "auto_version", "min_module_size",
+];
+
+const DATAMATRIX: &[&str] = &["x", "y", "size", "value", "color", "background", "shape"];
+
+const TABLE: &[&str] = &[
+    "x", "y", "rows", "column_widths", "font", "header_font", "font_fallback",
+    "missing_glyph_policy", "size", "line_height", "line_height_ratio", "color", "border",
+    "border_color", "padding", "header_rows", "header_fill", "max_height", "continued_y",
+    "continued_caption",
+];
+
+const CHECK: &[&str] = &[
+    "x", "y", "size", "state", "stroke", "stroke_color", "mark_color", "mark_stroke",
+    "fill_color", "corner_radius",
+];
+
+const NOTE: &[&str] = &["x", "y", "contents", "title", "color", "icon", "open"];
+
+const SIGNATURE_LINE: &[&str] = &[
+    "x", "y", "w", "label", "sublabels", "font", "size", "color", "stroke", "mark",
+];
+
+const IMAGE_PLACEHOLDER: &[&str] = &[
+    "x", "y", "w", "h", "aspect", "align", "font", "label", "note", "size", "color", "fill_color",
+];
+
+const GRID: &[&str] = &[
+    "spacing", "major_every", "color", "stroke", "major_stroke", "font", "size", "label_color",
+];
+
+const GROUP: &[&str] = &["elements", "x", "y", "rotation", "scale", "transparency_group", "opacity"];
+
+const FRAGMENT: &[&str] = &["fragment_ref", "x", "y", "scale"];
+
+const RAW_CONTENT: &[&str] = &["operators", "raw_resources"];
+
+/// The keys of an element type (canonical name) beyond `COMMON`
+fn element_keys(kind: &str) -> &'static [&'static [&'static str]] {
+    match kind {
+        "text" => &[TEXT, TEXT_FONT],
+        "textbox" => &[TEXTBOX, TEXT_FONT],
+        "rect" => &[RECT],
+        "line" => &[LINE],
+        "polygon" => &[POLYGON],
+        "leader" => &[LEADER],
+        "progress_ring" => &[PROGRESS_RING],
+        "image" => &[IMAGE],
+        "barcode" | "gs1_128" => &[BARCODE],
+        "qrcode" => &[QRCODE],
+        "datamatrix" | "gs1_datamatrix" => &[DATAMATRIX],
+        "table" => &[TABLE],
+        "check" => &[CHECK],
+        "note" => &[NOTE],
+        "signature_line" => &[SIGNATURE_LINE],
+        "image_placeholder" => &[IMAGE_PLACEHOLDER],
+        "grid" => &[GRID],
+        "group" => &[GROUP],
+        "fragment" => &[FRAGMENT],
+        "raw_content" => &[RAW_CONTENT],
+        _ => &[],
+    }
+}
+
+/// Every element type, for the flat `defaults` shared by all of them
+const ELEMENT_TYPES: &[&str] = &[
+    "text", "textbox", "rect", "line", "polygon", "leader", "progress_ring", "image",
+    "barcode", "gs1_128", "qrcode", "datamatrix", "gs1_datamatrix", "table", "check",
+    "note", "signature_line", "image_placeholder", "group", "fragment", "raw_content",
+];
+
+pub const DOCUMENT: &[&str] = &[
+    "metadata", "defaults", "units", "strict_keys", "palette", "font_families", "styles", "fragments",
+    "pages", "repeating_elements", "layers", "resources", "output_intent", "open_action", "origin",
+    "normalize_text", "watermark", "header", "footer",
+];
+
+pub const PAGE: &[&str] = &["size", "landscape", "background", "repeat", "margins", "elements", "origin", "tag"];
+
+pub const REGION: &[&str] = &["elements", "offset", "skip_first"];
+
+pub const METADATA: &[&str] = &["title", "author", "subject", "creator", "creation_date"];
+
+pub const RESOURCES: &[&str] = &["fonts", "images"];
+
+pub const FONT_RESOURCE: &[&str] = &["path", "bytes", "url", "embed", "cap_height_glyphs"];
+
+pub const IMAGE_RESOURCE: &[&str] = &["path", "bytes", "url", "svg", "frame", "profile"];
+
+/// Whether elements of `kind` (a canonical type) accept `key`
+pub fn element_accepts(kind: &str, key: &str) -> bool {
+    COMMON.contains(&key) || element_keys(kind).iter().any(|keys| keys.contains(&key))
+}
+
+/// Whether any element type accepts `key`, as a flat default must
+pub fn any_element_accepts(key: &str) -> bool {
+    ELEMENT_TYPES.iter().any(|kind| element_accepts(kind, key))
+}
+
+/// Check the keys of an element of type `kind`.
+pub fn check_element(dict: &Bound<'_, PyDict>, kind: &str) -> Result<()> {
+    let mut accepted: Vec<&str> = COMMON.to_vec();
+    element_keys(kind).iter().for_each(|keys| accepted.extend_from_slice(keys));
+    check(dict, &accepted, &format!("{} elements", kind))
+}
+
+/// Reject the first key of `dict` not in `accepted`, suggesting the
+/// closest accepted key when one is near. `what` names the dict's owner.
+pub fn check(dict: &Bound<'_, PyDict>, accepted: &[&str], what: &str) -> Result<()> {
+    for key in dict.keys() {
+        let key: String = key
+            .extract()
+            .map_err(|e| RupdfError::InvalidDocument(format!("key must be a string: {}", e)))?;
+        if accepted.contains(&key.as_str()) {
+            continue;
+        }
+        let hint = match suggest(&key, accepted) {
+            Some(near) => format!("; did you mean '{}'?", near),
+            None => String::new(),
+        };
+        return Err(RupdfError::InvalidDocument(format!("unknown key '{}' for {}{}", key, what, hint)));
+    }
+    Ok(())
+}
+
+/// The accepted key closest to `key`, if a typo could explain the
+/// difference: at most 2 edits, and fewer than half the key's length.
+fn suggest<'a>(key: &str, accepted: &[&'a str]) -> Option<&'a str> {
+    accepted
+        .iter()
+        .map(|&candidate| (distance(key, candidate), candidate))
+        .filter(|&(d, _)| d <= 2 && d * 2 < key.chars().count())
+        .min_by_key(|&(d, _)| d)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance in characters
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (above + 1).min(row[j] + 1).min(diagonal + usize::from(ca != cb));
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn typos_suggest_the_nearest_key() {
+        assert_eq!(distance("colour", "color"), 1);
+        assert_eq!(distance("front_size", "font_size"), 1);
+        assert_eq!(suggest("colour", TEXT), Some("color"));
+        assert_eq!(suggest("front_size", BARCODE), Some("font_size"));
+        assert_eq!(suggest("fil_color", RECT), Some("fill_color"));
+        assert_eq!(suggest("zz", RECT), None);
+    }
+
+    #[test]
+    fn element_types_are_all_listed() {
+        for kind in ELEMENT_TYPES {
+            assert!(!element_keys(kind).is_empty(), "{} has no keys", kind);
+        }
+        assert!(element_accepts("text", "every_page"));
+        assert!(!element_accepts("rect", "font"));
+        assert!(any_element_accepts("font"));
+    }
+}
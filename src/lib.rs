@@ -1,52 +1,679 @@
+mod bounds;
 mod elements;
 mod error;
+mod extract;
+mod job;
+mod keys;
+mod markup;
+mod numfmt;
+mod origin;
 mod pdf;
+mod preflight;
+mod rasterize;
+mod remote;
 mod resources;
 mod runs;
+mod sanitize;
 mod types;
+mod units;
+mod warnings;
+mod woff;
+mod woff2;
 
+use bounds::BoundsCheck;
 use error::PyRupdfError;
-use pdf::PdfGenerator;
+use pdf::{PdfGenerator, RenderStats};
 use pyo3::prelude::*;
-use pyo3::types::{PyBytes, PyDict};
-use resources::LoadedResources;
-use types::Document;
+use pyo3::types::{PyBytes, PyDict, PyList, PyTuple};
+use remote::RemoteAccess;
+use resources::{LoadedFont, LoadedResources, MetricSource};
+use types::{BarcodeKind, Document, FontResource};
+use units::{Length, Units};
+use warnings::{PyRupdfWarning, Warnings};
+
+/// Check a parsed document's numbers, load its resources, move
+/// bottom-left coordinates to top-left ones, flow overlong tables onto
+/// continuation pages, place the header, footer and watermark on every
+/// page and run the checks. Touches no Python objects, so callers run it
+/// with the GIL released.
+fn prepare(
+    doc: &mut Document,
+    bounds_check: BoundsCheck,
+    bounds_margin: f32,
+    remote: RemoteAccess,
+) -> error::Result<(LoadedResources, Warnings)> {
+    let mut warnings = Warnings::default();
+    sanitize::check(doc, &mut warnings)?;
+    remote::fetch_sources(&mut doc.resources, remote)?;
+    let resources = LoadedResources::load(&doc.resources)?;
+    origin::flip(doc, &resources)?;
+    elements::table::paginate(doc, &resources)?;
+    doc.place_regions();
+    doc.place_watermark();
+
+    resources.check_unembedded_fonts(doc.resources.output_intent.as_ref(), &mut warnings)?;
+    bounds::check(doc, &resources, bounds_check, bounds_margin, &mut warnings)?;
+    elements::fit::check(doc, &resources, &mut warnings)?;
+    Ok((resources, warnings))
+}
 
 /// Render a document to PDF bytes
 ///
 /// Args:
 ///     document: A dictionary containing the document structure with pages and elements
 ///     compress: Whether to compress the output (default: True)
+///     bounds_check: "off" (default), "warn" or "error" for elements that
+///         extend past the page
+///     bounds_margin: Inset from the page edges used by bounds_check, in
+///         the document's units
+///     allow_remote: Fetch "url" font and image sources: True, or a dict
+///         with "timeout" (seconds, default 10) and "max_bytes" (default
+///         20 MiB). Off by default; needs the "remote" build feature.
+///     return_stats: Also return a dict describing what the PDF holds
+///         (default: False)
+///     collect_errors: Parse the whole document before failing, and raise
+///         every element error found at once (default: False, which stops
+///         at the first)
+///     allow_raw: Render raw_content elements, whose operators are written
+///         into the page unchanged (default: False, which rejects them)
+///     include_layers: Names of the document's layers to draw; the others
+///         are left out entirely (default: none)
+///     debug_uncompressed: Deflate nothing, font programs, SVG forms and
+///         color glyph bitmaps included, overriding compress. For
+///         golden-file diffs; the same document always renders to the
+///         same bytes (default: False)
 ///
 /// Returns:
-///     bytes: The rendered PDF as bytes
+///     bytes: The rendered PDF as bytes; with return_stats, a (bytes, dict)
+///     tuple. The dict has "pages" (page objects, repeats included),
+///     "bytes" (the whole file), "content_bytes" (all content streams as
+///     written), "warnings" (how many were emitted), "fonts" (by name:
+///     "postscript_name", "glyphs" in the subset, "embedded" and "bytes",
+///     the compressed font program) and "images" (one dict per image
+///     XObject, an image drawn at several sizes having one per size:
+///     "name", "encoding" ("jpeg", "flate" or "svg"), "width" and "height"
+///     in pixels and "dpi" at the drawn size, None for SVG, "bytes" and
+///     "profile": "embed" or "srgb" for a source with its own ICC
+///     profile, as its resource's "profile" handled it, otherwise None)
 ///
 /// Raises:
-///     RupdfError: If rendering fails
+///     RupdfError: If rendering fails. With collect_errors, parse errors
+///         are raised together, with an "errors" attribute listing them
+///         as dicts with keys "page", "element", "id" and "message"
+///
+/// Warns:
+///     RupdfWarning: For each non-fatal problem found
+///
+/// The GIL is released after the document is parsed; concurrent calls
+/// from several threads share no state.
 #[pyfunction]
-#[pyo3(signature = (document, compress = true))]
+#[pyo3(signature = (document, compress = true, bounds_check = BoundsCheck::Off, bounds_margin = 0.0, allow_remote = RemoteAccess::Off, return_stats = false, collect_errors = false, allow_raw = false, include_layers = Vec::new(), debug_uncompressed = false))]
+#[allow(clippy::too_many_arguments)]
 fn render_pdf<'py>(
     py: Python<'py>,
     document: &Bound<'py, PyDict>,
     compress: bool,
-) -> PyResult<Bound<'py, PyBytes>> {
+    bounds_check: BoundsCheck,
+    bounds_margin: f32,
+    allow_remote: RemoteAccess,
+    return_stats: bool,
+    collect_errors: bool,
+    allow_raw: bool,
+    include_layers: Vec<String>,
+    debug_uncompressed: bool,
+) -> PyResult<Bound<'py, PyAny>> {
+    let options = RenderOptions {
+        compress,
+        bounds_check,
+        bounds_margin,
+        allow_remote,
+        collect_errors,
+        allow_raw,
+        include_layers,
+        debug_uncompressed,
+    };
+    let rendered = render(py, document, options)?;
+    let pdf = PyBytes::new(py, &rendered.pdf).into_any();
+    if !return_stats {
+        return Ok(pdf);
+    }
+    let stats = stats_to_py(py, &rendered.stats, rendered.warnings)?;
+    Ok((pdf, stats).into_pyobject(py)?.into_any())
+}
+
+/// The keyword arguments every render_pdf variant takes
+struct RenderOptions {
+    compress: bool,
+    bounds_check: BoundsCheck,
+    bounds_margin: f32,
+    allow_remote: RemoteAccess,
+    collect_errors: bool,
+    allow_raw: bool,
+    include_layers: Vec<String>,
+    debug_uncompressed: bool,
+}
+
+/// A rendered PDF with the document as it was laid out
+struct Rendered {
+    doc: Document,
+    pdf: Vec<u8>,
+    stats: RenderStats,
+    /// How many warnings were emitted
+    warnings: usize,
+}
+
+/// Parse and render a document and emit its warnings, as `render_pdf`
+/// does.
+fn render(py: Python<'_>, document: &Bound<'_, PyDict>, options: RenderOptions) -> PyResult<Rendered> {
     // Parse document from Python dict
-    let doc = Document::from_py(document).map_err(PyErr::from)?;
+    let doc = if options.collect_errors { Document::from_py_collecting(document) } else { Document::from_py(document) };
+    let mut doc = doc.map_err(PyErr::from)?;
+    doc.select_layers(&options.include_layers).map_err(PyErr::from)?;
+    doc.check_raw_allowed(options.allow_raw).map_err(PyErr::from)?;
+
+    // The rest works on owned Rust data only, so other threads may run
+    let (pdf, warnings, stats) = py
+        .detach(|| {
+            let (resources, warnings) =
+                prepare(&mut doc, options.bounds_check, options.bounds_margin, options.allow_remote)?;
+            let generator =
+                PdfGenerator::new(&doc, &resources, options.compress).debug_uncompressed(options.debug_uncompressed);
+            let pdf = generator.generate()?;
+            Ok::<_, error::RupdfError>((pdf, warnings, generator.stats()))
+        })
+        .map_err(PyErr::from)?;
+
+    warnings.emit(py)?;
+    Ok(Rendered { doc, pdf, stats, warnings: warnings.iter().count() })
+}
+
+/// The `return_stats` dict; see `render_pdf`.
+fn stats_to_py<'py>(py: Python<'py>, stats: &RenderStats, warnings: usize) -> PyResult<Bound<'py, PyDict>> {
+    let fonts = PyDict::new(py);
+    for font in &stats.fonts {
+        let info = PyDict::new(py);
+        info.set_item("postscript_name", &font.postscript_name)?;
+        info.set_item("glyphs", font.glyphs)?;
+        info.set_item("embedded", font.embedded)?;
+        info.set_item("bytes", font.bytes)?;
+        fonts.set_item(&font.name, info)?;
+    }
+    let images = PyList::empty(py);
+    for image in &stats.images {
+        let info = PyDict::new(py);
+        info.set_item("name", &image.name)?;
+        info.set_item("encoding", image.encoding.as_str())?;
+        info.set_item("width", image.pixels.map(|(w, _)| w))?;
+        info.set_item("height", image.pixels.map(|(_, h)| h))?;
+        info.set_item("dpi", image.dpi)?;
+        info.set_item("bytes", image.bytes)?;
+        info.set_item("profile", image.profile.map(|p| p.as_str()))?;
+        images.append(info)?;
+    }
+    let dict = PyDict::new(py);
+    dict.set_item("pages", stats.pages)?;
+    dict.set_item("bytes", stats.total_bytes)?;
+    dict.set_item("content_bytes", stats.content_bytes)?;
+    dict.set_item("warnings", warnings)?;
+    dict.set_item("fonts", fonts)?;
+    dict.set_item("images", images)?;
+    Ok(dict)
+}
+
+/// Render a PDF document and index its pages
+///
+/// For batch jobs that merge many records into one PDF and later cut it
+/// apart: each page dict may carry a "tag", any value, which is echoed for
+/// every page it produces, with where that page was written.
+///
+/// Args:
+///     document: A document dict, as for render_pdf
+///     compress, bounds_check, bounds_margin, allow_remote, return_stats,
+///     collect_errors, allow_raw, include_layers, debug_uncompressed: As
+///         for render_pdf
+///
+/// Returns:
+///     tuple[bytes, list[dict]]: The PDF, as render_pdf returns it, and one
+///     dict per page in the rendered PDF, in order: "tag" (the page dict's,
+///     or None; a table's continuation pages and a repeated page's copies
+///     share it), "page" (index from 0, copies counted), "object" (object
+///     number of the page dictionary), "content_object" (object number of
+///     its content stream) and "content_offset" (byte offset of that
+///     object in the file). Copies of a repeated page share a content
+///     stream unless a header or footer numbers them. With return_stats,
+///     render_pdf's stats dict follows as a third item.
+///
+/// Raises:
+///     RupdfError: If rendering fails, as render_pdf raises
+///
+/// Warns:
+///     RupdfWarning: For each non-fatal problem found, as render_pdf does
+#[pyfunction]
+#[pyo3(signature = (document, compress = true, bounds_check = BoundsCheck::Off, bounds_margin = 0.0, allow_remote = RemoteAccess::Off, return_stats = false, collect_errors = false, allow_raw = false, include_layers = Vec::new(), debug_uncompressed = false))]
+#[allow(clippy::too_many_arguments)]
+fn render_pdf_with_index<'py>(
+    py: Python<'py>,
+    document: &Bound<'py, PyDict>,
+    compress: bool,
+    bounds_check: BoundsCheck,
+    bounds_margin: f32,
+    allow_remote: RemoteAccess,
+    return_stats: bool,
+    collect_errors: bool,
+    allow_raw: bool,
+    include_layers: Vec<String>,
+    debug_uncompressed: bool,
+) -> PyResult<Bound<'py, PyTuple>> {
+    let options = RenderOptions {
+        compress,
+        bounds_check,
+        bounds_margin,
+        allow_remote,
+        collect_errors,
+        allow_raw,
+        include_layers,
+        debug_uncompressed,
+    };
+    let Rendered { doc, pdf, stats, warnings } = render(py, document, options)?;
+
+    // Tags are read from the page dicts as given, never converted
+    let pages = document.get_item("pages")?.expect("pages were parsed").cast_into::<PyList>()?;
+    let index = PyList::empty(py);
+    for (n, placed) in stats.page_objects.iter().enumerate() {
+        let page = pages.get_item(doc.pages[placed.page].source)?.cast_into::<PyDict>()?;
+        let entry = PyDict::new(py);
+        entry.set_item("tag", page.get_item("tag")?)?;
+        entry.set_item("page", n)?;
+        entry.set_item("object", placed.object)?;
+        entry.set_item("content_object", placed.content_object)?;
+        entry.set_item("content_offset", placed.content_offset)?;
+        index.append(entry)?;
+    }
+    let pdf = PyBytes::new(py, &pdf);
+    if !return_stats {
+        return (pdf, index).into_pyobject(py);
+    }
+    let stats = stats_to_py(py, &stats, warnings)?;
+    (pdf, index, stats).into_pyobject(py)
+}
+
+/// Check a document without rendering it
+///
+/// Parses the document, loads its resources and runs the requested checks.
+///
+/// Args:
+///     document: A dictionary containing the document structure with pages and elements
+///     bounds_check: "off", "warn" (default) or "error" for elements that
+///         extend past the page
+///     bounds_margin: Inset from the page edges used by bounds_check, in
+///         the document's units
+///     allow_remote: Fetch "url" sources, as for render_pdf
+///     allow_raw: Accept raw_content elements, as for render_pdf
+///     include_layers: Layers to check as drawn, as for render_pdf; the
+///         others are only parsed
+///
+/// Returns:
+///     list[dict]: One dict per warning with keys "code", "page",
+///     "element", "id" and "message". Elements sharing an id are
+///     reported here (code "duplicate_id") but not by render_pdf.
+///
+/// Raises:
+///     RupdfError: If the document is invalid, with every element error
+///         found listed in its "errors" attribute as for render_pdf's
+///         collect_errors; or on the first violation in "error" mode
+#[pyfunction]
+#[pyo3(signature = (document, bounds_check = BoundsCheck::Warn, bounds_margin = 0.0, allow_remote = RemoteAccess::Off, allow_raw = false, include_layers = Vec::new()))]
+fn validate_document<'py>(
+    py: Python<'py>,
+    document: &Bound<'py, PyDict>,
+    bounds_check: BoundsCheck,
+    bounds_margin: f32,
+    allow_remote: RemoteAccess,
+    allow_raw: bool,
+    include_layers: Vec<String>,
+) -> PyResult<Bound<'py, PyList>> {
+    let mut doc = Document::from_py_collecting(document).map_err(PyErr::from)?;
+    doc.select_layers(&include_layers).map_err(PyErr::from)?;
+    doc.check_raw_allowed(allow_raw).map_err(PyErr::from)?;
+    let mut duplicates = Warnings::default();
+    doc.warn_duplicate_ids(&mut duplicates);
+    let (_, warnings) = py
+        .detach(|| prepare(&mut doc, bounds_check, bounds_margin, allow_remote))
+        .map_err(PyErr::from)?;
+
+    let items = duplicates
+        .iter()
+        .chain(warnings.iter())
+        .map(|w| w.to_py(py))
+        .collect::<PyResult<Vec<_>>>()?;
+    PyList::new(py, items)
+}
+
+/// Hash the layout a document renders to, for snapshot tests
+///
+/// The document is laid out exactly as render_pdf would, and everything
+/// its pages draw is hashed: wrapped lines, baselines and kerning, image
+/// and barcode geometry, page sizes. Metadata, dates and the embedded font
+/// and image data are left out, and no PDF is kept, so the same document
+/// gives the same fingerprint on every run and platform and a different
+/// one when any position moves.
+///
+/// Args:
+///     document: A document dict, as for render_pdf
+///     allow_remote: As for render_pdf
+///     allow_raw: As for render_pdf
+///     include_layers: As for render_pdf
+///
+/// Returns:
+///     str: 16 hex digits
+///
+/// Raises:
+///     RupdfError: If rendering fails
+///
+/// Warns:
+///     RupdfWarning: For each non-fatal problem found, as render_pdf does
+#[pyfunction]
+#[pyo3(signature = (document, allow_remote = RemoteAccess::Off, allow_raw = false, include_layers = Vec::new()))]
+fn render_layout_fingerprint(
+    py: Python<'_>,
+    document: &Bound<'_, PyDict>,
+    allow_remote: RemoteAccess,
+    allow_raw: bool,
+    include_layers: Vec<String>,
+) -> PyResult<String> {
+    let mut doc = Document::from_py(document).map_err(PyErr::from)?;
+    doc.select_layers(&include_layers).map_err(PyErr::from)?;
+    doc.check_raw_allowed(allow_raw).map_err(PyErr::from)?;
+    let (fingerprint, warnings) = py
+        .detach(|| {
+            let (resources, warnings) = prepare(&mut doc, BoundsCheck::Off, 0.0, allow_remote)?;
+            let fingerprint = PdfGenerator::new(&doc, &resources, false).layout_fingerprint()?;
+            Ok::<_, error::RupdfError>((fingerprint, warnings))
+        })
+        .map_err(PyErr::from)?;
+    warnings.emit(py)?;
+    Ok(fingerprint)
+}
+
+/// Render one page of a document to a PNG image
+///
+/// The document is rendered to PDF exactly as render_pdf would and the
+/// page is then painted from that PDF, so the image shows the same line
+/// breaks, kerning and placement. Use it for thumbnails and previews of
+/// rupdf documents, for example as an image resource of another document.
+/// Image fades and note annotations are not drawn, and CMYK and spot
+/// colors are shown as RGB approximations.
+///
+/// Args:
+///     document: A document dict, as for render_pdf
+///     page: Index of the page in the rendered PDF, from 0; repeated
+///         pages count once per copy
+///     dpi: Pixels per inch; 72 gives one pixel per point
+///     allow_remote: As for render_pdf
+///     allow_raw: As for render_pdf
+///     include_layers: As for render_pdf
+///
+/// Returns:
+///     bytes: An RGBA PNG on a white page
+///
+/// Raises:
+///     RupdfError: If rendering fails, or the page would take more than
+///         16384 × 16384 pixels
+///     ValueError: If dpi isn't positive
+///     IndexError: If the document has no such page
+///
+/// Warns:
+///     RupdfWarning: For each non-fatal problem found, as render_pdf does
+#[pyfunction]
+#[pyo3(signature = (document, page = 0, dpi = 72.0, allow_remote = RemoteAccess::Off, allow_raw = false, include_layers = Vec::new()))]
+#[allow(clippy::too_many_arguments)]
+fn rasterize_page<'py>(
+    py: Python<'py>,
+    document: &Bound<'py, PyDict>,
+    page: usize,
+    dpi: f32,
+    allow_remote: RemoteAccess,
+    allow_raw: bool,
+    include_layers: Vec<String>,
+) -> PyResult<Bound<'py, PyBytes>> {
+    if !dpi.is_finite() || dpi <= 0.0 {
+        return Err(pyo3::exceptions::PyValueError::new_err(format!("Invalid dpi: {}. Must be positive", dpi)));
+    }
+    let mut doc = Document::from_py(document).map_err(PyErr::from)?;
+    doc.select_layers(&include_layers).map_err(PyErr::from)?;
+    doc.check_raw_allowed(allow_raw).map_err(PyErr::from)?;
+    let rendered = py
+        .detach(|| {
+            let (resources, warnings) = prepare(&mut doc, BoundsCheck::Off, 0.0, allow_remote)?;
+            // Tables may have added pages; copies each count
+            let count: usize = doc.pages.iter().map(|p| p.repeat).sum();
+            if page >= count {
+                return Ok(Err(count));
+            }
+            let pdf = PdfGenerator::new(&doc, &resources, false).generate()?;
+            let png = rasterize::rasterize(&pdf, page, dpi, &resources.fonts)?;
+            Ok::<_, error::RupdfError>(Ok((png, warnings)))
+        })
+        .map_err(PyErr::from)?;
+    let (png, warnings) = rendered.map_err(|count| {
+        pyo3::exceptions::PyIndexError::new_err(format!("Page {} is out of range; the document has {} pages", page, count))
+    })?;
+    warnings.emit(py)?;
+    Ok(PyBytes::new(py, &png))
+}
+
+/// Report the metrics rupdf reads from a font
+///
+/// Args:
+///     font: A font resource dict with "path" or "bytes", as in
+///         resources.fonts
+///
+/// Returns:
+///     dict: "postscript_name", "units_per_em", "ascender", "descender",
+///     "cap_height" and "x_height" (font units), "num_glyphs" and
+///     "outlines" ("cff" or "truetype"). "cap_height_source" and
+///     "x_height_source" say where each height came from: "os2", "glyph"
+///     (measured from the character in "cap_height_glyph" or
+///     "x_height_glyph") or "estimate"
+///
+/// Raises:
+///     RupdfError: If the font can't be read or parsed
+#[pyfunction]
+fn inspect_font<'py>(py: Python<'py>, font: &Bound<'py, PyDict>) -> PyResult<Bound<'py, PyDict>> {
+    let loaded = load_font(font)?;
+    let info = PyDict::new(py);
+    info.set_item("postscript_name", &loaded.postscript_name)?;
+    info.set_item("units_per_em", loaded.units_per_em)?;
+    info.set_item("ascender", loaded.ascender)?;
+    info.set_item("descender", loaded.descender)?;
+    info.set_item("cap_height", loaded.cap_height)?;
+    info.set_item("x_height", loaded.x_height)?;
+    for (key, source) in [("cap_height", loaded.cap_height_source), ("x_height", loaded.x_height_source)] {
+        info.set_item(format!("{}_source", key), source.name())?;
+        let glyph = match source {
+            MetricSource::Glyph(ch) => Some(ch),
+            _ => None,
+        };
+        info.set_item(format!("{}_glyph", key), glyph)?;
+    }
+    info.set_item("num_glyphs", loaded.num_glyphs)?;
+    info.set_item("outlines", if loaded.cff { "cff" } else { "truetype" })?;
+    Ok(info)
+}
+
+/// List the characters of a string a font has no glyph for
+///
+/// Args:
+///     font: A font resource dict with "path" or "bytes"
+///     text: The text to check, NFC-normalized first as documents are by
+///         default
+///
+/// Returns:
+///     list[str]: Each uncovered character once, in order of first use.
+///     Control characters such as "\n" and variation selectors are ignored.
+///
+/// Raises:
+///     RupdfError: If the font can't be read or parsed
+#[pyfunction]
+fn font_covers(font: &Bound<'_, PyDict>, text: &str) -> PyResult<Vec<String>> {
+    let loaded = load_font(font)?;
+    Ok(runs::missing(&runs::nfc(text), &loaded).into_iter().map(String::from).collect())
+}
+
+/// Measure a Code 128 or GS1-128 symbol without rendering it
+///
+/// The value is encoded exactly as a barcode element's is, so the result
+/// matches what render_pdf draws.
+///
+/// Args:
+///     value: The barcode value; GS1-128 values use the parenthesized
+///         "(01)...(10)..." form
+///     symbology: "code128" (default) or "gs1_128"
+///     options: Optional dict with "module_width", the width of one module
+///         in points or as a string with a unit suffix (default 1pt)
+///
+/// Returns:
+///     dict: "modules" (symbol width in modules, quiet zones excluded),
+///     "quiet_zone_modules" (light modules needed on each side), "width"
+///     (the bars at module_width, in points; use it as the element's w),
+///     "min_width" (width plus both quiet zones), "checksum_added" (True:
+///     every Code 128 symbol ends with a modulo 103 check character) and
+///     "human_readable" (the text printed under the bars)
+///
+/// Raises:
+///     RupdfError: If the value can't be encoded
+#[pyfunction]
+#[pyo3(signature = (value, symbology = BarcodeKind::Code128, options = None))]
+fn measure_barcode<'py>(
+    py: Python<'py>,
+    value: &str,
+    symbology: BarcodeKind,
+    options: Option<&Bound<'py, PyDict>>,
+) -> PyResult<Bound<'py, PyDict>> {
+    let module_width = match options {
+        Some(options) => options.get_item("module_width")?.map(|v| v.extract::<Length>()).transpose()?,
+        None => None,
+    }
+    .map_or(1.0, |length| Units::default().to_points(length));
+    if !module_width.is_finite() || module_width <= 0.0 {
+        return Err(pyo3::exceptions::PyValueError::new_err(format!(
+            "Invalid module_width: {}. Must be positive",
+            module_width
+        )));
+    }
+    let encoded = elements::barcode::encode(symbology, value).map_err(PyErr::from)?;
+    let modules = encoded.geometry.total_modules;
+    let quiet_zone = elements::barcode::QUIET_ZONE_MODULES;
+    let info = PyDict::new(py);
+    info.set_item("modules", modules)?;
+    info.set_item("quiet_zone_modules", quiet_zone)?;
+    info.set_item("width", modules as f32 * module_width)?;
+    info.set_item("min_width", (modules + 2 * quiet_zone) as f32 * module_width)?;
+    info.set_item("checksum_added", true)?;
+    info.set_item("human_readable", encoded.human_readable)?;
+    Ok(info)
+}
 
-    // Load resources
-    let resources = LoadedResources::load(&doc.resources).map_err(PyErr::from)?;
+/// Read back what a PDF written by render_pdf draws, for tests and QA
+///
+/// Only rupdf's own output is understood, compressed or not; this is not a
+/// general PDF reader. Clipping paths and images are left out.
+///
+/// Args:
+///     pdf: The PDF bytes
+///
+/// Returns:
+///     dict: "pages", a list with one dict per page holding "width",
+///     "height" and:
+///     "text", one entry per text-showing operator with "text" (decoded
+///     through the font's ToUnicode map), "font" (its BaseFont name),
+///     "size", "x" and "y" (start of the baseline), "width" (the advance,
+///     spacing included) and "color" (fill color components, 0-1);
+///     "rects", painted rects with "x", "y", "w", "h" and "fill" and
+///     "stroke" (color components, or None when not painted that way);
+///     "lines", stroked straight segments with "x1", "y1", "x2", "y2",
+///     "width" and "color". Positions are in points from the page's
+///     top-left corner, like document coordinates.
+///
+/// Raises:
+///     RupdfError: If the bytes aren't a PDF this function can read
+#[pyfunction]
+fn extract_content<'py>(py: Python<'py>, pdf: &[u8]) -> PyResult<Bound<'py, PyDict>> {
+    let pages = extract::extract(pdf).map_err(PyErr::from)?;
+    extract::to_py(py, &pages)
+}
 
-    // Generate PDF
-    let generator = PdfGenerator::new(&doc, &resources, compress);
-    let pdf_bytes = generator.generate().map_err(PyErr::from)?;
+/// Check a document's fonts and images before rendering it
+///
+/// Every declared resource is loaded as render_pdf loads it, carrying on
+/// past failures, and raster images are decoded in full. Nothing is
+/// rendered.
+///
+/// Args:
+///     document: A dictionary containing the document structure with pages and elements
+///     samples: Strings to check every font's coverage of, such as
+///         representative names or addresses (default: none)
+///     allow_remote: Fetch "url" sources, as for render_pdf
+///     allow_raw: Accept raw_content elements, as for render_pdf
+///
+/// Returns:
+///     dict: "ok" (True when every resource the document draws loaded
+///     and none is missing), "fonts" and "images" (by name, every
+///     declared resource), "missing_fonts" and "missing_images" (names
+///     drawn but not declared). Each font has "ok", "error" (why it
+///     failed, else None), "referenced" (drawn by a visible element),
+///     "postscript_name", "num_glyphs", "embedded" and "missing" (the
+///     sample characters it has no glyph for). Each image has "ok",
+///     "error", "referenced", "kind" ("raster" or "svg"), "width" and
+///     "height" (pixels, or points for SVG), "sizes" (one dict per size
+///     drawn, with "width" and "height" in points and, for raster
+///     images, "pixel_width", "pixel_height" and "dpi" as embedded) and
+///     "unsupported" (SVG features drawn differently or not at all).
+///     The last fields are None for a resource that failed.
+///
+/// Raises:
+///     RupdfError: If the document itself is invalid
+#[pyfunction]
+#[pyo3(name = "preflight", signature = (document, samples = Vec::new(), allow_remote = RemoteAccess::Off, allow_raw = false))]
+fn preflight_document<'py>(
+    py: Python<'py>,
+    document: &Bound<'py, PyDict>,
+    samples: Vec<String>,
+    allow_remote: RemoteAccess,
+    allow_raw: bool,
+) -> PyResult<Bound<'py, PyDict>> {
+    let mut doc = Document::from_py(document).map_err(PyErr::from)?;
+    doc.check_raw_allowed(allow_raw).map_err(PyErr::from)?;
+    let report = py
+        .detach(|| {
+            remote::fetch_sources(&mut doc.resources, allow_remote)?;
+            Ok::<_, error::RupdfError>(preflight::check(&doc, &samples))
+        })
+        .map_err(PyErr::from)?;
+    preflight::to_py(py, &report)
+}
 
-    Ok(PyBytes::new(py, &pdf_bytes))
+/// Load a standalone font dict the same way resources.fonts entries are.
+fn load_font(font: &Bound<'_, PyDict>) -> PyResult<LoadedFont> {
+    let resource = FontResource::from_py(font, "font").map_err(PyErr::from)?;
+    LoadedResources::load_font("font", &resource).map_err(PyErr::from)
 }
 
 /// The rupdf Python module
 #[pymodule]
 fn _rupdf(py: Python<'_>, m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(render_pdf, m)?)?;
+    m.add_function(wrap_pyfunction!(render_pdf_with_index, m)?)?;
+    m.add_function(wrap_pyfunction!(validate_document, m)?)?;
+    m.add_function(wrap_pyfunction!(render_layout_fingerprint, m)?)?;
+    m.add_function(wrap_pyfunction!(rasterize_page, m)?)?;
+    m.add_function(wrap_pyfunction!(preflight_document, m)?)?;
+    m.add_function(wrap_pyfunction!(inspect_font, m)?)?;
+    m.add_function(wrap_pyfunction!(font_covers, m)?)?;
+    m.add_function(wrap_pyfunction!(measure_barcode, m)?)?;
+    m.add_function(wrap_pyfunction!(extract_content, m)?)?;
+    m.add_class::<job::PdfRenderJob>()?;
     m.add("RupdfError", py.get_type::<PyRupdfError>())?;
+    m.add("RupdfWarning", py.get_type::<PyRupdfWarning>())?;
     Ok(())
 }
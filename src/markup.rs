@@ -0,0 +1,231 @@
+//! Inline markup for `markup: true` text and text boxes.
+//!
+//! `**...**` sets text in the document's `bold` style and `{name}...{/name}`
+//! in the style `name`, both looked up in the document's `styles` table.
+//! Spans nest and must close innermost first; the innermost span that sets
+//! a font or a color wins it. A backslash takes the next character
+//! literally, and a `{` that doesn't start a tag is kept as text.
+//!
+//! Parsing flattens the markup into style characters (see
+//! `runs::style_char`) ahead of the characters each style covers. The
+//! resolver drops the style back to plain after every whitespace
+//! character, so each word of the flattened text carries its own style
+//! and wraps on its own like any other word.
+
+use crate::runs::style_char;
+use crate::types::TextStyle;
+use std::collections::HashMap;
+
+/// Markup flattened into styled text
+#[derive(Debug, Clone, PartialEq)]
+pub struct Marked {
+    /// The text, with a style character wherever the style changes
+    pub text: String,
+    /// The styles the text uses; style character `k` selects entry `k - 1`
+    pub styles: Vec<TextStyle>,
+}
+
+/// An open span and the character offset of its opening tag
+enum Open {
+    Bold(usize),
+    Named(String, usize),
+}
+
+impl Open {
+    fn describe(&self) -> String {
+        match self {
+            Open::Bold(at) => format!("'**' at offset {}", at),
+            Open::Named(name, at) => format!("'{{{}}}' at offset {}", name, at),
+        }
+    }
+}
+
+/// The tag name of `{name}` or `{/name}` starting at `chars[at]`, with
+/// whether it closes and the tag's length in characters.
+fn tag(chars: &[char], at: usize) -> Option<(String, bool, usize)> {
+    let end = chars[at..].iter().position(|&c| c == '}')? + at;
+    let inner: String = chars[at + 1..end].iter().collect();
+    let (name, closing) = match inner.strip_prefix('/') {
+        Some(name) => (name.to_string(), true),
+        None => (inner, false),
+    };
+    let valid = !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+    valid.then_some((name, closing, end - at + 1))
+}
+
+/// Flatten `source` against the document's `styles`. Errors name the
+/// character offset of the offending tag.
+pub fn parse(source: &str, styles: &HashMap<String, TextStyle>) -> Result<Marked, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut marked = Marked { text: String::with_capacity(source.len()), styles: Vec::new() };
+    let mut open: Vec<Open> = Vec::new();
+    // Style index for the characters being read, and the one the resolver
+    // will be in after the text written so far
+    let (mut current, mut active) = (0, 0);
+    let mut i = 0;
+    while i < chars.len() {
+        let ch = chars[i];
+        let literal = match ch {
+            '\\' => {
+                i += 1;
+                Some(*chars.get(i).ok_or_else(|| format!("trailing '\\' at offset {}", i - 1))?)
+            }
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                match open.iter().rposition(|o| matches!(o, Open::Bold(_))) {
+                    Some(pos) if pos + 1 == open.len() => {
+                        open.pop();
+                    }
+                    Some(pos) => {
+                        return Err(format!(
+                            "'**' at offset {} closes {} before {}",
+                            i,
+                            open[pos].describe(),
+                            open[open.len() - 1].describe()
+                        ));
+                    }
+                    None => {
+                        if !styles.contains_key("bold") {
+                            return Err(format!("'**' at offset {} needs a 'bold' entry in styles", i));
+                        }
+                        open.push(Open::Bold(i));
+                    }
+                }
+                i += 2;
+                None
+            }
+            '{' => match tag(&chars, i) {
+                Some((name, false, len)) => {
+                    if !styles.contains_key(&name) {
+                        return Err(format!("unknown style '{}' at offset {}", name, i));
+                    }
+                    open.push(Open::Named(name, i));
+                    i += len;
+                    None
+                }
+                Some((name, true, len)) => {
+                    match open.last() {
+                        Some(Open::Named(top, _)) if *top == name => {
+                            open.pop();
+                        }
+                        Some(top) => {
+                            return Err(format!("'{{/{}}}' at offset {} doesn't close {}", name, i, top.describe()));
+                        }
+                        None => return Err(format!("'{{/{}}}' at offset {} closes nothing", name, i)),
+                    }
+                    i += len;
+                    None
+                }
+                None => Some(ch),
+            },
+            _ => Some(ch),
+        };
+        let Some(ch) = literal else {
+            current = style_index(&open, styles, &mut marked.styles);
+            continue;
+        };
+        // Whitespace keeps whatever style precedes it, so no word is a
+        // lone style character
+        if !ch.is_whitespace() && current != active {
+            marked.text.push(style_char(current));
+            active = current;
+        }
+        marked.text.push(ch);
+        if ch.is_whitespace() {
+            active = 0;
+        }
+        i += 1;
+    }
+    if let Some(unclosed) = open.last() {
+        return Err(format!("unclosed {}", unclosed.describe()));
+    }
+    Ok(marked)
+}
+
+/// Style index of the open spans combined, registering the combination
+/// in `used` on first use. 0 is plain text.
+fn style_index(open: &[Open], styles: &HashMap<String, TextStyle>, used: &mut Vec<TextStyle>) -> usize {
+    let mut style = TextStyle::default();
+    for span in open {
+        let name = match span {
+            Open::Bold(_) => "bold",
+            Open::Named(name, _) => name.as_str(),
+        };
+        let layer = &styles[name];
+        if layer.font.is_some() {
+            style.font = layer.font.clone();
+        }
+        if layer.color.is_some() {
+            style.color = layer.color.clone();
+        }
+    }
+    if style == TextStyle::default() {
+        return 0;
+    }
+    match used.iter().position(|s| *s == style) {
+        Some(k) => k + 1,
+        None => {
+            used.push(style);
+            used.len()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Color;
+
+    fn styles() -> HashMap<String, TextStyle> {
+        HashMap::from([
+            ("bold".to_string(), TextStyle { font: Some("B".to_string()), color: None }),
+            ("red".to_string(), TextStyle { font: None, color: Some(Color::rgba(255, 0, 0, 255)) }),
+        ])
+    }
+
+    fn s(k: usize) -> char {
+        style_char(k)
+    }
+
+    #[test]
+    fn spans_become_style_characters() {
+        let marked = parse("a **b** {red}c{/red}", &styles()).unwrap();
+        assert_eq!(marked.text, format!("a {}b {}c", s(1), s(2)));
+        assert_eq!(marked.styles.len(), 2);
+        assert_eq!(marked.styles[0].font.as_deref(), Some("B"));
+    }
+
+    #[test]
+    fn each_word_restates_its_style() {
+        let marked = parse("{red}one two{/red} three", &styles()).unwrap();
+        assert_eq!(marked.text, format!("{}one {}two three", s(1), s(1)));
+    }
+
+    #[test]
+    fn nested_spans_combine() {
+        let marked = parse("**{red}x{/red}y**", &styles()).unwrap();
+        assert_eq!(marked.text, format!("{}x{}y", s(2), s(1)));
+        assert_eq!(marked.styles[1], TextStyle { font: Some("B".to_string()), color: Some(Color::rgba(255, 0, 0, 255)) });
+    }
+
+    #[test]
+    fn escapes_and_stray_braces_are_text() {
+        let marked = parse(r"\*\*{not a tag} \{red}", &styles()).unwrap();
+        assert_eq!(marked.text, "**{not a tag} {red}");
+        assert!(marked.styles.is_empty());
+    }
+
+    #[test]
+    fn unbalanced_markup_names_the_offset() {
+        assert_eq!(parse("ab **c", &styles()).unwrap_err(), "unclosed '**' at offset 3");
+        assert_eq!(parse("x{/red}", &styles()).unwrap_err(), "'{/red}' at offset 1 closes nothing");
+        assert_eq!(
+            parse("{red}**a{/red}**", &styles()).unwrap_err(),
+            "'{/red}' at offset 8 doesn't close '**' at offset 5"
+        );
+        assert_eq!(
+            parse("**{red}a**", &styles()).unwrap_err(),
+            "'**' at offset 8 closes '**' at offset 0 before '{red}' at offset 2"
+        );
+        assert_eq!(parse("{blue}a{/blue}", &styles()).unwrap_err(), "unknown style 'blue' at offset 0");
+    }
+}
@@ -0,0 +1,239 @@
+//! Number formatting for `{"number": ..., "format": {...}}` text content.
+//!
+//! Numbers are formatted from their shortest decimal form (what Python's
+//! `repr` prints for a float, or the digits of an int or `Decimal`), so
+//! rounding works on the digits the caller sees: 2.675 rounds half away
+//! from zero to 2.68, not to the 2.67 its binary value would give. Nothing
+//! depends on the system locale.
+
+/// How negative numbers are marked
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NegativeStyle {
+    /// A leading hyphen-minus, before any prefix: -$1,234.50
+    #[default]
+    Minus,
+    /// Accounting style, around prefix and suffix: ($1,234.50)
+    Parentheses,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NumberFormat {
+    /// Fixed number of decimal places; `None` keeps the number's own
+    pub decimals: Option<u32>,
+    /// Separator between groups of three integer digits; empty for none
+    pub thousands: String,
+    /// Decimal mark
+    pub decimal: String,
+    pub prefix: String,
+    pub suffix: String,
+    pub negative: NegativeStyle,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        Self {
+            decimals: None,
+            thousands: String::new(),
+            decimal: ".".to_string(),
+            prefix: String::new(),
+            suffix: String::new(),
+            negative: NegativeStyle::Minus,
+        }
+    }
+}
+
+/// Thousands separator and decimal mark for a locale name such as "de",
+/// "de_DE" or "en-US". Regions only matter where they change the
+/// convention (Swiss German uses an apostrophe and a period).
+pub fn locale_separators(name: &str) -> Option<(&'static str, &'static str)> {
+    let name = name.to_ascii_lowercase().replace('-', "_");
+    let (language, region) = name.split_once('_').unwrap_or((name.as_str(), ""));
+    Some(match (language, region) {
+        ("c" | "posix", "") => ("", "."),
+        ("de" | "fr" | "it", "ch") => ("\u{2019}", "."),
+        ("pt", "pt") => ("\u{a0}", ","),
+        ("en" | "ja" | "zh" | "ko" | "th" | "he", _) => (",", "."),
+        ("de" | "es" | "it" | "nl" | "pt" | "da" | "id" | "tr" | "el", _) => (".", ","),
+        ("fr" | "sv" | "fi" | "nb" | "no" | "pl" | "cs" | "sk" | "ru" | "uk" | "hu", _) => ("\u{a0}", ","),
+        _ => return None,
+    })
+}
+
+/// A decimal number split into its digits
+struct Digits {
+    negative: bool,
+    int: Vec<u8>,
+    frac: Vec<u8>,
+}
+
+impl Digits {
+    /// Parse `[+-]digits[.digits][e[+-]digits]`, the forms Python prints
+    /// for ints, floats and Decimals.
+    fn parse(s: &str) -> Option<Self> {
+        let (negative, s) = match s.as_bytes().first()? {
+            b'-' => (true, &s[1..]),
+            b'+' => (false, &s[1..]),
+            _ => (false, s),
+        };
+        // Wide enough for any f64; larger exponents are rejected rather
+        // than expanded into pages of zeros
+        let (mantissa, exponent) = match s.find(['e', 'E']) {
+            Some(i) => (&s[..i], s[i + 1..].parse::<i32>().ok().filter(|e| e.abs() <= 400)?),
+            None => (s, 0),
+        };
+        let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        let digit = |c: u8| c.is_ascii_digit().then(|| c - b'0');
+        let int: Vec<u8> = int.bytes().map(digit).collect::<Option<_>>()?;
+        let frac: Vec<u8> = frac.bytes().map(digit).collect::<Option<_>>()?;
+        if int.is_empty() && frac.is_empty() {
+            return None;
+        }
+        // Move the decimal point by the exponent
+        let point = int.len() as i64 + exponent as i64;
+        let mut all = int;
+        all.extend(frac);
+        let (int, frac) = if point <= 0 {
+            let mut frac = vec![0; (-point) as usize];
+            frac.extend(all);
+            (Vec::new(), frac)
+        } else if point as usize >= all.len() {
+            all.resize(point as usize, 0);
+            (all, Vec::new())
+        } else {
+            let frac = all.split_off(point as usize);
+            (all, frac)
+        };
+        Some(Self { negative, int, frac }.trimmed())
+    }
+
+    /// Drop leading integer zeros (keeping one) and trailing fraction zeros.
+    fn trimmed(mut self) -> Self {
+        let lead = self.int.iter().take_while(|&&d| d == 0).count();
+        self.int.drain(..lead);
+        if self.int.is_empty() {
+            self.int.push(0);
+        }
+        while self.frac.last() == Some(&0) {
+            self.frac.pop();
+        }
+        self
+    }
+
+    /// Round to exactly `decimals` places, half away from zero.
+    fn round(mut self, decimals: usize) -> Self {
+        if self.frac.len() > decimals {
+            let round_up = self.frac[decimals] >= 5;
+            self.frac.truncate(decimals);
+            if round_up {
+                let mut carry = true;
+                for d in self.frac.iter_mut().rev().chain(self.int.iter_mut().rev()) {
+                    if *d == 9 {
+                        *d = 0;
+                    } else {
+                        *d += 1;
+                        carry = false;
+                        break;
+                    }
+                }
+                if carry {
+                    self.int.insert(0, 1);
+                }
+            }
+        }
+        self.frac.resize(decimals, 0);
+        self
+    }
+
+    fn is_zero(&self) -> bool {
+        self.int.iter().chain(&self.frac).all(|&d| d == 0)
+    }
+}
+
+/// Format `number`, given in decimal notation, or describe why it can't be.
+pub fn format(number: &str, fmt: &NumberFormat) -> Result<String, String> {
+    let digits = Digits::parse(number.trim()).ok_or_else(|| format!("'{}' is not a finite number", number))?;
+    let digits = match fmt.decimals {
+        Some(decimals) => digits.round(decimals as usize),
+        None => digits,
+    };
+    // -0.001 rounded to two places is 0.00, not -0.00
+    let negative = digits.negative && !digits.is_zero();
+
+    let mut body = fmt.prefix.clone();
+    for (i, d) in digits.int.iter().enumerate() {
+        if i > 0 && (digits.int.len() - i) % 3 == 0 {
+            body.push_str(&fmt.thousands);
+        }
+        body.push((b'0' + d) as char);
+    }
+    if !digits.frac.is_empty() {
+        body.push_str(&fmt.decimal);
+        body.extend(digits.frac.iter().map(|d| (b'0' + d) as char));
+    }
+    body.push_str(&fmt.suffix);
+
+    Ok(match (negative, fmt.negative) {
+        (false, _) => body,
+        (true, NegativeStyle::Minus) => format!("-{}", body),
+        (true, NegativeStyle::Parentheses) => format!("({})", body),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn money() -> NumberFormat {
+        NumberFormat {
+            decimals: Some(2),
+            thousands: ",".to_string(),
+            prefix: "$".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn groups_and_pads() {
+        assert_eq!(format("1234567.5", &money()).unwrap(), "$1,234,567.50");
+        assert_eq!(format("999", &money()).unwrap(), "$999.00");
+        assert_eq!(format("0.5", &money()).unwrap(), "$0.50");
+    }
+
+    #[test]
+    fn rounds_the_decimal_digits_half_away_from_zero() {
+        assert_eq!(format("2.675", &money()).unwrap(), "$2.68");
+        assert_eq!(format("-2.675", &money()).unwrap(), "-$2.68");
+        assert_eq!(format("999.995", &money()).unwrap(), "$1,000.00");
+        assert_eq!(format("-0.001", &money()).unwrap(), "$0.00");
+    }
+
+    #[test]
+    fn negative_styles() {
+        let fmt = NumberFormat { negative: NegativeStyle::Parentheses, suffix: " USD".to_string(), ..money() };
+        assert_eq!(format("-1234", &fmt).unwrap(), "($1,234.00 USD)");
+    }
+
+    #[test]
+    fn keeps_own_decimals_without_a_fixed_count() {
+        let fmt = NumberFormat::default();
+        assert_eq!(format("1234.250", &fmt).unwrap(), "1234.25");
+        assert_eq!(format("1.5E+3", &fmt).unwrap(), "1500");
+        assert_eq!(format("12e-3", &fmt).unwrap(), "0.012");
+    }
+
+    #[test]
+    fn locales() {
+        assert_eq!(locale_separators("de_DE"), Some((".", ",")));
+        assert_eq!(locale_separators("en-GB"), Some((",", ".")));
+        assert_eq!(locale_separators("de_CH"), Some(("\u{2019}", ".")));
+        assert_eq!(locale_separators("xx"), None);
+    }
+
+    #[test]
+    fn rejects_non_numbers() {
+        assert!(format("inf", &money()).is_err());
+        assert!(format("1.2.3", &money()).is_err());
+        assert!(format("", &money()).is_err());
+        assert!(format("1e100000", &money()).is_err());
+    }
+}
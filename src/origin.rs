@@ -0,0 +1,382 @@
+//! Bottom-left coordinates (`origin: "bottom_left"`).
+//!
+//! A document may measure y up from the page's bottom-left corner, as PDF
+//! itself does. Everything after parsing works in top-left coordinates,
+//! so `flip` moves every element there once: after resources are loaded,
+//! since an image sized by one side needs its aspect ratio, and before
+//! tables are paginated.
+//!
+//! Points flip about the page's height: text baselines, line ends,
+//! polygon points, ring centers, leader and signature rules, and textbox
+//! and table anchors. A box placed by its corner (rect, image, barcode,
+//! QR and Data Matrix symbols, check, note, placeholder) is placed by its
+//! bottom-left corner instead of its top-left, so it also moves by its
+//! height. Group and fragment contents flip about their origin (a group that
+//! leaves its children in place flips them with the page), and radial
+//! gradient centers and rect rotation origins about the middle of the
+//! rect or page they belong to. A rotated image or barcode keeps turning
+//! about the anchor it was placed by, on its bottom edge. An image `quad`
+//! names the image's bottom-left, bottom-right and top-left. A grid still
+//! covers the page but counts its rows up from the bottom edge.
+
+use crate::error::Result;
+use crate::pdf::PdfGenerator;
+use crate::resources::LoadedResources;
+use crate::types::*;
+use crate::units::Origin;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Move a bottom-left document's elements to top-left coordinates. Does
+/// nothing for a top-left document.
+pub fn flip(doc: &mut Document, resources: &LoadedResources) -> Result<()> {
+    if doc.origin == Origin::TopLeft {
+        return Ok(());
+    }
+    // Each fragment is flipped once, however often it is placed
+    let mut fragments = HashMap::new();
+    for page in &mut doc.pages {
+        if let PageBackground::Gradient(gradient) = &mut page.background {
+            gradient.flip_center(page.height);
+        }
+        for element in &mut page.elements {
+            flip_element(element, page.height, resources, &mut fragments)?;
+        }
+    }
+
+    // A repeating element keeps its distance from the bottom of pages of
+    // any height, so it needs a copy for each height it is drawn at
+    let mut repeating = Vec::with_capacity(doc.repeating.len());
+    for mut item in std::mem::take(&mut doc.repeating) {
+        let mut by_height: Vec<(f32, Vec<usize>)> = Vec::new();
+        for (i, page) in doc.pages.iter().enumerate().filter(|&(i, _)| item.pages.includes(i)) {
+            match by_height.iter_mut().find(|(height, _)| *height == page.height) {
+                Some((_, pages)) => pages.push(i),
+                None => by_height.push((page.height, vec![i])),
+            }
+        }
+        if by_height.len() == 1 {
+            flip_element(&mut item.element, by_height[0].0, resources, &mut fragments)?;
+            repeating.push(item);
+            continue;
+        }
+        // Drawn nowhere when there are no heights
+        for (height, pages) in by_height {
+            let mut copy = item.clone();
+            copy.pages = PageFilter { only: Some(pages), except: Vec::new() };
+            flip_element(&mut copy.element, height, resources, &mut fragments)?;
+            repeating.push(copy);
+        }
+    }
+    doc.repeating = repeating;
+
+    // Laid out around its placement point, which is a fraction of the
+    // page's height up from the bottom
+    if let Some(watermark) = &mut doc.watermark {
+        flip_element(&mut watermark.element, 0.0, resources, &mut fragments)?;
+        watermark.placement.1 = 1.0 - watermark.placement.1;
+    }
+    // Laid out from their origin, which keeps its distance from the edge
+    for region in [&mut doc.header, &mut doc.footer].into_iter().flatten() {
+        for element in &mut region.elements {
+            flip_element(element, 0.0, resources, &mut fragments)?;
+        }
+    }
+    Ok(())
+}
+
+/// Move a box turned `rotation` degrees about its top-left anchor `(x, y)`
+/// so it lands where the same turn about the anchor `h` below, on its
+/// bottom edge, puts it
+fn turn_about_bottom(rotation: f32, h: f32, x: &mut f32, y: &mut f32) {
+    if rotation != 0.0 {
+        let (dx, dy) = rotate_point(rotation, (0.0, h), (0.0, 0.0));
+        *x -= dx;
+        *y += h - dy;
+    }
+}
+
+/// Flip `element` on a page `height` tall; 0 flips about the origin.
+fn flip_element(
+    element: &mut Element,
+    height: f32,
+    resources: &LoadedResources,
+    fragments: &mut HashMap<String, Arc<Fragment>>,
+) -> Result<()> {
+    let point = |y: f32| height - y;
+    match element {
+        Element::Text(t) => t.y = point(t.y),
+        Element::TextBox(tb) => tb.y = point(tb.y),
+        Element::Rect(r) => {
+            r.y = point(r.y) - r.h;
+            if let Some(gradient) = &mut r.gradient {
+                gradient.flip_center(r.h.abs());
+            }
+            if let RotationOrigin::Point(_, y) = &mut r.rotation_origin {
+                *y = r.h.abs() - *y;
+            }
+        }
+        Element::Line(l) => {
+            l.y1 = point(l.y1);
+            l.y2 = point(l.y2);
+        }
+        Element::Polygon(p) => {
+            for (_, y) in &mut p.points {
+                *y = point(*y);
+            }
+        }
+        Element::Leader(l) => l.y = point(l.y),
+        Element::ProgressRing(r) => r.cy = point(r.cy),
+        // Given as the image's bottom-left, bottom-right and top-left
+        Element::Image(ImageElement { quad: Some(quad), x, y, .. }) => {
+            let flip = |(px, py): (f32, f32)| (px, point(py));
+            let (bottom_left, bottom_right, top_left) = (flip(quad.origin), flip(quad.x_corner), flip(quad.y_corner));
+            quad.origin = top_left;
+            quad.x_corner = (bottom_right.0 + top_left.0 - bottom_left.0, bottom_right.1 + top_left.1 - bottom_left.1);
+            quad.y_corner = bottom_left;
+            (*x, *y) = quad.origin;
+        }
+        Element::Image(img) => {
+            // A hidden image's source isn't looked up, and it isn't drawn
+            let h = match img.h {
+                Some(h) => h,
+                None if !img.visible => 0.0,
+                None => {
+                    let (src_w, src_h) = resources.get_image(&img.image_ref)?.dimensions();
+                    PdfGenerator::compute_image_dimensions(src_w, src_h, img.w, None).1
+                }
+            };
+            img.y = point(img.y) - h;
+            turn_about_bottom(img.rotation, h, &mut img.x, &mut img.y);
+        }
+        Element::Barcode(b) => {
+            b.y = point(b.y) - b.h;
+            turn_about_bottom(b.rotation, b.h, &mut b.x, &mut b.y);
+        }
+        Element::QRCode(qr) => qr.y = point(qr.y) - qr.size,
+        Element::DataMatrix(dm) => dm.y = point(dm.y) - dm.size,
+        // A table grows down from its top edge
+        Element::Table(t) => {
+            t.y = point(t.y);
+            t.continued_y = t.continued_y.map(point);
+        }
+        Element::Check(c) => c.y = point(c.y) - c.size,
+        Element::Note(n) => n.y = point(n.y) - NoteElement::ICON_SIZE,
+        Element::SignatureLine(s) => s.y = point(s.y),
+        Element::ImagePlaceholder(p) => p.y = point(p.y) - p.h,
+        // Covers the page; only its rows and labels count from the bottom
+        Element::Grid(g) => g.from_bottom = true,
+        // A group left in place keeps drawing its children where they are,
+        // flipped with the page; one that moves them flips them about its
+        // origin
+        Element::Group(g) if g.is_identity() => {
+            for child in &mut g.elements {
+                flip_element(child, height, resources, fragments)?;
+            }
+        }
+        Element::Group(g) => {
+            g.y = point(g.y);
+            for child in &mut g.elements {
+                flip_element(child, 0.0, resources, fragments)?;
+            }
+        }
+        Element::Fragment(f) => {
+            f.y = point(f.y);
+            f.fragment = flip_fragment(&f.fragment, resources, fragments)?;
+        }
+        // Already in PDF user space
+        Element::RawContent(_) => {}
+    }
+    Ok(())
+}
+
+/// The fragment with its elements flipped about its origin, shared by
+/// every placement.
+fn flip_fragment(
+    fragment: &Arc<Fragment>,
+    resources: &LoadedResources,
+    fragments: &mut HashMap<String, Arc<Fragment>>,
+) -> Result<Arc<Fragment>> {
+    if let Some(flipped) = fragments.get(&fragment.name) {
+        return Ok(flipped.clone());
+    }
+    let mut elements = fragment.elements.clone();
+    for element in &mut elements {
+        flip_element(element, 0.0, resources, fragments)?;
+    }
+    let flipped = Arc::new(Fragment { name: fragment.name.clone(), elements });
+    fragments.insert(fragment.name.clone(), flipped.clone());
+    Ok(flipped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::Units;
+
+    fn line(y1: f32, y2: f32) -> Element {
+        Element::Line(LineElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x1: 72.0,
+            y1,
+            x2: 144.0,
+            y2,
+            stroke: 1.0,
+            hairline: false,
+            color: Color::black(),
+            dash: None,
+            cap: None,
+            join: None,
+            miter_limit: None,
+        })
+    }
+
+    fn page(height: f32, elements: Vec<Element>) -> Page {
+        Page { width: 612.0, height, background: PageBackground::Color(Color::white()), repeat: 1, elements, source: 0 }
+    }
+
+    fn bottom_left_doc(pages: Vec<Page>, repeating: Vec<RepeatingElement>) -> Document {
+        Document {
+            metadata: Metadata::default(),
+            pages,
+            repeating,
+            layers: Vec::new(),
+            resources: Resources::default(),
+            open_action: None,
+            origin: Origin::BottomLeft,
+            units: Units::default(),
+            watermark: None,
+            header: None,
+            footer: None,
+        }
+    }
+
+    fn ends(element: &Element) -> (f32, f32) {
+        match element {
+            Element::Line(l) => (l.y1, l.y2),
+            other => panic!("not a line: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_points_flip_about_the_page_height() {
+        let mut doc = bottom_left_doc(vec![page(792.0, vec![line(100.0, 700.0)])], Vec::new());
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        flip(&mut doc, &resources).unwrap();
+        assert_eq!(ends(&doc.pages[0].elements[0]), (692.0, 92.0));
+
+        // A top-left document is left alone
+        doc.origin = Origin::TopLeft;
+        flip(&mut doc, &resources).unwrap();
+        assert_eq!(ends(&doc.pages[0].elements[0]), (692.0, 92.0));
+    }
+
+    #[test]
+    fn test_repeating_element_is_copied_per_page_height() {
+        let footer = RepeatingElement { element: line(20.0, 20.0), pages: PageFilter::default(), under_content: false, page_numbers: false };
+        let pages = vec![page(792.0, Vec::new()), page(612.0, Vec::new()), page(792.0, Vec::new())];
+        let mut doc = bottom_left_doc(pages, vec![footer]);
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        flip(&mut doc, &resources).unwrap();
+
+        // 20pt above the bottom of each page
+        assert_eq!(doc.repeating.len(), 2);
+        assert_eq!(doc.repeating[0].pages.only, Some(vec![0, 2]));
+        assert_eq!(ends(&doc.repeating[0].element), (772.0, 772.0));
+        assert_eq!(doc.repeating[1].pages.only, Some(vec![1]));
+        assert_eq!(ends(&doc.repeating[1].element), (592.0, 592.0));
+    }
+
+    #[test]
+    fn test_fragment_flips_about_its_origin_once() {
+        let fragment = Arc::new(Fragment { name: "mark".to_string(), elements: vec![line(0.0, 10.0)] });
+        let place = |y: f32| {
+            Element::Fragment(FragmentElement {
+                id: None,
+                tagging: Tagging::default(),
+                z: 0,
+                visible: true,
+                fragment: fragment.clone(),
+                x: 0.0,
+                y,
+                scale: 1.0,
+            })
+        };
+        let mut doc = bottom_left_doc(vec![page(792.0, vec![place(100.0), place(200.0)])], Vec::new());
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        flip(&mut doc, &resources).unwrap();
+
+        let Element::Fragment(first) = &doc.pages[0].elements[0] else { unreachable!() };
+        let Element::Fragment(second) = &doc.pages[0].elements[1] else { unreachable!() };
+        assert_eq!((first.y, second.y), (692.0, 592.0));
+        // Drawn upward from the placement, and still one fragment
+        assert_eq!(ends(&first.fragment.elements[0]), (0.0, -10.0));
+        assert!(Arc::ptr_eq(&first.fragment, &second.fragment));
+    }
+
+    #[test]
+    fn test_group_left_in_place_flips_its_children_with_the_page() {
+        let group = |y: f32| {
+            Element::Group(GroupElement {
+                id: None,
+                tagging: Tagging::default(),
+                z: 0,
+                visible: true,
+                elements: vec![line(100.0, 110.0)],
+                x: 0.0,
+                y,
+                rotation: 0.0,
+                scale: 1.0,
+                transparency_group: false,
+                opacity: 1.0,
+            })
+        };
+        let mut doc = bottom_left_doc(vec![page(792.0, vec![group(0.0), group(50.0)])], Vec::new());
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        flip(&mut doc, &resources).unwrap();
+
+        let Element::Group(still) = &doc.pages[0].elements[0] else { unreachable!() };
+        let Element::Group(moved) = &doc.pages[0].elements[1] else { unreachable!() };
+        assert!(still.is_identity());
+        assert_eq!(ends(&still.elements[0]), (692.0, 682.0));
+        assert_eq!(moved.y, 742.0);
+        assert_eq!(ends(&moved.elements[0]), (-100.0, -110.0));
+    }
+
+    #[test]
+    fn test_image_quad_names_the_bottom_corners() {
+        // Bottom-left (100, 100), bottom-right (300, 100), top-left (150, 200)
+        let quad = ImageQuad { origin: (100.0, 100.0), x_corner: (300.0, 100.0), y_corner: (150.0, 200.0) };
+        let image = Element::Image(ImageElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 100.0,
+            y: 100.0,
+            w: Some(200.0),
+            h: None,
+            image_ref: "hero".to_string(),
+            align: TextAlign::Left,
+            fade: None,
+            rotation: 0.0,
+            quad: Some(quad),
+            clip: None,
+            border_width: 0.0,
+            border_color: Color::black(),
+        });
+        let mut doc = bottom_left_doc(vec![page(792.0, vec![image])], Vec::new());
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        flip(&mut doc, &resources).unwrap();
+
+        let Element::Image(img) = &doc.pages[0].elements[0] else { unreachable!() };
+        let quad = img.quad.unwrap();
+        assert_eq!(quad.origin, (150.0, 592.0));
+        assert_eq!(quad.x_corner, (350.0, 592.0));
+        assert_eq!(quad.y_corner, (100.0, 692.0));
+        assert_eq!((img.x, img.y), (150.0, 592.0));
+    }
+}
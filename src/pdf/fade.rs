@@ -0,0 +1,121 @@
+//! Image fades as luminosity soft masks.
+//!
+//! A fade is a white-to-black axial shading painted into a small Form
+//! XObject, used as the `/SMask` of an ExtGState: white keeps the image
+//! opaque, black makes it transparent. Masks are built in box-local
+//! coordinates (origin at the placed image's bottom-left corner), so one
+//! mask serves every image with the same fade and size. Callers translate
+//! to the image origin before selecting the ExtGState.
+
+use crate::pdf::refs::RefRegistry;
+use crate::pdf::shading::{self, ShadingRegistry};
+use crate::types::ImageFade;
+use pdf_writer::types::MaskType;
+use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+struct FadeEntry {
+    name: String,
+    shading: String,
+    w: f32,
+    h: f32,
+    state_ref: Ref,
+    form_ref: Ref,
+}
+
+/// Fade masks used by the document, collected during the first pass.
+#[derive(Default)]
+pub struct FadeMasks {
+    entries: Vec<FadeEntry>,
+    by_name: HashMap<String, usize>,
+}
+
+impl FadeMasks {
+    /// ExtGState resource name for `fade` over a `w × h` image. Derived
+    /// from the fade and size alone so renderers need no registry access.
+    pub fn name(fade: &ImageFade, w: f32, h: f32) -> String {
+        let mut hasher = DefaultHasher::new();
+        format!("{:?}_{:.2}x{:.2}", fade, w, h).hash(&mut hasher);
+        format!("Fade{:016x}", Hasher::finish(&hasher))
+    }
+
+    /// Register a fade over a `w × h` image, allocating its shading and
+    /// objects on first use.
+    pub fn register(
+        &mut self,
+        fade: &ImageFade,
+        w: f32,
+        h: f32,
+        shadings: &mut ShadingRegistry,
+        refs: &mut RefRegistry,
+    ) {
+        let name = Self::name(fade, w, h);
+        if self.by_name.contains_key(&name) {
+            return;
+        }
+        let shading = shadings.register(&fade.gradient(), w, h, refs);
+        self.by_name.insert(name.clone(), self.entries.len());
+        self.entries.push(FadeEntry {
+            shading,
+            w,
+            h,
+            state_ref: refs.alloc(format!("fade[{}]", name)),
+            form_ref: refs.alloc(format!("fade[{}].form", name)),
+            name,
+        });
+    }
+
+    /// (resource name, ExtGState ref) pairs for resource dictionaries.
+    pub fn ext_g_states(&self) -> impl Iterator<Item = (String, Ref)> + '_ {
+        self.entries.iter().map(|e| (e.name.clone(), e.state_ref))
+    }
+
+    /// Write each mask form and the ExtGState that selects it. Shadings
+    /// are written by the registry they were registered with.
+    pub fn write(&self, pdf: &mut Pdf, shadings: &ShadingRegistry) {
+        for entry in &self.entries {
+            let mut content = Content::new();
+            shading::paint(&mut content, &entry.shading, 0.0, 0.0, entry.w, entry.h);
+            let data = content.finish();
+
+            let mut form = pdf.form_xobject(entry.form_ref, &data);
+            form.bbox(Rect::new(0.0, 0.0, entry.w, entry.h));
+            form.group().transparency().color_space().device_rgb();
+            let mut resources = form.resources();
+            let mut sh = resources.shadings();
+            for (name, shading_ref) in shadings.resources().filter(|(n, _)| *n == entry.shading) {
+                sh.pair(Name(name.as_bytes()), shading_ref);
+            }
+            sh.finish();
+            resources.finish();
+            form.finish();
+
+            let mut gs = pdf.ext_graphics(entry.state_ref);
+            gs.soft_mask().subtype(MaskType::Luminosity).group(entry.form_ref);
+            gs.finish();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::FadeEdge;
+
+    #[test]
+    fn registry_dedupes_by_fade_and_size() {
+        let fade = ImageFade { edge: FadeEdge::Bottom, start: 0.5, end: 1.0 };
+        let mut alloc = RefRegistry::default();
+        let mut shadings = ShadingRegistry::default();
+        let mut masks = FadeMasks::default();
+        masks.register(&fade, 100.0, 50.0, &mut shadings, &mut alloc);
+        masks.register(&fade, 100.0, 50.0, &mut shadings, &mut alloc);
+        masks.register(&fade, 100.0, 60.0, &mut shadings, &mut alloc);
+        let names: Vec<String> = masks.ext_g_states().map(|(n, _)| n).collect();
+        assert_eq!(names.len(), 2);
+        assert_eq!(names[0], FadeMasks::name(&fade, 100.0, 50.0));
+        assert_ne!(names[0], names[1]);
+    }
+}
@@ -0,0 +1,62 @@
+//! Layout fingerprints, for `render_layout_fingerprint`.
+//!
+//! Every position rendering computes ends up in a content stream: wrapped
+//! lines and their baselines, kerning, image placements, barcode bars. A
+//! hash of the streams as drawn, before optimizing and compressing, moves
+//! whenever the layout does, and leaves out the document info, dates and
+//! the objects fonts and images are embedded in. FNV-1a is used rather
+//! than `DefaultHasher` so the result is the same across runs, platforms
+//! and Rust releases.
+
+const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fingerprint(u64);
+
+impl Default for Fingerprint {
+    fn default() -> Self {
+        Self(OFFSET_BASIS)
+    }
+}
+
+impl Fingerprint {
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= u64::from(b);
+            self.0 = self.0.wrapping_mul(PRIME);
+        }
+    }
+
+    /// Add one stream, length first so adjacent streams can't run together
+    pub fn add(&mut self, bytes: &[u8]) {
+        self.write(&(bytes.len() as u64).to_le_bytes());
+        self.write(bytes);
+    }
+
+    pub fn hex(&self) -> String {
+        format!("{:016x}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_reference_fnv1a() {
+        let mut f = Fingerprint::default();
+        f.write(b"a");
+        assert_eq!(f.hex(), "af63dc4c8601ec8c");
+    }
+
+    #[test]
+    fn stream_boundaries_count() {
+        let mut joined = Fingerprint::default();
+        joined.add(b"ab");
+        let mut split = Fingerprint::default();
+        split.add(b"a");
+        split.add(b"b");
+        assert_ne!(joined, split);
+    }
+}
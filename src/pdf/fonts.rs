@@ -1,5 +1,6 @@
 use crate::error::{Result, RupdfError};
 use crate::resources::LoadedFont;
+use crate::pdf::stats::FontStats;
 use pdf_writer::types::{CidFontType, FontFlags, SystemInfo, UnicodeCmap};
 use pdf_writer::{Filter, Finish, Name, Pdf, Ref, Str};
 use std::collections::{BTreeMap, HashSet};
@@ -11,10 +12,12 @@ use std::collections::{BTreeMap, HashSet};
 /// via `crate::runs::resolve` and register the resolved glyphs here.
 pub struct FontEmbedder<'a> {
     font: &'a LoadedFont,
-    #[allow(dead_code)]
     font_name: &'a str,
     used_glyphs: HashSet<u16>,
     char_to_glyph: BTreeMap<char, u16>,
+    /// Glyph registered for each ASCII char, so the common case skips
+    /// both sets
+    ascii_glyphs: [Option<u16>; 128],
 }
 
 impl<'a> FontEmbedder<'a> {
@@ -24,17 +27,42 @@ impl<'a> FontEmbedder<'a> {
             font_name,
             used_glyphs: HashSet::new(),
             char_to_glyph: BTreeMap::new(),
+            ascii_glyphs: [None; 128],
         }
     }
 
+    /// Whether the font program is embedded, needing a FontFile object;
+    /// false for fonts loaded with `embed: false`
+    pub fn embeds_program(&self) -> bool {
+        self.font.embed
+    }
+
     /// Register a (char, glyph_id) pair as used. The glyph id must already
     /// be resolved against this embedder's font.
     pub fn use_glyph(&mut self, ch: char, glyph_id: u16) {
+        if let Some(seen) = self.ascii_glyphs.get_mut(ch as usize) {
+            if *seen == Some(glyph_id) {
+                return;
+            }
+            *seen = Some(glyph_id);
+        }
         self.used_glyphs.insert(glyph_id);
         self.char_to_glyph.insert(ch, glyph_id);
     }
 
-    /// Embed the font into the PDF and return the font dictionary reference
+    /// Register a glyph shown by id alone, as `raw_content` text is. With
+    /// no char, it is left out of the ToUnicode map.
+    pub fn use_glyph_id(&mut self, glyph_id: u16) {
+        self.used_glyphs.insert(glyph_id);
+    }
+
+    /// Write the font dictionaries into the PDF. Fonts loaded with
+    /// `embed: false` reference the PostScript name only: they get no
+    /// `font_file_ref` and no font program is written, but widths and the
+    /// ToUnicode map are kept so layout and text extraction still work.
+    /// The font program is deflated unless `compress` is false.
+    /// Returns what was embedded, for `RenderStats`.
+    #[allow(clippy::too_many_arguments)]
     pub fn embed(
         &self,
         pdf: &mut Pdf,
@@ -42,11 +70,9 @@ impl<'a> FontEmbedder<'a> {
         cid_font_ref: Ref,
         descriptor_ref: Ref,
         cmap_ref: Ref,
-        font_file_ref: Ref,
-    ) -> Result<()> {
-        // Subset the font
-        let subset_data = self.subset_font()?;
-
+        font_file_ref: Option<Ref>,
+        compress: bool,
+    ) -> Result<FontStats> {
         // Build glyph widths array
         let widths = self.build_widths();
 
@@ -55,7 +81,19 @@ impl<'a> FontEmbedder<'a> {
         pdf.stream(cmap_ref, &cmap_data);
 
         // Write font file stream
-        pdf.stream(font_file_ref, &subset_data).filter(Filter::FlateDecode);
+        let mut program_size = 0;
+        if let Some(font_file_ref) = font_file_ref {
+            let subset_data = self.subset_font()?;
+            if compress {
+                // Compress with zlib (not raw deflate) - PDF FlateDecode expects zlib format
+                let compressed = miniz_oxide::deflate::compress_to_vec_zlib(&subset_data, 6);
+                pdf.stream(font_file_ref, &compressed).filter(Filter::FlateDecode);
+                program_size = compressed.len();
+            } else {
+                pdf.stream(font_file_ref, &subset_data);
+                program_size = subset_data.len();
+            }
+        }
 
         // Write font descriptor - use actual PostScript name for compatibility
         let ps_name = &self.font.postscript_name;
@@ -66,9 +104,11 @@ impl<'a> FontEmbedder<'a> {
         descriptor.italic_angle(0.0);
         descriptor.ascent(self.font.ascender as f32);
         descriptor.descent(self.font.descender as f32);
-        descriptor.cap_height(self.font.ascender as f32 * 0.8);
+        descriptor.cap_height(self.font.cap_height as f32);
         descriptor.stem_v(80.0);
-        descriptor.font_file2(font_file_ref);
+        if let Some(font_file_ref) = font_file_ref {
+            descriptor.font_file2(font_file_ref);
+        }
         descriptor.finish();
 
         // Write CID font
@@ -87,7 +127,7 @@ impl<'a> FontEmbedder<'a> {
         if !widths.is_empty() {
             let mut w = cid_font.widths();
             for (glyph_id, width) in &widths {
-                w.consecutive(*glyph_id, [*width as f32].into_iter());
+                w.consecutive(*glyph_id, [*width as f32]);
             }
             w.finish();
         }
@@ -102,25 +142,29 @@ impl<'a> FontEmbedder<'a> {
         type0.to_unicode(cmap_ref);
         type0.finish();
 
-        Ok(())
+        Ok(FontStats {
+            name: self.font_name.to_string(),
+            postscript_name: ps_name.clone(),
+            glyphs: self.used_glyphs.len(),
+            embedded: self.font.embed,
+            bytes: program_size,
+        })
     }
 
     fn subset_font(&self) -> Result<Vec<u8>> {
-        // Collect glyph IDs to keep
-        let glyph_ids: Vec<u16> = self.used_glyphs.iter().copied().collect();
+        // Collect glyph IDs to keep, in order so the subset is reproducible
+        let mut glyph_ids: Vec<u16> = self.used_glyphs.iter().copied().collect();
+        glyph_ids.sort_unstable();
 
         // Use subsetter to create subset
         let profile = subsetter::Profile::pdf(&glyph_ids);
-        let subset = subsetter::subset(&self.font.data, 0, profile).map_err(|e| {
+        let subset = subsetter::subset(self.font.data(), 0, profile).map_err(|e| {
             RupdfError::InvalidFont(
                 self.font_name.to_string(),
                 format!("Failed to subset font: {:?}", e),
             )
         })?;
-
-        // Compress with zlib (not raw deflate) - PDF FlateDecode expects zlib format
-        let compressed = miniz_oxide::deflate::compress_to_vec_zlib(&subset, 6);
-        Ok(compressed)
+        Ok(subset)
     }
 
     fn build_widths(&self) -> Vec<(u16, u16)> {
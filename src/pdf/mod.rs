@@ -1,14 +1,26 @@
+mod fade;
+mod fingerprint;
 mod fonts;
+mod optimize;
+mod outline;
+mod pattern;
+mod raster;
+mod refs;
+mod shading;
+mod stats;
+mod text_cache;
 mod writer;
 
 pub use fonts::{encode_glyphs, FontEmbedder};
-pub use writer::PdfGenerator;
+pub use stats::RenderStats;
+pub use writer::{PageProgress, PdfGenerator};
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::resources::LoadedResources;
+    use crate::resources::{LoadedImage, LoadedResources};
     use crate::types::*;
+    use crate::units::{Origin, Units};
 
     fn make_empty_doc() -> Document {
         Document {
@@ -16,10 +28,20 @@ mod tests {
             pages: vec![Page {
                 width: 612.0,
                 height: 792.0,
-                background: Color::white(),
+                background: PageBackground::Color(Color::white()),
+                repeat: 1,
                 elements: vec![],
+                source: 0,
             }],
             resources: Resources::default(),
+            repeating: vec![],
+            layers: vec![],
+            open_action: None,
+            origin: Origin::TopLeft,
+            units: Units::default(),
+            watermark: None,
+            header: None,
+            footer: None,
         }
     }
 
@@ -29,21 +51,48 @@ mod tests {
             pages: vec![Page {
                 width: 612.0,
                 height: 792.0,
-                background: Color::white(),
+                background: PageBackground::Color(Color::white()),
+                repeat: 1,
+                source: 0,
                 elements: vec![
                     Element::Rect(RectElement {
+                        id: None,
+                        tagging: Tagging::default(),
+                        z: 0,
+                        visible: true,
                         x: 72.0,
                         y: 72.0,
                         w: 100.0,
                         h: 50.0,
                         stroke: 1.0,
+                        hairline: false,
                         stroke_color: Color::black(),
-                        fill_color: Some(Color { r: 200, g: 200, b: 255, a: 255 }),
-                        corner_radius: 0.0,
+                        fill_color: Some(Color::rgba(200, 200, 255, 255)),
+                        fill_pattern: None,
+                        gradient: None,
+                        corner_radius: CornerRadii::default(),
+                        corner_style: CornerStyle::Circular,
+                        sides: None,
+                        dash: None,
+                        miter_limit: None,
+                        stroke_align: StrokeAlign::Center,
+                        skew: Skew::default(),
+                        rotation: 0.0,
+                        rotation_origin: RotationOrigin::Center,
+                        cap: None,
+                        join: None,
                     }),
                 ],
             }],
             resources: Resources::default(),
+            repeating: vec![],
+            layers: vec![],
+            open_action: None,
+            origin: Origin::TopLeft,
+            units: Units::default(),
+            watermark: None,
+            header: None,
+            footer: None,
         }
     }
 
@@ -53,19 +102,38 @@ mod tests {
             pages: vec![Page {
                 width: 612.0,
                 height: 792.0,
-                background: Color::white(),
+                background: PageBackground::Color(Color::white()),
+                repeat: 1,
+                source: 0,
                 elements: vec![
                     Element::Line(LineElement {
+                        id: None,
+                        tagging: Tagging::default(),
+                        z: 0,
+                        visible: true,
                         x1: 72.0,
                         y1: 72.0,
                         x2: 200.0,
                         y2: 150.0,
                         stroke: 2.0,
-                        color: Color { r: 255, g: 0, b: 0, a: 255 },
+                        hairline: false,
+                        color: Color::rgba(255, 0, 0, 255),
+                        dash: None,
+                        cap: None,
+                        join: None,
+                        miter_limit: None,
                     }),
                 ],
             }],
             resources: Resources::default(),
+            repeating: vec![],
+            layers: vec![],
+            open_action: None,
+            origin: Origin::TopLeft,
+            units: Units::default(),
+            watermark: None,
+            header: None,
+            footer: None,
         }
     }
 
@@ -80,23 +148,37 @@ mod tests {
                 Page {
                     width: 612.0,
                     height: 792.0,
-                    background: Color::white(),
+                    background: PageBackground::Color(Color::white()),
+                    repeat: 1,
                     elements: vec![],
+                    source: 0,
                 },
                 Page {
                     width: 612.0,
                     height: 792.0,
-                    background: Color { r: 240, g: 240, b: 255, a: 255 },
+                    background: PageBackground::Color(Color::rgba(240, 240, 255, 255)),
+                    repeat: 1,
                     elements: vec![],
+                    source: 1,
                 },
                 Page {
                     width: 595.0,
                     height: 842.0,
-                    background: Color::white(),
+                    background: PageBackground::Color(Color::white()),
+                    repeat: 1,
                     elements: vec![],
+                    source: 2,
                 },
             ],
             resources: Resources::default(),
+            repeating: vec![],
+            layers: vec![],
+            open_action: None,
+            origin: Origin::TopLeft,
+            units: Units::default(),
+            watermark: None,
+            header: None,
+            footer: None,
         }
     }
 
@@ -147,6 +229,92 @@ mod tests {
         assert!(pdf_str.contains("Multi-Page Test"), "Should have title");
     }
 
+    #[test]
+    fn test_generate_with_reports_each_page() {
+        let mut doc = make_multi_page_doc();
+        doc.pages[1].repeat = 2;
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let generator = PdfGenerator::new(&doc, &resources, false);
+
+        let mut seen = Vec::new();
+        let pdf = generator.generate_with(|page| {
+            seen.push(page);
+            Ok(())
+        });
+        assert!(pdf.unwrap().starts_with(b"%PDF-"));
+        let copies: Vec<(usize, usize, usize)> = seen.iter().map(|p| (p.index, p.copies, p.total)).collect();
+        assert_eq!(copies, vec![(0, 1, 3), (1, 2, 3), (2, 1, 3)]);
+
+        // An error from the callback stops generation
+        let mut calls = 0;
+        let result = generator.generate_with(|_| {
+            calls += 1;
+            Err(crate::error::RupdfError::PdfError("stop".to_string()))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_repeated_page_shares_content() {
+        let mut doc = make_rect_doc();
+        doc.pages[0].repeat = 3;
+        doc.pages.push(make_empty_doc().pages.remove(0));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        assert!(pdf_str.contains("/Count 4"));
+        assert_eq!(pdf_str.matches("/Type /Page\n").count(), 4);
+        let single = make_rect_doc();
+        let single_pdf = PdfGenerator::new(&single, &resources, false).generate().unwrap();
+        assert_eq!(
+            pdf_str.matches(" re\n").count(),
+            String::from_utf8_lossy(&single_pdf).matches(" re\n").count(),
+            "The repeated page's content is written once"
+        );
+        // The three copies reference one resource dictionary; the last page has its own
+        assert_eq!(pdf_str.matches("/Resources <<").count(), 1);
+    }
+
+    #[test]
+    fn test_page_objects_locate_content_streams() {
+        let mut doc = make_rect_doc();
+        doc.pages[0].repeat = 2;
+        let mut second = make_empty_doc().pages.remove(0);
+        second.source = 1;
+        doc.pages.push(second);
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let generator = PdfGenerator::new(&doc, &resources, true);
+        let pdf = generator.generate().unwrap();
+
+        let placed = generator.stats().page_objects;
+        assert_eq!(placed.iter().map(|p| p.page).collect::<Vec<_>>(), [0, 0, 1]);
+        assert_eq!(placed[0].content_object, placed[1].content_object, "Copies share their content");
+        assert_ne!(placed[0].object, placed[1].object);
+        for p in &placed {
+            let header = format!("{} 0 obj", p.content_object);
+            assert!(pdf[p.content_offset..].starts_with(header.as_bytes()), "{} at {}", header, p.content_offset);
+            let page = format!("\n{} 0 obj\n<<\n  /Type /Page\n", p.object);
+            assert!(String::from_utf8_lossy(&pdf).contains(&page));
+        }
+    }
+
+    #[test]
+    fn test_open_action_targets_repeated_copy() {
+        let mut doc = make_multi_page_doc();
+        doc.pages[0].repeat = 2;
+        doc.open_action = Some(OpenAction { page: 3, view: OpenView::Xyz { x: 72.0, y: 100.0, zoom: Some(1.5) } });
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        // Output page 3 is the 595 x 842 page, so y = 100 is 742 from the bottom
+        let kids = pdf_str.split("/Kids [").nth(1).unwrap();
+        let fourth = kids.split(" 0 R").nth(3).unwrap().trim();
+        assert!(pdf_str.contains(&format!("/OpenAction [{} 0 R /XYZ 72 742 1.5]", fourth)));
+    }
+
     #[test]
     fn test_compression_uses_flatedecode() {
         let doc = make_multi_page_doc();
@@ -158,6 +326,84 @@ mod tests {
         assert!(compressed.starts_with(b"%PDF-"));
     }
 
+    #[test]
+    fn test_debug_uncompressed_renders_are_identical() {
+        let mut doc = make_multi_page_doc();
+        for (alias, file) in [("sans", "IBMPlexSans-Regular.otf"), ("bold", "IBMPlexSans-Bold.otf"), ("mono", "IBMPlexMono-Regular.otf")] {
+            doc.resources.fonts.insert(alias.to_string(), asset_font(file));
+        }
+        doc.resources.images.insert(
+            "logo".to_string(),
+            ImageResource { source: ImageSource::Path(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/test-svg.svg").to_string()), frame: 0, profile: ImageProfile::Embed },
+        );
+        for (i, page) in doc.pages.iter_mut().enumerate() {
+            for (j, font) in ["sans", "bold", "mono"].into_iter().enumerate() {
+                page.elements.push(Element::Text(TextElement {
+                    id: None,
+                    tagging: Tagging::default(),
+                    z: 0,
+                    visible: true,
+                    x: 72.0,
+                    y: 72.0 + 24.0 * j as f32,
+                    text: format!("Page {} in {}", i + 1, font),
+                    font: font.to_string(),
+                    font_fallback: vec![],
+                    missing_glyph_policy: MissingGlyphPolicy::Drop,
+                    emoji: EmojiOptions::default(),
+                    size: 12.0,
+                    color: Color::black(),
+                    align: TextAlign::Left,
+                    vertical_anchor: VerticalAnchor::Baseline,
+                    highlight_color: None,
+                    highlight_padding: 1.0,
+                    transform: TextTransform::None,
+                    fit_width: None,
+                    max_width: None,
+                    skew: Skew::default(),
+                    rotation: 0.0,
+                    outline: false,
+                    styles: Vec::new(),
+                }));
+            }
+            for alpha in [255, 191, 127, 63] {
+                page.elements.push(filled_rect(alpha as f32, Color::rgba(0, 0, 255, alpha)));
+            }
+            page.elements.push(Element::Image(ImageElement {
+                id: None,
+                tagging: Tagging::default(),
+                z: 0,
+                visible: true,
+                x: 300.0,
+                y: 72.0,
+                w: Some(50.0),
+                h: Some(50.0),
+                image_ref: "logo".to_string(),
+                align: TextAlign::Left,
+                fade: None,
+                rotation: 0.0,
+                quad: None,
+                clip: None,
+                border_width: 0.0,
+                border_color: Color::black(),
+            }));
+        }
+
+        // Each render loads and hashes afresh, so nothing carries over
+        let render = || {
+            let resources = LoadedResources::load(&doc.resources).unwrap();
+            PdfGenerator::new(&doc, &resources, true).debug_uncompressed(true).generate().unwrap()
+        };
+        let first = render();
+        for _ in 0..4 {
+            assert!(render() == first, "renders differ");
+        }
+        let pdf_str = String::from_utf8_lossy(&first);
+        assert!(!pdf_str.contains("FlateDecode"), "nothing is deflated");
+        assert_eq!(pdf_str.matches("/FontFile2").count(), 3);
+        // The SVG form's paths are written as they are drawn
+        assert!(pdf_str.contains("/Subtype /Form\n  /BBox"), "{}", &pdf_str[..2000]);
+    }
+
     #[test]
     fn test_page_dimensions_in_mediabox() {
         let doc = Document {
@@ -165,10 +411,20 @@ mod tests {
             pages: vec![Page {
                 width: 595.0,
                 height: 842.0,
-                background: Color::white(),
+                background: PageBackground::Color(Color::white()),
+                repeat: 1,
                 elements: vec![],
+                source: 0,
             }],
             resources: Resources::default(),
+            repeating: vec![],
+            layers: vec![],
+            open_action: None,
+            origin: Origin::TopLeft,
+            units: Units::default(),
+            watermark: None,
+            header: None,
+            footer: None,
         };
         let resources = LoadedResources::load(&doc.resources).unwrap();
         let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
@@ -186,14 +442,3003 @@ mod tests {
             pages: vec![Page {
                 width: 612.0,
                 height: 792.0,
-                background: Color { r: 200, g: 220, b: 255, a: 255 },
+                background: PageBackground::Color(Color::rgba(200, 220, 255, 255)),
+                repeat: 1,
                 elements: vec![],
+                source: 0,
             }],
             resources: Resources::default(),
+            repeating: vec![],
+            layers: vec![],
+            open_action: None,
+            origin: Origin::TopLeft,
+            units: Units::default(),
+            watermark: None,
+            header: None,
+            footer: None,
         };
         let resources = LoadedResources::load(&doc.resources).unwrap();
         let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
 
         assert!(pdf.len() > 200, "Should have background content");
     }
+
+    fn single_page_doc(background: PageBackground, resources: Resources) -> Document {
+        Document {
+            metadata: Metadata::default(),
+            pages: vec![Page {
+                width: 612.0,
+                height: 792.0,
+                background,
+                repeat: 1,
+                elements: vec![],
+                source: 0,
+            }],
+            resources,
+            repeating: vec![],
+            layers: vec![],
+            open_action: None,
+            origin: Origin::TopLeft,
+            units: Units::default(),
+            watermark: None,
+            header: None,
+            footer: None,
+        }
+    }
+
+    #[test]
+    fn test_gradient_background_writes_axial_shading() {
+        let gradient = Gradient {
+            kind: GradientKind::Linear { angle: 90.0 },
+            stops: vec![
+                GradientStop { offset: 0.0, color: Color::white() },
+                GradientStop { offset: 0.5, color: Color::rgba(200, 220, 255, 255) },
+                GradientStop { offset: 1.0, color: Color::black() },
+            ],
+        };
+        let doc = single_page_doc(PageBackground::Gradient(gradient), Resources::default());
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        assert!(pdf_str.contains("/ShadingType 2"), "Should have axial shading");
+        assert!(pdf_str.contains("/FunctionType 3"), "Three stops need a stitching function");
+        assert!(pdf_str.contains("/Sh0 sh"), "Should paint the shading");
+    }
+
+    #[test]
+    fn test_layout_fingerprint_follows_content_only() {
+        let fingerprint = |doc: &Document| {
+            let resources = LoadedResources::load(&doc.resources).unwrap();
+            PdfGenerator::new(doc, &resources, true).layout_fingerprint().unwrap()
+        };
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), Resources::default());
+        doc.pages[0].elements.push(filled_rect(100.0, Color::black()));
+        let plain = fingerprint(&doc);
+        assert_eq!(plain.len(), 16);
+
+        doc.metadata.title = Some("Packing slip".to_string());
+        assert_eq!(fingerprint(&doc), plain, "Metadata isn't layout");
+
+        doc.pages[0].elements[0] = filled_rect(101.0, Color::black());
+        assert_ne!(fingerprint(&doc), plain, "Moving a rect is");
+    }
+
+    #[test]
+    fn test_rect_gradient_clips_to_outline() {
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), Resources::default());
+        let Element::Rect(mut rect) = filled_rect(100.0, Color::white()) else { unreachable!() };
+        rect.w = 200.0;
+        rect.h = 50.0;
+        rect.corner_radius = CornerRadii::from(8.0);
+        rect.gradient = Some(Gradient {
+            kind: GradientKind::Linear { angle: 0.0 },
+            stops: vec![
+                GradientStop { offset: 0.0, color: Color::rgba(255, 0, 0, 255) },
+                GradientStop { offset: 1.0, color: Color::rgba(0, 0, 255, 255) },
+            ],
+        });
+        doc.pages[0].elements.push(Element::Rect(rect));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        assert!(pdf_str.contains("/ShadingType 2"), "Should have axial shading");
+        // The rounded outline clips the shading, and the fill is painted under it
+        let clip = pdf_str.find("W\nn").expect("Should clip to the outline");
+        let paint = pdf_str.find("/Sh0 sh").expect("Should paint the shading");
+        assert!(clip < paint);
+        assert!(pdf_str[..clip].contains(" c\n"), "Clip should follow the rounded corners");
+        assert!(pdf_str[..clip].contains("f\n"), "Fill should come before the gradient");
+    }
+
+    #[test]
+    fn test_image_background_is_embedded() {
+        let mut res = Resources::default();
+        res.images.insert(
+            "letterhead".to_string(),
+            ImageResource { source: ImageSource::Path(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/test-png.png").to_string()), frame: 0, profile: ImageProfile::Embed },
+        );
+        let background = PageBackground::Image {
+            image_ref: "letterhead".to_string(),
+            fit: ImageFit::Cover,
+        };
+        let doc = single_page_doc(background, res);
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        assert!(pdf_str.contains("/Subtype /Image"), "Should embed the background image");
+        assert!(pdf_str.contains("/letterhead_"), "Should reference the sized XObject");
+    }
+
+    #[test]
+    fn test_repeating_elements_respect_page_filter() {
+        let blank = |w| Page {
+            width: w,
+            height: 792.0,
+            background: PageBackground::Color(Color::white()),
+            repeat: 1,
+            elements: vec![],
+            source: 0,
+        };
+        let mut res = Resources::default();
+        res.images.insert(
+            "logo".to_string(),
+            ImageResource { source: ImageSource::Path(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/test-png.png").to_string()), frame: 0, profile: ImageProfile::Embed },
+        );
+        let doc = Document {
+            metadata: Metadata::default(),
+            pages: vec![blank(612.0), blank(612.0), blank(612.0)],
+            resources: res,
+            repeating: vec![
+                RepeatingElement {
+                    element: Element::Line(LineElement {
+                        id: None,
+                        tagging: Tagging::default(),
+                        z: 0,
+                        visible: true,
+                        x1: 36.0,
+                        y1: 760.0,
+                        x2: 576.0,
+                        y2: 760.0,
+                        stroke: 0.25,
+                        hairline: false,
+                        color: Color::black(),
+                        dash: None,
+                        cap: None,
+                        join: None,
+                        miter_limit: None,
+                    }),
+                    pages: PageFilter { only: None, except: vec![1] },
+                    under_content: false,
+                    page_numbers: false,
+                },
+                RepeatingElement {
+                    element: Element::Image(ImageElement {
+                        id: None,
+                        tagging: Tagging::default(),
+                        z: 0,
+                        visible: true,
+                        x: 36.0,
+                        y: 36.0,
+                        w: Some(50.0),
+                        h: Some(50.0),
+                        image_ref: "logo".to_string(),
+                        align: TextAlign::Left,
+                        fade: None,
+                        rotation: 0.0,
+                        quad: None,
+                        clip: None,
+                        border_width: 0.0,
+                        border_color: Color::black(),
+                    }),
+                    pages: PageFilter::default(),
+                    under_content: true,
+                    page_numbers: false,
+                },
+            ],
+            layers: vec![],
+            open_action: None,
+            origin: Origin::TopLeft,
+            units: Units::default(),
+            watermark: None,
+            header: None,
+            footer: None,
+        };
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        assert_eq!(pdf_str.matches("0.25 w").count(), 2, "Rule skips page 1");
+        assert_eq!(pdf_str.matches("/Subtype /Image").count(), 1, "Logo embedded once");
+        assert_eq!(pdf_str.matches("/logo_50x50 Do").count(), 3, "Logo drawn on every page");
+    }
+
+    fn asset_font(file: &str) -> FontResource {
+        FontResource {
+            source: FontSource::Path(format!("{}/assets/{}", env!("CARGO_MANIFEST_DIR"), file)),
+            embed: true,
+            cap_height_glyphs: None,
+        }
+    }
+
+    #[test]
+    fn test_render_types_are_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Document>();
+        assert_send_sync::<LoadedResources>();
+        assert_send_sync::<crate::resources::LoadedFont>();
+        assert_send_sync::<crate::resources::LoadedImage>();
+        assert_send_sync::<PdfGenerator<'static>>();
+    }
+
+    #[test]
+    fn test_concurrent_renders_are_isolated() {
+        let mut res = Resources::default();
+        res.fonts.insert("sans".to_string(), asset_font("IBMPlexSans-Regular.otf"));
+        // One set of loaded resources, shared by every thread
+        let resources = LoadedResources::load(&res).unwrap();
+        let docs: Vec<Document> = (0..8)
+            .map(|i| {
+                let mut doc = single_page_doc(PageBackground::Color(Color::white()), res.clone());
+                doc.metadata.title = Some(format!("Document {}", i));
+                doc.pages[0].repeat = i + 1;
+                doc.pages[0].elements.push(Element::Text(TextElement {
+                    id: None,
+                    tagging: Tagging::default(),
+                    z: 0,
+                    visible: true,
+                    x: 72.0,
+                    y: 72.0,
+                    text: "Hello".to_string(),
+                    font: "sans".to_string(),
+                    font_fallback: vec![],
+                    missing_glyph_policy: MissingGlyphPolicy::Drop,
+                    emoji: EmojiOptions::default(),
+                    size: (i + 10) as f32,
+                    color: Color::black(),
+                    align: TextAlign::Left,
+                    vertical_anchor: VerticalAnchor::Baseline,
+                    highlight_color: None,
+                    highlight_padding: 1.0,
+                    transform: TextTransform::None,
+                    fit_width: None,
+                    max_width: None,
+                    skew: Skew::default(),
+                    rotation: 0.0,
+                    outline: false,
+                    styles: Vec::new(),
+                }));
+                doc
+            })
+            .collect();
+
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = docs
+                .iter()
+                .map(|doc| scope.spawn(|| PdfGenerator::new(doc, &resources, false).generate().unwrap()))
+                .collect();
+            for (i, handle) in handles.into_iter().enumerate() {
+                let pdf = handle.join().unwrap();
+                let pdf_str = String::from_utf8_lossy(&pdf);
+                assert!(pdf_str.contains(&format!("(Document {})", i)));
+                assert!(pdf_str.contains(&format!("/Count {}", i + 1)));
+                assert!(pdf_str.contains(&format!(" {} Tf", i + 10)));
+            }
+        });
+    }
+
+    #[test]
+    fn test_repeated_string_measured_per_size() {
+        let mut res = Resources::default();
+        res.fonts.insert("sans".to_string(), asset_font("IBMPlexSans-Regular.otf"));
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), res);
+        // The same string right-aligned at two sizes shares one cache entry
+        for (y, size) in [(100.0, 10.0), (200.0, 20.0), (300.0, 10.0)] {
+            doc.pages[0].elements.push(Element::Text(TextElement {
+                id: None,
+                tagging: Tagging::default(),
+                z: 0,
+                visible: true,
+                x: 500.0,
+                y,
+                text: "NET WT".to_string(),
+                font: "sans".to_string(),
+                font_fallback: vec![],
+                missing_glyph_policy: MissingGlyphPolicy::Drop,
+                emoji: EmojiOptions::default(),
+                size,
+                color: Color::black(),
+                align: TextAlign::Right,
+                vertical_anchor: VerticalAnchor::Baseline,
+                highlight_color: None,
+                highlight_padding: 1.0,
+                transform: TextTransform::None,
+                fit_width: None,
+                max_width: None,
+                skew: Skew::default(),
+                rotation: 0.0,
+                outline: false,
+                styles: Vec::new(),
+            }));
+        }
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        let xs: Vec<f32> = pdf_str
+            .lines()
+            .filter_map(|line| line.strip_suffix(" Td"))
+            .map(|line| line.split(' ').next().unwrap().parse().unwrap())
+            .collect();
+        assert_eq!(xs.len(), 3);
+        let widths: Vec<f32> = xs.iter().map(|x| 500.0 - x).collect();
+        assert!((widths[1] - 2.0 * widths[0]).abs() < 0.01, "{:?}", widths);
+        assert_eq!(widths[0], widths[2]);
+
+        // Strings memoized by the first run register their glyphs again
+        let generator = PdfGenerator::new(&doc, &resources, false);
+        generator.generate().unwrap();
+        let again = generator.generate().unwrap();
+        assert_eq!(again.len(), pdf.len());
+        assert!(String::from_utf8_lossy(&again).contains("/FontFile"));
+    }
+
+    #[test]
+    fn test_emoji_draw_as_color_bitmaps() {
+        let mut res = Resources::default();
+        res.fonts.insert("sans".to_string(), asset_font("IBMPlexSans-Regular.otf"));
+        res.fonts.insert("color".to_string(), asset_font("TestEmoji-sbix.ttf"));
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), res);
+        doc.pages[0].elements.push(Element::Text(TextElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 72.0,
+            y: 72.0,
+            text: "Hi \u{1F600} there \u{1F600}".to_string(),
+            font: "sans".to_string(),
+            font_fallback: vec![],
+            missing_glyph_policy: MissingGlyphPolicy::Raise,
+            emoji: EmojiOptions { font: Some("color".to_string()), replacement: None },
+            size: 12.0,
+            color: Color::black(),
+            align: TextAlign::Left,
+            vertical_anchor: VerticalAnchor::Baseline,
+            highlight_color: None,
+            highlight_padding: 1.0,
+            transform: TextTransform::None,
+            fit_width: None,
+            max_width: None,
+            skew: Skew::default(),
+            rotation: 0.0,
+            outline: false,
+            styles: Vec::new(),
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        assert_eq!(pdf_str.matches("/SMask").count(), 1, "One bitmap shared by both emoji");
+        assert_eq!(pdf_str.matches("/emoji_color_1 Do").count(), 2);
+        assert!(!pdf_str.contains("/BaseFont /color"), "Bitmap font is not embedded as text");
+    }
+
+    #[test]
+    fn test_extracted_text_follows_alignment() {
+        let mut res = Resources::default();
+        res.fonts.insert("sans".to_string(), asset_font("IBMPlexSans-Regular.otf"));
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), res);
+        for align in [TextAlign::Left, TextAlign::Center, TextAlign::Right] {
+            doc.pages[0].elements.push(Element::Text(TextElement {
+                id: None,
+                tagging: Tagging::default(),
+                z: 0,
+                visible: true,
+                x: 300.0,
+                y: 72.0,
+                text: "Total due".to_string(),
+                font: "sans".to_string(),
+                font_fallback: vec![],
+                missing_glyph_policy: MissingGlyphPolicy::Raise,
+                emoji: EmojiOptions::default(),
+                size: 12.0,
+                color: Color::black(),
+                align,
+                vertical_anchor: VerticalAnchor::Baseline,
+                highlight_color: None,
+                highlight_padding: 1.0,
+                transform: TextTransform::None,
+                fit_width: None,
+                max_width: None,
+                skew: Skew::default(),
+                rotation: 0.0,
+                outline: false,
+                styles: Vec::new(),
+            }));
+        }
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        for compress in [false, true] {
+            let pdf = PdfGenerator::new(&doc, &resources, compress).generate().unwrap();
+            let pages = crate::extract::extract(&pdf).unwrap();
+            let [left, center, right] = &pages[0].text[..] else {
+                panic!("expected three text runs");
+            };
+            assert!(left.text == "Total due" && left.font == "IBMPlexSans");
+            assert!((left.x - 300.0).abs() < 0.01);
+            assert!((center.x + center.width / 2.0 - 300.0).abs() < 0.01);
+            assert!((right.x + right.width - 300.0).abs() < 0.01);
+            assert!(pages[0].text.iter().all(|t| (t.y - 72.0).abs() < 0.01));
+        }
+    }
+
+    #[test]
+    fn test_max_width_clips_about_the_anchor() {
+        let mut res = Resources::default();
+        res.fonts.insert("sans".to_string(), asset_font("IBMPlexSans-Regular.otf"));
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), res);
+        let name = |text: &str, overflow| {
+            Element::Text(TextElement {
+                id: None,
+                tagging: Tagging::default(),
+                z: 0,
+                visible: true,
+                x: 300.0,
+                y: 72.0,
+                text: text.to_string(),
+                font: "sans".to_string(),
+                font_fallback: vec![],
+                missing_glyph_policy: MissingGlyphPolicy::Raise,
+                emoji: EmojiOptions::default(),
+                size: 12.0,
+                color: Color::black(),
+                align: TextAlign::Center,
+                vertical_anchor: VerticalAnchor::Baseline,
+                highlight_color: None,
+                highlight_padding: 1.0,
+                transform: TextTransform::None,
+                fit_width: None,
+                max_width: Some(MaxWidth { width: 60.0, overflow, min_size: 6.0 }),
+                skew: Skew::default(),
+                rotation: 0.0,
+                outline: false,
+                styles: Vec::new(),
+            })
+        };
+        doc.pages[0].elements.push(name("Bartholomew Higginbottom", TextOverflow::Clip));
+        doc.pages[0].elements.push(name("Bart", TextOverflow::Clip));
+        doc.pages[0].elements.push(name("Bartholomew Higginbottom", TextOverflow::Ellipsis));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        // Only the overlong name is clipped, to 60 pt centered on x
+        assert_eq!(pdf_str.matches("270 0 60 792 re\nW").count(), 1);
+        assert!(pdf_str.contains("/ToUnicode"));
+        assert!(pdf_str.contains("<2026>"), "ellipsis glyph is mapped");
+    }
+
+    #[test]
+    fn test_text_highlight_drawn_behind_glyphs() {
+        let mut res = Resources::default();
+        res.fonts.insert("sans".to_string(), asset_font("IBMPlexSans-Regular.otf"));
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), res);
+        doc.pages[0].elements.push(Element::Text(TextElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 300.0,
+            y: 100.0,
+            text: "Changed".to_string(),
+            font: "sans".to_string(),
+            font_fallback: vec![],
+            missing_glyph_policy: MissingGlyphPolicy::Drop,
+            emoji: EmojiOptions::default(),
+            size: 10.0,
+            color: Color::black(),
+            align: TextAlign::Right,
+            vertical_anchor: VerticalAnchor::Baseline,
+            highlight_color: Some(Color::rgba(255, 235, 0, 127)),
+            highlight_padding: 2.0,
+            transform: TextTransform::None,
+            fit_width: None,
+            max_width: None,
+            skew: Skew::default(),
+            rotation: 0.0,
+            outline: false,
+            styles: Vec::new(),
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        let highlight = pdf_str.find("/A127 gs").expect("Translucent fill uses an ExtGState");
+        let fill = pdf_str[highlight..].find(" re\nf").expect("Highlight rect is filled") + highlight;
+        assert!(fill < pdf_str.find("BT").unwrap(), "Highlight precedes the text");
+        // Right-aligned: the rect ends `padding` past the anchor
+        let rect_line = pdf_str[..fill].rsplit('\n').next().unwrap();
+        let nums: Vec<f32> = rect_line.split(' ').map(|n| n.parse().unwrap()).collect();
+        assert!((nums[0] + nums[2] - 302.0).abs() < 1e-3, "{}", rect_line);
+    }
+
+    #[test]
+    fn test_small_caps_switch_size_per_piece() {
+        let mut res = Resources::default();
+        res.fonts.insert("sans".to_string(), asset_font("IBMPlexSans-Regular.otf"));
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), res);
+        doc.pages[0].elements.push(Element::Text(TextElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 72.0,
+            y: 72.0,
+            text: "Hello World".to_string(),
+            font: "sans".to_string(),
+            font_fallback: vec![],
+            missing_glyph_policy: MissingGlyphPolicy::Drop,
+            emoji: EmojiOptions::default(),
+            size: 20.0,
+            color: Color::black(),
+            align: TextAlign::Left,
+            vertical_anchor: VerticalAnchor::Baseline,
+            highlight_color: None,
+            highlight_padding: 1.0,
+            transform: TextTransform::SmallCaps,
+            fit_width: None,
+            max_width: None,
+            skew: Skew::default(),
+            rotation: 0.0,
+            outline: false,
+            styles: Vec::new(),
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        // "H", "ELLO ", "W", "ORLD": alternating full and 75% size
+        assert_eq!(pdf_str.matches(" 20 Tf").count(), 2);
+        assert_eq!(pdf_str.matches(" 15 Tf").count(), 2);
+        assert_eq!(pdf_str.matches(" Td\n").count(), 4, "Each piece after the first moves by the one before");
+        // Only capitals are subset: no lowercase 'e' in the ToUnicode map
+        assert!(pdf_str.contains("<0045>"));
+        assert!(!pdf_str.contains("<0065>"));
+    }
+
+    #[test]
+    fn test_fit_width_sets_spacing_or_scale() {
+        let mut res = Resources::default();
+        res.fonts.insert("sans".to_string(), asset_font("IBMPlexSans-Regular.otf"));
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), res);
+        for (y, mode) in [(72.0, FitMode::Tracking), (144.0, FitMode::Scale)] {
+            doc.pages[0].elements.push(Element::Text(TextElement {
+                id: None,
+                tagging: Tagging::default(),
+                z: 0,
+                visible: true,
+                x: 400.0,
+                y,
+                text: "Hello".to_string(),
+                font: "sans".to_string(),
+                font_fallback: vec![],
+                missing_glyph_policy: MissingGlyphPolicy::Drop,
+                emoji: EmojiOptions::default(),
+                size: 20.0,
+                color: Color::black(),
+                align: TextAlign::Right,
+                vertical_anchor: VerticalAnchor::Baseline,
+                highlight_color: None,
+                highlight_padding: 1.0,
+                transform: TextTransform::None,
+                fit_width: Some(TextFit { width: 80.0, mode, min: 0.5, max: 2.0 }),
+                max_width: None,
+                skew: Skew::default(),
+                rotation: 0.0,
+                outline: false,
+                styles: Vec::new(),
+            }));
+        }
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        assert_eq!(pdf_str.matches(" Tc\n").count(), 1);
+        assert_eq!(pdf_str.matches(" Tz\n").count(), 1);
+        assert!(pdf_str.contains("\n320 720 Td\n"), "Right-aligned at 400 pt, both lines start 80 pt earlier");
+        assert!(pdf_str.contains("\n320 648 Td\n"));
+    }
+
+    fn render_text_with_font(embed: bool) -> String {
+        let mut res = Resources::default();
+        res.fonts.insert(
+            "sans".to_string(),
+            FontResource {
+                source: FontSource::Path(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/IBMPlexSans-Regular.otf").to_string()),
+                embed,
+                cap_height_glyphs: None,
+            },
+        );
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), res);
+        doc.pages[0].elements.push(Element::Text(TextElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 72.0,
+            y: 72.0,
+            text: "Hello".to_string(),
+            font: "sans".to_string(),
+            font_fallback: vec![],
+            missing_glyph_policy: MissingGlyphPolicy::Drop,
+            emoji: EmojiOptions::default(),
+            size: 12.0,
+            color: Color::black(),
+            align: TextAlign::Left,
+            vertical_anchor: VerticalAnchor::Baseline,
+            highlight_color: None,
+            highlight_padding: 1.0,
+            transform: TextTransform::None,
+            fit_width: None,
+            max_width: None,
+            skew: Skew::default(),
+            rotation: 0.0,
+            outline: false,
+            styles: Vec::new(),
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+        String::from_utf8_lossy(&pdf).into_owned()
+    }
+
+    #[test]
+    fn test_unembedded_font_omits_font_program() {
+        let embedded = render_text_with_font(true);
+        assert!(embedded.contains("/FontFile2"), "Embedded font should carry its program");
+
+        let referenced = render_text_with_font(false);
+        assert!(!referenced.contains("/FontFile2"), "Font program should be skipped");
+        assert!(referenced.contains("/BaseFont /IBMPlexSans"), "Should reference the PostScript name");
+        assert!(referenced.contains("/W ["), "Widths should still be written");
+        assert!(referenced.contains("/ToUnicode"), "Text should stay extractable");
+    }
+
+    #[test]
+    fn test_long_table_continues_with_header() {
+        let mut res = Resources::default();
+        res.fonts.insert("sans".to_string(), asset_font("IBMPlexSans-Regular.otf"));
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), res);
+        let mut rows = vec![vec!["Item".to_string(), "Qty".to_string()]];
+        rows.extend((0..60).map(|i| vec![format!("Part {}", i), i.to_string()]));
+        doc.pages[0].elements.push(Element::Table(TableElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 72.0,
+            y: 72.0,
+            column_widths: vec![200.0, 60.0],
+            rows,
+            header_rows: 1,
+            font: "sans".to_string(),
+            header_font: None,
+            font_fallback: vec![],
+            missing_glyph_policy: MissingGlyphPolicy::Drop,
+            size: 10.0,
+            line_height: 12.0,
+            color: Color::black(),
+            padding: 4.0,
+            border: 0.5,
+            border_color: Color::black(),
+            header_fill: Some(Color::rgba(204, 204, 204, 255)),
+            max_height: Some(400.0),
+            continued_y: None,
+            page_margin_bottom: 0.0,
+            continued_caption: Some("(continued)".to_string()),
+            continuation: false,
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        crate::elements::table::paginate(&mut doc, &resources).unwrap();
+
+        // 20 pt rows: 19 body rows fit under the header, 18 once the
+        // caption takes a line
+        let body_rows: Vec<usize> = doc.pages.iter().map(|p| match &p.elements[0] {
+            Element::Table(t) => t.rows.len() - t.header_rows,
+            _ => unreachable!(),
+        }).collect();
+        assert_eq!(body_rows, vec![19, 18, 18, 5]);
+        assert!(doc.pages.iter().all(|p| p.source == 0), "Continuation pages share the first page's source");
+
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        assert!(pdf_str.contains("/Count 4"));
+        assert_eq!(pdf_str.matches("0.8 0.8 0.8 rg").count(), 8, "Header cells filled on every page");
+    }
+
+    #[test]
+    fn test_note_writes_text_annotation_and_popup() {
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), Resources::default());
+        doc.pages[0].repeat = 2;
+        doc.pages[0].elements.push(Element::Note(NoteElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 100.0,
+            y: 100.0,
+            contents: "Check the total".to_string(),
+            title: Some("Reviewer".to_string()),
+            color: Color::rgba(255, 0, 0, 255),
+            icon: NoteIcon::Comment,
+            open: true,
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        assert_eq!(pdf_str.matches("/Subtype /Text").count(), 2, "One note per page copy");
+        assert_eq!(pdf_str.matches("/Subtype /Popup").count(), 2);
+        assert_eq!(pdf_str.matches("/Annots [").count(), 2);
+        assert!(pdf_str.contains("/Rect [100 672 120 692]"));
+        assert!(pdf_str.contains("/Contents (Check the total)"));
+        assert!(pdf_str.contains("/T (Reviewer)"));
+        assert!(pdf_str.contains("/C [1 0 0]"));
+        assert!(pdf_str.contains("/Name /Comment"));
+        assert!(pdf_str.contains("/Open true"));
+    }
+
+    #[test]
+    fn test_footer_numbers_every_page_copy() {
+        let mut res = Resources::default();
+        res.fonts.insert("sans".to_string(), asset_font("IBMPlexSans-Regular.otf"));
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), res);
+        doc.pages[0].repeat = 2;
+        let mut landscape = doc.pages[0].clone();
+        (landscape.width, landscape.height, landscape.repeat) = (792.0, 612.0, 1);
+        doc.pages.push(landscape);
+        let text = |text: &str| Element::Text(TextElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 72.0,
+            y: 0.0,
+            text: text.to_string(),
+            font: "sans".to_string(),
+            font_fallback: vec![],
+            missing_glyph_policy: MissingGlyphPolicy::Raise,
+            emoji: EmojiOptions::default(),
+            size: 10.0,
+            color: Color::black(),
+            align: TextAlign::Left,
+            vertical_anchor: VerticalAnchor::Baseline,
+            highlight_color: None,
+            highlight_padding: 1.0,
+            transform: TextTransform::None,
+            fit_width: None,
+            max_width: None,
+            skew: Skew::default(),
+            rotation: 0.0,
+            outline: false,
+            styles: Vec::new(),
+        });
+        doc.header = Some(PageRegion { elements: vec![text("Report")], offset: 36.0, skip_first: true });
+        doc.footer = Some(PageRegion { elements: vec![text("Page {page} of {pages}")], offset: 36.0, skip_first: false });
+        doc.place_regions();
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pages = crate::extract::extract(&pdf).unwrap();
+        let runs: Vec<Vec<(&str, f32)>> =
+            pages.iter().map(|page| page.text.iter().map(|t| (t.text.as_str(), t.y)).collect()).collect();
+        // The cover's copies share the header's page filter, so both skip it
+        assert_eq!(runs[0], [("Page 1 of 3", 756.0)]);
+        assert_eq!(runs[1], [("Page 2 of 3", 756.0)]);
+        assert_eq!(runs[2], [("Report", 36.0), ("Page 3 of 3", 576.0)]);
+    }
+
+    fn render_check(state: CheckState) -> String {
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), Resources::default());
+        doc.pages[0].elements.push(Element::Check(CheckElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 100.0,
+            y: 100.0,
+            size: 10.0,
+            state,
+            stroke: 1.0,
+            stroke_color: Color::black(),
+            fill_color: None,
+            corner_radius: 0.0,
+            mark_color: Color::rgba(255, 0, 0, 255),
+            mark_stroke: 1.2,
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+        String::from_utf8_lossy(&pdf).into_owned()
+    }
+
+    #[test]
+    fn test_check_states_draw_paths() {
+        let unchecked = render_check(CheckState::Unchecked);
+        assert!(unchecked.contains("100 682 10 10 re"), "Box drawn at the top-left corner");
+        assert!(!unchecked.contains("1 0 0 RG"), "No mark when unchecked");
+
+        let checked = render_check(CheckState::Checked);
+        assert!(checked.contains("1 0 0 RG"));
+        assert_eq!(checked.matches(" m\n").count(), 1, "Tick is one stroke");
+
+        let crossed = render_check(CheckState::Crossed);
+        assert_eq!(crossed.matches(" m\n").count(), 2, "Cross is two strokes");
+        assert!(crossed.contains("102.5 684.5 l"), "Strokes scale with the box");
+    }
+
+    #[test]
+    fn test_signature_line_renders_rule_and_captions() {
+        let mut res = Resources::default();
+        res.fonts.insert("sans".to_string(), asset_font("IBMPlexSans-Regular.otf"));
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), res);
+        doc.pages[0].elements.push(Element::SignatureLine(SignatureLineElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 72.0,
+            y: 100.0,
+            w: 200.0,
+            label: Some("Jane Doe".to_string()),
+            sublabels: vec!["Title".to_string(), "Date".to_string()],
+            font: "sans".to_string(),
+            size: 10.0,
+            color: Color::black(),
+            stroke: 1.0,
+            mark: false,
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        assert!(pdf_str.contains("72 692 m\n272 692 l"), "Rule");
+        assert_eq!(pdf_str.matches("BT").count(), 3, "One text object per caption");
+        // Cap lines 6 pt under the rule's lower edge (cap height 6.98 pt)
+        assert!(pdf_str.contains("72 678.52 Td"), "Label");
+        assert!(pdf_str.contains("172 664.52 Td"), "Second sublabel in the right half");
+    }
+
+    #[test]
+    fn test_image_placeholder_draws_box_cross_and_caption() {
+        let mut res = Resources::default();
+        res.fonts.insert("sans".to_string(), asset_font("IBMPlexSans-Regular.otf"));
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), res);
+        doc.pages[0].elements.push(Element::ImagePlaceholder(ImagePlaceholderElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 72.0,
+            y: 100.0,
+            w: 200.0,
+            h: 100.0,
+            align: TextAlign::Left,
+            label: Some("Photo".to_string()),
+            note: None,
+            font: "sans".to_string(),
+            size: 10.0,
+            color: Color::black(),
+            fill_color: Color::white(),
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        assert!(pdf_str.contains("72 692 m\n272 592 l"), "Falling diagonal");
+        assert!(pdf_str.contains("72 592 m\n272 692 l"), "Rising diagonal");
+        assert_eq!(pdf_str.matches("BT").count(), 1, "Caption");
+        assert!(pdf_str.contains("/FontFile"), "Caption font is embedded");
+    }
+
+    #[test]
+    fn test_grid_draws_lines_and_labels_beneath_content() {
+        let mut res = Resources::default();
+        res.fonts.insert("sans".to_string(), asset_font("IBMPlexSans-Regular.otf"));
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), res);
+        doc.pages[0].elements.push(Element::Line(LineElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x1: 10.0,
+            y1: 10.0,
+            x2: 20.0,
+            y2: 10.0,
+            stroke: 1.0,
+            hairline: false,
+            color: Color::black(),
+            dash: None,
+            cap: None,
+            join: None,
+            miter_limit: None,
+        }));
+        doc.pages[0].elements.push(Element::Grid(GridElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: -1,
+            visible: true,
+            spacing: 72.0,
+            major_every: 2,
+            color: Color::rgba(0, 0, 255, 255),
+            stroke: 0.25,
+            major_stroke: 1.0,
+            font: Some("sans".to_string()),
+            size: 6.0,
+            label_color: Color::black(),
+            unit: 1.0,
+            from_bottom: false,
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        // 9 columns and 12 rows on a 612 x 792 page
+        let moves = pdf_str.lines().filter(|l| l.ends_with(" m") && l.split(' ').count() == 3).count();
+        assert_eq!(moves, 9 + 12 + 1, "{}", pdf_str);
+        assert!(pdf_str.contains("72 792 m\n72 0 l"), "First column: {}", pdf_str);
+        assert!(pdf_str.contains("0 648 m\n612 648 l"), "Second row: {}", pdf_str);
+        // Labels on the 4 major columns and 5 major rows past the corner
+        assert_eq!(pdf_str.lines().filter(|&l| l == "BT").count(), 9, "{}", pdf_str);
+        let grid = pdf_str.find("0 0 1 RG").unwrap();
+        let line = pdf_str.find("10 782 m").unwrap();
+        assert!(grid < line, "z -1 puts the grid under the line");
+    }
+
+    fn render_leader(style: LeaderStyle, font: Option<&str>) -> String {
+        let mut res = Resources::default();
+        res.fonts.insert("sans".to_string(), asset_font("IBMPlexSans-Regular.otf"));
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), res);
+        doc.pages[0].elements.push(Element::Leader(LeaderElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x1: 72.0,
+            x2: 172.0,
+            y: 100.0,
+            style,
+            dot_size: None,
+            gap: None,
+            color: Color::black(),
+            font: font.map(str::to_string),
+            size: 10.0,
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+        String::from_utf8_lossy(&pdf).into_owned()
+    }
+
+    #[test]
+    fn test_leader_dots_are_round_caps_on_a_zero_dash() {
+        let dots = render_leader(LeaderStyle::Dots, None);
+        assert!(dots.contains("1 J"), "Round caps");
+        assert!(dots.contains("[0 3] 0 d"), "1pt dots on a 3pt pitch");
+        // 34 dots, the last centered half a dot in from x2, plus the overrun
+        assert!(dots.contains("72.5 692 m\n173 692 l"), "{}", dots);
+
+        let dashes = render_leader(LeaderStyle::Dashes, None);
+        assert!(!dashes.contains("1 J"));
+        assert!(dashes.contains("[3 2] 0 d"));
+        assert!(dashes.contains("74 692 m\n172 692 l"), "20 whole dashes end at x2");
+
+        let solid = render_leader(LeaderStyle::Solid, None);
+        assert!(!solid.contains(" d\n"));
+        assert!(solid.contains("72 692 m\n172 692 l"));
+    }
+
+    #[test]
+    fn test_leader_rests_on_the_font_baseline() {
+        // Sized and placed like the font's period: 1.29pt tall at 10pt,
+        // dipping 0.12pt below the baseline at y = 692
+        let dots = render_leader(LeaderStyle::Dots, Some("sans"));
+        assert!(dots.contains("1.29 w"));
+        assert!(dots.contains("[0 3.87] 0 d"), "Gap defaults to twice the dot");
+        assert!(dots.contains(" 692.525 m"), "{}", dots);
+    }
+
+    fn render_progress_ring(value: f32) -> String {
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), Resources::default());
+        doc.pages[0].elements.push(Element::ProgressRing(ProgressRingElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            cx: 100.0,
+            cy: 100.0,
+            radius: 50.0,
+            thickness: 8.0,
+            value,
+            start_angle: 0.0,
+            track_color: Some(Color::rgba(224, 224, 224, 255)),
+            color: Color::black(),
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+        String::from_utf8_lossy(&pdf).into_owned()
+    }
+
+    #[test]
+    fn test_progress_ring_fill_is_a_capped_arc_over_a_closed_track() {
+        let quarter = render_progress_ring(0.25);
+        assert_eq!(quarter.matches(" c\n").count(), 5, "Four track pieces, one fill piece");
+        assert_eq!(quarter.matches("\nh\n").count(), 1, "Only the track is closed");
+        assert!(quarter.contains("1 J"), "Round ends on the fill");
+        // From 12 o'clock clockwise to 3 o'clock
+        assert!(quarter.contains("1 J\n100 742 m\n"), "{}", quarter);
+        assert!(quarter.contains(" 150 692 c\nS"), "{}", quarter);
+    }
+
+    #[test]
+    fn test_progress_ring_empty_and_full() {
+        let empty = render_progress_ring(0.0);
+        assert_eq!(empty.matches(" c\n").count(), 4, "Track only");
+        assert!(!empty.contains("1 J"));
+
+        // A whole turn is four pieces closed on themselves, never one
+        // degenerate arc from 12 o'clock back to itself
+        let full = render_progress_ring(1.0);
+        assert_eq!(full.matches(" c\n").count(), 8);
+        assert_eq!(full.matches("\nh\n").count(), 2);
+        assert!(!full.contains("1 J"));
+    }
+
+    #[test]
+    fn test_rect_sides_stroke_listed_edges_only() {
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), Resources::default());
+        doc.pages[0].elements.push(Element::Rect(RectElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 100.0,
+            y: 100.0,
+            w: 200.0,
+            h: 50.0,
+            stroke: 2.0,
+            hairline: false,
+            stroke_color: Color::rgba(255, 0, 0, 255),
+            fill_color: Some(Color::rgba(0, 0, 255, 255)),
+            fill_pattern: None,
+            gradient: None,
+            corner_radius: CornerRadii::default(),
+            corner_style: CornerStyle::Circular,
+            sides: Some(RectSides { top: true, bottom: true, ..Default::default() }),
+            dash: None,
+            miter_limit: None,
+            stroke_align: StrokeAlign::Center,
+            skew: Skew::default(),
+            rotation: 0.0,
+            rotation_origin: RotationOrigin::Center,
+            cap: None,
+            join: None,
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        assert!(pdf_str.contains("100 642 200 50 re\nf"), "Fill still covers the full area");
+        assert!(pdf_str.contains("2 J"), "Projecting caps close the corners");
+        assert!(pdf_str.contains("100 692 m\n300 692 l"), "Top edge");
+        assert!(pdf_str.contains("300 642 m\n100 642 l"), "Bottom edge");
+        assert_eq!(pdf_str.matches(" m\n").count(), 2, "No left or right edge");
+    }
+
+    #[test]
+    fn test_rect_fill_pattern_is_one_shared_tiling_pattern() {
+        let hatched = |x: f32| {
+            Element::Rect(RectElement {
+                id: None,
+                tagging: Tagging::default(),
+                z: 0,
+                visible: true,
+                x,
+                y: 100.0,
+                w: 100.0,
+                h: 50.0,
+                stroke: 0.0,
+                hairline: false,
+                stroke_color: Color::black(),
+                fill_color: None,
+                fill_pattern: Some(FillPattern {
+                    style: HatchStyle::Cross,
+                    spacing: 4.0,
+                    line_width: 0.5,
+                    color: Color::rgba(255, 0, 0, 255),
+                }),
+                gradient: None,
+                corner_radius: CornerRadii::default(),
+                corner_style: CornerStyle::Circular,
+                sides: None,
+                dash: None,
+                miter_limit: None,
+                stroke_align: StrokeAlign::Center,
+                skew: Skew::default(),
+                rotation: 0.0,
+                rotation_origin: RotationOrigin::Center,
+                cap: None,
+                join: None,
+            })
+        };
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), Resources::default());
+        doc.pages[0].elements.extend([hatched(50.0), hatched(300.0)]);
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        assert_eq!(pdf_str.matches("/PatternType 1").count(), 1, "Deduplicated");
+        assert!(pdf_str.contains("/PaintType 1"));
+        assert!(pdf_str.contains("/XStep 5.656854"), "{}", pdf_str);
+        assert_eq!(pdf_str.matches("/Pattern cs\n/Hatch").count(), 2);
+        assert!(pdf_str.contains("50 642 100 50 re\nf"));
+        assert!(pdf_str.contains("/Pattern <<\n"), "Listed in the page resources");
+    }
+
+    #[test]
+    fn test_z_sorts_paint_order_stably() {
+        let rect = |x: f32, z: i32| {
+            Element::Rect(RectElement {
+                id: None,
+                tagging: Tagging::default(),
+                z,
+                visible: true,
+                x,
+                y: 100.0,
+                w: 10.0,
+                h: 10.0,
+                stroke: 0.0,
+                hairline: false,
+                stroke_color: Color::black(),
+                fill_color: Some(Color::black()),
+                fill_pattern: None,
+                gradient: None,
+                corner_radius: CornerRadii::default(),
+                corner_style: CornerStyle::Circular,
+                sides: None,
+                dash: None,
+                miter_limit: None,
+                stroke_align: StrokeAlign::Center,
+                skew: Skew::default(),
+                rotation: 0.0,
+                rotation_origin: RotationOrigin::Center,
+                cap: None,
+                join: None,
+            })
+        };
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), Resources::default());
+        doc.pages[0].elements = vec![rect(1.0, 10), rect(2.0, 0), rect(3.0, -1), rect(4.0, 0)];
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+        let pdf_str = String::from_utf8_lossy(&pdf);
+
+        let order: Vec<usize> = [3.0, 2.0, 4.0, 1.0]
+            .iter()
+            .map(|x| pdf_str.find(&format!("\n{} 682 10 10 re", x)).unwrap())
+            .collect();
+        assert!(order.windows(2).all(|w| w[0] < w[1]), "Underlay, then z 0 in list order, then overlay");
+    }
+
+    #[test]
+    fn test_skew_shears_about_the_origin() {
+        let mut res = Resources::default();
+        res.fonts.insert("sans".to_string(), asset_font("IBMPlexSans-Regular.otf"));
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), res);
+        doc.pages[0].elements.push(Element::Rect(RectElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 100.0,
+            y: 100.0,
+            w: 200.0,
+            h: 50.0,
+            stroke: 0.0,
+            hairline: false,
+            stroke_color: Color::black(),
+            fill_color: Some(Color::black()),
+            fill_pattern: None,
+            gradient: None,
+            corner_radius: CornerRadii::default(),
+            corner_style: CornerStyle::Circular,
+            sides: None,
+            dash: None,
+            miter_limit: None,
+            stroke_align: StrokeAlign::Center,
+            skew: Skew { x: 45.0, y: 0.0 },
+            rotation: 0.0,
+            rotation_origin: RotationOrigin::Center,
+            cap: None,
+            join: None,
+        }));
+        doc.pages[0].elements.push(Element::Text(TextElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 300.0,
+            y: 200.0,
+            text: "Slanted".to_string(),
+            font: "sans".to_string(),
+            font_fallback: vec![],
+            missing_glyph_policy: MissingGlyphPolicy::Drop,
+            emoji: EmojiOptions::default(),
+            size: 12.0,
+            color: Color::black(),
+            align: TextAlign::Right,
+            vertical_anchor: VerticalAnchor::Baseline,
+            highlight_color: Some(Color::rgba(255, 255, 0, 255)),
+            highlight_padding: 1.0,
+            transform: TextTransform::None,
+            fit_width: None,
+            max_width: None,
+            skew: Skew { x: 0.0, y: -45.0 },
+            rotation: 0.0,
+            outline: false,
+            styles: Vec::new(),
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        // The rect about its center (200, 667); the right-aligned text about
+        // its anchor (300, 592), with the highlight inside the same shear
+        assert!(pdf_str.contains("q\n1 0 1 1 -667 0 cm\n0 0 0 rg\n100 642 200 50 re"), "{}", pdf_str);
+        assert!(pdf_str.contains("q\n1 -1 0 1 0 300 cm\n1 1 0 rg"), "{}", pdf_str);
+        assert!(pdf_str.contains("258.78 592 Td"), "Alignment uses the unskewed width");
+    }
+
+    #[test]
+    fn test_text_rotation_turns_about_the_anchor() {
+        let mut res = Resources::default();
+        res.fonts.insert("sans".to_string(), asset_font("IBMPlexSans-Regular.otf"));
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), res);
+        doc.pages[0].elements.push(Element::Text(TextElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 300.0,
+            y: 200.0,
+            text: "Slanted".to_string(),
+            font: "sans".to_string(),
+            font_fallback: vec![],
+            missing_glyph_policy: MissingGlyphPolicy::Drop,
+            emoji: EmojiOptions::default(),
+            size: 12.0,
+            color: Color::black(),
+            align: TextAlign::Center,
+            vertical_anchor: VerticalAnchor::Baseline,
+            highlight_color: Some(Color::rgba(255, 255, 0, 255)),
+            highlight_padding: 1.0,
+            transform: TextTransform::None,
+            fit_width: None,
+            max_width: None,
+            skew: Skew::default(),
+            rotation: 90.0,
+            outline: false,
+            styles: Vec::new(),
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        // A quarter turn counter-clockwise about the anchor (300, 592),
+        // around the highlight as well as the glyphs
+        let turn = pdf_str.find("q\n0 1 -1 0 892 292 cm\n").expect("Should turn about the anchor");
+        let highlight = pdf_str.find("1 1 0 rg").expect("Should draw the highlight");
+        assert!(turn < highlight, "{}", pdf_str);
+        assert!(pdf_str.contains("279.39 592 Td"), "Centering uses the unturned width");
+    }
+
+    #[test]
+    fn test_outlined_text_fills_paths_without_a_font() {
+        let mut res = Resources::default();
+        res.fonts.insert("sans".to_string(), asset_font("IBMPlexSans-Regular.otf"));
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), res);
+        doc.pages[0].elements.push(Element::Text(TextElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 72.0,
+            y: 100.0,
+            text: "Lo".to_string(),
+            font: "sans".to_string(),
+            font_fallback: vec![],
+            missing_glyph_policy: MissingGlyphPolicy::Drop,
+            emoji: EmojiOptions::default(),
+            size: 20.0,
+            color: Color::rgba(200, 0, 0, 255),
+            align: TextAlign::Left,
+            vertical_anchor: VerticalAnchor::Baseline,
+            highlight_color: None,
+            highlight_padding: 0.0,
+            transform: TextTransform::None,
+            fit_width: None,
+            max_width: None,
+            skew: Skew::default(),
+            rotation: 0.0,
+            outline: true,
+            styles: Vec::new(),
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        assert!(!pdf_str.contains("/Font"), "No font objects: {}", pdf_str);
+        assert!(!pdf_str.contains("BT\n"), "{}", pdf_str);
+        assert!(pdf_str.contains("0.78431374 0 0 rg\n"), "{}", pdf_str);
+        // The L's stem sits on the baseline past its side bearing, the
+        // "o" is curved, and one fill covers the run
+        assert!(pdf_str.contains("73.86 692 l\n"), "{}", pdf_str);
+        assert!(pdf_str.contains(" c\n"), "{}", pdf_str);
+        assert_eq!(pdf_str.matches("\nf\n").count(), 1, "{}", pdf_str);
+    }
+
+    fn aligned_rect(stroke_align: StrokeAlign, corner_radius: f32) -> String {
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), Resources::default());
+        doc.pages[0].elements.push(Element::Rect(RectElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 100.0,
+            y: 100.0,
+            w: 200.0,
+            h: 50.0,
+            stroke: 4.0,
+            hairline: false,
+            stroke_color: Color::black(),
+            fill_color: Some(Color::rgba(0, 0, 255, 255)),
+            fill_pattern: None,
+            gradient: None,
+            corner_radius: corner_radius.into(),
+            corner_style: CornerStyle::Circular,
+            sides: None,
+            dash: None,
+            miter_limit: None,
+            stroke_align,
+            skew: Skew::default(),
+            rotation: 0.0,
+            rotation_origin: RotationOrigin::Center,
+            cap: None,
+            join: None,
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+        String::from_utf8_lossy(&pdf).into_owned()
+    }
+
+    fn stroked_shapes(stroke: f32, hairline: bool) -> String {
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), Resources::default());
+        doc.pages[0].elements.push(Element::Line(LineElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x1: 72.0,
+            y1: 72.0,
+            x2: 200.0,
+            y2: 72.0,
+            stroke,
+            hairline,
+            color: Color::black(),
+            dash: None,
+            cap: None,
+            join: None,
+            miter_limit: None,
+        }));
+        doc.pages[0].elements.push(Element::Rect(RectElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 100.0,
+            y: 100.0,
+            w: 200.0,
+            h: 50.0,
+            stroke,
+            hairline,
+            stroke_color: Color::black(),
+            fill_color: None,
+            fill_pattern: None,
+            gradient: None,
+            corner_radius: CornerRadii::default(),
+            corner_style: CornerStyle::Circular,
+            sides: None,
+            dash: None,
+            miter_limit: None,
+            stroke_align: StrokeAlign::Center,
+            skew: Skew::default(),
+            rotation: 0.0,
+            rotation_origin: RotationOrigin::Center,
+            cap: None,
+            join: None,
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+        String::from_utf8_lossy(&pdf).into_owned()
+    }
+
+    #[test]
+    fn test_hairline_strokes_at_width_zero() {
+        let hairline = stroked_shapes(0.0, true);
+        assert_eq!(hairline.matches("0 w\n").count(), 1, "Set once for both: {}", hairline);
+        assert_eq!(hairline.matches("\nS\n").count(), 2, "Line and rect both stroke");
+
+        // Zero width without `hairline` means no stroke at all
+        let none = stroked_shapes(0.0, false);
+        assert!(!none.contains("\nS\n"), "{}", none);
+        assert!(!none.contains("72 720 m"), "The line draws nothing");
+    }
+
+    #[test]
+    fn test_rect_stroke_align_moves_only_the_stroke() {
+        let center = aligned_rect(StrokeAlign::Center, 0.0);
+        assert_eq!(center.matches("100 642 200 50 re").count(), 2, "Fill and stroke share the bounds");
+
+        let inside = aligned_rect(StrokeAlign::Inside, 0.0);
+        assert!(inside.contains("100 642 200 50 re\nf"), "Fill keeps the declared bounds");
+        assert!(inside.contains("102 644 196 46 re\nS"), "{}", inside);
+
+        let outside = aligned_rect(StrokeAlign::Outside, 0.0);
+        assert!(outside.contains("98 640 204 54 re\nS"), "{}", outside);
+
+        // Rounded corners keep concentric with the fill: radius 10 becomes 8 inside
+        let rounded = aligned_rect(StrokeAlign::Inside, 10.0);
+        assert!(rounded.contains("110 692 m\n290 692 l"), "Fill radius");
+        assert!(rounded.contains("110 690 m\n290 690 l"), "{}", rounded);
+    }
+
+    fn rounded_rect_path(radius: impl Into<CornerRadii>, style: CornerStyle) -> String {
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), Resources::default());
+        doc.pages[0].elements.push(Element::Rect(RectElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 100.0,
+            y: 100.0,
+            w: 200.0,
+            h: 100.0,
+            stroke: 0.0,
+            hairline: false,
+            stroke_color: Color::black(),
+            fill_color: Some(Color::black()),
+            fill_pattern: None,
+            gradient: None,
+            corner_radius: radius.into(),
+            corner_style: style,
+            sides: None,
+            dash: None,
+            miter_limit: None,
+            stroke_align: StrokeAlign::Center,
+            skew: Skew::default(),
+            rotation: 0.0,
+            rotation_origin: RotationOrigin::Center,
+            cap: None,
+            join: None,
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+        String::from_utf8_lossy(&pdf).into_owned()
+    }
+
+    #[test]
+    fn test_continuous_corners_reach_further_along_edges() {
+        let circular = rounded_rect_path(10.0, CornerStyle::Circular);
+        assert!(circular.contains("110 692 m\n290 692 l\n295.523 692 300 687.523 300 682 c"), "{}", circular);
+
+        // The curve starts 1.28 radii from the corner with 0.86-radius handles
+        let continuous = rounded_rect_path(25.0, CornerStyle::Continuous);
+        assert!(continuous.contains("132 692 m\n268 692 l\n289.5 692 300 681.5 300 660 c"), "{}", continuous);
+
+        // Large radii clamp the extent to half the short side, so the
+        // corners meet without crossing
+        let clamped = rounded_rect_path(60.0, CornerStyle::Continuous);
+        assert!(clamped.contains("150 692 m\n250 692 l"), "{}", clamped);
+        assert!(clamped.contains("300 642 c\n300 642 l"), "{}", clamped);
+    }
+
+    #[test]
+    fn test_rect_rotation_turns_whole_rect() {
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), Resources::default());
+        let Element::Rect(mut rect) = filled_rect(100.0, Color::black()) else { unreachable!() };
+        rect.w = 200.0;
+        rect.h = 100.0;
+        rect.rotation = 90.0;
+        rect.corner_radius = CornerRadii::from(10.0);
+        doc.pages[0].elements.push(Element::Rect(rect));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+        let pdf_str = String::from_utf8_lossy(&pdf);
+
+        // A quarter turn clockwise about the center (200, 642 in PDF
+        // space), set once before the rounded path is built
+        let turn = pdf_str.find("q\n0 -1 1 0 -442 842 cm\n").expect("Should turn about the center");
+        let path = pdf_str.find("110 692 m").expect("Should draw the rounded outline");
+        assert!(turn < path);
+        assert_eq!(pdf_str.matches(" cm\n").count(), 1);
+    }
+
+    #[test]
+    fn test_per_corner_radii() {
+        let top = CornerRadii { top_left: 20.0, top_right: 20.0, bottom_right: 0.0, bottom_left: 0.0 };
+        let path = rounded_rect_path(top, CornerStyle::Circular);
+        // Square corners are plain line joins, with no curve
+        let expected = "120 692 m\n280 692 l\n291.046 692 300 683.046 300 672 c\n300 592 l\n100 592 l\n100 672 l\n100 683.046 108.954 692 120 692 c\nh";
+        assert!(path.contains(expected), "{}", path);
+
+        // Adjacent radii adding up to more than an edge are scaled down
+        // together: 150 + 150 along the 200pt top edge, and 150 + 0 down
+        // the 100pt sides, scale by 2/3
+        let wide = CornerRadii { top_left: 150.0, top_right: 150.0, bottom_right: 0.0, bottom_left: 0.0 };
+        let path = rounded_rect_path(wide, CornerStyle::Circular);
+        assert!(path.contains("200 692 m\n200 692 l\n"), "{}", path);
+        assert!(path.contains("300 592 c\n300 592 l\n100 592 l\n100 592 l\n"), "{}", path);
+    }
+
+    #[test]
+    fn test_dash_phase_and_miter_limit() {
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), Resources::default());
+        doc.pages[0].elements.push(Element::Rect(RectElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 100.0,
+            y: 100.0,
+            w: 200.0,
+            h: 50.0,
+            stroke: 2.0,
+            hairline: false,
+            stroke_color: Color::black(),
+            fill_color: None,
+            fill_pattern: None,
+            gradient: None,
+            corner_radius: CornerRadii::default(),
+            corner_style: CornerStyle::Circular,
+            sides: None,
+            dash: Some(StrokeDash { array: vec![6.0, 3.0], phase: 1.5, round_caps: false }),
+            miter_limit: Some(2.0),
+            stroke_align: StrokeAlign::Center,
+            skew: Skew::default(),
+            rotation: 0.0,
+            rotation_origin: RotationOrigin::Center,
+            cap: None,
+            join: None,
+        }));
+        doc.pages[0].elements.push(Element::Line(LineElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x1: 100.0,
+            y1: 200.0,
+            x2: 300.0,
+            y2: 200.0,
+            stroke: 1.0,
+            hairline: false,
+            color: Color::black(),
+            dash: Some(StrokeDash { array: vec![2.0], phase: 0.0, round_caps: false }),
+            cap: None,
+            join: None,
+            miter_limit: None,
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        assert!(pdf_str.contains("[6 3] 1.5 d"));
+        assert!(pdf_str.contains("2 M"));
+        assert!(pdf_str.contains("[2] 0 d"));
+        assert_eq!(pdf_str.matches(" M\n").count(), 1, "Lines keep the default miter limit");
+    }
+
+    fn translucent_rect(x: f32) -> Element {
+        Element::Rect(RectElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x,
+            y: 100.0,
+            w: 50.0,
+            h: 50.0,
+            stroke: 0.0,
+            hairline: false,
+            stroke_color: Color::black(),
+            fill_color: Some(Color::rgba(255, 0, 0, 255)),
+            fill_pattern: None,
+            gradient: None,
+            corner_radius: CornerRadii::default(),
+            corner_style: CornerStyle::Circular,
+            sides: None,
+            dash: None,
+            miter_limit: None,
+            stroke_align: StrokeAlign::Center,
+            skew: Skew::default(),
+            rotation: 0.0,
+            rotation_origin: RotationOrigin::Center,
+            cap: None,
+            join: None,
+        })
+    }
+
+    #[test]
+    fn test_transparency_group_draws_children_as_one_form() {
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), Resources::default());
+        doc.pages[0].elements.push(Element::Group(GroupElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            elements: vec![translucent_rect(100.0), translucent_rect(125.0)],
+            x: 0.0,
+            y: 0.0,
+            rotation: 0.0,
+            scale: 1.0,
+            transparency_group: true,
+            opacity: 0.5,
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        assert!(pdf_str.contains("/Subtype /Form"));
+        assert!(pdf_str.contains("/S /Transparency"));
+        assert!(pdf_str.contains("/I true"));
+        assert!(pdf_str.contains("/K false"));
+        assert!(pdf_str.contains("/A128 gs\n/G0 Do"), "Group drawn once at its exact opacity");
+        assert_eq!(pdf_str.matches(" re\nf").count(), 2, "Children live in the form, drawn once");
+    }
+
+    #[test]
+    fn test_group_form_covers_what_its_placement_shows() {
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), Resources::default());
+        doc.pages[0].elements.push(Element::Group(GroupElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            elements: vec![translucent_rect(2000.0)],
+            x: 0.0,
+            y: 0.0,
+            rotation: 0.0,
+            scale: 0.25,
+            transparency_group: true,
+            opacity: 0.5,
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        // A quarter-size group sees four pages' width of its own space, so
+        // a child at x = 2000 still lands on the page
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        assert!(pdf_str.contains("/BBox [0 -2376 2448 792]"), "{}", pdf_str);
+    }
+
+    #[test]
+    fn test_plain_group_draws_children_inline() {
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), Resources::default());
+        doc.pages[0].elements.push(Element::Group(GroupElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            elements: vec![translucent_rect(100.0), translucent_rect(125.0)],
+            x: 0.0,
+            y: 0.0,
+            rotation: 0.0,
+            scale: 1.0,
+            transparency_group: false,
+            opacity: 1.0,
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        assert!(!pdf_str.contains("/Subtype /Form"));
+        assert_eq!(pdf_str.matches(" re\nf").count(), 2);
+    }
+
+    #[test]
+    fn test_nested_groups_compose_transforms() {
+        let group = |elements, x, y, rotation, scale| {
+            Element::Group(GroupElement {
+                id: None,
+                tagging: Tagging::default(),
+                z: 0,
+                visible: true,
+                elements,
+                x,
+                y,
+                rotation,
+                scale,
+                transparency_group: false,
+                opacity: 1.0,
+            })
+        };
+        let inner = group(vec![translucent_rect(0.0)], 10.0, 0.0, 0.0, 2.0);
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), Resources::default());
+        doc.pages[0].elements.push(group(vec![inner], 200.0, 300.0, 90.0, 1.0));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        // The outer turn is applied first, so the inner scale happens in
+        // the turned frame
+        let outer = pdf_str.find("q\n0 -1 1 0 -592 492 cm\n").expect("outer group turned about (200, 300)");
+        let inner = pdf_str.find("q\n2 0 0 2 10 -792 cm\n").expect("inner group scaled from (10, 0)");
+        assert!(outer < inner);
+        assert_eq!(pdf_str.matches(" re\nf").count(), 1);
+    }
+
+    fn render_fragment_placements(placements: &[(f32, f32, f32)]) -> String {
+        let fragment = std::sync::Arc::new(Fragment {
+            name: "box".to_string(),
+            elements: vec![translucent_rect(0.0)],
+        });
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), Resources::default());
+        for &(x, y, scale) in placements {
+            doc.pages[0].elements.push(Element::Fragment(FragmentElement {
+                id: None,
+                tagging: Tagging::default(),
+                z: 0,
+                visible: true,
+                fragment: fragment.clone(),
+                x,
+                y,
+                scale,
+            }));
+        }
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+        String::from_utf8_lossy(&pdf).into_owned()
+    }
+
+    #[test]
+    fn test_fragment_placed_once_draws_inline() {
+        let pdf_str = render_fragment_placements(&[(50.0, 200.0, 1.0)]);
+        assert!(!pdf_str.contains("/Subtype /Form"));
+        assert!(pdf_str.contains("1 0 0 1 50 -200 cm"), "Origin moved to (50, 200)");
+        assert_eq!(pdf_str.matches(" re\nf").count(), 1);
+    }
+
+    #[test]
+    fn test_fragment_placed_twice_shares_one_form() {
+        let pdf_str = render_fragment_placements(&[(50.0, 200.0, 1.0), (300.0, 400.0, 0.5)]);
+        assert_eq!(pdf_str.matches("/Subtype /Form").count(), 1);
+        assert_eq!(pdf_str.matches(" re\nf").count(), 1, "Drawn once, into the form");
+        assert!(pdf_str.contains("1 0 0 1 50 -200 cm\n/F0 Do"));
+        // Half size: the page-height origin offset scales too, 792 - 400 - 396
+        assert!(pdf_str.contains("0.5 0 0 0.5 300 -4 cm\n/F0 Do"), "{}", pdf_str);
+    }
+
+    #[test]
+    fn test_image_fade_applies_luminosity_mask_at_placed_bounds() {
+        let mut res = Resources::default();
+        res.images.insert(
+            "hero".to_string(),
+            ImageResource { source: ImageSource::Path(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/test-png.png").to_string()), frame: 0, profile: ImageProfile::Embed },
+        );
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), res);
+        doc.pages[0].elements.push(Element::Image(ImageElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 300.0,
+            y: 100.0,
+            w: Some(200.0),
+            h: Some(100.0),
+            image_ref: "hero".to_string(),
+            align: TextAlign::Center,
+            fade: Some(ImageFade { edge: FadeEdge::Bottom, start: 0.6, end: 1.0 }),
+            rotation: 0.0,
+            quad: None,
+            clip: None,
+            border_width: 0.0,
+            border_color: Color::black(),
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        assert!(pdf_str.contains("/S /Luminosity"), "Fade is a luminosity soft mask");
+        assert!(pdf_str.contains("/ShadingType 2"), "Mask paints an axial shading");
+        assert!(pdf_str.contains("/BBox [0 0 200 100]"), "Mask covers the placed image");
+        // Centered: the image origin is x - w/2, page height - y - h
+        let mask = fade::FadeMasks::name(&ImageFade { edge: FadeEdge::Bottom, start: 0.6, end: 1.0 }, 200.0, 100.0);
+        assert!(pdf_str.contains(&format!("1 0 0 1 200 592 cm\n/{} gs", mask)), "{}", mask);
+    }
+
+    #[test]
+    fn test_image_rotation_turns_about_the_anchor() {
+        let mut res = Resources::default();
+        res.images.insert(
+            "hero".to_string(),
+            ImageResource { source: ImageSource::Path(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/test-png.png").to_string()), frame: 0, profile: ImageProfile::Embed },
+        );
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), res);
+        doc.pages[0].elements.push(Element::Image(ImageElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 300.0,
+            y: 100.0,
+            w: Some(200.0),
+            h: Some(100.0),
+            image_ref: "hero".to_string(),
+            align: TextAlign::Center,
+            fade: None,
+            rotation: 90.0,
+            quad: None,
+            clip: None,
+            border_width: 0.0,
+            border_color: Color::black(),
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        // A quarter turn clockwise about the middle of the top edge
+        // (300, 692 in PDF space), then the usual unturned placement
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        assert!(pdf_str.contains("q\n0 -1 1 0 -392 992 cm\n200 0 0 100 200 592 cm\n"), "{}", pdf_str);
+    }
+
+    #[test]
+    fn test_image_quad_maps_onto_its_corners() {
+        let mut res = Resources::default();
+        res.images.insert(
+            "hero".to_string(),
+            ImageResource { source: ImageSource::Path(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/test-png.png").to_string()), frame: 0, profile: ImageProfile::Embed },
+        );
+        let quad = ImageQuad { origin: (100.0, 100.0), x_corner: (260.0, 220.0), y_corner: (160.0, 180.0) };
+        let (w, h) = quad.size();
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), res);
+        doc.pages[0].elements.push(Element::Image(ImageElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 100.0,
+            y: 100.0,
+            w: Some(w),
+            h: Some(h),
+            image_ref: "hero".to_string(),
+            align: TextAlign::Left,
+            fade: None,
+            rotation: 0.0,
+            quad: Some(quad),
+            clip: None,
+            border_width: 0.0,
+            border_color: Color::black(),
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        // The 200 × 100 box at the origin, its bottom-left moved to the
+        // bottom-left corner (160, 180), or (160, 612) in PDF space
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        assert!(pdf_str.contains("q\n0.8 -0.6 -0.6 0.8 160 612 cm\n200 0 0 100 0 0 cm\n"), "{}", pdf_str);
+    }
+
+    #[test]
+    fn test_image_clip_fits_the_display_box() {
+        let mut res = Resources::default();
+        res.images.insert(
+            "portrait".to_string(),
+            ImageResource { source: ImageSource::Path(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/test-png.png").to_string()), frame: 0, profile: ImageProfile::Embed },
+        );
+        let render = |clip| {
+            let mut doc = single_page_doc(PageBackground::Color(Color::white()), res.clone());
+            doc.pages[0].elements.push(Element::Image(ImageElement {
+                id: None,
+                tagging: Tagging::default(),
+                z: 0,
+                visible: true,
+                x: 100.0,
+                y: 100.0,
+                w: Some(200.0),
+                h: Some(100.0),
+                image_ref: "portrait".to_string(),
+                align: TextAlign::Left,
+                fade: None,
+                rotation: 0.0,
+                quad: None,
+                clip: Some(clip),
+                border_width: 0.0,
+                border_color: Color::black(),
+            }));
+            let resources = LoadedResources::load(&doc.resources).unwrap();
+            let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+            String::from_utf8_lossy(&pdf).into_owned()
+        };
+
+        // The circle is as wide as the box is tall, centered in it, and the
+        // ellipse reaches its sides; both clip ahead of the placement
+        let circle = render(ImageClip::Circle);
+        let start = circle.find("q\n250 642 m\n").expect("circle starts at its right edge");
+        let clip = circle[start..].find("h\nW\nn\n200 0 0 100 100 592 cm\n");
+        assert!(clip.is_some(), "{}", &circle[start..]);
+        assert!(render(ImageClip::Ellipse).contains("q\n300 642 m\n"));
+        let rounded = render(ImageClip::Rounded(CornerRadii::from(10.0)));
+        assert!(rounded.contains("q\n110 692 m\n290 692 l\n"));
+    }
+
+    #[test]
+    fn test_image_border_is_stroked_over_the_image() {
+        let mut res = Resources::default();
+        res.images.insert(
+            "portrait".to_string(),
+            ImageResource { source: ImageSource::Path(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/test-png.png").to_string()), frame: 0, profile: ImageProfile::Embed },
+        );
+        let render = |clip| {
+            let mut doc = single_page_doc(PageBackground::Color(Color::white()), res.clone());
+            doc.pages[0].elements.push(Element::Image(ImageElement {
+                id: None,
+                tagging: Tagging::default(),
+                z: 0,
+                visible: true,
+                x: 200.0,
+                y: 100.0,
+                w: Some(200.0),
+                h: Some(100.0),
+                image_ref: "portrait".to_string(),
+                align: TextAlign::Center,
+                fade: None,
+                rotation: 0.0,
+                quad: None,
+                clip,
+                border_width: 2.0,
+                border_color: Color::rgba(255, 0, 0, 127),
+            }));
+            let resources = LoadedResources::load(&doc.resources).unwrap();
+            let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+            String::from_utf8_lossy(&pdf).into_owned()
+        };
+
+        // The box is centered on x, and the border follows the image once
+        // its clip is lifted
+        let boxed = render(None);
+        let drawn = boxed.find(" Do\nQ\n").expect("image drawn in its own state");
+        assert!(
+            boxed[drawn..].starts_with(" Do\nQ\n/A127 gs\n1 0 0 RG\n2 w\n100 592 200 100 re\nS\n"),
+            "{}",
+            &boxed[drawn..]
+        );
+        let circle = render(Some(ImageClip::Circle));
+        let drawn = circle.find(" Do\nQ\n").expect("image drawn in its own state");
+        assert!(circle[drawn..].contains("2 w\n250 642 m\n"), "{}", &circle[drawn..]);
+        assert!(circle[drawn..].contains("h\nS\n"));
+    }
+
+    fn large_jpeg(width: u32, height: u32) -> Vec<u8> {
+        let img = image::RgbImage::from_fn(width, height, |x, y| image::Rgb([(x % 256) as u8, (y % 256) as u8, 128]));
+        let mut out = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut out, 90)
+            .encode(img.as_raw(), width, height, image::ColorType::Rgb8)
+            .unwrap();
+        out
+    }
+
+    #[test]
+    fn test_large_jpeg_drawn_small_is_downscaled() {
+        let mut res = Resources::default();
+        res.images.insert("photo".to_string(), ImageResource { source: ImageSource::Bytes(large_jpeg(2400, 1600)), frame: 0, profile: ImageProfile::Embed });
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), res);
+        // One inch wide at 300 DPI needs 300 pixels, an exact 1/8 DCT scale
+        doc.pages[0].elements.push(Element::Image(ImageElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 100.0,
+            y: 100.0,
+            w: Some(72.0),
+            h: None,
+            image_ref: "photo".to_string(),
+            align: TextAlign::Left,
+            fade: None,
+            rotation: 0.0,
+            quad: None,
+            clip: None,
+            border_width: 0.0,
+            border_color: Color::black(),
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        match resources.get_image("photo").unwrap() {
+            LoadedImage::Raster { width, height, .. } => assert_eq!((*width, *height), (2400, 1600)),
+            _ => panic!("expected a raster image"),
+        }
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        assert!(pdf_str.contains("/Width 300"), "Should embed at the drawn size");
+        assert!(pdf_str.contains("/Height 200"));
+    }
+
+    #[test]
+    fn test_raster_size_probed_from_header() {
+        // A PNG cut off halfway still loads its size; its pixels fail when written
+        let png = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/test-png.png")).unwrap();
+        let mut res = Resources::default();
+        res.images.insert("cut".to_string(), ImageResource { source: ImageSource::Bytes(png[..png.len() / 2].to_vec()), frame: 0, profile: ImageProfile::Embed });
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), res);
+        doc.pages[0].elements.push(Element::Image(ImageElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 100.0,
+            y: 100.0,
+            w: Some(72.0),
+            h: None,
+            image_ref: "cut".to_string(),
+            align: TextAlign::Left,
+            fade: None,
+            rotation: 0.0,
+            quad: None,
+            clip: None,
+            border_width: 0.0,
+            border_color: Color::black(),
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        assert!(matches!(resources.get_image("cut").unwrap(), LoadedImage::Raster { .. }));
+        let err = PdfGenerator::new(&doc, &resources, false).generate().unwrap_err();
+        assert!(err.to_string().contains("Failed to decode"), "{}", err);
+    }
+
+    #[test]
+    fn test_image_errors_name_first_user() {
+        let png = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/test-png.png")).unwrap();
+        let mut res = Resources::default();
+        res.images.insert("cut".to_string(), ImageResource { source: ImageSource::Bytes(png[..png.len() / 2].to_vec()), frame: 0, profile: ImageProfile::Embed });
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), res);
+        let image = |id: Option<&str>| {
+            Element::Image(ImageElement {
+                id: id.map(str::to_string),
+                tagging: Tagging::default(),
+                z: 0,
+                visible: true,
+                x: 100.0,
+                y: 100.0,
+                w: Some(72.0),
+                h: None,
+                image_ref: "cut".to_string(),
+                align: TextAlign::Left,
+                fade: None,
+                rotation: 0.0,
+                quad: None,
+                clip: None,
+                border_width: 0.0,
+                border_color: Color::black(),
+            })
+        };
+        doc.pages[0].elements.push(Element::Note(NoteElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 10.0,
+            y: 10.0,
+            contents: "first".to_string(),
+            title: None,
+            color: Color::black(),
+            icon: NoteIcon::default(),
+            open: false,
+        }));
+        doc.pages[0].elements.push(image(Some("header.logo")));
+        doc.pages[0].elements.push(image(None));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let err = PdfGenerator::new(&doc, &resources, false).generate().unwrap_err().to_string();
+        assert!(err.starts_with("Page 0, element 1 (id 'header.logo'): Invalid image data for 'cut'"), "{}", err);
+    }
+
+    #[test]
+    fn test_inline_images_flow_with_textbox_text() {
+        let mut res = Resources::default();
+        res.fonts.insert("sans".to_string(), asset_font("IBMPlexSans-Regular.otf"));
+        res.images.insert(
+            "logo".to_string(),
+            ImageResource { source: ImageSource::Path(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/test-png.png").to_string()), frame: 0, profile: ImageProfile::Embed },
+        );
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), res);
+        let logo = |h| InlineImage { image_ref: "logo".to_string(), h, baseline_shift: 0.0 };
+        let slot = crate::runs::inline_image_char;
+        doc.pages[0].elements.push(Element::TextBox(TextBoxElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 72.0,
+            y: 72.0,
+            w: 300.0,
+            h: 200.0,
+            box_align_x: BoxAlignX::Left,
+            box_align_y: BoxAlignY::Top,
+            text_align_x: TextAlign::Left,
+            text_align_y: TextAlignY::Top,
+            text: format!("Made by {} and {}\nBig {}", slot(0), slot(1), slot(2)),
+            inline_images: vec![logo(12.0), logo(12.0), logo(30.0)],
+            font: "sans".to_string(),
+            font_fallback: vec![],
+            missing_glyph_policy: MissingGlyphPolicy::Raise,
+            emoji: EmojiOptions::default(),
+            size: 12.0,
+            line_height: 14.0,
+            color: Color::black(),
+            columns: 1,
+            column_gap: 12.0,
+            balance: false,
+            styles: Vec::new(),
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        assert_eq!(pdf_str.matches("/Subtype /Image").count(), 2, "One XObject per display size");
+        assert_eq!(pdf_str.matches("/logo_12x12 Do").count(), 2);
+        assert_eq!(pdf_str.matches("/logo_30x30 Do").count(), 1);
+    }
+
+    #[test]
+    fn test_markup_sets_spans_in_style_fonts_and_colors() {
+        let mut res = Resources::default();
+        res.fonts.insert("sans".to_string(), asset_font("IBMPlexSans-Regular.otf"));
+        res.fonts.insert("sans-bold".to_string(), asset_font("IBMPlexSans-Bold.otf"));
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), res);
+        let styles = std::collections::HashMap::from([
+            ("bold".to_string(), TextStyle { font: Some("sans-bold".to_string()), color: None }),
+            ("red".to_string(), TextStyle { font: None, color: Some(Color::rgba(255, 0, 0, 255)) }),
+        ]);
+        let marked = crate::markup::parse("Pick **4** of {red}SKU 1234{/red} now", &styles).unwrap();
+        doc.pages[0].elements.push(Element::TextBox(TextBoxElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 72.0,
+            y: 72.0,
+            w: 300.0,
+            h: 200.0,
+            box_align_x: BoxAlignX::Left,
+            box_align_y: BoxAlignY::Top,
+            text_align_x: TextAlign::Left,
+            text_align_y: TextAlignY::Top,
+            text: marked.text,
+            inline_images: vec![],
+            font: "sans".to_string(),
+            font_fallback: vec![],
+            missing_glyph_policy: MissingGlyphPolicy::Raise,
+            emoji: EmojiOptions::default(),
+            size: 12.0,
+            line_height: 14.0,
+            color: Color::black(),
+            columns: 1,
+            column_gap: 12.0,
+            balance: false,
+            styles: marked.styles,
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        assert_eq!(pdf_str.matches("/IBMPlexSans-Bold 12 Tf").count(), 1);
+        // The red span switches the fill and the text after it switches back
+        assert_eq!(pdf_str.matches("1 0 0 rg").count(), 1);
+        assert_eq!(pdf_str.matches("0 0 0 rg").count(), 2);
+    }
+
+    fn gif_resource(frame: u32) -> Resources {
+        let mut res = Resources::default();
+        res.images.insert(
+            "anim".to_string(),
+            ImageResource {
+                source: ImageSource::Path(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/test-anim.gif").to_string()),
+                frame,
+                profile: ImageProfile::Embed,
+            },
+        );
+        res
+    }
+
+    #[test]
+    fn test_gif_frame_selection() {
+        // Frame 1 only covers 10x10 of the 40x20 canvas, composited over frame 0
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), gif_resource(1));
+        doc.pages[0].elements.push(Element::Image(ImageElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 100.0,
+            y: 100.0,
+            w: None,
+            h: None,
+            image_ref: "anim".to_string(),
+            align: TextAlign::Left,
+            fade: None,
+            rotation: 0.0,
+            quad: None,
+            clip: None,
+            border_width: 0.0,
+            border_color: Color::black(),
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        match resources.get_image("anim").unwrap() {
+            LoadedImage::Raster { width, height, frame, .. } => assert_eq!((*width, *height, *frame), (40, 20, 1)),
+            _ => panic!("expected a raster image"),
+        }
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        assert!(pdf_str.contains("/Width 40"), "Should embed the full canvas");
+        assert!(pdf_str.contains("/Height 20"));
+    }
+
+    #[test]
+    fn test_gif_frame_out_of_range() {
+        let err = LoadedResources::load(&gif_resource(3)).err().unwrap();
+        assert!(err.to_string().contains("frame 3 is out of range; the GIF has 3 frames"), "{}", err);
+        assert!(LoadedResources::load(&gif_resource(2)).is_ok());
+    }
+
+    #[test]
+    fn test_frame_on_still_image_fails() {
+        let mut res = Resources::default();
+        res.images.insert(
+            "logo".to_string(),
+            ImageResource {
+                source: ImageSource::Path(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/test-png.png").to_string()),
+                frame: 1,
+                profile: ImageProfile::Embed,
+            },
+        );
+        let err = LoadedResources::load(&res).err().unwrap();
+        assert!(err.to_string().contains("only GIF images have more than one frame"), "{}", err);
+    }
+
+    #[test]
+    fn test_sixteen_bit_pngs_render() {
+        for file in ["test-gray16.png", "test-rgb16.png"] {
+            let mut res = Resources::default();
+            res.images.insert(
+                "ramp".to_string(),
+                ImageResource { source: ImageSource::Path(format!("{}/assets/{}", env!("CARGO_MANIFEST_DIR"), file)), frame: 0, profile: ImageProfile::Embed },
+            );
+            let mut doc = single_page_doc(PageBackground::Color(Color::white()), res);
+            doc.pages[0].elements.push(Element::Image(ImageElement {
+                id: None,
+                tagging: Tagging::default(),
+                z: 0,
+                visible: true,
+                x: 100.0,
+                y: 100.0,
+                w: Some(256.0),
+                h: None,
+                image_ref: "ramp".to_string(),
+                align: TextAlign::Left,
+                fade: None,
+                rotation: 0.0,
+                quad: None,
+                clip: None,
+                border_width: 0.0,
+                border_color: Color::black(),
+            }));
+            let resources = LoadedResources::load(&doc.resources).unwrap();
+            let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+            let pdf_str = String::from_utf8_lossy(&pdf);
+            assert!(pdf_str.contains("/Subtype /Image"), "{}", file);
+            assert!(pdf_str.contains("/Width 256"), "{}", file);
+            assert!(pdf_str.contains("/BitsPerComponent 8"), "{}", file);
+        }
+    }
+
+    fn spot(name: &str, tint: f32) -> Color {
+        Color {
+            model: ColorModel::Spot(SpotColor { name: name.to_string(), tint }),
+            ..Color::rgba(230, 0, 126, 255)
+        }
+    }
+
+    #[test]
+    fn test_spot_colors_share_one_separation() {
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), Resources::default());
+        doc.pages.push(doc.pages[0].clone());
+        for (page, tint) in doc.pages.iter_mut().zip([1.0, 0.5]) {
+            page.elements.push(Element::Rect(RectElement {
+                id: None,
+                tagging: Tagging::default(),
+                z: 0,
+                visible: true,
+                x: 100.0,
+                y: 100.0,
+                w: 50.0,
+                h: 50.0,
+                stroke: 1.0,
+                hairline: false,
+                stroke_color: spot("PANTONE 213 C", 1.0),
+                fill_color: Some(spot("PANTONE 213 C", tint)),
+                fill_pattern: None,
+                gradient: None,
+                corner_radius: CornerRadii::default(),
+                corner_style: CornerStyle::Circular,
+                sides: None,
+                dash: None,
+                miter_limit: None,
+                stroke_align: StrokeAlign::Center,
+                skew: Skew::default(),
+                rotation: 0.0,
+                rotation_origin: RotationOrigin::Center,
+                cap: None,
+                join: None,
+            }));
+        }
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        assert_eq!(pdf_str.matches("/Separation").count(), 1, "One color space per ink");
+        assert!(pdf_str.contains("/Separation /PANTONE#20213#20C /DeviceRGB"));
+        assert!(pdf_str.contains("/C1 [0.9019608 0 0.49411765]"), "Tint 1 maps to the alternate");
+        let key = writer::spot_key("PANTONE 213 C");
+        assert!(pdf_str.contains(&format!("/{} cs\n0.5 scn", key)));
+        assert!(pdf_str.contains(&format!("/{} CS\n1 SCN", key)));
+        assert!(!pdf_str.contains(" rg\n"), "No process fill for spot shapes");
+    }
+
+    #[test]
+    fn test_cmyk_and_rgb_mix_in_one_page() {
+        let mut doc = single_page_doc(
+            PageBackground::Color(Color::cmyka(0.05, 0.0, 0.1, 0.0, 255)),
+            Resources::default(),
+        );
+        doc.pages[0].elements.push(Element::Rect(RectElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 100.0,
+            y: 100.0,
+            w: 50.0,
+            h: 50.0,
+            stroke: 1.0,
+            hairline: false,
+            stroke_color: Color::cmyka(0.0, 0.0, 0.0, 1.0, 255),
+            fill_color: Some(Color::rgba(255, 0, 0, 128)),
+            fill_pattern: None,
+            gradient: None,
+            corner_radius: CornerRadii::default(),
+            corner_style: CornerStyle::Circular,
+            sides: None,
+            dash: None,
+            miter_limit: None,
+            stroke_align: StrokeAlign::Center,
+            skew: Skew::default(),
+            rotation: 0.0,
+            rotation_origin: RotationOrigin::Center,
+            cap: None,
+            join: None,
+        }));
+        doc.pages[0].elements.push(Element::Line(LineElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x1: 100.0,
+            y1: 200.0,
+            x2: 300.0,
+            y2: 200.0,
+            stroke: 2.0,
+            hairline: false,
+            color: Color::cmyka(1.0, 0.5, 0.0, 0.0, 128),
+            dash: None,
+            cap: None,
+            join: None,
+            miter_limit: None,
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        assert!(pdf_str.contains("0.05 0 0.1 0 k"), "Background filled in CMYK");
+        assert!(pdf_str.contains("0 0 0 1 K"));
+        assert!(pdf_str.contains("1 0 0 rg"), "RGB colors still use rg");
+        // The alpha state in effect for the line's stroke
+        let before_line = &pdf_str[..pdf_str.find("1 0.5 0 0 K").unwrap()];
+        assert!(before_line.rfind("/A127 gs") > before_line.rfind("/A255 gs"), "Alpha applies to CMYK as to RGB");
+    }
+
+    /// Bare ICC header: enough for the profile checks, not a usable profile
+    fn icc_header(space: &[u8; 4]) -> Vec<u8> {
+        let mut data = vec![0u8; 128];
+        data[16..20].copy_from_slice(space);
+        data[36..40].copy_from_slice(b"acsp");
+        data
+    }
+
+    fn output_intent(space: &[u8; 4], tag_images: bool) -> OutputIntent {
+        OutputIntent {
+            profile: ProfileSource::Bytes(icc_header(space)),
+            condition: "FOGRA39".to_string(),
+            info: Some("Coated FOGRA39".to_string()),
+            kind: OutputIntentKind::PdfX,
+            tag_images,
+        }
+    }
+
+    #[test]
+    fn test_output_intent_in_catalog() {
+        let res = Resources { output_intent: Some(output_intent(b"CMYK", false)), ..Default::default() };
+        let doc = single_page_doc(PageBackground::Color(Color::white()), res);
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        assert!(pdf_str.contains("/OutputIntents ["));
+        assert!(pdf_str.contains("/S /GTS_PDFX"));
+        assert!(pdf_str.contains("/OutputConditionIdentifier (FOGRA39)"));
+        assert!(pdf_str.contains("/Info (Coated FOGRA39)"));
+        assert!(pdf_str.contains("/N 4"), "Component count read from the profile header");
+        assert!(pdf_str.contains("/Alternate /DeviceCMYK"));
+    }
+
+    #[test]
+    fn test_output_intent_tags_images() {
+        let mut res = Resources::default();
+        res.images.insert(
+            "logo".to_string(),
+            ImageResource { source: ImageSource::Path(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/test-png.png").to_string()), frame: 0, profile: ImageProfile::Embed },
+        );
+        res.output_intent = Some(output_intent(b"RGB ", true));
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), res);
+        doc.pages[0].elements.push(Element::Image(ImageElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 100.0,
+            y: 100.0,
+            w: Some(100.0),
+            h: Some(100.0),
+            image_ref: "logo".to_string(),
+            align: TextAlign::Left,
+            fade: None,
+            rotation: 0.0,
+            quad: None,
+            clip: None,
+            border_width: 0.0,
+            border_color: Color::black(),
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        assert!(pdf_str.contains("/ColorSpace [/ICCBased"));
+        assert!(!pdf_str.contains("/ColorSpace /DeviceRGB"));
+    }
+
+    #[test]
+    fn test_output_intent_rejects_bad_profiles() {
+        let mut res = Resources { output_intent: Some(output_intent(b"CMYK", true)), ..Default::default() };
+        let err = LoadedResources::load(&res).err().unwrap().to_string();
+        assert!(err.contains("tag_images needs an RGB profile"), "{}", err);
+
+        res.output_intent = Some(OutputIntent {
+            profile: ProfileSource::Bytes(vec![0; 64]),
+            ..output_intent(b"RGB ", false)
+        });
+        let err = LoadedResources::load(&res).err().unwrap().to_string();
+        assert!(err.contains("not an ICC profile"), "{}", err);
+    }
+
+    fn filled_rect(x: f32, color: Color) -> Element {
+        Element::Rect(RectElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x,
+            y: 100.0,
+            w: 5.0,
+            h: 5.0,
+            stroke: 0.0,
+            hairline: false,
+            stroke_color: Color::black(),
+            fill_color: Some(color),
+            fill_pattern: None,
+            gradient: None,
+            corner_radius: CornerRadii::default(),
+            corner_style: CornerStyle::Circular,
+            sides: None,
+            dash: None,
+            miter_limit: None,
+            stroke_align: StrokeAlign::Center,
+            skew: Skew::default(),
+            rotation: 0.0,
+            rotation_origin: RotationOrigin::Center,
+            cap: None,
+            join: None,
+        })
+    }
+
+    #[test]
+    fn test_repeated_state_is_written_once() {
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), Resources::default());
+        for i in 0..100 {
+            doc.pages[0].elements.push(filled_rect(i as f32 * 5.0, Color::rgba(255, 0, 0, 255)));
+        }
+        doc.pages[0].elements.push(filled_rect(0.0, Color::rgba(0, 0, 255, 128)));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        assert_eq!(pdf_str.matches(" re\nf\n").count(), 101, "Every rect is still filled");
+        assert_eq!(pdf_str.matches("1 0 0 rg\n").count(), 1, "{}", pdf_str);
+        assert_eq!(pdf_str.matches("0 0 1 rg\n").count(), 1);
+        assert_eq!(pdf_str.matches("q\n").count(), 1, "Only the translucent fill keeps its save/restore");
+        // Stream size: each rect costs its `re` and `f` and nothing else
+        let stream = &pdf_str[pdf_str.find("stream\n").unwrap()..pdf_str.find("endstream").unwrap()];
+        assert!(stream.len() < 2000, "{} bytes", stream.len());
+    }
+
+    #[test]
+    fn test_matrix_and_barcode_modules_share_one_fill() {
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), Resources::default());
+        doc.pages[0].elements.push(Element::QRCode(QRCodeElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 100.0,
+            y: 100.0,
+            size: 100.0,
+            value: QrPayload::Text("https://example.com/a/fairly/long/url".to_string()),
+            error_correction: QrErrorCorrection::Medium,
+            version: None,
+            auto_version: true,
+            min_module_size: None,
+            color: Color::black(),
+            background: Color::white(),
+        }));
+        doc.pages[0].elements.push(Element::Barcode(BarcodeElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            kind: BarcodeKind::Code128,
+            x: 100.0,
+            y: 300.0,
+            w: 200.0,
+            h: 50.0,
+            value: "RUPDF-12345".to_string(),
+            human_readable: false,
+            font: String::new(),
+            font_size: 10.0,
+            text_overflow: TextOverflow::Shrink,
+            text_min_size: 5.0,
+            color: Color::black(),
+            rotation: 0.0,
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        let stream = &pdf_str[pdf_str.find("stream\n").unwrap()..pdf_str.find("endstream").unwrap()];
+        // White backgrounds are paper, so one fill for the modules and one
+        // for the bars
+        assert_eq!(stream.matches("\nf\n").count(), 2, "{}", stream);
+        // Horizontal runs of modules are merged: a 29x29 symbol has far
+        // fewer rects than dark modules
+        assert!(stream.matches(" re\n").count() < 300, "{}", stream);
+        assert!(stream.len() < 12_000, "{} bytes", stream.len());
+    }
+
+    #[test]
+    fn test_barcode_rotation_turns_about_top_left() {
+        let barcode = |y: f32, rotation: f32| {
+            Element::Barcode(BarcodeElement {
+                id: None,
+                tagging: Tagging::default(),
+                z: 0,
+                visible: true,
+                kind: BarcodeKind::Code128,
+                x: 100.0,
+                y,
+                w: 200.0,
+                h: 50.0,
+                value: "RUPDF-12345".to_string(),
+                human_readable: false,
+                font: String::new(),
+                font_size: 10.0,
+                text_overflow: TextOverflow::Shrink,
+                text_min_size: 5.0,
+                color: Color::black(),
+                rotation,
+            })
+        };
+        let render = |elements: Vec<Element>| {
+            let mut doc = single_page_doc(PageBackground::Color(Color::white()), Resources::default());
+            doc.pages[0].elements = elements;
+            let resources = LoadedResources::load(&doc.resources).unwrap();
+            let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+            String::from_utf8_lossy(&pdf).into_owned()
+        };
+
+        // Drawn once: the bars are laid out unturned inside the turn about
+        // (100, 492 in PDF space)
+        let pdf_str = render(vec![barcode(300.0, 90.0)]);
+        let turn = pdf_str.find("q\n0 -1 1 0 -392 592 cm\n").expect("Should turn about the corner");
+        let bars = pdf_str.find(" 442 ").expect("Should lay bars out from the unturned bottom");
+        assert!(turn < bars, "{}", pdf_str);
+
+        // Drawn twice, turned or not, the copies share one form
+        let pdf_str = render(vec![barcode(300.0, 90.0), barcode(600.0, 0.0)]);
+        assert_eq!(pdf_str.matches("/Subtype /Form").count(), 1);
+        assert!(pdf_str.contains("q\n0 -1 1 0 100 492 cm\n/S0 Do\nQ"), "{}", pdf_str);
+        assert!(pdf_str.contains("q\n1 0 0 1 100 192 cm\n/S0 Do\nQ"), "{}", pdf_str);
+    }
+
+    #[test]
+    fn test_stats_describe_what_was_written() {
+        let mut res = Resources::default();
+        res.fonts.insert("sans".to_string(), asset_font("IBMPlexSans-Regular.otf"));
+        res.images.insert(
+            "logo".to_string(),
+            ImageResource { source: ImageSource::Path(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/test-png.png").to_string()), frame: 0, profile: ImageProfile::Embed },
+        );
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), res);
+        doc.pages[0].repeat = 3;
+        for size in [20.0, 40.0] {
+            doc.pages[0].elements.push(Element::Image(ImageElement {
+                id: None,
+                tagging: Tagging::default(),
+                z: 0,
+                visible: true,
+                x: 100.0,
+                y: 100.0,
+                w: Some(size),
+                h: Some(size),
+                image_ref: "logo".to_string(),
+                align: TextAlign::Left,
+                fade: None,
+                rotation: 0.0,
+                quad: None,
+                clip: None,
+                border_width: 0.0,
+                border_color: Color::black(),
+            }));
+        }
+        doc.pages[0].elements.push(filled_rect(0.0, Color::black()));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let generator = PdfGenerator::new(&doc, &resources, true);
+        let pdf = generator.generate().unwrap();
+
+        let stats = generator.stats();
+        assert_eq!(stats.pages, 3, "Repeats count as pages");
+        assert_eq!(stats.total_bytes, pdf.len());
+        assert!(stats.content_bytes > 0 && stats.content_bytes < pdf.len());
+        assert!(stats.fonts.is_empty(), "No text, no fonts");
+        assert_eq!(stats.images.len(), 2, "One XObject per drawn size");
+        for image in &stats.images {
+            assert_eq!(image.name, "logo");
+            assert_eq!(image.encoding, stats::ImageEncoding::Jpeg);
+            assert!(image.bytes > 0);
+            let dpi = image.dpi.unwrap();
+            // 300 DPI, rounded up to whole pixels
+            assert!((300.0..310.0).contains(&dpi), "{}", dpi);
+        }
+
+        // A second generate starts over
+        generator.generate().unwrap();
+        assert_eq!(generator.stats(), stats);
+    }
+
+    fn polygon(close: bool, fill_rule: FillRule) -> String {
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), Resources::default());
+        doc.pages[0].elements.push(Element::Polygon(PolygonElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            points: vec![(100.0, 100.0), (200.0, 100.0), (150.0, 50.0)],
+            stroke: 2.0,
+            hairline: false,
+            stroke_color: Color::black(),
+            fill_color: Some(Color::rgba(255, 0, 0, 255)),
+            close,
+            fill_rule,
+            cap: None,
+            join: None,
+            miter_limit: None,
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+        String::from_utf8_lossy(&pdf).into_owned()
+    }
+
+    #[test]
+    fn test_polygon_fills_and_strokes_its_points() {
+        let closed = polygon(true, FillRule::NonZero);
+        let path = "100 692 m\n200 692 l\n150 742 l\nh\n";
+        assert!(closed.contains(&format!("{}f\n", path)), "{}", closed);
+        assert!(closed.contains(&format!("{}S\n", path)), "{}", closed);
+
+        // An open polygon is filled closed but stroked open
+        let open = polygon(false, FillRule::EvenOdd);
+        assert!(open.contains(&format!("{}f*\n", path)), "{}", open);
+        assert!(open.contains("150 742 l\nS\n"), "{}", open);
+    }
+
+    #[test]
+    fn test_repeated_symbols_share_one_form() {
+        let qr = |x: f32, value: &str| {
+            Element::QRCode(QRCodeElement {
+                id: Some(format!("qr-{}", x)),
+                tagging: Tagging::default(),
+                z: 0,
+                visible: true,
+                x,
+                y: 100.0,
+                size: 50.0,
+                value: QrPayload::Text(value.to_string()),
+                error_correction: QrErrorCorrection::Medium,
+                version: None,
+                auto_version: true,
+                min_module_size: None,
+                color: Color::black(),
+                background: Color::white(),
+            })
+        };
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), Resources::default());
+        doc.pages[0].elements.push(qr(100.0, "https://example.com/support"));
+        doc.pages[0].elements.push(qr(200.0, "https://example.com/support"));
+        doc.pages[0].elements.push(qr(300.0, "https://example.com/other"));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+        let pdf_str = String::from_utf8_lossy(&pdf);
+
+        // The shared symbol is drawn once, into a form placed twice by its
+        // top-left corner; the other is drawn inline
+        assert_eq!(pdf_str.matches("/Subtype /Form").count(), 1);
+        assert!(pdf_str.contains("q\n1 0 0 1 100 692 cm\n/S0 Do\nQ\n"), "{}", pdf_str);
+        assert!(pdf_str.contains("q\n1 0 0 1 200 692 cm\n/S0 Do\nQ\n"), "{}", pdf_str);
+        // Modules are filled once in the form and once for the other symbol
+        assert_eq!(pdf_str.matches(" re\nf\n").count(), 2);
+        assert!(pdf_str.contains("/S0 "), "the form is in the page resources");
+    }
+
+    #[test]
+    fn test_raw_content_is_written_with_resolved_names() {
+        let mut resources = Resources::default();
+        resources.fonts.insert("body".to_string(), asset_font("IBMPlexSans-Regular.otf"));
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), resources);
+        let raw_resources = vec![
+            ("F1".to_string(), RawResource::Font("body".to_string())),
+            ("Half".to_string(), RawResource::Alpha(127)),
+        ];
+        let operators = "/Half gs 0 0 1 rg 10 10 50 50 re f BT /F1 12 Tf 72 72 Td <0024> Tj ET".to_string();
+        let uses = crate::elements::raw::check(&operators, &raw_resources).unwrap();
+        doc.pages[0].elements.push(Element::RawContent(RawContentElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            operators,
+            resources: raw_resources,
+            uses,
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+        let pdf_str = String::from_utf8_lossy(&pdf);
+
+        // Declared names become the document's own, inside a saved state
+        let expected = "q\n/A127 gs\n0 0 1 rg\n10 10 50 50 re\nf\nBT\n/IBMPlexSans 12 Tf\n72 72 Td\n<0024> Tj\nET\nQ\n";
+        assert!(pdf_str.contains(expected), "{}", pdf_str);
+        // The font is embedded for the glyph it shows
+        assert!(pdf_str.contains("/FontFile"), "{}", pdf_str);
+    }
+
+    #[test]
+    fn test_dotted_dash_strokes_round_dots() {
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), Resources::default());
+        doc.pages[0].elements.push(Element::Line(LineElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x1: 100.0,
+            y1: 200.0,
+            x2: 300.0,
+            y2: 200.0,
+            stroke: 0.5,
+            hairline: false,
+            color: Color::black(),
+            dash: Some(StrokeDash { array: vec![0.0, 1.0], phase: 0.0, round_caps: true }),
+            cap: None,
+            join: None,
+            miter_limit: None,
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        assert!(pdf_str.contains("[0 1] 0 d\n1 J\n"), "{}", pdf_str);
+    }
+
+    #[test]
+    fn test_tagged_images_share_their_icc_profile() {
+        let mut res = Resources::default();
+        let asset = |file: &str, profile| ImageResource {
+            source: ImageSource::Path(format!("{}/assets/{}", env!("CARGO_MANIFEST_DIR"), file)),
+            frame: 0,
+            profile,
+        };
+        res.images.insert("a".to_string(), asset("test-p3.png", ImageProfile::Embed));
+        res.images.insert("b".to_string(), asset("test-p3.png", ImageProfile::Embed));
+        res.images.insert("converted".to_string(), asset("test-p3.png", ImageProfile::Srgb));
+        res.images.insert("untagged".to_string(), asset("test-rgb16.png", ImageProfile::Embed));
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), res);
+        for (i, name) in ["a", "b", "converted", "untagged"].into_iter().enumerate() {
+            doc.pages[0].elements.push(Element::Image(ImageElement {
+                id: None,
+                tagging: Tagging::default(),
+                z: 0,
+                visible: true,
+                x: 100.0 * i as f32,
+                y: 100.0,
+                w: Some(16.0),
+                h: Some(16.0),
+                image_ref: name.to_string(),
+                align: TextAlign::Left,
+                fade: None,
+                rotation: 0.0,
+                quad: None,
+                clip: None,
+                border_width: 0.0,
+                border_color: Color::black(),
+            }));
+        }
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let generator = PdfGenerator::new(&doc, &resources, false);
+        let pdf = String::from_utf8_lossy(&generator.generate().unwrap()).into_owned();
+
+        // One profile stream, referenced by both embedding images
+        assert_eq!(pdf.matches("/Alternate /DeviceRGB").count(), 1);
+        assert_eq!(pdf.matches("/ColorSpace [/ICCBased").count(), 2);
+        assert_eq!(pdf.matches("/ColorSpace /DeviceRGB").count(), 2);
+        let profiles: Vec<_> = generator.stats().images.iter().map(|image| (image.name.clone(), image.profile)).collect();
+        assert_eq!(profiles, [
+            ("a".to_string(), Some(ImageProfile::Embed)),
+            ("b".to_string(), Some(ImageProfile::Embed)),
+            ("converted".to_string(), Some(ImageProfile::Srgb)),
+            ("untagged".to_string(), None),
+        ]);
+    }
+
+    #[test]
+    fn test_polyline_caps_and_joins() {
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), Resources::default());
+        doc.pages[0].elements.push(Element::Polygon(PolygonElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            points: vec![(100.0, 100.0), (150.0, 140.0), (200.0, 100.0)],
+            stroke: 8.0,
+            hairline: false,
+            stroke_color: Color::black(),
+            fill_color: None,
+            close: false,
+            fill_rule: FillRule::NonZero,
+            cap: Some(LineCap::Round),
+            join: Some(LineJoin::Bevel),
+            miter_limit: Some(2.0),
+        }));
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let pdf = PdfGenerator::new(&doc, &resources, false).generate().unwrap();
+        let pdf_str = String::from_utf8_lossy(&pdf);
+
+        // Set with the width, inside the element's saved state
+        let expected = "q\n0 0 0 RG\n8 w\n1 J\n2 j\n2 M\n100 692 m\n150 652 l\n200 692 l\nS\nQ\n";
+        assert!(pdf_str.contains(expected), "{}", pdf_str);
+    }
+
+    /// Generate `doc` and check it has one object per allocated ref,
+    /// numbered without gaps
+    fn assert_refs_all_written(doc: &Document) {
+        let resources = LoadedResources::load(&doc.resources).unwrap();
+        let generator = PdfGenerator::new(doc, &resources, false);
+        let pdf = generator.generate().unwrap();
+        let pdf_str = String::from_utf8_lossy(&pdf);
+        let map = generator.ref_map();
+        let allocated = map.lines().count();
+        let mut written: Vec<usize> = pdf_str
+            .lines()
+            .filter_map(|l| l.strip_suffix(" 0 obj")?.parse().ok())
+            .collect();
+        written.sort();
+        assert_eq!(written, (1..=allocated).collect::<Vec<_>>(), "{}", map);
+        assert!(pdf_str.contains(&format!("/Size {}", allocated + 1)), "{}", map);
+    }
+
+    #[test]
+    fn test_every_allocated_ref_is_written() {
+        assert_refs_all_written(&make_empty_doc());
+        // Document info
+        assert_refs_all_written(&make_multi_page_doc());
+
+        // An embedded font and one referenced by name, which has no FontFile
+        let mut res = Resources::default();
+        res.fonts.insert("sans".to_string(), asset_font("IBMPlexSans-Regular.otf"));
+        res.fonts.insert("named".to_string(), FontResource { embed: false, ..asset_font("IBMPlexSans-Regular.otf") });
+        let mut doc = single_page_doc(PageBackground::Color(Color::white()), res);
+        for (y, font) in [(100.0, "sans"), (200.0, "named")] {
+            doc.pages[0].elements.push(Element::SignatureLine(SignatureLineElement {
+                id: None,
+                tagging: Tagging::default(),
+                z: 0,
+                visible: true,
+                x: 72.0,
+                y,
+                w: 200.0,
+                label: Some("Jane Doe".to_string()),
+                sublabels: vec![],
+                font: font.to_string(),
+                size: 10.0,
+                color: Color::black(),
+                stroke: 1.0,
+                mark: false,
+            }));
+        }
+        assert_refs_all_written(&doc);
+
+        // A stitched gradient, a hatch and notes on a repeated page
+        let gradient = Gradient {
+            kind: GradientKind::Linear { angle: 90.0 },
+            stops: vec![
+                GradientStop { offset: 0.0, color: Color::white() },
+                GradientStop { offset: 0.5, color: Color::rgba(200, 220, 255, 255) },
+                GradientStop { offset: 1.0, color: Color::black() },
+            ],
+        };
+        let mut doc = single_page_doc(PageBackground::Gradient(gradient), Resources::default());
+        doc.pages[0].repeat = 3;
+        let mut rect = make_rect_doc().pages.remove(0).elements.remove(0);
+        if let Element::Rect(r) = &mut rect {
+            r.fill_pattern = Some(FillPattern {
+                style: HatchStyle::Cross,
+                spacing: 4.0,
+                line_width: 0.5,
+                color: Color::black(),
+            });
+        }
+        doc.pages[0].elements.push(rect);
+        for x in [100.0, 200.0] {
+            doc.pages[0].elements.push(Element::Note(NoteElement {
+                id: None,
+                tagging: Tagging::default(),
+                z: 0,
+                visible: true,
+                x,
+                y: 100.0,
+                contents: "Check".to_string(),
+                title: None,
+                color: Color::rgba(255, 235, 0, 255),
+                icon: NoteIcon::Comment,
+                open: false,
+            }));
+        }
+        assert_refs_all_written(&doc);
+    }
 }
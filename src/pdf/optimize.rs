@@ -0,0 +1,373 @@
+//! Redundant graphics state removal for finished content streams.
+//!
+//! Renderers wrap each element in `q`/`Q` and set its colors in full,
+//! which keeps them independent but repeats the same operators thousands
+//! of times on pages of small shapes (QR modules, table cells, charts).
+//! `optimize` rewrites a stream so fill and stroke colors, line widths and
+//! alpha states are only set when they differ from the ones in effect,
+//! just before something is drawn, and drops `q`/`Q` pairs that isolate
+//! nothing but those. Every other operator is copied as is, with the
+//! tracked state brought up to date first, so the page paints exactly as
+//! before.
+//!
+//! Renderers set colors and widths before they draw but leave alpha at
+//! whatever is in effect when opaque, so a pair that sets alpha where the
+//! alpha outside it is unknown is kept: there is no operator that puts
+//! the unknown alpha back.
+
+/// One operator with its operands, as written in the stream
+#[derive(Clone, Copy, PartialEq)]
+struct Op<'a> {
+    operands: &'a [u8],
+    name: &'a [u8],
+}
+
+impl Op<'_> {
+    fn write(&self, out: &mut Vec<u8>) {
+        if !self.operands.is_empty() {
+            out.extend_from_slice(self.operands);
+            out.push(b' ');
+        }
+        out.extend_from_slice(self.name);
+        out.push(b'\n');
+    }
+
+    /// The parameter this operator sets, if it is one that's tracked
+    fn param(&self) -> Option<Param> {
+        match self.name {
+            b"rg" | b"g" | b"k" => Some(Param::Fill),
+            b"RG" | b"G" | b"K" => Some(Param::Stroke),
+            b"w" => Some(Param::Width),
+            b"gs" if is_alpha_state(self.operands) => Some(Param::Alpha),
+            _ => None,
+        }
+    }
+}
+
+/// Alpha graphics states are named `/A<alpha>` and set only `ca`/`CA`
+fn is_alpha_state(operands: &[u8]) -> bool {
+    operands.len() > 2 && operands.starts_with(b"/A") && operands[2..].iter().all(u8::is_ascii_digit)
+}
+
+#[derive(Clone, Copy)]
+enum Param {
+    Fill,
+    Stroke,
+    Width,
+    Alpha,
+}
+
+/// The tracked parameters. `None` is unknown: set by an operator that
+/// isn't tracked, or inherited by a form from wherever it is drawn.
+/// Each is stamped with when it was set, so flushing keeps input order.
+#[derive(Clone, Copy, Default)]
+struct State<'a> {
+    params: [Option<(Op<'a>, usize)>; 4],
+}
+
+impl<'a> State<'a> {
+    fn get(&self, param: Param) -> Option<Op<'a>> {
+        self.params[param as usize].map(|(op, _)| op)
+    }
+
+    fn set(&mut self, param: Param, op: Option<Op<'a>>, stamp: usize) {
+        self.params[param as usize] = op.map(|op| (op, stamp));
+    }
+}
+
+fn is_path(name: &[u8]) -> bool {
+    matches!(name, b"m" | b"l" | b"c" | b"v" | b"y" | b"h" | b"re")
+}
+
+fn is_paint(name: &[u8]) -> bool {
+    matches!(name, b"f" | b"F" | b"f*" | b"S" | b"s" | b"B" | b"B*" | b"b" | b"b*" | b"n")
+}
+
+/// Whether a `q`/`Q` pair around `op` can go: it paints paths or sets
+/// tracked state, which is restored by tracking rather than by `Q`.
+fn needs_no_isolation(op: &Op) -> bool {
+    is_path(op.name) || is_paint(op.name) || op.param().is_some() || matches!(op.name, b"q" | b"Q")
+}
+
+/// Rewrite a content stream without redundant state operators. Streams
+/// it can't read (such as inline images) are returned unchanged.
+pub fn optimize(data: &[u8]) -> Vec<u8> {
+    let Some(ops) = tokenize(data) else { return data.to_vec() };
+
+    // A q/Q pair can be dropped when nothing directly inside needs it
+    let mut droppable = vec![false; ops.len()];
+    // Whether a pair sets alpha, itself or in a pair it holds
+    let mut sets_alpha = vec![false; ops.len()];
+    let mut open: Vec<(usize, bool)> = Vec::new();
+    for (i, op) in ops.iter().enumerate() {
+        match op.name {
+            b"q" => open.push((i, true)),
+            b"Q" => {
+                if let Some((start, simple)) = open.pop() {
+                    droppable[start] = simple;
+                    if let Some(&(outer, _)) = open.last() {
+                        sets_alpha[outer] |= sets_alpha[start];
+                    }
+                }
+            }
+            _ if matches!(op.param(), Some(Param::Alpha)) => {
+                if let Some(&(start, _)) = open.last() {
+                    sets_alpha[start] = true;
+                }
+            }
+            _ if !needs_no_isolation(op) => {
+                if let Some((_, simple)) = open.last_mut() {
+                    *simple = false;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut out = Vec::with_capacity(data.len());
+    // What the input has set, and what the output has
+    let mut wanted = State::default();
+    let mut written = State::default();
+    // Per open q: whether it was dropped, and the states it saved
+    let mut saved: Vec<(bool, State, State)> = Vec::new();
+    let mut in_path = false;
+
+    for (i, op) in ops.iter().enumerate() {
+        if let Some(param) = op.param() {
+            wanted.set(param, Some(*op), i);
+            continue;
+        }
+        match op.name {
+            b"q" => {
+                let drop = droppable[i] && !(sets_alpha[i] && wanted.get(Param::Alpha).is_none());
+                if !drop {
+                    flush(&wanted, &mut written, &mut out);
+                    op.write(&mut out);
+                }
+                saved.push((drop, wanted, written));
+            }
+            b"Q" => match saved.pop() {
+                Some((dropped, saved_wanted, saved_written)) => {
+                    if !dropped {
+                        op.write(&mut out);
+                        written = saved_written;
+                    }
+                    wanted = saved_wanted;
+                }
+                None => {
+                    op.write(&mut out);
+                    wanted = State::default();
+                    written = State::default();
+                }
+            },
+            name if is_path(name) => {
+                // State operators aren't allowed inside a path
+                if !in_path {
+                    flush(&wanted, &mut written, &mut out);
+                    in_path = true;
+                }
+                op.write(&mut out);
+            }
+            name => {
+                if !in_path {
+                    flush(&wanted, &mut written, &mut out);
+                }
+                op.write(&mut out);
+                if is_paint(name) {
+                    in_path = false;
+                }
+                // Operators that change tracked state in untracked ways
+                let unknown: &[Param] = match name {
+                    b"cs" | b"sc" | b"scn" => &[Param::Fill],
+                    b"CS" | b"SC" | b"SCN" => &[Param::Stroke],
+                    // An ExtGState may set the line width as well as alpha
+                    b"gs" => &[Param::Alpha, Param::Width],
+                    _ => &[],
+                };
+                for &param in unknown {
+                    wanted.set(param, None, i);
+                    written.set(param, None, i);
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Write whatever `wanted` sets that `written` doesn't, in the order the
+/// input set them
+fn flush<'a>(wanted: &State<'a>, written: &mut State<'a>, out: &mut Vec<u8>) {
+    let mut params = [Param::Fill, Param::Stroke, Param::Width, Param::Alpha];
+    params.sort_by_key(|&param| wanted.params[param as usize].map_or(0, |(_, stamp)| stamp));
+    for param in params {
+        if let Some((op, stamp)) = wanted.params[param as usize] {
+            if written.get(param) != Some(op) {
+                op.write(out);
+                written.set(param, Some(op), stamp);
+            }
+        }
+    }
+}
+
+fn is_space(b: u8) -> bool {
+    matches!(b, b' ' | b'\t' | b'\n' | b'\r' | b'\x0c' | b'\0')
+}
+
+fn is_delimiter(b: u8) -> bool {
+    matches!(b, b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%')
+}
+
+/// Split a stream into operators. `None` if it holds anything this
+/// doesn't read: inline images, or unbalanced strings and brackets.
+fn tokenize(data: &[u8]) -> Option<Vec<Op<'_>>> {
+    let mut ops = Vec::new();
+    let mut pos = 0;
+    // Start and end of the current operator's operands
+    let mut operands: Option<(usize, usize)> = None;
+    while pos < data.len() {
+        let b = data[pos];
+        if is_space(b) {
+            pos += 1;
+            continue;
+        }
+        let start = pos;
+        match b {
+            b'%' => {
+                while pos < data.len() && data[pos] != b'\n' && data[pos] != b'\r' {
+                    pos += 1;
+                }
+                continue;
+            }
+            b'(' => pos = skip_string(data, pos)?,
+            b'[' | b'<' => pos = skip_nested(data, pos)?,
+            b'/' => {
+                pos += 1;
+                while pos < data.len() && !is_space(data[pos]) && !is_delimiter(data[pos]) {
+                    pos += 1;
+                }
+            }
+            b')' | b']' | b'>' | b'{' | b'}' => return None,
+            _ => {
+                while pos < data.len() && !is_space(data[pos]) && !is_delimiter(data[pos]) {
+                    pos += 1;
+                }
+                let word = &data[start..pos];
+                let operand = word[0].is_ascii_digit()
+                    || matches!(word[0], b'+' | b'-' | b'.')
+                    || matches!(word, b"true" | b"false" | b"null");
+                if !operand {
+                    if word == b"BI" {
+                        return None;
+                    }
+                    let operands = operands.take().map_or(&data[start..start], |(from, to)| &data[from..to]);
+                    ops.push(Op { operands, name: word });
+                    continue;
+                }
+            }
+        }
+        operands = Some((operands.map_or(start, |(from, _)| from), pos));
+    }
+    // Trailing operands without an operator
+    if operands.is_some() {
+        return None;
+    }
+    Some(ops)
+}
+
+/// Skip a literal string starting at `pos`, returning the position after it
+fn skip_string(data: &[u8], mut pos: usize) -> Option<usize> {
+    let mut depth = 0;
+    while pos < data.len() {
+        match data[pos] {
+            b'\\' => pos += 1,
+            b'(' => depth += 1,
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(pos + 1);
+                }
+            }
+            _ => {}
+        }
+        pos += 1;
+    }
+    None
+}
+
+/// Skip an array, hex string or dictionary starting at `pos`
+fn skip_nested(data: &[u8], mut pos: usize) -> Option<usize> {
+    let mut depth = 0;
+    while pos < data.len() {
+        match data[pos] {
+            b'(' => {
+                pos = skip_string(data, pos)?;
+                continue;
+            }
+            b'[' | b'<' => depth += 1,
+            b']' | b'>' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(pos + 1);
+                }
+            }
+            _ => {}
+        }
+        pos += 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(stream: &str) -> String {
+        String::from_utf8(optimize(stream.as_bytes())).unwrap()
+    }
+
+    #[test]
+    fn repeated_state_is_set_once() {
+        let stream = "/A255 gs\nq\n1 0 0 rg\n/A255 gs\n0 0 1 1 re\nf\nQ\nq\n1 0 0 rg\n/A255 gs\n2 0 1 1 re\nf\nQ\n";
+        assert_eq!(run(stream), "1 0 0 rg\n/A255 gs\n0 0 1 1 re\nf\n2 0 1 1 re\nf\n");
+    }
+
+    #[test]
+    fn alpha_set_from_unknown_keeps_its_pair() {
+        // The second rect is drawn at whatever alpha the stream started
+        // with, which only Q can bring back
+        let stream = "q\n/A128 gs\n1 0 0 rg\n0 0 1 1 re\nf\nQ\nq\n1 0 0 rg\n2 0 1 1 re\nf\nQ\n";
+        assert_eq!(run(stream), "q\n/A128 gs\n1 0 0 rg\n0 0 1 1 re\nf\nQ\n1 0 0 rg\n2 0 1 1 re\nf\n");
+    }
+
+    #[test]
+    fn restored_state_is_set_again() {
+        // The second rect relies on the color Q restores, so it is
+        // written back once the first rect's q/Q is gone
+        let stream = "0 0 1 rg\nq\n1 0 0 rg\n0 0 1 1 re\nf\nQ\n2 0 1 1 re\nf\n";
+        assert_eq!(run(stream), "1 0 0 rg\n0 0 1 1 re\nf\n0 0 1 rg\n2 0 1 1 re\nf\n");
+    }
+
+    #[test]
+    fn isolation_is_kept_where_needed() {
+        // Clips and transforms keep their q/Q; text is drawn in the state
+        // the input set for it
+        let stream = "q\n0 0 10 10 re\nW\nn\n1 0 0 rg\n0 0 5 5 re\nf\nQ\nq\n1 0 0 1 5 5 cm\n1 0 0 rg\nBT\n/F1 12 Tf\n(a\\)b) Tj\nET\nQ\n";
+        let out = run(stream);
+        assert_eq!(out.matches("q\n").count(), 2);
+        assert_eq!(out.matches("Q\n").count(), 2);
+        assert_eq!(out.matches("1 0 0 rg").count(), 2, "each q/Q starts from the state outside it");
+        assert!(out.contains("(a\\)b) Tj"));
+    }
+
+    #[test]
+    fn untracked_color_spaces_reset_tracking() {
+        let stream = "1 0 0 rg\n0 0 1 1 re\nf\n/Spot cs\n1 scn\n0 0 1 1 re\nf\n1 0 0 rg\n0 0 1 1 re\nf\n";
+        assert_eq!(run(stream).matches("1 0 0 rg").count(), 2);
+    }
+
+    #[test]
+    fn unreadable_streams_are_kept() {
+        let stream = b"BI /W 1 /H 1 ID \xff EI\n";
+        assert_eq!(optimize(stream), stream.to_vec());
+    }
+}
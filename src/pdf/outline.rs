@@ -0,0 +1,119 @@
+//! Text filled as glyph outlines.
+//!
+//! A text element with `outline` draws each glyph as a path instead of
+//! showing it with the font, for output devices that mishandle embedded
+//! CID fonts and for short strings where embedding a subset isn't worth
+//! it. Glyphs land where `Tj` would put them: the same advances, fitted
+//! spacing and horizontal scale, and the style fills of a marked-up line.
+//! TrueType quadratic curves are raised to cubics, since PDF paths have
+//! no quadratic segment.
+
+use crate::elements::fit::Fitted;
+use crate::pdf::writer::set_fill;
+use crate::resources::LoadedFont;
+use crate::runs::Segment;
+use crate::types::Color;
+use pdf_writer::Content;
+
+/// Appends outline segments to a content stream, mapping font units to
+/// page space
+struct GlyphPath<'c> {
+    content: &'c mut Content,
+    /// Glyph origin on the baseline, in points
+    origin: (f32, f32),
+    /// Points per font unit, horizontally and vertically
+    scale: (f32, f32),
+    /// Current point in font units, for raising quadratics
+    current: (f32, f32),
+}
+
+impl GlyphPath<'_> {
+    fn map(&self, x: f32, y: f32) -> (f32, f32) {
+        (self.origin.0 + x * self.scale.0, self.origin.1 + y * self.scale.1)
+    }
+}
+
+impl ttf_parser::OutlineBuilder for GlyphPath<'_> {
+    fn move_to(&mut self, x: f32, y: f32) {
+        let (px, py) = self.map(x, y);
+        self.content.move_to(px, py);
+        self.current = (x, y);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let (px, py) = self.map(x, y);
+        self.content.line_to(px, py);
+        self.current = (x, y);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (x0, y0) = self.current;
+        let c1 = (x0 + 2.0 / 3.0 * (x1 - x0), y0 + 2.0 / 3.0 * (y1 - y0));
+        let c2 = (x + 2.0 / 3.0 * (x1 - x), y + 2.0 / 3.0 * (y1 - y));
+        self.curve_to(c1.0, c1.1, c2.0, c2.1, x, y);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let (a, b) = self.map(x1, y1);
+        let (c, d) = self.map(x2, y2);
+        let (e, f) = self.map(x, y);
+        self.content.cubic_to(a, b, c, d, e, f);
+        self.current = (x, y);
+    }
+
+    fn close(&mut self) {
+        self.content.close_path();
+    }
+}
+
+/// Fill a piece's text segments as outlines, with the piece starting at
+/// `origin` on the baseline and the plain fill already set. Each run is
+/// one path and one fill. Returns the color glyphs to draw over it, as
+/// `show_segments` does.
+pub fn fill_segments(
+    content: &mut Content,
+    segments: &[Segment],
+    chain: &[&LoadedFont],
+    size: f32,
+    fitted: Option<&Fitted>,
+    origin: (f32, f32),
+    fills: &[Color],
+) -> Vec<(f32, usize, u16)> {
+    let (char_spacing, h_scale) = fitted.map_or((0.0, 1.0), |f| (f.char_spacing, f.scale));
+    let mut color_glyphs = Vec::new();
+    // Style whose fill is set; the caller sets the plain one
+    let mut fill = 0;
+    for segment in segments {
+        let Segment::Text { x, run } = segment else {
+            if let Segment::Image { x, chain_index, glyph_id } = segment {
+                color_glyphs.push((*x, *chain_index, *glyph_id));
+            }
+            continue;
+        };
+        if !fills.is_empty() && fills[run.style] != fills[fill] {
+            set_fill(content, &fills[run.style]);
+            fill = run.style;
+        }
+        let font = chain[run.chain_index];
+        let per_unit = size / font.units_per_em as f32;
+        let mut pen = origin.0 + x;
+        let mut drawn = false;
+        for &(_, glyph_id) in &run.glyphs {
+            let mut path = GlyphPath {
+                content: &mut *content,
+                origin: (pen, origin.1),
+                scale: (per_unit * h_scale, per_unit),
+                current: (0.0, 0.0),
+            };
+            drawn |= font.outline_glyph(glyph_id, &mut path);
+            pen += font.advance_pts(glyph_id, size) * h_scale + char_spacing;
+        }
+        if drawn {
+            content.fill_nonzero();
+        }
+    }
+    if fills.get(fill) != fills.first() {
+        set_fill(content, &fills[0]);
+    }
+    color_glyphs
+}
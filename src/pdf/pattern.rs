@@ -0,0 +1,158 @@
+//! Hatch fills as PDF tiling patterns.
+//!
+//! Each distinct pattern is one small tile, repeated by the viewer over
+//! whatever area is filled, so a hatched page-sized box costs no more than
+//! a hatched thumbnail. Tiles live in the default coordinate space of the
+//! page, so neighboring hatched shapes line up. Renderers derive the
+//! resource name from the pattern itself and need no registry access.
+
+use crate::pdf::refs::RefRegistry;
+use crate::pdf::writer::set_stroke;
+use crate::types::{FillPattern, HatchStyle};
+use pdf_writer::types::{LineCapStyle, PaintType, TilingType};
+use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+struct PatternEntry {
+    name: String,
+    pattern: FillPattern,
+    pattern_ref: Ref,
+}
+
+/// Tiling patterns used by the document, collected during the first pass.
+#[derive(Default)]
+pub struct HatchPatterns {
+    entries: Vec<PatternEntry>,
+    by_name: HashMap<String, usize>,
+}
+
+impl HatchPatterns {
+    /// Pattern resource name for `pattern`.
+    pub fn name(pattern: &FillPattern) -> String {
+        let mut hasher = DefaultHasher::new();
+        format!("{:?}", pattern).hash(&mut hasher);
+        format!("Hatch{:016x}", Hasher::finish(&hasher))
+    }
+
+    /// Register a pattern, allocating its object on first use.
+    pub fn register(&mut self, pattern: &FillPattern, refs: &mut RefRegistry) {
+        let name = Self::name(pattern);
+        if self.by_name.contains_key(&name) {
+            return;
+        }
+        self.by_name.insert(name.clone(), self.entries.len());
+        let pattern_ref = refs.alloc(format!("pattern[{}]", name));
+        self.entries.push(PatternEntry { name, pattern: pattern.clone(), pattern_ref });
+    }
+
+    /// (resource name, pattern ref) pairs for resource dictionaries.
+    pub fn patterns(&self) -> impl Iterator<Item = (String, Ref)> + '_ {
+        self.entries.iter().map(|e| (e.name.clone(), e.pattern_ref))
+    }
+
+    /// Write each pattern. `color_spaces` are the document's spot color
+    /// spaces, for patterns drawn in a spot ink.
+    pub fn write(&self, pdf: &mut Pdf, color_spaces: &[(String, Ref)]) {
+        for entry in &self.entries {
+            let (cell, data) = tile(&entry.pattern);
+            let mut pattern = pdf.tiling_pattern(entry.pattern_ref, &data);
+            pattern.paint_type(PaintType::Colored);
+            pattern.tiling_type(TilingType::ConstantSpacing);
+            pattern.bbox(Rect::new(0.0, 0.0, cell, cell));
+            pattern.x_step(cell);
+            pattern.y_step(cell);
+            let mut resources = pattern.resources();
+            if entry.pattern.color.spot().is_some() {
+                let mut spaces = resources.color_spaces();
+                for (name, space_ref) in color_spaces {
+                    spaces.pair(Name(name.as_bytes()), *space_ref);
+                }
+                spaces.finish();
+            }
+            resources.finish();
+            pattern.finish();
+        }
+    }
+}
+
+/// The tile's side and content stream. Line styles put `spacing` between
+/// neighboring lines; diagonal tiles are √2 wider to do so at 45°.
+fn tile(pattern: &FillPattern) -> (f32, Vec<u8>) {
+    let s = pattern.spacing;
+    let w = pattern.line_width;
+    let mut content = Content::new();
+    set_stroke(&mut content, &pattern.color);
+    content.set_line_width(w);
+    let cell = match pattern.style {
+        HatchStyle::Diagonal | HatchStyle::Cross => s * std::f32::consts::SQRT_2,
+        _ => s,
+    };
+    // Diagonals run a line width past the tile on each side, so the bbox
+    // clip rather than the line caps decides where they end, and the lines
+    // through neighboring tiles fill this tile's corners
+    let mut diagonal = |rising: bool| {
+        for k in [-1.0, 0.0, 1.0] {
+            let (x0, x1) = (k * cell - w, k * cell + cell + w);
+            let (y0, y1) = if rising { (-w, cell + w) } else { (cell + w, -w) };
+            content.move_to(x0, y0);
+            content.line_to(x1, y1);
+        }
+    };
+    match pattern.style {
+        HatchStyle::Diagonal => diagonal(true),
+        HatchStyle::Cross => {
+            diagonal(true);
+            diagonal(false);
+        }
+        HatchStyle::Horizontal => {
+            content.move_to(0.0, s / 2.0);
+            content.line_to(s, s / 2.0);
+        }
+        HatchStyle::Vertical => {
+            content.move_to(s / 2.0, 0.0);
+            content.line_to(s / 2.0, s);
+        }
+        HatchStyle::Dots => {
+            // A zero-length line with round caps is a dot `w` across
+            content.set_line_cap(LineCapStyle::RoundCap);
+            content.move_to(s / 2.0, s / 2.0);
+            content.line_to(s / 2.0, s / 2.0);
+        }
+    }
+    content.stroke();
+    (cell, content.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Color;
+
+    fn hatch(style: HatchStyle, spacing: f32) -> FillPattern {
+        FillPattern { style, spacing, line_width: 0.5, color: Color::black() }
+    }
+
+    #[test]
+    fn registry_dedupes_by_pattern() {
+        let mut alloc = RefRegistry::default();
+        let mut patterns = HatchPatterns::default();
+        patterns.register(&hatch(HatchStyle::Diagonal, 4.0), &mut alloc);
+        patterns.register(&hatch(HatchStyle::Diagonal, 4.0), &mut alloc);
+        patterns.register(&hatch(HatchStyle::Diagonal, 6.0), &mut alloc);
+        patterns.register(&hatch(HatchStyle::Cross, 4.0), &mut alloc);
+        let names: Vec<String> = patterns.patterns().map(|(n, _)| n).collect();
+        assert_eq!(names.len(), 3);
+        assert_eq!(names[0], HatchPatterns::name(&hatch(HatchStyle::Diagonal, 4.0)));
+    }
+
+    #[test]
+    fn diagonal_tiles_space_lines_at_45_degrees() {
+        let (cell, _) = tile(&hatch(HatchStyle::Diagonal, 4.0));
+        assert!((cell - 5.657).abs() < 1e-3);
+        let (cell, data) = tile(&hatch(HatchStyle::Horizontal, 4.0));
+        assert_eq!(cell, 4.0);
+        assert!(String::from_utf8_lossy(&data).contains("0 2 m\n4 2 l"));
+    }
+}
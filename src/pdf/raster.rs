@@ -0,0 +1,173 @@
+//! Pixel work for raster images before they are JPEG-encoded: flattening
+//! alpha against white, downscaling to the embedded size, for sources
+//! with more than 8 bits per channel, dithering down to 8 bits and, for
+//! sources converted out of their own ICC profile, converting to sRGB.
+
+use image::{DynamicImage, ImageBuffer, Pixel, Rgb, Rgb32FImage, RgbImage};
+use moxcms::{CmsError, ColorProfile, Layout, TransformOptions};
+
+/// Flatten alpha channel against white background
+/// This properly composites transparent pixels instead of just discarding alpha
+pub fn flatten_alpha_to_white(img: &DynamicImage) -> RgbImage {
+    let rgba = img.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    let mut rgb = RgbImage::new(width, height);
+
+    for (x, y, pixel) in rgba.enumerate_pixels() {
+        let alpha = pixel[3] as f32 / 255.0;
+        // Composite: result = foreground * alpha + background * (1 - alpha)
+        // With white background (255, 255, 255)
+        let r = (pixel[0] as f32 * alpha + 255.0 * (1.0 - alpha)) as u8;
+        let g = (pixel[1] as f32 * alpha + 255.0 * (1.0 - alpha)) as u8;
+        let b = (pixel[2] as f32 * alpha + 255.0 * (1.0 - alpha)) as u8;
+        rgb.put_pixel(x, y, Rgb([r, g, b]));
+    }
+    rgb
+}
+
+/// Whether the decoded image has more than 8 bits per channel.
+pub fn is_high_bit_depth(img: &DynamicImage) -> bool {
+    use image::ColorType::*;
+    matches!(img.color(), L16 | La16 | Rgb16 | Rgba16 | Rgb32F | Rgba32F)
+}
+
+/// `flatten_alpha_to_white` at full precision, with channels in 0..=1.
+/// A 16-bit alpha channel keeps all of its steps.
+pub fn flatten_alpha_to_white_f32(img: &DynamicImage) -> Rgb32FImage {
+    let rgba = img.to_rgba32f();
+    let (width, height) = rgba.dimensions();
+    Rgb32FImage::from_fn(width, height, |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        Rgb([r * a + 1.0 - a, g * a + 1.0 - a, b * a + 1.0 - a])
+    })
+}
+
+/// Downscale to fit within `target` pixels, preserving aspect ratio.
+/// Images already within it are returned unchanged; never upscales.
+pub fn fit_pixels<P>(
+    img: ImageBuffer<P, Vec<P::Subpixel>>,
+    (target_width, target_height): (u32, u32),
+) -> ImageBuffer<P, Vec<P::Subpixel>>
+where
+    P: Pixel + 'static,
+    P::Subpixel: 'static,
+{
+    let (src_width, src_height) = img.dimensions();
+    if src_width <= target_width && src_height <= target_height {
+        return img;
+    }
+    // Calculate scale to fit within target bounds while preserving aspect ratio
+    let scale_x = target_width as f32 / src_width as f32;
+    let scale_y = target_height as f32 / src_height as f32;
+    let scale = scale_x.min(scale_y).min(1.0); // Never upscale
+
+    let new_width = (src_width as f32 * scale).round() as u32;
+    let new_height = (src_height as f32 * scale).round() as u32;
+
+    // Use Lanczos3 for high-quality downscaling
+    image::imageops::resize(&img, new_width, new_height, image::imageops::FilterType::Lanczos3)
+}
+
+/// Quantize to 8 bits per channel with Floyd–Steinberg error diffusion:
+/// each pixel's rounding error is spread onto the neighbors still to come,
+/// so a gradient between two 8-bit levels averages to its true value
+/// instead of banding. Values outside 0..=1 (Lanczos overshoot) clamp.
+pub fn dither_to_rgb8(img: &Rgb32FImage) -> RgbImage {
+    let (width, height) = img.dimensions();
+    let (w, h) = (width as usize, height as usize);
+    let mut levels: Vec<f32> = img.as_raw().iter().map(|v| v * 255.0).collect();
+    let mut out = RgbImage::new(width, height);
+    for y in 0..h {
+        for x in 0..w {
+            for c in 0..3 {
+                let i = (y * w + x) * 3 + c;
+                let quantized = levels[i].round().clamp(0.0, 255.0);
+                let error = levels[i] - quantized;
+                out.get_pixel_mut(x as u32, y as u32)[c] = quantized as u8;
+                let mut spread = |dx: isize, dy: usize, weight: f32| {
+                    let nx = x as isize + dx;
+                    if nx >= 0 && (nx as usize) < w && y + dy < h {
+                        levels[((y + dy) * w + nx as usize) * 3 + c] += error * weight;
+                    }
+                };
+                spread(1, 0, 7.0 / 16.0);
+                spread(-1, 1, 3.0 / 16.0);
+                spread(0, 1, 5.0 / 16.0);
+                spread(1, 1, 1.0 / 16.0);
+            }
+        }
+    }
+    out
+}
+
+/// Convert pixels from the RGB color space the ICC profile `icc`
+/// describes to sRGB.
+pub fn convert_to_srgb(img: &mut RgbImage, icc: &[u8]) -> Result<(), CmsError> {
+    let source = ColorProfile::new_from_slice(icc)?;
+    let transform = source.create_transform_8bit(Layout::Rgb, &ColorProfile::new_srgb(), Layout::Rgb, TransformOptions::default())?;
+    let mut converted = vec![0; img.as_raw().len()];
+    transform.transform(img.as_raw(), &mut converted)?;
+    *img = RgbImage::from_raw(img.width(), img.height(), converted).expect("same size as the source");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Luma, LumaA};
+
+    #[test]
+    fn dithering_averages_between_levels() {
+        // 100.5 sits halfway between two 8-bit levels
+        let img = Rgb32FImage::from_pixel(32, 32, Rgb([100.5 / 255.0; 3]));
+        let out = dither_to_rgb8(&img);
+        assert!(out.pixels().all(|p| p[0] == 100 || p[0] == 101));
+        let mean = out.pixels().map(|p| p[0] as f32).sum::<f32>() / (32.0 * 32.0);
+        assert!((mean - 100.5).abs() < 0.05, "{}", mean);
+    }
+
+    #[test]
+    fn dithering_clamps_overshoot() {
+        let img = Rgb32FImage::from_fn(4, 1, |x, _| Rgb([if x % 2 == 0 { 1.2 } else { -0.2 }; 3]));
+        let out = dither_to_rgb8(&img);
+        assert!(out.pixels().all(|p| p[0] == 255 || p[0] == 0));
+    }
+
+    #[test]
+    fn sixteen_bit_alpha_flattens_at_full_precision() {
+        // Black at just over half coverage: 8-bit alpha would round it
+        let img = DynamicImage::ImageLumaA16(ImageBuffer::from_pixel(2, 2, LumaA([0u16, 32896])));
+        assert!(is_high_bit_depth(&img));
+        let flat = flatten_alpha_to_white_f32(&img);
+        let expected = 1.0 - 32896.0 / 65535.0;
+        assert!((flat.get_pixel(0, 0)[0] - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn eight_bit_sources_are_not_high_depth() {
+        let img = DynamicImage::ImageLuma8(ImageBuffer::from_pixel(1, 1, Luma([7u8])));
+        assert!(!is_high_bit_depth(&img));
+    }
+
+    #[test]
+    fn fit_pixels_only_downscales() {
+        let img = RgbImage::new(100, 50);
+        assert_eq!(fit_pixels(img.clone(), (200, 200)).dimensions(), (100, 50));
+        assert_eq!(fit_pixels(img, (50, 50)).dimensions(), (50, 25));
+    }
+
+    #[test]
+    fn display_p3_converts_to_srgb() {
+        let p3 = ColorProfile::new_display_p3().encode().unwrap();
+        let mut img = RgbImage::from_pixel(2, 2, Rgb([200, 100, 50]));
+        convert_to_srgb(&mut img, &p3).unwrap();
+        // P3's wider red primary takes a more saturated sRGB value
+        let [r, g, b] = img.get_pixel(1, 1).0;
+        assert!(r > 210 && g < 100 && b < 50, "{:?}", (r, g, b));
+
+        let srgb = ColorProfile::new_srgb().encode().unwrap();
+        let mut img = RgbImage::from_pixel(1, 1, Rgb([200, 100, 50]));
+        convert_to_srgb(&mut img, &srgb).unwrap();
+        assert!(img.get_pixel(0, 0).0.iter().zip([200, 100, 50]).all(|(&a, b)| a.abs_diff(b) <= 1));
+    }
+}
@@ -0,0 +1,224 @@
+//! Gradient fills as PDF axial and radial shadings.
+//!
+//! Shadings are written in box-local coordinates (origin at the bottom-left
+//! corner of the filled box), so one shading object serves every box with the
+//! same gradient and size. Callers translate to the box origin before `sh`.
+
+use crate::pdf::refs::RefRegistry;
+use crate::types::{Gradient, GradientKind};
+use pdf_writer::types::FunctionShadingType;
+use pdf_writer::{Content, Finish, Name, Pdf, Ref};
+use std::collections::HashMap;
+
+struct ShadingEntry {
+    gradient: Gradient,
+    w: f32,
+    h: f32,
+    shading_ref: Ref,
+}
+
+/// Shadings used by the document, collected during the first pass.
+#[derive(Default)]
+pub struct ShadingRegistry {
+    entries: Vec<ShadingEntry>,
+    by_key: HashMap<String, usize>,
+}
+
+impl ShadingRegistry {
+    fn key(gradient: &Gradient, w: f32, h: f32) -> String {
+        format!("{:?}_{:.2}x{:.2}", gradient, w, h)
+    }
+
+    fn name(index: usize) -> String {
+        format!("Sh{}", index)
+    }
+
+    /// Register a gradient filling a `w × h` box, allocating its object ref
+    /// on first use. Returns the resource name.
+    pub fn register(&mut self, gradient: &Gradient, w: f32, h: f32, refs: &mut RefRegistry) -> String {
+        let key = Self::key(gradient, w, h);
+        let index = *self.by_key.entry(key).or_insert_with(|| {
+            let name = Self::name(self.entries.len());
+            self.entries.push(ShadingEntry {
+                gradient: gradient.clone(),
+                w,
+                h,
+                shading_ref: refs.alloc(format!("shading[{}]", name)),
+            });
+            self.entries.len() - 1
+        });
+        Self::name(index)
+    }
+
+    /// Resource name of a gradient registered in the first pass.
+    pub fn lookup(&self, gradient: &Gradient, w: f32, h: f32) -> Option<String> {
+        self.by_key.get(&Self::key(gradient, w, h)).map(|&i| Self::name(i))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// (resource name, shading ref) pairs for page resource dictionaries.
+    pub fn resources(&self) -> impl Iterator<Item = (String, Ref)> + '_ {
+        self.entries
+            .iter()
+            .enumerate()
+            .map(|(i, e)| (Self::name(i), e.shading_ref))
+    }
+
+    /// Write every shading and its color function(s).
+    pub fn write(&self, pdf: &mut Pdf, refs: &mut RefRegistry) {
+        for (i, entry) in self.entries.iter().enumerate() {
+            let function_ref = write_function(pdf, &entry.gradient, &format!("shading[{}]", Self::name(i)), refs);
+            let mut shading = pdf.function_shading(entry.shading_ref);
+            match entry.gradient.kind {
+                GradientKind::Linear { angle } => {
+                    shading.shading_type(FunctionShadingType::Axial);
+                    shading.coords(axial_coords(angle, entry.w, entry.h));
+                }
+                GradientKind::Radial { center, radius } => {
+                    shading.shading_type(FunctionShadingType::Radial);
+                    shading.coords(radial_coords(center, radius, entry.w, entry.h));
+                }
+            }
+            shading.color_space().device_rgb();
+            shading.function(function_ref);
+            shading.extend([true, true]);
+            shading.finish();
+        }
+    }
+}
+
+/// Axis endpoints `[x0, y0, x1, y1]` for a linear gradient at `angle`
+/// degrees across a `w × h` box, in box-local PDF coordinates.
+///
+/// The axis passes through the box center and is just long enough that the
+/// first and last stops land on the box corners furthest along it (the
+/// same convention as CSS `linear-gradient`).
+fn axial_coords(angle: f32, w: f32, h: f32) -> [f32; 4] {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    // Angles are measured clockwise in the y-down user space; flip y for PDF.
+    let (dx, dy) = (cos, -sin);
+    let half = (w * cos.abs() + h * sin.abs()) / 2.0;
+    let (cx, cy) = (w / 2.0, h / 2.0);
+    [cx - dx * half, cy - dy * half, cx + dx * half, cy + dy * half]
+}
+
+/// Circles `[x0, y0, r0, x1, y1, r1]` for a radial gradient across a
+/// `w × h` box, in box-local PDF coordinates: from a point at `center`
+/// (top-left based, default the middle) out to `radius`, by default just
+/// reaching the farthest corner as CSS `farthest-corner` does.
+fn radial_coords(center: Option<(f32, f32)>, radius: Option<f32>, w: f32, h: f32) -> [f32; 6] {
+    let (cx, cy) = center.unwrap_or((w / 2.0, h / 2.0));
+    let radius = radius.unwrap_or_else(|| {
+        let (dx, dy) = (cx.max(w - cx), cy.max(h - cy));
+        dx.hypot(dy)
+    });
+    let y = h - cy;
+    [cx, y, 0.0, cx, y, radius]
+}
+
+/// Write the color function for `gradient`, with refs named after the
+/// shading's, and return its ref: a single Type 2 interpolation for two
+/// stops, a Type 3 stitch of them otherwise. Stop alpha is ignored;
+/// shadings are opaque.
+fn write_function(pdf: &mut Pdf, gradient: &Gradient, shading: &str, refs: &mut RefRegistry) -> Ref {
+    let stops = &gradient.stops;
+    let rgb = |i: usize| {
+        let (r, g, b) = stops[i].color.to_rgb_floats();
+        [r, g, b]
+    };
+
+    let mut segments = Vec::with_capacity(stops.len() - 1);
+    for i in 0..stops.len() - 1 {
+        let segment_ref = refs.alloc(format!("{}.function[{}]", shading, i));
+        let mut f = pdf.exponential_function(segment_ref);
+        f.domain([0.0, 1.0]);
+        f.c0(rgb(i));
+        f.c1(rgb(i + 1));
+        f.n(1.0);
+        f.finish();
+        segments.push(segment_ref);
+    }
+
+    // Axis parameters before the first offset or past the last are clamped
+    // to the function domain, so the end colors run out to the box edges.
+    let first = stops[0].offset;
+    let last = stops[stops.len() - 1].offset;
+    if segments.len() == 1 && first == 0.0 && last == 1.0 {
+        return segments[0];
+    }
+
+    let stitch_ref = refs.alloc(format!("{}.stitch", shading));
+    let mut f = pdf.stitching_function(stitch_ref);
+    f.domain([first, last.max(first + f32::EPSILON)]);
+    f.functions(segments.iter().copied());
+    f.bounds(stops[1..stops.len() - 1].iter().map(|s| s.offset));
+    f.encode(segments.iter().flat_map(|_| [0.0, 1.0]));
+    f.finish();
+    stitch_ref
+}
+
+/// Paint a registered shading over the box whose bottom-left PDF corner is
+/// (`x`, `pdf_y`). The caller is responsible for any clip narrower than
+/// the box itself.
+pub fn paint(content: &mut Content, name: &str, x: f32, pdf_y: f32, w: f32, h: f32) {
+    content.save_state();
+    content.rect(x, pdf_y, w, h);
+    content.clip_nonzero();
+    content.end_path();
+    content.transform([1.0, 0.0, 0.0, 1.0, x, pdf_y]);
+    content.shading(Name(name.as_bytes()));
+    content.restore_state();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn approx(a: [f32; 4], b: [f32; 4]) -> bool {
+        a.iter().zip(b.iter()).all(|(x, y)| (x - y).abs() < 1e-3)
+    }
+
+    #[test]
+    fn horizontal_axis_spans_width() {
+        assert!(approx(axial_coords(0.0, 200.0, 100.0), [0.0, 50.0, 200.0, 50.0]));
+    }
+
+    #[test]
+    fn ninety_degrees_runs_top_to_bottom() {
+        // y-down angle 90 = downward on the page = decreasing PDF y.
+        assert!(approx(axial_coords(90.0, 200.0, 100.0), [100.0, 100.0, 100.0, 0.0]));
+    }
+
+    #[test]
+    fn radial_defaults_to_middle_and_farthest_corner() {
+        let coords = radial_coords(None, None, 80.0, 60.0);
+        assert_eq!(coords, [40.0, 30.0, 0.0, 40.0, 30.0, 50.0]);
+        // Off-center, y measured down from the top
+        let coords = radial_coords(Some((20.0, 15.0)), None, 80.0, 60.0);
+        assert_eq!(coords, [20.0, 45.0, 0.0, 20.0, 45.0, 75.0]);
+        assert_eq!(radial_coords(Some((0.0, 0.0)), Some(10.0), 80.0, 60.0)[5], 10.0);
+    }
+
+    #[test]
+    fn registry_dedupes_by_gradient_and_size() {
+        use crate::types::{Color, GradientStop};
+        let gradient = Gradient {
+            kind: GradientKind::Linear { angle: 0.0 },
+            stops: vec![
+                GradientStop { offset: 0.0, color: Color::black() },
+                GradientStop { offset: 1.0, color: Color::white() },
+            ],
+        };
+        let mut alloc = RefRegistry::default();
+        let mut registry = ShadingRegistry::default();
+        let a = registry.register(&gradient, 100.0, 50.0, &mut alloc);
+        let b = registry.register(&gradient, 100.0, 50.0, &mut alloc);
+        let c = registry.register(&gradient, 100.0, 60.0, &mut alloc);
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert_eq!(registry.lookup(&gradient, 100.0, 60.0), Some(c));
+    }
+}
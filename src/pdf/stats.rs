@@ -0,0 +1,91 @@
+//! Sizes of what went into a generated PDF, for `render_pdf(return_stats=True)`.
+//!
+//! `PdfGenerator` fills these in as it writes: the font and image writers
+//! report the streams they embed, and every content stream is counted as
+//! it is finished.
+
+use crate::types::ImageProfile;
+
+/// One embedded font
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontStats {
+    /// The font's name in `resources.fonts`
+    pub name: String,
+    pub postscript_name: String,
+    /// Glyphs in the subset
+    pub glyphs: usize,
+    /// Whether the font program is embedded (`embed: false` references it by name)
+    pub embedded: bool,
+    /// The compressed subset's size; 0 when not embedded
+    pub bytes: usize,
+}
+
+/// How an image XObject's data is encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageEncoding {
+    /// JPEG (DCTDecode), as photos and other rasters are embedded
+    Jpeg,
+    /// Deflated pixels (FlateDecode), as color emoji bitmaps are embedded
+    Flate,
+    /// Vector drawing in a form XObject
+    Svg,
+}
+
+impl ImageEncoding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ImageEncoding::Jpeg => "jpeg",
+            ImageEncoding::Flate => "flate",
+            ImageEncoding::Svg => "svg",
+        }
+    }
+}
+
+/// One image XObject. A raster drawn at several sizes has one per size.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageStats {
+    /// The image's name in `resources.images`, or the emoji glyph's key
+    pub name: String,
+    pub encoding: ImageEncoding,
+    /// Pixel size as embedded; `None` for SVG
+    pub pixels: Option<(u32, u32)>,
+    /// Resolution at the size it is drawn, in pixels per inch; `None` for
+    /// SVG and emoji, which are scaled with the text
+    pub dpi: Option<f32>,
+    /// Size of the image data (masks included) or form content
+    pub bytes: usize,
+    /// How the source's own ICC profile was honored; `None` when it has
+    /// none
+    pub profile: Option<ImageProfile>,
+}
+
+/// Where one page object was written, for `render_pdf_with_index`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageObject {
+    /// Index into the document's `pages`
+    pub page: usize,
+    /// Object number of the page dictionary
+    pub object: i32,
+    /// Object number of its content stream, shared by the copies of a
+    /// repeated page that draw the same content
+    pub content_object: i32,
+    /// Byte offset of the content stream object in the file
+    pub content_offset: usize,
+}
+
+/// Totals for one generated PDF
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RenderStats {
+    /// Page objects written, each copy of a repeated page included
+    pub pages: usize,
+    /// Page, group and fragment content streams, as written
+    pub content_bytes: usize,
+    /// Fonts in name order
+    pub fonts: Vec<FontStats>,
+    /// Images in name order
+    pub images: Vec<ImageStats>,
+    /// The whole file
+    pub total_bytes: usize,
+    /// Each page object in page order, copies of repeated pages included
+    pub page_objects: Vec<PageObject>,
+}
@@ -0,0 +1,242 @@
+//! Per-render memo of resolved text.
+//!
+//! Label runs draw the same literals ("NET WT", a company address) on
+//! every page. Each distinct string is resolved against its font chain,
+//! encoded and measured once per generator; later draws reuse the result.
+//! The memo is keyed by everything resolution depends on: the chain's
+//! aliases, the markup style fonts, the missing glyph policy, the emoji
+//! fallback and the string
+//! (plus size, width and inline image widths for wrapped text boxes).
+
+use crate::error::Result;
+use crate::pdf::encode_glyphs;
+use crate::resources::LoadedFont;
+use crate::runs::{self, EmojiFallback, ResolvedChar, Segment};
+use crate::types::MissingGlyphPolicy;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Entries kept per map; strings first seen after this are not memoized
+const MAX_ENTRIES: usize = 4096;
+/// Longer strings are resolved on every draw
+const MAX_TEXT_LEN: usize = 1024;
+
+/// A string resolved against a font chain, with the bytes shown for each
+/// of its text segments and its width at the sizes it was drawn at.
+pub struct ShapedText {
+    pub chars: Vec<ResolvedChar>,
+    /// One entry per `Segment::Text` of `runs::segments`, in order
+    pub encoded: Vec<Vec<u8>>,
+    widths: Mutex<Vec<(u32, f32)>>,
+    /// Set once the first pass has registered its glyphs
+    registered: AtomicBool,
+}
+
+impl ShapedText {
+    pub fn new(chars: Vec<ResolvedChar>, chain: &[&LoadedFont], chain_names: &[&str]) -> Self {
+        // Segment boundaries depend on the chain, not the size
+        let encoded = runs::segments(&chars, chain, chain_names, 1.0)
+            .iter()
+            .filter_map(|segment| match segment {
+                Segment::Text { run, .. } => Some(encode_glyphs(&run.glyphs)),
+                Segment::Image { .. } | Segment::Inline { .. } => None,
+            })
+            .collect();
+        Self { chars, encoded, widths: Mutex::new(Vec::new()), registered: AtomicBool::new(false) }
+    }
+
+    /// Whether this is the first call, so the caller should register the
+    /// glyphs. A memoized string is registered on its first draw only;
+    /// its chain, and so its embedders, are part of the memo key.
+    pub fn first_registration(&self) -> bool {
+        !self.registered.swap(true, Ordering::Relaxed)
+    }
+
+    /// Advance width in points at `size`, measured once per size.
+    pub fn width(&self, chain: &[&LoadedFont], size: f32) -> f32 {
+        let mut widths = self.widths.lock().unwrap();
+        if let Some(&(_, width)) = widths.iter().find(|(bits, _)| *bits == size.to_bits()) {
+            return width;
+        }
+        let width = runs::measure(&self.chars, chain, size);
+        widths.push((size.to_bits(), width));
+        width
+    }
+}
+
+#[derive(Default)]
+struct Maps {
+    texts: HashMap<String, Arc<ShapedText>>,
+    wrapped: HashMap<String, Arc<Vec<ShapedText>>>,
+}
+
+/// Memo shared by every draw in one generator. Entries are handed out as
+/// `Arc`s so the lock is only held for lookups and inserts.
+#[derive(Default)]
+pub struct TextCache {
+    maps: Mutex<Maps>,
+}
+
+fn chain_key(
+    chain_names: &[&str],
+    style_fonts: &[Option<usize>],
+    policy: MissingGlyphPolicy,
+    emoji: &EmojiFallback,
+) -> String {
+    format!("{:?}|{:?}|{:?}|{:?}|{:?}", chain_names, style_fonts, policy, emoji.font, emoji.replacement)
+}
+
+impl TextCache {
+    /// Resolve `text` against the chain (see `runs::resolve_styled`), or
+    /// return the memoized result.
+    pub fn shape(
+        &self,
+        text: &str,
+        chain: &[&LoadedFont],
+        chain_names: &[&str],
+        style_fonts: &[Option<usize>],
+        policy: MissingGlyphPolicy,
+        emoji: &EmojiFallback,
+    ) -> Result<Arc<ShapedText>> {
+        let resolve = || -> Result<ShapedText> {
+            let chars = runs::resolve_styled(text, chain, chain_names, style_fonts, policy, emoji)?;
+            Ok(ShapedText::new(chars, chain, chain_names))
+        };
+        if text.len() > MAX_TEXT_LEN {
+            return Ok(Arc::new(resolve()?));
+        }
+        let key = format!("{}|{}", chain_key(chain_names, style_fonts, policy, emoji), text);
+        if let Some(shaped) = self.maps.lock().unwrap().texts.get(&key) {
+            return Ok(shaped.clone());
+        }
+        let shaped = Arc::new(resolve()?);
+        let mut maps = self.maps.lock().unwrap();
+        if maps.texts.len() < MAX_ENTRIES {
+            maps.texts.insert(key, shaped.clone());
+        }
+        Ok(shaped)
+    }
+
+    /// Word-wrap `text` to `max_width` at `size` (see `runs::wrap`), or
+    /// return the memoized lines.
+    #[allow(clippy::too_many_arguments)]
+    pub fn wrap(
+        &self,
+        text: &str,
+        inline: &[f32],
+        chain: &[&LoadedFont],
+        chain_names: &[&str],
+        style_fonts: &[Option<usize>],
+        size: f32,
+        max_width: f32,
+        policy: MissingGlyphPolicy,
+        emoji: &EmojiFallback,
+    ) -> Result<Arc<Vec<ShapedText>>> {
+        let wrap = || -> Result<Vec<ShapedText>> {
+            let lines = runs::wrap(text, inline, chain, chain_names, style_fonts, size, max_width, policy, emoji)?;
+            Ok(lines.into_iter().map(|chars| ShapedText::new(chars, chain, chain_names)).collect())
+        };
+        if text.len() > MAX_TEXT_LEN {
+            return Ok(Arc::new(wrap()?));
+        }
+        let key = format!(
+            "{}|{}|{}|{:?}|{}",
+            chain_key(chain_names, style_fonts, policy, emoji),
+            size.to_bits(),
+            max_width.to_bits(),
+            inline,
+            text
+        );
+        if let Some(lines) = self.maps.lock().unwrap().wrapped.get(&key) {
+            return Ok(lines.clone());
+        }
+        let lines = Arc::new(wrap()?);
+        let mut maps = self.maps.lock().unwrap();
+        if maps.wrapped.len() < MAX_ENTRIES {
+            maps.wrapped.insert(key, lines.clone());
+        }
+        Ok(lines)
+    }
+
+    /// Clear every entry's registration, for a first pass whose embedders
+    /// start empty.
+    pub fn reset_registrations(&self) {
+        let maps = self.maps.lock().unwrap();
+        let lines = maps.wrapped.values().flat_map(|lines| lines.iter());
+        for shaped in maps.texts.values().map(|shaped| &**shaped).chain(lines) {
+            shaped.registered.store(false, Ordering::Relaxed);
+        }
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        let maps = self.maps.lock().unwrap();
+        maps.texts.len() + maps.wrapped.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::LoadedResources;
+    use crate::types::{FontResource, FontSource, Resources};
+
+    fn resources() -> LoadedResources {
+        let mut res = Resources::default();
+        res.fonts.insert(
+            "f".to_string(),
+            FontResource {
+                source: FontSource::Path(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/IBMPlexSans-Regular.otf").to_string()),
+                embed: true,
+                cap_height_glyphs: None,
+            },
+        );
+        LoadedResources::load(&res).unwrap()
+    }
+
+    #[test]
+    fn repeated_strings_resolve_once() {
+        let resources = resources();
+        let chain = vec![resources.get_font("f").unwrap()];
+        let names = vec!["f"];
+        let cache = TextCache::default();
+        let emoji = EmojiFallback::default();
+
+        let first = cache.shape("NET WT", &chain, &names, &[], MissingGlyphPolicy::Drop, &emoji).unwrap();
+        let again = cache.shape("NET WT", &chain, &names, &[], MissingGlyphPolicy::Drop, &emoji).unwrap();
+        assert!(Arc::ptr_eq(&first, &again));
+        assert_eq!(first.encoded.len(), 1);
+        assert_eq!(first.encoded[0].len(), 12, "two bytes per glyph");
+        assert_eq!(first.width(&chain, 10.0), runs::measure(&first.chars, &chain, 10.0));
+
+        // A different policy resolves separately
+        cache.shape("NET WT", &chain, &names, &[], MissingGlyphPolicy::Raise, &emoji).unwrap();
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn memoized_strings_register_once_per_pass() {
+        let resources = resources();
+        let chain = vec![resources.get_font("f").unwrap()];
+        let cache = TextCache::default();
+        let emoji = EmojiFallback::default();
+
+        let first = cache.shape("NET WT", &chain, &["f"], &[], MissingGlyphPolicy::Drop, &emoji).unwrap();
+        assert!(first.first_registration());
+        let again = cache.shape("NET WT", &chain, &["f"], &[], MissingGlyphPolicy::Drop, &emoji).unwrap();
+        assert!(!again.first_registration());
+        cache.reset_registrations();
+        assert!(again.first_registration());
+    }
+
+    #[test]
+    fn long_strings_are_not_kept() {
+        let resources = resources();
+        let chain = vec![resources.get_font("f").unwrap()];
+        let cache = TextCache::default();
+        let text = "x".repeat(MAX_TEXT_LEN + 1);
+        cache.shape(&text, &chain, &["f"], &[], MissingGlyphPolicy::Drop, &EmojiFallback::default()).unwrap();
+        assert_eq!(cache.len(), 0);
+    }
+}
@@ -1,10 +1,28 @@
+use crate::elements::{barcode, fit, leader, overflow, raw, ring, table};
 use crate::error::{Result, RupdfError};
+use crate::extract::{apply, concat, Matrix, IDENTITY};
+use crate::pdf::fade::FadeMasks;
+use crate::pdf::fingerprint::Fingerprint;
+use crate::pdf::pattern::HatchPatterns;
+use crate::pdf::optimize;
+use crate::pdf::outline;
+use crate::pdf::raster;
+use crate::pdf::refs::RefRegistry;
+use crate::pdf::shading::{self, ShadingRegistry};
+use crate::pdf::stats::{ImageEncoding, ImageStats, PageObject, RenderStats};
+use crate::pdf::text_cache::TextCache;
 use crate::pdf::{encode_glyphs, FontEmbedder};
 use crate::resources::{LoadedFont, LoadedImage, LoadedResources};
-use crate::runs::{self, ResolvedChar};
+use crate::runs::{self, EmojiFallback, ResolvedChar, Segment};
 use crate::types::*;
+use crate::units::Origin;
+use pdf_writer::types::{AnnotationFlags, AnnotationIcon, AnnotationType, ColorSpaceOperand, LineCapStyle, LineJoinStyle, OutputIntentSubtype};
+use pdf_writer::writers::{Annotation, Destination};
 use pdf_writer::{Content, Date, Filter, Finish, Name, Pdf, Rect, Ref, Str, TextStr};
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
 
 /// (alias, font) entries describing a primary-plus-fallback font chain.
 /// Tuple values are Copy because they hold borrowed references.
@@ -23,6 +41,59 @@ fn build_chain<'a>(
     Ok(chain)
 }
 
+/// Chain for a text element plus its emoji fallback and the chain index of
+/// each markup style's font (see `runs::resolve_styled`). A configured
+/// emoji font and the style fonts are appended to the chain so their
+/// glyphs resolve like any other.
+fn text_chain<'a>(
+    resources: &'a LoadedResources,
+    primary: &'a str,
+    fallback: &'a [String],
+    emoji: &'a EmojiOptions,
+    styles: &'a [TextStyle],
+) -> Result<(FontChain<'a>, Vec<Option<usize>>, EmojiFallback<'a>)> {
+    let mut chain = build_chain(resources, primary, fallback)?;
+    let font = match &emoji.font {
+        Some(alias) => {
+            chain.push((alias.as_str(), resources.get_font(alias)?));
+            Some(chain.len() - 1)
+        }
+        None => None,
+    };
+    let mut style_fonts = Vec::with_capacity(styles.len());
+    for style in styles {
+        let Some(alias) = &style.font else {
+            style_fonts.push(None);
+            continue;
+        };
+        let index = match chain.iter().position(|(name, _)| name == alias) {
+            Some(index) => index,
+            None => {
+                chain.push((alias.as_str(), resources.get_font(alias)?));
+                chain.len() - 1
+            }
+        };
+        style_fonts.push(Some(index));
+    }
+    Ok((chain, style_fonts, EmojiFallback { font, replacement: emoji.replacement.as_deref() }))
+}
+
+/// Fill for each markup style of a text in `color`, index 0 being plain
+/// text; empty for text without markup.
+fn style_fills(color: &Color, styles: &[TextStyle]) -> Vec<Color> {
+    if styles.is_empty() {
+        return Vec::new();
+    }
+    std::iter::once(color.clone())
+        .chain(styles.iter().map(|s| s.color.clone().unwrap_or_else(|| color.clone())))
+        .collect()
+}
+
+/// XObject resource name for a color glyph bitmap.
+fn color_glyph_key(alias: &str, glyph_id: u16) -> String {
+    format!("emoji_{}_{}", alias, glyph_id)
+}
+
 fn chain_fonts<'a>(chain: &FontChain<'a>) -> Vec<&'a LoadedFont> {
     chain.iter().map(|(_, f)| *f).collect()
 }
@@ -33,30 +104,764 @@ fn chain_aliases<'a>(chain: &FontChain<'a>) -> Vec<&'a str> {
 
 /// Lazily create embedders for the fonts referenced by `resolved`, and
 /// register the (char, glyph_id) for each resolved char on its embedder.
+/// Color glyphs are recorded in `color_glyphs` instead, keyed by XObject
+/// name.
 fn register_resolved_chars<'a>(
     resolved: &[ResolvedChar],
     chain: &FontChain<'a>,
     font_embedders: &mut HashMap<String, FontEmbedder<'a>>,
     alias_to_ps: &mut HashMap<String, String>,
+    color_glyphs: &mut HashMap<String, (&'a LoadedFont, u16)>,
 ) {
-    for c in resolved {
-        let Some((idx, gid)) = c.glyph else { continue };
+    // Runs of one font are registered against one embedder lookup
+    let run_of = |c: &ResolvedChar| c.glyph.map(|(idx, _)| (idx, c.image));
+    let mut start = 0;
+    while start < resolved.len() {
+        let run = run_of(&resolved[start]);
+        let end = resolved[start..]
+            .iter()
+            .position(|c| run_of(c) != run)
+            .map_or(resolved.len(), |n| start + n);
+        let chars = &resolved[start..end];
+        start = end;
+        let Some((idx, image)) = run else { continue };
         let (alias, font) = chain[idx];
-        alias_to_ps
-            .entry(alias.to_string())
-            .or_insert_with(|| font.postscript_name.clone());
-        let embedder = font_embedders
-            .entry(alias.to_string())
-            .or_insert_with(|| FontEmbedder::new(font, alias));
-        embedder.use_glyph(c.ch, gid);
+        if image {
+            for c in chars {
+                let Some((_, gid)) = c.glyph else { continue };
+                color_glyphs.insert(color_glyph_key(alias, gid), (font, gid));
+            }
+            continue;
+        }
+        if !font_embedders.contains_key(alias) {
+            alias_to_ps.insert(alias.to_string(), font.postscript_name.clone());
+            font_embedders.insert(alias.to_string(), FontEmbedder::new(font, alias));
+        }
+        let embedder = font_embedders.get_mut(alias).expect("embedder was just inserted");
+        for c in chars {
+            if let Some((_, gid)) = c.glyph {
+                embedder.use_glyph(c.ch, gid);
+            }
+        }
+    }
+}
+
+/// Content written once as a Form XObject and drawn by reference, each as
+/// (XObject name, ref)
+#[derive(Default)]
+struct Forms {
+    /// Transparency groups and shared fragments, keyed by `group_key` or
+    /// `fragment_key`
+    groups: HashMap<(usize, u32), (String, Ref)>,
+    /// Symbols placed more than once, keyed by `symbol_key`
+    symbols: HashMap<String, (String, Ref)>,
+}
+
+/// Identify a group by its address and the height of the page it is drawn
+/// on. Children are placed in page coordinates, so a group repeated on
+/// pages of different heights needs one form per height.
+fn group_key(group: &GroupElement, page_height: f32) -> (usize, u32) {
+    (group as *const GroupElement as usize, page_height.to_bits())
+}
+
+/// Find the transparency groups under `element`, outermost first.
+fn collect_groups<'a>(element: &'a Element, page: &'a Page, groups: &mut Vec<(&'a GroupElement, &'a Page)>) {
+    let group = match element {
+        Element::Group(group) if group.visible => group,
+        Element::Fragment(f) if f.visible => {
+            for child in &f.fragment.elements {
+                collect_groups(child, page, groups);
+            }
+            return;
+        }
+        _ => return,
+    };
+    let key = group_key(group, page.height);
+    if group.transparency_group && !groups.iter().any(|(g, p)| group_key(g, p.height) == key) {
+        groups.push((group, page));
+    }
+    for child in &group.elements {
+        collect_groups(child, page, groups);
+    }
+}
+
+/// Record where each group and fragment under `element` can show
+/// anything: the page box mapped back through `ctm`, the transform the
+/// element is drawn under, and through the form's own placement. A form
+/// drawn in several places gets the union of them, as
+/// `[left, bottom, right, top]` in the form's space.
+fn collect_form_boxes(element: &Element, page: &Page, ctm: Matrix, boxes: &mut HashMap<(usize, u32), [f32; 4]>) {
+    if !element.visible() {
+        return;
+    }
+    let (key, ctm, children) = match element {
+        Element::Group(g) => {
+            let ctm = if g.is_identity() { ctm } else { concat(&g.matrix(page.height), &ctm) };
+            (group_key(g, page.height), ctm, &g.elements)
+        }
+        Element::Fragment(f) => {
+            (fragment_key(&f.fragment, page.height), concat(&f.matrix(page.height), &ctm), &f.fragment.elements)
+        }
+        _ => return,
+    };
+    let visible = page_box_in(ctm, page);
+    boxes
+        .entry(key)
+        .and_modify(|b| *b = [b[0].min(visible[0]), b[1].min(visible[1]), b[2].max(visible[2]), b[3].max(visible[3])])
+        .or_insert(visible);
+    for child in children {
+        collect_form_boxes(child, page, ctm, boxes);
+    }
+}
+
+/// The page box in the space `ctm` maps onto the page, as
+/// `[left, bottom, right, top]`
+fn page_box_in(ctm: Matrix, page: &Page) -> [f32; 4] {
+    let [a, b, c, d, e, f] = ctm;
+    let det = a * d - b * c;
+    let inverse = [d / det, -b / det, -c / det, a / det, (c * f - d * e) / det, (b * e - a * f) / det];
+    let corners = [(0.0, 0.0), (page.width, 0.0), (page.width, page.height), (0.0, page.height)]
+        .map(|(x, y)| apply(&inverse, x, y));
+    let (xs, ys) = (corners.map(|(x, _)| x), corners.map(|(_, y)| y));
+    [
+        xs.into_iter().fold(f32::INFINITY, f32::min),
+        ys.into_iter().fold(f32::INFINITY, f32::min),
+        xs.into_iter().fold(f32::NEG_INFINITY, f32::max),
+        ys.into_iter().fold(f32::NEG_INFINITY, f32::max),
+    ]
+}
+
+/// Identify a fragment like a group: its elements are drawn in page
+/// coordinates, so it needs one form per page height.
+fn fragment_key(fragment: &Fragment, page_height: f32) -> (usize, u32) {
+    (fragment as *const Fragment as usize, page_height.to_bits())
+}
+
+/// Count the placements of each fragment under `element`. A fragment's
+/// own contents are walked on its first placement only: placed again, it
+/// is drawn from the one form its contents were drawn into.
+fn collect_fragments<'a>(element: &'a Element, page: &'a Page, uses: &mut Vec<(&'a Fragment, &'a Page, usize)>) {
+    if !element.visible() {
+        return;
+    }
+    let children = match element {
+        Element::Group(group) => &group.elements,
+        Element::Fragment(placement) => {
+            let fragment = &*placement.fragment;
+            let key = fragment_key(fragment, page.height);
+            if let Some(seen) = uses.iter_mut().find(|(f, p, _)| fragment_key(f, p.height) == key) {
+                seen.2 += 1;
+                return;
+            }
+            uses.push((fragment, page, 1));
+            &fragment.elements
+        }
+        _ => return,
+    };
+    for child in children {
+        collect_fragments(child, page, uses);
+    }
+}
+
+/// A barcode, QR code or Data Matrix element moved to the origin, with
+/// what only identifies or places it reset: everything left decides how
+/// the symbol is drawn. `None` for other elements.
+fn symbol_at_origin(element: &Element) -> Option<Element> {
+    let mut symbol = element.clone();
+    let (x, y, id, tagging, z) = match &mut symbol {
+        Element::Barcode(b) => {
+            // Turning is placement too, so a turned copy shares the form
+            b.rotation = 0.0;
+            (&mut b.x, &mut b.y, &mut b.id, &mut b.tagging, &mut b.z)
+        }
+        Element::QRCode(qr) => (&mut qr.x, &mut qr.y, &mut qr.id, &mut qr.tagging, &mut qr.z),
+        Element::DataMatrix(dm) => (&mut dm.x, &mut dm.y, &mut dm.id, &mut dm.tagging, &mut dm.z),
+        _ => return None,
+    };
+    (*x, *y, *id, *tagging, *z) = (0.0, 0.0, None, Tagging::default(), 0);
+    Some(symbol)
+}
+
+/// Identify a symbol by its drawing parameters, so identical barcodes
+/// anywhere in the document share one form.
+fn symbol_key(symbol: &Element) -> String {
+    format!("{:?}", symbol)
+}
+
+/// Symbols seen in the first pass, in order: the symbol at the origin,
+/// where it is first drawn (for errors) and its placement count
+#[derive(Default)]
+struct SymbolUses {
+    symbols: Vec<(Element, String, usize)>,
+    index: HashMap<String, usize>,
+}
+
+impl SymbolUses {
+    /// Count the symbols under `element`, drawn at `location`
+    fn collect(&mut self, element: &Element, location: &dyn Fn() -> String) {
+        if !element.visible() {
+            return;
+        }
+        match element {
+            Element::Group(g) => {
+                for child in &g.elements {
+                    self.collect(child, location);
+                }
+            }
+            Element::Fragment(f) => {
+                for child in &f.fragment.elements {
+                    self.collect(child, location);
+                }
+            }
+            _ => {
+                let Some(symbol) = symbol_at_origin(element) else { return };
+                let key = symbol_key(&symbol);
+                match self.index.get(&key) {
+                    Some(&i) => self.symbols[i].2 += 1,
+                    None => {
+                        self.index.insert(key, self.symbols.len());
+                        self.symbols.push((symbol, location(), 1));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Register the hatch fills under `element`.
+fn collect_patterns(element: &Element, patterns: &mut HatchPatterns, refs: &mut RefRegistry) {
+    if !element.visible() {
+        return;
+    }
+    match element {
+        Element::Rect(r) => {
+            if let Some(pattern) = &r.fill_pattern {
+                patterns.register(pattern, refs);
+            }
+        }
+        Element::Group(g) => {
+            for child in &g.elements {
+                collect_patterns(child, patterns, refs);
+            }
+        }
+        Element::Fragment(f) => {
+            for child in &f.fragment.elements {
+                collect_patterns(child, patterns, refs);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Record the notes in `element`, including those inside groups.
+fn collect_notes<'e>(element: &'e Element, notes: &mut Vec<&'e NoteElement>) {
+    if !element.visible() {
+        return;
+    }
+    match element {
+        Element::Note(note) => notes.push(note),
+        Element::Group(group) => {
+            for child in &group.elements {
+                collect_notes(child, notes);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Popup size for notes, in points; viewers let readers resize it
+const POPUP_SIZE: (f32, f32) = (200.0, 120.0);
+
+/// Write a note's Text annotation and the popup that shows its contents.
+fn write_note(pdf: &mut Pdf, note: &NoteElement, (note_ref, popup_ref): (Ref, Ref), page_ref: Ref, page: &Page) {
+    let top = page.height - note.y;
+    let size = NoteElement::ICON_SIZE;
+    let mut annot = pdf.indirect(note_ref).start::<Annotation>();
+    annot.subtype(AnnotationType::Text);
+    annot.rect(Rect::new(note.x, top - size, note.x + size, top));
+    annot.contents(TextStr(&note.contents));
+    if let Some(title) = &note.title {
+        annot.author(TextStr(title));
+    }
+    match note.color.model {
+        ColorModel::Cmyk([c, m, y, k]) => annot.color_cmyk(c, m, y, k),
+        _ => {
+            let (r, g, b) = note.color.to_rgb_floats();
+            annot.color_rgb(r, g, b)
+        }
+    };
+    annot.icon(match note.icon {
+        NoteIcon::Note => AnnotationIcon::Note,
+        NoteIcon::Comment => AnnotationIcon::Comment,
+        NoteIcon::Help => AnnotationIcon::Help,
+        NoteIcon::Insert => AnnotationIcon::Insert,
+        NoteIcon::Key => AnnotationIcon::Key,
+        NoteIcon::Paragraph => AnnotationIcon::Paragraph,
+        NoteIcon::NewParagraph => AnnotationIcon::NewParagraph,
+    });
+    // The icon keeps its size and orientation however the page is viewed
+    annot.flags(AnnotationFlags::NO_ZOOM | AnnotationFlags::NO_ROTATE);
+    annot.pair(Name(b"P"), page_ref);
+    annot.pair(Name(b"Open"), note.open);
+    annot.pair(Name(b"Popup"), popup_ref);
+    annot.finish();
+
+    // The popup opens beside the icon, on its left near the right edge
+    let (w, h) = POPUP_SIZE;
+    let left = if note.x + size + w <= page.width { note.x + size } else { (note.x - w).max(0.0) };
+    let mut popup = pdf.indirect(popup_ref).dict();
+    popup.pair(Name(b"Type"), Name(b"Annot"));
+    popup.pair(Name(b"Subtype"), Name(b"Popup"));
+    popup.pair(Name(b"Rect"), Rect::new(left, top - h, left + w, top));
+    popup.pair(Name(b"Parent"), note_ref);
+    popup.pair(Name(b"P"), page_ref);
+    popup.pair(Name(b"Open"), note.open);
+}
+
+/// Fill a resource dictionary with everything content may reference. Pages
+/// and group forms share the same document-wide resources.
+fn write_resources(
+    mut resources: pdf_writer::writers::Resources,
+    font_refs: &HashMap<String, Ref>,
+    xobject_refs: &[(&String, Ref)],
+    shadings: &ShadingRegistry,
+    ext_g_states: &[(String, Ref)],
+    color_spaces: &[(String, Ref)],
+    patterns: &[(String, Ref)],
+) {
+    if !font_refs.is_empty() {
+        let mut fonts = resources.fonts();
+        for (font_name, &font_ref) in sorted(font_refs) {
+            fonts.pair(Name(font_name.as_bytes()), font_ref);
+        }
+        fonts.finish();
+    }
+
+    // Images, color glyphs and group forms
+    if !xobject_refs.is_empty() {
+        let mut xobjects = resources.x_objects();
+        for &(name, xobject_ref) in xobject_refs {
+            xobjects.pair(Name(name.as_bytes()), xobject_ref);
+        }
+        xobjects.finish();
+    }
+
+    if !shadings.is_empty() {
+        let mut sh = resources.shadings();
+        for (name, shading_ref) in shadings.resources() {
+            sh.pair(Name(name.as_bytes()), shading_ref);
+        }
+        sh.finish();
+    }
+
+    // Alpha states and fade masks
+    if !ext_g_states.is_empty() {
+        let mut ext_g = resources.ext_g_states();
+        for (name, state_ref) in ext_g_states {
+            ext_g.pair(Name(name.as_bytes()), *state_ref);
+        }
+        ext_g.finish();
+    }
+
+    // Spot color Separations
+    if !color_spaces.is_empty() {
+        let mut spaces = resources.color_spaces();
+        for (name, space_ref) in color_spaces {
+            spaces.pair(Name(name.as_bytes()), *space_ref);
+        }
+        spaces.finish();
+    }
+
+    // Hatch fills
+    if !patterns.is_empty() {
+        let mut pats = resources.patterns();
+        for (name, pattern_ref) in patterns {
+            pats.pair(Name(name.as_bytes()), *pattern_ref);
+        }
+        pats.finish();
+    }
+}
+
+/// Entries of a map in key order, for output that doesn't depend on hash
+/// order.
+fn sorted<K: Ord, V>(map: impl IntoIterator<Item = (K, V)>) -> Vec<(K, V)> {
+    let mut entries: Vec<(K, V)> = map.into_iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    entries
+}
+
+/// Color space resource name for a spot color. Derived from the colorant
+/// name alone, so every use of one ink shares a single Separation.
+pub(crate) fn spot_key(name: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    name.hash(&mut hasher);
+    format!("Spot{:016x}", Hasher::finish(&hasher))
+}
+
+/// Set the fill color: `rg` for RGB, `k` for CMYK, and `cs`/`scn` in the
+/// ink's Separation color space for spot colors.
+pub(super) fn set_fill(content: &mut Content, color: &Color) {
+    match &color.model {
+        ColorModel::Spot(spot) => {
+            let key = spot_key(&spot.name);
+            content.set_fill_color_space(ColorSpaceOperand::Named(Name(key.as_bytes())));
+            content.set_fill_color([spot.tint]);
+        }
+        &ColorModel::Cmyk([c, m, y, k]) => {
+            content.set_fill_cmyk(c, m, y, k);
+        }
+        ColorModel::Rgb => {
+            let (r, g, b) = color.to_rgb_floats();
+            content.set_fill_rgb(r, g, b);
+        }
+    }
+}
+
+/// Set the stroke color; see `set_fill`.
+pub(super) fn set_stroke(content: &mut Content, color: &Color) {
+    match &color.model {
+        ColorModel::Spot(spot) => {
+            let key = spot_key(&spot.name);
+            content.set_stroke_color_space(ColorSpaceOperand::Named(Name(key.as_bytes())));
+            content.set_stroke_color([spot.tint]);
+        }
+        &ColorModel::Cmyk([c, m, y, k]) => {
+            content.set_stroke_cmyk(c, m, y, k);
+        }
+        ColorModel::Rgb => {
+            let (r, g, b) = color.to_rgb_floats();
+            content.set_stroke_rgb(r, g, b);
+        }
+    }
+}
+
+/// Set the dash pattern, if any; strokes are solid by default.
+fn set_dash(content: &mut Content, dash: Option<&StrokeDash>) {
+    if let Some(dash) = dash {
+        content.set_dash_pattern(dash.array.iter().copied(), dash.phase);
+        if dash.round_caps {
+            content.set_line_cap(LineCapStyle::RoundCap);
+        }
+    }
+}
+
+/// Set the caps, joins and miter limit a stroke asks for, leaving the
+/// current ones where it doesn't. Goes after `set_dash`, so an explicit
+/// cap replaces a dotted dash's round one.
+fn set_line_style(content: &mut Content, cap: Option<LineCap>, join: Option<LineJoin>, miter_limit: Option<f32>) {
+    if let Some(cap) = cap {
+        content.set_line_cap(match cap {
+            LineCap::Butt => LineCapStyle::ButtCap,
+            LineCap::Round => LineCapStyle::RoundCap,
+            LineCap::Square => LineCapStyle::ProjectingSquareCap,
+        });
+    }
+    if let Some(join) = join {
+        content.set_line_join(match join {
+            LineJoin::Miter => LineJoinStyle::MiterJoin,
+            LineJoin::Round => LineJoinStyle::RoundJoin,
+            LineJoin::Bevel => LineJoinStyle::BevelJoin,
+        });
+    }
+    if let Some(limit) = miter_limit {
+        content.set_miter_limit(limit);
+    }
+}
+
+/// Record the spot inks used by `element`, keyed by resource name, with
+/// the first alternate color seen for each.
+fn collect_spots(element: &Element, spots: &mut HashMap<String, (String, Color)>) {
+    if !element.visible() {
+        return;
+    }
+    let mut add = |color: &Color| {
+        if let Some(spot) = color.spot() {
+            spots
+                .entry(spot_key(&spot.name))
+                .or_insert_with(|| (spot.name.clone(), color.clone()));
+        }
+    };
+    match element {
+        Element::Text(t) => {
+            add(&t.color);
+            t.highlight_color.iter().for_each(&mut add);
+            t.styles.iter().filter_map(|s| s.color.as_ref()).for_each(&mut add);
+        }
+        Element::TextBox(tb) => {
+            add(&tb.color);
+            tb.styles.iter().filter_map(|s| s.color.as_ref()).for_each(&mut add);
+        }
+        Element::Rect(r) => {
+            add(&r.stroke_color);
+            r.fill_color.iter().for_each(&mut add);
+            r.fill_pattern.iter().for_each(|p| add(&p.color));
+        }
+        Element::Line(l) => add(&l.color),
+        Element::Polygon(p) => {
+            add(&p.stroke_color);
+            p.fill_color.iter().for_each(&mut add);
+        }
+        Element::Leader(l) => add(&l.color),
+        Element::ProgressRing(r) => {
+            add(&r.color);
+            r.track_color.iter().for_each(&mut add);
+        }
+        Element::QRCode(qr) => {
+            add(&qr.color);
+            add(&qr.background);
+        }
+        Element::DataMatrix(dm) => {
+            add(&dm.color);
+            add(&dm.background);
+        }
+        Element::Table(t) => {
+            add(&t.color);
+            add(&t.border_color);
+            t.header_fill.iter().for_each(&mut add);
+        }
+        Element::Check(c) => {
+            add(&c.stroke_color);
+            add(&c.mark_color);
+            c.fill_color.iter().for_each(&mut add);
+        }
+        Element::Group(g) => {
+            for child in &g.elements {
+                collect_spots(child, spots);
+            }
+        }
+        Element::Fragment(f) => {
+            for child in &f.fragment.elements {
+                collect_spots(child, spots);
+            }
+        }
+        Element::Barcode(b) => add(&b.color),
+        Element::SignatureLine(s) => add(&s.color),
+        Element::ImagePlaceholder(p) => {
+            add(&p.color);
+            add(&p.fill_color);
+        }
+        Element::Grid(g) => {
+            add(&g.color);
+            add(&g.label_color);
+        }
+        Element::Image(img) => add(&img.border_color),
+        // Annotation colors are written inline, without color spaces; raw
+        // content may only use device colors
+        Element::Note(_) | Element::RawContent(_) => {}
     }
 }
 
+/// Write a Separation color space for a spot ink: tint 0 is white and
+/// tint 1 the alternate RGB color.
+fn write_separation(pdf: &mut Pdf, id: Ref, name: &str, alt: &Color) {
+    let (r, g, b) = alt.to_rgb_floats();
+    let mut separation = pdf.color_space(id).separation(Name(name.as_bytes()));
+    separation.alternate_color_space().device_rgb();
+    let mut tint = separation.tint_exponential();
+    tint.domain([0.0, 1.0]);
+    tint.c0([1.0, 1.0, 1.0]);
+    tint.c1([r, g, b]);
+    tint.n(1.0);
+    tint.finish();
+}
+
 /// Main PDF generator
+/// Reported by `PdfGenerator::generate_with` after each page is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageProgress {
+    /// Index into the document's `pages`
+    pub index: usize,
+    /// Page objects written for it (its `repeat`)
+    pub copies: usize,
+    /// Number of entries in `pages`
+    pub total: usize,
+}
+
+/// What `{page}` and `{pages}` stand for in the text of a header or footer
+#[derive(Debug, Clone, Copy)]
+enum PageNumbers {
+    /// In the first pass: every character a number could be written
+    /// with, so any page's number has its glyphs
+    AnyDigits,
+    /// The page's 1-based number and the page count
+    Page(usize, usize),
+}
+
+/// How a `{page…}` placeholder writes its number
+#[derive(Debug, Clone, Copy)]
+enum NumberFormat {
+    Decimal,
+    /// `roman`: i, ii, iii, iv, …
+    Roman,
+    /// `Roman`: I, II, III, IV, …
+    UpperRoman,
+    /// `alpha`: a, b, …, z, aa, ab, …
+    Alpha,
+    /// `Alpha`: A, B, …, Z, AA, AB, …
+    UpperAlpha,
+}
+
+impl NumberFormat {
+    /// Every character a number in this format could use
+    fn alphabet(self) -> &'static str {
+        match self {
+            Self::Decimal => "-0123456789",
+            Self::Roman => "ivxlcdm",
+            Self::UpperRoman => "IVXLCDM",
+            Self::Alpha => "abcdefghijklmnopqrstuvwxyz",
+            Self::UpperAlpha => "ABCDEFGHIJKLMNOPQRSTUVWXYZ",
+        }
+    }
+
+    /// `n` in this format. Roman and letter numbers start at 1, so
+    /// lower ones are written as nothing.
+    fn write(self, n: i64) -> String {
+        match self {
+            Self::Decimal => n.to_string(),
+            _ if n < 1 => String::new(),
+            Self::Roman => roman(n).to_lowercase(),
+            Self::UpperRoman => roman(n),
+            Self::Alpha => letters(n).to_lowercase(),
+            Self::UpperAlpha => letters(n),
+        }
+    }
+}
+
+/// `n` (at least 1) in upper-case roman numerals, with one `M` for
+/// each thousand
+fn roman(mut n: i64) -> String {
+    const NUMERALS: [(i64, &str); 13] = [
+        (1000, "M"), (900, "CM"), (500, "D"), (400, "CD"), (100, "C"), (90, "XC"),
+        (50, "L"), (40, "XL"), (10, "X"), (9, "IX"), (5, "V"), (4, "IV"), (1, "I"),
+    ];
+    let mut out = String::new();
+    for (value, numeral) in NUMERALS {
+        while n >= value {
+            out.push_str(numeral);
+            n -= value;
+        }
+    }
+    out
+}
+
+/// `n` (at least 1) in upper-case letters as spreadsheet columns are
+/// named: A to Z, then AA
+fn letters(mut n: i64) -> String {
+    let mut out = Vec::new();
+    while n > 0 {
+        n -= 1;
+        out.push(b'A' + (n % 26) as u8);
+        n /= 26;
+    }
+    out.reverse();
+    String::from_utf8(out).unwrap_or_default()
+}
+
+/// One placeholder: `{page}` or `{pages}`, with an optional `+N` or `-N`
+/// offset and then an optional `:format`
+#[derive(Debug, Clone, Copy)]
+struct PageToken {
+    /// `{pages}` rather than `{page}`
+    count: bool,
+    offset: i64,
+    format: NumberFormat,
+}
+
+impl PageToken {
+    /// Parse the text between the braces, or `None` if it isn't a
+    /// placeholder this knows
+    fn parse(body: &str) -> Option<Self> {
+        let (head, format) = match body.split_once(':') {
+            Some((head, format)) => (head, Some(format)),
+            None => (body, None),
+        };
+        let format = match format {
+            None => NumberFormat::Decimal,
+            Some("roman") => NumberFormat::Roman,
+            Some("Roman") => NumberFormat::UpperRoman,
+            Some("alpha") => NumberFormat::Alpha,
+            Some("Alpha") => NumberFormat::UpperAlpha,
+            Some(_) => return None,
+        };
+        let (count, offset) = match head.strip_prefix("pages") {
+            Some(offset) => (true, offset),
+            None => (false, head.strip_prefix("page")?),
+        };
+        let offset = match offset.as_bytes().first() {
+            None => 0,
+            Some(b'+' | b'-') if offset[1..].bytes().all(|b| b.is_ascii_digit()) => offset.parse().ok()?,
+            Some(_) => return None,
+        };
+        Some(Self { count, offset, format })
+    }
+}
+
+impl PageNumbers {
+    /// `text` with its placeholders replaced, if it has any
+    fn apply(self, text: &TextElement) -> Result<Option<TextElement>> {
+        if !text.text.contains("{page") {
+            return Ok(None);
+        }
+        let mut out = String::with_capacity(text.text.len());
+        let mut rest = text.text.as_str();
+        while let Some(start) = rest.find("{page") {
+            out.push_str(&rest[..start]);
+            let tail = &rest[start..];
+            let end = tail.find('}');
+            let token = end.map_or(tail, |end| &tail[..=end]);
+            let parsed = end.and_then(|end| PageToken::parse(&tail[1..end]))
+                .ok_or_else(|| RupdfError::InvalidDocument(format!(
+                    "Unknown page number placeholder \"{}\"; use {{page}} or {{pages}}, optionally with +N or -N, \
+                     then :roman, :Roman, :alpha or :Alpha",
+                    token,
+                )))?;
+            match self {
+                Self::AnyDigits => out.push_str(parsed.format.alphabet()),
+                Self::Page(page, pages) => {
+                    let n = if parsed.count { pages } else { page } as i64 + parsed.offset;
+                    out.push_str(&parsed.format.write(n));
+                }
+            }
+            rest = &tail[token.len()..];
+        }
+        out.push_str(rest);
+        let mut text = text.clone();
+        text.text = out;
+        Ok(Some(text))
+    }
+}
+
+/// Objects written ahead of the pages that every page refers to
+struct PageEnv<'e, 'a> {
+    page_tree_ref: Ref,
+    shared_resources_ref: Option<Ref>,
+    font_embedders: &'e HashMap<String, FontEmbedder<'a>>,
+    alias_to_ps: &'e HashMap<String, String>,
+    alpha_states: &'e HashMap<u8, Ref>,
+    forms: &'e Forms,
+    shadings: &'e ShadingRegistry,
+    font_refs: &'e HashMap<String, Ref>,
+    xobject_refs: &'e [(&'e String, Ref)],
+    ext_g_states: &'e [(String, Ref)],
+    color_spaces: &'e [(String, Ref)],
+    patterns: &'e [(String, Ref)],
+}
+
 pub struct PdfGenerator<'a> {
     doc: &'a Document,
     resources: &'a LoadedResources,
     compress: bool,
+    /// Leave every stream unfiltered, font programs, SVG forms and color
+    /// glyph bitmaps included; see `debug_uncompressed`
+    debug_uncompressed: bool,
+    /// Strings already resolved, encoded and measured by this generator
+    text_cache: TextCache,
+    /// What the last generate wrote
+    stats: Mutex<RenderStats>,
+    /// The refs the last generate allocated
+    refs: Mutex<RefRegistry>,
+    /// Set while a header or footer is collected or drawn
+    page_numbers: Mutex<Option<PageNumbers>>,
 }
 
 impl<'a> PdfGenerator<'a> {
@@ -65,18 +870,80 @@ impl<'a> PdfGenerator<'a> {
             doc,
             resources,
             compress,
+            debug_uncompressed: false,
+            text_cache: TextCache::default(),
+            stats: Mutex::default(),
+            refs: Mutex::default(),
+            page_numbers: Mutex::default(),
         }
     }
 
+    /// Write no stream through FlateDecode, not even those `compress`
+    /// leaves deflated when false (font programs, SVG forms, color glyph
+    /// bitmaps), so the file can be diffed as text against a golden copy.
+    /// Output is otherwise the same bytes `compress: false` gives.
+    pub fn debug_uncompressed(mut self, on: bool) -> Self {
+        self.debug_uncompressed = on;
+        self.compress &= !on;
+        self
+    }
+
+    /// Sizes of the fonts, images and content streams the last
+    /// `generate` wrote
+    pub fn stats(&self) -> RenderStats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    /// Every object the last `generate` allocated, one `number name` line
+    /// each, for tracking down a malformed file
+    #[allow(dead_code)]
+    pub fn ref_map(&self) -> String {
+        self.refs.lock().unwrap().dump()
+    }
+
     pub fn generate(&self) -> Result<Vec<u8>> {
+        self.generate_with(|_| Ok(()))
+    }
+
+    /// Lay the document out as `generate` would and hash what its pages,
+    /// groups, fragments and symbols draw, as a 16-digit hex string. Fonts
+    /// and images aren't embedded and the file is thrown away.
+    pub fn layout_fingerprint(&self) -> Result<String> {
+        let mut fingerprint = Fingerprint::default();
+        for page in &self.doc.pages {
+            fingerprint.add(format!("{} {} {}", page.width, page.height, page.repeat).as_bytes());
+        }
+        self.build(Some(&mut fingerprint), |_| Ok(()))?;
+        Ok(fingerprint.hex())
+    }
+
+    /// Generate the PDF, calling `on_page` as each entry of `pages` is
+    /// finished. Fonts, images and other shared objects are written first;
+    /// pages are then rendered and written one at a time. An error from
+    /// `on_page` stops generation and is returned.
+    pub fn generate_with(&self, on_page: impl FnMut(PageProgress) -> Result<()>) -> Result<Vec<u8>> {
+        self.build(None, on_page)
+    }
+
+    /// Generate the PDF as `generate_with` describes. With a `fingerprint`,
+    /// every content stream is hashed into it as drawn, and fonts and
+    /// images aren't embedded.
+    fn build(
+        &self,
+        mut fingerprint: Option<&mut Fingerprint>,
+        mut on_page: impl FnMut(PageProgress) -> Result<()>,
+    ) -> Result<Vec<u8>> {
         let mut pdf = Pdf::new();
-        let mut ref_alloc = Ref::new(1);
+        let mut refs = RefRegistry::default();
 
         // Allocate refs for catalog and page tree
-        let catalog_ref = ref_alloc.bump();
-        let page_tree_ref = ref_alloc.bump();
+        let catalog_ref = refs.alloc("catalog");
+        let page_tree_ref = refs.alloc("pages");
 
-        // First pass: collect all used fonts and images
+        // First pass: collect all used fonts and images. Each memoized
+        // string registers its glyphs once, on its first draw.
+        self.text_cache.reset_registrations();
+        *self.stats.lock().unwrap() = RenderStats::default();
         let mut font_embedders: HashMap<String, FontEmbedder> = HashMap::new();
         // Track each unique (image_ref, size) for per-size 300 DPI embedding
         // Key: "imagename_WxH" where W/H are display points rounded to int
@@ -84,118 +951,214 @@ impl<'a> PdfGenerator<'a> {
         let mut image_usages: HashMap<String, (String, f32, f32)> = HashMap::new();
         // Map user alias -> PostScript name for font references
         let mut alias_to_ps: HashMap<String, String> = HashMap::new();
-
-        for page in &self.doc.pages {
-            for element in &page.elements {
-                match element {
-                    Element::Text(t) => {
-                        let chain = build_chain(self.resources, &t.font, &t.font_fallback)?;
-                        let chain_refs = chain_fonts(&chain);
-                        let chain_names = chain_aliases(&chain);
-                        let resolved = runs::resolve(
-                            &t.text, &chain_refs, &chain_names, t.missing_glyph_policy,
-                        )?;
-                        register_resolved_chars(&resolved, &chain, &mut font_embedders, &mut alias_to_ps);
-                    }
-                    Element::TextBox(tb) => {
-                        let chain = build_chain(self.resources, &tb.font, &tb.font_fallback)?;
-                        let chain_refs = chain_fonts(&chain);
-                        let chain_names = chain_aliases(&chain);
-                        let resolved = runs::resolve(
-                            &tb.text, &chain_refs, &chain_names, tb.missing_glyph_policy,
-                        )?;
-                        register_resolved_chars(&resolved, &chain, &mut font_embedders, &mut alias_to_ps);
-                    }
-                    Element::Barcode(b) if b.human_readable => {
-                        // Barcode HR text uses a single font (no fallback in this
-                        // element type). Resolve through the same machinery with
-                        // a length-1 chain so encoding stays uniform.
-                        let chain = build_chain(self.resources, &b.font, &[])?;
-                        let chain_refs = chain_fonts(&chain);
-                        let chain_names = chain_aliases(&chain);
-                        // For GS1-128, the human-readable text is the parenthesized
-                        // form, which adds '(' and ')' glyphs not present in `value`.
-                        let hr_text = match b.kind {
-                            crate::types::BarcodeKind::Gs1_128 => {
-                                let fields = rubar_core::gs1::parse(&b.value)
-                                    .map_err(|e| RupdfError::InvalidBarcode {
-                                        value: b.value.clone(),
-                                        reason: e.to_string(),
-                                    })?;
-                                rubar_core::gs1::format_human_readable(&fields)
-                            }
-                            crate::types::BarcodeKind::Code128 => b.value.clone(),
-                        };
-                        let resolved = runs::resolve(
-                            &hr_text, &chain_refs, &chain_names, MissingGlyphPolicy::Drop,
-                        )?;
-                        register_resolved_chars(&resolved, &chain, &mut font_embedders, &mut alias_to_ps);
-                    }
-                    Element::Image(img) => {
-                        // Check image type to determine tracking strategy
-                        let loaded = self.resources.get_image(&img.image_ref)?;
-                        let (src_w, src_h) = loaded.dimensions();
-                        // Compute final render dimensions
-                        let (final_w, final_h) = Self::compute_image_dimensions(
-                            src_w, src_h, img.w, img.h
-                        );
-                        let key = match loaded {
-                            LoadedImage::Svg { .. } => {
-                                // SVGs are vector - use original name, size 0x0 as placeholder
-                                img.image_ref.clone()
-                            }
-                            LoadedImage::Raster { .. } => {
-                                // Raster images get per-size entries for 300 DPI
-                                Self::image_size_key(&img.image_ref, final_w, final_h)
-                            }
-                        };
-                        image_usages.entry(key).or_insert_with(|| {
-                            (img.image_ref.clone(), final_w, final_h)
-                        });
+        // Gradient shadings, allocated as they are first seen
+        let mut shadings = ShadingRegistry::default();
+        // Image fade soft masks
+        let mut fades = FadeMasks::default();
+        // Hatch fill tiling patterns
+        let mut hatches = HatchPatterns::default();
+        // Color emoji bitmaps drawn inline with text, by XObject name
+        let mut color_glyphs: HashMap<String, (&LoadedFont, u16)> = HashMap::new();
+        // Transparency groups and the page each is drawn for
+        let mut groups: Vec<(&GroupElement, &Page)> = Vec::new();
+        // Fragments, a page each is drawn for and their placement counts
+        let mut fragments: Vec<(&Fragment, &Page, usize)> = Vec::new();
+        // What each group and fragment form can show, by form key
+        let mut form_boxes: HashMap<(usize, u32), [f32; 4]> = HashMap::new();
+        // Spot inks by color space name: (colorant, first color using it)
+        let mut spots: HashMap<String, (String, Color)> = HashMap::new();
+        // Barcode and matrix symbols and their placement counts
+        let mut symbols = SymbolUses::default();
+
+        for (page_index, page) in self.doc.pages.iter().enumerate() {
+            match &page.background {
+                PageBackground::Color(color) => {
+                    if let Some(spot) = color.spot() {
+                        spots
+                            .entry(spot_key(&spot.name))
+                            .or_insert_with(|| (spot.name.clone(), color.clone()));
                     }
-                    _ => {}
                 }
+                PageBackground::Gradient(gradient) => {
+                    shadings.register(gradient, page.width, page.height, &mut refs);
+                }
+                PageBackground::Image { image_ref, fit } => {
+                    let loaded = self.resources.get_image(image_ref)?;
+                    let (src_w, src_h) = loaded.dimensions();
+                    let (_, _, final_w, final_h) =
+                        Self::fit_image(src_w, src_h, page.width, page.height, *fit);
+                    let key = Self::xobject_key(loaded, image_ref, final_w, final_h);
+                    image_usages
+                        .entry(key)
+                        .or_insert_with(|| (image_ref.clone(), final_w, final_h));
+                }
+            }
+
+            for (i, element) in page.elements.iter().enumerate() {
+                let at = |e: RupdfError| e.in_element(format!("Page {}, element {}", page_index, element.label(i)));
+                self.collect_element(element, &mut font_embedders, &mut alias_to_ps, &mut image_usages, &mut color_glyphs)
+                    .map_err(at)?;
+                self.collect_shadings(element, &mut fades, &mut shadings, &mut refs).map_err(at)?;
+                collect_patterns(element, &mut hatches, &mut refs);
+                collect_groups(element, page, &mut groups);
+                collect_fragments(element, page, &mut fragments);
+                collect_form_boxes(element, page, IDENTITY, &mut form_boxes);
+                collect_spots(element, &mut spots);
+                symbols.collect(element, &|| format!("Page {}, element {}", page_index, element.label(i)));
             }
         }
 
-        // Allocate refs for fonts (5 refs each: Type0, CIDFont, Descriptor, CMap, FontFile)
-        // Use PostScript names as keys for font_refs (for page resources)
+        // Repeating elements are collected once, however many pages they hit
+        for (r, repeating) in self.doc.repeating.iter().enumerate() {
+            if (0..self.doc.pages.len()).any(|i| repeating.pages.includes(i)) {
+                let at = |e: RupdfError| e.in_element(format!("Repeating element {}", repeating.element.label(r)));
+                *self.page_numbers.lock().unwrap() = repeating.page_numbers.then_some(PageNumbers::AnyDigits);
+                let collected =
+                    self.collect_element(&repeating.element, &mut font_embedders, &mut alias_to_ps, &mut image_usages, &mut color_glyphs);
+                *self.page_numbers.lock().unwrap() = None;
+                collected.map_err(at)?;
+                self.collect_shadings(&repeating.element, &mut fades, &mut shadings, &mut refs).map_err(at)?;
+                collect_patterns(&repeating.element, &mut hatches, &mut refs);
+                collect_spots(&repeating.element, &mut spots);
+            }
+            for (i, page) in self.doc.pages.iter().enumerate() {
+                if repeating.pages.includes(i) {
+                    collect_groups(&repeating.element, page, &mut groups);
+                    collect_fragments(&repeating.element, page, &mut fragments);
+                    collect_form_boxes(&repeating.element, page, IDENTITY, &mut form_boxes);
+                    symbols.collect(&repeating.element, &|| format!("Repeating element {}", repeating.element.label(r)));
+                }
+            }
+        }
+
+        // Allocate refs for fonts: Type0, CIDFont, Descriptor, CMap and,
+        // for embedded fonts, FontFile. Use PostScript names as keys for
+        // font_refs (for page resources).
+        // Objects are allocated and written in sorted order throughout, so
+        // the same document always gives the same bytes.
         let mut font_refs: HashMap<String, Ref> = HashMap::new();
-        let mut font_all_refs: HashMap<String, (Ref, Ref, Ref, Ref, Ref)> = HashMap::new();
-        for font_alias in font_embedders.keys() {
+        let mut font_all_refs: HashMap<String, (Ref, Ref, Ref, Ref, Option<Ref>)> = HashMap::new();
+        for (font_alias, embedder) in sorted(&font_embedders) {
             let ps_name = alias_to_ps.get(font_alias)
                 .expect("font alias was inserted in first pass");
-            let type0_ref = ref_alloc.bump();
-            let cid_ref = ref_alloc.bump();
-            let desc_ref = ref_alloc.bump();
-            let cmap_ref = ref_alloc.bump();
-            let file_ref = ref_alloc.bump();
+            let type0_ref = refs.alloc(format!("font[{}]", font_alias));
+            let cid_ref = refs.alloc(format!("font[{}].cid", font_alias));
+            let desc_ref = refs.alloc(format!("font[{}].descriptor", font_alias));
+            let cmap_ref = refs.alloc(format!("font[{}].cmap", font_alias));
+            let file_ref = embedder.embeds_program().then(|| refs.alloc(format!("font[{}].file", font_alias)));
             font_refs.insert(ps_name.clone(), type0_ref);
             font_all_refs.insert(font_alias.clone(), (type0_ref, cid_ref, desc_ref, cmap_ref, file_ref));
         }
 
         // Allocate refs for images (one per unique size)
         let mut image_refs: HashMap<String, Ref> = HashMap::new();
-        for size_key in image_usages.keys() {
-            image_refs.insert(size_key.clone(), ref_alloc.bump());
+        for (size_key, _) in sorted(&image_usages) {
+            image_refs.insert(size_key.clone(), refs.alloc(format!("image[{}]", size_key)));
+        }
+        for (key, _) in sorted(&color_glyphs) {
+            image_refs.insert(key.clone(), refs.alloc(format!("image[{}]", key)));
+        }
+        let mut forms = Forms::default();
+        for (i, (group, page)) in groups.iter().enumerate() {
+            forms.groups.insert(group_key(group, page.height), (format!("G{}", i), refs.alloc(format!("group[G{}]", i))));
+        }
+        // A fragment placed once is drawn inline; only shared ones get a form
+        fragments.retain(|&(_, _, uses)| uses > 1);
+        for (i, (fragment, page, _)) in fragments.iter().enumerate() {
+            forms.groups.insert(fragment_key(fragment, page.height), (format!("F{}", i), refs.alloc(format!("fragment[F{}]", i))));
+        }
+        // Likewise a symbol: one drawn in several places, such as a QR code
+        // on every page, is drawn once and placed by reference
+        let symbols: Vec<(Element, String, usize)> =
+            symbols.symbols.into_iter().filter(|&(_, _, uses)| uses > 1).collect();
+        for (i, (symbol, _, _)) in symbols.iter().enumerate() {
+            forms.symbols.insert(symbol_key(symbol), (format!("S{}", i), refs.alloc(format!("symbol[S{}]", i))));
         }
 
-        // Allocate refs for pages
-        let mut page_refs: Vec<Ref> = Vec::with_capacity(self.doc.pages.len());
-        let mut content_refs: Vec<Ref> = Vec::with_capacity(self.doc.pages.len());
-        for _ in &self.doc.pages {
-            page_refs.push(ref_alloc.bump());
-            content_refs.push(ref_alloc.bump());
+        // Allocate refs for pages: one page object per copy of a repeated
+        // page, all sharing its content stream unless a header or footer
+        // numbers each copy
+        let mut page_refs: Vec<Vec<Ref>> = Vec::with_capacity(self.doc.pages.len());
+        let mut content_refs: Vec<Vec<Ref>> = Vec::with_capacity(self.doc.pages.len());
+        for (i, page) in self.doc.pages.iter().enumerate() {
+            page_refs.push((0..page.repeat).map(|copy| refs.alloc(format!("page[{}].{}", i, copy))).collect());
+            content_refs.push(match self.numbers_pages(i) && page.repeat > 1 {
+                true => (0..page.repeat).map(|copy| refs.alloc(format!("content[{}].{}", i, copy))).collect(),
+                false => vec![refs.alloc(format!("content[{}]", i))],
+            });
         }
+        // Repeated pages point at one shared resource dictionary
+        let shared_resources_ref =
+            self.doc.pages.iter().any(|page| page.repeat > 1).then(|| refs.alloc("resources"));
 
         // Allocate refs for alpha graphics states (we'll create a few common ones)
         let mut alpha_states: HashMap<u8, Ref> = HashMap::new();
         for alpha in [255u8, 191, 127, 63] {
-            alpha_states.insert(alpha, ref_alloc.bump());
+            alpha_states.insert(alpha, refs.alloc(format!("gs[{}]", alpha)));
         }
+        // Group opacity is applied exactly rather than snapped to the nearest
+        for (group, _) in &groups {
+            let alpha = group.alpha();
+            alpha_states.entry(alpha).or_insert_with(|| refs.alloc(format!("gs[{}]", alpha)));
+        }
+
+        // Write the output intent profile, referenced by the catalog and,
+        // with `tag_images`, by raster images
+        let output_profile = self.resources.output_profile.as_ref().map(|profile| {
+            Self::write_icc_profile(&mut pdf, refs.alloc("icc[output]"), &profile.data, profile.components)
+        });
+        let image_profile = match &self.doc.resources.output_intent {
+            Some(intent) if intent.tag_images => output_profile,
+            _ => None,
+        };
+
+        let output_intent = match (&self.doc.resources.output_intent, output_profile) {
+            (Some(intent), Some(profile_ref)) => {
+                let intent_ref = refs.alloc("output_intent");
+                let mut dict = pdf.indirect(intent_ref).start::<pdf_writer::writers::OutputIntent>();
+                dict.subtype(match intent.kind {
+                    OutputIntentKind::PdfA => OutputIntentSubtype::PDFA,
+                    OutputIntentKind::PdfX => OutputIntentSubtype::PDFX,
+                });
+                dict.output_condition_identifier(TextStr(&intent.condition));
+                if let Some(info) = &intent.info {
+                    dict.info(TextStr(info));
+                }
+                dict.dest_output_profile(profile_ref);
+                dict.finish();
+                Some(intent_ref)
+            }
+            _ => None,
+        };
 
         // Write catalog
-        pdf.catalog(catalog_ref).pages(page_tree_ref);
+        let mut catalog = pdf.catalog(catalog_ref);
+        catalog.pages(page_tree_ref);
+        if let Some(intent_ref) = output_intent {
+            catalog.insert(Name(b"OutputIntents")).array().item(intent_ref);
+        }
+        if let Some(action) = &self.doc.open_action {
+            let (page, page_ref) = self.doc.pages.iter()
+                .zip(&page_refs)
+                .flat_map(|(page, refs)| refs.iter().map(move |&r| (page, r)))
+                .nth(action.page)
+                .ok_or_else(|| RupdfError::InvalidDocument(format!(
+                    "open_action page {} is out of range", action.page
+                )))?;
+            let destination = catalog.insert(Name(b"OpenAction")).start::<Destination>().page(page_ref);
+            match action.view {
+                OpenView::Fit => destination.fit(),
+                OpenView::FitWidth => destination.fit_horizontal(page.height),
+                OpenView::Xyz { x, y, zoom } => {
+                    let top = match self.doc.origin {
+                        Origin::TopLeft => page.height - y,
+                        Origin::BottomLeft => y,
+                    };
+                    destination.xyz(x, top, zoom)
+                }
+            }
+        }
+        catalog.finish();
 
         // Write document info
         if self.doc.metadata.title.is_some()
@@ -204,7 +1167,7 @@ impl<'a> PdfGenerator<'a> {
             || self.doc.metadata.creator.is_some()
             || self.doc.metadata.creation_date.is_some()
         {
-            let info_ref = ref_alloc.bump();
+            let info_ref = refs.alloc("info");
             let mut info = pdf.document_info(info_ref);
             if let Some(title) = &self.doc.metadata.title {
                 info.title(TextStr(title));
@@ -228,12 +1191,12 @@ impl<'a> PdfGenerator<'a> {
 
         // Write page tree
         let mut pages = pdf.pages(page_tree_ref);
-        pages.kids(page_refs.iter().copied());
-        pages.count(self.doc.pages.len() as i32);
+        pages.kids(page_refs.iter().flatten().copied());
+        pages.count(page_refs.iter().map(Vec::len).sum::<usize>() as i32);
         pages.finish();
 
         // Write alpha graphics states
-        for (&alpha, &state_ref) in &alpha_states {
+        for (&alpha, &state_ref) in sorted(&alpha_states) {
             let alpha_f = alpha as f32 / 255.0;
             let mut gs = pdf.ext_graphics(state_ref);
             gs.non_stroking_alpha(alpha_f);
@@ -242,145 +1205,819 @@ impl<'a> PdfGenerator<'a> {
         }
 
         // Write fonts
-        for (font_name, embedder) in &font_embedders {
+        let mut font_stats = Vec::with_capacity(font_embedders.len());
+        let embed = fingerprint.is_none();
+        for (font_name, embedder) in sorted(&font_embedders).into_iter().filter(|_| embed) {
             let (type0_ref, cid_ref, desc_ref, cmap_ref, file_ref) = font_all_refs[font_name];
-            embedder.embed(&mut pdf, type0_ref, cid_ref, desc_ref, cmap_ref, file_ref)?;
+            font_stats.push(embedder.embed(
+                &mut pdf, type0_ref, cid_ref, desc_ref, cmap_ref, file_ref, !self.debug_uncompressed,
+            )?);
+        }
+
+        // Write images (each size gets its own XObject at 300 DPI). Images
+        // carrying the same ICC profile share one stream of it.
+        let mut image_stats = Vec::with_capacity(image_refs.len());
+        let mut icc_profiles: HashMap<&[u8], Ref> = HashMap::new();
+        for (size_key, &image_ref) in sorted(&image_refs).into_iter().filter(|_| embed) {
+            if let Some(&(font, glyph_id)) = color_glyphs.get(size_key) {
+                let mask_ref = refs.alloc(format!("image[{}].mask", size_key));
+                let mut stats = self.write_color_glyph(&mut pdf, (image_ref, mask_ref), font, glyph_id)?;
+                stats.name = size_key.clone();
+                image_stats.push(stats);
+                continue;
+            }
+            let (image_name, w, h) = image_usages.get(size_key)
+                .expect("size_key was inserted in first pass");
+            let loaded = self.resources.get_image(image_name)?;
+            let handling = self.doc.resources.images.get(image_name).map_or(ImageProfile::Embed, |r| r.profile);
+            let (profile, convert) = match (loaded, handling) {
+                (LoadedImage::Raster { icc: Some(icc), .. }, ImageProfile::Embed) => {
+                    let profile_ref = *icc_profiles.entry(icc.as_slice())
+                        .or_insert_with(|| Self::write_icc_profile(&mut pdf, refs.alloc(format!("icc[{}]", image_name)), icc, 3));
+                    (Some(profile_ref), None)
+                }
+                (LoadedImage::Raster { icc: Some(icc), .. }, ImageProfile::Srgb) => (None, Some(icc.as_slice())),
+                _ => (image_profile, None),
+            };
+            let mut stats = self.write_image(&mut pdf, image_ref, loaded, image_name, (*w, *h), profile, convert)
+                .map_err(|e| match self.image_user(image_name) {
+                    Some(location) => e.in_element(location),
+                    None => e,
+                })?;
+            if let LoadedImage::Raster { icc: Some(_), .. } = loaded {
+                stats.profile = Some(handling);
+            }
+            image_stats.push(stats);
+        }
+        font_stats.sort_by(|a, b| a.name.cmp(&b.name));
+        image_stats.sort_by(|a, b| a.name.cmp(&b.name).then(a.pixels.cmp(&b.pixels)));
+        {
+            let mut stats = self.stats.lock().unwrap();
+            stats.fonts = font_stats;
+            stats.images = image_stats;
+        }
+
+        // Write gradient shadings
+        shadings.write(&mut pdf, &mut refs);
+
+        // Write image fade masks
+        fades.write(&mut pdf, &shadings);
+
+        let ext_g_states: Vec<(String, Ref)> = sorted(&alpha_states).into_iter()
+            .map(|(&alpha, &state_ref)| (format!("A{}", alpha), state_ref))
+            .chain(fades.ext_g_states())
+            .collect();
+
+        // Write spot color Separations, in a stable order
+        let mut spot_keys: Vec<&String> = spots.keys().collect();
+        spot_keys.sort();
+        let color_spaces: Vec<(String, Ref)> = spot_keys
+            .into_iter()
+            .map(|key| {
+                let (name, alt) = &spots[key];
+                let space_ref = refs.alloc(format!("separation[{}]", key));
+                write_separation(&mut pdf, space_ref, name, alt);
+                (key.clone(), space_ref)
+            })
+            .collect();
+
+        // Write hatch fill patterns
+        hatches.write(&mut pdf, &color_spaces);
+        let patterns: Vec<(String, Ref)> = hatches.patterns().collect();
+
+        let mut xobject_refs: Vec<(&String, Ref)> = image_refs.iter()
+            .map(|(name, &r)| (name, r))
+            .chain(forms.groups.values().chain(forms.symbols.values()).map(|(name, r)| (name, *r)))
+            .collect();
+        xobject_refs.sort();
+
+        // Write transparency groups: each renders its children into an
+        // isolated, non-knockout Form XObject
+        for (group, page) in &groups {
+            let (_, form_ref) = forms.groups[&group_key(group, page.height)];
+            let mut content = Content::new();
+            for (_, child) in Element::paint_order(&group.elements) {
+                self.render_element(&mut content, child, page, &font_embedders, &alias_to_ps, &alpha_states, &shadings, &forms)?;
+            }
+            let data = self.finish_content(content, fingerprint.as_deref_mut());
+            let mut form = pdf.form_xobject(form_ref, &data);
+            if self.compress {
+                form.filter(Filter::FlateDecode);
+            }
+            // Wherever the group is drawn, nothing outside the page shows
+            let [left, bottom, right, top] = form_boxes[&group_key(group, page.height)];
+            form.bbox(Rect::new(left, bottom, right, top));
+            form.group().transparency().isolated(true).knockout(false);
+            write_resources(form.resources(), &font_refs, &xobject_refs, &shadings, &ext_g_states, &color_spaces, &patterns);
+            form.finish();
+        }
+
+        // Write shared fragments: each draws its elements once, with its
+        // origin at the page's top-left, for every placement to reuse
+        for (fragment, page, _) in &fragments {
+            let (_, form_ref) = forms.groups[&fragment_key(fragment, page.height)];
+            let mut content = Content::new();
+            for (i, child) in Element::paint_order(&fragment.elements) {
+                self.render_element(&mut content, child, page, &font_embedders, &alias_to_ps, &alpha_states, &shadings, &forms)
+                    .map_err(|e| e.in_element(format!("Fragment '{}', element {}", fragment.name, child.label(i))))?;
+            }
+            let data = self.finish_content(content, fingerprint.as_deref_mut());
+            let mut form = pdf.form_xobject(form_ref, &data);
+            if self.compress {
+                form.filter(Filter::FlateDecode);
+            }
+            // Covers every placement's view of the page
+            let [left, bottom, right, top] = form_boxes[&fragment_key(fragment, page.height)];
+            form.bbox(Rect::new(left, bottom, right, top));
+            write_resources(form.resources(), &font_refs, &xobject_refs, &shadings, &ext_g_states, &color_spaces, &patterns);
+            form.finish();
+        }
+
+        // Write shared symbols, each drawn with its top-left corner at the
+        // origin for placements to move into place
+        for (symbol, location, _) in &symbols {
+            let (_, form_ref) = forms.symbols[&symbol_key(symbol)];
+            let mut content = Content::new();
+            self.render_symbol(&mut content, symbol, 0.0, &font_embedders, &alias_to_ps, &alpha_states)
+                .map_err(|e| e.in_element(location))?;
+            let data = self.finish_content(content, fingerprint.as_deref_mut());
+            let mut form = pdf.form_xobject(form_ref, &data);
+            if self.compress {
+                form.filter(Filter::FlateDecode);
+            }
+            // Human-readable text may overhang the bars; allow the symbol's
+            // size of slack around it
+            let (w, h) = match symbol {
+                Element::Barcode(b) => (b.w, b.h),
+                Element::QRCode(qr) => (qr.size, qr.size),
+                Element::DataMatrix(dm) => (dm.size, dm.size),
+                _ => unreachable!("only symbols are collected"),
+            };
+            form.bbox(Rect::new(-w, -2.0 * h, 2.0 * w, h));
+            write_resources(form.resources(), &font_refs, &xobject_refs, &shadings, &ext_g_states, &color_spaces, &patterns);
+            form.finish();
         }
 
-        // Write images (each size gets its own XObject at 300 DPI)
-        for (size_key, &image_ref) in &image_refs {
-            let (image_name, w, h) = image_usages.get(size_key)
-                .expect("size_key was inserted in first pass");
-            let loaded = self.resources.get_image(image_name)?;
-            self.write_image(&mut pdf, image_ref, loaded, image_name, (*w, *h))?;
+        if let Some(resources_ref) = shared_resources_ref {
+            let resources = pdf.indirect(resources_ref).start::<pdf_writer::writers::Resources>();
+            write_resources(resources, &font_refs, &xobject_refs, &shadings, &ext_g_states, &color_spaces, &patterns);
         }
 
-        // Write pages and content
+        // Write pages one at a time, each with its content stream
+        let env = PageEnv {
+            page_tree_ref,
+            shared_resources_ref,
+            font_embedders: &font_embedders,
+            alias_to_ps: &alias_to_ps,
+            alpha_states: &alpha_states,
+            forms: &forms,
+            shadings: &shadings,
+            font_refs: &font_refs,
+            xobject_refs: &xobject_refs,
+            ext_g_states: &ext_g_states,
+            color_spaces: &color_spaces,
+            patterns: &patterns,
+        };
+        let page_count = page_refs.iter().map(Vec::len).sum();
+        let mut first_number = 1;
         for (i, page) in self.doc.pages.iter().enumerate() {
-            let page_ref = page_refs[i];
-            let content_ref = content_refs[i];
+            let numbers = (first_number, page_count);
+            self.write_page(
+                &mut pdf, &mut refs, page, i, (&content_refs[i], &page_refs[i]), numbers, &env, fingerprint.as_deref_mut(),
+            )?;
+            first_number += page.repeat;
+            on_page(PageProgress { index: i, copies: page.repeat, total: self.doc.pages.len() })?;
+        }
 
-            // Generate content stream
-            let content_data = self.render_page_content(page, &font_embedders, &alias_to_ps, &image_refs, &alpha_states)?;
+        let pdf = pdf.finish();
+        *self.refs.lock().unwrap() = refs;
+        let mut stats = self.stats.lock().unwrap();
+        stats.pages = page_refs.iter().map(Vec::len).sum();
+        stats.total_bytes = pdf.len();
+        Ok(pdf)
+    }
 
-            // Write content stream
+    /// Render one entry of `pages` and write its content streams, its page
+    /// object for each copy and their annotations. Its copies are numbered
+    /// from `first_number` of `page_count`.
+    #[allow(clippy::too_many_arguments)]
+    fn write_page(
+        &self,
+        pdf: &mut Pdf,
+        refs: &mut RefRegistry,
+        page: &Page,
+        i: usize,
+        (content_refs, page_refs): (&[Ref], &[Ref]),
+        (first_number, page_count): (usize, usize),
+        env: &PageEnv,
+        mut fingerprint: Option<&mut Fingerprint>,
+    ) -> Result<()> {
+        let mut content_offsets = Vec::with_capacity(content_refs.len());
+        for (copy, &content_ref) in content_refs.iter().enumerate() {
+            let content_data = self.render_page_content(
+                page, i, PageNumbers::Page(first_number + copy, page_count),
+                env.font_embedders, env.alias_to_ps, env.shadings, env.alpha_states, env.forms, fingerprint.as_deref_mut(),
+            )?;
+            content_offsets.push(pdf.len());
             let mut stream = pdf.stream(content_ref, &content_data);
             if self.compress {
                 stream.filter(Filter::FlateDecode);
             }
             stream.finish();
+        }
+
+        // Notes become annotations on every copy of the page
+        let mut notes = Vec::new();
+        let repeating = self.doc.repeating.iter().filter(|r| r.pages.includes(i)).map(|r| &r.element);
+        for element in page.elements.iter().chain(repeating) {
+            collect_notes(element, &mut notes);
+        }
+
+        // Write page dictionaries
+        for (copy, &page_ref) in page_refs.iter().enumerate() {
+            let annots: Vec<(Ref, Ref)> = (0..notes.len())
+                .map(|n| {
+                    let note = format!("page[{}].{}.note[{}]", i, copy, n);
+                    (refs.alloc(&note), refs.alloc(format!("{}.popup", note)))
+                })
+                .collect();
+            for (note, &refs) in notes.iter().zip(&annots) {
+                write_note(pdf, note, refs, page_ref, page);
+            }
 
-            // Write page dictionary
             let mut page_dict = pdf.page(page_ref);
-            page_dict.parent(page_tree_ref);
+            page_dict.parent(env.page_tree_ref);
             page_dict.media_box(Rect::new(0.0, 0.0, page.width, page.height));
 
             // Page resources
-            let mut resources = page_dict.resources();
-
-            // Font resources
-            if !font_refs.is_empty() {
-                let mut fonts = resources.fonts();
-                for (font_name, &font_ref) in &font_refs {
-                    fonts.pair(Name(font_name.as_bytes()), font_ref);
+            match env.shared_resources_ref {
+                Some(resources_ref) if page.repeat > 1 => {
+                    page_dict.pair(Name(b"Resources"), resources_ref);
                 }
-                fonts.finish();
+                _ => write_resources(
+                    page_dict.resources(), env.font_refs, env.xobject_refs, env.shadings, env.ext_g_states,
+                    env.color_spaces, env.patterns,
+                ),
+            }
+            let content = copy.min(content_refs.len() - 1);
+            page_dict.contents(content_refs[content]);
+            self.stats.lock().unwrap().page_objects.push(PageObject {
+                page: i,
+                object: page_ref.get(),
+                content_object: content_refs[content].get(),
+                content_offset: content_offsets[content],
+            });
+            if !annots.is_empty() {
+                page_dict.insert(Name(b"Annots")).array().items(annots.iter().flat_map(|&(note, popup)| [note, popup]));
             }
+            page_dict.finish();
+        }
+        Ok(())
+    }
 
-            // Image resources (XObjects)
-            if !image_refs.is_empty() {
-                let mut xobjects = resources.x_objects();
-                for (img_name, &img_ref) in &image_refs {
-                    xobjects.pair(Name(img_name.as_bytes()), img_ref);
+    /// First-pass collection for one element: register the glyphs it uses
+    /// on the font embedders and the image sizes it draws.
+    fn collect_element(
+        &self,
+        element: &'a Element,
+        font_embedders: &mut HashMap<String, FontEmbedder<'a>>,
+        alias_to_ps: &mut HashMap<String, String>,
+        image_usages: &mut HashMap<String, (String, f32, f32)>,
+        color_glyphs: &mut HashMap<String, (&'a LoadedFont, u16)>,
+    ) -> Result<()> {
+        // Hidden elements register no glyphs or images, so placeholder
+        // content never reaches the output
+        if !element.visible() {
+            return Ok(());
+        }
+        match element {
+            Element::Text(t) => {
+                let (chain, style_fonts, emoji) =
+                    text_chain(self.resources, &t.font, &t.font_fallback, &t.emoji, &t.styles)?;
+                let chain_refs = chain_fonts(&chain);
+                let chain_names = chain_aliases(&chain);
+                // Header and footer text registers every character a page
+                // number could need
+                let numbered = self.page_numbers.lock().unwrap().map(|numbers| numbers.apply(t)).transpose()?.flatten();
+                let t = numbered.as_ref().unwrap_or(t);
+                // Trimming or shrinking keeps the fonts, so the chain stays
+                let constrained = overflow::constrain(t, self.resources)?;
+                for (text, _) in &constrained.as_ref().unwrap_or(t).pieces() {
+                    let shaped = self.text_cache.shape(
+                        text, &chain_refs, &chain_names, &style_fonts, t.missing_glyph_policy, &emoji,
+                    )?;
+                    // Outlines need no embedder; color glyphs are still
+                    // images. The memo stays unregistered for shown text.
+                    if t.outline {
+                        for c in shaped.chars.iter().filter(|c| c.image) {
+                            let Some((idx, gid)) = c.glyph else { continue };
+                            let (alias, font) = chain[idx];
+                            color_glyphs.insert(color_glyph_key(alias, gid), (font, gid));
+                        }
+                    } else if shaped.first_registration() {
+                        register_resolved_chars(&shaped.chars, &chain, font_embedders, alias_to_ps, color_glyphs);
+                    }
+                }
+            }
+            Element::TextBox(tb) => {
+                let (chain, style_fonts, emoji) =
+                    text_chain(self.resources, &tb.font, &tb.font_fallback, &tb.emoji, &tb.styles)?;
+                let chain_refs = chain_fonts(&chain);
+                let chain_names = chain_aliases(&chain);
+                // Wrapped as rendered, so inline images are sized and the
+                // lines are memoized for the second pass
+                let sizes = self.inline_image_sizes(tb)?;
+                let widths: Vec<f32> = sizes.iter().map(|&(w, _)| w).collect();
+                let lines = self.text_cache.wrap(
+                    &tb.text,
+                    &widths,
+                    &chain_refs,
+                    &chain_names,
+                    &style_fonts,
+                    tb.size,
+                    tb.column_width(),
+                    tb.missing_glyph_policy,
+                    &emoji,
+                )?;
+                for line in lines.iter().filter(|line| line.first_registration()) {
+                    register_resolved_chars(&line.chars, &chain, font_embedders, alias_to_ps, color_glyphs);
+                }
+                for (image, &(w, h)) in tb.inline_images.iter().zip(&sizes) {
+                    let loaded = self.resources.get_image(&image.image_ref)?;
+                    let key = Self::xobject_key(loaded, &image.image_ref, w, h);
+                    image_usages.entry(key).or_insert_with(|| (image.image_ref.clone(), w, h));
+                }
+            }
+            Element::Barcode(b) if b.human_readable => {
+                // Barcode HR text uses a single font (no fallback in this
+                // element type). Resolve through the same machinery with
+                // a length-1 chain so encoding stays uniform.
+                let chain = build_chain(self.resources, &b.font, &[])?;
+                let chain_refs = chain_fonts(&chain);
+                let chain_names = chain_aliases(&chain);
+                // For GS1-128, the human-readable text is the parenthesized
+                // form, which adds '(' and ')' glyphs not present in `value`.
+                let hr_text = barcode::encode(b.kind, &b.value)?.human_readable;
+                let resolved = runs::resolve(
+                    &hr_text, &chain_refs, &chain_names, MissingGlyphPolicy::Drop, &EmojiFallback::default(),
+                )?;
+                register_resolved_chars(&resolved, &chain, font_embedders, alias_to_ps, color_glyphs);
+            }
+            Element::Group(g) => {
+                for child in &g.elements {
+                    self.collect_element(child, font_embedders, alias_to_ps, image_usages, color_glyphs)?;
+                }
+            }
+            Element::Fragment(f) => {
+                for child in &f.fragment.elements {
+                    self.collect_element(child, font_embedders, alias_to_ps, image_usages, color_glyphs)?;
+                }
+            }
+            Element::SignatureLine(_) | Element::ImagePlaceholder(_) => {
+                // The parts are built on each call, so resolve their text
+                // against the element's own font rather than recursing
+                let (font, parts) = match element {
+                    Element::SignatureLine(s) => (&s.font, s.parts()),
+                    Element::ImagePlaceholder(p) => (&p.font, p.parts()),
+                    _ => unreachable!(),
+                };
+                let chain = build_chain(self.resources, font, &[])?;
+                let chain_refs = chain_fonts(&chain);
+                let chain_names = chain_aliases(&chain);
+                for part in parts {
+                    if let Element::Text(t) = part {
+                        let shaped = self.text_cache.shape(
+                            &t.text, &chain_refs, &chain_names, &[], t.missing_glyph_policy, &EmojiFallback::default(),
+                        )?;
+                        if shaped.first_registration() {
+                            register_resolved_chars(&shaped.chars, &chain, font_embedders, alias_to_ps, color_glyphs);
+                        }
+                    }
+                }
+            }
+            // Labels depend on the page size, but are only ever digits and
+            // a decimal point
+            Element::Grid(g) => {
+                if let Some(font) = &g.font {
+                    let chain = build_chain(self.resources, font, &[])?;
+                    let shaped = self.text_cache.shape(
+                        "0123456789.", &chain_fonts(&chain), &chain_aliases(&chain), &[],
+                        MissingGlyphPolicy::Drop, &EmojiFallback::default(),
+                    )?;
+                    if shaped.first_registration() {
+                        register_resolved_chars(&shaped.chars, &chain, font_embedders, alias_to_ps, color_glyphs);
+                    }
+                }
+            }
+            Element::Table(t) => {
+                for r in 0..t.rows.len() {
+                    let chain = build_chain(self.resources, t.row_font(r), &t.font_fallback)?;
+                    let chain_refs = chain_fonts(&chain);
+                    let chain_names = chain_aliases(&chain);
+                    for cell in &t.rows[r] {
+                        let resolved = runs::resolve(
+                            cell, &chain_refs, &chain_names, t.missing_glyph_policy, &EmojiFallback::default(),
+                        )?;
+                        register_resolved_chars(&resolved, &chain, font_embedders, alias_to_ps, color_glyphs);
+                    }
+                }
+                if let Some(caption) = &t.continued_caption {
+                    let chain = build_chain(self.resources, &t.font, &t.font_fallback)?;
+                    let resolved = runs::resolve(
+                        caption, &chain_fonts(&chain), &chain_aliases(&chain), t.missing_glyph_policy, &EmojiFallback::default(),
+                    )?;
+                    register_resolved_chars(&resolved, &chain, font_embedders, alias_to_ps, color_glyphs);
+                }
+            }
+            Element::Image(img) => {
+                // Check image type to determine tracking strategy
+                let loaded = self.resources.get_image(&img.image_ref)?;
+                let (src_w, src_h) = loaded.dimensions();
+                // Compute final render dimensions
+                let (final_w, final_h) = Self::compute_image_dimensions(
+                    src_w, src_h, img.w, img.h
+                );
+                let key = Self::xobject_key(loaded, &img.image_ref, final_w, final_h);
+                image_usages.entry(key).or_insert_with(|| {
+                    (img.image_ref.clone(), final_w, final_h)
+                });
+            }
+            Element::RawContent(raw) => {
+                for (i, (_, resource)) in raw.resources.iter().enumerate() {
+                    if !raw.uses.names.iter().any(|&(_, used)| used == i) {
+                        continue;
+                    }
+                    match resource {
+                        RawResource::Font(alias) => {
+                            let font = self.resources.get_font(alias)?;
+                            alias_to_ps.insert(alias.clone(), font.postscript_name.clone());
+                            let embedder = font_embedders
+                                .entry(alias.clone())
+                                .or_insert_with(|| FontEmbedder::new(font, alias));
+                            for &(_, gid) in raw.uses.glyphs.iter().filter(|&&(f, _)| f == i) {
+                                if gid >= font.num_glyphs {
+                                    return Err(RupdfError::InvalidDocument(format!(
+                                        "raw_content: glyph {} is not in font '{}', which has {}",
+                                        gid, alias, font.num_glyphs
+                                    )));
+                                }
+                                embedder.use_glyph_id(gid);
+                            }
+                        }
+                        // Drawn at its natural size, as a raster's pixels in
+                        // points or an SVG's own size
+                        RawResource::Image(name) => {
+                            let loaded = self.resources.get_image(name)?;
+                            let (w, h) = loaded.dimensions();
+                            let key = Self::xobject_key(loaded, name, w, h);
+                            image_usages.entry(key).or_insert_with(|| (name.clone(), w, h));
+                        }
+                        RawResource::Alpha(_) => {}
+                    }
                 }
-                xobjects.finish();
             }
+            _ => {}
+        }
+        Ok(())
+    }
 
-            // Graphics state resources
-            if !alpha_states.is_empty() {
-                let mut ext_g = resources.ext_g_states();
-                for (&alpha, &state_ref) in &alpha_states {
-                    let name = format!("A{}", alpha);
-                    ext_g.pair(Name(name.as_bytes()), state_ref);
+    /// Register the fade masks of the images under `element` and the
+    /// gradients of its rects.
+    fn collect_shadings(
+        &self,
+        element: &Element,
+        fades: &mut FadeMasks,
+        shadings: &mut ShadingRegistry,
+        refs: &mut RefRegistry,
+    ) -> Result<()> {
+        if !element.visible() {
+            return Ok(());
+        }
+        match element {
+            Element::Image(img) => {
+                if let Some(fade) = &img.fade {
+                    let (src_w, src_h) = self.resources.get_image(&img.image_ref)?.dimensions();
+                    let (w, h) = Self::compute_image_dimensions(src_w, src_h, img.w, img.h);
+                    fades.register(fade, w, h, shadings, refs);
+                }
+            }
+            Element::Rect(r) => {
+                if let Some(gradient) = &r.gradient {
+                    shadings.register(gradient, r.w.abs(), r.h.abs(), refs);
+                }
+            }
+            Element::Group(g) => {
+                for child in &g.elements {
+                    self.collect_shadings(child, fades, shadings, refs)?;
+                }
+            }
+            Element::Fragment(f) => {
+                for child in &f.fragment.elements {
+                    self.collect_shadings(child, fades, shadings, refs)?;
                 }
-                ext_g.finish();
             }
+            _ => {}
+        }
+        Ok(())
+    }
 
-            resources.finish();
-            page_dict.contents(content_ref);
-            page_dict.finish();
+    /// Where the first element drawing `image_ref` sits. Each image is
+    /// written once for all of its users, so errors writing it are located
+    /// at the first.
+    fn image_user(&self, image_ref: &str) -> Option<String> {
+        fn draws(element: &Element, image_ref: &str) -> bool {
+            element.visible() && match element {
+                Element::Image(img) => img.image_ref == image_ref,
+                Element::TextBox(tb) => tb.inline_images.iter().any(|image| image.image_ref == image_ref),
+                Element::Group(g) => g.elements.iter().any(|child| draws(child, image_ref)),
+                Element::Fragment(f) => f.fragment.elements.iter().any(|child| draws(child, image_ref)),
+                _ => false,
+            }
         }
+        for (page_index, page) in self.doc.pages.iter().enumerate() {
+            if let Some((i, element)) = page.elements.iter().enumerate().find(|(_, e)| draws(e, image_ref)) {
+                return Some(format!("Page {}, element {}", page_index, element.label(i)));
+            }
+        }
+        self.doc.repeating.iter().enumerate()
+            .find(|(_, r)| draws(&r.element, image_ref))
+            .map(|(i, r)| format!("Repeating element {}", r.element.label(i)))
+    }
 
-        Ok(pdf.finish())
+    /// Whether page `i` has a header or footer that draws its number
+    fn numbers_pages(&self, i: usize) -> bool {
+        fn draws_numbers(element: &Element) -> bool {
+            match element {
+                Element::Text(t) => t.text.contains("{page"),
+                Element::Group(g) => g.elements.iter().any(draws_numbers),
+                _ => false,
+            }
+        }
+        self.doc.repeating.iter().any(|r| r.page_numbers && r.pages.includes(i) && draws_numbers(&r.element))
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn render_page_content(
         &self,
         page: &Page,
+        page_index: usize,
+        numbers: PageNumbers,
         font_embedders: &HashMap<String, FontEmbedder>,
         alias_to_ps: &HashMap<String, String>,
-        _image_refs: &HashMap<String, Ref>,
+        shadings: &ShadingRegistry,
         alpha_states: &HashMap<u8, Ref>,
+        forms: &Forms,
+        fingerprint: Option<&mut Fingerprint>,
     ) -> Result<Vec<u8>> {
         let mut content = Content::new();
 
-        // Draw background if not white
-        if page.background.r != 255 || page.background.g != 255 || page.background.b != 255 || page.background.a != 255 {
-            content.save_state();
+        self.render_background(&mut content, page, shadings, alpha_states)?;
+
+        // Repeating elements flagged under_content go beneath the page's own.
+        // Each layer paints in `z` order; the sorts are stable, so elements
+        // without `z` keep their list order.
+        let (mut under, mut over): (Vec<_>, Vec<_>) = self.doc.repeating
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| r.pages.includes(page_index))
+            .partition(|(_, r)| r.under_content);
+        under.sort_by_key(|(_, r)| r.element.z());
+        over.sort_by_key(|(_, r)| r.element.z());
+
+        // Render elements, each labeled for errors with its list index
+        let repeating = |&(i, r): &(usize, &'a RepeatingElement)| (&r.element, "repeating element", i, r.page_numbers);
+        let elements = under.iter().map(repeating)
+            .chain(Element::paint_order(&page.elements).into_iter().map(|(i, e)| (e, "element", i, false)))
+            .chain(over.iter().map(repeating));
+        for (element, list, i, numbered) in elements {
+            *self.page_numbers.lock().unwrap() = numbered.then_some(numbers);
+            let rendered =
+                self.render_element(&mut content, element, page, font_embedders, alias_to_ps, alpha_states, shadings, forms);
+            *self.page_numbers.lock().unwrap() = None;
+            rendered.map_err(|e| e.in_element(format!("Page {}, {} {}", page_index, list, element.label(i))))?;
+        }
 
-            let (r, g, b) = page.background.to_rgb_floats();
+        Ok(self.finish_content(content, fingerprint))
+    }
 
-            // Set alpha if needed
-            if page.background.a != 255 {
-                let alpha_name = self.get_alpha_state_name(page.background.a, alpha_states);
-                content.set_parameters(Name(alpha_name.as_bytes()));
-            }
+    /// Serialize a content stream, compressing it if enabled, and hash it
+    /// as drawn into `fingerprint` if given.
+    fn finish_content(&self, content: Content, fingerprint: Option<&mut Fingerprint>) -> Vec<u8> {
+        let data = content.finish();
+        if let Some(fingerprint) = fingerprint {
+            fingerprint.add(&data);
+        }
+        let data = optimize::optimize(&data);
 
-            content.set_fill_rgb(r, g, b);
-            content.rect(0.0, 0.0, page.width, page.height);
-            content.fill_nonzero();
+        let data = if self.compress {
+            // Use zlib format (not raw deflate) - PDF FlateDecode expects zlib header/checksum
+            miniz_oxide::deflate::compress_to_vec_zlib(&data, 6)
+        } else {
+            data
+        };
+        self.stats.lock().unwrap().content_bytes += data.len();
+        data
+    }
 
-            content.restore_state();
-        }
+    fn render_background(
+        &self,
+        content: &mut Content,
+        page: &Page,
+        shadings: &ShadingRegistry,
+        alpha_states: &HashMap<u8, Ref>,
+    ) -> Result<()> {
+        match &page.background {
+            PageBackground::Color(color) => {
+                // Plain white is the paper color; skip drawing it
+                if color.is_paper() {
+                    return Ok(());
+                }
+                content.save_state();
 
-        // Render elements
-        for element in &page.elements {
-            match element {
-                Element::Text(t) => {
-                    self.render_text(&mut content, t, page.height, font_embedders, alias_to_ps, alpha_states)?;
+                // Set alpha if needed
+                if color.a != 255 {
+                    let alpha_name = self.get_alpha_state_name(color.a, alpha_states);
+                    content.set_parameters(Name(alpha_name.as_bytes()));
                 }
-                Element::TextBox(tb) => {
-                    self.render_textbox(&mut content, tb, page.height, font_embedders, alias_to_ps, alpha_states)?;
+
+                set_fill(content, color);
+                content.rect(0.0, 0.0, page.width, page.height);
+                content.fill_nonzero();
+
+                content.restore_state();
+            }
+            PageBackground::Gradient(gradient) => {
+                let name = shadings
+                    .lookup(gradient, page.width, page.height)
+                    .expect("background gradient was registered in first pass");
+                shading::paint(content, &name, 0.0, 0.0, page.width, page.height);
+            }
+            PageBackground::Image { image_ref, fit } => {
+                let loaded = self.resources.get_image(image_ref)?;
+                let (src_w, src_h) = loaded.dimensions();
+                let (x, y, w, h) = Self::fit_image(src_w, src_h, page.width, page.height, *fit);
+
+                // Clip to the page so `cover` overflow never reaches the
+                // page edges of viewers that ignore the MediaBox.
+                content.save_state();
+                content.rect(0.0, 0.0, page.width, page.height);
+                content.clip_nonzero();
+                content.end_path();
+                Self::place_xobject(content, loaded, image_ref, x, page.height - y - h, w, h);
+                content.restore_state();
+            }
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn render_element(
+        &self,
+        content: &mut Content,
+        element: &Element,
+        page: &Page,
+        font_embedders: &HashMap<String, FontEmbedder>,
+        alias_to_ps: &HashMap<String, String>,
+        alpha_states: &HashMap<u8, Ref>,
+        shadings: &ShadingRegistry,
+        forms: &Forms,
+    ) -> Result<()> {
+        if !element.visible() {
+            return Ok(());
+        }
+        // Decorative content is marked so assistive technology skips it
+        let artifact = element.tagging().artifact;
+        if artifact {
+            content.begin_marked_content(Name(b"Artifact"));
+        }
+        match element {
+            Element::Text(t) => {
+                self.render_text(content, t, page.height, font_embedders, alias_to_ps, alpha_states)?;
+            }
+            Element::TextBox(tb) => {
+                self.render_textbox(content, tb, page.height, font_embedders, alias_to_ps, alpha_states)?;
+            }
+            Element::Rect(r) => {
+                self.render_rect(content, r, page.height, alpha_states, shadings);
+            }
+            Element::Line(l) => {
+                self.render_line(content, l, page.height, alpha_states);
+            }
+            Element::Polygon(p) => {
+                self.render_polygon(content, p, page.height, alpha_states);
+            }
+            Element::Leader(l) => {
+                self.render_leader(content, l, page.height, alpha_states)?;
+            }
+            Element::ProgressRing(r) => {
+                self.render_progress_ring(content, r, page.height, alpha_states);
+            }
+            Element::Image(img) => {
+                self.render_image(content, img, page.height, alpha_states)?;
+            }
+            Element::Barcode(_) | Element::QRCode(_) | Element::DataMatrix(_) => {
+                let form = symbol_at_origin(element).and_then(|symbol| forms.symbols.get(&symbol_key(&symbol)));
+                match form {
+                    Some((name, _)) => Self::place_symbol(content, element, name, page.height),
+                    None => self.render_symbol(content, element, page.height, font_embedders, alias_to_ps, alpha_states)?,
                 }
-                Element::Rect(r) => {
-                    self.render_rect(&mut content, r, page.height, alpha_states);
+            }
+            Element::Check(c) => {
+                self.render_check(content, c, page.height, alpha_states, shadings);
+            }
+            // Notes are annotations, written with the page dictionary
+            Element::Note(_) => {}
+            Element::SignatureLine(s) => {
+                for part in &s.parts() {
+                    self.render_element(content, part, page, font_embedders, alias_to_ps, alpha_states, shadings, forms)?;
                 }
-                Element::Line(l) => {
-                    self.render_line(&mut content, l, page.height, alpha_states);
+            }
+            Element::ImagePlaceholder(p) => {
+                for part in &p.parts() {
+                    self.render_element(content, part, page, font_embedders, alias_to_ps, alpha_states, shadings, forms)?;
                 }
-                Element::Image(img) => {
-                    self.render_image(&mut content, img, page.height)?;
+            }
+            Element::Grid(g) => {
+                for part in &g.parts(page.width, page.height) {
+                    self.render_element(content, part, page, font_embedders, alias_to_ps, alpha_states, shadings, forms)?;
                 }
-                Element::Barcode(b) => {
-                    self.render_barcode(&mut content, b, page.height, font_embedders, alias_to_ps, alpha_states)?;
+            }
+            Element::Group(g) => {
+                content.save_state();
+                if !g.is_identity() {
+                    content.transform(g.matrix(page.height));
                 }
-                Element::QRCode(qr) => {
-                    self.render_qrcode(&mut content, qr, page.height, alpha_states)?;
+                if g.transparency_group {
+                    let (name, _) = &forms.groups[&group_key(g, page.height)];
+                    content.set_parameters(Name(format!("A{}", g.alpha()).as_bytes()));
+                    content.x_object(Name(name.as_bytes()));
+                } else {
+                    for (_, child) in Element::paint_order(&g.elements) {
+                        self.render_element(content, child, page, font_embedders, alias_to_ps, alpha_states, shadings, forms)?;
+                    }
                 }
-                Element::DataMatrix(dm) => {
-                    self.render_datamatrix(&mut content, dm, page.height, alpha_states)?;
+                content.restore_state();
+            }
+            Element::Fragment(f) => {
+                // The elements are drawn as if the fragment's origin were the
+                // page's top-left corner, then moved and scaled into place
+                let h = page.height;
+                content.save_state();
+                content.transform(f.matrix(h));
+                match forms.groups.get(&fragment_key(&f.fragment, h)) {
+                    Some((name, _)) => {
+                        content.x_object(Name(name.as_bytes()));
+                    }
+                    None => {
+                        for (_, child) in Element::paint_order(&f.fragment.elements) {
+                            self.render_element(content, child, page, font_embedders, alias_to_ps, alpha_states, shadings, forms)?;
+                        }
+                    }
                 }
+                content.restore_state();
+            }
+            Element::Table(t) => {
+                self.render_table(content, t, page.height, alias_to_ps, alpha_states)?;
+            }
+            Element::RawContent(r) => {
+                self.render_raw(content, r, alias_to_ps, alpha_states)?;
             }
         }
+        if artifact {
+            content.end_marked_content();
+        }
+        Ok(())
+    }
 
-        let data = content.finish();
-
-        if self.compress {
-            // Use zlib format (not raw deflate) - PDF FlateDecode expects zlib header/checksum
-            Ok(miniz_oxide::deflate::compress_to_vec_zlib(&data, 6))
-        } else {
-            Ok(data)
+    /// Write raw content's operators as given, with its declared names
+    /// swapped for those of the resources they refer to.
+    fn render_raw(
+        &self,
+        content: &mut Content,
+        raw: &RawContentElement,
+        alias_to_ps: &HashMap<String, String>,
+        alpha_states: &HashMap<u8, Ref>,
+    ) -> Result<()> {
+        let mut names = vec![String::new(); raw.resources.len()];
+        for &(_, i) in &raw.uses.names {
+            names[i] = match &raw.resources[i].1 {
+                RawResource::Font(alias) => alias_to_ps
+                    .get(alias)
+                    .expect("raw content font alias was collected in first pass")
+                    .clone(),
+                RawResource::Image(name) => {
+                    let loaded = self.resources.get_image(name)?;
+                    let (w, h) = loaded.dimensions();
+                    Self::xobject_key(loaded, name, w, h)
+                }
+                RawResource::Alpha(alpha) => self.get_alpha_state_name(*alpha, alpha_states),
+            };
         }
+        let operators = raw::resolve_names(&raw.operators, &raw.uses, |i| names[i].clone());
+        content.save_state();
+        content.op(&operators);
+        content.restore_state();
+        Ok(())
     }
 
     fn get_alpha_state_name(&self, alpha: u8, alpha_states: &HashMap<u8, Ref>) -> String {
@@ -401,7 +2038,12 @@ impl<'a> PdfGenerator<'a> {
         alias_to_ps: &HashMap<String, String>,
         alpha_states: &HashMap<u8, Ref>,
     ) -> Result<()> {
-        let chain = build_chain(self.resources, &text.font, &text.font_fallback)?;
+        let numbered = self.page_numbers.lock().unwrap().map(|numbers| numbers.apply(text)).transpose()?.flatten();
+        let text = numbered.as_ref().unwrap_or(text);
+        let constrained = overflow::constrain(text, self.resources)?;
+        let text = constrained.as_ref().unwrap_or(text);
+        let (chain, style_fonts, emoji) =
+            text_chain(self.resources, &text.font, &text.font_fallback, &text.emoji, &text.styles)?;
         let chain_refs = chain_fonts(&chain);
         let chain_names = chain_aliases(&chain);
 
@@ -415,21 +2057,82 @@ impl<'a> PdfGenerator<'a> {
             VerticalAnchor::Center => page_height - text.y - cap_height_pts / 2.0,
         };
 
-        let resolved = runs::resolve(
-            &text.text, &chain_refs, &chain_names, text.missing_glyph_policy,
-        )?;
-        let total_width = runs::measure(&resolved, &chain_refs, text.size);
+        // Small caps switch size between pieces; otherwise there is one
+        let shaped = text
+            .pieces()
+            .iter()
+            .map(|(piece, size)| {
+                let shaped = self.text_cache.shape(
+                    piece, &chain_refs, &chain_names, &style_fonts, text.missing_glyph_policy, &emoji,
+                )?;
+                Ok((shaped, *size))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let natural: f32 = shaped.iter().map(|(s, size)| s.width(&chain_refs, *size)).sum();
+        let glyphs = fit::glyph_count(shaped.iter().map(|(s, _)| s.chars.as_slice()));
+        let fitted = text.fit_width.as_ref().map(|target| fit::fit(target, natural, glyphs));
+        let pieces: Vec<_> = shaped
+            .iter()
+            .map(|(s, size)| {
+                let mut segments = runs::segments(&s.chars, &chain_refs, &chain_names, *size);
+                let mut width = s.width(&chain_refs, *size);
+                if let Some(fitted) = &fitted {
+                    width = fitted.place(&mut segments, width);
+                }
+                (segments, *size, width, s.encoded.as_slice())
+            })
+            .collect();
+        let total_width = fitted.map_or(natural, |f| f.width);
         let x = match text.align {
             TextAlign::Left => text.x,
             TextAlign::Center => text.x - total_width / 2.0,
             TextAlign::Right => text.x - total_width,
         };
 
-        let render_runs = runs::group_runs(&resolved, &chain_names);
-        if render_runs.is_empty() {
+        if pieces.iter().all(|(segments, _, _, _)| segments.is_empty()) {
             return Ok(());
         }
 
+        // Turned outermost, about the anchor, so the width clip and the
+        // highlight turn with the glyphs and alignment holds at any angle
+        let rotated = text.rotation != 0.0;
+        if rotated {
+            content.save_state();
+            content.transform(rotation_matrix(-text.rotation, text.x, page_height - text.y));
+        }
+
+        // Clipped in page space, so skewed glyphs stay inside the width too
+        let clip = overflow::clip(text, total_width);
+        if let Some((left, right)) = clip {
+            content.save_state();
+            content.rect(left, 0.0, right - left, page_height);
+            content.clip_nonzero();
+            content.end_path();
+        }
+
+        // The highlight shears with the glyphs, about the anchor point on
+        // the baseline; widths and alignment stay unskewed
+        let skewed = !text.skew.is_identity();
+        if skewed {
+            content.save_state();
+            content.transform(text.skew.matrix(text.x, baseline_y));
+        }
+
+        if let Some(highlight) = &text.highlight_color {
+            let pad = text.highlight_padding;
+            let descender = primary.descender_pts(text.size);
+            let height = primary.ascender_pts(text.size) - descender;
+            content.save_state();
+            if highlight.a != 255 {
+                let alpha_name = self.get_alpha_state_name(highlight.a, alpha_states);
+                content.set_parameters(Name(alpha_name.as_bytes()));
+            }
+            set_fill(content, highlight);
+            content.rect(x - pad, baseline_y + descender - pad, total_width + 2.0 * pad, height + 2.0 * pad);
+            content.fill_nonzero();
+            content.restore_state();
+        }
+
         content.save_state();
 
         if text.color.a != 255 {
@@ -437,22 +2140,59 @@ impl<'a> PdfGenerator<'a> {
             content.set_parameters(Name(alpha_name.as_bytes()));
         }
 
-        let (r, g, b) = text.color.to_rgb_floats();
-        content.set_fill_rgb(r, g, b);
-
-        content.begin_text();
-        content.next_line(x, baseline_y);
-        for run in &render_runs {
-            let ps_name = alias_to_ps
-                .get(run.font_alias)
-                .expect("font alias was collected in first pass");
-            content.set_font(Name(ps_name.as_bytes()), text.size);
-            let bytes = encode_glyphs(&run.glyphs);
-            content.show(Str(&bytes));
+        set_fill(content, &text.color);
+        let fills = style_fills(&text.color, &text.styles);
+
+        if text.outline {
+            let mut offset = 0.0;
+            for (segments, size, width, _) in &pieces {
+                let origin = (x + offset, baseline_y);
+                let glyphs =
+                    outline::fill_segments(content, segments, &chain_refs, *size, fitted.as_ref(), origin, &fills);
+                Self::draw_color_glyphs(content, &chain, &glyphs, x + offset, baseline_y, *size);
+                offset += width;
+            }
+        } else {
+            content.begin_text();
+            if let Some(fitted) = &fitted {
+                if fitted.char_spacing != 0.0 {
+                    content.set_char_spacing(fitted.char_spacing);
+                }
+                if fitted.scale != 1.0 {
+                    content.set_horizontal_scaling(fitted.scale * 100.0);
+                }
+            }
+            content.next_line(x, baseline_y);
+            // `offset` is where the piece starts, `cursor` the line origin the
+            // last Td moved to, both relative to `x`
+            let (mut offset, mut cursor) = (0.0, 0.0);
+            let mut color_glyphs = Vec::new();
+            for (segments, size, width, encoded) in &pieces {
+                if offset != cursor {
+                    content.next_line(offset - cursor, 0.0);
+                    cursor = offset;
+                }
+                let (moved, glyphs) = Self::show_segments(content, segments, Some(encoded), alias_to_ps, *size, &fills);
+                cursor += moved;
+                color_glyphs.push((offset, glyphs, *size));
+                offset += width;
+            }
+            content.end_text();
+            for (offset, glyphs, size) in &color_glyphs {
+                Self::draw_color_glyphs(content, &chain, glyphs, x + offset, baseline_y, *size);
+            }
         }
-        content.end_text();
 
         content.restore_state();
+        if skewed {
+            content.restore_state();
+        }
+        if clip.is_some() {
+            content.restore_state();
+        }
+        if rotated {
+            content.restore_state();
+        }
 
         Ok(())
     }
@@ -466,7 +2206,8 @@ impl<'a> PdfGenerator<'a> {
         alias_to_ps: &HashMap<String, String>,
         alpha_states: &HashMap<u8, Ref>,
     ) -> Result<()> {
-        let chain = build_chain(self.resources, &textbox.font, &textbox.font_fallback)?;
+        let (chain, style_fonts, emoji) =
+            text_chain(self.resources, &textbox.font, &textbox.font_fallback, &textbox.emoji, &textbox.styles)?;
         let chain_refs = chain_fonts(&chain);
         let chain_names = chain_aliases(&chain);
         let primary = chain_refs[0];
@@ -483,24 +2224,33 @@ impl<'a> PdfGenerator<'a> {
             BoxAlignY::Bottom => textbox.y - textbox.h,
         };
 
-        // Step 2: Word wrap text against the font chain
-        let lines = runs::wrap(
+        // Step 2: Word wrap text against the font chain at the column width
+        let column_w = textbox.column_width();
+        let inline_sizes = self.inline_image_sizes(textbox)?;
+        let inline_widths: Vec<f32> = inline_sizes.iter().map(|&(w, _)| w).collect();
+        let lines = self.text_cache.wrap(
             &textbox.text,
+            &inline_widths,
             &chain_refs,
             &chain_names,
+            &style_fonts,
             textbox.size,
-            textbox.w,
+            column_w,
             textbox.missing_glyph_policy,
+            &emoji,
         )?;
-        let num_lines = lines.len();
-        if num_lines == 0 {
+        if lines.is_empty() {
             return Ok(());
         }
 
-        // Step 3: Block metrics from the primary font
+        // Step 3: Block metrics from the primary font. Lines flow down each
+        // column in turn; vertical alignment uses the tallest column.
         let cap_height = primary.cap_height_pts(textbox.size);
         let ascender = primary.ascender_pts(textbox.size);
         let descender = primary.descender_pts(textbox.size).abs();
+        let capacity = ((textbox.h - cap_height) / textbox.line_height).floor().max(0.0) as usize + 1;
+        let columns = runs::column_ranges(lines.len(), textbox.columns as usize, capacity, textbox.balance);
+        let num_lines = columns.iter().map(|c| c.len()).max().unwrap_or(0);
         let text_block_height = if num_lines == 1 {
             cap_height
         } else {
@@ -544,28 +2294,36 @@ impl<'a> PdfGenerator<'a> {
             content.set_parameters(Name(alpha_name.as_bytes()));
         }
 
-        let (r, g, b) = textbox.color.to_rgb_floats();
-        content.set_fill_rgb(r, g, b);
+        set_fill(content, &textbox.color);
+        let fills = style_fills(&textbox.color, &textbox.styles);
 
         content.begin_text();
 
         // Step 6: Render each line. The cursor advances per Td/Tj; font is
-        // switched mid-line via Tf at sub-run boundaries.
+        // switched mid-line via Tf at sub-run boundaries. Color glyphs and
+        // inline images are drawn after the text object closes, inside the
+        // same clip.
         let mut prev_x = 0.0;
         let mut prev_y = 0.0;
-        for (i, line_chars) in lines.iter().enumerate() {
-            if line_chars.is_empty() {
+        let mut line_color_glyphs = Vec::new();
+        let mut line_inline_images = Vec::new();
+        let placed = columns.iter().enumerate().flat_map(|(c, range)| {
+            let column_left = box_left + c as f32 * (column_w + textbox.column_gap);
+            lines[range.clone()].iter().enumerate().map(move |(i, line)| (column_left, i, line))
+        });
+        for (column_left, i, line) in placed {
+            if line.chars.is_empty() {
                 continue;
             }
 
             let line_y = first_baseline_y + i as f32 * textbox.line_height;
             let pdf_y = page_height - line_y;
 
-            let line_width = runs::measure(line_chars, &chain_refs, textbox.size);
+            let line_width = line.width(&chain_refs, textbox.size);
             let line_x = match textbox.text_align_x {
-                TextAlign::Left => box_left,
-                TextAlign::Center => box_left + (textbox.w - line_width) / 2.0,
-                TextAlign::Right => box_left + textbox.w - line_width,
+                TextAlign::Left => column_left,
+                TextAlign::Center => column_left + (column_w - line_width) / 2.0,
+                TextAlign::Right => column_left + column_w - line_width,
             };
 
             let dx = line_x - prev_x;
@@ -574,21 +2332,212 @@ impl<'a> PdfGenerator<'a> {
             prev_x = line_x;
             prev_y = pdf_y;
 
-            let line_runs = runs::group_runs(line_chars, &chain_names);
-            for run in &line_runs {
-                let ps_name = alias_to_ps
-                    .get(run.font_alias)
-                    .expect("font alias was collected in first pass");
-                content.set_font(Name(ps_name.as_bytes()), textbox.size);
-                let bytes = encode_glyphs(&run.glyphs);
-                content.show(Str(&bytes));
+            let segments = runs::segments(&line.chars, &chain_refs, &chain_names, textbox.size);
+            let (moved, color_glyphs) =
+                Self::show_segments(content, &segments, Some(&line.encoded), alias_to_ps, textbox.size, &fills);
+            prev_x += moved;
+            if !color_glyphs.is_empty() {
+                line_color_glyphs.push((line_x, pdf_y, color_glyphs));
+            }
+            for segment in &segments {
+                if let Segment::Inline { x, index } = segment {
+                    line_inline_images.push((line_x + x, pdf_y, *index));
+                }
+            }
+        }
+
+        content.end_text();
+        for (line_x, pdf_y, color_glyphs) in &line_color_glyphs {
+            Self::draw_color_glyphs(content, &chain, color_glyphs, *line_x, *pdf_y, textbox.size);
+        }
+        for &(x, pdf_y, index) in &line_inline_images {
+            let image = &textbox.inline_images[index];
+            let (w, h) = inline_sizes[index];
+            let loaded = self.resources.get_image(&image.image_ref)?;
+            content.save_state();
+            Self::place_xobject(content, loaded, &image.image_ref, x, pdf_y + image.baseline_shift, w, h);
+            content.restore_state();
+        }
+        content.restore_state();
+
+        Ok(())
+    }
+
+    fn render_table(
+        &self,
+        content: &mut Content,
+        table: &TableElement,
+        page_height: f32,
+        alias_to_ps: &HashMap<String, String>,
+        alpha_states: &HashMap<u8, Ref>,
+    ) -> Result<()> {
+        if table.rows.is_empty() {
+            return Ok(());
+        }
+        let rows = table::layout(table, self.resources)?;
+        let mut top = table.y;
+
+        content.save_state();
+        if table.color.a != 255 {
+            let alpha_name = self.get_alpha_state_name(table.color.a, alpha_states);
+            content.set_parameters(Name(alpha_name.as_bytes()));
+        }
+        set_fill(content, &table.color);
+
+        // Continuation caption, on its own line above the header
+        if let (true, Some(caption)) = (table.continuation, &table.continued_caption) {
+            let chain = build_chain(self.resources, &table.font, &table.font_fallback)?;
+            let chain_refs = chain_fonts(&chain);
+            let chain_names = chain_aliases(&chain);
+            let resolved = runs::resolve(
+                caption, &chain_refs, &chain_names, table.missing_glyph_policy, &EmojiFallback::default(),
+            )?;
+            let baseline_y = page_height - top - table::first_baseline(table, chain_refs[0]) + table.padding;
+            let segments = runs::segments(&resolved, &chain_refs, &chain_names, table.size);
+            content.begin_text();
+            content.next_line(table.x, baseline_y);
+            let (_, color_glyphs) = Self::show_segments(content, &segments, None, alias_to_ps, table.size, &[]);
+            content.end_text();
+            Self::draw_color_glyphs(content, &chain, &color_glyphs, table.x, baseline_y, table.size);
+            top += table.caption_height();
+        }
+
+        for (row, row_layout) in rows.iter().enumerate() {
+            let row_h = row_layout.height;
+            let chain = build_chain(self.resources, table.row_font(row), &table.font_fallback)?;
+            let chain_refs = chain_fonts(&chain);
+            let chain_names = chain_aliases(&chain);
+            let first_baseline = table::first_baseline(table, chain_refs[0]);
+            let pdf_bottom = page_height - top - row_h;
+            let mut left = table.x;
+            for (column, &w) in table.column_widths.iter().enumerate() {
+                if let (true, Some(fill)) = (row < table.header_rows, &table.header_fill) {
+                    content.save_state();
+                    if fill.a != 255 {
+                        let alpha_name = self.get_alpha_state_name(fill.a, alpha_states);
+                        content.set_parameters(Name(alpha_name.as_bytes()));
+                    }
+                    set_fill(content, fill);
+                    content.rect(left, pdf_bottom, w, row_h);
+                    content.fill_nonzero();
+                    content.restore_state();
+                }
+
+                for (i, line) in row_layout.cells[column].iter().enumerate() {
+                    if line.is_empty() {
+                        continue;
+                    }
+                    let x = left + table.padding;
+                    let baseline_y = page_height - top - first_baseline - i as f32 * table.line_height;
+                    let segments = runs::segments(line, &chain_refs, &chain_names, table.size);
+                    content.begin_text();
+                    content.next_line(x, baseline_y);
+                    let (_, color_glyphs) = Self::show_segments(content, &segments, None, alias_to_ps, table.size, &[]);
+                    content.end_text();
+                    Self::draw_color_glyphs(content, &chain, &color_glyphs, x, baseline_y, table.size);
+                }
+
+                if table.border > 0.0 {
+                    content.save_state();
+                    if table.border_color.a != 255 {
+                        let alpha_name = self.get_alpha_state_name(table.border_color.a, alpha_states);
+                        content.set_parameters(Name(alpha_name.as_bytes()));
+                    }
+                    set_stroke(content, &table.border_color);
+                    content.set_line_width(table.border);
+                    content.rect(left, pdf_bottom, w, row_h);
+                    content.stroke();
+                    content.restore_state();
+                }
+                left += w;
+            }
+            top += row_h;
+        }
+
+        content.restore_state();
+        Ok(())
+    }
+
+    /// Show a line's text runs. The text line matrix must be at the line
+    /// origin; it is moved with `Td` past any color glyph, which cannot be
+    /// drawn inside a text object. Returns how far the line matrix moved
+    /// and the (x offset, chain index, glyph id) of each color glyph.
+    /// `encoded` holds pre-encoded bytes for each text segment, from the
+    /// text cache; without it runs are encoded here.
+    fn show_segments(
+        content: &mut Content,
+        segments: &[Segment],
+        encoded: Option<&[Vec<u8>]>,
+        alias_to_ps: &HashMap<String, String>,
+        size: f32,
+        fills: &[Color],
+    ) -> (f32, Vec<(f32, usize, u16)>) {
+        let mut moved = 0.0;
+        let mut after_image = false;
+        let mut color_glyphs = Vec::new();
+        let mut shown = 0;
+        // Style whose fill is set; the caller sets the plain one
+        let mut fill = 0;
+        for segment in segments {
+            match segment {
+                Segment::Image { x, chain_index, glyph_id } => {
+                    color_glyphs.push((*x, *chain_index, *glyph_id));
+                    after_image = true;
+                }
+                Segment::Inline { .. } => after_image = true,
+                Segment::Text { x, run } => {
+                    if after_image {
+                        content.next_line(x - moved, 0.0);
+                        moved = *x;
+                        after_image = false;
+                    }
+                    if !fills.is_empty() && fills[run.style] != fills[fill] {
+                        set_fill(content, &fills[run.style]);
+                        fill = run.style;
+                    }
+                    let ps_name = alias_to_ps
+                        .get(run.font_alias)
+                        .expect("font alias was collected in first pass");
+                    content.set_font(Name(ps_name.as_bytes()), size);
+                    match encoded {
+                        Some(encoded) => content.show(Str(&encoded[shown])),
+                        None => content.show(Str(&encode_glyphs(&run.glyphs))),
+                    };
+                    shown += 1;
+                }
             }
         }
+        if fills.get(fill) != fills.first() {
+            set_fill(content, &fills[0]);
+        }
+        (moved, color_glyphs)
+    }
 
-        content.end_text();
-        content.restore_state();
-
-        Ok(())
+    /// Draw color glyph bitmaps for a line whose baseline origin is
+    /// (`x`, `baseline_y`) in PDF coordinates.
+    fn draw_color_glyphs(
+        content: &mut Content,
+        chain: &FontChain,
+        color_glyphs: &[(f32, usize, u16)],
+        x: f32,
+        baseline_y: f32,
+        size: f32,
+    ) {
+        for &(offset, chain_index, glyph_id) in color_glyphs {
+            let (alias, font) = chain[chain_index];
+            let Some(glyph) = font.raster_glyph(glyph_id) else { continue };
+            content.save_state();
+            content.transform([
+                glyph.width * size,
+                0.0,
+                0.0,
+                glyph.height * size,
+                x + offset + glyph.left * size,
+                baseline_y + glyph.bottom * size,
+            ]);
+            content.x_object(Name(color_glyph_key(alias, glyph_id).as_bytes()));
+            content.restore_state();
+        }
     }
 
     fn render_rect(
@@ -597,6 +2546,7 @@ impl<'a> PdfGenerator<'a> {
         rect: &RectElement,
         page_height: f32,
         alpha_states: &HashMap<u8, Ref>,
+        shadings: &ShadingRegistry,
     ) {
         // Save state to isolate graphics state changes
         content.save_state();
@@ -604,96 +2554,257 @@ impl<'a> PdfGenerator<'a> {
         // Convert to PDF coordinates (bottom-left origin)
         let pdf_y = page_height - rect.y - rect.h;
 
+        // Turning first makes it the outer transform, so the skewed shape
+        // is what turns
+        if rect.rotation != 0.0 {
+            let (ox, oy) = rect.rotation_pivot();
+            content.transform(rotation_matrix(rect.rotation, ox, page_height - oy));
+        }
+        if !rect.skew.is_identity() {
+            content.transform(rect.skew.matrix(rect.x + rect.w / 2.0, pdf_y + rect.h / 2.0));
+        }
+
         // Fill if fill_color is specified
         if let Some(fill) = &rect.fill_color {
             if fill.a != 255 {
                 let alpha_name = self.get_alpha_state_name(fill.a, alpha_states);
                 content.set_parameters(Name(alpha_name.as_bytes()));
             }
-            let (r, g, b) = fill.to_rgb_floats();
-            content.set_fill_rgb(r, g, b);
-            Self::draw_rect_path(content, rect.x, pdf_y, rect.w, rect.h, rect.corner_radius);
+            set_fill(content, fill);
+            Self::draw_rect_path(content, rect.x, pdf_y, rect.w, rect.h, rect.corner_radius, rect.corner_style);
+            content.fill_nonzero();
+        }
+
+        // Gradient over the fill, clipped to the rect's outline. Shadings
+        // are opaque, so the fill's alpha is reset.
+        if let Some(gradient) = &rect.gradient {
+            let (w, h) = (rect.w.abs(), rect.h.abs());
+            let name = shadings
+                .lookup(gradient, w, h)
+                .expect("rect gradient was registered in first pass");
+            content.save_state();
+            if rect.fill_color.as_ref().is_some_and(|fill| fill.a != 255) {
+                content.set_parameters(Name(b"A255"));
+            }
+            Self::draw_rect_path(content, rect.x, pdf_y, rect.w, rect.h, rect.corner_radius, rect.corner_style);
+            content.clip_nonzero();
+            content.end_path();
+            shading::paint(content, &name, rect.x.min(rect.x + rect.w), pdf_y.min(pdf_y + rect.h), w, h);
+            content.restore_state();
+        }
+
+        // Hatching over the fill, painted with its own opaque color
+        if let Some(pattern) = &rect.fill_pattern {
+            content.save_state();
+            if rect.fill_color.as_ref().is_some_and(|fill| fill.a != 255) {
+                content.set_parameters(Name(b"A255"));
+            }
+            content.set_fill_color_space(ColorSpaceOperand::Pattern);
+            content.set_fill_pattern(None, Name(HatchPatterns::name(pattern).as_bytes()));
+            Self::draw_rect_path(content, rect.x, pdf_y, rect.w, rect.h, rect.corner_radius, rect.corner_style);
             content.fill_nonzero();
+            content.restore_state();
         }
 
-        // Stroke
-        if rect.stroke > 0.0 {
+        // Stroke; a hairline is width 0, which PDF draws as thin as the
+        // device can
+        if rect.stroke > 0.0 || rect.hairline {
             if rect.stroke_color.a != 255 {
                 let alpha_name = self.get_alpha_state_name(rect.stroke_color.a, alpha_states);
                 content.set_parameters(Name(alpha_name.as_bytes()));
             }
-            let (r, g, b) = rect.stroke_color.to_rgb_floats();
-            content.set_stroke_rgb(r, g, b);
+            set_stroke(content, &rect.stroke_color);
             content.set_line_width(rect.stroke);
-            Self::draw_rect_path(content, rect.x, pdf_y, rect.w, rect.h, rect.corner_radius);
+            set_dash(content, rect.dash.as_ref());
+            set_line_style(content, rect.cap, rect.join, rect.miter_limit);
+            let (x, y, w, h, radii) = rect.stroke_box();
+            let pdf_y = page_height - y - h;
+            match rect.sides {
+                None => Self::draw_rect_path(content, x, pdf_y, w, h, radii, rect.corner_style),
+                Some(sides) => {
+                    // Projecting caps square off the corners where two
+                    // stroked edges meet, as the full outline would; dots
+                    // and explicit caps keep theirs
+                    if rect.cap.is_none() && !rect.dash.as_ref().is_some_and(|dash| dash.round_caps) {
+                        content.set_line_cap(LineCapStyle::ProjectingSquareCap);
+                    }
+                    let (left, right, bottom, top) = (x, x + w, pdf_y, pdf_y + h);
+                    let edges = [
+                        (sides.top, (left, top), (right, top)),
+                        (sides.right, (right, top), (right, bottom)),
+                        (sides.bottom, (right, bottom), (left, bottom)),
+                        (sides.left, (left, bottom), (left, top)),
+                    ];
+                    for (_, (x1, y1), (x2, y2)) in edges.into_iter().filter(|edge| edge.0) {
+                        content.move_to(x1, y1);
+                        content.line_to(x2, y2);
+                    }
+                }
+            }
             content.stroke();
         }
 
         content.restore_state();
     }
 
-    /// Draw a rectangle path, optionally with rounded corners
-    fn draw_rect_path(content: &mut Content, x: f32, y: f32, w: f32, h: f32, radius: f32) {
-        if radius <= 0.0 {
+    fn render_check(
+        &self,
+        content: &mut Content,
+        check: &CheckElement,
+        page_height: f32,
+        alpha_states: &HashMap<u8, Ref>,
+        shadings: &ShadingRegistry,
+    ) {
+        let square = RectElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: check.x,
+            y: check.y,
+            w: check.size,
+            h: check.size,
+            stroke: check.stroke,
+            stroke_color: check.stroke_color.clone(),
+            fill_color: check.fill_color.clone(),
+            fill_pattern: None,
+            gradient: None,
+            corner_radius: check.corner_radius.into(),
+            corner_style: CornerStyle::Circular,
+            sides: None,
+            dash: None,
+            miter_limit: None,
+            stroke_align: StrokeAlign::Center,
+            skew: Skew::default(),
+            rotation: 0.0,
+            rotation_origin: RotationOrigin::Center,
+            hairline: false,
+            cap: None,
+            join: None,
+        };
+        self.render_rect(content, &square, page_height, alpha_states, shadings);
+
+        // Mark strokes as (x, y) fractions of the box, y down from its top
+        let strokes: &[&[(f32, f32)]] = match check.state {
+            CheckState::Unchecked => return,
+            CheckState::Checked => &[&[(0.22, 0.52), (0.42, 0.72), (0.78, 0.28)]],
+            CheckState::Crossed => &[&[(0.25, 0.25), (0.75, 0.75)], &[(0.75, 0.25), (0.25, 0.75)]],
+        };
+
+        content.save_state();
+        if check.mark_color.a != 255 {
+            let alpha_name = self.get_alpha_state_name(check.mark_color.a, alpha_states);
+            content.set_parameters(Name(alpha_name.as_bytes()));
+        }
+        set_stroke(content, &check.mark_color);
+        content.set_line_width(check.mark_stroke);
+        content.set_line_cap(LineCapStyle::RoundCap);
+        content.set_line_join(LineJoinStyle::RoundJoin);
+        let top = page_height - check.y;
+        for points in strokes {
+            let point = |&(fx, fy): &(f32, f32)| (check.x + fx * check.size, top - fy * check.size);
+            let (x, y) = point(&points[0]);
+            content.move_to(x, y);
+            for p in &points[1..] {
+                let (x, y) = point(p);
+                content.line_to(x, y);
+            }
+        }
+        content.stroke();
+        content.restore_state();
+    }
+
+    /// Draw a rectangle path, each corner rounded by its own radius. Fill
+    /// and stroke both draw through here, so they stay coincident.
+    fn draw_rect_path(content: &mut Content, x: f32, y: f32, w: f32, h: f32, radii: CornerRadii, style: CornerStyle) {
+        if !radii.any() {
             // Simple rectangle
             content.rect(x, y, w, h);
-        } else {
-            // Rounded rectangle using cubic Bézier curves
-            // Clamp radius to half the smallest dimension
-            let r = radius.min(w / 2.0).min(h / 2.0);
-
+            return;
+        }
+        // Corners are named as seen on the page, so a box given with a
+        // negative size is normalized first
+        let (x, w) = if w < 0.0 { (x + w, -w) } else { (x, w) };
+        let (y, h) = if h < 0.0 { (y + h, -h) } else { (y, h) };
+
+        // Rounded rectangle using cubic Bézier curves. Each corner's
+        // extent is how far its curve reaches along the edges; `c` is the
+        // length of its control handles. Fitting the extents so no two on
+        // an edge overlap keeps neighboring corners from crossing.
+        let (extents, handle) = match style {
             // Control point factor for quarter-circle approximation
             // k = 4/3 * (sqrt(2) - 1) ≈ 0.5523
-            let k = 0.5523;
-            let c = r * k;
-
-            // Start at top-left, after the corner curve
-            content.move_to(x + r, y + h);
-
-            // Top edge (left to right)
-            content.line_to(x + w - r, y + h);
-
-            // Top-right corner
-            content.cubic_to(
-                x + w - r + c, y + h,      // control point 1
-                x + w, y + h - r + c,      // control point 2
-                x + w, y + h - r,          // end point
-            );
+            CornerStyle::Circular => (radii, 0.5523),
+            // The curve starts 1.28 radii from the corner with 0.86-radius
+            // handles, so curvature builds gradually from the straight edge
+            // instead of jumping to 1/r. Fitting the extent scales the
+            // radius down with it.
+            CornerStyle::Continuous => (radii.map(|r| r * 1.28), 0.86 / 1.28),
+        };
+        let CornerRadii { top_left: tl, top_right: tr, bottom_right: br, bottom_left: bl } = extents.fit(w, h);
+        let (top, right) = (y + h, x + w);
 
-            // Right edge (top to bottom)
-            content.line_to(x + w, y + r);
+        // Start at top-left, after the corner curve
+        content.move_to(x + tl, top);
 
-            // Bottom-right corner
-            content.cubic_to(
-                x + w, y + r - c,          // control point 1
-                x + w - r + c, y,          // control point 2
-                x + w - r, y,              // end point
-            );
+        // Top edge (left to right), then the top-right corner
+        content.line_to(right - tr, top);
+        if tr > 0.0 {
+            let c = tr * handle;
+            content.cubic_to(right - tr + c, top, right, top - tr + c, right, top - tr);
+        }
 
-            // Bottom edge (right to left)
-            content.line_to(x + r, y);
+        // Right edge (top to bottom), then the bottom-right corner
+        content.line_to(right, y + br);
+        if br > 0.0 {
+            let c = br * handle;
+            content.cubic_to(right, y + br - c, right - br + c, y, right - br, y);
+        }
 
-            // Bottom-left corner
-            content.cubic_to(
-                x + r - c, y,              // control point 1
-                x, y + r - c,              // control point 2
-                x, y + r,                  // end point
-            );
+        // Bottom edge (right to left), then the bottom-left corner
+        content.line_to(x + bl, y);
+        if bl > 0.0 {
+            let c = bl * handle;
+            content.cubic_to(x + bl - c, y, x, y + bl - c, x, y + bl);
+        }
 
-            // Left edge (bottom to top)
-            content.line_to(x, y + h - r);
+        // Left edge (bottom to top), then the top-left corner
+        content.line_to(x, top - tl);
+        if tl > 0.0 {
+            let c = tl * handle;
+            content.cubic_to(x, top - tl + c, x + tl - c, top, x + tl, top);
+        }
 
-            // Top-left corner
-            content.cubic_to(
-                x, y + h - r + c,          // control point 1
-                x + r - c, y + h,          // control point 2
-                x + r, y + h,              // end point
-            );
+        content.close_path();
+    }
 
-            content.close_path();
+    /// Trace an image's clip shape fitted to the box at (`x`, `y`), in PDF
+    /// coordinates.
+    fn draw_clip_path(content: &mut Content, clip: ImageClip, x: f32, y: f32, w: f32, h: f32) {
+        match clip {
+            ImageClip::Circle => {
+                let d = w.min(h);
+                Self::draw_ellipse_path(content, x + (w - d) / 2.0, y + (h - d) / 2.0, d, d);
+            }
+            ImageClip::Ellipse => Self::draw_ellipse_path(content, x, y, w, h),
+            ImageClip::Rounded(radii) => Self::draw_rect_path(content, x, y, w, h, radii, CornerStyle::Circular),
         }
     }
 
+    /// Trace the ellipse inscribed in a box as four quarter curves, with
+    /// the same handle factor as `draw_rect_path`'s circular corners.
+    fn draw_ellipse_path(content: &mut Content, x: f32, y: f32, w: f32, h: f32) {
+        let (rx, ry) = (w / 2.0, h / 2.0);
+        let (cx, cy) = (x + rx, y + ry);
+        let (kx, ky) = (rx * 0.5523, ry * 0.5523);
+        content.move_to(cx + rx, cy);
+        content.cubic_to(cx + rx, cy + ky, cx + kx, cy + ry, cx, cy + ry);
+        content.cubic_to(cx - kx, cy + ry, cx - rx, cy + ky, cx - rx, cy);
+        content.cubic_to(cx - rx, cy - ky, cx - kx, cy - ry, cx, cy - ry);
+        content.cubic_to(cx + kx, cy - ry, cx + rx, cy - ky, cx + rx, cy);
+        content.close_path();
+    }
+
     fn render_line(
         &self,
         content: &mut Content,
@@ -701,6 +2812,11 @@ impl<'a> PdfGenerator<'a> {
         page_height: f32,
         alpha_states: &HashMap<u8, Ref>,
     ) {
+        // As for rects, zero width is no stroke unless asked for as a hairline
+        if line.stroke <= 0.0 && !line.hairline {
+            return;
+        }
+
         // Save state to isolate graphics state changes
         content.save_state();
 
@@ -713,9 +2829,10 @@ impl<'a> PdfGenerator<'a> {
             content.set_parameters(Name(alpha_name.as_bytes()));
         }
 
-        let (r, g, b) = line.color.to_rgb_floats();
-        content.set_stroke_rgb(r, g, b);
+        set_stroke(content, &line.color);
         content.set_line_width(line.stroke);
+        set_dash(content, line.dash.as_ref());
+        set_line_style(content, line.cap, line.join, line.miter_limit);
         content.move_to(line.x1, pdf_y1);
         content.line_to(line.x2, pdf_y2);
         content.stroke();
@@ -723,11 +2840,145 @@ impl<'a> PdfGenerator<'a> {
         content.restore_state();
     }
 
+    fn render_polygon(
+        &self,
+        content: &mut Content,
+        polygon: &PolygonElement,
+        page_height: f32,
+        alpha_states: &HashMap<u8, Ref>,
+    ) {
+        let path = |content: &mut Content, close: bool| {
+            let mut points = polygon.points.iter().map(|&(x, y)| (x, page_height - y));
+            let (x, y) = points.next().expect("polygons have at least 3 points");
+            content.move_to(x, y);
+            for (x, y) in points {
+                content.line_to(x, y);
+            }
+            if close {
+                content.close_path();
+            }
+        };
+
+        content.save_state();
+
+        // Filling closes the path whether or not the stroke does
+        if let Some(fill) = &polygon.fill_color {
+            if fill.a != 255 {
+                let alpha_name = self.get_alpha_state_name(fill.a, alpha_states);
+                content.set_parameters(Name(alpha_name.as_bytes()));
+            }
+            set_fill(content, fill);
+            path(content, true);
+            match polygon.fill_rule {
+                FillRule::NonZero => content.fill_nonzero(),
+                FillRule::EvenOdd => content.fill_even_odd(),
+            };
+        }
+
+        // As for rects, zero width is no stroke unless asked for as a hairline
+        if polygon.stroke > 0.0 || polygon.hairline {
+            if polygon.stroke_color.a != 255 {
+                let alpha_name = self.get_alpha_state_name(polygon.stroke_color.a, alpha_states);
+                content.set_parameters(Name(alpha_name.as_bytes()));
+            }
+            set_stroke(content, &polygon.stroke_color);
+            content.set_line_width(polygon.stroke);
+            set_line_style(content, polygon.cap, polygon.join, polygon.miter_limit);
+            path(content, polygon.close);
+            content.stroke();
+        }
+
+        content.restore_state();
+    }
+
+    fn render_leader(
+        &self,
+        content: &mut Content,
+        leader: &LeaderElement,
+        page_height: f32,
+        alpha_states: &HashMap<u8, Ref>,
+    ) -> Result<()> {
+        let Some(stroke) = leader::layout(leader, self.resources)? else {
+            return Ok(());
+        };
+        content.save_state();
+
+        if leader.color.a != 255 {
+            let alpha_name = self.get_alpha_state_name(leader.color.a, alpha_states);
+            content.set_parameters(Name(alpha_name.as_bytes()));
+        }
+
+        set_stroke(content, &leader.color);
+        content.set_line_width(stroke.width);
+        if stroke.round_caps {
+            content.set_line_cap(LineCapStyle::RoundCap);
+        }
+        if let Some((on, off)) = stroke.dash {
+            content.set_dash_pattern([on, off], 0.0);
+        }
+        // The last dot is a zero-length dash at the very end of the line;
+        // run on half a pitch so rounding can't drop it
+        let overrun = match stroke.dash {
+            Some((0.0, pitch)) => pitch / 2.0,
+            _ => 0.0,
+        };
+        let pdf_y = page_height - stroke.y;
+        content.move_to(stroke.start, pdf_y);
+        content.line_to(stroke.end + overrun, pdf_y);
+        content.stroke();
+
+        content.restore_state();
+        Ok(())
+    }
+
+    fn render_progress_ring(
+        &self,
+        content: &mut Content,
+        ring: &ProgressRingElement,
+        page_height: f32,
+        alpha_states: &HashMap<u8, Ref>,
+    ) {
+        let arcs = ring::arcs(ring);
+        let layers = [
+            (arcs.track.as_ref().zip(ring.track_color.as_ref()), true),
+            (arcs.fill.as_ref().map(|f| (f, &ring.color)), arcs.fill_closed),
+        ];
+        for (arc, closed) in layers {
+            let Some((segments, color)) = arc else { continue };
+            content.save_state();
+
+            if color.a != 255 {
+                let alpha_name = self.get_alpha_state_name(color.a, alpha_states);
+                content.set_parameters(Name(alpha_name.as_bytes()));
+            }
+
+            set_stroke(content, color);
+            content.set_line_width(ring.thickness);
+            // Open arcs get round ends; a whole circle is closed instead, so
+            // no cap sits over its seam
+            if !closed {
+                content.set_line_cap(LineCapStyle::RoundCap);
+            }
+            let (x0, y0) = segments[0][0];
+            content.move_to(x0, page_height - y0);
+            for &[_, (x1, y1), (x2, y2), (x3, y3)] in segments {
+                content.cubic_to(x1, page_height - y1, x2, page_height - y2, x3, page_height - y3);
+            }
+            if closed {
+                content.close_path();
+            }
+            content.stroke();
+
+            content.restore_state();
+        }
+    }
+
     fn render_image(
         &self,
         content: &mut Content,
         img: &ImageElement,
         page_height: f32,
+        alpha_states: &HashMap<u8, Ref>,
     ) -> Result<()> {
         // Save graphics state
         content.save_state();
@@ -753,31 +3004,138 @@ impl<'a> PdfGenerator<'a> {
         // Convert to PDF coordinates (y is top edge, PDF uses bottom-left origin)
         let pdf_y = page_height - img.y - final_h;
 
+        // Turned about the anchor ahead of the placement, so SVG forms
+        // and raster images turn alike; sizes stay unturned
+        if img.rotation != 0.0 {
+            content.transform(rotation_matrix(img.rotation, img.x, page_height - img.y));
+        }
+        // A quad maps the box drawn at the origin onto its corners
+        let (render_x, pdf_y) = match &img.quad {
+            Some(quad) => {
+                content.transform(quad.matrix(page_height));
+                (0.0, 0.0)
+            }
+            None => (render_x, pdf_y),
+        };
+        // A border is stroked once the clip and placement are undone
+        let bordered = img.border_width > 0.0;
+        if bordered {
+            content.save_state();
+        }
+        // Clipped in the box the image is drawn into, so the shape turns
+        // and maps with it, raster and SVG alike
+        if let Some(clip) = img.clip {
+            Self::draw_clip_path(content, clip, render_x, pdf_y, final_w, final_h);
+            content.clip_nonzero();
+            content.end_path();
+        }
+
+        match &img.fade {
+            Some(fade) => {
+                // The mask is in image-local coordinates: move to the image
+                // origin before selecting it, then draw at the origin.
+                content.transform([1.0, 0.0, 0.0, 1.0, render_x, pdf_y]);
+                content.set_parameters(Name(FadeMasks::name(fade, final_w, final_h).as_bytes()));
+                Self::place_xobject(content, loaded, &img.image_ref, 0.0, 0.0, final_w, final_h);
+            }
+            None => Self::place_xobject(content, loaded, &img.image_ref, render_x, pdf_y, final_w, final_h),
+        }
+
+        // Border over the image, along the clip shape or the display box
+        if bordered {
+            content.restore_state();
+            if img.border_color.a != 255 {
+                let alpha_name = self.get_alpha_state_name(img.border_color.a, alpha_states);
+                content.set_parameters(Name(alpha_name.as_bytes()));
+            }
+            set_stroke(content, &img.border_color);
+            content.set_line_width(img.border_width);
+            match img.clip {
+                Some(clip) => Self::draw_clip_path(content, clip, render_x, pdf_y, final_w, final_h),
+                None => {
+                    content.rect(render_x, pdf_y, final_w, final_h);
+                }
+            }
+            content.stroke();
+        }
+
+        // Restore graphics state
+        content.restore_state();
+
+        Ok(())
+    }
+
+    /// Display size of each of a text box's inline images: `h` tall and as
+    /// wide as the image's aspect ratio gives.
+    fn inline_image_sizes(&self, textbox: &TextBoxElement) -> Result<Vec<(f32, f32)>> {
+        textbox
+            .inline_images
+            .iter()
+            .map(|image| {
+                let (src_w, src_h) = self.resources.get_image(&image.image_ref)?.dimensions();
+                Ok(Self::compute_image_dimensions(src_w, src_h, None, Some(image.h)))
+            })
+            .collect()
+    }
+
+    /// Draw an image XObject into the box with bottom-left PDF corner
+    /// (`x`, `pdf_y`) and size `w × h`.
+    fn place_xobject(
+        content: &mut Content,
+        loaded: &LoadedImage,
+        image_ref: &str,
+        x: f32,
+        pdf_y: f32,
+        w: f32,
+        h: f32,
+    ) {
         // Transform and draw - both SVG and raster use same positioning logic
-        let xobject_name = match loaded {
+        match loaded {
             LoadedImage::Svg { width, height, .. } => {
                 // SVG Form XObjects use native BBox coordinates, scale to target size
-                let scale_x = final_w / width;
-                let scale_y = final_h / height;
-                content.transform([scale_x, 0.0, 0.0, scale_y, render_x, pdf_y]);
-                // SVGs use original name (vector, no per-size variants)
-                img.image_ref.clone()
+                content.transform([w / width, 0.0, 0.0, h / height, x, pdf_y]);
             }
             LoadedImage::Raster { .. } => {
                 // Raster images are in unit coordinates (0-1), scale by target size
-                content.transform([final_w, 0.0, 0.0, final_h, render_x, pdf_y]);
-                // Raster images use size-specific key (each size embedded at 300 DPI)
-                Self::image_size_key(&img.image_ref, final_w, final_h)
+                content.transform([w, 0.0, 0.0, h, x, pdf_y]);
             }
-        };
-
-        // Draw image XObject
+        }
+        let xobject_name = Self::xobject_key(loaded, image_ref, w, h);
         content.x_object(Name(xobject_name.as_bytes()));
+    }
 
-        // Restore graphics state
-        content.restore_state();
+    /// Draw a barcode, QR code or Data Matrix element in full.
+    fn render_symbol(
+        &self,
+        content: &mut Content,
+        element: &Element,
+        page_height: f32,
+        font_embedders: &HashMap<String, FontEmbedder>,
+        alias_to_ps: &HashMap<String, String>,
+        alpha_states: &HashMap<u8, Ref>,
+    ) -> Result<()> {
+        match element {
+            Element::Barcode(b) => self.render_barcode(content, b, page_height, font_embedders, alias_to_ps, alpha_states),
+            Element::QRCode(qr) => self.render_qrcode(content, qr, page_height, alpha_states),
+            Element::DataMatrix(dm) => self.render_datamatrix(content, dm, page_height, alpha_states),
+            _ => Ok(()),
+        }
+    }
 
-        Ok(())
+    /// Draw a symbol from its shared form, which has the symbol's top-left
+    /// corner at the origin.
+    fn place_symbol(content: &mut Content, element: &Element, form_name: &str, page_height: f32) {
+        let (x, y, rotation) = match element {
+            Element::Barcode(b) => (b.x, b.y, b.rotation),
+            Element::QRCode(qr) => (qr.x, qr.y, 0.0),
+            Element::DataMatrix(dm) => (dm.x, dm.y, 0.0),
+            _ => return,
+        };
+        let [a, b, c, d, _, _] = rotation_matrix(rotation, 0.0, 0.0);
+        content.save_state();
+        content.transform([a, b, c, d, x, page_height - y]);
+        content.x_object(Name(form_name.as_bytes()));
+        content.restore_state();
     }
 
     fn render_barcode(
@@ -787,38 +3145,19 @@ impl<'a> PdfGenerator<'a> {
         page_height: f32,
         font_embedders: &HashMap<String, FontEmbedder>,
         alias_to_ps: &HashMap<String, String>,
-        _alpha_states: &HashMap<u8, Ref>,
+        alpha_states: &HashMap<u8, Ref>,
     ) -> Result<()> {
-        use crate::types::BarcodeKind;
-        use rubar_core::{encode_code128, gs1, Code128Symbol};
-
         // Save state to isolate graphics state changes
         content.save_state();
 
-        let (symbols, human_readable_text) = match barcode.kind {
-            BarcodeKind::Code128 => {
-                // Plain Code 128 with Code-B start: a single Data symbol is enough
-                // — rubar_core::encode_code128 auto-inserts Code-B when no start
-                // symbol is specified.
-                (
-                    vec![Code128Symbol::Data(barcode.value.clone())],
-                    barcode.value.clone(),
-                )
-            }
-            BarcodeKind::Gs1_128 => {
-                let fields = gs1::parse(&barcode.value)
-                    .map_err(|e| RupdfError::InvalidBarcode {
-                        value: barcode.value.clone(),
-                        reason: e.to_string(),
-                    })?;
-                (gs1::to_symbols(&fields), gs1::format_human_readable(&fields))
-            }
-        };
+        // Bars and text are laid out unturned, then turned together about
+        // the top-left corner
+        if barcode.rotation != 0.0 {
+            content.transform(rotation_matrix(barcode.rotation, barcode.x, page_height - barcode.y));
+        }
 
-        let geometry = encode_code128(&symbols).map_err(|e| RupdfError::InvalidBarcode {
-            value: barcode.value.clone(),
-            reason: e.to_string(),
-        })?;
+        let barcode::EncodedBarcode { geometry, human_readable: human_readable_text } =
+            barcode::encode(barcode.kind, &barcode.value)?;
 
         // Calculate bar dimensions
         let bar_height = if barcode.human_readable {
@@ -833,13 +3172,20 @@ impl<'a> PdfGenerator<'a> {
         let bar_top_y = page_height - barcode.y;
         let bar_bottom_y = bar_top_y - bar_height;
 
-        // Draw bars
-        content.set_fill_rgb(0.0, 0.0, 0.0);
+        // Bars and human-readable text share the barcode color
+        if barcode.color.a != 255 {
+            let alpha_name = self.get_alpha_state_name(barcode.color.a, alpha_states);
+            content.set_parameters(Name(alpha_name.as_bytes()));
+        }
+        set_fill(content, &barcode.color);
 
+        // All bars in one path and one fill
         for bar in &geometry.bars {
             let x = barcode.x + bar.x as f32 * module_width;
             let width = bar.width as f32 * module_width;
             content.rect(x, bar_bottom_y, width, bar_height);
+        }
+        if !geometry.bars.is_empty() {
             content.fill_nonzero();
         }
 
@@ -852,17 +3198,34 @@ impl<'a> PdfGenerator<'a> {
 
             let chain_refs: Vec<&LoadedFont> = vec![font];
             let chain_names: Vec<&str> = vec![barcode.font.as_str()];
-            let resolved = runs::resolve(
-                &human_readable_text, &chain_refs, &chain_names, MissingGlyphPolicy::Drop,
+            let resolve = |text: &str| {
+                runs::resolve(text, &chain_refs, &chain_names, MissingGlyphPolicy::Drop, &EmojiFallback::default())
+            };
+            // The element draws no quiet zone, so the text has the whole
+            // symbol width
+            let room = barcode.w;
+            let (text, font_size, clipped) = overflow::constrain_line(
+                &human_readable_text,
+                barcode.font_size,
+                room,
+                barcode.text_overflow,
+                barcode.text_min_size,
+                |text, size| Ok(runs::measure(&resolve(text)?, &chain_refs, size)),
             )?;
-            let text_width = runs::measure(&resolved, &chain_refs, barcode.font_size);
-            let text_x = barcode.x + (barcode.w - text_width) / 2.0;
+            let resolved = resolve(&text)?;
+            let text_width = runs::measure(&resolved, &chain_refs, font_size);
+            let text_x = barcode.x + (room - text_width) / 2.0;
 
-            let ascender_pts = font.ascender_pts(barcode.font_size);
+            let ascender_pts = font.ascender_pts(font_size);
             let text_y = bar_bottom_y - 2.0 - ascender_pts;
 
+            if clipped {
+                content.rect(barcode.x, text_y - font_size, room, font_size + ascender_pts);
+                content.clip_nonzero();
+                content.end_path();
+            }
             content.begin_text();
-            content.set_font(Name(ps_name.as_bytes()), barcode.font_size);
+            content.set_font(Name(ps_name.as_bytes()), font_size);
             content.next_line(text_x, text_y);
             for run in runs::group_runs(&resolved, &chain_names) {
                 let bytes = encode_glyphs(&run.glyphs);
@@ -886,12 +3249,7 @@ impl<'a> PdfGenerator<'a> {
         page_height: f32,
         alpha_states: &HashMap<u8, Ref>,
     ) -> Result<()> {
-        use rubar_core::encode_qr;
-
-        let geometry = encode_qr(&qr.value).map_err(|e| RupdfError::InvalidBarcode {
-            value: qr.value.clone(),
-            reason: e.to_string(),
-        })?;
+        let geometry = crate::elements::qr::encode(qr)?;
 
         self.render_matrix(
             content,
@@ -983,13 +3341,12 @@ impl<'a> PdfGenerator<'a> {
         let top_y = page_height - y;
 
         // Background fill (uses the bounding box, not the rendered rect)
-        if background.r != 255 || background.g != 255 || background.b != 255 {
+        if !background.is_paper() {
             if background.a != 255 {
                 let alpha_name = self.get_alpha_state_name(background.a, alpha_states);
                 content.set_parameters(Name(alpha_name.as_bytes()));
             }
-            let (r, g, b) = background.to_rgb_floats();
-            content.set_fill_rgb(r, g, b);
+            set_fill(content, background);
             content.rect(x, top_y - rendered_h, rendered_w, rendered_h);
             content.fill_nonzero();
         }
@@ -999,73 +3356,174 @@ impl<'a> PdfGenerator<'a> {
             let alpha_name = self.get_alpha_state_name(color.a, alpha_states);
             content.set_parameters(Name(alpha_name.as_bytes()));
         }
-        let (r, g, b) = color.to_rgb_floats();
-        content.set_fill_rgb(r, g, b);
+        set_fill(content, color);
 
+        // One rect per horizontal run of dark modules, all in one fill
+        let mut painted = false;
         for (row, line) in geometry.modules.iter().enumerate() {
-            for (col, &dark) in line.iter().enumerate() {
-                if dark {
-                    let mx = x + col as f32 * module_size;
-                    let my = top_y - (row + 1) as f32 * module_size;
-                    content.rect(mx, my, module_size, module_size);
+            let my = top_y - (row + 1) as f32 * module_size;
+            let mut col = 0;
+            while col < line.len() {
+                if !line[col] {
+                    col += 1;
+                    continue;
+                }
+                let start = col;
+                while col < line.len() && line[col] {
+                    col += 1;
                 }
+                let mx = x + start as f32 * module_size;
+                content.rect(mx, my, (col - start) as f32 * module_size, module_size);
+                painted = true;
             }
         }
-        content.fill_nonzero();
+        if painted {
+            content.fill_nonzero();
+        }
 
         content.restore_state();
     }
 
-    fn write_image(&self, pdf: &mut Pdf, image_ref: Ref, loaded: &LoadedImage, name: &str, max_size_pts: (f32, f32)) -> Result<()> {
+    /// Write an ICC profile stream with `components` color components as
+    /// `profile_ref`, returning it.
+    fn write_icc_profile(pdf: &mut Pdf, profile_ref: Ref, data: &[u8], components: i32) -> Ref {
+        let mut stream = pdf.icc_profile(profile_ref, data);
+        stream.n(components);
+        match components {
+            1 => stream.alternate().device_gray(),
+            3 => stream.alternate().device_rgb(),
+            _ => stream.alternate().device_cmyk(),
+        }
+        stream.finish();
+        profile_ref
+    }
+
+    /// Write an image XObject. Raster images are DeviceRGB, or ICCBased on
+    /// `profile` when given; with `convert`, their pixels are converted
+    /// from that ICC profile to sRGB first.
+    #[allow(clippy::too_many_arguments)]
+    fn write_image(
+        &self,
+        pdf: &mut Pdf,
+        image_ref: Ref,
+        loaded: &LoadedImage,
+        name: &str,
+        max_size_pts: (f32, f32),
+        profile: Option<Ref>,
+        convert: Option<&[u8]>,
+    ) -> Result<ImageStats> {
         match loaded {
-            LoadedImage::Svg { tree, .. } => {
-                crate::elements::svg::write_svg_form(pdf, image_ref, tree, name)
-            }
-            LoadedImage::Raster { data, .. } => {
-                self.write_raster_image(pdf, image_ref, data, name, max_size_pts)
+            LoadedImage::Svg { data, .. } => {
+                let tree = LoadedImage::svg_tree(name, data)?;
+                let bytes = crate::elements::svg::write_svg_form(pdf, image_ref, &tree, name, !self.debug_uncompressed)?;
+                Ok(ImageStats { name: name.to_string(), encoding: ImageEncoding::Svg, pixels: None, dpi: None, bytes, profile: None })
             }
+            LoadedImage::Raster { data, width, height, frame, .. } => self.write_raster_image(
+                pdf,
+                image_ref,
+                (data, *frame),
+                (*width, *height),
+                name,
+                max_size_pts,
+                (profile, convert),
+            ),
         }
     }
 
-    fn write_raster_image(&self, pdf: &mut Pdf, image_ref: Ref, data: &[u8], name: &str, max_size_pts: (f32, f32)) -> Result<()> {
-        // Decode image
-        let img = image::load_from_memory(data).map_err(|e| {
-            RupdfError::InvalidImage(name.to_string(), format!("Failed to decode: {}", e))
+    /// Write a color glyph bitmap as an RGB image with an alpha soft mask,
+    /// so emoji composite over whatever is behind the text.
+    fn write_color_glyph(&self, pdf: &mut Pdf, (image_ref, mask_ref): (Ref, Ref), font: &LoadedFont, glyph_id: u16) -> Result<ImageStats> {
+        let glyph = font.raster_glyph(glyph_id).ok_or_else(|| {
+            RupdfError::InvalidFont(font.postscript_name.clone(), format!("Missing bitmap for glyph {}", glyph_id))
+        })?;
+        let img = image::load_from_memory(&glyph.png).map_err(|e| {
+            RupdfError::InvalidFont(font.postscript_name.clone(), format!("Failed to decode glyph {} bitmap: {}", glyph_id, e))
         })?;
+        let rgba = img.to_rgba8();
+        let (width, height) = rgba.dimensions();
+        let mut rgb = Vec::with_capacity((width * height * 3) as usize);
+        let mut alpha = Vec::with_capacity((width * height) as usize);
+        for pixel in rgba.pixels() {
+            rgb.extend_from_slice(&pixel.0[..3]);
+            alpha.push(pixel.0[3]);
+        }
 
-        // Convert to RGB, flattening alpha against white background
-        let rgb = Self::flatten_alpha_to_white(&img);
-        let src_width = rgb.width();
-        let src_height = rgb.height();
+        let deflate = |data: Vec<u8>| match self.debug_uncompressed {
+            true => data,
+            false => miniz_oxide::deflate::compress_to_vec_zlib(&data, 6),
+        };
+        let mask_data = deflate(alpha);
+        let mut mask = pdf.image_xobject(mask_ref, &mask_data);
+        if !self.debug_uncompressed {
+            mask.filter(Filter::FlateDecode);
+        }
+        mask.width(width as i32);
+        mask.height(height as i32);
+        mask.color_space().device_gray();
+        mask.bits_per_component(8);
+        mask.finish();
+
+        let rgb_data = deflate(rgb);
+        let mut image = pdf.image_xobject(image_ref, &rgb_data);
+        if !self.debug_uncompressed {
+            image.filter(Filter::FlateDecode);
+        }
+        image.width(width as i32);
+        image.height(height as i32);
+        image.color_space().device_rgb();
+        image.bits_per_component(8);
+        image.s_mask(mask_ref);
+        image.finish();
 
-        // Calculate target dimensions for 300 DPI
-        // max_size_pts is in points (72 points per inch)
-        // target_pixels = (points / 72) * 300
-        let target_dpi = 300.0;
-        let target_width = ((max_size_pts.0 / 72.0) * target_dpi).ceil() as u32;
-        let target_height = ((max_size_pts.1 / 72.0) * target_dpi).ceil() as u32;
-
-        // Only downscale if source is larger than target
-        let (final_img, final_width, final_height) = if src_width > target_width || src_height > target_height {
-            // Calculate scale to fit within target bounds while preserving aspect ratio
-            let scale_x = target_width as f32 / src_width as f32;
-            let scale_y = target_height as f32 / src_height as f32;
-            let scale = scale_x.min(scale_y).min(1.0); // Never upscale
-
-            let new_width = (src_width as f32 * scale).round() as u32;
-            let new_height = (src_height as f32 * scale).round() as u32;
-
-            // Use Lanczos3 for high-quality downscaling
-            let resized = image::imageops::resize(
-                &rgb,
-                new_width,
-                new_height,
-                image::imageops::FilterType::Lanczos3,
-            );
-            (resized, new_width, new_height)
+        Ok(ImageStats {
+            name: String::new(),
+            encoding: ImageEncoding::Flate,
+            pixels: Some((width, height)),
+            dpi: None,
+            bytes: mask_data.len() + rgb_data.len(),
+            profile: None,
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn write_raster_image(
+        &self,
+        pdf: &mut Pdf,
+        image_ref: Ref,
+        (data, frame): (&[u8], u32),
+        (header_width, header_height): (u32, u32),
+        name: &str,
+        max_size_pts: (f32, f32),
+        (profile, convert): (Option<Ref>, Option<&[u8]>),
+    ) -> Result<ImageStats> {
+        let (target_width, target_height) = Self::target_pixels(max_size_pts);
+
+        // Decode image, at no more than the size it is drawn at
+        let fit = (target_width as f32 / header_width as f32)
+            .min(target_height as f32 / header_height as f32)
+            .min(1.0);
+        let needed = (
+            (header_width as f32 * fit).ceil() as u32,
+            (header_height as f32 * fit).ceil() as u32,
+        );
+        let img = Self::decode_raster(data, frame, name, needed)?;
+
+        // Convert to RGB, flattening alpha against white background, and
+        // downscale if the source is larger than the target. 16-bit
+        // sources stay in floating point until a final dithered step down
+        // to 8 bits, so smooth gradients don't band.
+        let mut final_img = if raster::is_high_bit_depth(&img) {
+            let rgb = raster::flatten_alpha_to_white_f32(&img);
+            raster::dither_to_rgb8(&raster::fit_pixels(rgb, (target_width, target_height)))
         } else {
-            (rgb, src_width, src_height)
+            raster::fit_pixels(raster::flatten_alpha_to_white(&img), (target_width, target_height))
         };
+        if let Some(icc) = convert {
+            raster::convert_to_srgb(&mut final_img, icc).map_err(|e| {
+                RupdfError::InvalidImage(name.to_string(), format!("Failed to convert to sRGB: {}", e))
+            })?;
+        }
+        let (final_width, final_height) = final_img.dimensions();
 
         // Encode as JPEG with 85% quality
         let mut jpeg_data = Vec::new();
@@ -1079,11 +3537,70 @@ impl<'a> PdfGenerator<'a> {
         image.filter(Filter::DctDecode);
         image.width(final_width as i32);
         image.height(final_height as i32);
-        image.color_space().device_rgb();
+        match profile {
+            Some(profile_ref) => image.color_space().icc_based(profile_ref),
+            None => image.color_space().device_rgb(),
+        }
         image.bits_per_component(8);
         image.finish();
 
-        Ok(())
+        Ok(ImageStats {
+            name: name.to_string(),
+            encoding: ImageEncoding::Jpeg,
+            pixels: Some((final_width, final_height)),
+            // Sources smaller than the target are embedded as they are
+            dpi: Some(final_width as f32 / (max_size_pts.0 / 72.0)),
+            bytes: jpeg_data.len(),
+            profile: None,
+        })
+    }
+
+    /// Decode a raster image. JPEGs are decoded with DCT scaling to the
+    /// smallest of 1/8, 1/4, 1/2 or full size that still covers `needed`
+    /// pixels, which skips most of the work for photos drawn small.
+    /// Later GIF frames decode the frames before them, which they are
+    /// composited over, but none after.
+    pub(crate) fn decode_raster(data: &[u8], frame: u32, name: &str, needed: (u32, u32)) -> Result<image::DynamicImage> {
+        let decode_err = |e: image::ImageError| {
+            RupdfError::InvalidImage(name.to_string(), format!("Failed to decode: {}", e))
+        };
+        if frame > 0 {
+            use image::AnimationDecoder;
+            let decoder = image::codecs::gif::GifDecoder::new(std::io::Cursor::new(data)).map_err(decode_err)?;
+            let frame = decoder.into_frames().nth(frame as usize).ok_or_else(|| {
+                RupdfError::InvalidImage(name.to_string(), format!("Failed to decode: no frame {}", frame))
+            })?;
+            return Ok(image::DynamicImage::ImageRgba8(frame.map_err(decode_err)?.into_buffer()));
+        }
+        if image::guess_format(data).ok() != Some(image::ImageFormat::Jpeg) {
+            return image::load_from_memory(data).map_err(decode_err);
+        }
+        let mut decoder = image::codecs::jpeg::JpegDecoder::new(std::io::Cursor::new(data)).map_err(decode_err)?;
+        let clamp = |n: u32| n.clamp(1, u16::MAX as u32) as u16;
+        decoder.scale(clamp(needed.0), clamp(needed.1)).map_err(decode_err)?;
+        image::DynamicImage::from_decoder(decoder).map_err(decode_err)
+    }
+
+    /// Pixels wanted for a raster image drawn `size_pts` large: 300 DPI.
+    /// Points are 1/72 inch, so target_pixels = (points / 72) * 300.
+    fn target_pixels(size_pts: (f32, f32)) -> (u32, u32) {
+        let target_dpi = 300.0;
+        (
+            ((size_pts.0 / 72.0) * target_dpi).ceil() as u32,
+            ((size_pts.1 / 72.0) * target_dpi).ceil() as u32,
+        )
+    }
+
+    /// The size a `width × height` pixel source is embedded at when drawn
+    /// `size_pts` large, as `write_raster_image` downscales it; sources
+    /// already within the target keep their size.
+    pub(crate) fn embedded_pixels((width, height): (u32, u32), size_pts: (f32, f32)) -> (u32, u32) {
+        let (target_width, target_height) = Self::target_pixels(size_pts);
+        if width <= target_width && height <= target_height {
+            return (width, height);
+        }
+        let scale = (target_width as f32 / width as f32).min(target_height as f32 / height as f32);
+        ((width as f32 * scale).round() as u32, (height as f32 * scale).round() as u32)
     }
 
     /// Compute final image dimensions from source size and optional target size
@@ -1091,7 +3608,7 @@ impl<'a> PdfGenerator<'a> {
     /// - If only w provided: scale height to preserve aspect ratio
     /// - If only h provided: scale width to preserve aspect ratio
     /// - If neither provided: use source dimensions
-    fn compute_image_dimensions(src_w: f32, src_h: f32, w: Option<f32>, h: Option<f32>) -> (f32, f32) {
+    pub(crate) fn compute_image_dimensions(src_w: f32, src_h: f32, w: Option<f32>, h: Option<f32>) -> (f32, f32) {
         match (w, h) {
             (Some(w), Some(h)) => (w, h),
             (Some(w), None) => {
@@ -1106,31 +3623,35 @@ impl<'a> PdfGenerator<'a> {
         }
     }
 
+    /// Scale a `src_w × src_h` image into a `frame_w × frame_h` frame.
+    /// Returns (x, y, w, h) relative to the frame's top-left corner; `cover`
+    /// may return negative offsets where the image overflows the frame.
+    pub(crate) fn fit_image(src_w: f32, src_h: f32, frame_w: f32, frame_h: f32, fit: ImageFit) -> (f32, f32, f32, f32) {
+        let scale = match fit {
+            ImageFit::Stretch => return (0.0, 0.0, frame_w, frame_h),
+            ImageFit::Cover => (frame_w / src_w).max(frame_h / src_h),
+            ImageFit::Contain => (frame_w / src_w).min(frame_h / src_h),
+        };
+        let (w, h) = (src_w * scale, src_h * scale);
+        ((frame_w - w) / 2.0, (frame_h - h) / 2.0, w, h)
+    }
+
+    /// XObject resource name for an image drawn at `w × h` points.
+    /// SVGs are vector and share one XObject; rasters get one per size so
+    /// each is embedded at 300 DPI.
+    fn xobject_key(loaded: &LoadedImage, image_ref: &str, w: f32, h: f32) -> String {
+        match loaded {
+            LoadedImage::Svg { .. } => image_ref.to_string(),
+            LoadedImage::Raster { .. } => Self::image_size_key(image_ref, w, h),
+        }
+    }
+
     /// Generate a unique key for an image at a specific display size
     /// Used to embed raster images at exactly 300 DPI for each usage
     fn image_size_key(image_ref: &str, w: f32, h: f32) -> String {
         // Round to avoid floating point comparison issues
         format!("{}_{:.0}x{:.0}", image_ref, w, h)
     }
-
-    /// Flatten alpha channel against white background
-    /// This properly composites transparent pixels instead of just discarding alpha
-    fn flatten_alpha_to_white(img: &image::DynamicImage) -> image::RgbImage {
-        let rgba = img.to_rgba8();
-        let (width, height) = rgba.dimensions();
-        let mut rgb = image::RgbImage::new(width, height);
-
-        for (x, y, pixel) in rgba.enumerate_pixels() {
-            let alpha = pixel[3] as f32 / 255.0;
-            // Composite: result = foreground * alpha + background * (1 - alpha)
-            // With white background (255, 255, 255)
-            let r = (pixel[0] as f32 * alpha + 255.0 * (1.0 - alpha)) as u8;
-            let g = (pixel[1] as f32 * alpha + 255.0 * (1.0 - alpha)) as u8;
-            let b = (pixel[2] as f32 * alpha + 255.0 * (1.0 - alpha)) as u8;
-            rgb.put_pixel(x, y, image::Rgb([r, g, b]));
-        }
-        rgb
-    }
 }
 
 /// Parse a date string into a PDF Date.
@@ -0,0 +1,391 @@
+//! Resource preflight: load every declared font and image the way
+//! rendering does, find which ones the document draws, and report on each
+//! without writing a PDF.
+//!
+//! References are found as the writer's first pass finds them: hidden
+//! elements and repeating elements on no page draw nothing, so they
+//! reference nothing either.
+
+use crate::pdf::PdfGenerator;
+use crate::resources::{LoadedImage, LoadedResources};
+use crate::runs;
+use crate::types::{Document, Element, PageBackground, RawResource};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// What `check` found
+pub struct Preflight {
+    pub fonts: BTreeMap<String, FontReport>,
+    pub images: BTreeMap<String, ImageReport>,
+    /// Fonts and images drawn but not declared in `resources`
+    pub missing_fonts: Vec<String>,
+    pub missing_images: Vec<String>,
+}
+
+impl Preflight {
+    /// Every referenced resource loaded and decoded, and none is missing.
+    /// Unreferenced resources and unsupported SVG features don't count.
+    pub fn ok(&self) -> bool {
+        self.missing_fonts.is_empty()
+            && self.missing_images.is_empty()
+            && self.fonts.values().all(|font| !font.referenced || font.info.is_ok())
+            && self.images.values().all(|image| !image.referenced || image.info.is_ok())
+    }
+}
+
+pub struct FontReport {
+    pub referenced: bool,
+    /// The font, or why it failed to load
+    pub info: Result<FontInfo, String>,
+}
+
+pub struct FontInfo {
+    pub postscript_name: String,
+    pub num_glyphs: u16,
+    pub embed: bool,
+    /// Characters of the samples the font has no glyph for, each once
+    pub missing: Vec<char>,
+}
+
+pub struct ImageReport {
+    pub referenced: bool,
+    /// The image, or why it failed to load or decode
+    pub info: Result<ImageInfo, String>,
+}
+
+pub struct ImageInfo {
+    pub svg: bool,
+    /// Pixels for raster images, points for SVGs
+    pub width: f32,
+    pub height: f32,
+    /// Each size the image is drawn at, in points, and for raster images
+    /// the pixels it is embedded with there
+    pub sizes: Vec<DrawnSize>,
+    /// SVG features drawn differently or not at all
+    pub unsupported: Vec<&'static str>,
+}
+
+pub struct DrawnSize {
+    pub width: f32,
+    pub height: f32,
+    pub pixels: Option<(u32, u32)>,
+}
+
+/// Report on `doc`'s resources. `samples` are checked against every font
+/// that loads.
+pub fn check(doc: &Document, samples: &[String]) -> Preflight {
+    let (resources, failures) = LoadedResources::load_each(&doc.resources);
+    let mut refs = References::default();
+    for page in &doc.pages {
+        if let PageBackground::Image { image_ref, fit } = &page.background {
+            let size = resources.images.get(image_ref).map(|loaded| {
+                let (src_w, src_h) = loaded.dimensions();
+                let (_, _, w, h) = PdfGenerator::fit_image(src_w, src_h, page.width, page.height, *fit);
+                (w, h)
+            });
+            refs.image(image_ref, size);
+        }
+        for element in &page.elements {
+            refs.scan(element, &resources);
+        }
+    }
+    for repeating in &doc.repeating {
+        if (0..doc.pages.len()).any(|i| repeating.pages.includes(i)) {
+            refs.scan(&repeating.element, &resources);
+        }
+    }
+    // Not yet placed, and drawn on every page
+    if let Some(watermark) = doc.watermark.as_ref().filter(|_| !doc.pages.is_empty()) {
+        refs.scan(&watermark.element, &resources);
+    }
+    let regions = [&doc.header, &doc.footer].into_iter().flatten();
+    for region in regions.filter(|region| doc.pages.len() > usize::from(region.skip_first)) {
+        for element in &region.elements {
+            refs.scan(element, &resources);
+        }
+    }
+
+    let mut fonts = BTreeMap::new();
+    for name in doc.resources.fonts.keys() {
+        let info = match (resources.fonts.get(name), failures.fonts.get(name)) {
+            (Some(font), _) => {
+                let mut missing = Vec::new();
+                for ch in samples.iter().flat_map(|sample| runs::missing(sample, font)) {
+                    if !missing.contains(&ch) {
+                        missing.push(ch);
+                    }
+                }
+                Ok(FontInfo {
+                    postscript_name: font.postscript_name.clone(),
+                    num_glyphs: font.num_glyphs,
+                    embed: font.embed,
+                    missing,
+                })
+            }
+            (None, Some(e)) => Err(e.to_string()),
+            (None, None) => unreachable!("every declared font loads or fails"),
+        };
+        fonts.insert(name.clone(), FontReport { referenced: refs.fonts.contains(name.as_str()), info });
+    }
+
+    let mut images = BTreeMap::new();
+    for name in doc.resources.images.keys() {
+        let sizes = refs.images.get(name.as_str());
+        let info = match (resources.images.get(name), failures.images.get(name)) {
+            (Some(loaded), _) => image_info(name, loaded, sizes.map_or(&[][..], Vec::as_slice)),
+            (None, Some(e)) => Err(e.to_string()),
+            (None, None) => unreachable!("every declared image loads or fails"),
+        };
+        images.insert(name.clone(), ImageReport { referenced: sizes.is_some(), info });
+    }
+
+    Preflight {
+        missing_fonts: refs.fonts.iter().filter(|name| !fonts.contains_key(**name)).map(|name| name.to_string()).collect(),
+        missing_images: refs.images.keys().filter(|name| !images.contains_key(**name)).map(|name| name.to_string()).collect(),
+        fonts,
+        images,
+    }
+}
+
+/// Describe a loaded image drawn at `sizes`. Raster pixels are decoded
+/// here, as large as the largest embedded size needs (the whole image
+/// when it isn't drawn), so data that only fails past the header is
+/// caught; SVGs were parsed in full when loaded.
+fn image_info(name: &str, loaded: &LoadedImage, sizes: &[(f32, f32)]) -> Result<ImageInfo, String> {
+    match loaded {
+        LoadedImage::Svg { data, width, height } => {
+            let tree = LoadedImage::svg_tree(name, data).map_err(|e| e.to_string())?;
+            Ok(ImageInfo {
+                svg: true,
+                width: *width,
+                height: *height,
+                sizes: sizes.iter().map(|&(width, height)| DrawnSize { width, height, pixels: None }).collect(),
+                unsupported: crate::elements::svg::unsupported_features(&tree),
+            })
+        }
+        LoadedImage::Raster { data, width, height, frame, .. } => {
+            let sizes: Vec<DrawnSize> = sizes
+                .iter()
+                .map(|&(w, h)| DrawnSize {
+                    width: w,
+                    height: h,
+                    pixels: Some(PdfGenerator::embedded_pixels((*width, *height), (w, h))),
+                })
+                .collect();
+            let needed = sizes
+                .iter()
+                .filter_map(|size| size.pixels)
+                .reduce(|a, b| (a.0.max(b.0), a.1.max(b.1)))
+                .unwrap_or((*width, *height));
+            PdfGenerator::decode_raster(data, *frame, name, needed).map_err(|e| e.to_string())?;
+            Ok(ImageInfo { svg: false, width: *width as f32, height: *height as f32, sizes, unsupported: Vec::new() })
+        }
+    }
+}
+
+/// Fonts and images the document draws, each image with the sizes it is
+/// drawn at (none known when it didn't load)
+#[derive(Default)]
+struct References<'a> {
+    fonts: BTreeSet<&'a str>,
+    images: BTreeMap<&'a str, Vec<(f32, f32)>>,
+}
+
+impl<'a> References<'a> {
+    fn font(&mut self, name: &'a str) {
+        self.fonts.insert(name);
+    }
+
+    /// Sizes equal to the nearest point, which share an image object when
+    /// written, are listed once.
+    fn image(&mut self, name: &'a str, size: Option<(f32, f32)>) {
+        let sizes = self.images.entry(name).or_default();
+        if let Some((w, h)) = size {
+            if !sizes.iter().any(|&(sw, sh)| sw.round() == w.round() && sh.round() == h.round()) {
+                sizes.push((w, h));
+            }
+        }
+    }
+
+    fn scan(&mut self, element: &'a Element, resources: &LoadedResources) {
+        if !element.visible() {
+            return;
+        }
+        let dimensions = |name: &str| resources.images.get(name).map(LoadedImage::dimensions);
+        match element {
+            Element::Text(t) => {
+                self.font(&t.font);
+                t.font_fallback.iter().for_each(|f| self.font(f));
+                t.emoji.font.iter().for_each(|f| self.font(f));
+                t.styles.iter().filter_map(|s| s.font.as_deref()).for_each(|f| self.font(f));
+            }
+            Element::TextBox(tb) => {
+                self.font(&tb.font);
+                tb.font_fallback.iter().for_each(|f| self.font(f));
+                tb.emoji.font.iter().for_each(|f| self.font(f));
+                tb.styles.iter().filter_map(|s| s.font.as_deref()).for_each(|f| self.font(f));
+                for image in &tb.inline_images {
+                    let size = dimensions(&image.image_ref)
+                        .map(|(w, h)| PdfGenerator::compute_image_dimensions(w, h, None, Some(image.h)));
+                    self.image(&image.image_ref, size);
+                }
+            }
+            Element::Barcode(b) if b.human_readable => self.font(&b.font),
+            Element::Leader(l) => l.font.iter().for_each(|f| self.font(f)),
+            Element::SignatureLine(s) => self.font(&s.font),
+            Element::ImagePlaceholder(p) => self.font(&p.font),
+            Element::Grid(g) => g.font.iter().for_each(|f| self.font(f)),
+            Element::Table(t) => {
+                self.font(&t.font);
+                t.header_font.iter().for_each(|f| self.font(f));
+                t.font_fallback.iter().for_each(|f| self.font(f));
+            }
+            Element::Image(img) => {
+                let size = dimensions(&img.image_ref)
+                    .map(|(w, h)| PdfGenerator::compute_image_dimensions(w, h, img.w, img.h));
+                self.image(&img.image_ref, size);
+            }
+            Element::Group(g) => g.elements.iter().for_each(|child| self.scan(child, resources)),
+            Element::Fragment(f) => f.fragment.elements.iter().for_each(|child| self.scan(child, resources)),
+            Element::RawContent(raw) => {
+                for (i, (_, resource)) in raw.resources.iter().enumerate() {
+                    if !raw.uses.names.iter().any(|&(_, used)| used == i) {
+                        continue;
+                    }
+                    match resource {
+                        RawResource::Font(alias) => self.font(alias),
+                        RawResource::Image(name) => self.image(name, dimensions(name)),
+                        RawResource::Alpha(_) => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The dict `preflight` returns; see there.
+pub fn to_py<'py>(py: Python<'py>, report: &Preflight) -> PyResult<Bound<'py, PyDict>> {
+    let fonts = PyDict::new(py);
+    for (name, font) in &report.fonts {
+        let item = PyDict::new(py);
+        item.set_item("ok", font.info.is_ok())?;
+        item.set_item("error", font.info.as_ref().err())?;
+        item.set_item("referenced", font.referenced)?;
+        let info = font.info.as_ref().ok();
+        item.set_item("postscript_name", info.map(|f| &f.postscript_name))?;
+        item.set_item("num_glyphs", info.map(|f| f.num_glyphs))?;
+        item.set_item("embedded", info.map(|f| f.embed))?;
+        item.set_item("missing", info.map(|f| f.missing.iter().map(char::to_string).collect::<Vec<_>>()))?;
+        fonts.set_item(name, item)?;
+    }
+    let images = PyDict::new(py);
+    for (name, image) in &report.images {
+        let item = PyDict::new(py);
+        item.set_item("ok", image.info.is_ok())?;
+        item.set_item("error", image.info.as_ref().err())?;
+        item.set_item("referenced", image.referenced)?;
+        let info = image.info.as_ref().ok();
+        item.set_item("kind", info.map(|i| if i.svg { "svg" } else { "raster" }))?;
+        item.set_item("width", info.map(|i| i.width))?;
+        item.set_item("height", info.map(|i| i.height))?;
+        let sizes = match info {
+            Some(info) => {
+                let sizes = PyList::empty(py);
+                for size in &info.sizes {
+                    let entry = PyDict::new(py);
+                    entry.set_item("width", size.width)?;
+                    entry.set_item("height", size.height)?;
+                    entry.set_item("pixel_width", size.pixels.map(|(w, _)| w))?;
+                    entry.set_item("pixel_height", size.pixels.map(|(_, h)| h))?;
+                    entry.set_item("dpi", size.pixels.map(|(w, _)| w as f32 / (size.width / 72.0)))?;
+                    sizes.append(entry)?;
+                }
+                Some(sizes)
+            }
+            None => None,
+        };
+        item.set_item("sizes", sizes)?;
+        item.set_item("unsupported", info.map(|i| i.unsupported.clone()))?;
+        images.set_item(name, item)?;
+    }
+    let dict = PyDict::new(py);
+    dict.set_item("ok", report.ok())?;
+    dict.set_item("fonts", fonts)?;
+    dict.set_item("images", images)?;
+    dict.set_item("missing_fonts", &report.missing_fonts)?;
+    dict.set_item("missing_images", &report.missing_images)?;
+    Ok(dict)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{
+        FontResource, FontSource, ImageFit, ImageProfile, ImageResource, ImageSource, Metadata, Page, Resources,
+    };
+    use crate::units::{Origin, Units};
+
+    const SVG: &str = r#"<svg xmlns="http://www.w3.org/2000/svg" width="40" height="20"><defs><linearGradient id="g"><stop offset="0"/><stop offset="1" stop-color="red"/></linearGradient></defs><rect width="40" height="20" fill="url(#g)" opacity="0.5"/><text>Hi</text></svg>"#;
+
+    fn asset(name: &str) -> String {
+        format!("{}/assets/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    fn doc(backgrounds: &[&str], resources: Resources) -> Document {
+        let page = |image_ref: &&str| Page {
+            width: 612.0,
+            height: 792.0,
+            background: PageBackground::Image { image_ref: image_ref.to_string(), fit: ImageFit::Cover },
+            repeat: 1,
+            elements: vec![],
+            source: 0,
+        };
+        Document {
+            metadata: Metadata::default(),
+            pages: backgrounds.iter().map(page).collect(),
+            resources,
+            repeating: vec![],
+            layers: vec![],
+            open_action: None,
+            origin: Origin::TopLeft,
+            units: Units::default(),
+            watermark: None,
+            header: None,
+            footer: None,
+        }
+    }
+
+    #[test]
+    fn reports_each_resource_without_stopping() {
+        let mut resources = Resources::default();
+        let font = |source| FontResource { source, embed: true, cap_height_glyphs: None };
+        resources.fonts.insert("sans".into(), font(FontSource::Path(asset("IBMPlexSans-Regular.otf"))));
+        resources.fonts.insert("broken".into(), font(FontSource::Bytes(b"not a font".to_vec())));
+        let image = |source| ImageResource { source, frame: 0, profile: ImageProfile::Embed };
+        resources.images.insert("logo".into(), image(ImageSource::Path(asset("test-png.png"))));
+        resources.images.insert("chart".into(), image(ImageSource::Svg(SVG.into())));
+
+        let report = check(&doc(&["logo"], resources.clone()), &["Hi \u{4e2d}".to_string()]);
+        assert!(report.ok(), "the broken font isn't referenced");
+        let sans = report.fonts["sans"].info.as_ref().unwrap();
+        assert_eq!(sans.postscript_name, "IBMPlexSans");
+        assert_eq!(sans.missing, vec!['\u{4e2d}']);
+        let err = report.fonts["broken"].info.as_ref().err().unwrap();
+        assert!(err.contains("broken"), "{}", err);
+        let chart = report.images["chart"].info.as_ref().unwrap();
+        assert!(chart.svg && !report.images["chart"].referenced);
+        assert_eq!(chart.unsupported, ["group opacity", "linear gradient fill", "text element"]);
+
+        // Covering the page, the 1280px square is drawn 792pt wide: under
+        // 300 DPI, so embedded whole
+        let logo = report.images["logo"].info.as_ref().unwrap();
+        assert_eq!((logo.sizes[0].width, logo.sizes[0].height), (792.0, 792.0));
+        assert_eq!(logo.sizes[0].pixels, Some((1280, 1280)));
+
+        let report = check(&doc(&["logo", "ghost"], resources), &[]);
+        assert!(!report.ok());
+        assert_eq!(report.missing_images, ["ghost"]);
+    }
+}
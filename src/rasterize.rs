@@ -0,0 +1,821 @@
+//! Painting rupdf's own output to pixels, for `rasterize_page`.
+//!
+//! A document is rendered to PDF as usual and one page's content is then
+//! painted with tiny-skia, so a thumbnail comes from the very layout the
+//! PDF has rather than from a second layout engine. Like `extract`, this
+//! reads what the crate writes and nothing more: paths and clips; gray,
+//! RGB, CMYK, ICC-based and spot colors; text in the document's fonts;
+//! JPEG and Flate images with their soft masks; Form XObjects and
+//! transparency groups; axial and radial shadings; and hatch patterns.
+//! Image fades and annotations are left out, and CMYK and spot inks are
+//! shown as their plain RGB approximations.
+
+use crate::error::{Result, RupdfError};
+use crate::extract::{code, concat, err, Font, Lexer, Matrix, Obj, Reader, Token, IDENTITY};
+use crate::resources::LoadedFont;
+use std::collections::HashMap;
+use std::rc::Rc;
+use tiny_skia::{
+    ClipMask, Color, FillRule, FilterQuality, GradientStop, LineCap, LineJoin, LinearGradient, Paint, Path,
+    PathBuilder, Pattern, Pixmap, PixmapPaint, Point, RadialGradient, Rect, Shader, SpreadMode, Stroke,
+    StrokeDash, Transform,
+};
+
+/// Most pixels a page is rasterized into: 16384 × 16384, 1 GiB of RGBA
+const MAX_PIXELS: u64 = 1 << 28;
+
+/// Paint page `page` (from 0) of a PDF written by rupdf at `dpi` and
+/// return it as PNG. `dpi` must be positive. `fonts` are the document's
+/// loaded fonts, matched to the PDF's by PostScript name, for glyph
+/// outlines.
+pub fn rasterize(pdf: &[u8], page: usize, dpi: f32, fonts: &HashMap<String, LoadedFont>) -> Result<Vec<u8>> {
+    let reader = Reader::parse(pdf)?;
+    let pages = reader.pages()?;
+    let dict = *pages.get(page).ok_or_else(|| err(format!("no page {}", page)))?;
+    let [left, bottom, right, top] = reader.media_box(dict)?;
+    let scale = dpi / 72.0;
+    let (width, height) = (((right - left) * scale).round().max(1.0), ((top - bottom) * scale).round().max(1.0));
+    // Checked before allocating, as a failed allocation aborts the process
+    let side = (i32::MAX / 4) as f32;
+    let too_large = || RupdfError::PdfError(format!("a {}x{} pixel page is too large to rasterize", width, height));
+    if !(width <= side && height <= side) || width as u64 * height as u64 > MAX_PIXELS {
+        return Err(too_large());
+    }
+    let mut pixmap = Pixmap::new(width as u32, height as u32).ok_or_else(too_large)?;
+    pixmap.fill(Color::WHITE);
+
+    // PDF space, y up from the bottom-left, to pixels, y down from the
+    // top-left; every stream starts from here
+    let base = [scale, 0.0, 0.0, -scale, -left * scale, top * scale];
+    let mut painter = Painter {
+        reader: &reader,
+        faces: fonts.values().map(|f| (f.postscript_name.as_str(), f)).collect(),
+        fonts: HashMap::new(),
+        glyphs: HashMap::new(),
+        images: HashMap::new(),
+        tiles: HashMap::new(),
+        base,
+    };
+    let resources = match dict.get("Resources") {
+        Some(resources) => reader.resolve(resources)?.clone(),
+        None => Obj::Null,
+    };
+    let contents: Vec<&Obj> = match dict.get("Contents") {
+        Some(Obj::Array(streams)) => streams.iter().collect(),
+        Some(stream) => vec![stream],
+        None => Vec::new(),
+    };
+    for stream in contents {
+        painter.run(&mut pixmap, &reader.stream(stream)?, &resources, State::new(base))?;
+    }
+    pixmap.encode_png().map_err(|e| RupdfError::PdfError(format!("can't encode PNG: {}", e)))
+}
+
+fn transform(m: &Matrix) -> Transform {
+    Transform::from_row(m[0], m[1], m[2], m[3], m[4], m[5])
+}
+
+/// A color space, as far as turning components into RGB goes
+#[derive(Debug, Clone, PartialEq)]
+enum Space {
+    Gray,
+    Rgb,
+    Cmyk,
+    /// A Separation ink, tint 0 to 1 running from `c0` to `c1` in RGB
+    Separation([f32; 3], [f32; 3]),
+    Pattern,
+}
+
+impl Space {
+    fn rgb(&self, c: &[f32]) -> [f32; 3] {
+        let at = |i: usize| c.get(i).copied().unwrap_or(0.0).clamp(0.0, 1.0);
+        match self {
+            Space::Gray | Space::Pattern => [at(0); 3],
+            Space::Rgb => [at(0), at(1), at(2)],
+            Space::Cmyk => {
+                let k = 1.0 - at(3);
+                [(1.0 - at(0)) * k, (1.0 - at(1)) * k, (1.0 - at(2)) * k]
+            }
+            Space::Separation(c0, c1) => {
+                let t = at(0);
+                [0, 1, 2].map(|i| c0[i] + t * (c1[i] - c0[i]))
+            }
+        }
+    }
+
+    /// A space's initial color: black, or full tint for an ink
+    fn initial(&self) -> Ink {
+        match self {
+            Space::Separation(_, c1) => Ink::Color(*c1),
+            _ => Ink::Color([0.0; 3]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Ink {
+    Color([f32; 3]),
+    /// A tiling pattern, by resource name
+    Pattern(String),
+}
+
+/// What an ink paints with, owned so that drawing can go on around it
+enum Source {
+    Solid(Color),
+    /// A pattern tile, its placement on the target and its opacity
+    Tile(Rc<Pixmap>, Transform, f32),
+}
+
+impl Source {
+    fn paint(&self) -> Paint<'_> {
+        let shader = match self {
+            Source::Solid(color) => Shader::SolidColor(*color),
+            Source::Tile(tile, ts, alpha) => {
+                Pattern::new(tile.as_ref().as_ref(), SpreadMode::Repeat, FilterQuality::Bilinear, *alpha, *ts)
+            }
+        };
+        Paint { shader, anti_alias: true, ..Paint::default() }
+    }
+}
+
+#[derive(Clone)]
+struct State {
+    ctm: Matrix,
+    fill_space: Space,
+    stroke_space: Space,
+    fill: Ink,
+    stroke: Ink,
+    fill_alpha: f32,
+    stroke_alpha: f32,
+    line_width: f32,
+    cap: LineCap,
+    join: LineJoin,
+    miter_limit: f32,
+    dash: Option<(Vec<f32>, f32)>,
+    /// Device-space clip; shared until narrowed
+    clip: Option<Rc<ClipMask>>,
+}
+
+impl State {
+    fn new(ctm: Matrix) -> Self {
+        Self {
+            ctm,
+            fill_space: Space::Gray,
+            stroke_space: Space::Gray,
+            fill: Ink::Color([0.0; 3]),
+            stroke: Ink::Color([0.0; 3]),
+            fill_alpha: 1.0,
+            stroke_alpha: 1.0,
+            line_width: 1.0,
+            cap: LineCap::Butt,
+            join: LineJoin::Miter,
+            miter_limit: 10.0,
+            dash: None,
+            clip: None,
+        }
+    }
+
+    fn stroke_style(&self) -> Stroke {
+        let dash = self.dash.as_ref().and_then(|(array, phase)| {
+            // An odd array repeats to make on/off pairs
+            let array = if array.len() % 2 == 1 { array.repeat(2) } else { array.clone() };
+            StrokeDash::new(array, *phase)
+        });
+        Stroke { width: self.line_width, miter_limit: self.miter_limit, line_cap: self.cap, line_join: self.join, dash }
+    }
+}
+
+/// A font as text is drawn with it: widths from the PDF, outlines from the
+/// matching loaded font
+struct TextFont<'f> {
+    metrics: Font,
+    face: Option<&'f LoadedFont>,
+}
+
+/// Collects a glyph outline in font units
+struct Outline(PathBuilder);
+
+impl ttf_parser::OutlineBuilder for Outline {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.0.move_to(x, y);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.0.line_to(x, y);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.0.quad_to(x1, y1, x, y);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.0.cubic_to(x1, y1, x2, y2, x, y);
+    }
+
+    fn close(&mut self) {
+        self.0.close();
+    }
+}
+
+/// Interpreter state for one page, Form XObjects and patterns included
+struct Painter<'r, 'f> {
+    reader: &'r Reader,
+    faces: HashMap<&'f str, &'f LoadedFont>,
+    fonts: HashMap<u32, TextFont<'f>>,
+    glyphs: HashMap<(u32, u16), Option<Path>>,
+    images: HashMap<u32, Pixmap>,
+    /// Pattern tiles, drawn once each at device resolution
+    tiles: HashMap<u32, Rc<Pixmap>>,
+    /// The page's PDF-to-pixel matrix, which patterns are placed in
+    base: Matrix,
+}
+
+impl<'f> Painter<'_, 'f> {
+    /// A named resource of `kind`, resolved, with its object number
+    fn resource<'o>(&'o self, resources: &'o Obj, kind: &str, name: &str) -> Result<(u32, &'o Obj)> {
+        let dict = match resources.get(kind) {
+            Some(dict) => self.reader.resolve(dict)?,
+            None => return Err(err(format!("no /{} resources for /{}", kind, name))),
+        };
+        match dict.get(name) {
+            Some(Obj::Ref(id)) => {
+                let object = self.reader.objects.get(id).ok_or_else(|| err(format!("missing object {}", id)))?;
+                Ok((*id, &object.value))
+            }
+            Some(direct) => Ok((0, direct)),
+            None => Err(err(format!("unknown {} /{}", kind, name))),
+        }
+    }
+
+    fn color_space(&self, resources: &Obj, name: &str) -> Result<Space> {
+        Ok(match name {
+            "DeviceGray" => Space::Gray,
+            "DeviceRGB" => Space::Rgb,
+            "DeviceCMYK" => Space::Cmyk,
+            "Pattern" => Space::Pattern,
+            _ => match self.resource(resources, "ColorSpace", name)?.1 {
+                Obj::Array(items) => self.array_space(items)?,
+                Obj::Name(device) => self.color_space(resources, &device.clone())?,
+                _ => return Err(err(format!("malformed color space /{}", name))),
+            },
+        })
+    }
+
+    fn array_space(&self, items: &[Obj]) -> Result<Space> {
+        match (items.first().and_then(Obj::as_name), items.get(1)) {
+            (Some("ICCBased"), Some(stream)) => {
+                let Obj::Ref(id) = stream else {
+                    return Err(err("ICC profile expected"));
+                };
+                let n = self.reader.objects.get(id).and_then(|o| o.value.get("N")).and_then(Obj::as_num);
+                Ok(match n {
+                    Some(1.0) => Space::Gray,
+                    Some(4.0) => Space::Cmyk,
+                    _ => Space::Rgb,
+                })
+            }
+            (Some("Separation"), _) => {
+                let function = self.reader.resolve(items.get(3).ok_or_else(|| err("Separation without a tint function"))?)?;
+                let color = |key: &str| -> [f32; 3] {
+                    let values: Vec<f32> = match function.get(key) {
+                        Some(Obj::Array(values)) => values.iter().filter_map(Obj::as_num).collect(),
+                        _ => Vec::new(),
+                    };
+                    [0, 1, 2].map(|i| values.get(i).copied().unwrap_or(0.0))
+                };
+                Ok(Space::Separation(color("C0"), color("C1")))
+            }
+            (Some("Pattern"), _) => Ok(Space::Pattern),
+            _ => Err(err("unsupported color space")),
+        }
+    }
+
+    fn font(&mut self, resources: &Obj, name: &str) -> Result<u32> {
+        let (id, _) = self.resource(resources, "Font", name)?;
+        if !self.fonts.contains_key(&id) {
+            let metrics = self.reader.load_font(&Obj::Ref(id))?;
+            let face = self.faces.get(metrics.name.as_str()).copied();
+            self.fonts.insert(id, TextFont { metrics, face });
+        }
+        Ok(id)
+    }
+
+    /// The outline of glyph `glyph` in font units; codes are glyph ids
+    /// under Identity-H
+    fn glyph(&mut self, font_id: u32, glyph: u16) -> Option<&Path> {
+        let face = self.fonts[&font_id].face;
+        self.glyphs
+            .entry((font_id, glyph))
+            .or_insert_with(|| {
+                let mut outline = Outline(PathBuilder::new());
+                face?.outline_glyph(glyph, &mut outline).then(|| outline.0.finish())?
+            })
+            .as_ref()
+    }
+
+    fn source(&mut self, ink: &Ink, alpha: f32, resources: &Obj) -> Result<Source> {
+        match ink {
+            Ink::Color([r, g, b]) => {
+                Ok(Source::Solid(Color::from_rgba(*r, *g, *b, alpha.clamp(0.0, 1.0)).unwrap_or(Color::BLACK)))
+            }
+            Ink::Pattern(name) => {
+                let (id, pattern) = self.resource(resources, "Pattern", name)?;
+                let pattern = pattern.clone();
+                let num = |key: &str| pattern.get(key).and_then(Obj::as_num).unwrap_or(1.0);
+                let (x_step, y_step) = (num("XStep"), num("YStep"));
+                let matrix = match pattern.get("Matrix") {
+                    Some(Obj::Array(m)) => m.iter().filter_map(Obj::as_num).collect::<Vec<_>>().try_into().unwrap_or(IDENTITY),
+                    _ => IDENTITY,
+                };
+                let pattern_to_device = concat(&matrix, &self.base);
+                // Tiles are drawn at the size they cover on the page
+                let scale = (pattern_to_device[0] * pattern_to_device[3] - pattern_to_device[1] * pattern_to_device[2]).abs().sqrt();
+                let (w, h) = ((x_step * scale).ceil().max(1.0), (y_step * scale).ceil().max(1.0));
+                if !self.tiles.contains_key(&id) {
+                    let mut tile = Pixmap::new(w as u32, h as u32).ok_or_else(|| err(format!("pattern /{} is too large", name)))?;
+                    let tile_resources = match pattern.get("Resources") {
+                        Some(r) => self.reader.resolve(r)?.clone(),
+                        None => Obj::Null,
+                    };
+                    let data = self.reader.stream(&Obj::Ref(id))?;
+                    let to_tile = [w / x_step, 0.0, 0.0, -h / y_step, 0.0, h];
+                    self.run(&mut tile, &data, &tile_resources, State::new(to_tile))?;
+                    self.tiles.insert(id, Rc::new(tile));
+                }
+                let from_tile = [x_step / w, 0.0, 0.0, -y_step / h, 0.0, y_step];
+                Ok(Source::Tile(self.tiles[&id].clone(), transform(&concat(&from_tile, &pattern_to_device)), alpha))
+            }
+        }
+    }
+
+    /// `clip` narrowed by `path`, given in device space. A path with
+    /// nothing on the target clips everything away.
+    fn narrow(clip: Option<&ClipMask>, path: &Path, rule: FillRule, target: &Pixmap) -> ClipMask {
+        if let Some(clip) = clip {
+            let mut narrowed = clip.clone();
+            if narrowed.intersect_path(path, rule, true).is_some() {
+                return narrowed;
+            }
+        }
+        let mut mask = ClipMask::new();
+        let _ = mask.set_path(target.width(), target.height(), path, rule, true);
+        mask
+    }
+
+    /// Interpret a content stream drawn onto `target` with `resources`
+    /// from `state`.
+    fn run(&mut self, target: &mut Pixmap, data: &[u8], resources: &Obj, state: State) -> Result<()> {
+        let mut lexer = Lexer::new(data);
+        let mut operands: Vec<Obj> = Vec::new();
+        let mut stack: Vec<State> = Vec::new();
+        let mut gs = state;
+        let mut path = PathBuilder::new();
+        let mut clip_rule: Option<FillRule> = None;
+        // Text state
+        let (mut tm, mut tlm) = (IDENTITY, IDENTITY);
+        let mut font: Option<u32> = None;
+        let (mut size, mut char_spacing, mut word_spacing, mut scaling, mut leading, mut rise, mut mode) =
+            (0.0, 0.0, 0.0, 100.0, 0.0, 0.0, 0);
+        while let Some(token) = lexer.next_token()? {
+            let op = match token {
+                Token::Word(op) => op,
+                token => {
+                    operands.push(lexer.value(token, false)?);
+                    continue;
+                }
+            };
+            let nums: Vec<f32> = operands.iter().filter_map(Obj::as_num).collect();
+            let num = |i: usize| nums.get(i).copied().unwrap_or(0.0);
+            match op.as_str() {
+                "q" => stack.push(gs.clone()),
+                "Q" => gs = stack.pop().ok_or_else(|| err("unbalanced Q"))?,
+                "cm" if nums.len() == 6 => gs.ctm = concat(&[num(0), num(1), num(2), num(3), num(4), num(5)], &gs.ctm),
+                "w" => gs.line_width = num(0),
+                "J" => gs.cap = [LineCap::Butt, LineCap::Round, LineCap::Square][(num(0) as usize).min(2)],
+                "j" => gs.join = [LineJoin::Miter, LineJoin::Round, LineJoin::Bevel][(num(0) as usize).min(2)],
+                "M" => gs.miter_limit = num(0),
+                "d" => {
+                    gs.dash = match operands.first() {
+                        Some(Obj::Array(array)) if !array.is_empty() => {
+                            Some((array.iter().filter_map(Obj::as_num).collect(), num(0)))
+                        }
+                        _ => None,
+                    }
+                }
+                "gs" => {
+                    let name = operands.first().and_then(Obj::as_name).unwrap_or_default();
+                    let state = self.resource(resources, "ExtGState", name)?.1;
+                    if let Some(alpha) = state.get("ca").and_then(Obj::as_num) {
+                        gs.fill_alpha = alpha;
+                    }
+                    if let Some(alpha) = state.get("CA").and_then(Obj::as_num) {
+                        gs.stroke_alpha = alpha;
+                    }
+                }
+                "g" | "rg" | "k" | "G" | "RG" | "K" => {
+                    let space = match op.to_ascii_lowercase().as_str() {
+                        "g" => Space::Gray,
+                        "rg" => Space::Rgb,
+                        _ => Space::Cmyk,
+                    };
+                    let ink = Ink::Color(space.rgb(&nums));
+                    if op.chars().all(|c| c.is_ascii_lowercase()) {
+                        (gs.fill_space, gs.fill) = (space, ink);
+                    } else {
+                        (gs.stroke_space, gs.stroke) = (space, ink);
+                    }
+                }
+                "cs" | "CS" => {
+                    let name = operands.first().and_then(Obj::as_name).unwrap_or_default();
+                    let space = self.color_space(resources, name)?;
+                    let ink = space.initial();
+                    if op == "cs" {
+                        (gs.fill_space, gs.fill) = (space, ink);
+                    } else {
+                        (gs.stroke_space, gs.stroke) = (space, ink);
+                    }
+                }
+                "sc" | "scn" | "SC" | "SCN" => {
+                    let space = if op.starts_with('s') { &gs.fill_space } else { &gs.stroke_space };
+                    let ink = match operands.last().and_then(Obj::as_name) {
+                        Some(pattern) => Ink::Pattern(pattern.to_string()),
+                        None => Ink::Color(space.rgb(&nums)),
+                    };
+                    if op.starts_with('s') {
+                        gs.fill = ink;
+                    } else {
+                        gs.stroke = ink;
+                    }
+                }
+                "m" => path.move_to(num(0), num(1)),
+                "l" => path.line_to(num(0), num(1)),
+                "c" => path.cubic_to(num(0), num(1), num(2), num(3), num(4), num(5)),
+                "v" => {
+                    let current = path.last_point().unwrap_or(Point::zero());
+                    path.cubic_to(current.x, current.y, num(0), num(1), num(2), num(3));
+                }
+                "y" => path.cubic_to(num(0), num(1), num(2), num(3), num(2), num(3)),
+                "h" => path.close(),
+                "re" => {
+                    let (x, y, w, h) = (num(0), num(1), num(2), num(3));
+                    path.move_to(x, y);
+                    path.line_to(x + w, y);
+                    path.line_to(x + w, y + h);
+                    path.line_to(x, y + h);
+                    path.close();
+                }
+                "W" => clip_rule = Some(FillRule::Winding),
+                "W*" => clip_rule = Some(FillRule::EvenOdd),
+                "S" | "s" | "f" | "F" | "f*" | "B" | "B*" | "b" | "b*" | "n" => {
+                    if matches!(op.as_str(), "s" | "b" | "b*") {
+                        path.close();
+                    }
+                    let Some(finished) = std::mem::take(&mut path).finish() else {
+                        clip_rule = None;
+                        operands.clear();
+                        continue;
+                    };
+                    let ts = transform(&gs.ctm);
+                    let clip = gs.clip.clone();
+                    if matches!(op.as_str(), "f" | "F" | "f*" | "B" | "B*" | "b" | "b*") {
+                        let rule = if op.ends_with('*') { FillRule::EvenOdd } else { FillRule::Winding };
+                        let source = self.source(&gs.fill, gs.fill_alpha, resources)?;
+                        target.fill_path(&finished, &source.paint(), rule, ts, clip.as_deref());
+                    }
+                    if matches!(op.as_str(), "S" | "s" | "B" | "B*" | "b" | "b*") {
+                        let source = self.source(&gs.stroke, gs.stroke_alpha, resources)?;
+                        target.stroke_path(&finished, &source.paint(), &gs.stroke_style(), ts, clip.as_deref());
+                    }
+                    if let Some(rule) = clip_rule.take() {
+                        if let Some(device) = finished.transform(ts) {
+                            gs.clip = Some(Rc::new(Self::narrow(gs.clip.as_deref(), &device, rule, target)));
+                        }
+                    }
+                }
+                "sh" => {
+                    let name = operands.first().and_then(Obj::as_name).unwrap_or_default();
+                    let shading = self.resource(resources, "Shading", name)?.1.clone();
+                    if let Some(mut shader) = self.shader(&shading, transform(&gs.ctm))? {
+                        shader.apply_opacity(gs.fill_alpha);
+                        let paint = Paint { shader, anti_alias: true, ..Paint::default() };
+                        let page = Rect::from_xywh(0.0, 0.0, target.width() as f32, target.height() as f32)
+                            .expect("pixmaps are never empty");
+                        target.fill_rect(page, &paint, Transform::identity(), gs.clip.as_deref());
+                    }
+                }
+                "BT" => {
+                    tm = IDENTITY;
+                    tlm = IDENTITY;
+                }
+                "Tf" => {
+                    if let Some(name) = operands.first().and_then(Obj::as_name) {
+                        font = Some(self.font(resources, name)?);
+                    }
+                    size = num(0);
+                }
+                "Td" | "TD" => {
+                    if op == "TD" {
+                        leading = -num(1);
+                    }
+                    tlm = concat(&[1.0, 0.0, 0.0, 1.0, num(0), num(1)], &tlm);
+                    tm = tlm;
+                }
+                "T*" => {
+                    tlm = concat(&[1.0, 0.0, 0.0, 1.0, 0.0, -leading], &tlm);
+                    tm = tlm;
+                }
+                "Tm" if nums.len() == 6 => {
+                    tlm = [num(0), num(1), num(2), num(3), num(4), num(5)];
+                    tm = tlm;
+                }
+                "Tc" => char_spacing = num(0),
+                "Tw" => word_spacing = num(0),
+                "Tz" => scaling = num(0),
+                "TL" => leading = num(0),
+                "Ts" => rise = num(0),
+                "Tr" => mode = num(0) as u8,
+                "Tj" | "TJ" => {
+                    let Some(font_id) = font else {
+                        return Err(err(format!("{} without a font", op)));
+                    };
+                    let items: Vec<Obj> = match operands.first() {
+                        Some(Obj::Array(items)) => items.clone(),
+                        Some(item) => vec![item.clone()],
+                        None => Vec::new(),
+                    };
+                    let th = scaling / 100.0;
+                    let units = self.fonts[&font_id].face.map_or(1000.0, |f| f32::from(f.units_per_em));
+                    // Render modes 0 to 2 fill, stroke or both; the rest draw nothing
+                    let fill = matches!(mode, 0 | 2).then(|| self.source(&gs.fill, gs.fill_alpha, resources)).transpose()?;
+                    let stroke = matches!(mode, 1 | 2).then(|| self.source(&gs.stroke, gs.stroke_alpha, resources)).transpose()?;
+                    for item in items {
+                        match item {
+                            Obj::Str(bytes) => {
+                                for pair in bytes.chunks(2) {
+                                    let code = code(pair);
+                                    let glyph_space = concat(
+                                        &[size * th / units, 0.0, 0.0, size / units, 0.0, rise],
+                                        &concat(&tm, &gs.ctm),
+                                    );
+                                    let ts = transform(&glyph_space);
+                                    let clip = gs.clip.clone();
+                                    let line_stroke = gs.stroke_style();
+                                    if let Some(outline) = self.glyph(font_id, code) {
+                                        if let Some(source) = &fill {
+                                            target.fill_path(outline, &source.paint(), FillRule::Winding, ts, clip.as_deref());
+                                        }
+                                        if let Some(source) = &stroke {
+                                            // The stroke width is in text space, not font units
+                                            let width = line_stroke.width * units / size.max(f32::EPSILON);
+                                            let style = Stroke { width, ..line_stroke };
+                                            target.stroke_path(outline, &source.paint(), &style, ts, clip.as_deref());
+                                        }
+                                    }
+                                    let metrics = &self.fonts[&font_id].metrics;
+                                    let width = metrics.widths.get(&code).copied().unwrap_or(metrics.default_width);
+                                    let spacing = if pair.len() == 1 && pair[0] == b' ' { word_spacing } else { 0.0 };
+                                    let advance = (width / 1000.0 * size + char_spacing + spacing) * th;
+                                    tm = concat(&[1.0, 0.0, 0.0, 1.0, advance, 0.0], &tm);
+                                }
+                            }
+                            Obj::Num(adjust) => tm = concat(&[1.0, 0.0, 0.0, 1.0, -adjust / 1000.0 * size * th, 0.0], &tm),
+                            _ => {}
+                        }
+                    }
+                }
+                "Do" => {
+                    let name = operands.first().and_then(Obj::as_name).unwrap_or_default();
+                    let (id, xobject) = self.resource(resources, "XObject", name)?;
+                    let xobject = xobject.clone();
+                    match xobject.get("Subtype").and_then(Obj::as_name) {
+                        Some("Image") => {
+                            if !self.images.contains_key(&id) {
+                                let image = self.image(id)?;
+                                self.images.insert(id, image);
+                            }
+                            let image = &self.images[&id];
+                            // Image space is a unit square, its first row at the top
+                            let (w, h) = (image.width() as f32, image.height() as f32);
+                            let ts = transform(&concat(&[1.0 / w, 0.0, 0.0, -1.0 / h, 0.0, 1.0], &gs.ctm));
+                            let paint = PixmapPaint { opacity: gs.fill_alpha, quality: FilterQuality::Bicubic, ..PixmapPaint::default() };
+                            target.draw_pixmap(0, 0, image.as_ref(), &paint, ts, gs.clip.as_deref());
+                        }
+                        Some("Form") => self.form(target, id, &xobject, resources, &gs)?,
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+            operands.clear();
+        }
+        Ok(())
+    }
+
+    /// Draw a Form XObject. A transparency group is painted on its own
+    /// layer and composited at the current fill alpha.
+    fn form(&mut self, target: &mut Pixmap, id: u32, form: &Obj, resources: &Obj, gs: &State) -> Result<()> {
+        let mut inner = gs.clone();
+        if let Some(Obj::Array(m)) = form.get("Matrix") {
+            let m: Vec<f32> = m.iter().filter_map(Obj::as_num).collect();
+            if let [a, b, c, d, e, f] = m[..] {
+                inner.ctm = concat(&[a, b, c, d, e, f], &gs.ctm);
+            }
+        }
+        if let Some(Obj::Array(bbox)) = form.get("BBox") {
+            let bbox: Vec<f32> = bbox.iter().filter_map(Obj::as_num).collect();
+            if let [x0, y0, x1, y1] = bbox[..] {
+                let rect = Rect::from_ltrb(x0.min(x1), y0.min(y1), x0.max(x1), y0.max(y1));
+                if let Some(device) = rect.map(PathBuilder::from_rect).and_then(|p| p.transform(transform(&inner.ctm))) {
+                    inner.clip = Some(Rc::new(Self::narrow(inner.clip.as_deref(), &device, FillRule::Winding, target)));
+                }
+            }
+        }
+        let form_resources = match form.get("Resources") {
+            Some(r) => self.reader.resolve(r)?.clone(),
+            None => resources.clone(),
+        };
+        let data = self.reader.stream(&Obj::Ref(id))?;
+        if form.get("Group").is_none() || gs.fill_alpha >= 1.0 {
+            return self.run(target, &data, &form_resources, inner);
+        }
+        let mut layer = Pixmap::new(target.width(), target.height()).expect("same size as the target");
+        (inner.fill_alpha, inner.stroke_alpha) = (1.0, 1.0);
+        self.run(&mut layer, &data, &form_resources, inner)?;
+        let paint = PixmapPaint { opacity: gs.fill_alpha, ..PixmapPaint::default() };
+        target.draw_pixmap(0, 0, layer.as_ref(), &paint, Transform::identity(), None);
+        Ok(())
+    }
+
+    /// An axial or radial shading as a shader in device space
+    fn shader(&self, shading: &Obj, ts: Transform) -> Result<Option<Shader<'static>>> {
+        let coords: Vec<f32> = match shading.get("Coords") {
+            Some(Obj::Array(coords)) => coords.iter().filter_map(Obj::as_num).collect(),
+            _ => return Err(err("shading has no /Coords")),
+        };
+        let function = self.reader.resolve(shading.get("Function").ok_or_else(|| err("shading has no /Function"))?)?;
+        let stops = self.stops(function)?;
+        Ok(match (shading.get("ShadingType").and_then(Obj::as_num), &coords[..]) {
+            (Some(2.0), &[x0, y0, x1, y1]) => {
+                LinearGradient::new(Point::from_xy(x0, y0), Point::from_xy(x1, y1), stops, SpreadMode::Pad, ts)
+            }
+            (Some(3.0), &[x0, y0, _, x1, y1, r1]) => {
+                RadialGradient::new(Point::from_xy(x0, y0), Point::from_xy(x1, y1), r1, stops, SpreadMode::Pad, ts)
+            }
+            _ => None,
+        })
+    }
+
+    /// Gradient stops for a Type 2 function or a Type 3 stitch of them,
+    /// over the shading's default 0 to 1 domain
+    fn stops(&self, function: &Obj) -> Result<Vec<GradientStop>> {
+        let array = |obj: &Obj, key: &str| -> Vec<f32> {
+            match obj.get(key) {
+                Some(Obj::Array(values)) => values.iter().filter_map(Obj::as_num).collect(),
+                _ => Vec::new(),
+            }
+        };
+        let color = |values: Vec<f32>| {
+            let at = |i: usize| values.get(i).copied().unwrap_or(0.0);
+            Color::from_rgba(at(0), at(1), at(2), 1.0).unwrap_or(Color::BLACK)
+        };
+        let domain = array(function, "Domain");
+        let (start, end) = (domain.first().copied().unwrap_or(0.0), domain.get(1).copied().unwrap_or(1.0));
+        match function.get("FunctionType").and_then(Obj::as_num) {
+            Some(2.0) => Ok(vec![
+                GradientStop::new(start, color(array(function, "C0"))),
+                GradientStop::new(end, color(array(function, "C1"))),
+            ]),
+            Some(3.0) => {
+                let Some(Obj::Array(functions)) = function.get("Functions") else {
+                    return Err(err("stitching function has no /Functions"));
+                };
+                let edges: Vec<f32> = std::iter::once(start).chain(array(function, "Bounds")).chain(std::iter::once(end)).collect();
+                let mut stops = Vec::new();
+                for (i, part) in functions.iter().enumerate() {
+                    let part = self.reader.resolve(part)?;
+                    stops.push(GradientStop::new(edges[i], color(array(part, "C0"))));
+                    stops.push(GradientStop::new(edges.get(i + 1).copied().unwrap_or(end), color(array(part, "C1"))));
+                }
+                Ok(stops)
+            }
+            _ => Err(err("unsupported shading function")),
+        }
+    }
+
+    /// Decode an image XObject to premultiplied RGBA
+    fn image(&self, id: u32) -> Result<Pixmap> {
+        let object = self.reader.objects.get(&id).ok_or_else(|| err(format!("missing object {}", id)))?;
+        let dict = &object.value;
+        let num = |key: &str| dict.get(key).and_then(Obj::as_num).unwrap_or(0.0) as u32;
+        let (width, height) = (num("Width"), num("Height"));
+        let mut rgba = match dict.get("Filter").and_then(Obj::as_name) {
+            Some("DCTDecode") => {
+                let data = object.stream.as_deref().ok_or_else(|| err(format!("object {} is not a stream", id)))?;
+                image::load_from_memory_with_format(data, image::ImageFormat::Jpeg)
+                    .map_err(|e| err(format!("image {}: {}", id, e)))?
+                    .to_rgba8()
+                    .into_raw()
+            }
+            _ => {
+                let samples = self.reader.stream(&Obj::Ref(id))?;
+                let space = match dict.get("ColorSpace") {
+                    Some(Obj::Name(name)) => match name.as_str() {
+                        "DeviceGray" => Space::Gray,
+                        "DeviceCMYK" => Space::Cmyk,
+                        _ => Space::Rgb,
+                    },
+                    Some(obj) => match self.reader.resolve(obj)? {
+                        Obj::Array(items) => self.array_space(items)?,
+                        _ => Space::Rgb,
+                    },
+                    None => Space::Rgb,
+                };
+                let components = match space {
+                    Space::Gray | Space::Separation(..) | Space::Pattern => 1,
+                    Space::Rgb => 3,
+                    Space::Cmyk => 4,
+                };
+                samples
+                    .chunks_exact(components)
+                    .flat_map(|pixel| {
+                        let values: Vec<f32> = pixel.iter().map(|&v| f32::from(v) / 255.0).collect();
+                        let [r, g, b] = space.rgb(&values);
+                        [r, g, b].map(|c| (c * 255.0).round() as u8).into_iter().chain([255])
+                    })
+                    .collect()
+            }
+        };
+        if rgba.len() != (width * height * 4) as usize {
+            return Err(err(format!("image {} has {} bytes for {}x{} pixels", id, rgba.len(), width, height)));
+        }
+        if let Some(Obj::Ref(mask)) = dict.get("SMask") {
+            let alpha = self.reader.stream(&Obj::Ref(*mask))?;
+            for (pixel, &a) in rgba.chunks_exact_mut(4).zip(&alpha) {
+                pixel[3] = a;
+            }
+        }
+        let mut pixmap = Pixmap::new(width, height).ok_or_else(|| err(format!("image {} is empty", id)))?;
+        for (out, pixel) in pixmap.data_mut().chunks_exact_mut(4).zip(rgba.chunks_exact(4)) {
+            let a = u16::from(pixel[3]);
+            for c in 0..3 {
+                out[c] = ((u16::from(pixel[c]) * a + 127) / 255) as u8;
+            }
+            out[3] = pixel[3];
+        }
+        Ok(pixmap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pdf_writer::{Finish, Name, Pdf, Rect as PdfRect, Ref};
+
+    /// A one-page 100 × 100 pt PDF drawing `content`, with an ExtGState
+    /// `/Half` at 50% alpha
+    fn page_pdf(content: &[u8]) -> Vec<u8> {
+        let mut pdf = Pdf::new();
+        let (catalog, tree, page, stream, half) = (Ref::new(1), Ref::new(2), Ref::new(3), Ref::new(4), Ref::new(5));
+        pdf.catalog(catalog).pages(tree);
+        pdf.pages(tree).kids([page]).count(1);
+        let mut p = pdf.page(page);
+        p.parent(tree).media_box(PdfRect::new(0.0, 0.0, 100.0, 100.0)).contents(stream);
+        p.resources().ext_g_states().pair(Name(b"Half"), half);
+        p.finish();
+        pdf.stream(stream, content);
+        pdf.ext_graphics(half).non_stroking_alpha(0.5);
+        pdf.finish()
+    }
+
+    fn pixel(png: &[u8], x: u32, y: u32) -> [u8; 4] {
+        let pixmap = Pixmap::decode_png(png).unwrap();
+        let c = pixmap.pixel(x, y).unwrap().demultiply();
+        [c.red(), c.green(), c.blue(), c.alpha()]
+    }
+
+    #[test]
+    fn huge_dpi_is_an_error_not_an_allocation() {
+        let err = rasterize(&page_pdf(b""), 0, 1e6, &HashMap::new()).unwrap_err();
+        assert!(err.to_string().contains("too large to rasterize"), "{}", err);
+    }
+
+    #[test]
+    fn fills_land_in_page_coordinates() {
+        // The bottom-left quarter, in PDF's y-up coordinates
+        let png = rasterize(&page_pdf(b"1 0 0 rg 0 0 50 50 re f"), 0, 72.0, &HashMap::new()).unwrap();
+        assert_eq!(pixel(&png, 10, 90), [255, 0, 0, 255]);
+        assert_eq!(pixel(&png, 90, 10), [255, 255, 255, 255]);
+
+        let png = rasterize(&page_pdf(b""), 0, 144.0, &HashMap::new()).unwrap();
+        assert_eq!(Pixmap::decode_png(&png).unwrap().width(), 200);
+    }
+
+    #[test]
+    fn state_restores_clip_and_alpha() {
+        let content = b"q 0 0 50 100 re W n /Half gs 0 0 1 rg 0 0 100 100 re f Q 0 0 1 rg 60 0 10 10 re f";
+        let png = rasterize(&page_pdf(content), 0, 72.0, &HashMap::new()).unwrap();
+        assert_eq!(pixel(&png, 10, 50), [127, 127, 255, 255], "half blue over white inside the clip");
+        assert_eq!(pixel(&png, 80, 50), [255, 255, 255, 255], "nothing outside it");
+        assert_eq!(pixel(&png, 65, 95), [0, 0, 255, 255], "opaque and unclipped after Q");
+    }
+}
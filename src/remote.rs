@@ -0,0 +1,264 @@
+//! Fetching `{"url": ...}` font and image sources.
+//!
+//! Remote sources are opt-in twice over: the crate must be built with the
+//! `remote` feature, and each render must pass `allow_remote`. Without the
+//! render flag nothing is fetched and the loaders reject URL sources, so a
+//! document alone can never make rupdf touch the network. When allowed,
+//! every URL is downloaded before resources load and swapped for its bytes.
+
+use crate::error::{Result, RupdfError};
+use crate::types::{FontSource, ImageSource, Resources};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use pyo3::Borrowed;
+
+const DEFAULT_TIMEOUT: f32 = 10.0;
+const DEFAULT_MAX_BYTES: u64 = 20 * 1024 * 1024;
+
+/// Whether a render may fetch URL sources, and the limits on each fetch
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum RemoteAccess {
+    #[default]
+    Off,
+    On {
+        /// Seconds allowed for each request, connection to last byte
+        timeout: f32,
+        /// Largest response body accepted
+        max_bytes: u64,
+    },
+}
+
+impl<'py> FromPyObject<'_, 'py> for RemoteAccess {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        let (timeout, max_bytes) = if let Ok(dict) = ob.cast::<PyDict>() {
+            let timeout = dict.get_item("timeout")?.map(|v| v.extract()).transpose()?;
+            let max_bytes = dict.get_item("max_bytes")?.map(|v| v.extract()).transpose()?;
+            (timeout.unwrap_or(DEFAULT_TIMEOUT), max_bytes.unwrap_or(DEFAULT_MAX_BYTES))
+        } else if ob.extract::<bool>()? {
+            (DEFAULT_TIMEOUT, DEFAULT_MAX_BYTES)
+        } else {
+            return Ok(RemoteAccess::Off);
+        };
+        if !timeout.is_finite() || timeout <= 0.0 {
+            return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Invalid allow_remote timeout: {}. Must be positive",
+                timeout
+            )));
+        }
+        Ok(RemoteAccess::On { timeout, max_bytes })
+    }
+}
+
+/// Error for a URL source that reached a loader unfetched.
+pub fn not_fetched(url: &str) -> RupdfError {
+    RupdfError::ResourceError(format!(
+        "URL source '{}' was not fetched; pass allow_remote=True to fetch remote resources",
+        url
+    ))
+}
+
+/// Replace every URL source in `resources` with its downloaded bytes.
+/// Does nothing when access is off.
+pub fn fetch_sources(resources: &mut Resources, access: RemoteAccess) -> Result<()> {
+    let RemoteAccess::On { timeout, max_bytes } = access else { return Ok(()) };
+    for font in resources.fonts.values_mut() {
+        if let FontSource::Url(url) = &font.source {
+            font.source = FontSource::Bytes(fetch(url, Kind::Font, timeout, max_bytes)?);
+        }
+    }
+    for image in resources.images.values_mut() {
+        if let ImageSource::Url(url) = &image.source {
+            image.source = ImageSource::Bytes(fetch(url, Kind::Image, timeout, max_bytes)?);
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Font,
+    Image,
+}
+
+#[cfg(feature = "remote")]
+impl Kind {
+    /// Whether a response's content type (without parameters) fits. Fonts
+    /// are often served as `application/octet-stream`.
+    fn accepts(self, content_type: &str) -> bool {
+        let content_type = content_type.to_ascii_lowercase();
+        match self {
+            Kind::Image => content_type.starts_with("image/"),
+            Kind::Font => {
+                content_type.starts_with("font/")
+                    || content_type.starts_with("application/font-")
+                    || content_type.starts_with("application/x-font-")
+                    || content_type == "application/vnd.ms-opentype"
+                    || content_type == "application/octet-stream"
+            }
+        }
+    }
+
+    fn noun(self) -> &'static str {
+        match self {
+            Kind::Font => "a font",
+            Kind::Image => "an image",
+        }
+    }
+}
+
+#[cfg(feature = "remote")]
+fn fetch(url: &str, kind: Kind, timeout: f32, max_bytes: u64) -> Result<Vec<u8>> {
+    use std::io::Read;
+
+    let fail = |reason: String| RupdfError::ResourceError(format!("Failed to fetch '{}': {}", url, reason));
+    if !(url.starts_with("https://") || url.starts_with("http://")) {
+        return Err(fail("only http and https URLs are supported".to_string()));
+    }
+    let agent = ureq::AgentBuilder::new()
+        .timeout(std::time::Duration::from_secs_f32(timeout))
+        .build();
+    let response = agent.get(url).call().map_err(|e| match e {
+        ureq::Error::Status(code, response) => fail(format!("HTTP {} {}", code, response.status_text())),
+        ureq::Error::Transport(transport) => fail(transport.to_string()),
+    })?;
+
+    let content_type = response.content_type().to_string();
+    if !kind.accepts(&content_type) {
+        return Err(fail(format!(
+            "HTTP {} with content type '{}', expected {}",
+            response.status(),
+            content_type,
+            kind.noun()
+        )));
+    }
+    let too_large = || fail(format!("response is larger than max_bytes ({} bytes)", max_bytes));
+    if let Some(length) = response.header("Content-Length").and_then(|v| v.trim().parse::<u64>().ok()) {
+        if length > max_bytes {
+            return Err(too_large());
+        }
+    }
+    let mut data = Vec::new();
+    response
+        .into_reader()
+        .take(max_bytes + 1)
+        .read_to_end(&mut data)
+        .map_err(|e| fail(e.to_string()))?;
+    if data.len() as u64 > max_bytes {
+        return Err(too_large());
+    }
+    Ok(data)
+}
+
+#[cfg(not(feature = "remote"))]
+fn fetch(url: &str, _kind: Kind, _timeout: f32, _max_bytes: u64) -> Result<Vec<u8>> {
+    Err(RupdfError::ResourceError(format!(
+        "Failed to fetch '{}': rupdf was built without the 'remote' feature",
+        url
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{ImageProfile, ImageResource};
+
+    fn url_resources(url: &str) -> Resources {
+        let mut res = Resources::default();
+        res.images.insert(
+            "logo".to_string(),
+            ImageResource { source: ImageSource::Url(url.to_string()), frame: 0, profile: ImageProfile::Embed },
+        );
+        res
+    }
+
+    #[test]
+    fn off_fetches_nothing() {
+        let mut res = url_resources("http://127.0.0.1:9/logo.png");
+        fetch_sources(&mut res, RemoteAccess::Off).unwrap();
+        assert!(matches!(res.images["logo"].source, ImageSource::Url(_)));
+        let err = crate::resources::LoadedResources::load(&res).err().unwrap();
+        assert!(err.to_string().contains("pass allow_remote=True"), "{}", err);
+    }
+
+    #[cfg(feature = "remote")]
+    #[test]
+    fn content_types() {
+        assert!(Kind::Image.accepts("image/png"));
+        assert!(!Kind::Image.accepts("text/html"));
+        assert!(Kind::Font.accepts("font/woff2"));
+        assert!(Kind::Font.accepts("application/octet-stream"));
+        assert!(!Kind::Font.accepts("image/png"));
+    }
+
+    #[cfg(not(feature = "remote"))]
+    #[test]
+    fn on_without_feature_fails() {
+        let mut res = url_resources("https://example.com/logo.png");
+        let access = RemoteAccess::On { timeout: 1.0, max_bytes: 1024 };
+        let err = fetch_sources(&mut res, access).unwrap_err();
+        assert!(err.to_string().contains("without the 'remote' feature"), "{}", err);
+    }
+
+    /// Serve one canned HTTP response on a local port.
+    #[cfg(feature = "remote")]
+    fn serve(status: &str, content_type: &str, body: Vec<u8>) -> String {
+        use std::io::{Read, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/asset", listener.local_addr().unwrap());
+        let head = format!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            status,
+            content_type,
+            body.len()
+        );
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request);
+            stream.write_all(head.as_bytes()).unwrap();
+            stream.write_all(&body).unwrap();
+        });
+        url
+    }
+
+    #[cfg(feature = "remote")]
+    fn fetch_image(url: &str, max_bytes: u64) -> Result<Resources> {
+        let mut res = url_resources(url);
+        fetch_sources(&mut res, RemoteAccess::On { timeout: 5.0, max_bytes })?;
+        Ok(res)
+    }
+
+    #[cfg(feature = "remote")]
+    #[test]
+    fn fetches_into_bytes() {
+        let png = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/test-png.png")).unwrap();
+        let res = fetch_image(&serve("200 OK", "image/png", png.clone()), DEFAULT_MAX_BYTES).unwrap();
+        assert!(matches!(&res.images["logo"].source, ImageSource::Bytes(b) if *b == png));
+        assert!(crate::resources::LoadedResources::load(&res).is_ok());
+    }
+
+    #[cfg(feature = "remote")]
+    #[test]
+    fn reports_url_and_status() {
+        let url = serve("404 Not Found", "text/html", b"missing".to_vec());
+        let err = fetch_image(&url, DEFAULT_MAX_BYTES).err().unwrap();
+        assert_eq!(err.to_string(), format!("Resource error: Failed to fetch '{}': HTTP 404 Not Found", url));
+    }
+
+    #[cfg(feature = "remote")]
+    #[test]
+    fn rejects_wrong_content_type() {
+        let url = serve("200 OK", "text/html", b"<html></html>".to_vec());
+        let err = fetch_image(&url, DEFAULT_MAX_BYTES).err().unwrap();
+        assert!(err.to_string().contains("HTTP 200 with content type 'text/html', expected an image"), "{}", err);
+    }
+
+    #[cfg(feature = "remote")]
+    #[test]
+    fn enforces_max_bytes() {
+        let url = serve("200 OK", "image/png", vec![0u8; 2048]);
+        let err = fetch_image(&url, 1024).err().unwrap();
+        assert!(err.to_string().contains("larger than max_bytes (1024 bytes)"), "{}", err);
+    }
+}
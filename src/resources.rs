@@ -1,18 +1,75 @@
 use crate::error::{Result, RupdfError};
-use crate::types::{FontSource, ImageSource, Resources};
-use std::collections::HashMap;
+use crate::types::{
+    FontResource, FontSource, ImageResource, ImageSource, OutputIntent, OutputIntentKind, ProfileSource, Resources,
+};
+use crate::warnings::{Warning, Warnings};
+use crate::woff;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::io::Cursor;
+
+/// Characters measured, first one present wins, for the cap height of a
+/// font whose OS/2 table doesn't give one: Latin, digits, CJK, Devanagari
+pub const CAP_HEIGHT_GLYPHS: &str = "H0水म";
+
+/// Where a font's cap height or x-height came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricSource {
+    /// The OS/2 table's `sCapHeight` or `sxHeight`
+    Os2,
+    /// The top of this character's outline
+    Glyph(char),
+    /// A share of a larger metric, with nothing better to go on
+    Estimate,
+}
+
+impl MetricSource {
+    /// Name reported by `inspect_font`
+    pub fn name(&self) -> &'static str {
+        match self {
+            MetricSource::Os2 => "os2",
+            MetricSource::Glyph(_) => "glyph",
+            MetricSource::Estimate => "estimate",
+        }
+    }
+}
+
+/// The first of `chars` with an outline in the font, and that outline's top
+fn glyph_top(face: &ttf_parser::Face, chars: &str) -> Option<(char, i16)> {
+    chars.chars().find_map(|ch| {
+        let bbox = face.glyph_bounding_box(face.glyph_index(ch)?)?;
+        Some((ch, bbox.y_max))
+    })
+}
+
+type Face<'a> = ttf_parser::Face<'a>;
+
+self_cell::self_cell!(
+    /// A font program and its face, parsed once when the font is loaded
+    struct FontProgram {
+        owner: Vec<u8>,
+        #[covariant]
+        dependent: Face,
+    }
+);
 
 /// Loaded font data with parsed metrics
 pub struct LoadedFont {
-    pub data: Vec<u8>,
+    program: FontProgram,
     pub postscript_name: String,           // Actual PostScript name from font file
     pub units_per_em: u16,
     pub ascender: i16,
     pub descender: i16,
     pub cap_height: i16,                   // Height of capital letters
+    pub cap_height_source: MetricSource,
+    pub x_height: i16,                     // Height of lowercase letters
+    pub x_height_source: MetricSource,
     pub glyph_widths: HashMap<u16, u16>, // glyph_id -> advance width
     pub cmap: HashMap<char, u16>,         // char -> glyph_id
+    pub num_glyphs: u16,
+    pub cff: bool,                        // CFF outlines (OpenType/CFF) rather than glyf
+    pub embed: bool,                      // Write the font program into the PDF
 }
 
 impl LoadedFont {
@@ -22,26 +79,32 @@ impl LoadedFont {
                 RupdfError::InvalidFont(name.to_string(), format!("Failed to read file: {}", e))
             })?,
             FontSource::Bytes(bytes) => bytes.clone(),
+            FontSource::Url(url) => return Err(crate::remote::not_fetched(url)),
         };
+        let data = woff::to_sfnt(data)
+            .map_err(|e| RupdfError::InvalidFont(name.to_string(), e))?;
 
-        let face = ttf_parser::Face::parse(&data, 0).map_err(|e| {
+        let program = FontProgram::try_new(data, |data| Face::parse(data, 0)).map_err(|e| {
             RupdfError::InvalidFont(name.to_string(), format!("Failed to parse font: {}", e))
         })?;
+        let face = program.borrow_dependent();
 
         let units_per_em = face.units_per_em();
         let ascender = face.ascender();
         let descender = face.descender();
+        let num_glyphs = face.number_of_glyphs();
+        let cff = face.tables().cff.is_some() || face.tables().cff2.is_some();
 
-        // Get cap height from OS/2 table, or estimate from 'H' glyph, or fallback to 70% of ascender
-        let cap_height = face.capital_height()
-            .map(|h| h as i16)
-            .or_else(|| {
-                // Try to get height of 'H' glyph
-                face.glyph_index('H')
-                    .and_then(|gid| face.glyph_bounding_box(gid))
-                    .map(|bbox| bbox.y_max)
-            })
-            .unwrap_or_else(|| (ascender as f32 * 0.7) as i16);
+        // OS/2 heights where the font sets them (older tables lack them,
+        // and some fonts leave zero), otherwise measured from glyphs
+        let (cap_height, cap_height_source) = Self::measure_cap_height(face, CAP_HEIGHT_GLYPHS);
+        let (x_height, x_height_source) = match face.x_height().filter(|&h| h > 0) {
+            Some(h) => (h, MetricSource::Os2),
+            None => match glyph_top(face, "x") {
+                Some((ch, top)) => (top, MetricSource::Glyph(ch)),
+                None => ((cap_height as f32 * 0.7) as i16, MetricSource::Estimate),
+            },
+        };
 
         // Extract PostScript name from name table (name_id 6)
         let postscript_name = face
@@ -77,17 +140,41 @@ impl LoadedFont {
         }
 
         Ok(Self {
-            data,
+            program,
             postscript_name,
             units_per_em,
             ascender,
             descender,
             cap_height,
+            cap_height_source,
+            x_height,
+            x_height_source,
             glyph_widths,
             cmap,
+            num_glyphs,
+            cff,
+            embed: true,
         })
     }
 
+    /// Cap height from the OS/2 table, else the top of the first of
+    /// `glyphs` the font has, else 70% of the ascender.
+    fn measure_cap_height(face: &ttf_parser::Face, glyphs: &str) -> (i16, MetricSource) {
+        if let Some(h) = face.capital_height().filter(|&h| h > 0) {
+            return (h, MetricSource::Os2);
+        }
+        match glyph_top(face, glyphs) {
+            Some((ch, top)) => (top, MetricSource::Glyph(ch)),
+            None => ((face.ascender() as f32 * 0.7) as i16, MetricSource::Estimate),
+        }
+    }
+
+    /// Measure the cap height from `glyphs` instead of the defaults, for a
+    /// font resource's `cap_height_glyphs`. The OS/2 value still wins.
+    pub fn set_cap_height_glyphs(&mut self, glyphs: &str) {
+        (self.cap_height, self.cap_height_source) = Self::measure_cap_height(self.face(), glyphs);
+    }
+
     /// Look up the glyph id for a character. Returns None if the font's cmap
     /// does not cover it. This is the only cmap accessor; resolution policy
     /// lives in `crate::runs`.
@@ -119,61 +206,191 @@ impl LoadedFont {
     pub fn descender_pts(&self, size: f32) -> f32 {
         self.descender as f32 * size / self.units_per_em as f32
     }
+
+    /// The glyph's outline bottom and top above the baseline, in points at
+    /// the given size. None for glyphs without an outline.
+    pub fn glyph_extent_pts(&self, glyph_id: u16, size: f32) -> Option<(f32, f32)> {
+        let bbox = self.face().glyph_bounding_box(ttf_parser::GlyphId(glyph_id))?;
+        let scale = size / self.units_per_em as f32;
+        Some((bbox.y_min as f32 * scale, bbox.y_max as f32 * scale))
+    }
+
+    /// The font program, as loaded (WOFF and WOFF2 unwrapped)
+    pub fn data(&self) -> &[u8] {
+        self.program.borrow_owner()
+    }
+
+    fn face(&self) -> &Face<'_> {
+        self.program.borrow_dependent()
+    }
+
+    /// Trace the glyph's outline, in font units, into `builder`. False for
+    /// glyphs without one, such as spaces and color bitmaps.
+    pub fn outline_glyph(&self, glyph_id: u16, builder: &mut dyn ttf_parser::OutlineBuilder) -> bool {
+        self.face().outline_glyph(ttf_parser::GlyphId(glyph_id), builder).is_some()
+    }
+
+    /// Whether the glyph has a vector outline. Color bitmap fonts map
+    /// characters to glyphs that exist only as images.
+    pub fn has_outline(&self, glyph_id: u16) -> bool {
+        self.face().glyph_bounding_box(ttf_parser::GlyphId(glyph_id)).is_some()
+    }
+
+    /// The glyph's color bitmap (sbix or CBDT), if the font has one.
+    /// Only PNG bitmaps are supported.
+    pub fn raster_glyph(&self, glyph_id: u16) -> Option<RasterGlyph> {
+        let image = self.face().glyph_raster_image(ttf_parser::GlyphId(glyph_id), RASTER_GLYPH_PPEM)?;
+        if image.format != ttf_parser::RasterImageFormat::PNG || image.pixels_per_em == 0 {
+            return None;
+        }
+        let ppem = image.pixels_per_em as f32;
+        Some(RasterGlyph {
+            png: image.data.to_vec(),
+            left: image.x as f32 / ppem,
+            bottom: image.y as f32 / ppem,
+            width: image.width as f32 / ppem,
+            height: image.height as f32 / ppem,
+        })
+    }
+}
+
+/// Strike size requested from color bitmap fonts. Fonts pick their closest
+/// strike; this covers emoji up to ~38pt at 300 DPI.
+const RASTER_GLYPH_PPEM: u16 = 160;
+
+/// A color bitmap glyph with its placement in ems relative to the glyph
+/// origin on the baseline (multiply by the font size for points).
+pub struct RasterGlyph {
+    pub png: Vec<u8>,
+    pub left: f32,
+    pub bottom: f32,
+    pub width: f32,
+    pub height: f32,
 }
 
 /// Loaded image data
 pub enum LoadedImage {
+    /// SVGs keep their source rather than the parsed tree: usvg trees are
+    /// reference counted with `Rc`, which would make resources `!Send`.
+    /// `svg_tree` parses again when the image is written.
     Svg {
-        tree: usvg::Tree,
+        data: Vec<u8>,
         width: f32,
         height: f32,
     },
+    /// Undecoded file bytes, with the size read from the header
     Raster {
         data: Vec<u8>,
         width: u32,
         height: u32,
+        /// Animation frame to decode (GIF only)
+        frame: u32,
+        /// The source's own ICC profile, if it carries an RGB one that
+        /// parses (PNG and JPEG only)
+        icc: Option<Vec<u8>>,
     },
 }
 
 impl LoadedImage {
-    pub fn load(name: &str, source: &ImageSource) -> Result<Self> {
-        let data = match source {
+    pub fn load(name: &str, resource: &ImageResource) -> Result<Self> {
+        let data = match &resource.source {
             ImageSource::Path(path) => fs::read(path).map_err(|e| {
                 RupdfError::InvalidImage(name.to_string(), format!("Failed to read file: {}", e))
             })?,
             ImageSource::Bytes(bytes) => bytes.clone(),
+            ImageSource::Url(url) => return Err(crate::remote::not_fetched(url)),
+            ImageSource::Svg(source) => return Self::load_svg(name, source),
         };
 
         // Check if it's SVG by looking for XML/SVG markers
-        if Self::is_svg(&data) {
-            let tree = usvg::Tree::from_data(&data, &usvg::Options::default()).map_err(|e| {
-                RupdfError::InvalidImage(name.to_string(), format!("Failed to parse SVG: {}", e))
-            })?;
-            let size = tree.size;
-            return Ok(LoadedImage::Svg {
-                tree,
-                width: size.width() as f32,
-                height: size.height() as f32,
-            });
+        if let Some(text) = svg_text(&data).filter(|text| is_svg(text)) {
+            return Self::load_svg(name, &text);
         }
 
-        // Try to decode as raster image
-        let img = image::load_from_memory(&data).map_err(|e| {
-            RupdfError::InvalidImage(name.to_string(), format!("Failed to decode image: {}", e))
-        })?;
+        // Raster images are only probed here: the header gives the size,
+        // and the pixels are decoded once, when the image is written
+        let frame = resource.frame;
+        if image::guess_format(&data).ok() == Some(image::ImageFormat::Gif) {
+            let (width, height) = Self::probe_gif(name, &data, frame)?;
+            return Ok(LoadedImage::Raster { data, width, height, frame, icc: None });
+        }
+        if frame != 0 {
+            return Err(RupdfError::InvalidImage(
+                name.to_string(),
+                format!("frame {} requested, but only GIF images have more than one frame", frame),
+            ));
+        }
+        let raster_err = match image::io::Reader::new(Cursor::new(&data))
+            .with_guessed_format()
+            .map_err(image::ImageError::IoError)
+            .and_then(|reader| reader.into_dimensions())
+        {
+            Ok((width, height)) => {
+                let icc = embedded_icc_profile(&data);
+                return Ok(LoadedImage::Raster { data, width, height, frame, icc });
+            }
+            Err(e) => e,
+        };
 
-        Ok(LoadedImage::Raster {
+        // Markup that `is_svg` didn't recognize gets a parse as SVG before
+        // failing, and the error says why each reading failed
+        let svg_err = match svg_text(&data) {
+            Some(text) if text.trim_start().starts_with('<') => {
+                match usvg::Tree::from_data(text.trim_start().as_bytes(), &usvg::Options::default()) {
+                    Ok(_) => return Self::load_svg(name, &text),
+                    Err(e) => e.to_string(),
+                }
+            }
+            _ => "not XML text".to_string(),
+        };
+        Err(RupdfError::InvalidImage(
+            name.to_string(),
+            format!("Failed to decode image as a raster image ({}) or as SVG ({})", raster_err, svg_err),
+        ))
+    }
+
+    /// Load SVG source, sized from its root element. Leading whitespace is
+    /// dropped, as an XML declaration must come first.
+    fn load_svg(name: &str, text: &str) -> Result<Self> {
+        let data = text.trim_start().as_bytes().to_vec();
+        let size = Self::svg_tree(name, &data)?.size;
+        Ok(LoadedImage::Svg {
             data,
-            width: img.width(),
-            height: img.height(),
+            width: size.width() as f32,
+            height: size.height() as f32,
         })
     }
 
-    fn is_svg(data: &[u8]) -> bool {
-        // Check for SVG file markers
-        let s = std::str::from_utf8(data).unwrap_or("");
-        let trimmed = s.trim_start();
-        trimmed.starts_with("<?xml") || trimmed.starts_with("<svg") || trimmed.starts_with("<!DOCTYPE svg")
+    /// Size of a GIF, after checking that it has frame `frame`. Frames are
+    /// counted from their headers without decoding any pixels. Each frame
+    /// is drawn composited onto the animation's canvas, as a viewer shows
+    /// it, so every frame has the canvas size whatever its own extent.
+    fn probe_gif(name: &str, data: &[u8], frame: u32) -> Result<(u32, u32)> {
+        let decode_err = |e: gif::DecodingError| {
+            RupdfError::InvalidImage(name.to_string(), format!("Failed to decode image: {}", e))
+        };
+        let mut options = gif::DecodeOptions::new();
+        options.skip_frame_decoding(true);
+        let mut decoder = options.read_info(Cursor::new(data)).map_err(decode_err)?;
+        let size = (decoder.width() as u32, decoder.height() as u32);
+        let mut frames = 0u32;
+        while decoder.next_frame_info().map_err(decode_err)?.is_some() {
+            if frames == frame {
+                return Ok(size);
+            }
+            frames += 1;
+        }
+        Err(RupdfError::InvalidImage(
+            name.to_string(),
+            format!("frame {} is out of range; the GIF has {} frame{}", frame, frames, if frames == 1 { "" } else { "s" }),
+        ))
+    }
+
+    /// Parse SVG source into a usvg tree.
+    pub fn svg_tree(name: &str, data: &[u8]) -> Result<usvg::Tree> {
+        usvg::Tree::from_data(data, &usvg::Options::default()).map_err(|e| {
+            RupdfError::InvalidImage(name.to_string(), format!("Failed to parse SVG: {}", e))
+        })
     }
 
     /// Get the source dimensions of the image in points
@@ -187,10 +404,167 @@ impl LoadedImage {
     }
 }
 
+/// Image bytes as text: UTF-8, or UTF-16 with a byte order mark. A UTF-8
+/// byte order mark is dropped. `None` for anything else, such as raster data.
+fn svg_text(data: &[u8]) -> Option<Cow<'_, str>> {
+    let utf16 = |bytes: &[u8], from: fn([u8; 2]) -> u16| {
+        let units: Vec<u16> = bytes.chunks_exact(2).map(|pair| from([pair[0], pair[1]])).collect();
+        String::from_utf16(&units).ok().map(Cow::Owned)
+    };
+    match data {
+        [0xEF, 0xBB, 0xBF, rest @ ..] => std::str::from_utf8(rest).ok().map(Cow::Borrowed),
+        [0xFE, 0xFF, rest @ ..] => utf16(rest, u16::from_be_bytes),
+        [0xFF, 0xFE, rest @ ..] => utf16(rest, u16::from_le_bytes),
+        _ => std::str::from_utf8(data).ok().map(Cow::Borrowed),
+    }
+}
+
+/// The RGB ICC profile a PNG (`iCCP` chunk) or JPEG (`APP2` segments)
+/// carries. A profile that doesn't parse, or that describes another color
+/// space, is ignored and the image is treated as untagged.
+fn embedded_icc_profile(data: &[u8]) -> Option<Vec<u8>> {
+    let profile = match data {
+        [0x89, b'P', b'N', b'G', ..] => png_icc_profile(data)?,
+        [0xFF, 0xD8, ..] => jpeg_icc_profile(data)?,
+        _ => return None,
+    };
+    let parsed = moxcms::ColorProfile::new_from_slice(&profile).ok()?;
+    (parsed.color_space == moxcms::DataColorSpace::Rgb).then_some(profile)
+}
+
+/// The decompressed profile from a PNG's `iCCP` chunk, which must come
+/// before the image data.
+fn png_icc_profile(data: &[u8]) -> Option<Vec<u8>> {
+    let mut rest = data.get(8..)?;
+    while rest.len() >= 12 {
+        let len = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let body = rest.get(8..8 + len)?;
+        match &rest[4..8] {
+            b"iCCP" => {
+                // Profile name, NUL, compression method (0, zlib), profile
+                let name_end = body.iter().position(|&b| b == 0)?;
+                return miniz_oxide::inflate::decompress_to_vec_zlib(body.get(name_end + 2..)?).ok();
+            }
+            b"IDAT" | b"IEND" => return None,
+            _ => rest = rest.get(12 + len..)?,
+        }
+    }
+    None
+}
+
+/// The profile from a JPEG's `ICC_PROFILE` APP2 segments. One too big for
+/// a segment is split across several, each numbered from 1.
+fn jpeg_icc_profile(data: &[u8]) -> Option<Vec<u8>> {
+    let mut chunks: Vec<(u8, &[u8])> = Vec::new();
+    let mut pos = 2;
+    // Segments up to the start of scan (0xDA), after which image data runs
+    while pos + 4 <= data.len() && data[pos] == 0xFF && data[pos + 1] != 0xDA {
+        let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        let body = data.get(pos + 4..pos + 2 + len)?;
+        if data[pos + 1] == 0xE2 {
+            if let Some(&[seq, _, ref chunk @ ..]) = body.strip_prefix(b"ICC_PROFILE\0") {
+                chunks.push((seq, chunk));
+            }
+        }
+        pos += 2 + len;
+    }
+    if chunks.is_empty() {
+        return None;
+    }
+    chunks.sort_by_key(|&(seq, _)| seq);
+    Some(chunks.into_iter().flat_map(|(_, chunk)| chunk.iter().copied()).collect())
+}
+
+/// Whether `text` is an SVG document: its root element, after any XML
+/// declaration, comments, processing instructions and DOCTYPE, is `svg`
+/// (with or without a namespace prefix).
+fn is_svg(text: &str) -> bool {
+    let mut rest = text.trim_start();
+    loop {
+        let skip = if rest.starts_with("<?") {
+            rest.find("?>").map(|end| end + 2)
+        } else if rest.starts_with("<!--") {
+            rest.find("-->").map(|end| end + 3)
+        } else if rest.starts_with("<!DOCTYPE") {
+            // The internal subset in [...] may hold '>' of its own
+            let mut depth = 0;
+            rest.char_indices().find_map(|(i, ch)| match ch {
+                '[' => { depth += 1; None }
+                ']' => { depth -= 1; None }
+                '>' if depth == 0 => Some(i + 1),
+                _ => None,
+            })
+        } else {
+            break;
+        };
+        match skip {
+            Some(end) => rest = rest[end..].trim_start(),
+            None => return false,
+        }
+    }
+    let Some(tag) = rest.strip_prefix('<') else { return false };
+    let name = tag.split(|c: char| c.is_whitespace() || c == '>' || c == '/').next().unwrap_or("");
+    name == "svg" || name.rsplit_once(':').is_some_and(|(_, local)| local == "svg")
+}
+
+/// A loaded output intent ICC profile
+pub struct IccProfile {
+    pub data: Vec<u8>,
+    /// Color components (`/N`): 1 for gray, 3 for RGB, 4 for CMYK
+    pub components: i32,
+}
+
+impl IccProfile {
+    pub fn load(intent: &OutputIntent) -> Result<Self> {
+        let data = match &intent.profile {
+            ProfileSource::Path(path) => fs::read(path).map_err(|e| {
+                RupdfError::ResourceError(format!("Failed to read ICC profile '{}': {}", path, e))
+            })?,
+            ProfileSource::Bytes(bytes) => bytes.clone(),
+        };
+
+        // 128-byte header: data color space at 16, 'acsp' signature at 36
+        if data.len() < 128 || &data[36..40] != b"acsp" {
+            return Err(RupdfError::ResourceError(
+                "output_intent profile is not an ICC profile".to_string(),
+            ));
+        }
+        let space = String::from_utf8_lossy(&data[16..20]).trim_end().to_string();
+        let components = match space.as_str() {
+            "GRAY" => 1,
+            "RGB" => 3,
+            "CMYK" => 4,
+            _ => {
+                return Err(RupdfError::ResourceError(format!(
+                    "ICC profile color space '{}' is not supported; use a GRAY, RGB or CMYK profile",
+                    space
+                )));
+            }
+        };
+        if intent.tag_images && components != 3 {
+            return Err(RupdfError::ResourceError(format!(
+                "output_intent tag_images needs an RGB profile, not {}",
+                space
+            )));
+        }
+        Ok(Self { data, components })
+    }
+}
+
+/// Resources `LoadedResources::load_each` couldn't load, by name
+#[derive(Default)]
+pub struct LoadFailures {
+    pub fonts: HashMap<String, RupdfError>,
+    pub images: HashMap<String, RupdfError>,
+}
+
 /// All loaded resources for rendering
 pub struct LoadedResources {
     pub fonts: HashMap<String, LoadedFont>,
     pub images: HashMap<String, LoadedImage>,
+    pub output_profile: Option<IccProfile>,
+    /// See `Resources::inherited_fonts`
+    pub inherited_fonts: HashSet<String>,
 }
 
 impl LoadedResources {
@@ -198,21 +572,100 @@ impl LoadedResources {
         let mut fonts = HashMap::new();
         let mut images = HashMap::new();
 
-        for (name, source) in &resources.fonts {
-            fonts.insert(name.clone(), LoadedFont::load(name, source)?);
+        for (name, resource) in &resources.fonts {
+            fonts.insert(name.clone(), Self::load_font(name, resource)?);
         }
 
-        for (name, source) in &resources.images {
-            images.insert(name.clone(), LoadedImage::load(name, source)?);
+        for (name, resource) in &resources.images {
+            images.insert(name.clone(), LoadedImage::load(name, resource)?);
         }
 
-        Ok(Self { fonts, images })
+        let output_profile = resources.output_intent.as_ref().map(IccProfile::load).transpose()?;
+
+        Ok(Self { fonts, images, output_profile, inherited_fonts: resources.inherited_fonts.clone() })
+    }
+
+    /// Load every font and image as `load` does, carrying on past the ones
+    /// that fail. Returns those that loaded, and the error of each that
+    /// didn't, by name. The output intent is left unloaded.
+    pub fn load_each(resources: &Resources) -> (Self, LoadFailures) {
+        let mut loaded = Self {
+            fonts: HashMap::new(),
+            images: HashMap::new(),
+            output_profile: None,
+            inherited_fonts: resources.inherited_fonts.clone(),
+        };
+        let mut failures = LoadFailures::default();
+        for (name, resource) in &resources.fonts {
+            match Self::load_font(name, resource) {
+                Ok(font) => {
+                    loaded.fonts.insert(name.clone(), font);
+                }
+                Err(e) => {
+                    failures.fonts.insert(name.clone(), e);
+                }
+            }
+        }
+        for (name, resource) in &resources.images {
+            match LoadedImage::load(name, resource) {
+                Ok(image) => {
+                    loaded.images.insert(name.clone(), image);
+                }
+                Err(e) => {
+                    failures.images.insert(name.clone(), e);
+                }
+            }
+        }
+        (loaded, failures)
+    }
+
+    pub fn load_font(name: &str, resource: &FontResource) -> Result<LoadedFont> {
+        let mut font = LoadedFont::load(name, &resource.source)?;
+        font.embed = resource.embed;
+        if let Some(glyphs) = &resource.cap_height_glyphs {
+            font.set_cap_height_glyphs(glyphs);
+        }
+        Ok(font)
+    }
+
+    /// Warn once per font declared with `embed: false`, since the output
+    /// then depends on the fonts installed wherever it is opened. PDF/A
+    /// requires every font embedded, so with a PDF/A output intent such a
+    /// font is an error instead.
+    pub fn check_unembedded_fonts(&self, intent: Option<&OutputIntent>, warnings: &mut Warnings) -> Result<()> {
+        let mut names: Vec<&String> = self.fonts.iter()
+            .filter(|(_, font)| !font.embed)
+            .map(|(name, _)| name)
+            .collect();
+        names.sort();
+        if let (Some(name), Some(OutputIntent { kind: OutputIntentKind::PdfA, .. })) = (names.first(), intent) {
+            return Err(RupdfError::ResourceError(format!(
+                "font '{}' has embed: false, but a PDF/A output_intent requires every font to be embedded",
+                name
+            )));
+        }
+        for name in names {
+            warnings.push(Warning {
+                code: "font_not_embedded",
+                page: None,
+                element: None,
+                id: None,
+                message: format!(
+                    "font '{}' ({}) is not embedded; it renders correctly only where that font is installed",
+                    name, self.fonts[name].postscript_name
+                ),
+            });
+        }
+        Ok(())
     }
 
     pub fn get_font(&self, name: &str) -> Result<&LoadedFont> {
         self.fonts
             .get(name)
-            .ok_or_else(|| RupdfError::MissingFont(name.to_string()))
+            .ok_or_else(|| match self.inherited_fonts.contains(name) {
+                true => RupdfError::MissingDefaultFont(name.to_string()),
+                false => RupdfError::MissingFont(name.to_string()),
+            })
     }
 
     pub fn get_image(&self, name: &str) -> Result<&LoadedImage> {
@@ -221,3 +674,137 @@ impl LoadedResources {
             .ok_or_else(|| RupdfError::MissingImage(name.to_string()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ImageProfile;
+
+    const SVG: &str = r#"<svg xmlns="http://www.w3.org/2000/svg" width="40" height="20"><rect width="40" height="20"/></svg>"#;
+
+    fn asset_font(file: &str, cap_height_glyphs: Option<&str>) -> LoadedFont {
+        let resource = FontResource {
+            source: FontSource::Path(format!("{}/assets/{}", env!("CARGO_MANIFEST_DIR"), file)),
+            embed: true,
+            cap_height_glyphs: cap_height_glyphs.map(str::to_string),
+        };
+        LoadedResources::load_font(file, &resource).unwrap()
+    }
+
+    #[test]
+    fn heights_prefer_the_os2_table() {
+        let font = asset_font("IBMPlexSans-Regular.otf", Some("水"));
+        assert_eq!((font.cap_height, font.cap_height_source), (698, MetricSource::Os2));
+        assert_eq!((font.x_height, font.x_height_source), (516, MetricSource::Os2));
+    }
+
+    #[test]
+    fn cjk_cap_height_is_measured_from_an_ideograph() {
+        // No OS/2 table and no Latin: the ideograph's top, not 70% of the
+        // ascender; x-height has nothing to measure
+        let font = asset_font("TestCJK.ttf", None);
+        assert_eq!((font.cap_height, font.cap_height_source), (840, MetricSource::Glyph('水')));
+        assert_eq!(font.x_height_source, MetricSource::Estimate);
+
+        let font = asset_font("TestCJK.ttf", Some("H字"));
+        assert_eq!((font.cap_height, font.cap_height_source), (830, MetricSource::Glyph('字')));
+        let font = asset_font("TestCJK.ttf", Some("H"));
+        assert_eq!((font.cap_height, font.cap_height_source), (616, MetricSource::Estimate));
+    }
+
+    #[test]
+    fn missing_inherited_font_names_defaults() {
+        let resources = Resources { inherited_fonts: HashSet::from(["body".to_string()]), ..Default::default() };
+        let resources = LoadedResources::load(&resources).unwrap();
+        let err = resources.get_font("body").err().unwrap();
+        assert_eq!(err.to_string(), "Missing font: 'body' (from defaults)");
+        let err = resources.get_font("heading").err().unwrap();
+        assert_eq!(err.to_string(), "Missing font: 'heading'");
+    }
+
+    #[test]
+    fn pdfa_output_intent_rejects_unembedded_fonts() {
+        let mut font = asset_font("IBMPlexSans-Regular.otf", None);
+        font.embed = false;
+        let resources = LoadedResources {
+            fonts: HashMap::from([("body".to_string(), font)]),
+            images: HashMap::new(),
+            output_profile: None,
+            inherited_fonts: HashSet::new(),
+        };
+        let intent = |kind| OutputIntent {
+            profile: ProfileSource::Bytes(Vec::new()),
+            condition: "sRGB".to_string(),
+            info: None,
+            kind,
+            tag_images: false,
+        };
+        let mut warnings = Warnings::default();
+        let err = resources.check_unembedded_fonts(Some(&intent(OutputIntentKind::PdfA)), &mut warnings).unwrap_err();
+        assert!(err.to_string().contains("font 'body' has embed: false"), "{}", err);
+
+        // Only a warning otherwise
+        resources.check_unembedded_fonts(None, &mut warnings).unwrap();
+        resources.check_unembedded_fonts(Some(&intent(OutputIntentKind::PdfX)), &mut warnings).unwrap();
+        assert_eq!(warnings.iter().filter(|w| w.code == "font_not_embedded").count(), 2);
+    }
+
+    #[test]
+    fn svg_is_found_past_prologs() {
+        assert!(is_svg(SVG));
+        assert!(is_svg(&format!("\n  <?xml version=\"1.0\"?>\n<!-- chart -->\n{}", SVG)));
+        let doctype = r#"<!DOCTYPE svg PUBLIC "-//W3C//DTD SVG 1.1//EN" [<!ENTITY a "<b>">]>"#;
+        assert!(is_svg(&format!("<?xml version=\"1.0\"?>{}{}", doctype, SVG)));
+        assert!(is_svg("<svg:svg xmlns:svg=\"http://www.w3.org/2000/svg\"/>"));
+        assert!(!is_svg("<?xml version=\"1.0\"?><html/>"));
+        assert!(!is_svg("<!-- unterminated <svg>"));
+        assert!(!is_svg("<svgx/>"));
+    }
+
+    #[test]
+    fn svg_text_drops_byte_order_marks() {
+        let utf8 = [&[0xEF, 0xBB, 0xBF][..], SVG.as_bytes()].concat();
+        assert_eq!(svg_text(&utf8).as_deref(), Some(SVG));
+        let utf16: Vec<u8> = [0xFF, 0xFE].into_iter().chain(SVG.encode_utf16().flat_map(u16::to_le_bytes)).collect();
+        assert_eq!(svg_text(&utf16).as_deref(), Some(SVG));
+        assert_eq!(svg_text(&[0x89, b'P', b'N', b'G', 0xFF]), None);
+    }
+
+    #[test]
+    fn svg_sources_load_without_sniffing() {
+        let resource = |source| ImageResource { source, frame: 0, profile: ImageProfile::Embed };
+        let loaded = LoadedImage::load("chart", &resource(ImageSource::Svg(SVG.to_string()))).unwrap();
+        assert_eq!(loaded.dimensions(), (40.0, 20.0));
+
+        let with_bom = [&[0xEF, 0xBB, 0xBF][..], b"  <!-- chart -->", SVG.as_bytes()].concat();
+        assert!(matches!(LoadedImage::load("chart", &resource(ImageSource::Bytes(with_bom))), Ok(LoadedImage::Svg { .. })));
+
+        let err = LoadedImage::load("chart", &resource(ImageSource::Svg("<svg".to_string()))).err().unwrap();
+        assert!(err.to_string().contains("Failed to parse SVG"), "{}", err);
+        let err = LoadedImage::load("chart", &resource(ImageSource::Bytes(b"<html></html>".to_vec()))).err().unwrap();
+        assert!(err.to_string().contains("as a raster image (") && err.to_string().contains("or as SVG ("), "{}", err);
+    }
+
+    #[test]
+    fn icc_profiles_are_read_from_png_and_jpeg() {
+        let png = fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/test-p3.png")).unwrap();
+        let profile = embedded_icc_profile(&png).unwrap();
+        assert_eq!(moxcms::ColorProfile::new_from_slice(&profile).unwrap().color_space, moxcms::DataColorSpace::Rgb);
+        let loaded = LoadedImage::load("p3", &ImageResource { source: ImageSource::Bytes(png), frame: 0, profile: ImageProfile::Embed }).unwrap();
+        assert!(matches!(loaded, LoadedImage::Raster { icc: Some(_), .. }));
+
+        // A JPEG profile split across two APP2 segments, out of order
+        let app2 = |seq: u8, chunk: &[u8]| {
+            let body = [&b"ICC_PROFILE\0"[..], &[seq, 2], chunk].concat();
+            [&[0xFF, 0xE2][..], &((body.len() + 2) as u16).to_be_bytes(), &body].concat()
+        };
+        let (first, second) = profile.split_at(100);
+        let jpeg = [&[0xFF, 0xD8][..], &app2(2, second), &app2(1, first), &[0xFF, 0xDA, 0, 2]].concat();
+        assert_eq!(embedded_icc_profile(&jpeg), Some(profile));
+
+        // Only RGB profiles are used; the pixels are decoded to RGB
+        let gray = moxcms::ColorProfile::new_gray_with_gamma(2.2).encode().unwrap();
+        let jpeg = [&[0xFF, 0xD8][..], &app2(1, &gray), &[0xFF, 0xDA, 0, 2]].concat();
+        assert_eq!(embedded_icc_profile(&jpeg), None);
+    }
+}
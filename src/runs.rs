@@ -12,6 +12,16 @@
 //! contiguous same-font chars into render runs (for `Tf`+`Tj` emission) and
 //! sums per-char advances for measurement.
 //!
+//! Emoji get one extra step. When an emoji-range character resolves to a
+//! glyph with no outline (or to nothing), it is drawn from a color bitmap
+//! (`sbix`/`CBDT`) if one is available, substituted with the configured
+//! replacement text, or treated as missing. See `EmojiFallback`.
+//!
+//! Variation selectors pick a presentation of the character before them,
+//! which without shaping is always its plain glyph, so they emit nothing.
+//! Text arrives NFC-normalized from parsing (see `nfc`), so a decomposed
+//! `e` + U+0301 uses the font's precomposed `é`.
+//!
 //! Forward-compatibility: a future styled-runs API will call `resolve` per
 //! user-supplied run (one resolve call per `{text, font, ...}` entry), then
 //! drive wrapping over a flattened sequence of resolved chars. The
@@ -20,6 +30,7 @@
 use crate::error::{Result, RupdfError};
 use crate::resources::LoadedFont;
 use crate::types::MissingGlyphPolicy;
+use std::ops::Range;
 
 /// A single character after font-chain resolution.
 #[derive(Debug, Clone)]
@@ -29,10 +40,20 @@ pub struct ResolvedChar {
     /// `None` for control characters (\n, \t, …), which carry structural
     /// meaning for wrapping but emit no glyph.
     pub glyph: Option<(usize, u16)>,
+    /// Draw `glyph` as its font's color bitmap rather than as text.
+    pub image: bool,
+    /// `(index, width)` for an inline image placeholder: the index into
+    /// the element's inline images and the box's width in points.
+    pub inline: Option<(usize, f32)>,
+    /// Markup style the char is set in (see `style_char`); 0 for plain.
+    pub style: usize,
 }
 
 impl ResolvedChar {
     pub fn advance_pts(&self, chain: &[&LoadedFont], size: f32) -> f32 {
+        if let Some((_, width)) = self.inline {
+            return width;
+        }
         match self.glyph {
             Some((idx, gid)) => chain[idx].advance_pts(gid, size),
             None => 0.0,
@@ -40,11 +61,107 @@ impl ResolvedChar {
     }
 }
 
-/// A contiguous run of resolved characters that share the same font.
-/// Built lazily by `group_runs`.
+/// First character of the private use range that stands in for inline
+/// images in flattened rich text: image `k` is `INLINE_IMAGE_BASE + k`.
+const INLINE_IMAGE_BASE: u32 = 0xF0000;
+
+/// The placeholder character for inline image `index`.
+pub fn inline_image_char(index: usize) -> char {
+    char::from_u32(INLINE_IMAGE_BASE + index as u32).expect("inline image index is in the private use range")
+}
+
+/// The inline image index `ch` stands in for, if it is a placeholder.
+fn inline_image_index(ch: char) -> Option<usize> {
+    (ch as u32).checked_sub(INLINE_IMAGE_BASE).filter(|_| (ch as u32) < STYLE_BASE).map(|i| i as usize)
+}
+
+/// First character of the private use range that marks markup styles in
+/// flattened text: style `k` is `STYLE_BASE + k`, with 0 for plain text.
+const STYLE_BASE: u32 = 0xF8000;
+
+/// The character that sets style `k` for the characters after it, up to
+/// the next whitespace.
+pub fn style_char(k: usize) -> char {
+    char::from_u32(STYLE_BASE + k as u32).expect("style index is in the private use range")
+}
+
+/// The style `ch` sets, if it is a style character.
+fn style_index(ch: char) -> Option<usize> {
+    (ch as u32).checked_sub(STYLE_BASE).map(|k| k as usize)
+}
+
+pub fn is_style_char(ch: char) -> bool {
+    style_index(ch).is_some()
+}
+
+/// How emoji without a usable outline are rendered. The default has no
+/// emoji font and no replacement, so such characters follow the element's
+/// `MissingGlyphPolicy` (unless a chain font has a bitmap for them).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EmojiFallback<'a> {
+    /// Chain index of a color bitmap font consulted for emoji.
+    pub font: Option<usize>,
+    /// Text substituted for emoji that no font can draw.
+    pub replacement: Option<&'a str>,
+}
+
+impl EmojiFallback<'_> {
+    fn is_configured(&self) -> bool {
+        self.font.is_some() || self.replacement.is_some()
+    }
+}
+
+/// Whether `ch` is in the Unicode ranges used for emoji pictographs.
+pub fn is_emoji(ch: char) -> bool {
+    matches!(ch as u32,
+        0x1F000..=0x1FAFF   // Mahjong through Symbols and Pictographs Extended-A
+        | 0x2600..=0x27BF   // Miscellaneous Symbols, Dingbats
+        | 0x2300..=0x23FF   // Miscellaneous Technical (⌚, ⏰, …)
+        | 0x2B00..=0x2BFF   // Arrows and stars (⭐, ⬛, …)
+        | 0x3030 | 0x303D | 0x3297 | 0x3299
+    )
+}
+
+/// Emoji sequence components with no standalone rendering: ZWJ, variation
+/// selectors, skin tone modifiers and tag characters. Without shaping,
+/// sequences render as their base emoji.
+fn is_emoji_component(ch: char) -> bool {
+    matches!(ch as u32, 0x200D | 0xFE0E | 0xFE0F | 0x1F3FB..=0x1F3FF | 0xE0020..=0xE007F)
+}
+
+/// Variation selectors: the emoji and text presentation selectors and the
+/// standardized and ideographic variation sequences' selectors.
+pub fn is_variation_selector(ch: char) -> bool {
+    matches!(ch as u32, 0xFE00..=0xFE0F | 0xE0100..=0xE01EF)
+}
+
+/// `text` in Unicode Normalization Form C, so precomposed and decomposed
+/// input measure and draw the same.
+pub fn nfc(text: &str) -> String {
+    use unicode_normalization::UnicodeNormalization;
+    text.nfc().collect()
+}
+
+/// Find a color bitmap for `ch`: in the chain font that maps it, then in
+/// the configured emoji font.
+fn color_glyph(
+    ch: char,
+    hit: Option<(usize, u16)>,
+    chain: &[&LoadedFont],
+    emoji: &EmojiFallback,
+) -> Option<(usize, u16)> {
+    let candidates = hit.into_iter().chain(
+        emoji.font.and_then(|idx| chain[idx].glyph_id_opt(ch).map(|gid| (idx, gid))),
+    );
+    candidates.into_iter().find(|&(idx, gid)| chain[idx].raster_glyph(gid).is_some())
+}
+
+/// A contiguous run of resolved characters that share the same font and
+/// style. Built lazily by `group_runs`.
 #[derive(Debug, Clone)]
 pub struct RenderRun<'a> {
     pub chain_index: usize,
+    pub style: usize,
     pub font_alias: &'a str,
     /// (char, glyph_id) pairs. The char is kept for ToUnicode mapping.
     pub glyphs: Vec<(char, u16)>,
@@ -52,8 +169,10 @@ pub struct RenderRun<'a> {
 
 /// Resolve every character in `text` against the font chain.
 ///
-/// Control characters (`is_control()`) pass through with `glyph: None`.
-/// Characters with no covering font:
+/// Control characters (`is_control()`) and variation selectors pass
+/// through with `glyph: None`. Emoji whose glyph has no outline go through
+/// `emoji` first. Characters
+/// with no covering font:
 ///   - `Drop`: silently omitted.
 ///   - `Raise`: returns `RupdfError::MissingGlyph` naming the primary font.
 pub fn resolve(
@@ -61,24 +180,67 @@ pub fn resolve(
     chain: &[&LoadedFont],
     chain_names: &[&str],
     policy: MissingGlyphPolicy,
+    emoji: &EmojiFallback,
+) -> Result<Vec<ResolvedChar>> {
+    resolve_styled(text, chain, chain_names, &[], policy, emoji)
+}
+
+/// `resolve` for text with style characters (see `style_char`), which
+/// emit nothing themselves. `style_fonts[k - 1]` is the chain index of the
+/// font style `k` sets, if any; its characters try that font in place of
+/// the primary, then the fallbacks. The style ends at whitespace.
+pub fn resolve_styled(
+    text: &str,
+    chain: &[&LoadedFont],
+    chain_names: &[&str],
+    style_fonts: &[Option<usize>],
+    policy: MissingGlyphPolicy,
+    emoji: &EmojiFallback,
 ) -> Result<Vec<ResolvedChar>> {
     debug_assert_eq!(chain.len(), chain_names.len());
     debug_assert!(!chain.is_empty(), "resolve called with empty chain");
 
     let mut out = Vec::with_capacity(text.len());
+    let mut style = 0;
     for ch in text.chars() {
-        if ch.is_control() {
-            out.push(ResolvedChar { ch, glyph: None });
+        if let Some(k) = style_index(ch) {
+            style = k;
             continue;
         }
-        let hit = chain
-            .iter()
-            .enumerate()
-            .find_map(|(idx, font)| font.glyph_id_opt(ch).map(|gid| (idx, gid)));
+        let current = style;
+        if ch.is_whitespace() {
+            style = 0;
+        }
+        if ch.is_control() || is_variation_selector(ch) || (emoji.is_configured() && is_emoji_component(ch)) {
+            out.push(ResolvedChar { ch, glyph: None, image: false, inline: None, style: current });
+            continue;
+        }
+        let lookup = |idx: usize| chain[idx].glyph_id_opt(ch).map(|gid| (idx, gid));
+        let mut hit = match current.checked_sub(1).and_then(|k| style_fonts.get(k).copied().flatten()) {
+            Some(font) => std::iter::once(font).chain(1..chain.len()).find_map(lookup),
+            None => (0..chain.len()).find_map(lookup),
+        };
+        if is_emoji(ch) && !hit.is_some_and(|(idx, gid)| chain[idx].has_outline(gid)) {
+            if let Some(glyph) = color_glyph(ch, hit, chain, emoji) {
+                out.push(ResolvedChar { ch, glyph: Some(glyph), image: true, inline: None, style: current });
+                continue;
+            }
+            if let Some(replacement) = emoji.replacement {
+                let plain = EmojiFallback::default();
+                let substituted = resolve_styled(replacement, chain, chain_names, style_fonts, policy, &plain)?;
+                out.extend(substituted.into_iter().map(|c| ResolvedChar { style: current, ..c }));
+                continue;
+            }
+            // An empty glyph would render as a blank; treat it as missing.
+            hit = None;
+        }
         match hit {
             Some((idx, gid)) => out.push(ResolvedChar {
                 ch,
                 glyph: Some((idx, gid)),
+                image: false,
+                inline: None,
+                style: current,
             }),
             None => match policy {
                 MissingGlyphPolicy::Drop => {}
@@ -94,9 +256,45 @@ pub fn resolve(
     Ok(out)
 }
 
+/// `resolve_styled` for one word, with the placeholders of `inline`
+/// images (see `inline_image_char`) turned into boxes as wide as `inline`
+/// gives. The word's style carries on past each image.
+fn resolve_with_inline(
+    word: &str,
+    inline: &[f32],
+    chain: &[&LoadedFont],
+    chain_names: &[&str],
+    style_fonts: &[Option<usize>],
+    policy: MissingGlyphPolicy,
+    emoji: &EmojiFallback,
+) -> Result<Vec<ResolvedChar>> {
+    // Restates the style in effect ahead of a part after an image
+    let restate = |style: usize, part: &str| match style {
+        0 => part.to_string(),
+        k => format!("{}{}", style_char(k), part),
+    };
+    let mut out = Vec::new();
+    let mut rest = word;
+    let mut style = 0;
+    while let Some((at, ch, index)) = rest
+        .char_indices()
+        .find_map(|(at, ch)| inline_image_index(ch).filter(|&i| i < inline.len()).map(|i| (at, ch, i)))
+    {
+        let part = restate(style, &rest[..at]);
+        out.extend(resolve_styled(&part, chain, chain_names, style_fonts, policy, emoji)?);
+        style = part.chars().rev().find_map(style_index).unwrap_or(style);
+        out.push(ResolvedChar { ch, glyph: None, image: false, inline: Some((index, inline[index])), style });
+        rest = &rest[at + ch.len_utf8()..];
+    }
+    let part = restate(style, rest);
+    out.extend(resolve_styled(&part, chain, chain_names, style_fonts, policy, emoji)?);
+    Ok(out)
+}
+
 /// Group a resolved-char sequence into contiguous same-font render runs.
 /// Chars with `glyph: None` (control / dropped) are excluded from runs;
 /// they remain visible in the source sequence for wrapping decisions.
+/// Color glyph images are excluded too; use `segments` to place them.
 pub fn group_runs<'a>(
     chars: &[ResolvedChar],
     chain_names: &[&'a str],
@@ -104,10 +302,14 @@ pub fn group_runs<'a>(
     let mut runs: Vec<RenderRun<'a>> = Vec::new();
     for c in chars {
         let Some((idx, gid)) = c.glyph else { continue };
+        if c.image {
+            continue;
+        }
         match runs.last_mut() {
-            Some(r) if r.chain_index == idx => r.glyphs.push((c.ch, gid)),
+            Some(r) if r.chain_index == idx && joins_style(r, c) => r.glyphs.push((c.ch, gid)),
             _ => runs.push(RenderRun {
                 chain_index: idx,
+                style: c.style,
                 font_alias: chain_names[idx],
                 glyphs: vec![(c.ch, gid)],
             }),
@@ -116,6 +318,99 @@ pub fn group_runs<'a>(
     runs
 }
 
+/// Whether `c` can extend `run` as far as style goes. Whitespace has no
+/// ink, so it takes whatever style the run has.
+fn joins_style(run: &RenderRun, c: &ResolvedChar) -> bool {
+    run.style == c.style || c.ch.is_whitespace()
+}
+
+/// A piece of a rendered line at `x` points from the line start.
+#[derive(Debug, Clone)]
+pub enum Segment<'a> {
+    Text { x: f32, run: RenderRun<'a> },
+    /// A color glyph drawn as an image rather than shown as text.
+    Image { x: f32, chain_index: usize, glyph_id: u16 },
+    /// An inline image box, by index into the element's inline images.
+    Inline { x: f32, index: usize },
+}
+
+/// Split a resolved line into text runs, color glyph images and inline
+/// image boxes, each positioned by the advances before it.
+pub fn segments<'a>(
+    chars: &[ResolvedChar],
+    chain: &[&LoadedFont],
+    chain_names: &[&'a str],
+    size: f32,
+) -> Vec<Segment<'a>> {
+    let mut out: Vec<Segment<'a>> = Vec::new();
+    let mut x = 0.0;
+    for c in chars {
+        if let Some((index, width)) = c.inline {
+            out.push(Segment::Inline { x, index });
+            x += width;
+            continue;
+        }
+        let Some((idx, gid)) = c.glyph else { continue };
+        if c.image {
+            out.push(Segment::Image { x, chain_index: idx, glyph_id: gid });
+        } else {
+            match out.last_mut() {
+                Some(Segment::Text { run, .. }) if run.chain_index == idx && joins_style(run, c) => {
+                    run.glyphs.push((c.ch, gid))
+                }
+                _ => out.push(Segment::Text {
+                    x,
+                    run: RenderRun {
+                        chain_index: idx,
+                        style: c.style,
+                        font_alias: chain_names[idx],
+                        glyphs: vec![(c.ch, gid)],
+                    },
+                }),
+            }
+        }
+        x += c.advance_pts(chain, size);
+    }
+    out
+}
+
+/// Characters of `text` that `font` alone can't draw, each listed once in
+/// order of first use. Mirrors `resolve` with no fallback or emoji font:
+/// control characters and variation selectors are skipped, and emoji with neither an outline nor a
+/// color bitmap count as missing.
+pub fn missing(text: &str, font: &LoadedFont) -> Vec<char> {
+    let mut out = Vec::new();
+    for ch in text.chars() {
+        if ch.is_control() || is_variation_selector(ch) || out.contains(&ch) {
+            continue;
+        }
+        let drawable = match font.glyph_id_opt(ch) {
+            Some(gid) => !is_emoji(ch) || font.has_outline(gid) || font.raster_glyph(gid).is_some(),
+            None => false,
+        };
+        if !drawable {
+            out.push(ch);
+        }
+    }
+    out
+}
+
+/// Resolve each `(text, size)` piece of a line, as from
+/// `TextElement::pieces`, against the font chain.
+pub fn resolve_pieces(
+    pieces: &[(String, f32)],
+    chain: &[&LoadedFont],
+    chain_names: &[&str],
+    style_fonts: &[Option<usize>],
+    policy: MissingGlyphPolicy,
+    emoji: &EmojiFallback,
+) -> Result<Vec<(Vec<ResolvedChar>, f32)>> {
+    pieces
+        .iter()
+        .map(|(text, size)| Ok((resolve_styled(text, chain, chain_names, style_fonts, policy, emoji)?, *size)))
+        .collect()
+}
+
 /// Sum the advance widths (in points) of a resolved-char slice.
 pub fn measure(chars: &[ResolvedChar], chain: &[&LoadedFont], size: f32) -> f32 {
     chars.iter().map(|c| c.advance_pts(chain, size)).sum()
@@ -124,21 +419,27 @@ pub fn measure(chars: &[ResolvedChar], chain: &[&LoadedFont], size: f32) -> f32
 /// Word-wrap `text` against the font chain to lines that fit within
 /// `max_width` points. Splits paragraphs on '\n' and words on whitespace
 /// (any run of whitespace collapses to a single space — matches the
-/// previous single-font wrap behavior). Returns one `Vec<ResolvedChar>`
-/// per output line.
+/// previous single-font wrap behavior). Inline image placeholders take
+/// the widths in `inline` and wrap with the characters around them.
+/// Words carry their own style characters (see `resolve_styled`).
+/// Returns one `Vec<ResolvedChar>` per output line.
+#[allow(clippy::too_many_arguments)]
 pub fn wrap(
     text: &str,
+    inline: &[f32],
     chain: &[&LoadedFont],
     chain_names: &[&str],
+    style_fonts: &[Option<usize>],
     size: f32,
     max_width: f32,
     policy: MissingGlyphPolicy,
+    emoji: &EmojiFallback,
 ) -> Result<Vec<Vec<ResolvedChar>>> {
     // Pre-resolve a single space for inter-word spacing. Space is in every
     // reasonable font's cmap, but if all fonts in the chain somehow lack
     // it, fall back to zero-width (the policy applies only to the input
     // string's chars, not to internal spacing).
-    let space_chars = resolve(" ", chain, chain_names, MissingGlyphPolicy::Drop)?;
+    let space_chars = resolve(" ", chain, chain_names, MissingGlyphPolicy::Drop, &EmojiFallback::default())?;
     let space_width = measure(&space_chars, chain, size);
 
     let mut lines: Vec<Vec<ResolvedChar>> = Vec::new();
@@ -159,7 +460,7 @@ pub fn wrap(
         let mut current_width = 0.0;
 
         for word in words {
-            let word_chars = resolve(word, chain, chain_names, policy)?;
+            let word_chars = resolve_with_inline(word, inline, chain, chain_names, style_fonts, policy, emoji)?;
             let word_width = measure(&word_chars, chain, size);
 
             if current_line.is_empty() {
@@ -184,6 +485,27 @@ pub fn wrap(
     Ok(lines)
 }
 
+/// Split `num_lines` wrapped lines across `columns` columns, returning the
+/// line range for each column (trailing ranges may be empty).
+///
+/// Without `balance`, each column takes up to `capacity` lines before the
+/// next one starts and the last column takes whatever remains. With
+/// `balance`, lines are spread so column heights differ by at most one.
+pub fn column_ranges(num_lines: usize, columns: usize, capacity: usize, balance: bool) -> Vec<Range<usize>> {
+    let per_column = if balance {
+        num_lines.div_ceil(columns)
+    } else {
+        capacity.max(1)
+    };
+    (0..columns)
+        .map(|c| {
+            let start = (c * per_column).min(num_lines);
+            let end = if c + 1 == columns { num_lines } else { (start + per_column).min(num_lines) };
+            start..end
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -204,7 +526,7 @@ mod tests {
         let font = load_sans();
         let chain = vec![&font];
         let names = vec!["sans"];
-        let chars = resolve("Hello", &chain, &names, MissingGlyphPolicy::Drop).unwrap();
+        let chars = resolve("Hello", &chain, &names, MissingGlyphPolicy::Drop, &EmojiFallback::default()).unwrap();
         assert_eq!(chars.len(), 5);
         for c in &chars {
             assert!(c.glyph.is_some());
@@ -218,7 +540,7 @@ mod tests {
         let chain = vec![&font];
         let names = vec!["sans"];
         // ❤ U+2764 is not in IBM Plex Sans.
-        let chars = resolve("A❤B", &chain, &names, MissingGlyphPolicy::Drop).unwrap();
+        let chars = resolve("A❤B", &chain, &names, MissingGlyphPolicy::Drop, &EmojiFallback::default()).unwrap();
         // Heart dropped, A and B kept.
         assert_eq!(chars.len(), 2);
         assert_eq!(chars[0].ch, 'A');
@@ -230,7 +552,7 @@ mod tests {
         let font = load_sans();
         let chain = vec![&font];
         let names = vec!["sans"];
-        let err = resolve("A❤B", &chain, &names, MissingGlyphPolicy::Raise).unwrap_err();
+        let err = resolve("A❤B", &chain, &names, MissingGlyphPolicy::Raise, &EmojiFallback::default()).unwrap_err();
         match err {
             RupdfError::MissingGlyph { glyph, font } => {
                 assert_eq!(glyph, '❤');
@@ -245,18 +567,157 @@ mod tests {
         let font = load_sans();
         let chain = vec![&font];
         let names = vec!["sans"];
-        let chars = resolve("A\nB", &chain, &names, MissingGlyphPolicy::Drop).unwrap();
+        let chars = resolve("A\nB", &chain, &names, MissingGlyphPolicy::Drop, &EmojiFallback::default()).unwrap();
         assert_eq!(chars.len(), 3);
         assert_eq!(chars[1].ch, '\n');
         assert!(chars[1].glyph.is_none());
     }
 
+    #[test]
+    fn variation_selectors_never_go_missing() {
+        let sans = load_sans();
+        let chain = vec![&sans];
+        let names = vec!["sans"];
+        let chars = resolve("\u{2764}\u{FE0E}A\u{FE0F}", &chain, &names, MissingGlyphPolicy::Drop, &EmojiFallback::default()).unwrap();
+        assert!(chars.iter().filter(|c| is_variation_selector(c.ch)).all(|c| c.glyph.is_none()));
+        assert!(resolve("A\u{FE0F}", &chain, &names, MissingGlyphPolicy::Raise, &EmojiFallback::default()).is_ok());
+        assert!(missing("A\u{FE0E}\u{E0100}", &sans).is_empty());
+    }
+
+    #[test]
+    fn nfc_composes_decomposed_marks() {
+        assert_eq!(nfc("e\u{301}"), "\u{E9}");
+        assert_eq!(nfc("\u{E9}"), "\u{E9}");
+        // No precomposed q with acute: left as is
+        assert_eq!(nfc("q\u{301}"), "q\u{301}");
+
+        let sans = load_sans();
+        let chain = vec![&sans];
+        let names = vec!["sans"];
+        let decomposed = resolve(&nfc("Caf\u{65}\u{301}"), &chain, &names, MissingGlyphPolicy::Raise, &EmojiFallback::default()).unwrap();
+        let precomposed = resolve("Caf\u{E9}", &chain, &names, MissingGlyphPolicy::Raise, &EmojiFallback::default()).unwrap();
+        assert_eq!(measure(&decomposed, &chain, 12.0), measure(&precomposed, &chain, 12.0));
+        assert_eq!(decomposed.len(), 4);
+    }
+
+    fn load_emoji() -> LoadedFont {
+        let path = assets_dir().join("TestEmoji-sbix.ttf");
+        LoadedFont::load("emoji", &FontSource::Path(path.to_str().unwrap().to_string())).unwrap()
+    }
+
+    #[test]
+    fn emoji_resolve_to_color_bitmaps() {
+        let (sans, emoji) = (load_sans(), load_emoji());
+        let chain = vec![&sans, &emoji];
+        let names = vec!["sans", "emoji"];
+        let fallback = EmojiFallback { font: Some(1), replacement: None };
+        let chars = resolve("A\u{1F600}\u{FE0F}B", &chain, &names, MissingGlyphPolicy::Raise, &fallback).unwrap();
+        assert_eq!(chars.len(), 4);
+        assert!(chars[1].image);
+        assert_eq!(chars[1].glyph.unwrap().0, 1);
+        assert!(chars[2].glyph.is_none(), "variation selector is ignorable");
+
+        let segs = segments(&chars, &chain, &names, 10.0);
+        assert_eq!(segs.len(), 3);
+        match (&segs[1], &segs[2]) {
+            (Segment::Image { x, .. }, Segment::Text { x: after, run }) => {
+                assert!((after - x - 11.0).abs() < 1e-3, "emoji advances 1.1 em");
+                assert_eq!(run.glyphs[0].0, 'B');
+            }
+            other => panic!("unexpected segments {:?}", other),
+        }
+    }
+
+    #[test]
+    fn style_chars_pick_the_style_font_until_whitespace() {
+        let font = load_sans();
+        let bold = LoadedFont::load(
+            "bold",
+            &FontSource::Path(assets_dir().join("IBMPlexSans-Bold.otf").to_str().unwrap().to_string()),
+        )
+        .unwrap();
+        let chain = vec![&font, &bold];
+        let names = vec!["sans", "bold"];
+        // Style 1 sets the bold font, style 2 only a color
+        let style_fonts = [Some(1), None];
+        let text = format!("a{}bc d{}e", style_char(1), style_char(2));
+        let chars = resolve_styled(&text, &chain, &names, &style_fonts, MissingGlyphPolicy::Raise, &EmojiFallback::default())
+            .unwrap();
+        let got: Vec<(char, usize, usize)> = chars.iter().map(|c| (c.ch, c.glyph.unwrap().0, c.style)).collect();
+        assert_eq!(got, [('a', 0, 0), ('b', 1, 1), ('c', 1, 1), (' ', 1, 1), ('d', 0, 0), ('e', 0, 2)]);
+
+        let segs = segments(&chars, &chain, &names, 10.0);
+        let runs: Vec<(usize, usize, usize)> = segs
+            .iter()
+            .map(|s| match s {
+                Segment::Text { run, .. } => (run.chain_index, run.style, run.glyphs.len()),
+                other => panic!("unexpected segment {:?}", other),
+            })
+            .collect();
+        assert_eq!(runs, [(0, 0, 1), (1, 1, 3), (0, 0, 1), (0, 2, 1)]);
+
+        // Each wrapped word restates its style
+        let text = format!("{}ab {}cd", style_char(1), style_char(1));
+        let lines = wrap(&text, &[], &chain, &names, &style_fonts, 10.0, 1.0, MissingGlyphPolicy::Raise, &EmojiFallback::default())
+            .unwrap();
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().flatten().all(|c| c.glyph.unwrap().0 == 1));
+    }
+
+    #[test]
+    fn emoji_replacement_substitutes_text() {
+        let font = load_sans();
+        let chain = vec![&font];
+        let names = vec!["sans"];
+        let fallback = EmojiFallback { font: None, replacement: Some("?") };
+        let chars = resolve("A\u{2764}B", &chain, &names, MissingGlyphPolicy::Raise, &fallback).unwrap();
+        let text: String = chars.iter().map(|c| c.ch).collect();
+        assert_eq!(text, "A?B");
+    }
+
+    #[test]
+    fn inline_images_wrap_as_boxes() {
+        let font = load_sans();
+        let chain = vec![&font];
+        let names = vec!["sans"];
+        let text = format!("a {}b c", inline_image_char(0));
+        let word = measure(&resolve("b", &chain, &names, MissingGlyphPolicy::Raise, &EmojiFallback::default()).unwrap(), &chain, 10.0);
+        // The image and the "b" glued to it stay on one line
+        let lines = wrap(&text, &[40.0], &chain, &names, &[], 10.0, 40.0 + word, MissingGlyphPolicy::Raise, &EmojiFallback::default()).unwrap();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(measure(&lines[1], &chain, 10.0), 40.0 + word);
+
+        let segs = segments(&lines[1], &chain, &names, 10.0);
+        match (&segs[0], &segs[1]) {
+            (Segment::Inline { x, index }, Segment::Text { x: after, .. }) => {
+                assert_eq!((*x, *index), (0.0, 0));
+                assert_eq!(*after, 40.0);
+            }
+            other => panic!("unexpected segments {:?}", other),
+        }
+    }
+
+    #[test]
+    fn columns_fill_in_turn() {
+        assert_eq!(column_ranges(7, 2, 5, false), vec![0..5, 5..7]);
+        // Overflow stays in the last column, where the box clips it
+        assert_eq!(column_ranges(12, 2, 5, false), vec![0..5, 5..12]);
+        assert_eq!(column_ranges(3, 3, 5, false), vec![0..3, 3..3, 3..3]);
+    }
+
+    #[test]
+    fn balanced_columns_differ_by_at_most_one() {
+        assert_eq!(column_ranges(7, 2, 5, true), vec![0..4, 4..7]);
+        assert_eq!(column_ranges(9, 3, 2, true), vec![0..3, 3..6, 6..9]);
+        assert_eq!(column_ranges(4, 1, 2, true), vec![0..4]);
+    }
+
     #[test]
     fn group_runs_single_font() {
         let font = load_sans();
         let chain = vec![&font];
         let names = vec!["sans"];
-        let chars = resolve("Hello", &chain, &names, MissingGlyphPolicy::Drop).unwrap();
+        let chars = resolve("Hello", &chain, &names, MissingGlyphPolicy::Drop, &EmojiFallback::default()).unwrap();
         let runs = group_runs(&chars, &names);
         assert_eq!(runs.len(), 1);
         assert_eq!(runs[0].glyphs.len(), 5);
@@ -268,7 +729,7 @@ mod tests {
         let font = load_sans();
         let chain = vec![&font];
         let names = vec!["sans"];
-        let chars = resolve("A\nB", &chain, &names, MissingGlyphPolicy::Drop).unwrap();
+        let chars = resolve("A\nB", &chain, &names, MissingGlyphPolicy::Drop, &EmojiFallback::default()).unwrap();
         let runs = group_runs(&chars, &names);
         // \n produces no run; A and B fold into a single same-font run.
         assert_eq!(runs.len(), 1);
@@ -280,7 +741,7 @@ mod tests {
         let font = load_sans();
         let chain = vec![&font];
         let names = vec!["sans"];
-        let lines = wrap("hello world", &chain, &names, 12.0, 500.0, MissingGlyphPolicy::Drop).unwrap();
+        let lines = wrap("hello world", &[], &chain, &names, &[], 12.0, 500.0, MissingGlyphPolicy::Drop, &EmojiFallback::default()).unwrap();
         assert_eq!(lines.len(), 1);
     }
 
@@ -289,7 +750,7 @@ mod tests {
         let font = load_sans();
         let chain = vec![&font];
         let names = vec!["sans"];
-        let lines = wrap("hello world", &chain, &names, 12.0, 30.0, MissingGlyphPolicy::Drop).unwrap();
+        let lines = wrap("hello world", &[], &chain, &names, &[], 12.0, 30.0, MissingGlyphPolicy::Drop, &EmojiFallback::default()).unwrap();
         assert!(lines.len() >= 2);
     }
 
@@ -298,7 +759,14 @@ mod tests {
         let font = load_sans();
         let chain = vec![&font];
         let names = vec!["sans"];
-        let lines = wrap("a\nb", &chain, &names, 12.0, 500.0, MissingGlyphPolicy::Drop).unwrap();
+        let lines = wrap("a\nb", &[], &chain, &names, &[], 12.0, 500.0, MissingGlyphPolicy::Drop, &EmojiFallback::default()).unwrap();
         assert_eq!(lines.len(), 2);
     }
+
+    #[test]
+    fn missing_lists_uncovered_chars_once() {
+        let (sans, emoji) = (load_sans(), load_emoji());
+        assert_eq!(missing("Hi\u{4E2D}\n\u{4E2D}\u{1F600}", &sans), vec!['\u{4E2D}', '\u{1F600}']);
+        assert!(missing("\u{1F600}", &emoji).is_empty());
+    }
 }
@@ -0,0 +1,564 @@
+//! Numeric sanity checks on every element, run before layout.
+//!
+//! A NaN or infinite number would reach the content stream as garbage
+//! that each viewer draws differently, so it is an error naming the
+//! element and field. All of them are raised together, listed in the
+//! exception's `errors` as with `collect_errors`. A finite value outside
+//! the range that makes sense for its field, such as a negative stroke
+//! width or a corner radius wider than its rect, is clamped into range
+//! with a "clamped" warning. Gradient stops with alpha, which shadings
+//! can't draw, get a "gradient_alpha" warning, and text drawn as outlines
+//! an "outlined_text" one.
+//!
+//! Each element type lists its numbers in one arm of `check_element`.
+//! The match is exhaustive, so a new element type has to say how its
+//! numbers are checked.
+
+use crate::error::{ParseError, Result, RupdfError};
+use crate::types::*;
+use crate::warnings::{Warning, Warnings};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Check every element's numbers, clamping what can be clamped.
+pub fn check(doc: &mut Document, warnings: &mut Warnings) -> Result<()> {
+    let mut errors = Vec::new();
+    // Each fragment is checked once, at its first placement
+    let mut fragments = HashMap::new();
+    let largest = doc.pages.iter().fold((0.0, 0.0), |(w, h), page| (page.width.max(w), page.height.max(h)));
+    for (p, page) in doc.pages.iter_mut().enumerate() {
+        if let PageBackground::Gradient(gradient) = &page.background {
+            if let Some(message) = translucent_stop(gradient) {
+                warnings.push(Warning {
+                    code: "gradient_alpha",
+                    page: Some(p),
+                    element: None,
+                    id: None,
+                    message: format!("background {}", message),
+                });
+            }
+        }
+        for (i, element) in page.elements.iter_mut().enumerate() {
+            let mut checker = Checker::new(Some(p), (page.width, page.height), i, element, &mut errors, warnings);
+            check_element(element, &mut checker, &mut fragments);
+        }
+    }
+    for (r, repeating) in doc.repeating.iter_mut().enumerate() {
+        let mut checker = Checker::new(None, largest, r, &repeating.element, &mut errors, warnings);
+        check_element(&mut repeating.element, &mut checker, &mut fragments);
+    }
+    // Checked as the repeating element it becomes
+    if let Some(watermark) = &mut doc.watermark {
+        let mut checker = Checker::new(None, largest, doc.repeating.len(), &watermark.element, &mut errors, warnings);
+        check_element(&mut watermark.element, &mut checker, &mut fragments);
+    }
+    // Labelled by their index in the header or footer
+    for region in [&mut doc.header, &mut doc.footer].into_iter().flatten() {
+        for (i, element) in region.elements.iter_mut().enumerate() {
+            let mut checker = Checker::new(None, largest, i, element, &mut errors, warnings);
+            check_element(element, &mut checker, &mut fragments);
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(RupdfError::Multiple(errors))
+    }
+}
+
+/// Describe the first stop of `gradient` that isn't opaque. Shadings
+/// can't carry alpha, so it is drawn opaque.
+fn translucent_stop(gradient: &Gradient) -> Option<String> {
+    let (i, stop) = gradient.stops.iter().enumerate().find(|(_, stop)| stop.color.a < 255)?;
+    Some(format!("gradient stop {} alpha {} is ignored; gradients are drawn opaque", i, stop.color.a))
+}
+
+/// Records problems with the numbers of one page or repeating element
+/// (group children and fragment contents included).
+struct Checker<'a> {
+    page: Option<usize>,
+    /// Size of the page, or of the largest page for elements drawn on
+    /// several
+    extent: (f32, f32),
+    index: usize,
+    id: Option<String>,
+    /// Type of the element whose fields are being checked
+    kind: &'static str,
+    errors: &'a mut Vec<ParseError>,
+    warnings: &'a mut Warnings,
+}
+
+impl<'a> Checker<'a> {
+    fn new(
+        page: Option<usize>,
+        extent: (f32, f32),
+        index: usize,
+        element: &Element,
+        errors: &'a mut Vec<ParseError>,
+        warnings: &'a mut Warnings,
+    ) -> Self {
+        let id = element.id().map(str::to_string);
+        Self { page, extent, index, id, kind: element.type_name(), errors, warnings }
+    }
+
+    fn label(&self) -> ElementLabel<'_> {
+        ElementLabel { index: self.index, id: self.id.as_deref() }
+    }
+
+    /// Record an error unless `value` is finite; returns whether it is.
+    fn finite(&mut self, field: &str, value: f32) -> bool {
+        if value.is_finite() {
+            return true;
+        }
+        self.error(format!("{} {} must be a finite number, got {}", self.kind, field, value));
+        false
+    }
+
+    fn error(&mut self, message: String) {
+        let location = match self.page {
+            Some(p) => format!("Page {}: Element {}", p, self.label()),
+            None => format!("Repeating element {}", self.label()),
+        };
+        self.errors.push(ParseError {
+            page: self.page,
+            element: Some(self.index),
+            id: self.id.clone(),
+            message: format!("{}: {}", location, message),
+        });
+    }
+
+    fn finite_opt(&mut self, field: &str, value: Option<f32>) {
+        if let Some(value) = value {
+            self.finite(field, value);
+        }
+    }
+
+    fn clamped(&mut self, message: String) {
+        self.warn("clamped", message);
+    }
+
+    fn warn(&mut self, code: &'static str, message: String) {
+        let message = match self.page {
+            Some(_) => message,
+            None => format!("repeating element {}: {}", self.label(), message),
+        };
+        self.warnings.push(Warning {
+            code,
+            page: self.page,
+            element: Some(self.index),
+            id: self.id.clone(),
+            message,
+        });
+    }
+
+    /// Check a width or size that can't be negative, clamping it to 0.
+    fn non_negative(&mut self, field: &str, value: &mut f32) {
+        if self.finite(field, *value) && *value < 0.0 {
+            self.clamped(format!("{} {} {} is negative; using 0", self.kind, field, value));
+            *value = 0.0;
+        }
+    }
+
+    /// Clamp an already checked `value` to `max`, described by `limit`.
+    fn at_most(&mut self, field: &str, value: &mut f32, max: f32, limit: &str) {
+        if *value > max {
+            self.clamped(format!("{} {} {} is more than {} ({}); using {}", self.kind, field, value, limit, max, max));
+            *value = max;
+        }
+    }
+
+    fn gradient(&mut self, gradient: &Gradient) {
+        match gradient.kind {
+            GradientKind::Linear { angle } => {
+                self.finite("gradient angle", angle);
+            }
+            GradientKind::Radial { center, radius } => {
+                if let Some((x, y)) = center {
+                    self.finite("gradient center x", x);
+                    self.finite("gradient center y", y);
+                }
+                if let Some(radius) = radius {
+                    self.finite("gradient radius", radius);
+                }
+            }
+        }
+        if let Some(message) = translucent_stop(gradient) {
+            self.warn("gradient_alpha", format!("{} {}", self.kind, message));
+        }
+    }
+
+    fn skew(&mut self, skew: &Skew) {
+        self.finite("skew_x", skew.x);
+        self.finite("skew_y", skew.y);
+    }
+
+    fn dash(&mut self, dash: &Option<StrokeDash>) {
+        if let Some(dash) = dash {
+            for &length in &dash.array {
+                self.finite("dash", length);
+            }
+            self.finite("dash_phase", dash.phase);
+        }
+    }
+}
+
+fn check_element(element: &mut Element, at: &mut Checker, fragments: &mut HashMap<String, Arc<Fragment>>) {
+    at.kind = element.type_name();
+    match element {
+        Element::Text(t) => {
+            at.finite("x", t.x);
+            at.finite("y", t.y);
+            at.non_negative("size", &mut t.size);
+            at.non_negative("highlight_padding", &mut t.highlight_padding);
+            at.skew(&t.skew);
+            at.finite("rotation", t.rotation);
+            if let Some(fit) = &t.fit_width {
+                at.finite("fit_width", fit.width);
+                at.finite("fit_width min", fit.min);
+                at.finite("fit_width max", fit.max);
+            }
+            if let Some(max_width) = &t.max_width {
+                at.finite("max_width", max_width.width);
+                at.finite("max_width min_size", max_width.min_size);
+            }
+            if t.outline {
+                at.warn("outlined_text", "text is drawn as glyph outlines and can't be selected or searched".into());
+            }
+        }
+        Element::TextBox(tb) => {
+            at.finite("x", tb.x);
+            at.finite("y", tb.y);
+            at.non_negative("w", &mut tb.w);
+            at.non_negative("h", &mut tb.h);
+            at.non_negative("size", &mut tb.size);
+            at.finite("line_height", tb.line_height);
+            at.non_negative("column_gap", &mut tb.column_gap);
+            for image in &mut tb.inline_images {
+                at.non_negative("inline image h", &mut image.h);
+                at.finite("inline image baseline_shift", image.baseline_shift);
+            }
+        }
+        Element::Rect(r) => {
+            at.finite("x", r.x);
+            at.finite("y", r.y);
+            let sized = at.finite("w", r.w) & at.finite("h", r.h);
+            at.non_negative("stroke", &mut r.stroke);
+            if r.corner_radius.is_uniform() {
+                let mut radius = r.corner_radius.top_left;
+                at.non_negative("corner_radius", &mut radius);
+                if sized {
+                    let half = r.w.abs().min(r.h.abs()) / 2.0;
+                    at.at_most("corner_radius", &mut radius, half, "half the shorter side");
+                }
+                r.corner_radius = radius.into();
+            } else {
+                for (corner, radius) in r.corner_radius.iter_mut() {
+                    at.non_negative(&format!("corner_radius {}", corner), radius);
+                }
+                let fitted = r.corner_radius.fit(r.w.abs(), r.h.abs());
+                if sized && r.corner_radius.iter().all(f32::is_finite) && fitted != r.corner_radius {
+                    at.clamped(format!(
+                        "rect corner_radius {} adds up to more than a side; using {}",
+                        r.corner_radius, fitted
+                    ));
+                    r.corner_radius = fitted;
+                }
+            }
+            at.skew(&r.skew);
+            at.finite("rotation", r.rotation);
+            if let RotationOrigin::Point(x, y) = r.rotation_origin {
+                at.finite("rotation_origin x", x);
+                at.finite("rotation_origin y", y);
+            }
+            at.dash(&r.dash);
+            at.finite_opt("miter_limit", r.miter_limit);
+            if let Some(gradient) = &r.gradient {
+                at.gradient(gradient);
+            }
+            if let Some(pattern) = &r.fill_pattern {
+                at.finite("fill_pattern spacing", pattern.spacing);
+                at.finite("fill_pattern line_width", pattern.line_width);
+            }
+        }
+        Element::Line(l) => {
+            at.finite("x1", l.x1);
+            at.finite("y1", l.y1);
+            at.finite("x2", l.x2);
+            at.finite("y2", l.y2);
+            at.non_negative("stroke", &mut l.stroke);
+            at.dash(&l.dash);
+            at.finite_opt("miter_limit", l.miter_limit);
+        }
+        Element::Polygon(p) => {
+            for &(x, y) in &p.points {
+                at.finite("point x", x);
+                at.finite("point y", y);
+            }
+            at.non_negative("stroke", &mut p.stroke);
+            at.finite_opt("miter_limit", p.miter_limit);
+        }
+        Element::Leader(l) => {
+            at.finite("x1", l.x1);
+            at.finite("x2", l.x2);
+            at.finite("y", l.y);
+            at.finite_opt("dot_size", l.dot_size);
+            at.finite_opt("gap", l.gap);
+            at.non_negative("size", &mut l.size);
+        }
+        Element::ProgressRing(r) => {
+            at.finite("cx", r.cx);
+            at.finite("cy", r.cy);
+            at.finite("radius", r.radius);
+            at.finite("thickness", r.thickness);
+            at.finite("value", r.value);
+            at.finite("start_angle", r.start_angle);
+        }
+        Element::Image(img) => {
+            at.finite("x", img.x);
+            at.finite("y", img.y);
+            at.finite("rotation", img.rotation);
+            if let Some(quad) = &img.quad {
+                for (x, y) in [quad.origin, quad.x_corner, quad.y_corner] {
+                    at.finite("quad", x);
+                    at.finite("quad", y);
+                }
+            }
+            if let Some(ImageClip::Rounded(radii)) = &mut img.clip {
+                for (corner, radius) in radii.iter_mut() {
+                    at.non_negative(&format!("clip {}", corner), radius);
+                }
+            }
+            if let Some(w) = &mut img.w {
+                at.non_negative("w", w);
+            }
+            if let Some(h) = &mut img.h {
+                at.non_negative("h", h);
+            }
+            at.non_negative("border_width", &mut img.border_width);
+            if let Some(fade) = &img.fade {
+                at.finite("fade start", fade.start);
+                at.finite("fade end", fade.end);
+            }
+        }
+        Element::Barcode(b) => {
+            at.finite("x", b.x);
+            at.finite("y", b.y);
+            at.non_negative("w", &mut b.w);
+            at.non_negative("h", &mut b.h);
+            at.non_negative("font_size", &mut b.font_size);
+        }
+        Element::QRCode(qr) => {
+            at.finite("x", qr.x);
+            at.finite("y", qr.y);
+            at.non_negative("size", &mut qr.size);
+            at.finite_opt("min_module_size", qr.min_module_size);
+        }
+        Element::DataMatrix(dm) => {
+            at.finite("x", dm.x);
+            at.finite("y", dm.y);
+            at.non_negative("size", &mut dm.size);
+        }
+        Element::Table(t) => {
+            at.finite("x", t.x);
+            at.finite("y", t.y);
+            for width in &mut t.column_widths {
+                at.non_negative("column_widths", width);
+            }
+            at.non_negative("size", &mut t.size);
+            at.finite("line_height", t.line_height);
+            at.non_negative("padding", &mut t.padding);
+            at.non_negative("border", &mut t.border);
+            at.finite_opt("max_height", t.max_height);
+            at.finite_opt("continued_y", t.continued_y);
+        }
+        Element::Check(c) => {
+            at.finite("x", c.x);
+            at.finite("y", c.y);
+            at.non_negative("size", &mut c.size);
+            at.non_negative("stroke", &mut c.stroke);
+            at.non_negative("mark_stroke", &mut c.mark_stroke);
+            at.non_negative("corner_radius", &mut c.corner_radius);
+            let half = c.size / 2.0;
+            at.at_most("corner_radius", &mut c.corner_radius, half, "half the size");
+        }
+        Element::Note(n) => {
+            at.finite("x", n.x);
+            at.finite("y", n.y);
+        }
+        Element::SignatureLine(s) => {
+            at.finite("x", s.x);
+            at.finite("y", s.y);
+            at.non_negative("w", &mut s.w);
+            at.non_negative("size", &mut s.size);
+            at.non_negative("stroke", &mut s.stroke);
+        }
+        Element::ImagePlaceholder(p) => {
+            at.finite("x", p.x);
+            at.finite("y", p.y);
+            at.non_negative("w", &mut p.w);
+            at.non_negative("h", &mut p.h);
+            at.non_negative("size", &mut p.size);
+        }
+        Element::Grid(g) => {
+            if at.finite("spacing", g.spacing) {
+                let (width, height) = at.extent;
+                let lines = g.line_count(width, height);
+                if lines > GridElement::MAX_LINES {
+                    at.error(format!(
+                        "grid spacing {} draws {} lines on a {} x {} page, more than {}; use a larger spacing",
+                        g.spacing, lines, width, height, GridElement::MAX_LINES
+                    ));
+                }
+            }
+            at.non_negative("stroke", &mut g.stroke);
+            at.non_negative("major_stroke", &mut g.major_stroke);
+            at.non_negative("size", &mut g.size);
+        }
+        Element::Group(g) => {
+            at.finite("x", g.x);
+            at.finite("y", g.y);
+            at.finite("rotation", g.rotation);
+            at.finite("opacity", g.opacity);
+            for child in &mut g.elements {
+                check_element(child, at, fragments);
+            }
+        }
+        Element::Fragment(f) => {
+            at.finite("x", f.x);
+            at.finite("y", f.y);
+            at.finite("scale", f.scale);
+            f.fragment = match fragments.get(&f.fragment.name) {
+                Some(checked) => checked.clone(),
+                None => {
+                    let mut elements = f.fragment.elements.clone();
+                    for child in &mut elements {
+                        check_element(child, at, fragments);
+                    }
+                    let checked = Arc::new(Fragment { name: f.fragment.name.clone(), elements });
+                    fragments.insert(checked.name.clone(), checked.clone());
+                    checked
+                }
+            };
+        }
+        // Its numbers are operands in the operator text
+        Element::RawContent(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::{Origin, Units};
+
+    fn line(y: f32, stroke: f32) -> Element {
+        Element::Line(LineElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x1: 72.0,
+            y1: y,
+            x2: 144.0,
+            y2: y,
+            stroke,
+            hairline: false,
+            color: Color::black(),
+            dash: None,
+            cap: None,
+            join: None,
+            miter_limit: None,
+        })
+    }
+
+    fn doc(elements: Vec<Element>, repeating: Vec<RepeatingElement>) -> Document {
+        Document {
+            metadata: Metadata::default(),
+            pages: vec![Page {
+                width: 612.0,
+                height: 792.0,
+                background: PageBackground::Color(Color::white()),
+                repeat: 1,
+                source: 0,
+                elements,
+            }],
+            repeating,
+            layers: Vec::new(),
+            resources: Resources::default(),
+            open_action: None,
+            origin: Origin::TopLeft,
+            units: Units::default(),
+            watermark: None,
+            header: None,
+            footer: None,
+        }
+    }
+
+    #[test]
+    fn test_negative_stroke_is_clamped_with_a_warning() {
+        let mut doc = doc(vec![line(100.0, -3.0)], Vec::new());
+        let mut warnings = Warnings::default();
+        check(&mut doc, &mut warnings).unwrap();
+
+        let Element::Line(l) = &doc.pages[0].elements[0] else { unreachable!() };
+        assert_eq!(l.stroke, 0.0);
+        let warnings: Vec<_> = warnings.iter().collect();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].code, "clamped");
+        assert_eq!(warnings[0].to_string(), "Page 0, element 0: line stroke -3 is negative; using 0");
+    }
+
+    #[test]
+    fn test_every_non_finite_number_is_reported() {
+        let footer = RepeatingElement { element: line(f32::INFINITY, 1.0), pages: PageFilter::default(), under_content: false, page_numbers: false };
+        let mut doc = doc(vec![line(100.0, 1.0), line(f32::NAN, f32::NAN)], vec![footer]);
+        let mut warnings = Warnings::default();
+        let Err(RupdfError::Multiple(errors)) = check(&mut doc, &mut warnings) else { panic!("expected errors") };
+
+        let messages: Vec<_> = errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, [
+            "Page 0: Element 1: line y1 must be a finite number, got NaN",
+            "Page 0: Element 1: line y2 must be a finite number, got NaN",
+            "Page 0: Element 1: line stroke must be a finite number, got NaN",
+            "Repeating element 0: line y1 must be a finite number, got inf",
+            "Repeating element 0: line y2 must be a finite number, got inf",
+        ]);
+        assert_eq!((errors[0].page, errors[0].element), (Some(0), Some(1)));
+        assert_eq!((errors[3].page, errors[3].element), (None, Some(0)));
+        // A NaN isn't clamped as well as reported
+        assert_eq!(warnings.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_grid_line_count_is_capped() {
+        let grid = |spacing| {
+            Element::Grid(GridElement {
+                id: None,
+                tagging: Tagging::default(),
+                z: 0,
+                visible: true,
+                spacing,
+                major_every: 5,
+                color: Color::black(),
+                stroke: 0.25,
+                major_stroke: 0.5,
+                font: None,
+                size: 6.0,
+                label_color: Color::black(),
+                unit: 1.0,
+                from_bottom: false,
+            })
+        };
+        let mut warnings = Warnings::default();
+        check(&mut doc(vec![grid(1.0)], Vec::new()), &mut warnings).unwrap();
+
+        // Sized against the largest page when repeated
+        let repeated = RepeatingElement { element: grid(0.0001), pages: PageFilter::default(), under_content: false, page_numbers: false };
+        let Err(RupdfError::Multiple(errors)) = check(&mut doc(Vec::new(), vec![repeated]), &mut warnings) else {
+            panic!("expected an error")
+        };
+        assert_eq!(
+            errors[0].message,
+            "Repeating element 0: grid spacing 0.0001 draws 14040002 lines on a 612 x 792 page, more than 10000; use a larger spacing"
+        );
+    }
+}
@@ -1,25 +1,88 @@
-use crate::error::{Result, RupdfError};
+use crate::elements::raw::{self, RawUses};
+use crate::error::{ParseError, Result, RupdfError};
+use crate::keys;
+use crate::markup;
+use crate::numfmt::{self, NegativeStyle, NumberFormat};
+use crate::units::{self, Length, Margins, Origin, PageFrame, PageLength, Units};
+use crate::warnings::{Warning, Warnings};
 use pyo3::prelude::*;
-use pyo3::types::{PyBytes, PyDict, PyList, PyTuple};
+use pyo3::types::{PyBool, PyBytes, PyDict, PyFloat, PyList, PyTuple};
 use pyo3::Borrowed;
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+use std::sync::Arc;
 
-/// RGBA color with values 0-255
-#[derive(Debug, Clone, Copy)]
+/// A named spot ink, drawn through a PDF Separation color space
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpotColor {
+    /// Colorant name as the print shop knows it, e.g. "PANTONE 2935 C"
+    pub name: String,
+    /// Ink coverage, 0.0-1.0
+    pub tint: f32,
+}
+
+/// How a color is written to the content stream
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ColorModel {
+    /// DeviceRGB from `r`, `g`, `b`
+    #[default]
+    Rgb,
+    /// DeviceCMYK components, 0.0-1.0 each
+    Cmyk([f32; 4]),
+    /// A Separation ink
+    Spot(SpotColor),
+}
+
+/// Color with RGBA values 0-255. CMYK and spot colors keep an RGB
+/// equivalent in `r`, `g`, `b` for gradients and Separation alternates;
+/// `model` decides what content streams paint with.
+#[derive(Debug, Clone, PartialEq)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
     pub b: u8,
     pub a: u8,
+    pub model: ColorModel,
 }
 
 impl Color {
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self { r, g, b, a, model: ColorModel::Rgb }
+    }
+
+    /// A CMYK color, components 0.0-1.0. The RGB equivalent is the naive
+    /// uncalibrated conversion.
+    pub fn cmyka(c: f32, m: f32, y: f32, k: f32, a: u8) -> Self {
+        let channel = |v: f32| ((1.0 - v) * (1.0 - k) * 255.0).round() as u8;
+        Self { r: channel(c), g: channel(m), b: channel(y), a, model: ColorModel::Cmyk([c, m, y, k]) }
+    }
+
+    /// The spot ink, if this is a spot color
+    pub fn spot(&self) -> Option<&SpotColor> {
+        match &self.model {
+            ColorModel::Spot(spot) => Some(spot),
+            _ => None,
+        }
+    }
+
+    /// Opaque white in any model, i.e. the bare paper
+    pub fn is_paper(&self) -> bool {
+        self.a == 255
+            && match &self.model {
+                ColorModel::Rgb => self.r == 255 && self.g == 255 && self.b == 255,
+                ColorModel::Cmyk(cmyk) => cmyk.iter().all(|&v| v == 0.0),
+                ColorModel::Spot(_) => false,
+            }
+    }
+
     pub fn black() -> Self {
-        Self { r: 0, g: 0, b: 0, a: 255 }
+        Self::rgba(0, 0, 0, 255)
     }
 
     pub fn white() -> Self {
-        Self { r: 255, g: 255, b: 255, a: 255 }
+        Self::rgba(255, 255, 255, 255)
     }
 
     /// Convert to RGB floats (0.0-1.0) for PDF
@@ -38,21 +101,144 @@ impl Color {
     }
 }
 
+fn cmyk_color(c: f32, m: f32, y: f32, k: f32, a: u8) -> PyResult<Color> {
+    if [c, m, y, k].iter().any(|v| !(0.0..=1.0).contains(v)) {
+        return Err(pyo3::exceptions::PyValueError::new_err(format!(
+            "CMYK components ({}, {}, {}, {}) must be between 0 and 1",
+            c, m, y, k
+        )));
+    }
+    Ok(Color::cmyka(c, m, y, k, a))
+}
+
+/// CSS color names accepted as colors
+const NAMED_COLORS: &[(&str, Color)] = &[
+    ("black", Color::rgba(0, 0, 0, 255)),
+    ("white", Color::rgba(255, 255, 255, 255)),
+    ("red", Color::rgba(255, 0, 0, 255)),
+    ("green", Color::rgba(0, 128, 0, 255)),
+    ("lime", Color::rgba(0, 255, 0, 255)),
+    ("blue", Color::rgba(0, 0, 255, 255)),
+    ("yellow", Color::rgba(255, 255, 0, 255)),
+    ("cyan", Color::rgba(0, 255, 255, 255)),
+    ("aqua", Color::rgba(0, 255, 255, 255)),
+    ("magenta", Color::rgba(255, 0, 255, 255)),
+    ("fuchsia", Color::rgba(255, 0, 255, 255)),
+    ("gray", Color::rgba(128, 128, 128, 255)),
+    ("grey", Color::rgba(128, 128, 128, 255)),
+    ("silver", Color::rgba(192, 192, 192, 255)),
+    ("maroon", Color::rgba(128, 0, 0, 255)),
+    ("olive", Color::rgba(128, 128, 0, 255)),
+    ("navy", Color::rgba(0, 0, 128, 255)),
+    ("purple", Color::rgba(128, 0, 128, 255)),
+    ("teal", Color::rgba(0, 128, 128, 255)),
+    ("orange", Color::rgba(255, 165, 0, 255)),
+    ("transparent", Color::rgba(0, 0, 0, 0)),
+];
+
+thread_local! {
+    /// Palette of the document being parsed, see `PaletteScope`
+    static PALETTE: RefCell<HashMap<String, Color>> = RefCell::new(HashMap::new());
+}
+
+/// Installs a document's `palette` for color strings while it parses.
+/// Colors are extracted with `FromPyObject` at every depth (gradient
+/// stops, spot alternates, backgrounds), so the palette is ambient rather
+/// than passed down; names still resolve at parse time. Dropping the
+/// scope removes it.
+pub struct PaletteScope;
+
+impl PaletteScope {
+    pub fn install(palette: HashMap<String, Color>) -> Self {
+        PALETTE.with(|p| *p.borrow_mut() = palette);
+        PaletteScope
+    }
+}
+
+impl Drop for PaletteScope {
+    fn drop(&mut self) {
+        PALETTE.with(|p| p.borrow_mut().clear());
+    }
+}
+
+/// Parse a color string: `#RRGGBB`, `#RRGGBBAA`, a palette name or a CSS
+/// color name, in that order, so a palette may redefine a CSS name.
+fn color_from_str(s: &str) -> std::result::Result<Color, String> {
+    if let Some(hex) = s.strip_prefix('#') {
+        let digits = (hex.len() == 6 || hex.len() == 8) && hex.bytes().all(|b| b.is_ascii_hexdigit());
+        if !digits {
+            return Err(format!("Invalid hex color '{}'. Must be #RRGGBB or #RRGGBBAA", s));
+        }
+        let byte = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+        let a = if hex.len() == 8 { byte(6) } else { 255 };
+        return Ok(Color::rgba(byte(0), byte(2), byte(4), a));
+    }
+    if let Some(color) = PALETTE.with(|p| p.borrow().get(s).cloned()) {
+        return Ok(color);
+    }
+    let lower = s.to_ascii_lowercase();
+    if let Some((_, color)) = NAMED_COLORS.iter().find(|(name, _)| *name == lower) {
+        return Ok(color.clone());
+    }
+    let mut names: Vec<String> = PALETTE.with(|p| p.borrow().keys().cloned().collect());
+    names.sort();
+    Err(if names.is_empty() {
+        format!("Unknown color '{}'. Must be #RRGGBB, #RRGGBBAA, a CSS color name or a palette name", s)
+    } else {
+        format!("Unknown color '{}'. Palette colors: {}", s, names.join(", "))
+    })
+}
+
 impl<'py> FromPyObject<'_, 'py> for Color {
     type Error = PyErr;
     fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        if let Ok(dict) = ob.cast::<PyDict>() {
+            if let Some((c, m, y, k)) = get_optional::<(f32, f32, f32, f32)>(&dict, "cmyk")? {
+                return cmyk_color(c, m, y, k, get_optional(&dict, "a")?.unwrap_or(255));
+            }
+            let name: String = get_required(&dict, "spot")?;
+            let alt: Color = get_required(&dict, "alt")?;
+            let tint: f32 = get_optional(&dict, "tint")?.unwrap_or(1.0);
+            if !(0.0..=1.0).contains(&tint) {
+                return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                    "Spot color '{}' tint {} is outside 0..1",
+                    name, tint
+                )));
+            }
+            return Ok(Self { model: ColorModel::Spot(SpotColor { name, tint }), ..alt });
+        }
+        if let Ok(s) = ob.extract::<String>() {
+            return color_from_str(&s).map_err(pyo3::exceptions::PyValueError::new_err);
+        }
         let tuple = ob.cast::<PyTuple>()?;
+        if tuple.len() == 3 {
+            return Ok(Self::rgba(
+                tuple.get_item(0)?.extract()?,
+                tuple.get_item(1)?.extract()?,
+                tuple.get_item(2)?.extract()?,
+                255,
+            ));
+        }
+        if tuple.len() == 5 {
+            return cmyk_color(
+                tuple.get_item(0)?.extract()?,
+                tuple.get_item(1)?.extract()?,
+                tuple.get_item(2)?.extract()?,
+                tuple.get_item(3)?.extract()?,
+                tuple.get_item(4)?.extract()?,
+            );
+        }
         if tuple.len() != 4 {
             return Err(pyo3::exceptions::PyValueError::new_err(
-                "Color must be a 4-tuple (r, g, b, a)",
+                "Color must be a 3-tuple (r, g, b), 4-tuple (r, g, b, a), 5-tuple (c, m, y, k, a) or a string",
             ));
         }
-        Ok(Self {
-            r: tuple.get_item(0)?.extract()?,
-            g: tuple.get_item(1)?.extract()?,
-            b: tuple.get_item(2)?.extract()?,
-            a: tuple.get_item(3)?.extract()?,
-        })
+        Ok(Self::rgba(
+            tuple.get_item(0)?.extract()?,
+            tuple.get_item(1)?.extract()?,
+            tuple.get_item(2)?.extract()?,
+            tuple.get_item(3)?.extract()?,
+        ))
     }
 }
 
@@ -81,6 +267,119 @@ impl<'py> FromPyObject<'_, 'py> for MissingGlyphPolicy {
     }
 }
 
+/// Color emoji handling for emoji the font chain has no outline for
+#[derive(Debug, Clone, Default)]
+pub struct EmojiOptions {
+    /// Alias of a color bitmap font (sbix/CBDT) to draw emoji from
+    pub font: Option<String>,
+    /// Text drawn in place of emoji that no font can render
+    pub replacement: Option<String>,
+}
+
+/// A named entry of the document's `styles` table, applied to spans of
+/// `markup: true` text (see `markup`)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TextStyle {
+    /// Font alias the span is set in instead of the element's font
+    pub font: Option<String>,
+    pub color: Option<Color>,
+}
+
+impl TextStyle {
+    fn from_py<'py>(dict: &Bound<'py, PyDict>) -> Result<Self> {
+        let style = Self { font: opt(dict, "font")?, color: opt(dict, "color")? };
+        if style == Self::default() {
+            return Err(RupdfError::InvalidDocument("a style needs a font or a color".to_string()));
+        }
+        Ok(style)
+    }
+}
+
+/// Weight a text element asks its `family` for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FontWeight {
+    #[default]
+    Regular,
+    Bold,
+}
+
+impl<'py> FromPyObject<'_, 'py> for FontWeight {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        let s: String = ob.extract()?;
+        match s.as_str() {
+            "regular" => Ok(FontWeight::Regular),
+            "bold" => Ok(FontWeight::Bold),
+            _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Invalid weight: '{}'. Must be 'regular' or 'bold'",
+                s
+            ))),
+        }
+    }
+}
+
+/// Slant a text element asks its `family` for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FontStyle {
+    #[default]
+    Normal,
+    Italic,
+}
+
+impl<'py> FromPyObject<'_, 'py> for FontStyle {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        let s: String = ob.extract()?;
+        match s.as_str() {
+            "normal" => Ok(FontStyle::Normal),
+            "italic" => Ok(FontStyle::Italic),
+            _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Invalid style: '{}'. Must be 'normal' or 'italic'",
+                s
+            ))),
+        }
+    }
+}
+
+/// An entry of the document's `font_families` table: the font alias of
+/// each face. Faces left out are an error only when asked for.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FontFamily {
+    pub regular: Option<String>,
+    pub bold: Option<String>,
+    pub italic: Option<String>,
+    pub bold_italic: Option<String>,
+}
+
+impl FontFamily {
+    fn from_py<'py>(dict: &Bound<'py, PyDict>) -> Result<Self> {
+        let family = Self {
+            regular: opt(dict, "regular")?,
+            bold: opt(dict, "bold")?,
+            italic: opt(dict, "italic")?,
+            bold_italic: opt(dict, "bold_italic")?,
+        };
+        if family == Self::default() {
+            return Err(RupdfError::InvalidDocument(
+                "a family needs at least one of regular, bold, italic or bold_italic".to_string(),
+            ));
+        }
+        Ok(family)
+    }
+
+    /// The alias of a face, with the face's key in `font_families` for
+    /// errors when it isn't mapped
+    pub fn face(&self, weight: FontWeight, style: FontStyle) -> (Option<&str>, &'static str) {
+        let (face, key) = match (weight, style) {
+            (FontWeight::Regular, FontStyle::Normal) => (&self.regular, "regular"),
+            (FontWeight::Bold, FontStyle::Normal) => (&self.bold, "bold"),
+            (FontWeight::Regular, FontStyle::Italic) => (&self.italic, "italic"),
+            (FontWeight::Bold, FontStyle::Italic) => (&self.bold_italic, "bold_italic"),
+        };
+        (face.as_deref(), key)
+    }
+}
+
 /// Text alignment
 #[derive(Debug, Clone, Copy, Default)]
 pub enum TextAlign {
@@ -131,6 +430,147 @@ impl<'py> FromPyObject<'_, 'py> for VerticalAnchor {
     }
 }
 
+/// Case transform applied to a text element's string when it is drawn
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum TextTransform {
+    #[default]
+    None,
+    Uppercase,
+    Lowercase,
+    Capitalize, // first letter of each word
+    SmallCaps,  // lowercase letters drawn as capitals at SMALL_CAPS_SCALE
+}
+
+/// How `fit_width` reaches its target width
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum FitMode {
+    #[default]
+    Tracking, // letter spacing (Tc)
+    Scale,    // horizontal scaling (Tz)
+}
+
+impl<'py> FromPyObject<'_, 'py> for FitMode {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        let s: String = ob.extract()?;
+        match s.as_str() {
+            "tracking" => Ok(FitMode::Tracking),
+            "scale" => Ok(FitMode::Scale),
+            _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Invalid fit mode: '{}'. Must be 'tracking' or 'scale'",
+                s
+            ))),
+        }
+    }
+}
+
+/// Stretch or squeeze single-line text to an exact width
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextFit {
+    /// Target width, in points
+    pub width: f32,
+    pub mode: FitMode,
+    /// Bounds on the fitted width as a ratio of the natural width
+    pub min: f32,
+    pub max: f32,
+}
+
+impl TextFit {
+    fn from_py<'py>(dict: &Bound<'py, PyDict>, ctx: &ParseContext<'py>) -> Result<Self> {
+        let fit = Self {
+            width: req_len(dict, "width", ctx)?,
+            mode: opt_default(dict, "mode")?,
+            min: opt_or(dict, "min", 0.5)?,
+            max: opt_or(dict, "max", 2.0)?,
+        };
+        if fit.width <= 0.0 {
+            return Err(RupdfError::InvalidDocument("fit_width width must be positive".to_string()));
+        }
+        if !(fit.min > 0.0 && fit.min <= 1.0 && fit.max >= 1.0) {
+            return Err(RupdfError::InvalidDocument(format!(
+                "fit_width needs 0 < min <= 1 <= max, got min {} and max {}",
+                fit.min, fit.max
+            )));
+        }
+        Ok(fit)
+    }
+}
+
+/// What single-line text wider than its `max_width` does
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum TextOverflow {
+    /// Drawn whole, clipped to the width
+    #[default]
+    Clip,
+    /// Trimmed to fit with a trailing "…"
+    Ellipsis,
+    /// Set smaller, down to `min_size`, then clipped
+    Shrink,
+}
+
+impl<'py> FromPyObject<'_, 'py> for TextOverflow {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        let s: String = ob.extract()?;
+        match s.as_str() {
+            "clip" => Ok(TextOverflow::Clip),
+            "ellipsis" => Ok(TextOverflow::Ellipsis),
+            "shrink" => Ok(TextOverflow::Shrink),
+            _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Invalid overflow: '{}'. Must be 'clip', 'ellipsis' or 'shrink'",
+                s
+            ))),
+        }
+    }
+}
+
+/// Widest single-line text may be, measured from its anchor as `align`
+/// places it
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaxWidth {
+    /// In points
+    pub width: f32,
+    pub overflow: TextOverflow,
+    /// Smallest size `Shrink` sets the text at, in points
+    pub min_size: f32,
+}
+
+impl<'py> FromPyObject<'_, 'py> for NegativeStyle {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        let s: String = ob.extract()?;
+        match s.as_str() {
+            "minus" => Ok(NegativeStyle::Minus),
+            "parentheses" => Ok(NegativeStyle::Parentheses),
+            _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Invalid negative: '{}'. Must be 'minus' or 'parentheses'",
+                s
+            ))),
+        }
+    }
+}
+
+/// Size of synthesized small capitals relative to the text size
+pub const SMALL_CAPS_SCALE: f32 = 0.75;
+
+impl<'py> FromPyObject<'_, 'py> for TextTransform {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        let s: String = ob.extract()?;
+        match s.as_str() {
+            "none" => Ok(TextTransform::None),
+            "uppercase" => Ok(TextTransform::Uppercase),
+            "lowercase" => Ok(TextTransform::Lowercase),
+            "capitalize" => Ok(TextTransform::Capitalize),
+            "smallcaps" | "small-caps" => Ok(TextTransform::SmallCaps),
+            _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Invalid transform: '{}'. Must be 'none', 'uppercase', 'lowercase', 'capitalize', or 'smallcaps'",
+                s
+            ))),
+        }
+    }
+}
+
 /// Box horizontal alignment (determines how x relates to box position)
 #[derive(Debug, Clone, Copy, Default, PartialEq)]
 pub enum BoxAlignX {
@@ -213,21 +653,146 @@ impl<'py> FromPyObject<'_, 'py> for TextAlignY {
 /// Text element
 #[derive(Debug, Clone)]
 pub struct TextElement {
+    pub id: Option<String>,
+    pub tagging: Tagging,
+    pub z: i32,
+    pub visible: bool,
     pub x: f32,
     pub y: f32,
     pub text: String,
     pub font: String,
     pub font_fallback: Vec<String>,
     pub missing_glyph_policy: MissingGlyphPolicy,
+    pub emoji: EmojiOptions,
     pub size: f32,
     pub color: Color,
     pub align: TextAlign,
     pub vertical_anchor: VerticalAnchor,
+    /// Fill drawn behind the text, sized from its measured extent
+    pub highlight_color: Option<Color>,
+    /// Extra space around the text inside the highlight, in points
+    pub highlight_padding: f32,
+    pub transform: TextTransform,
+    pub fit_width: Option<TextFit>,
+    pub max_width: Option<MaxWidth>,
+    /// Sheared about the text's origin on the baseline
+    pub skew: Skew,
+    /// Counter-clockwise turn in degrees about the anchor point `align`
+    /// and `vertical_anchor` pick, applied after `skew`
+    pub rotation: f32,
+    /// Filled as glyph outlines rather than shown with the font, which is
+    /// then not embedded for it; the text can't be selected or searched
+    pub outline: bool,
+    /// Styles selected by style characters in `text` (see `markup`)
+    pub styles: Vec<TextStyle>,
+}
+
+impl TextElement {
+    /// The string as drawn after `transform`, split into `(text, size)`
+    /// pieces. Only small caps yields several: runs of lowercase letters
+    /// become capitals at `SMALL_CAPS_SCALE` of the size, each piece
+    /// starting with the style character in effect.
+    pub fn pieces(&self) -> Vec<(String, f32)> {
+        let text = match self.transform {
+            TextTransform::None => self.text.clone(),
+            TextTransform::Uppercase => self.text.to_uppercase(),
+            TextTransform::Lowercase => self.text.to_lowercase(),
+            TextTransform::Capitalize => {
+                let mut word_start = true;
+                let mut out = String::with_capacity(self.text.len());
+                for ch in self.text.chars() {
+                    if crate::runs::is_style_char(ch) {
+                        out.push(ch);
+                        continue;
+                    }
+                    if word_start {
+                        out.extend(ch.to_uppercase());
+                    } else {
+                        out.push(ch);
+                    }
+                    word_start = ch.is_whitespace();
+                }
+                out
+            }
+            TextTransform::SmallCaps => {
+                let small = self.size * SMALL_CAPS_SCALE;
+                let mut pieces: Vec<(String, f32)> = Vec::new();
+                let mut style = None;
+                for ch in self.text.chars() {
+                    if crate::runs::is_style_char(ch) {
+                        style = Some(ch);
+                        if let Some((piece, _)) = pieces.last_mut() {
+                            piece.push(ch);
+                        }
+                        continue;
+                    }
+                    let size = if ch.is_lowercase() { small } else { self.size };
+                    if pieces.last().is_none_or(|(_, s)| *s != size) {
+                        pieces.push((style.map(String::from).unwrap_or_default(), size));
+                    }
+                    let piece = &mut pieces.last_mut().expect("piece pushed above").0;
+                    piece.extend(ch.to_uppercase());
+                    if ch.is_whitespace() {
+                        style = None;
+                    }
+                }
+                return pieces;
+            }
+        };
+        vec![(text, self.size)]
+    }
+}
+
+/// Line arrangement of a hatch fill
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum HatchStyle {
+    /// Parallel lines rising to the right at 45°
+    #[default]
+    Diagonal,
+    /// Lines at both 45° diagonals
+    Cross,
+    Horizontal,
+    Vertical,
+    /// Round dots on a square grid, `line_width` across
+    Dots,
+}
+
+impl<'py> FromPyObject<'_, 'py> for HatchStyle {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        let s: String = ob.extract()?;
+        match s.as_str() {
+            "diagonal" => Ok(HatchStyle::Diagonal),
+            "cross" => Ok(HatchStyle::Cross),
+            "horizontal" => Ok(HatchStyle::Horizontal),
+            "vertical" => Ok(HatchStyle::Vertical),
+            "dots" => Ok(HatchStyle::Dots),
+            _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Invalid fill_pattern style: '{}'. Must be 'diagonal', 'cross', 'horizontal', 'vertical', or 'dots'",
+                s
+            ))),
+        }
+    }
+}
+
+/// Hatch fill, drawn as a PDF tiling pattern
+#[derive(Debug, Clone, PartialEq)]
+pub struct FillPattern {
+    pub style: HatchStyle,
+    /// Distance between neighboring lines (or dot centers), in points
+    pub spacing: f32,
+    pub line_width: f32,
+    /// Pattern colors are opaque; alpha is ignored
+    pub color: Color,
 }
 
 /// Rectangle element
 #[derive(Debug, Clone)]
 pub struct RectElement {
+    pub id: Option<String>,
+    pub tagging: Tagging,
+    pub z: i32,
+    pub visible: bool,
     pub x: f32,
     pub y: f32,
     pub w: f32,
@@ -235,62 +800,1553 @@ pub struct RectElement {
     pub stroke: f32,
     pub stroke_color: Color,
     pub fill_color: Option<Color>,
-    pub corner_radius: f32,
+    /// Hatching painted over `fill_color`, if any
+    pub fill_pattern: Option<FillPattern>,
+    /// Gradient painted over `fill_color`, inside the outline
+    pub gradient: Option<Gradient>,
+    pub corner_radius: CornerRadii,
+    pub corner_style: CornerStyle,
+    /// Edges to stroke; `None` strokes the whole outline
+    pub sides: Option<RectSides>,
+    pub dash: Option<StrokeDash>,
+    /// Longest miter, as a multiple of the stroke width, before a corner
+    /// is beveled; `None` keeps the PDF default of 10
+    pub miter_limit: Option<f32>,
+    pub stroke_align: StrokeAlign,
+    /// Stroke at the device's thinnest width, as for `LineElement`
+    pub hairline: bool,
+    /// Sheared about the rect's center
+    pub skew: Skew,
+    /// Clockwise turn in degrees, applied after `skew`
+    pub rotation: f32,
+    pub rotation_origin: RotationOrigin,
+    /// Stroke end caps; `None` keeps the defaults (see `LineElement`),
+    /// with projecting caps where `sides` leaves edges open
+    pub cap: Option<LineCap>,
+    /// Stroke corner joins; `None` keeps miter joins
+    pub join: Option<LineJoin>,
 }
 
-/// Line element
-#[derive(Debug, Clone)]
-pub struct LineElement {
-    pub x1: f32,
-    pub y1: f32,
-    pub x2: f32,
-    pub y2: f32,
-    pub stroke: f32,
-    pub color: Color,
+impl RectElement {
+    /// The point `rotation` turns the rect about, in page coordinates
+    pub fn rotation_pivot(&self) -> (f32, f32) {
+        match self.rotation_origin {
+            RotationOrigin::Center => (self.x + self.w / 2.0, self.y + self.h / 2.0),
+            RotationOrigin::Point(x, y) => (self.x.min(self.x + self.w) + x, self.y.min(self.y + self.h) + y),
+        }
+    }
+
+    /// The box the stroke is centered on, as (x, y, w, h, corner_radius).
+    /// For inside and outside alignment this is the declared box moved in
+    /// or out by half the stroke, so the stroke's edge lands on the
+    /// declared bounds; the corner radii move with it.
+    pub fn stroke_box(&self) -> (f32, f32, f32, f32, CornerRadii) {
+        let shift = match self.stroke_align {
+            StrokeAlign::Center => return (self.x, self.y, self.w, self.h, self.corner_radius),
+            StrokeAlign::Inside => -self.stroke / 2.0,
+            StrokeAlign::Outside => self.stroke / 2.0,
+        };
+        let (left, top) = (self.x.min(self.x + self.w), self.y.min(self.y + self.h));
+        let w = (self.w.abs() + 2.0 * shift).max(0.0);
+        let h = (self.h.abs() + 2.0 * shift).max(0.0);
+        // Square corners stay square: an outside stroke's miter joins
+        // already meet at the outer corner
+        let radii = self.corner_radius.map(|r| if r > 0.0 { (r + shift).max(0.0) } else { 0.0 });
+        (left - shift, top - shift, w, h, radii)
+    }
+
+    /// How far the stroke reaches past the declared bounds
+    pub fn stroke_overhang(&self) -> f32 {
+        if self.stroke <= 0.0 {
+            return 0.0;
+        }
+        match self.stroke_align {
+            StrokeAlign::Center => self.stroke / 2.0,
+            StrokeAlign::Inside => 0.0,
+            StrokeAlign::Outside => self.stroke,
+        }
+    }
 }
 
-/// Image element
-#[derive(Debug, Clone)]
-pub struct ImageElement {
-    pub x: f32,
-    pub y: f32,
-    pub w: Option<f32>,  // If only w provided, scale preserving aspect ratio
-    pub h: Option<f32>,  // If only h provided, scale preserving aspect ratio
-    pub image_ref: String,
-    pub align: TextAlign,  // Horizontal alignment: left (default), center, right
+/// Where a rectangle's stroke sits relative to its declared bounds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StrokeAlign {
+    /// Straddling the bounds, half inside and half outside (PDF's own)
+    #[default]
+    Center,
+    /// Entirely within the bounds, like a CSS border
+    Inside,
+    /// Entirely outside the bounds
+    Outside,
 }
 
-/// Barcode flavour
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum BarcodeKind {
-    /// Plain Code 128 (any printable ASCII payload)
-    Code128,
-    /// GS1-128: Code 128 with FNC1 designator and Application Identifiers.
-    /// `value` is a parenthesized string like `(01)12345678901234(10)BATCH`.
-    Gs1_128,
+impl<'py> FromPyObject<'_, 'py> for StrokeAlign {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        let s: String = ob.extract()?;
+        match s.as_str() {
+            "center" => Ok(StrokeAlign::Center),
+            "inside" => Ok(StrokeAlign::Inside),
+            "outside" => Ok(StrokeAlign::Outside),
+            _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Invalid stroke_align: '{}'. Must be 'center', 'inside', or 'outside'",
+                s
+            ))),
+        }
+    }
 }
 
-/// Barcode element (Code 128 / GS1-128)
-#[derive(Debug, Clone)]
-pub struct BarcodeElement {
-    pub kind: BarcodeKind,
+/// Shear applied to an element about an origin, in degrees. Positive
+/// `x` slants the top forward, like an oblique; positive `y` raises the
+/// right side.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Skew {
     pub x: f32,
     pub y: f32,
-    pub w: f32,
-    pub h: f32,
-    pub value: String,
-    pub human_readable: bool,
-    pub font: String,
-    pub font_size: f32,
 }
 
-/// QR Code element
-#[derive(Debug, Clone)]
-pub struct QRCodeElement {
-    pub x: f32,
-    pub y: f32,
+impl Skew {
+    pub fn is_identity(&self) -> bool {
+        self.x == 0.0 && self.y == 0.0
+    }
+
+    /// PDF `cm` operands shearing about `(ox, oy)` in PDF coordinates
+    pub fn matrix(&self, ox: f32, oy: f32) -> [f32; 6] {
+        let (tx, ty) = (self.x.to_radians().tan(), self.y.to_radians().tan());
+        [1.0, ty, tx, 1.0, -tx * oy, -ty * ox]
+    }
+
+    /// Where the shear moves a point, both in top-left coordinates
+    pub fn apply(&self, (x, y): (f32, f32), (ox, oy): (f32, f32)) -> (f32, f32) {
+        let (tx, ty) = (self.x.to_radians().tan(), self.y.to_radians().tan());
+        // y grows downward here, so each shear runs opposite to PDF's
+        (x - tx * (y - oy), y - ty * (x - ox))
+    }
+}
+
+/// Point an element turns about
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum RotationOrigin {
+    #[default]
+    Center,
+    /// Measured from the element's top-left corner
+    Point(f32, f32),
+}
+
+/// PDF `cm` operands turning `degrees` clockwise (as seen on the page)
+/// about `(ox, oy)` in PDF coordinates
+pub fn rotation_matrix(degrees: f32, ox: f32, oy: f32) -> [f32; 6] {
+    // Clockwise on the page is clockwise in PDF's y-up space too, which
+    // is a negative angle there
+    let (sin, cos) = quarter_exact((-degrees).to_radians().sin_cos());
+    [cos, sin, -sin, cos, ox - cos * ox + sin * oy, oy - sin * ox - cos * oy]
+}
+
+/// Zero the float noise `sin_cos` leaves at quarter turns, so a 90° turn
+/// writes `0 -1 1 0` rather than `-0.00000004371139 -1 1 ...`
+fn quarter_exact((sin, cos): (f32, f32)) -> (f32, f32) {
+    let snap = |v: f32| if v.abs() < 1e-6 { 0.0 } else { v };
+    (snap(sin), snap(cos))
+}
+
+/// Where turning `degrees` clockwise about `origin` moves a point, both
+/// in top-left coordinates
+pub fn rotate_point(degrees: f32, (x, y): (f32, f32), (ox, oy): (f32, f32)) -> (f32, f32) {
+    let (sin, cos) = quarter_exact(degrees.to_radians().sin_cos());
+    let (dx, dy) = (x - ox, y - oy);
+    // With y growing downward, the usual formula turns clockwise
+    (ox + dx * cos - dy * sin, oy + dx * sin + dy * cos)
+}
+
+/// Shape of rounded rectangle corners
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CornerStyle {
+    /// Quarter circles of `corner_radius`
+    #[default]
+    Circular,
+    /// Superellipse-like corners that ease into the edges, as in iOS
+    Continuous,
+}
+
+impl<'py> FromPyObject<'_, 'py> for CornerStyle {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        let s: String = ob.extract()?;
+        match s.as_str() {
+            "circular" => Ok(CornerStyle::Circular),
+            "continuous" => Ok(CornerStyle::Continuous),
+            _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Invalid corner_style: '{}'. Must be 'circular' or 'continuous'",
+                s
+            ))),
+        }
+    }
+}
+
+/// A rectangle's corner radii, from `corner_radius`: one length for all
+/// four corners, or a `(top_left, top_right, bottom_right, bottom_left)`
+/// tuple
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CornerRadii {
+    pub top_left: f32,
+    pub top_right: f32,
+    pub bottom_right: f32,
+    pub bottom_left: f32,
+}
+
+impl CornerRadii {
+    pub fn is_uniform(&self) -> bool {
+        let r = self.top_left;
+        self.top_right == r && self.bottom_right == r && self.bottom_left == r
+    }
+
+    /// Whether any corner is rounded
+    pub fn any(&self) -> bool {
+        self.iter().any(|r| r > 0.0)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = f32> {
+        [self.top_left, self.top_right, self.bottom_right, self.bottom_left].into_iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&'static str, &mut f32)> {
+        [
+            ("top_left", &mut self.top_left),
+            ("top_right", &mut self.top_right),
+            ("bottom_right", &mut self.bottom_right),
+            ("bottom_left", &mut self.bottom_left),
+        ]
+        .into_iter()
+    }
+
+    pub fn map(&self, f: impl Fn(f32) -> f32) -> Self {
+        Self {
+            top_left: f(self.top_left),
+            top_right: f(self.top_right),
+            bottom_right: f(self.bottom_right),
+            bottom_left: f(self.bottom_left),
+        }
+    }
+
+    /// The radii scaled down together, as CSS does, until no two on the
+    /// same edge of a `w × h` box add up to more than its length. A single
+    /// radius is thereby held to half the shorter side.
+    pub fn fit(&self, w: f32, h: f32) -> Self {
+        let edges = [
+            (w, self.top_left + self.top_right),
+            (w, self.bottom_left + self.bottom_right),
+            (h, self.top_left + self.bottom_left),
+            (h, self.top_right + self.bottom_right),
+        ];
+        let scale = edges
+            .into_iter()
+            .filter(|&(_, sum)| sum > 0.0)
+            .fold(1.0f32, |scale, (edge, sum)| scale.min(edge / sum));
+        if scale < 1.0 { self.map(|r| r * scale) } else { *self }
+    }
+}
+
+impl From<f32> for CornerRadii {
+    fn from(r: f32) -> Self {
+        Self { top_left: r, top_right: r, bottom_right: r, bottom_left: r }
+    }
+}
+
+impl fmt::Display for CornerRadii {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_uniform() {
+            write!(f, "{}", self.top_left)
+        } else {
+            write!(f, "({}, {}, {}, {})", self.top_left, self.top_right, self.bottom_right, self.bottom_left)
+        }
+    }
+}
+
+/// The edges of a rectangle that get stroked
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RectSides {
+    pub top: bool,
+    pub right: bool,
+    pub bottom: bool,
+    pub left: bool,
+}
+
+impl<'py> FromPyObject<'_, 'py> for RectSides {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        let names: Vec<String> = ob.extract()?;
+        let mut sides = Self::default();
+        for name in names {
+            let side = match name.as_str() {
+                "top" => &mut sides.top,
+                "right" => &mut sides.right,
+                "bottom" => &mut sides.bottom,
+                "left" => &mut sides.left,
+                _ => {
+                    return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                        "Invalid side: '{}'. Must be 'top', 'right', 'bottom', or 'left'",
+                        name
+                    )));
+                }
+            };
+            *side = true;
+        }
+        Ok(sides)
+    }
+}
+
+/// What a check element draws inside its box
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CheckState {
+    #[default]
+    Unchecked,
+    Checked,
+    Crossed,
+}
+
+impl<'py> FromPyObject<'_, 'py> for CheckState {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        let s: String = ob.extract()?;
+        match s.as_str() {
+            "unchecked" => Ok(CheckState::Unchecked),
+            "checked" => Ok(CheckState::Checked),
+            "crossed" => Ok(CheckState::Crossed),
+            _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Invalid state: '{}'. Must be 'unchecked', 'checked', or 'crossed'",
+                s
+            ))),
+        }
+    }
+}
+
+/// Checkbox drawn as vector paths: a square, plus a tick or cross mark
+#[derive(Debug, Clone)]
+pub struct CheckElement {
+    pub id: Option<String>,
+    pub tagging: Tagging,
+    pub z: i32,
+    pub visible: bool,
+    pub x: f32,
+    pub y: f32,
+    pub size: f32,
+    pub state: CheckState,
+    pub stroke: f32,
+    pub stroke_color: Color,
+    pub fill_color: Option<Color>,
+    pub corner_radius: f32,
+    pub mark_color: Color,
+    /// Line width of the tick or cross
+    pub mark_stroke: f32,
+}
+
+/// Icon a viewer shows for a note
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NoteIcon {
+    #[default]
+    Note,
+    Comment,
+    Help,
+    Insert,
+    Key,
+    Paragraph,
+    NewParagraph,
+}
+
+impl<'py> FromPyObject<'_, 'py> for NoteIcon {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        let s: String = ob.extract()?;
+        match s.as_str() {
+            "note" => Ok(NoteIcon::Note),
+            "comment" => Ok(NoteIcon::Comment),
+            "help" => Ok(NoteIcon::Help),
+            "insert" => Ok(NoteIcon::Insert),
+            "key" => Ok(NoteIcon::Key),
+            "paragraph" => Ok(NoteIcon::Paragraph),
+            "new_paragraph" => Ok(NoteIcon::NewParagraph),
+            _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Invalid icon: '{}'. Must be 'note', 'comment', 'help', 'insert', 'key', 'paragraph', or 'new_paragraph'",
+                s
+            ))),
+        }
+    }
+}
+
+/// Sticky-note comment, written as a Text annotation with a popup rather
+/// than as page content
+#[derive(Debug, Clone)]
+pub struct NoteElement {
+    pub id: Option<String>,
+    pub tagging: Tagging,
+    pub z: i32,
+    pub visible: bool,
+    /// Top left corner of the icon
+    pub x: f32,
+    pub y: f32,
+    pub contents: String,
+    /// Author shown in the popup's title bar
+    pub title: Option<String>,
+    /// Icon and popup title bar color
+    pub color: Color,
+    pub icon: NoteIcon,
+    /// Show the popup when the document opens
+    pub open: bool,
+}
+
+impl NoteElement {
+    /// Side of the square icon viewers draw, in points
+    pub const ICON_SIZE: f32 = 20.0;
+}
+
+/// "Sign here" block: a rule, an optional "X" above its left end and
+/// caption rows beneath it, laid out from the font size
+#[derive(Debug, Clone)]
+pub struct SignatureLineElement {
+    pub id: Option<String>,
+    pub tagging: Tagging,
+    pub z: i32,
+    pub visible: bool,
+    /// Left end of the rule
+    pub x: f32,
+    /// Center line of the rule
+    pub y: f32,
+    pub w: f32,
+    /// First caption row, usually the printed name
+    pub label: Option<String>,
+    /// Next caption row (title, date), spread across the width in equal
+    /// columns
+    pub sublabels: Vec<String>,
+    pub font: String,
+    pub size: f32,
+    pub color: Color,
+    /// Rule thickness
+    pub stroke: f32,
+    /// Draw an "X" above the left end of the rule
+    pub mark: bool,
+}
+
+impl SignatureLineElement {
+    /// Gap from the bottom of the rule to the first caption's cap line, in
+    /// multiples of the size
+    pub const CAPTION_GAP: f32 = 0.6;
+    /// Distance between caption rows, in multiples of the size
+    pub const ROW_PITCH: f32 = 1.4;
+    /// Gap from the top of the rule to the mark's baseline, in multiples
+    /// of the size
+    pub const MARK_GAP: f32 = 0.3;
+
+    /// The block as a line and text elements. Captions hang from their cap
+    /// line, which comes from the font, so they sit the same distance under
+    /// the rule whatever the font.
+    pub fn parts(&self) -> Vec<Element> {
+        let text = |x: f32, y: f32, text: &str, vertical_anchor: VerticalAnchor| {
+            Element::Text(TextElement {
+                id: None,
+                tagging: Tagging::default(),
+                z: 0,
+                visible: true,
+                x,
+                y,
+                text: text.to_string(),
+                font: self.font.clone(),
+                font_fallback: Vec::new(),
+                missing_glyph_policy: MissingGlyphPolicy::Drop,
+                emoji: EmojiOptions::default(),
+                size: self.size,
+                color: self.color.clone(),
+                align: TextAlign::Left,
+                vertical_anchor,
+                highlight_color: None,
+                highlight_padding: 0.0,
+                transform: TextTransform::None,
+                fit_width: None,
+                max_width: None,
+                skew: Skew::default(),
+                rotation: 0.0,
+                outline: false,
+                styles: Vec::new(),
+            })
+        };
+        let half = self.stroke / 2.0;
+        let mut parts = vec![Element::Line(LineElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x1: self.x,
+            y1: self.y,
+            x2: self.x + self.w,
+            y2: self.y,
+            stroke: self.stroke,
+            hairline: false,
+            color: self.color.clone(),
+            dash: None,
+            cap: None,
+            join: None,
+            miter_limit: None,
+        })];
+        if self.mark {
+            let baseline = self.y - half - self.size * Self::MARK_GAP;
+            parts.push(text(self.x, baseline, "X", VerticalAnchor::Baseline));
+        }
+        let mut row = self.y + half + self.size * Self::CAPTION_GAP;
+        if let Some(label) = &self.label {
+            parts.push(text(self.x, row, label, VerticalAnchor::Capline));
+            row += self.size * Self::ROW_PITCH;
+        }
+        let column = self.w / self.sublabels.len().max(1) as f32;
+        for (i, sublabel) in self.sublabels.iter().enumerate() {
+            parts.push(text(self.x + column * i as f32, row, sublabel, VerticalAnchor::Capline));
+        }
+        parts
+    }
+}
+
+/// A list of child elements drawn together, laid out in local coordinates
+/// that the group moves, turns and scales into place
+#[derive(Debug, Clone)]
+pub struct GroupElement {
+    pub id: Option<String>,
+    pub tagging: Tagging,
+    pub z: i32,
+    pub visible: bool,
+    pub elements: Vec<Element>,
+    /// Where the children's origin lands on the page
+    pub x: f32,
+    pub y: f32,
+    /// Clockwise turn in degrees about `(x, y)`
+    pub rotation: f32,
+    pub scale: f32,
+    /// Composite the children as one unit (a PDF transparency group) so
+    /// `opacity` applies to the group as a whole; set by any opacity
+    /// below 1
+    pub transparency_group: bool,
+    /// Opacity of the whole group, 0.0-1.0
+    pub opacity: f32,
+}
+
+/// A named list of elements from the document's `fragments`, laid out in
+/// local coordinates with the origin at the fragment's top-left
+#[derive(Debug)]
+pub struct Fragment {
+    pub name: String,
+    pub elements: Vec<Element>,
+}
+
+/// One placement of a fragment
+#[derive(Debug, Clone)]
+pub struct FragmentElement {
+    pub id: Option<String>,
+    pub tagging: Tagging,
+    pub z: i32,
+    pub visible: bool,
+    /// Shared by every element placing the same fragment
+    pub fragment: Arc<Fragment>,
+    /// Where the fragment's origin lands on the page
+    pub x: f32,
+    pub y: f32,
+    pub scale: f32,
+}
+
+impl FragmentElement {
+    /// PDF `cm` operands for drawing the fragment's elements on a page
+    /// `page_height` tall: they are drawn as if the fragment's origin were
+    /// the page's top-left corner, then scaled and moved into place
+    pub fn matrix(&self, page_height: f32) -> [f32; 6] {
+        [self.scale, 0.0, 0.0, self.scale, self.x, page_height - self.y - self.scale * page_height]
+    }
+}
+
+/// Operators written to the content stream as given, from `raw_content`.
+/// Only rendered with `allow_raw`.
+#[derive(Debug, Clone)]
+pub struct RawContentElement {
+    pub id: Option<String>,
+    pub tagging: Tagging,
+    pub z: i32,
+    pub visible: bool,
+    /// In PDF user space: points, origin at the page's bottom-left
+    pub operators: String,
+    /// Names the operators may use, and what each refers to
+    pub resources: Vec<(String, RawResource)>,
+    /// Where the operators use `resources`, found when parsed
+    pub uses: RawUses,
+}
+
+/// A resource `raw_content` operators refer to by a name of their own
+#[derive(Debug, Clone, PartialEq)]
+pub enum RawResource {
+    /// A font in `resources.fonts`, for `Tf`
+    Font(String),
+    /// An image in `resources.images` at its natural size, for `Do`
+    Image(String),
+    /// An alpha graphics state (0-255), for `gs`
+    Alpha(u8),
+}
+
+impl<'py> FromPyObject<'_, 'py> for RawResource {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        let dict = ob.cast::<PyDict>()?;
+        if let Some(font) = get_optional::<String>(&dict, "font")? {
+            return Ok(RawResource::Font(font));
+        }
+        if let Some(image) = get_optional::<String>(&dict, "image")? {
+            return Ok(RawResource::Image(image));
+        }
+        if let Some(alpha) = get_optional::<u8>(&dict, "alpha")? {
+            return Ok(RawResource::Alpha(alpha));
+        }
+        Err(pyo3::exceptions::PyValueError::new_err(
+            "A raw resource must have 'font', 'image' or 'alpha'",
+        ))
+    }
+}
+
+impl GroupElement {
+    /// `opacity` on the 0-255 alpha scale used by colors
+    pub fn alpha(&self) -> u8 {
+        (self.opacity * 255.0).round() as u8
+    }
+
+    /// Whether the children are drawn where they are, in page coordinates
+    pub fn is_identity(&self) -> bool {
+        self.x == 0.0 && self.y == 0.0 && self.rotation == 0.0 && self.scale == 1.0
+    }
+
+    /// Where a child's point lands on the page, both in top-left coordinates
+    pub fn place(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        let (dx, dy) = rotate_point(self.rotation, (x * self.scale, y * self.scale), (0.0, 0.0));
+        (self.x + dx, self.y + dy)
+    }
+
+    /// PDF `cm` operands for drawing the children on a page `page_height`
+    /// tall: they are drawn as if the group's origin were the page's
+    /// top-left corner, then scaled, turned and moved into place
+    pub fn matrix(&self, page_height: f32) -> [f32; 6] {
+        let [a, b, c, d, _, _] = rotation_matrix(self.rotation, 0.0, 0.0);
+        let (a, b, c, d) = (a * self.scale, b * self.scale, c * self.scale, d * self.scale);
+        [a, b, c, d, self.x - c * page_height, page_height - self.y - d * page_height]
+    }
+}
+
+/// A stroke dash pattern
+#[derive(Debug, Clone, PartialEq)]
+pub struct StrokeDash {
+    /// Alternating dash and gap lengths, in points
+    pub array: Vec<f32>,
+    /// Distance into the pattern at which the stroke starts, in points
+    pub phase: f32,
+    /// Stroke with round caps, so zero-length dashes draw as dots (the
+    /// `"dotted"` preset)
+    pub round_caps: bool,
+}
+
+/// Line element
+#[derive(Debug, Clone)]
+pub struct LineElement {
+    pub id: Option<String>,
+    pub tagging: Tagging,
+    pub z: i32,
+    pub visible: bool,
+    pub x1: f32,
+    pub y1: f32,
+    pub x2: f32,
+    pub y2: f32,
+    pub stroke: f32,
+    /// Drawn at the output device's thinnest width (PDF line width 0),
+    /// from `stroke: "hairline"`; `stroke` is then 0
+    pub hairline: bool,
+    pub color: Color,
+    pub dash: Option<StrokeDash>,
+    /// End caps; `None` keeps butt caps, or round ones for dotted dashes
+    pub cap: Option<LineCap>,
+    /// Joins, which a single segment has none of; `None` keeps miter joins
+    pub join: Option<LineJoin>,
+    /// As for `RectElement`
+    pub miter_limit: Option<f32>,
+}
+
+/// Polygon element: a path through `points`, closed by default
+#[derive(Debug, Clone)]
+pub struct PolygonElement {
+    pub id: Option<String>,
+    pub tagging: Tagging,
+    pub z: i32,
+    pub visible: bool,
+    /// Vertices in document coordinates, at least 3
+    pub points: Vec<(f32, f32)>,
+    pub stroke: f32,
+    /// Drawn at the output device's thinnest width, as for `LineElement`
+    pub hairline: bool,
+    pub stroke_color: Color,
+    pub fill_color: Option<Color>,
+    /// Join the last point back to the first. An open polygon is still
+    /// filled as if closed; only its stroke is left open.
+    pub close: bool,
+    pub fill_rule: FillRule,
+    /// Stroke end caps, for an open polygon; `None` keeps butt caps
+    pub cap: Option<LineCap>,
+    /// Stroke corner joins; `None` keeps miter joins
+    pub join: Option<LineJoin>,
+    /// As for `RectElement`
+    pub miter_limit: Option<f32>,
+}
+
+/// How the ends of an open stroke are drawn, from `cap`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineCap {
+    /// Squared off at the end point
+    Butt,
+    /// A half circle around the end point
+    Round,
+    /// Squared off half the stroke width past the end point
+    Square,
+}
+
+impl<'py> FromPyObject<'_, 'py> for LineCap {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        let s: String = ob.extract()?;
+        match s.as_str() {
+            "butt" => Ok(LineCap::Butt),
+            "round" => Ok(LineCap::Round),
+            "square" => Ok(LineCap::Square),
+            _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Invalid cap: '{}'. Must be 'butt', 'round' or 'square'",
+                s
+            ))),
+        }
+    }
+}
+
+/// How a stroke turns its corners, from `join`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineJoin {
+    /// Edges extended to a point, beveled past `miter_limit`
+    Miter,
+    /// A circular arc around the corner
+    Round,
+    /// Cut straight across the corner
+    Bevel,
+}
+
+impl<'py> FromPyObject<'_, 'py> for LineJoin {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        let s: String = ob.extract()?;
+        match s.as_str() {
+            "miter" => Ok(LineJoin::Miter),
+            "round" => Ok(LineJoin::Round),
+            "bevel" => Ok(LineJoin::Bevel),
+            _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Invalid join: '{}'. Must be 'miter', 'round' or 'bevel'",
+                s
+            ))),
+        }
+    }
+}
+
+/// Which parts of a self-intersecting shape are inside it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FillRule {
+    /// Inside wherever the outline winds around a point a nonzero number of times
+    #[default]
+    NonZero,
+    /// Inside wherever a ray from a point crosses the outline an odd number
+    /// of times, so overlaps of a star or ring cut holes
+    EvenOdd,
+}
+
+impl<'py> FromPyObject<'_, 'py> for FillRule {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        let s: String = ob.extract()?;
+        match s.as_str() {
+            "nonzero" => Ok(FillRule::NonZero),
+            "evenodd" => Ok(FillRule::EvenOdd),
+            _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Invalid fill_rule: '{}'. Must be 'nonzero' or 'evenodd'",
+                s
+            ))),
+        }
+    }
+}
+
+/// What a leader draws between its ends
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LeaderStyle {
+    /// Round dots `dot_size` across
+    #[default]
+    Dots,
+    /// Dashes `dot_size` thick
+    Dashes,
+    /// One unbroken rule `dot_size` thick
+    Solid,
+}
+
+impl<'py> FromPyObject<'_, 'py> for LeaderStyle {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        let s: String = ob.extract()?;
+        match s.as_str() {
+            "dots" => Ok(LeaderStyle::Dots),
+            "dashes" => Ok(LeaderStyle::Dashes),
+            "solid" => Ok(LeaderStyle::Solid),
+            _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Invalid leader style: '{}'. Must be 'dots', 'dashes', or 'solid'",
+                s
+            ))),
+        }
+    }
+}
+
+/// Dotted (or dashed, or ruled) fill between a label and a value, as in
+/// tables of contents
+#[derive(Debug, Clone)]
+pub struct LeaderElement {
+    pub id: Option<String>,
+    pub tagging: Tagging,
+    pub z: i32,
+    pub visible: bool,
+    pub x1: f32,
+    pub x2: f32,
+    /// Center line of the marks, or with `font` the baseline they sit on
+    pub y: f32,
+    pub style: LeaderStyle,
+    /// Dot diameter, or dash and rule thickness. Defaults to the height of
+    /// the font's period with `font`, else 1pt
+    pub dot_size: Option<f32>,
+    /// Space between marks; defaults to twice the dot size
+    pub gap: Option<f32>,
+    pub color: Color,
+    /// Font of the text the leader sits beside, so its dots rest on the
+    /// same baseline as that text's periods
+    pub font: Option<String>,
+    /// Size of that text; required with `font`
+    pub size: f32,
+}
+
+/// Circular progress gauge: a full-circle track with a round-capped arc
+/// over it covering `value` of the turn
+#[derive(Debug, Clone)]
+pub struct ProgressRingElement {
+    pub id: Option<String>,
+    pub tagging: Tagging,
+    pub z: i32,
+    pub visible: bool,
+    pub cx: f32,
+    pub cy: f32,
+    /// Radius of the stroke's center line
+    pub radius: f32,
+    /// Stroke width of both track and fill
+    pub thickness: f32,
+    /// Filled fraction, 0 to 1
+    pub value: f32,
+    /// Where the fill starts, in degrees clockwise from 12 o'clock
+    pub start_angle: f32,
+    /// The unfilled circle; not drawn when None
+    pub track_color: Option<Color>,
+    pub color: Color,
+}
+
+/// Image element
+#[derive(Debug, Clone)]
+pub struct ImageElement {
+    pub id: Option<String>,
+    pub tagging: Tagging,
+    pub z: i32,
+    pub visible: bool,
+    pub x: f32,
+    pub y: f32,
+    pub w: Option<f32>,  // If only w provided, scale preserving aspect ratio
+    pub h: Option<f32>,  // If only h provided, scale preserving aspect ratio
+    pub image_ref: String,
+    pub align: TextAlign,  // Horizontal alignment: left (default), center, right
+    pub fade: Option<ImageFade>,  // Fade to transparent toward one edge
+    /// Clockwise turn in degrees about the anchor: `x` on the top edge
+    pub rotation: f32,
+    /// Corners the image's top-left, top-right and bottom-left land on,
+    /// in place of `x`, `y`, `w`, `h`, `align` and `rotation`. `x` and `y`
+    /// are the first corner and `w` and `h` the edge lengths, so sizes
+    /// work as for a plain image.
+    pub quad: Option<ImageQuad>,
+    pub clip: Option<ImageClip>,
+    /// Stroke width of the outline drawn over the image, following its
+    /// clip shape; 0 draws none
+    pub border_width: f32,
+    pub border_color: Color,
+}
+
+/// Shape an image is clipped to, from `clip`, fitted to its display box
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageClip {
+    /// The largest circle centered in the box
+    Circle,
+    /// The ellipse touching the box's four edges
+    Ellipse,
+    /// The box with rounded corners
+    Rounded(CornerRadii),
+}
+
+/// Three corners of a parallelogram an image is mapped onto: the image's
+/// top-left (`origin`), top-right (`x_corner`) and bottom-left
+/// (`y_corner`), in top-left page coordinates
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageQuad {
+    pub origin: (f32, f32),
+    pub x_corner: (f32, f32),
+    pub y_corner: (f32, f32),
+}
+
+impl ImageQuad {
+    /// Lengths of the top and left edges, the image's display size
+    pub fn size(&self) -> (f32, f32) {
+        let (ux, uy) = (self.x_corner.0 - self.origin.0, self.x_corner.1 - self.origin.1);
+        let (vx, vy) = (self.y_corner.0 - self.origin.0, self.y_corner.1 - self.origin.1);
+        (ux.hypot(uy), vx.hypot(vy))
+    }
+
+    /// The four corners, clockwise from the origin
+    pub fn corners(&self) -> [(f32, f32); 4] {
+        let bottom_right = (
+            self.x_corner.0 + self.y_corner.0 - self.origin.0,
+            self.x_corner.1 + self.y_corner.1 - self.origin.1,
+        );
+        [self.origin, self.x_corner, bottom_right, self.y_corner]
+    }
+
+    /// PDF `cm` operands mapping a `w × h` box at the origin, in PDF
+    /// coordinates, onto the quad on a page `page_height` tall
+    pub fn matrix(&self, page_height: f32) -> [f32; 6] {
+        let (w, h) = self.size();
+        let (ux, uy) = ((self.x_corner.0 - self.origin.0) / w, (self.x_corner.1 - self.origin.1) / w);
+        let (vx, vy) = ((self.y_corner.0 - self.origin.0) / h, (self.y_corner.1 - self.origin.1) / h);
+        // The box's bottom-left goes to the image's bottom-left corner and
+        // its up direction against the quad's downward left edge
+        [ux, -uy, -vx, vy, self.y_corner.0, page_height - self.y_corner.1]
+    }
+}
+
+/// Stand-in for an image still to come: a box crossed corner to corner,
+/// with a caption centered in it. Sized and aligned like `ImageElement`,
+/// so the image can take its place later without moving.
+#[derive(Debug, Clone)]
+pub struct ImagePlaceholderElement {
+    pub id: Option<String>,
+    pub tagging: Tagging,
+    pub z: i32,
+    pub visible: bool,
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+    pub align: TextAlign,
+    pub label: Option<String>,
+    /// Smaller second caption line, e.g. the expected aspect ratio
+    pub note: Option<String>,
+    pub font: String,
+    pub size: f32,
+    /// Outline, cross and caption
+    pub color: Color,
+    pub fill_color: Color,
+}
+
+impl ImagePlaceholderElement {
+    /// Note size as a fraction of the label size
+    pub const NOTE_SCALE: f32 = 0.8;
+    /// Distance between the label and note centers, in multiples of the size
+    pub const ROW_PITCH: f32 = 1.3;
+    /// Width of the outline and cross
+    pub const STROKE: f32 = 0.75;
+
+    /// Left edge of the box, from `x` and `align` as for an image
+    pub fn left(&self) -> f32 {
+        match self.align {
+            TextAlign::Left => self.x,
+            TextAlign::Center => self.x - self.w / 2.0,
+            TextAlign::Right => self.x - self.w,
+        }
+    }
+
+    /// The placeholder as a rect, two lines and text elements. Captions are
+    /// highlighted in the fill color so the cross doesn't run through them.
+    pub fn parts(&self) -> Vec<Element> {
+        let (left, top) = (self.left(), self.y);
+        let (right, bottom) = (left + self.w, top + self.h);
+        let line = |x1: f32, y1: f32, x2: f32, y2: f32| {
+            Element::Line(LineElement {
+                id: None,
+                tagging: Tagging::default(),
+                z: 0,
+                visible: true,
+                x1,
+                y1,
+                x2,
+                y2,
+                stroke: Self::STROKE,
+                hairline: false,
+                color: self.color.clone(),
+                dash: None,
+                cap: None,
+                join: None,
+                miter_limit: None,
+            })
+        };
+        let text = |y: f32, text: &str, size: f32| {
+            Element::Text(TextElement {
+                id: None,
+                tagging: Tagging::default(),
+                z: 0,
+                visible: true,
+                x: left + self.w / 2.0,
+                y,
+                text: text.to_string(),
+                font: self.font.clone(),
+                font_fallback: Vec::new(),
+                missing_glyph_policy: MissingGlyphPolicy::Drop,
+                emoji: EmojiOptions::default(),
+                size,
+                color: self.color.clone(),
+                align: TextAlign::Center,
+                vertical_anchor: VerticalAnchor::Center,
+                highlight_color: Some(self.fill_color.clone()),
+                highlight_padding: size * 0.25,
+                transform: TextTransform::None,
+                fit_width: None,
+                max_width: None,
+                skew: Skew::default(),
+                rotation: 0.0,
+                outline: false,
+                styles: Vec::new(),
+            })
+        };
+        let mut parts = vec![
+            Element::Rect(RectElement {
+                id: None,
+                tagging: Tagging::default(),
+                z: 0,
+                visible: true,
+                x: left,
+                y: top,
+                w: self.w,
+                h: self.h,
+                stroke: Self::STROKE,
+                stroke_color: self.color.clone(),
+                fill_color: Some(self.fill_color.clone()),
+                fill_pattern: None,
+                gradient: None,
+                corner_radius: CornerRadii::default(),
+                corner_style: CornerStyle::default(),
+                sides: None,
+                dash: None,
+                miter_limit: None,
+                stroke_align: StrokeAlign::Inside,
+                hairline: false,
+                skew: Skew::default(),
+                rotation: 0.0,
+                rotation_origin: RotationOrigin::Center,
+                cap: None,
+                join: None,
+            }),
+            line(left, top, right, bottom),
+            line(left, bottom, right, top),
+        ];
+        let middle = top + self.h / 2.0;
+        let rows: Vec<(&str, f32)> = self
+            .label
+            .iter()
+            .map(|label| (label.as_str(), self.size))
+            .chain(self.note.iter().map(|note| (note.as_str(), self.size * Self::NOTE_SCALE)))
+            .collect();
+        let first = middle - (rows.len().max(1) - 1) as f32 * self.size * Self::ROW_PITCH / 2.0;
+        for (i, (row, size)) in rows.into_iter().enumerate() {
+            parts.push(text(first + i as f32 * self.size * Self::ROW_PITCH, row, size));
+        }
+        parts
+    }
+}
+
+/// Layout aid: lines across the whole page every `spacing`, heavier every
+/// `major_every` cells, with the major lines' coordinates printed along
+/// the top and left edges when a `font` is given
+#[derive(Debug, Clone)]
+pub struct GridElement {
+    pub id: Option<String>,
+    pub tagging: Tagging,
+    pub z: i32,
+    pub visible: bool,
+    /// Distance between lines
+    pub spacing: f32,
+    /// Cells between major lines
+    pub major_every: u32,
+    pub color: Color,
+    /// Minor and major line widths
+    pub stroke: f32,
+    pub major_stroke: f32,
+    /// Font for coordinate labels; no labels without one
+    pub font: Option<String>,
+    pub size: f32,
+    pub label_color: Color,
+    /// Points per label unit, so labels read in the document's `units`
+    pub unit: f32,
+    /// Count rows up from the bottom edge, for `origin: "bottom_left"`
+    pub from_bottom: bool,
+}
+
+impl GridElement {
+    /// Gap between a label and its line and the page edge, in multiples of
+    /// the size
+    pub const LABEL_GAP: f32 = 0.3;
+
+    /// Most lines a grid may draw on one page
+    pub const MAX_LINES: u64 = 10_000;
+
+    /// Lines the grid draws on a `width` x `height` page, both ways
+    pub fn line_count(&self, width: f32, height: f32) -> u64 {
+        let across = |extent: f32| (extent as f64 / self.spacing as f64).floor() as u64 + 1;
+        across(width) + across(height)
+    }
+
+    /// The grid on a `width` x `height` page as lines and labels
+    pub fn parts(&self, width: f32, height: f32) -> Vec<Element> {
+        let line = |x1: f32, y1: f32, x2: f32, y2: f32, major: bool| {
+            Element::Line(LineElement {
+                id: None,
+                tagging: Tagging::default(),
+                z: 0,
+                visible: true,
+                x1,
+                y1,
+                x2,
+                y2,
+                stroke: if major { self.major_stroke } else { self.stroke },
+                hairline: false,
+                color: self.color.clone(),
+                dash: None,
+                cap: None,
+                join: None,
+                miter_limit: None,
+            })
+        };
+        let text = |x: f32, y: f32, value: f32, vertical_anchor: VerticalAnchor| {
+            let label = format!("{:.2}", value / self.unit);
+            Element::Text(TextElement {
+                id: None,
+                tagging: Tagging::default(),
+                z: 0,
+                visible: true,
+                x,
+                y,
+                text: label.trim_end_matches('0').trim_end_matches('.').to_string(),
+                font: self.font.clone().unwrap_or_default(),
+                font_fallback: Vec::new(),
+                missing_glyph_policy: MissingGlyphPolicy::Drop,
+                emoji: EmojiOptions::default(),
+                size: self.size,
+                color: self.label_color.clone(),
+                align: TextAlign::Left,
+                vertical_anchor,
+                highlight_color: None,
+                highlight_padding: 0.0,
+                transform: TextTransform::None,
+                fit_width: None,
+                max_width: None,
+                skew: Skew::default(),
+                rotation: 0.0,
+                outline: false,
+                styles: Vec::new(),
+            })
+        };
+        let major = |i: u32| i.is_multiple_of(self.major_every);
+        let gap = self.size * Self::LABEL_GAP;
+        let mut lines = Vec::new();
+        let mut labels = Vec::new();
+        let mut i = 0;
+        while i as f32 * self.spacing <= width {
+            let x = i as f32 * self.spacing;
+            lines.push(line(x, 0.0, x, height, major(i)));
+            if i > 0 && major(i) && self.font.is_some() {
+                labels.push(text(x + gap, gap, x, VerticalAnchor::Capline));
+            }
+            i += 1;
+        }
+        let mut i = 0;
+        while i as f32 * self.spacing <= height {
+            let offset = i as f32 * self.spacing;
+            let y = if self.from_bottom { height - offset } else { offset };
+            lines.push(line(0.0, y, width, y, major(i)));
+            if i > 0 && major(i) && self.font.is_some() {
+                labels.push(text(gap, y - gap, offset, VerticalAnchor::Baseline));
+            }
+            i += 1;
+        }
+        // Labels over every line
+        lines.extend(labels);
+        lines
+    }
+}
+
+/// Image edge a fade runs toward
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FadeEdge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+impl<'py> FromPyObject<'_, 'py> for FadeEdge {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        let s: String = ob.extract()?;
+        match s.as_str() {
+            "top" => Ok(FadeEdge::Top),
+            "bottom" => Ok(FadeEdge::Bottom),
+            "left" => Ok(FadeEdge::Left),
+            "right" => Ok(FadeEdge::Right),
+            _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Invalid fade edge: '{}'. Must be 'top', 'bottom', 'left', or 'right'",
+                s
+            ))),
+        }
+    }
+}
+
+/// Linear fade from opaque to transparent. `start` and `end` are fractions
+/// of the placed image, measured from the opposite side toward `edge`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageFade {
+    pub edge: FadeEdge,
+    pub start: f32,
+    pub end: f32,
+}
+
+impl ImageFade {
+    /// The fade as a white (opaque) to black (transparent) gradient, for
+    /// use as a luminosity mask.
+    pub fn gradient(&self) -> Gradient {
+        let angle = match self.edge {
+            FadeEdge::Right => 0.0,
+            FadeEdge::Bottom => 90.0,
+            FadeEdge::Left => 180.0,
+            FadeEdge::Top => 270.0,
+        };
+        Gradient {
+            kind: GradientKind::Linear { angle },
+            stops: vec![
+                GradientStop { offset: self.start, color: Color::white() },
+                GradientStop { offset: self.end, color: Color::black() },
+            ],
+        }
+    }
+}
+
+impl<'py> FromPyObject<'_, 'py> for ImageFade {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        let dict = ob.cast::<PyDict>()?;
+        let fade = Self {
+            edge: get_optional(&dict, "edge")?.unwrap_or(FadeEdge::Bottom),
+            start: get_optional(&dict, "start")?.unwrap_or(0.5),
+            end: get_optional(&dict, "end")?.unwrap_or(1.0),
+        };
+        if !(0.0 <= fade.start && fade.start < fade.end && fade.end <= 1.0) {
+            return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Fade start {} and end {} must satisfy 0 <= start < end <= 1",
+                fade.start, fade.end
+            )));
+        }
+        Ok(fade)
+    }
+}
+
+/// Barcode flavour
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BarcodeKind {
+    /// Plain Code 128 (any printable ASCII payload)
+    Code128,
+    /// GS1-128: Code 128 with FNC1 designator and Application Identifiers.
+    /// `value` is a parenthesized string like `(01)12345678901234(10)BATCH`.
+    Gs1_128,
+}
+
+impl<'py> FromPyObject<'_, 'py> for BarcodeKind {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        let s: String = ob.extract()?;
+        match s.as_str() {
+            "code128" | "barcode" | "barcode128" => Ok(BarcodeKind::Code128),
+            "gs1_128" | "gs1-128" | "gs1" => Ok(BarcodeKind::Gs1_128),
+            _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Invalid symbology: '{}'. Must be 'code128' or 'gs1_128'",
+                s
+            ))),
+        }
+    }
+}
+
+/// Barcode element (Code 128 / GS1-128)
+#[derive(Debug, Clone)]
+pub struct BarcodeElement {
+    pub id: Option<String>,
+    pub tagging: Tagging,
+    pub z: i32,
+    pub visible: bool,
+    pub kind: BarcodeKind,
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+    pub value: String,
+    pub human_readable: bool,
+    pub font: String,
+    pub font_size: f32,
+    /// What human-readable text wider than `w` does; shrinks by default
+    pub text_overflow: TextOverflow,
+    /// Smallest size `Shrink` sets the text at, in points
+    pub text_min_size: f32,
+    pub color: Color,
+    /// Clockwise quarter turns in degrees (0, 90, 180 or 270) about the
+    /// top-left corner; `w` and `h` are measured before turning
+    pub rotation: f32,
+}
+
+/// QR error-correction level (share of codewords that can be recovered)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QrErrorCorrection {
+    Low,      // ~7%
+    #[default]
+    Medium,   // ~15%
+    Quartile, // ~25%
+    High,     // ~30%
+}
+
+impl QrErrorCorrection {
+    /// Single-letter name used in the QR spec and in error messages
+    pub fn letter(&self) -> char {
+        match self {
+            QrErrorCorrection::Low => 'L',
+            QrErrorCorrection::Medium => 'M',
+            QrErrorCorrection::Quartile => 'Q',
+            QrErrorCorrection::High => 'H',
+        }
+    }
+}
+
+impl<'py> FromPyObject<'_, 'py> for QrErrorCorrection {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        let s: String = ob.extract()?;
+        match s.as_str() {
+            "L" | "l" => Ok(QrErrorCorrection::Low),
+            "M" | "m" => Ok(QrErrorCorrection::Medium),
+            "Q" | "q" => Ok(QrErrorCorrection::Quartile),
+            "H" | "h" => Ok(QrErrorCorrection::High),
+            _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Invalid error_correction: '{}'. Must be 'L', 'M', 'Q', or 'H'",
+                s
+            ))),
+        }
+    }
+}
+
+/// QR payload. Text is encoded as UTF-8; bytes are encoded as-is in byte mode.
+#[derive(Debug, Clone)]
+pub enum QrPayload {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl QrPayload {
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            QrPayload::Text(s) => s.as_bytes(),
+            QrPayload::Bytes(b) => b,
+        }
+    }
+
+    /// Human-readable form for error messages (lossy for binary payloads)
+    pub fn display(&self) -> String {
+        match self {
+            QrPayload::Text(s) => s.clone(),
+            QrPayload::Bytes(b) => String::from_utf8_lossy(b).into_owned(),
+        }
+    }
+}
+
+impl<'py> FromPyObject<'_, 'py> for QrPayload {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        if let Ok(bytes) = ob.cast::<PyBytes>() {
+            return Ok(QrPayload::Bytes(bytes.as_bytes().to_vec()));
+        }
+        Ok(QrPayload::Text(ob.extract()?))
+    }
+}
+
+impl QrPayload {
+    /// Read a QR `value`: text, bytes, or a structured dict (see `QrData`)
+    /// serialized to its standard text payload.
+    fn from_py<'py>(value: &Bound<'py, PyAny>) -> Result<Self> {
+        match value.cast::<PyDict>() {
+            Ok(dict) => Ok(QrPayload::Text(QrData::from_py(dict)?.payload())),
+            Err(_) => to_doc_err(value.extract()),
+        }
+    }
+}
+
+/// Security of the network in a `wifi` QR payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WifiSecurity {
+    /// WPA or WPA2 personal
+    Wpa,
+    Wep,
+    /// WPA3 personal
+    Sae,
+    /// Open network
+    None,
+}
+
+/// Structured QR value, serialized by `payload` (in `elements::qr`)
+#[derive(Debug, Clone, PartialEq)]
+pub enum QrData {
+    /// vCard 3.0 contact card
+    VCard {
+        /// Formatted name (`FN`)
+        name: String,
+        /// Structured name (`N`); without either, `N` repeats `name`
+        family_name: Option<String>,
+        given_name: Option<String>,
+        org: Option<String>,
+        title: Option<String>,
+        phones: Vec<String>,
+        emails: Vec<String>,
+        url: Option<String>,
+        note: Option<String>,
+    },
+    /// `WIFI:` network join payload
+    Wifi {
+        ssid: String,
+        password: Option<String>,
+        security: WifiSecurity,
+        hidden: bool,
+    },
+}
+
+const VCARD_FIELDS: &[&str] = &[
+    "kind", "name", "family_name", "given_name", "org", "title", "phone", "email", "url", "note",
+];
+const WIFI_FIELDS: &[&str] = &["kind", "ssid", "password", "security", "hidden"];
+
+impl QrData {
+    pub fn from_py<'py>(dict: &Bound<'py, PyDict>) -> Result<Self> {
+        let kind: String = opt(dict, "kind")?.ok_or_else(|| {
+            RupdfError::InvalidDocument("QR value dict must have a 'kind': 'vcard' or 'wifi'".to_string())
+        })?;
+        let fields = match kind.as_str() {
+            "vcard" => VCARD_FIELDS,
+            "wifi" => WIFI_FIELDS,
+            _ => {
+                return Err(RupdfError::InvalidDocument(format!(
+                    "Invalid QR value kind: '{}'. Must be 'vcard' or 'wifi'",
+                    kind
+                )))
+            }
+        };
+        for key in dict.keys() {
+            let key: String = to_doc_err(key.extract())?;
+            if !fields.contains(&key.as_str()) {
+                return Err(RupdfError::InvalidDocument(format!(
+                    "Unknown field '{}' in QR {} value. Must be one of: {}",
+                    key,
+                    kind,
+                    fields[1..].join(", ")
+                )));
+            }
+        }
+        let required = |key: &str| -> Result<String> {
+            opt::<String>(dict, key)?.filter(|s| !s.is_empty()).ok_or_else(|| {
+                RupdfError::InvalidDocument(format!("QR {} value is missing required field '{}'", kind, key))
+            })
+        };
+        // A string or a list of strings
+        let one_or_many = |key: &str| -> Result<Vec<String>> {
+            match opt::<Bound<'py, PyAny>>(dict, key)? {
+                None => Ok(Vec::new()),
+                Some(v) => match v.extract::<String>() {
+                    Ok(s) => Ok(vec![s]),
+                    Err(_) => v.extract().map_err(|_| {
+                        RupdfError::InvalidDocument(format!(
+                            "QR {} value field '{}' must be a string or a list of strings",
+                            kind, key
+                        ))
+                    }),
+                },
+            }
+        };
+
+        if kind == "vcard" {
+            return Ok(QrData::VCard {
+                name: required("name")?,
+                family_name: opt(dict, "family_name")?,
+                given_name: opt(dict, "given_name")?,
+                org: opt(dict, "org")?,
+                title: opt(dict, "title")?,
+                phones: one_or_many("phone")?,
+                emails: one_or_many("email")?,
+                url: opt(dict, "url")?,
+                note: opt(dict, "note")?,
+            });
+        }
+
+        let ssid = required("ssid")?;
+        let password: Option<String> = opt(dict, "password")?;
+        let security = match opt::<String>(dict, "security")?.as_deref() {
+            None if password.is_some() => WifiSecurity::Wpa,
+            None | Some("nopass") => WifiSecurity::None,
+            Some("WPA") | Some("WPA2") => WifiSecurity::Wpa,
+            Some("WEP") => WifiSecurity::Wep,
+            Some("SAE") | Some("WPA3") => WifiSecurity::Sae,
+            Some(other) => {
+                return Err(RupdfError::InvalidDocument(format!(
+                    "Invalid QR wifi security: '{}'. Must be 'WPA', 'WPA2', 'WPA3', 'SAE', 'WEP' or 'nopass'",
+                    other
+                )))
+            }
+        };
+        if security == WifiSecurity::None && password.is_some() {
+            return Err(RupdfError::InvalidDocument(
+                "QR wifi value has a password but security 'nopass'".to_string(),
+            ));
+        }
+        if security != WifiSecurity::None {
+            required("password")?;
+        }
+        Ok(QrData::Wifi { ssid, password, security, hidden: opt_or(dict, "hidden", false)? })
+    }
+}
+
+/// QR Code element
+#[derive(Debug, Clone)]
+pub struct QRCodeElement {
+    pub id: Option<String>,
+    pub tagging: Tagging,
+    pub z: i32,
+    pub visible: bool,
+    pub x: f32,
+    pub y: f32,
     pub size: f32,  // QR codes are square
-    pub value: String,
+    pub value: QrPayload,
+    pub error_correction: QrErrorCorrection,
+    /// Requested symbol version (1-40). With `auto_version` this is a
+    /// minimum; without it the payload must fit this exact version.
+    pub version: Option<u8>,
+    pub auto_version: bool,
+    /// Smallest acceptable module edge in points; symbols that would need
+    /// smaller modules at `size` are rejected instead of printed unscannable.
+    pub min_module_size: Option<f32>,
     pub color: Color,       // Foreground color (dark modules)
     pub background: Color,  // Background color (light modules)
 }
@@ -344,6 +2400,10 @@ impl<'py> FromPyObject<'_, 'py> for DataMatrixShape {
 /// Data Matrix element
 #[derive(Debug, Clone)]
 pub struct DataMatrixElement {
+    pub id: Option<String>,
+    pub tagging: Tagging,
+    pub z: i32,
+    pub visible: bool,
     pub kind: DataMatrixKind,
     pub x: f32,
     pub y: f32,
@@ -356,77 +2416,851 @@ pub struct DataMatrixElement {
     pub background: Color,  // Background color (light modules)
 }
 
-/// TextBox element - multi-line text with word wrapping
-#[derive(Debug, Clone)]
-pub struct TextBoxElement {
-    pub x: f32,
-    pub y: f32,
-    pub w: f32,
-    pub h: f32,
-    pub box_align_x: BoxAlignX,
-    pub box_align_y: BoxAlignY,
-    pub text_align_x: TextAlign,
-    pub text_align_y: TextAlignY,
-    pub text: String,
-    pub font: String,
-    pub font_fallback: Vec<String>,
-    pub missing_glyph_policy: MissingGlyphPolicy,
-    pub size: f32,
-    pub line_height: f32,
-    pub color: Color,
+/// TextBox element - multi-line text with word wrapping
+#[derive(Debug, Clone)]
+pub struct TextBoxElement {
+    pub id: Option<String>,
+    pub tagging: Tagging,
+    pub z: i32,
+    pub visible: bool,
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+    pub box_align_x: BoxAlignX,
+    pub box_align_y: BoxAlignY,
+    pub text_align_x: TextAlign,
+    pub text_align_y: TextAlignY,
+    /// The text, with a placeholder character for each inline image (see
+    /// `runs::inline_image_char`)
+    pub text: String,
+    /// Images flowed with the text, in placeholder order
+    pub inline_images: Vec<InlineImage>,
+    pub font: String,
+    pub font_fallback: Vec<String>,
+    pub missing_glyph_policy: MissingGlyphPolicy,
+    pub emoji: EmojiOptions,
+    pub size: f32,
+    pub line_height: f32,
+    pub color: Color,
+    /// Number of columns the text flows through, left to right
+    pub columns: u32,
+    /// Horizontal space between columns, in points
+    pub column_gap: f32,
+    /// Spread lines evenly across columns instead of filling each in turn
+    pub balance: bool,
+    /// Styles selected by style characters in `text` (see `markup`)
+    pub styles: Vec<TextStyle>,
+}
+
+/// An image span of rich text, laid out as a box that wraps like a word
+#[derive(Debug, Clone)]
+pub struct InlineImage {
+    pub image_ref: String,
+    /// Display height; the width follows from the image's aspect ratio
+    pub h: f32,
+    /// Raise of the image's bottom edge above the baseline
+    pub baseline_shift: f32,
+}
+
+impl TextBoxElement {
+    /// Width of one column.
+    pub fn column_width(&self) -> f32 {
+        let gaps = self.column_gap * (self.columns - 1) as f32;
+        (self.w - gaps) / self.columns as f32
+    }
+}
+
+/// Table of wrapped text cells. Rows that don't fit below `y` flow onto
+/// continuation pages, each headed by a copy of the header rows.
+#[derive(Debug, Clone)]
+pub struct TableElement {
+    pub id: Option<String>,
+    pub tagging: Tagging,
+    pub z: i32,
+    pub visible: bool,
+    pub x: f32,
+    pub y: f32,
+    /// Width of each column, in points
+    pub column_widths: Vec<f32>,
+    /// Cell text, one entry per column in every row
+    pub rows: Vec<Vec<String>>,
+    /// Leading rows repeated at the top of every continuation
+    pub header_rows: usize,
+    pub font: String,
+    /// Font for header rows; `font` when unset
+    pub header_font: Option<String>,
+    pub font_fallback: Vec<String>,
+    pub missing_glyph_policy: MissingGlyphPolicy,
+    pub size: f32,
+    pub line_height: f32,
+    pub color: Color,
+    /// Space between cell borders and cell text
+    pub padding: f32,
+    /// Cell border width; 0 draws no borders
+    pub border: f32,
+    pub border_color: Color,
+    pub header_fill: Option<Color>,
+    /// Tallest the table may be on one page. Defaults to the room between
+    /// the table's top and the bottom margin (see `bottom_margin`).
+    pub max_height: Option<f32>,
+    /// Top of the table on continuation pages; `y` when unset
+    pub continued_y: Option<f32>,
+    /// Bottom margin of the page the table was parsed on; 0 when the page
+    /// sets none
+    pub page_margin_bottom: f32,
+    /// Line drawn above the header on continuation pages
+    pub continued_caption: Option<String>,
+    /// Set on the parts created by pagination, which draw the caption
+    pub continuation: bool,
+}
+
+impl TableElement {
+    pub fn width(&self) -> f32 {
+        self.column_widths.iter().sum()
+    }
+
+    /// Font alias for the cells of `row`.
+    pub fn row_font(&self, row: usize) -> &str {
+        match &self.header_font {
+            Some(font) if row < self.header_rows => font,
+            _ => &self.font,
+        }
+    }
+
+    /// Height of the caption line, when this part draws one.
+    pub fn caption_height(&self) -> f32 {
+        if self.continuation && self.continued_caption.is_some() {
+            self.line_height
+        } else {
+            0.0
+        }
+    }
+}
+
+/// A gradient color stop. `offset` runs from 0.0 (start) to 1.0 (end).
+#[derive(Debug, Clone)]
+pub struct GradientStop {
+    pub offset: f32,
+    pub color: Color,
+}
+
+/// Gradient geometry
+#[derive(Debug, Clone, Copy)]
+pub enum GradientKind {
+    /// Axial gradient across the filled box. `angle` is in degrees:
+    /// 0 runs left to right, 90 top to bottom.
+    Linear { angle: f32 },
+    /// Circles spreading from `center`, in points from the box's top-left
+    /// corner (default its middle), out to `radius` (default the distance
+    /// to the farthest corner).
+    Radial { center: Option<(f32, f32)>, radius: Option<f32> },
+}
+
+/// Gradient fill (PDF shading)
+#[derive(Debug, Clone)]
+pub struct Gradient {
+    pub kind: GradientKind,
+    pub stops: Vec<GradientStop>,
+}
+
+impl<'py> FromPyObject<'_, 'py> for Gradient {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        let dict = ob.cast::<PyDict>()?;
+        let kind_name: String = get_optional(&dict, "type")?.unwrap_or_else(|| "linear".to_string());
+        let kind = match kind_name.as_str() {
+            "linear" => GradientKind::Linear {
+                angle: get_optional(&dict, "angle")?.unwrap_or(0.0),
+            },
+            "radial" => {
+                let points = |length: Length| Units::default().to_points(length);
+                let radius = get_optional::<Length>(&dict, "radius")?.map(points);
+                if let Some(radius) = radius.filter(|r| r.is_nan() || *r <= 0.0) {
+                    return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                        "Gradient radius must be positive, got {}",
+                        radius
+                    )));
+                }
+                GradientKind::Radial {
+                    center: get_optional::<(Length, Length)>(&dict, "center")?.map(|(x, y)| (points(x), points(y))),
+                    radius,
+                }
+            }
+            _ => {
+                return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                    "Invalid gradient type: '{}'. Must be 'linear' or 'radial'",
+                    kind_name
+                )))
+            }
+        };
+
+        let raw_stops: Vec<(f32, Color)> = get_required(&dict, "stops")?;
+        if raw_stops.len() < 2 {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "Gradient needs at least 2 stops",
+            ));
+        }
+        let mut prev = 0.0;
+        for (i, (offset, _)) in raw_stops.iter().enumerate() {
+            if !(0.0..=1.0).contains(offset) {
+                return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                    "Gradient stop {} offset {} is outside 0..1",
+                    i, offset
+                )));
+            }
+            if *offset < prev {
+                return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                    "Gradient stop {} offset {} is less than the previous offset {}",
+                    i, offset, prev
+                )));
+            }
+            prev = *offset;
+        }
+
+        Ok(Self {
+            kind,
+            stops: raw_stops
+                .into_iter()
+                .map(|(offset, color)| GradientStop { offset, color })
+                .collect(),
+        })
+    }
+}
+
+impl Gradient {
+    /// Measure a radial gradient's center from the bottom of a box
+    /// `height` tall instead of the top, or back.
+    pub fn flip_center(&mut self, height: f32) {
+        if let GradientKind::Radial { center: Some((_, y)), .. } = &mut self.kind {
+            *y = height - *y;
+        }
+    }
+}
+
+/// How an image is scaled into a fixed frame
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImageFit {
+    /// Scale to cover the frame, cropping overflow (aspect preserved)
+    #[default]
+    Cover,
+    /// Scale to fit inside the frame, centered (aspect preserved)
+    Contain,
+    /// Stretch to the exact frame size
+    Stretch,
+}
+
+impl<'py> FromPyObject<'_, 'py> for ImageFit {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        let s: String = ob.extract()?;
+        match s.as_str() {
+            "cover" => Ok(ImageFit::Cover),
+            "contain" => Ok(ImageFit::Contain),
+            "stretch" | "fill" => Ok(ImageFit::Stretch),
+            _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Invalid fit: '{}'. Must be 'cover', 'contain', or 'stretch'",
+                s
+            ))),
+        }
+    }
+}
+
+/// Page background: a flat color, a gradient, or an image filling the page
+#[derive(Debug, Clone)]
+pub enum PageBackground {
+    Color(Color),
+    Gradient(Gradient),
+    Image { image_ref: String, fit: ImageFit },
+}
+
+impl<'py> FromPyObject<'_, 'py> for PageBackground {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        let Ok(dict) = ob.cast::<PyDict>() else {
+            return Ok(PageBackground::Color(ob.extract()?));
+        };
+        if dict.contains("spot")? || dict.contains("cmyk")? {
+            return Ok(PageBackground::Color(ob.extract()?));
+        }
+        if let Some(gradient) = get_optional::<Gradient>(&dict, "gradient")? {
+            return Ok(PageBackground::Gradient(gradient));
+        }
+        if let Some(image_ref) = get_optional::<String>(&dict, "image_ref")? {
+            return Ok(PageBackground::Image {
+                image_ref,
+                fit: get_optional(&dict, "fit")?.unwrap_or_default(),
+            });
+        }
+        Err(pyo3::exceptions::PyValueError::new_err(
+            "Background dict must have 'gradient', 'image_ref', 'spot' or 'cmyk'",
+        ))
+    }
+}
+
+/// All element types
+#[derive(Debug, Clone)]
+pub enum Element {
+    Text(TextElement),
+    TextBox(TextBoxElement),
+    Rect(RectElement),
+    Line(LineElement),
+    Polygon(PolygonElement),
+    Leader(LeaderElement),
+    ProgressRing(ProgressRingElement),
+    Image(ImageElement),
+    Barcode(BarcodeElement),
+    QRCode(QRCodeElement),
+    DataMatrix(DataMatrixElement),
+    Table(TableElement),
+    Check(CheckElement),
+    Note(NoteElement),
+    SignatureLine(SignatureLineElement),
+    ImagePlaceholder(ImagePlaceholderElement),
+    Grid(GridElement),
+    Group(GroupElement),
+    Fragment(FragmentElement),
+    RawContent(RawContentElement),
+}
+
+/// How an element appears in tagged output. Elements are painted in list
+/// order; `reading_order` sorts an element among its page's structure
+/// elements independently of that, once a structure tree is written.
+/// Artifacts are decorative and left out of the structure altogether.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Tagging {
+    pub reading_order: Option<i32>,
+    pub artifact: bool,
+}
+
+/// An element's index in its list plus its `id`, as shown in messages:
+/// `3`, or `3 (id 'total')`
+#[derive(Debug, Clone, Copy)]
+pub struct ElementLabel<'a> {
+    pub index: usize,
+    pub id: Option<&'a str>,
+}
+
+impl fmt::Display for ElementLabel<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.index)?;
+        if let Some(id) = self.id {
+            write!(f, " (id '{}')", id)?;
+        }
+        Ok(())
+    }
+}
+
+/// A single page
+#[derive(Debug, Clone)]
+pub struct Page {
+    pub width: f32,
+    pub height: f32,
+    pub background: PageBackground,
+    /// Consecutive copies of this page in the output. Copies share one
+    /// content stream and resource dictionary.
+    pub repeat: usize,
+    pub elements: Vec<Element>,
+    /// Index of the entry of the document's `pages` this page came from,
+    /// for `render_pdf_with_index` to look its `tag` up by. Continuation
+    /// pages share their first page's.
+    pub source: usize,
+}
+
+/// How an open action frames its page
+#[derive(Debug, Clone, PartialEq)]
+pub enum OpenView {
+    /// The whole page in the window
+    Fit,
+    /// The page width in the window, from the top of the page
+    FitWidth,
+    /// The point (`x`, `y`) at the window's top left corner, at `zoom`
+    /// (1.0 = 100%) or the viewer's current zoom
+    Xyz { x: f32, y: f32, zoom: Option<f32> },
+}
+
+impl OpenView {
+    fn from_py<'py>(value: &Bound<'py, PyAny>, ctx: &ParseContext<'py>) -> Result<Self> {
+        if let Ok(dict) = value.cast::<PyDict>() {
+            let zoom: Option<f32> = opt(dict, "zoom")?;
+            if zoom.is_some_and(|z| z <= 0.0) {
+                return Err(RupdfError::InvalidDocument("open_action zoom must be positive".to_string()));
+            }
+            return Ok(OpenView::Xyz {
+                x: opt_len_or(dict, "x", 0.0, ctx)?,
+                y: opt_len_or(dict, "y", 0.0, ctx)?,
+                zoom,
+            });
+        }
+        match to_doc_err(value.extract::<String>())?.as_str() {
+            "fit" => Ok(OpenView::Fit),
+            "fit_width" => Ok(OpenView::FitWidth),
+            other => Err(RupdfError::InvalidDocument(format!(
+                "Invalid open_action view: '{}'. Must be 'fit', 'fit_width' or a dict with x, y and zoom",
+                other
+            ))),
+        }
+    }
+}
+
+/// The page and view a document opens at
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpenAction {
+    /// Index of the page in the output, counting repeats and table
+    /// continuation pages
+    pub page: usize,
+    pub view: OpenView,
+}
+
+impl OpenAction {
+    fn from_py<'py>(dict: &Bound<'py, PyDict>, ctx: &ParseContext<'py>) -> Result<Self> {
+        let view = match opt::<Bound<'py, PyAny>>(dict, "view")? {
+            Some(value) => OpenView::from_py(&value, ctx)?,
+            None => OpenView::Fit,
+        };
+        Ok(Self { page: opt_or(dict, "page", 0)?, view })
+    }
+}
+
+/// Document metadata
+#[derive(Debug, Clone, Default)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub subject: Option<String>,
+    pub creator: Option<String>,
+    pub creation_date: Option<String>,
+}
+
+/// Font resource - path, bytes or (with allow_remote) a URL
+#[derive(Debug, Clone)]
+pub enum FontSource {
+    Path(String),
+    Bytes(Vec<u8>),
+    /// Replaced by `Bytes` when resources are fetched (see `remote`)
+    Url(String),
+}
+
+/// The `path`, `bytes` or `url` key of a resource dict, whichever is set.
+enum SourceKey {
+    Path(String),
+    Bytes(Vec<u8>),
+    Url(String),
+    Svg(String),
+}
+
+impl SourceKey {
+    /// `kind` ("Font", "Image") and `name` label errors. With `svg`, an
+    /// `svg` source string is accepted too.
+    fn from_py<'py>(dict: &Bound<'py, PyDict>, kind: &str, name: &str, svg: bool) -> Result<Self> {
+        let path: Option<String> = opt(dict, "path")?;
+        let bytes: Option<Bound<'py, PyBytes>> = opt(dict, "bytes")?;
+        let url: Option<String> = opt(dict, "url")?;
+        let source: Option<String> = if svg { opt(dict, "svg")? } else { None };
+        let keys = if svg { ["'path', 'bytes', 'url'", "'svg'"] } else { ["'path', 'bytes'", "'url'"] };
+        match (path, bytes, url, source) {
+            (Some(p), None, None, None) => Ok(SourceKey::Path(p)),
+            (None, Some(b), None, None) => Ok(SourceKey::Bytes(b.as_bytes().to_vec())),
+            (None, None, Some(u), None) => Ok(SourceKey::Url(u)),
+            (None, None, None, Some(s)) => Ok(SourceKey::Svg(s)),
+            (None, None, None, None) => Err(RupdfError::ResourceError(format!(
+                "{} '{}' must have one of {} or {}", kind, name, keys[0], keys[1]
+            ))),
+            _ => Err(RupdfError::ResourceError(format!(
+                "{} '{}' has more than one of {} and {}; only one is allowed", kind, name, keys[0], keys[1]
+            ))),
+        }
+    }
+}
+
+impl FontSource {
+    /// Read the `path`, `bytes` or `url` key of a font dict; `name` labels errors.
+    pub fn from_py<'py>(dict: &Bound<'py, PyDict>, name: &str) -> Result<Self> {
+        Ok(match SourceKey::from_py(dict, "Font", name, false)? {
+            SourceKey::Path(p) => FontSource::Path(p),
+            SourceKey::Bytes(b) => FontSource::Bytes(b),
+            SourceKey::Url(u) => FontSource::Url(u),
+            SourceKey::Svg(_) => unreachable!("fonts have no svg source"),
+        })
+    }
+}
+
+/// A font resource and how it is written to the PDF
+#[derive(Debug, Clone)]
+pub struct FontResource {
+    pub source: FontSource,
+    /// Embed a subset of the font program. When false only the font
+    /// dictionary and metrics are written and viewers must supply the font.
+    pub embed: bool,
+    /// Characters to measure the cap height from when the font's OS/2
+    /// table has none, in place of `CAP_HEIGHT_GLYPHS`
+    pub cap_height_glyphs: Option<String>,
+}
+
+impl FontResource {
+    pub fn from_py<'py>(dict: &Bound<'py, PyDict>, name: &str) -> Result<Self> {
+        Ok(Self {
+            source: FontSource::from_py(dict, name)?,
+            embed: opt_or(dict, "embed", true)?,
+            cap_height_glyphs: opt(dict, "cap_height_glyphs")?,
+        })
+    }
+}
+
+/// Image resource - path, bytes or (with allow_remote) a URL
+#[derive(Debug, Clone)]
+pub enum ImageSource {
+    Path(String),
+    Bytes(Vec<u8>),
+    /// Replaced by `Bytes` when resources are fetched (see `remote`)
+    Url(String),
+    /// SVG source text, loaded as SVG without sniffing the format
+    Svg(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct ImageResource {
+    pub source: ImageSource,
+    /// Frame of an animated GIF to draw, as the animation shows it at
+    /// that frame. Other formats only have frame 0.
+    pub frame: u32,
+    /// What to do with an ICC profile embedded in a PNG or JPEG source
+    pub profile: ImageProfile,
+}
+
+/// How a raster image's own ICC profile is honored, from its `profile`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImageProfile {
+    /// Embed the profile and tag the image ICCBased on it
+    #[default]
+    Embed,
+    /// Convert the pixels to sRGB and embed them as DeviceRGB
+    Srgb,
+}
+
+impl ImageProfile {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ImageProfile::Embed => "embed",
+            ImageProfile::Srgb => "srgb",
+        }
+    }
+}
+
+impl<'py> FromPyObject<'_, 'py> for ImageProfile {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        let s: String = ob.extract()?;
+        match s.as_str() {
+            "embed" => Ok(ImageProfile::Embed),
+            "srgb" => Ok(ImageProfile::Srgb),
+            _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Invalid image profile: '{}'. Must be 'embed' or 'srgb'",
+                s
+            ))),
+        }
+    }
+}
+
+/// ICC profile - either path or bytes
+#[derive(Debug, Clone)]
+pub enum ProfileSource {
+    Path(String),
+    Bytes(Vec<u8>),
+}
+
+/// The standard an output intent is declared for (its `/S` subtype)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputIntentKind {
+    #[default]
+    PdfA, // GTS_PDFA1
+    PdfX, // GTS_PDFX
+}
+
+impl<'py> FromPyObject<'_, 'py> for OutputIntentKind {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        let s: String = ob.extract()?;
+        match s.as_str() {
+            "pdfa" => Ok(OutputIntentKind::PdfA),
+            "pdfx" => Ok(OutputIntentKind::PdfX),
+            _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Invalid output intent subtype: '{}'. Must be 'pdfa' or 'pdfx'",
+                s
+            ))),
+        }
+    }
+}
+
+/// Document output intent: the ICC profile of the intended output condition
+#[derive(Debug, Clone)]
+pub struct OutputIntent {
+    pub profile: ProfileSource,
+    /// OutputConditionIdentifier, e.g. "FOGRA39" or "sRGB IEC61966-2.1"
+    pub condition: String,
+    /// Human-readable description of the condition
+    pub info: Option<String>,
+    pub kind: OutputIntentKind,
+    /// Tag raster images ICCBased with the profile instead of DeviceRGB
+    pub tag_images: bool,
+}
+
+impl OutputIntent {
+    pub fn from_py<'py>(dict: &Bound<'py, PyDict>) -> Result<Self> {
+        let path: Option<String> = opt(dict, "path")?;
+        let bytes: Option<Bound<'py, PyBytes>> = opt(dict, "bytes")?;
+        let profile = match (path, bytes) {
+            (Some(p), None) => ProfileSource::Path(p),
+            (None, Some(b)) => ProfileSource::Bytes(b.as_bytes().to_vec()),
+            (Some(_), Some(_)) => {
+                return Err(RupdfError::ResourceError(
+                    "output_intent has both 'path' and 'bytes'; only one is allowed".to_string(),
+                ));
+            }
+            (None, None) => {
+                return Err(RupdfError::ResourceError(
+                    "output_intent must have either 'path' or 'bytes'".to_string(),
+                ));
+            }
+        };
+        let context = |e| match e {
+            RupdfError::InvalidDocument(msg) => RupdfError::InvalidDocument(format!("output_intent: {}", msg)),
+            e => e,
+        };
+        Ok(Self {
+            profile,
+            condition: req(dict, "condition").map_err(context)?,
+            info: opt(dict, "info").map_err(context)?,
+            kind: opt_default(dict, "subtype").map_err(context)?,
+            tag_images: opt_or(dict, "tag_images", false).map_err(context)?,
+        })
+    }
+}
+
+/// All resources for a document
+#[derive(Debug, Clone, Default)]
+pub struct Resources {
+    pub fonts: HashMap<String, FontResource>,
+    pub images: HashMap<String, ImageResource>,
+    /// From the document-level `output_intent`
+    pub output_intent: Option<OutputIntent>,
+    /// Font names that elements take only from the document `defaults`,
+    /// so a missing one is reported as coming from there
+    pub inherited_fonts: HashSet<String>,
 }
 
-/// All element types
-#[derive(Debug, Clone)]
-pub enum Element {
-    Text(TextElement),
-    TextBox(TextBoxElement),
-    Rect(RectElement),
-    Line(LineElement),
-    Image(ImageElement),
-    Barcode(BarcodeElement),
-    QRCode(QRCodeElement),
-    DataMatrix(DataMatrixElement),
+/// Which pages a repeating element appears on
+#[derive(Debug, Clone, Default)]
+pub struct PageFilter {
+    /// If set, only these page indices
+    pub only: Option<Vec<usize>>,
+    /// Page indices to skip
+    pub except: Vec<usize>,
 }
 
-/// A single page
+impl PageFilter {
+    pub fn includes(&self, page: usize) -> bool {
+        self.only.as_ref().is_none_or(|only| only.contains(&page)) && !self.except.contains(&page)
+    }
+}
+
+/// An element drawn on every page, subject to its page filter
 #[derive(Debug, Clone)]
-pub struct Page {
-    pub width: f32,
-    pub height: f32,
-    pub background: Color,
-    pub elements: Vec<Element>,
+pub struct RepeatingElement {
+    pub element: Element,
+    pub pages: PageFilter,
+    /// Draw beneath the page's own elements instead of over them
+    pub under_content: bool,
+    /// Draw its text with `{page}` and `{pages}` replaced by the page's
+    /// number and the page count, as header and footer text is
+    pub page_numbers: bool,
 }
 
-/// Document metadata
-#[derive(Debug, Clone, Default)]
-pub struct Metadata {
-    pub title: Option<String>,
-    pub author: Option<String>,
-    pub subject: Option<String>,
-    pub creator: Option<String>,
-    pub creation_date: Option<String>,
+impl RepeatingElement {
+    /// Parse the `index`th entry of `repeating_elements` or a layer.
+    fn from_py_item<'py>(item: &Bound<'py, PyAny>, index: usize, ctx: &ParseContext<'py>) -> Result<Self> {
+        let dict = item.cast::<PyDict>()
+            .map_err(|_| RupdfError::InvalidDocument(format!("Element {} must be a dict", index)))?;
+        let element = Element::from_py_indexed(dict, index, ctx)?;
+        let id = element.id().map(str::to_string);
+        let label = ElementLabel { index, id: id.as_deref() };
+        with_element_context(Self::from_py(dict, element), label)
+    }
+
+    /// Read the repeat options (`only_pages`, `except_pages`,
+    /// `under_content`) from the element's dict.
+    fn from_py<'py>(dict: &Bound<'py, PyDict>, element: Element) -> Result<Self> {
+        Ok(Self {
+            element,
+            pages: PageFilter {
+                only: opt(dict, "only_pages")?,
+                except: opt_or(dict, "except_pages", Vec::new())?,
+            },
+            under_content: opt_or(dict, "under_content", false)?,
+            page_numbers: false,
+        })
+    }
 }
 
-/// Font resource - either path or bytes
+/// An element drawn on every page from the document's `watermark`, placed
+/// at a fraction of each page's size
 #[derive(Debug, Clone)]
-pub enum FontSource {
-    Path(String),
-    Bytes(Vec<u8>),
+pub struct Watermark {
+    /// Laid out around the placement point, which is its origin
+    pub element: Element,
+    pub opacity: f32,
+    /// Counter-clockwise turn in degrees about the placement point
+    pub rotation: f32,
+    /// Fractions of the page's width and height, from its top-left
+    pub placement: (f32, f32),
+    /// Draw beneath the page's own elements instead of over them
+    pub behind: bool,
+}
+
+impl Watermark {
+    /// Keys of the watermark dict that aren't the element's
+    const KEYS: [&'static str; 4] = ["opacity", "rotation", "placement", "behind"];
+
+    fn from_py<'py>(dict: &Bound<'py, PyDict>, ctx: &ParseContext<'py>) -> Result<Self> {
+        let opacity: f32 = opt_or(dict, "opacity", 1.0)?;
+        if !(0.0..=1.0).contains(&opacity) {
+            return Err(RupdfError::InvalidDocument(format!("opacity must be between 0 and 1, got {}", opacity)));
+        }
+        let placement = match opt::<Bound<'py, PyAny>>(dict, "placement")? {
+            None => (0.5, 0.5),
+            Some(value) => match value.extract::<String>() {
+                Ok(name) if name == "center" => (0.5, 0.5),
+                Ok(name) => {
+                    return Err(RupdfError::InvalidDocument(format!(
+                        "placement must be \"center\" or (x, y) fractions of the page size, got '{}'", name
+                    )));
+                }
+                Err(_) => value.extract::<(f32, f32)>().map_err(|_| RupdfError::InvalidDocument(
+                    "placement must be \"center\" or (x, y) fractions of the page size".to_string(),
+                ))?,
+            },
+        };
+
+        // The element is read from the rest of the dict. Its position is
+        // relative to the placement point, so `x` and `y` default to it,
+        // and text is centered on it unless it says otherwise.
+        let element_dict = to_doc_err(dict.copy())?;
+        for key in Self::KEYS {
+            if to_doc_err(element_dict.contains(key))? {
+                to_doc_err(element_dict.del_item(key))?;
+            }
+        }
+        let kind = opt::<String>(dict, "type")?.and_then(|name| canonical_element_type(&name));
+        if kind.is_some_and(|kind| crate::keys::element_accepts(kind, "x")) {
+            for key in ["x", "y"] {
+                if !to_doc_err(element_dict.contains(key))? {
+                    to_doc_err(element_dict.set_item(key, 0.0))?;
+                }
+            }
+        }
+        if kind == Some("text") {
+            for key in ["align", "vertical_anchor"] {
+                if !to_doc_err(element_dict.contains(key))? {
+                    to_doc_err(element_dict.set_item(key, "center"))?;
+                }
+            }
+        }
+        let rotation: f32 = opt_or(dict, "rotation", 0.0)?;
+        if ![rotation, placement.0, placement.1].iter().all(|v| v.is_finite()) {
+            return Err(RupdfError::InvalidDocument("rotation and placement must be finite numbers".to_string()));
+        }
+        Ok(Self {
+            element: Element::from_py_indexed(&element_dict, 0, ctx)?,
+            opacity,
+            rotation,
+            placement,
+            behind: opt_or(dict, "behind", false)?,
+        })
+    }
+
+    /// The watermark as placed on a `width × height` page
+    pub fn placed(&self, width: f32, height: f32) -> Element {
+        Element::Group(GroupElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: self.element.visible(),
+            elements: vec![self.element.clone()],
+            x: self.placement.0 * width,
+            y: self.placement.1 * height,
+            rotation: -self.rotation,
+            scale: 1.0,
+            transparency_group: self.opacity < 1.0,
+            opacity: self.opacity,
+        })
+    }
 }
 
-/// Image resource - either path or bytes
+/// A band of elements drawn on every page from the document's `header` or
+/// `footer`, laid out from a point `offset` in from the top or bottom edge
 #[derive(Debug, Clone)]
-pub enum ImageSource {
-    Path(String),
-    Bytes(Vec<u8>),
+pub struct PageRegion {
+    pub elements: Vec<Element>,
+    pub offset: f32,
+    /// Leave the first page, a cover, without it
+    pub skip_first: bool,
 }
 
-/// All resources for a document
-#[derive(Debug, Clone, Default)]
-pub struct Resources {
-    pub fonts: HashMap<String, FontSource>,
-    pub images: HashMap<String, ImageSource>,
+impl PageRegion {
+    /// Parse a list of elements, or a dict of `elements`, `offset` and
+    /// `skip_first`.
+    fn from_py<'py>(value: &Bound<'py, PyAny>, ctx: &ParseContext<'py>) -> Result<Self> {
+        let (list, offset, skip_first) = match value.cast::<PyDict>() {
+            Ok(dict) => {
+                if ctx.strict {
+                    keys::check(dict, keys::REGION, "header and footer")?;
+                }
+                let offset = opt_len_or(dict, "offset", 0.0, ctx)?;
+                if !offset.is_finite() {
+                    return Err(RupdfError::InvalidDocument(format!("offset must be a finite number, got {}", offset)));
+                }
+                (req::<Bound<'py, PyList>>(dict, "elements")?, offset, opt_or(dict, "skip_first", false)?)
+            }
+            Err(_) => {
+                let list = value.cast::<PyList>().map_err(|_| RupdfError::InvalidDocument(
+                    "must be a list of elements or a dict with 'elements'".to_string(),
+                ))?;
+                (list.clone(), 0.0, false)
+            }
+        };
+        let mut elements = Vec::with_capacity(list.len());
+        for (i, item) in list.iter().enumerate() {
+            let dict = item.cast::<PyDict>()
+                .map_err(|_| RupdfError::InvalidDocument(format!("Element {} must be a dict", i)))?;
+            elements.push(Element::from_py_indexed(dict, i, ctx)?);
+        }
+        Ok(Self { elements, offset, skip_first })
+    }
+
+    /// The region as placed with its origin `y` down the page
+    fn placed(&self, y: f32) -> Element {
+        Element::Group(GroupElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            elements: self.elements.clone(),
+            x: 0.0,
+            y,
+            rotation: 0.0,
+            scale: 1.0,
+            transparency_group: false,
+            opacity: 1.0,
+        })
+    }
+}
+
+/// A named list of repeating elements drawn only when a render selects
+/// it with `include_layers`
+#[derive(Debug, Clone)]
+pub struct Layer {
+    pub name: String,
+    pub elements: Vec<RepeatingElement>,
 }
 
 /// Complete document
@@ -434,31 +3268,86 @@ pub struct Resources {
 pub struct Document {
     pub metadata: Metadata,
     pub pages: Vec<Page>,
+    /// Elements drawn on every page, from `repeating_elements` and page 0
+    /// elements flagged `every_page`
+    pub repeating: Vec<RepeatingElement>,
+    /// Layers from `layers`, until `select_layers` moves the selected
+    /// ones into `repeating` and drops the rest
+    pub layers: Vec<Layer>,
     pub resources: Resources,
+    /// Where viewers open the document
+    pub open_action: Option<OpenAction>,
+    /// Where coordinates are measured from. Elements are parsed in it and
+    /// moved to top-left coordinates by `origin::flip` before rendering;
+    /// the open action's point stays in it.
+    pub origin: Origin,
+    /// What bare numbers are measured in, from `units`. Lengths in the
+    /// document are converted while parsing; this is kept for options
+    /// given with it, such as `bounds_margin`.
+    pub units: Units,
+    /// From `watermark`, until `place_watermark` adds it to `repeating`
+    pub watermark: Option<Watermark>,
+    /// From `header` and `footer`, until `place_regions` adds them to
+    /// `repeating`
+    pub header: Option<PageRegion>,
+    pub footer: Option<PageRegion>,
 }
 
 // Parsing helpers
 
-fn get_optional<'py, T>(dict: &Bound<'py, PyDict>, key: &str) -> PyResult<Option<T>>
+/// Key lookup used by the parsing helpers: a plain dict, or an element dict
+/// layered over the document `defaults`.
+trait Lookup<'py> {
+    /// The value for `key`, plus the defaults section it was inherited from
+    /// (`None` when the value is set directly).
+    fn lookup(&self, key: &str) -> PyResult<Option<(Bound<'py, PyAny>, Option<&str>)>>;
+}
+
+impl<'py> Lookup<'py> for Bound<'py, PyDict> {
+    fn lookup(&self, key: &str) -> PyResult<Option<(Bound<'py, PyAny>, Option<&str>)>> {
+        Ok(self.get_item(key)?.map(|v| (v, None)))
+    }
+}
+
+impl<'py> Lookup<'py> for Borrowed<'_, 'py, PyDict> {
+    fn lookup(&self, key: &str) -> PyResult<Option<(Bound<'py, PyAny>, Option<&str>)>> {
+        Ok(self.get_item(key)?.map(|v| (v, None)))
+    }
+}
+
+/// Extract a looked-up value, naming the defaults section on failure so the
+/// error doesn't read as if the element itself were wrong.
+fn extract_value<'py, T>(val: Bound<'py, PyAny>, origin: Option<&str>, key: &str) -> PyResult<T>
+where
+    for<'a> T: FromPyObject<'a, 'py>,
+{
+    val.extract().map_err(Into::into).map_err(|e: PyErr| match origin {
+        Some(section) => pyo3::exceptions::PyValueError::new_err(format!(
+            "invalid value for '{}' inherited from {}: {}",
+            key, section, e
+        )),
+        None => e,
+    })
+}
+
+fn get_optional<'py, T>(dict: &impl Lookup<'py>, key: &str) -> PyResult<Option<T>>
 where
     for<'a> T: FromPyObject<'a, 'py>,
 {
-    match dict.get_item(key)? {
-        Some(val) if !val.is_none() => Ok(Some(val.extract().map_err(Into::into)?)),
+    match dict.lookup(key)? {
+        Some((val, origin)) if !val.is_none() => Ok(Some(extract_value(val, origin, key)?)),
         _ => Ok(None),
     }
 }
 
-fn get_required<'py, T>(dict: &Bound<'py, PyDict>, key: &str) -> PyResult<T>
+fn get_required<'py, T>(dict: &impl Lookup<'py>, key: &str) -> PyResult<T>
 where
     for<'a> T: FromPyObject<'a, 'py>,
 {
-    dict.get_item(key)?
-        .ok_or_else(|| {
-            pyo3::exceptions::PyKeyError::new_err(format!("Missing required key: '{}'", key))
-        })?
-        .extract()
-        .map_err(Into::into)
+    let (val, origin) = dict.lookup(key)?.ok_or_else(|| {
+        pyo3::exceptions::PyKeyError::new_err(format!("Missing required key: '{}'", key))
+    })?;
+    extract_value(val, origin, key)
 }
 
 /// Convert PyResult to our Result, wrapping errors in InvalidDocument
@@ -467,7 +3356,7 @@ fn to_doc_err<T>(result: PyResult<T>) -> Result<T> {
 }
 
 /// Get a required field from dict, returning InvalidDocument error on failure
-fn req<'py, T>(dict: &Bound<'py, PyDict>, key: &str) -> Result<T>
+fn req<'py, T>(dict: &impl Lookup<'py>, key: &str) -> Result<T>
 where
     for<'a> T: FromPyObject<'a, 'py>,
 {
@@ -475,7 +3364,7 @@ where
 }
 
 /// Get an optional field from dict with a default value
-fn opt_or<'py, T>(dict: &Bound<'py, PyDict>, key: &str, default: T) -> Result<T>
+fn opt_or<'py, T>(dict: &impl Lookup<'py>, key: &str, default: T) -> Result<T>
 where
     for<'a> T: FromPyObject<'a, 'py>,
 {
@@ -483,7 +3372,7 @@ where
 }
 
 /// Get an optional field from dict with Default::default()
-fn opt_default<'py, T>(dict: &Bound<'py, PyDict>, key: &str) -> Result<T>
+fn opt_default<'py, T>(dict: &impl Lookup<'py>, key: &str) -> Result<T>
 where
     for<'a> T: FromPyObject<'a, 'py> + Default,
 {
@@ -491,85 +3380,1323 @@ where
 }
 
 /// Get an optional field from dict
-fn opt<'py, T>(dict: &Bound<'py, PyDict>, key: &str) -> Result<Option<T>>
+fn opt<'py, T>(dict: &impl Lookup<'py>, key: &str) -> Result<Option<T>>
 where
     for<'a> T: FromPyObject<'a, 'py>,
 {
     to_doc_err(get_optional(dict, key))
 }
 
+/// Canonical element type name, folding the aliases accepted by
+/// `Element::from_py_indexed`. `None` for unknown types.
+fn canonical_element_type(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "text" => "text",
+        "textbox" => "textbox",
+        "rect" => "rect",
+        "line" => "line",
+        "polygon" => "polygon",
+        "leader" => "leader",
+        "progress_ring" => "progress_ring",
+        "image" => "image",
+        "barcode" | "barcode128" => "barcode",
+        "gs1_128" | "gs1-128" | "gs1" => "gs1_128",
+        "qrcode" | "qr" => "qrcode",
+        "datamatrix" => "datamatrix",
+        "gs1_datamatrix" | "gs1-datamatrix" => "gs1_datamatrix",
+        "table" => "table",
+        "check" | "checkbox" => "check",
+        "note" => "note",
+        "signature_line" => "signature_line",
+        "image_placeholder" => "image_placeholder",
+        "grid" => "grid",
+        "group" => "group",
+        "fragment" => "fragment",
+        "raw_content" => "raw_content",
+        _ => return None,
+    })
+}
+
+/// Document-level `defaults`: values filled into elements that omit a key.
+///
+/// Keys whose name is an element type and whose value is a dict form a
+/// per-type section (`{"text": {"font": "body"}}`); every other key is a
+/// flat default shared by all element types. Lookup order is the element
+/// itself, then its type section, then the flat defaults.
+#[derive(Default, Clone)]
+pub struct Defaults<'py> {
+    flat: Option<Bound<'py, PyDict>>,
+    by_type: HashMap<&'static str, Bound<'py, PyDict>>,
+}
+
+impl<'py> Defaults<'py> {
+    /// With `strict`, every key must be accepted by the section's type, or
+    /// for flat keys, by some element type.
+    pub fn from_py(dict: &Bound<'py, PyDict>, strict: bool) -> Result<Self> {
+        let py = dict.py();
+        let flat = PyDict::new(py);
+        let mut by_type = HashMap::new();
+        for (key, value) in dict.iter() {
+            let name: String = key.extract()
+                .map_err(|e| RupdfError::InvalidDocument(format!("defaults: key must be a string: {}", e)))?;
+            if name == "type" {
+                return Err(RupdfError::InvalidDocument(
+                    "defaults: 'type' cannot be defaulted".to_string(),
+                ));
+            }
+            match (canonical_element_type(&name), value.cast::<PyDict>()) {
+                (Some(kind), Ok(section)) => {
+                    if strict {
+                        keys::check_element(section, kind)
+                            .map_err(|e| RupdfError::InvalidDocument(format!("defaults.{}: {}", name, e)))?;
+                    }
+                    by_type.insert(kind, section.clone());
+                }
+                (Some(_), Err(_)) => {
+                    return Err(RupdfError::InvalidDocument(format!(
+                        "defaults.{}: per-type defaults must be a dict", name
+                    )));
+                }
+                (None, _) => {
+                    if strict && !keys::any_element_accepts(&name) {
+                        return Err(RupdfError::InvalidDocument(format!(
+                            "defaults: unknown key '{}' for any element type", name
+                        )));
+                    }
+                    to_doc_err(flat.set_item(key, value))?;
+                }
+            }
+        }
+        Ok(Self {
+            flat: (!flat.is_empty()).then_some(flat),
+            by_type,
+        })
+    }
+}
+
+/// An element dict layered over the defaults for its type.
+struct ElementFields<'a, 'py> {
+    dict: &'a Bound<'py, PyDict>,
+    /// Canonical element type, when known
+    kind: Option<&'static str>,
+    /// (label for error messages, per-type section)
+    section: Option<(String, &'a Bound<'py, PyDict>)>,
+    flat: Option<&'a Bound<'py, PyDict>>,
+}
+
+impl<'py> Lookup<'py> for ElementFields<'_, 'py> {
+    fn lookup(&self, key: &str) -> PyResult<Option<(Bound<'py, PyAny>, Option<&str>)>> {
+        debug_assert!(
+            self.kind.is_none_or(|kind| keys::element_accepts(kind, key)),
+            "'{}' is read for {:?} elements but not listed in keys.rs",
+            key,
+            self.kind
+        );
+        if let Some(v) = self.dict.get_item(key)? {
+            return Ok(Some((v, None)));
+        }
+        if let Some((label, section)) = &self.section {
+            if let Some(v) = section.get_item(key)? {
+                return Ok(Some((v, Some(label.as_str()))));
+            }
+        }
+        if let Some(flat) = self.flat {
+            if let Some(v) = flat.get_item(key)? {
+                return Ok(Some((v, Some("defaults"))));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// State shared across page and element parsing
+#[derive(Default)]
+pub struct ParseContext<'py> {
+    pub defaults: Defaults<'py>,
+    pub units: Units,
+    /// The page whose elements are being parsed, for page-relative lengths
+    pub frame: Option<PageFrame>,
+    /// Fragments parsed so far, for `fragment` elements to place
+    pub fragments: HashMap<String, Arc<Fragment>>,
+    /// The document's named styles, for `markup: true` text
+    pub styles: HashMap<String, TextStyle>,
+    /// The document's font families, for text set by `family`
+    pub font_families: HashMap<String, FontFamily>,
+    /// Reject keys a dict doesn't accept (`strict_keys`)
+    pub strict: bool,
+    /// The document's `origin`
+    pub origin: Origin,
+    /// The aliases in `resources.fonts`, sorted, so elements can check a
+    /// font exists while parsing; `None` outside a document
+    pub fonts: Option<Vec<String>>,
+    /// Keep text as given rather than in NFC (`normalize_text: false`)
+    pub raw_text: bool,
+    /// Every font name elements give, mapped to whether only `defaults`
+    /// gave it; the copies a page makes share the document's
+    pub font_names: Rc<RefCell<HashMap<String, bool>>>,
+}
+
+impl<'py> ParseContext<'py> {
+    /// Resolve a length for the field `key` to points.
+    fn to_points(&self, length: PageLength, key: &str) -> Result<f32> {
+        length.to_points(self.units, self.frame.as_ref(), key)
+    }
+
+    /// Text to draw, NFC-normalized unless the document opts out, so every
+    /// later step measures and encodes the same characters
+    fn text(&self, text: String) -> String {
+        if self.raw_text {
+            text
+        } else {
+            crate::runs::nfc(&text)
+        }
+    }
+
+    /// Note the fonts an element names and whether it inherited them, for
+    /// `Resources::inherited_fonts`. Values are checked when the element
+    /// reads them, so ones that don't parse are skipped here.
+    fn note_fonts(&self, fields: &ElementFields<'_, 'py>) -> Result<()> {
+        let Some(kind) = fields.kind else {
+            return Ok(());
+        };
+        for key in ["font", "header_font", "emoji_font", "font_fallback"] {
+            if !keys::element_accepts(kind, key) {
+                continue;
+            }
+            let Some((value, origin)) = to_doc_err(fields.lookup(key))? else {
+                continue;
+            };
+            let names = match value.extract::<String>() {
+                Ok(name) => vec![name],
+                Err(_) => value.extract::<Vec<String>>().unwrap_or_default(),
+            };
+            let mut fonts = self.font_names.borrow_mut();
+            for name in names {
+                *fonts.entry(name).or_insert(true) &= origin.is_some();
+            }
+        }
+        Ok(())
+    }
+
+    fn fields<'a>(&'a self, dict: &'a Bound<'py, PyDict>, element_type: &str) -> ElementFields<'a, 'py> {
+        let kind = canonical_element_type(element_type);
+        let section = kind
+            .and_then(|kind| {
+                self.defaults.by_type.get(kind).map(|d| (format!("defaults.{}", kind), d))
+            });
+        ElementFields {
+            dict,
+            kind,
+            section,
+            flat: self.defaults.flat.as_ref(),
+        }
+    }
+}
+
+/// Get a required length, converted to points
+fn req_len<'py>(dict: &impl Lookup<'py>, key: &str, ctx: &ParseContext<'py>) -> Result<f32> {
+    ctx.to_points(req(dict, key)?, key)
+}
+
+/// Get an optional length, converted to points
+fn opt_len<'py>(dict: &impl Lookup<'py>, key: &str, ctx: &ParseContext<'py>) -> Result<Option<f32>> {
+    opt::<PageLength>(dict, key)?.map(|l| ctx.to_points(l, key)).transpose()
+}
+
+/// Get an optional length, converted to points, with a default already in points
+fn opt_len_or<'py>(dict: &impl Lookup<'py>, key: &str, default: f32, ctx: &ParseContext<'py>) -> Result<f32> {
+    opt_len(dict, key, ctx).map(|l| l.unwrap_or(default))
+}
+
+/// Get an optional dash pattern from `dash` and `dash_phase`. An empty
+/// `dash` list means a solid stroke.
+/// Get an optional `dash`: a list of dash and gap lengths, or a preset
+/// (`"dashed"`, `"dotted"`) scaled to the `stroke` width so it keeps its
+/// look at any width.
+fn opt_dash<'py>(dict: &impl Lookup<'py>, stroke: f32, ctx: &ParseContext<'py>) -> Result<Option<StrokeDash>> {
+    let Some(value) = opt::<Bound<'py, PyAny>>(dict, "dash")? else {
+        return Ok(None);
+    };
+    let phase = opt_len_or(dict, "dash_phase", 0.0, ctx)?;
+    if let Ok(preset) = value.extract::<String>() {
+        let dash = |array: Vec<f32>, round_caps| Ok(Some(StrokeDash { array, phase, round_caps }));
+        return match preset.as_str() {
+            // A hairline has no width to scale by, so its presets use 1pt
+            "dashed" => dash(vec![3.0 * stroke.max(1.0), 2.0 * stroke.max(1.0)], false),
+            // Round caps turn zero-length dashes into dots a stroke across
+            "dotted" if stroke > 0.0 => dash(vec![0.0, 2.0 * stroke], true),
+            "dotted" => dash(vec![1.0, 1.0], false),
+            other => Err(RupdfError::InvalidDocument(format!(
+                "Invalid dash: '{}'. Must be 'dashed', 'dotted' or a list of lengths",
+                other
+            ))),
+        };
+    }
+    let lengths: Vec<Length> = to_doc_err(value.extract())?;
+    if lengths.is_empty() {
+        return Ok(None);
+    }
+    let array: Vec<f32> = lengths.into_iter().map(|l| ctx.units.to_points(l)).collect();
+    if array.iter().any(|&v| v < 0.0) || array.iter().all(|&v| v == 0.0) {
+        return Err(RupdfError::InvalidDocument(
+            "dash must be a list of non-negative lengths, not all zero".to_string(),
+        ));
+    }
+    Ok(Some(StrokeDash { array, phase, round_caps: false }))
+}
+
+/// Get an optional `corner_radius`: one length for every corner, or four
+/// as `(top_left, top_right, bottom_right, bottom_left)`.
+fn opt_corner_radius<'py>(dict: &impl Lookup<'py>, ctx: &ParseContext<'py>) -> Result<CornerRadii> {
+    match opt::<Bound<'py, PyAny>>(dict, "corner_radius")? {
+        Some(value) => corner_radii(&value, "corner_radius", ctx),
+        None => Ok(CornerRadii::default()),
+    }
+}
+
+/// Get an optional image `clip`: `"circle"`, `"ellipse"`, or corner radii
+/// given as for `corner_radius`.
+fn opt_image_clip<'py>(dict: &impl Lookup<'py>, ctx: &ParseContext<'py>) -> Result<Option<ImageClip>> {
+    let Some(value) = opt::<Bound<'py, PyAny>>(dict, "clip")? else {
+        return Ok(None);
+    };
+    match value.extract::<String>().as_deref() {
+        Ok("circle") => Ok(Some(ImageClip::Circle)),
+        Ok("ellipse") => Ok(Some(ImageClip::Ellipse)),
+        Ok(name) if value.extract::<PageLength>().is_err() => Err(RupdfError::InvalidDocument(format!(
+            "clip must be 'circle', 'ellipse' or a corner radius, got '{}'", name
+        ))),
+        _ => Ok(Some(ImageClip::Rounded(corner_radii(&value, "clip", ctx)?))),
+    }
+}
+
+/// Corner radii from `value`, the `key` field: one length for every
+/// corner, or four as `(top_left, top_right, bottom_right, bottom_left)`
+fn corner_radii<'py>(value: &Bound<'py, PyAny>, key: &str, ctx: &ParseContext<'py>) -> Result<CornerRadii> {
+    if let Ok(length) = value.extract::<PageLength>() {
+        return Ok(ctx.to_points(length, key)?.into());
+    }
+    let radii: Option<Vec<f32>> = value
+        .extract::<Vec<Length>>()
+        .ok()
+        .map(|lengths| lengths.into_iter().map(|l| ctx.units.to_points(l)).collect());
+    match radii.as_deref() {
+        Some(&[top_left, top_right, bottom_right, bottom_left]) => {
+            Ok(CornerRadii { top_left, top_right, bottom_right, bottom_left })
+        }
+        _ => Err(RupdfError::InvalidDocument(format!(
+            "{} must be a length or a (top_left, top_right, bottom_right, bottom_left) tuple of lengths",
+            key
+        ))),
+    }
+}
+
+/// Get an optional `stroke`: a length, or `"hairline"` for the device's
+/// thinnest line. Returns the width in points and whether it is a hairline.
+fn opt_stroke<'py>(dict: &impl Lookup<'py>, default: f32, ctx: &ParseContext<'py>) -> Result<(f32, bool)> {
+    let Some(value) = opt::<Bound<'py, PyAny>>(dict, "stroke")? else {
+        return Ok((default, false));
+    };
+    if value.extract::<String>().is_ok_and(|s| s == "hairline") {
+        return Ok((0.0, true));
+    }
+    Ok((ctx.units.to_points(to_doc_err(value.extract::<Length>())?), false))
+}
+
+/// Get an optional `miter_limit`, which PDF requires to be at least 1
+fn opt_miter_limit<'py>(dict: &impl Lookup<'py>) -> Result<Option<f32>> {
+    let limit: Option<f32> = opt(dict, "miter_limit")?;
+    match limit {
+        Some(limit) if limit < 1.0 => Err(RupdfError::InvalidDocument(format!(
+            "miter_limit must be at least 1, got {}",
+            limit
+        ))),
+        _ => Ok(limit),
+    }
+}
+
+/// Get an optional `fill_pattern` dict. Spacing defaults to 4pt and the
+/// line width to 0.5pt.
+fn opt_fill_pattern<'py>(dict: &impl Lookup<'py>, ctx: &ParseContext<'py>) -> Result<Option<FillPattern>> {
+    let Some(pattern) = opt::<Bound<'py, PyDict>>(dict, "fill_pattern")? else {
+        return Ok(None);
+    };
+    let spacing = opt_len_or(&pattern, "spacing", 4.0, ctx)?;
+    let line_width = opt_len_or(&pattern, "line_width", 0.5, ctx)?;
+    for (key, value) in [("spacing", spacing), ("line_width", line_width)] {
+        if value.is_nan() || value <= 0.0 {
+            return Err(RupdfError::InvalidDocument(format!(
+                "fill_pattern {} must be positive, got {}",
+                key, value
+            )));
+        }
+    }
+    Ok(Some(FillPattern {
+        style: opt_default(&pattern, "style")?,
+        spacing,
+        line_width,
+        color: opt_or(&pattern, "color", Color::black())?,
+    }))
+}
+
+/// Get an optional `gradient` dict. A radial gradient's `center` and
+/// `radius` are lengths in the document's units.
+fn opt_gradient<'py>(dict: &impl Lookup<'py>, ctx: &ParseContext<'py>) -> Result<Option<Gradient>> {
+    let Some(mut gradient) = opt::<Gradient>(dict, "gradient")? else {
+        return Ok(None);
+    };
+    if let GradientKind::Radial { center, radius } = &mut gradient.kind {
+        let spec: Bound<'py, PyDict> = req(dict, "gradient")?;
+        let points = |length: Length| ctx.units.to_points(length);
+        *center = opt::<(Length, Length)>(&spec, "center")?.map(|(x, y)| (points(x), points(y)));
+        *radius = opt::<Length>(&spec, "radius")?.map(points);
+    }
+    Ok(Some(gradient))
+}
+
+/// Get an optional `rotation_origin`: `"center"` or an (x, y) point
+/// from the element's top-left corner, in the document's units.
+fn opt_rotation_origin<'py>(dict: &impl Lookup<'py>, ctx: &ParseContext<'py>) -> Result<RotationOrigin> {
+    let Some(value) = opt::<Bound<'py, PyAny>>(dict, "rotation_origin")? else {
+        return Ok(RotationOrigin::Center);
+    };
+    if value.extract::<String>().is_ok_and(|s| s == "center") {
+        return Ok(RotationOrigin::Center);
+    }
+    match value.extract::<(Length, Length)>() {
+        Ok((x, y)) => Ok(RotationOrigin::Point(ctx.units.to_points(x), ctx.units.to_points(y))),
+        Err(_) => Err(RupdfError::InvalidDocument(
+            "rotation_origin must be 'center' or an (x, y) point from the top-left corner".to_string(),
+        )),
+    }
+}
+
+/// Get a barcode's `font`. Left out, it is `"mono"`, or the document's
+/// only font when there is no `"mono"`. Human-readable text needs it to
+/// exist, which is checked here rather than when the text is drawn.
+fn barcode_font<'py>(dict: &impl Lookup<'py>, human_readable: bool, ctx: &ParseContext<'py>) -> Result<String> {
+    let given = opt::<String>(dict, "font")?;
+    let Some(fonts) = ctx.fonts.as_ref().filter(|_| human_readable) else {
+        return Ok(given.unwrap_or_else(|| "mono".to_string()));
+    };
+    let known = |name: &str| fonts.iter().any(|font| font == name);
+    let registered = match fonts.as_slice() {
+        [] => "none are registered".to_string(),
+        fonts => format!("registered: {}", fonts.join(", ")),
+    };
+    match given {
+        Some(font) if known(&font) => Ok(font),
+        Some(font) => Err(RupdfError::InvalidDocument(format!(
+            "human_readable text needs font '{}', which is not in resources.fonts ({})", font, registered
+        ))),
+        None if known("mono") => Ok("mono".to_string()),
+        None => match fonts.as_slice() {
+            [only] => Ok(only.clone()),
+            _ => Err(RupdfError::InvalidDocument(format!(
+                "human_readable text needs a font; 'font' was not given, so it defaulted to 'mono', \
+                 which is not in resources.fonts ({})",
+                registered
+            ))),
+        },
+    }
+}
+
+/// Get an image's optional `quad`: three `(x, y)` corners that must span
+/// a parallelogram, not a line.
+fn opt_quad<'py>(dict: &impl Lookup<'py>, ctx: &ParseContext<'py>) -> Result<Option<ImageQuad>> {
+    let Some(points) = opt::<Vec<(PageLength, PageLength)>>(dict, "quad")? else {
+        return Ok(None);
+    };
+    let [origin, x_corner, y_corner] = points
+        .into_iter()
+        .map(|(x, y)| Ok((ctx.to_points(x, "x")?, ctx.to_points(y, "y")?)))
+        .collect::<Result<Vec<_>>>()?[..]
+    else {
+        return Err(RupdfError::InvalidDocument(
+            "quad must be 3 corners: the image's top-left, top-right and bottom-left".to_string(),
+        ));
+    };
+    let quad = ImageQuad { origin, x_corner, y_corner };
+    let (w, h) = quad.size();
+    let (ux, uy) = (x_corner.0 - origin.0, x_corner.1 - origin.1);
+    let (vx, vy) = (y_corner.0 - origin.0, y_corner.1 - origin.1);
+    // The sine of the angle between the edges, so the check doesn't
+    // depend on the quad's size
+    let sine = (ux * vy - uy * vx) / (w * h);
+    if sine.is_nan() || sine.abs() <= 1e-4 {
+        return Err(RupdfError::InvalidDocument(format!(
+            "quad corners ({}, {}), ({}, {}) and ({}, {}) are collinear, so the image would have no area",
+            origin.0, origin.1, x_corner.0, x_corner.1, y_corner.0, y_corner.1
+        )));
+    }
+    Ok(Some(quad))
+}
+
+/// Get optional `skew_x` / `skew_y` angles in degrees. A quarter turn or
+/// more would shear the element to infinity.
+fn opt_skew<'py>(dict: &impl Lookup<'py>) -> Result<Skew> {
+    let mut skew = Skew::default();
+    for (key, angle) in [("skew_x", &mut skew.x), ("skew_y", &mut skew.y)] {
+        *angle = opt_or(dict, key, 0.0)?;
+        if angle.is_nan() || angle.abs() >= 90.0 {
+            return Err(RupdfError::InvalidDocument(format!(
+                "{} must be between -90 and 90 degrees, got {}",
+                key, angle
+            )));
+        }
+    }
+    Ok(skew)
+}
+
+/// Get a required rich `text`: a string, or a list of spans. A span is a
+/// string, `{"text": ...}` or an inline image `{"image_ref", "h",
+/// "baseline_shift"}`. Images are flattened into the text as placeholder
+/// characters (see `runs::inline_image_char`).
+fn req_rich_text<'py>(dict: &impl Lookup<'py>, ctx: &ParseContext<'py>) -> Result<(String, Vec<InlineImage>)> {
+    let value = req::<Bound<'py, PyAny>>(dict, "text")?;
+    if let Ok(text) = value.extract::<String>() {
+        return Ok((text, Vec::new()));
+    }
+    let spans = value.cast::<PyList>().map_err(|_| {
+        RupdfError::InvalidDocument("text must be a string or a list of spans".to_string())
+    })?;
+    let fail = |i: usize, message: String| RupdfError::InvalidDocument(format!("text span {}: {}", i, message));
+    let span_err = |i: usize, e: RupdfError| match e {
+        RupdfError::InvalidDocument(message) => fail(i, message),
+        other => other,
+    };
+    let mut text = String::new();
+    let mut images = Vec::new();
+    for (i, span) in spans.iter().enumerate() {
+        if let Ok(s) = span.extract::<String>() {
+            text.push_str(&s);
+            continue;
+        }
+        let Ok(span) = span.cast::<PyDict>() else {
+            return Err(fail(i, "must be a string or a dict".to_string()));
+        };
+        if to_doc_err(span.contains("image_ref"))? {
+            let h = req_len(span, "h", ctx).map_err(|e| span_err(i, e))?;
+            if h <= 0.0 {
+                return Err(fail(i, format!("h must be positive, got {}", h)));
+            }
+            text.push(crate::runs::inline_image_char(images.len()));
+            images.push(InlineImage {
+                image_ref: req(span, "image_ref").map_err(|e| span_err(i, e))?,
+                h,
+                baseline_shift: opt_len_or(span, "baseline_shift", 0.0, ctx).map_err(|e| span_err(i, e))?,
+            });
+        } else {
+            text.push_str(&req::<String>(span, "text").map_err(|e| span_err(i, e))?);
+        }
+    }
+    Ok((text, images))
+}
+
+/// Get a text element's optional `max_width`, with its `overflow` and the
+/// `min_size` shrinking stops at (default half of `size`).
+fn opt_max_width<'py>(dict: &impl Lookup<'py>, size: f32, ctx: &ParseContext<'py>) -> Result<Option<MaxWidth>> {
+    let Some(width) = opt_len(dict, "max_width", ctx)? else {
+        return Ok(None);
+    };
+    if width.is_nan() || width <= 0.0 {
+        return Err(RupdfError::InvalidDocument(format!("max_width must be positive, got {}", width)));
+    }
+    let min_size = opt_len_or(dict, "min_size", size / 2.0, ctx)?;
+    if min_size.is_nan() || min_size <= 0.0 || min_size > size {
+        return Err(RupdfError::InvalidDocument(format!(
+            "min_size must be positive and at most size {}, got {}",
+            size, min_size
+        )));
+    }
+    Ok(Some(MaxWidth { width, overflow: opt_default(dict, "overflow")?, min_size }))
+}
+
+/// Get a text element's font alias: `font`, or the face of `family` that
+/// `weight` and `style` pick. With a family, also returns its bold face
+/// in the same style, if mapped, for markup to fall back on.
+fn req_font<'py>(dict: &impl Lookup<'py>, ctx: &ParseContext<'py>) -> Result<(String, Option<String>)> {
+    let weight: Option<FontWeight> = opt(dict, "weight")?;
+    let style: Option<FontStyle> = opt(dict, "style")?;
+    let Some(name) = opt::<String>(dict, "family")? else {
+        if weight.is_some() || style.is_some() {
+            return Err(RupdfError::InvalidDocument("weight and style need a family".to_string()));
+        }
+        return Ok((req(dict, "font")?, None));
+    };
+    let family = ctx.font_families.get(&name).ok_or_else(|| {
+        RupdfError::InvalidDocument(format!("unknown font family '{}'", name))
+    })?;
+    let style = style.unwrap_or_default();
+    let (face, key) = family.face(weight.unwrap_or_default(), style);
+    let face = face.ok_or_else(|| {
+        RupdfError::InvalidDocument(format!("font family '{}' has no {} face", name, key))
+    })?;
+    let bold = family.face(FontWeight::Bold, style).0;
+    Ok((face.to_string(), bold.map(str::to_string)))
+}
+
+/// Flatten `text` as inline markup (see `markup`) when the element sets
+/// `markup: true`, returning it with the styles it uses. Without a `bold`
+/// entry in the document's styles, `**` uses `family_bold`, the bold face
+/// of the element's family.
+fn opt_markup<'py>(
+    dict: &impl Lookup<'py>,
+    text: String,
+    family_bold: Option<String>,
+    ctx: &ParseContext<'py>,
+) -> Result<(String, Vec<TextStyle>)> {
+    if !opt_or(dict, "markup", false)? {
+        return Ok((text, Vec::new()));
+    }
+    let marked = match family_bold {
+        Some(bold) if !ctx.styles.contains_key("bold") => {
+            let mut styles = ctx.styles.clone();
+            styles.insert("bold".to_string(), TextStyle { font: Some(bold), color: None });
+            markup::parse(&text, &styles)
+        }
+        _ => markup::parse(&text, &ctx.styles),
+    }
+    .map_err(|e| RupdfError::InvalidDocument(format!("markup: {}", e)))?;
+    Ok((marked.text, marked.styles))
+}
+
+/// Get a text element's required `text`: a string, or a number to format
+/// as `{"number": ..., "format": {...}}`. The number may be an int, a
+/// float, a `Decimal` or a decimal string; see `numfmt` for the rules.
+fn req_text<'py>(dict: &impl Lookup<'py>) -> Result<String> {
+    let value = req::<Bound<'py, PyAny>>(dict, "text")?;
+    if let Ok(text) = value.extract::<String>() {
+        return Ok(text);
+    }
+    let spec = value.cast::<PyDict>().map_err(|_| {
+        RupdfError::InvalidDocument("text must be a string or a {\"number\": ...} dict".to_string())
+    })?;
+    let number = req::<Bound<'py, PyAny>>(spec, "number")?;
+    let digits = if number.is_instance_of::<PyBool>() {
+        return Err(RupdfError::InvalidDocument("text number must be a number, got a bool".to_string()));
+    } else if let Ok(float) = number.cast::<PyFloat>() {
+        // Rust's shortest round-trip form has the same digits as repr()
+        float.value().to_string()
+    } else {
+        to_doc_err(number.str())?.to_string()
+    };
+
+    let mut fmt = NumberFormat::default();
+    if let Some(spec) = opt::<Bound<'py, PyDict>>(spec, "format")? {
+        if let Some(locale) = opt::<String>(&spec, "locale")? {
+            let (thousands, decimal) = numfmt::locale_separators(&locale).ok_or_else(|| {
+                RupdfError::InvalidDocument(format!(
+                    "Unknown locale: '{}'. Set thousands and decimal instead",
+                    locale
+                ))
+            })?;
+            fmt.thousands = thousands.to_string();
+            fmt.decimal = decimal.to_string();
+        }
+        fmt.decimals = opt(&spec, "decimals")?;
+        if let Some(decimals) = fmt.decimals.filter(|&d| d > 20) {
+            return Err(RupdfError::InvalidDocument(format!(
+                "format decimals must be at most 20, got {}",
+                decimals
+            )));
+        }
+        fmt.thousands = opt_or(&spec, "thousands", fmt.thousands)?;
+        fmt.decimal = opt_or(&spec, "decimal", fmt.decimal)?;
+        fmt.prefix = opt_or(&spec, "prefix", fmt.prefix)?;
+        fmt.suffix = opt_or(&spec, "suffix", fmt.suffix)?;
+        fmt.negative = opt_default(&spec, "negative")?;
+    }
+    numfmt::format(&digits, &fmt).map_err(|e| RupdfError::InvalidDocument(format!("text number {}", e)))
+}
+
 /// Helper to add element index context to errors
-fn with_element_context<T>(result: Result<T>, index: usize) -> Result<T> {
+fn with_element_context<T>(result: Result<T>, index: ElementLabel<'_>) -> Result<T> {
     result.map_err(|e| {
         RupdfError::InvalidDocument(format!("Element {}: {}", index, e))
     })
 }
 
 impl Element {
+    /// Canonical type name, for diagnostics
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Element::Text(_) => "text",
+            Element::TextBox(_) => "textbox",
+            Element::Rect(_) => "rect",
+            Element::Line(_) => "line",
+            Element::Polygon(_) => "polygon",
+            Element::Leader(_) => "leader",
+            Element::ProgressRing(_) => "progress_ring",
+            Element::Image(_) => "image",
+            Element::Barcode(b) => match b.kind {
+                BarcodeKind::Code128 => "barcode",
+                BarcodeKind::Gs1_128 => "gs1_128",
+            },
+            Element::QRCode(_) => "qrcode",
+            Element::DataMatrix(dm) => match dm.kind {
+                DataMatrixKind::Plain => "datamatrix",
+                DataMatrixKind::Gs1 => "gs1_datamatrix",
+            },
+            Element::Table(_) => "table",
+            Element::Check(_) => "check",
+            Element::Note(_) => "note",
+            Element::SignatureLine(_) => "signature_line",
+            Element::ImagePlaceholder(_) => "image_placeholder",
+            Element::Grid(_) => "grid",
+            Element::Group(_) => "group",
+            Element::Fragment(_) => "fragment",
+            Element::RawContent(_) => "raw_content",
+        }
+    }
+
+    /// The caller's `id`, which is never drawn; it only labels the element
+    /// in errors and warnings.
+    pub fn id(&self) -> Option<&str> {
+        match self {
+            Element::Text(e) => e.id.as_deref(),
+            Element::TextBox(e) => e.id.as_deref(),
+            Element::Rect(e) => e.id.as_deref(),
+            Element::Line(e) => e.id.as_deref(),
+            Element::Polygon(e) => e.id.as_deref(),
+            Element::Leader(e) => e.id.as_deref(),
+            Element::ProgressRing(e) => e.id.as_deref(),
+            Element::Image(e) => e.id.as_deref(),
+            Element::Barcode(e) => e.id.as_deref(),
+            Element::QRCode(e) => e.id.as_deref(),
+            Element::DataMatrix(e) => e.id.as_deref(),
+            Element::Table(e) => e.id.as_deref(),
+            Element::Check(e) => e.id.as_deref(),
+            Element::Note(e) => e.id.as_deref(),
+            Element::SignatureLine(e) => e.id.as_deref(),
+            Element::ImagePlaceholder(e) => e.id.as_deref(),
+            Element::Grid(e) => e.id.as_deref(),
+            Element::Group(e) => e.id.as_deref(),
+            Element::Fragment(e) => e.id.as_deref(),
+            Element::RawContent(e) => e.id.as_deref(),
+        }
+    }
+
+    pub fn tagging(&self) -> Tagging {
+        match self {
+            Element::Text(e) => e.tagging,
+            Element::TextBox(e) => e.tagging,
+            Element::Rect(e) => e.tagging,
+            Element::Line(e) => e.tagging,
+            Element::Polygon(e) => e.tagging,
+            Element::Leader(e) => e.tagging,
+            Element::ProgressRing(e) => e.tagging,
+            Element::Image(e) => e.tagging,
+            Element::Barcode(e) => e.tagging,
+            Element::QRCode(e) => e.tagging,
+            Element::DataMatrix(e) => e.tagging,
+            Element::Table(e) => e.tagging,
+            Element::Check(e) => e.tagging,
+            Element::Note(e) => e.tagging,
+            Element::SignatureLine(e) => e.tagging,
+            Element::ImagePlaceholder(e) => e.tagging,
+            Element::Grid(e) => e.tagging,
+            Element::Group(e) => e.tagging,
+            Element::Fragment(e) => e.tagging,
+            Element::RawContent(e) => e.tagging,
+        }
+    }
+
+    /// Paint order among the element's siblings; lower paints first
+    pub fn z(&self) -> i32 {
+        match self {
+            Element::Text(e) => e.z,
+            Element::TextBox(e) => e.z,
+            Element::Rect(e) => e.z,
+            Element::Line(e) => e.z,
+            Element::Polygon(e) => e.z,
+            Element::Leader(e) => e.z,
+            Element::ProgressRing(e) => e.z,
+            Element::Image(e) => e.z,
+            Element::Barcode(e) => e.z,
+            Element::QRCode(e) => e.z,
+            Element::DataMatrix(e) => e.z,
+            Element::Table(e) => e.z,
+            Element::Check(e) => e.z,
+            Element::Note(e) => e.z,
+            Element::SignatureLine(e) => e.z,
+            Element::ImagePlaceholder(e) => e.z,
+            Element::Grid(e) => e.z,
+            Element::Group(e) => e.z,
+            Element::Fragment(e) => e.z,
+            Element::RawContent(e) => e.z,
+        }
+    }
+
+    /// Whether the element is drawn. Hidden elements stay in their lists,
+    /// keeping indices stable, but are skipped by every render pass.
+    pub fn visible(&self) -> bool {
+        match self {
+            Element::Text(e) => e.visible,
+            Element::TextBox(e) => e.visible,
+            Element::Rect(e) => e.visible,
+            Element::Line(e) => e.visible,
+            Element::Polygon(e) => e.visible,
+            Element::Leader(e) => e.visible,
+            Element::ProgressRing(e) => e.visible,
+            Element::Image(e) => e.visible,
+            Element::Barcode(e) => e.visible,
+            Element::QRCode(e) => e.visible,
+            Element::DataMatrix(e) => e.visible,
+            Element::Table(e) => e.visible,
+            Element::Check(e) => e.visible,
+            Element::Note(e) => e.visible,
+            Element::SignatureLine(e) => e.visible,
+            Element::ImagePlaceholder(e) => e.visible,
+            Element::Grid(e) => e.visible,
+            Element::Group(e) => e.visible,
+            Element::Fragment(e) => e.visible,
+            Element::RawContent(e) => e.visible,
+        }
+    }
+
+    /// `elements` in paint order with their list indices: sorted by `z`,
+    /// stably, so elements with equal `z` keep their list order.
+    pub fn paint_order(elements: &[Element]) -> Vec<(usize, &Element)> {
+        let mut ordered: Vec<_> = elements.iter().enumerate().collect();
+        ordered.sort_by_key(|(_, element)| element.z());
+        ordered
+    }
+
+    /// This element's label at `index` in its list
+    pub fn label(&self, index: usize) -> ElementLabel<'_> {
+        ElementLabel { index, id: self.id() }
+    }
+
     #[allow(dead_code)]
     pub fn from_py<'py>(dict: &Bound<'py, PyDict>) -> Result<Self> {
-        Self::from_py_indexed(dict, 0)
+        Self::from_py_indexed(dict, 0, &ParseContext::default())
     }
 
-    pub fn from_py_indexed<'py>(dict: &Bound<'py, PyDict>, index: usize) -> Result<Self> {
+    pub fn from_py_indexed<'py>(dict: &Bound<'py, PyDict>, index: usize, ctx: &ParseContext<'py>) -> Result<Self> {
+        // Read before the defaults are layered on: ids are never inherited
+        let id: Option<String> = with_element_context(opt(dict, "id"), ElementLabel { index, id: None })?;
+        let index = ElementLabel { index, id: id.as_deref() };
         let element_type: String = with_element_context(req(dict, "type"), index)?;
+        if let Some(kind) = canonical_element_type(&element_type).filter(|_| ctx.strict) {
+            with_element_context(keys::check_element(dict, kind), index)?;
+        }
+        let dict = &ctx.fields(dict, &element_type);
+        with_element_context(ctx.note_fonts(dict), index)?;
+        let tagging = Tagging {
+            reading_order: with_element_context(opt(dict, "reading_order"), index)?,
+            artifact: with_element_context(opt_or(dict, "artifact", false), index)?,
+        };
+        if tagging.artifact && tagging.reading_order.is_some() {
+            return Err(RupdfError::InvalidDocument(format!(
+                "Element {}: reading_order can't be set on an artifact, which has no place in the reading order",
+                index
+            )));
+        }
+        let z: i32 = with_element_context(opt_or(dict, "z", 0), index)?;
+        let visible: bool = with_element_context(opt_or(dict, "visible", true), index)?;
 
         match element_type.as_str() {
-            "text" => Ok(Element::Text(TextElement {
-                x: with_element_context(req(dict, "x"), index)?,
-                y: with_element_context(req(dict, "y"), index)?,
-                text: with_element_context(req(dict, "text"), index)?,
-                font: with_element_context(req(dict, "font"), index)?,
+            "text" => {
+                let (font, family_bold) = with_element_context(req_font(dict, ctx), index)?;
+                let (text, styles) = with_element_context(
+                    req_text(dict).and_then(|text| opt_markup(dict, ctx.text(text), family_bold, ctx)),
+                    index,
+                )?;
+                let size: f32 = with_element_context(req_len(dict, "size", ctx), index)?;
+                let fit_width = match with_element_context(opt::<Bound<'py, PyDict>>(dict, "fit_width"), index)? {
+                    Some(fit) => Some(with_element_context(TextFit::from_py(&fit, ctx), index)?),
+                    None => None,
+                };
+                let max_width = with_element_context(opt_max_width(dict, size, ctx), index)?;
+                if fit_width.is_some() && max_width.is_some() {
+                    return Err(RupdfError::InvalidDocument(format!(
+                        "Element {}: max_width can't be combined with fit_width", index
+                    )));
+                }
+                Ok(Element::Text(TextElement {
+                id: id.clone(),
+                tagging,
+                z,
+                visible,
+                x: with_element_context(req_len(dict, "x", ctx), index)?,
+                y: with_element_context(req_len(dict, "y", ctx), index)?,
+                text,
+                font,
                 font_fallback: with_element_context(opt_or(dict, "font_fallback", Vec::new()), index)?,
                 missing_glyph_policy: with_element_context(opt_default(dict, "missing_glyph_policy"), index)?,
-                size: with_element_context(req(dict, "size"), index)?,
+                emoji: EmojiOptions {
+                    font: with_element_context(opt(dict, "emoji_font"), index)?,
+                    replacement: with_element_context(opt(dict, "emoji_replacement"), index)?,
+                },
+                size,
                 color: with_element_context(opt_or(dict, "color", Color::black()), index)?,
                 align: with_element_context(opt_default(dict, "align"), index)?,
                 vertical_anchor: with_element_context(opt_default(dict, "vertical_anchor"), index)?,
-            })),
+                highlight_color: with_element_context(opt(dict, "highlight_color"), index)?,
+                highlight_padding: with_element_context(opt_len_or(dict, "highlight_padding", 1.0, ctx), index)?,
+                transform: with_element_context(opt_default(dict, "transform"), index)?,
+                fit_width,
+                max_width,
+                skew: with_element_context(opt_skew(dict), index)?,
+                rotation: with_element_context(opt_or(dict, "rotation", 0.0), index)?,
+                outline: with_element_context(opt_or(dict, "outline", false), index)?,
+                styles,
+            }))
+            }
 
             "textbox" => {
-                let size: f32 = with_element_context(req(dict, "size"), index)?;
-                let line_height: f32 = with_element_context(opt_or(dict, "line_height", size * 1.2), index)?;
+                let size: f32 = with_element_context(req_len(dict, "size", ctx), index)?;
+                let line_height_ratio: f32 = with_element_context(opt_or(dict, "line_height_ratio", 1.2), index)?;
+                let line_height: f32 = with_element_context(opt_len_or(dict, "line_height", size * line_height_ratio, ctx), index)?;
+                let w: f32 = with_element_context(req_len(dict, "w", ctx), index)?;
+                let columns: u32 = with_element_context(opt_or(dict, "columns", 1), index)?;
+                let column_gap: f32 = with_element_context(opt_len_or(dict, "column_gap", 12.0, ctx), index)?;
+                if columns == 0 {
+                    return Err(RupdfError::InvalidDocument(format!(
+                        "Element {}: columns must be at least 1", index
+                    )));
+                }
+                if column_gap < 0.0 || (columns > 1 && column_gap * (columns - 1) as f32 >= w) {
+                    return Err(RupdfError::InvalidDocument(format!(
+                        "Element {}: column_gap {} leaves no room for {} columns in width {}",
+                        index, column_gap, columns, w
+                    )));
+                }
+                let (font, family_bold) = with_element_context(req_font(dict, ctx), index)?;
+                let (text, inline_images) = with_element_context(req_rich_text(dict, ctx), index)?;
+                let (text, styles) = with_element_context(opt_markup(dict, ctx.text(text), family_bold, ctx), index)?;
                 Ok(Element::TextBox(TextBoxElement {
-                    x: with_element_context(req(dict, "x"), index)?,
-                    y: with_element_context(req(dict, "y"), index)?,
-                    w: with_element_context(req(dict, "w"), index)?,
-                    h: with_element_context(req(dict, "h"), index)?,
+                    id: id.clone(),
+                    tagging,
+                    z,
+                    visible,
+                    x: with_element_context(req_len(dict, "x", ctx), index)?,
+                    y: with_element_context(req_len(dict, "y", ctx), index)?,
+                    w,
+                    h: with_element_context(req_len(dict, "h", ctx), index)?,
                     box_align_x: with_element_context(opt_default(dict, "box_align_x"), index)?,
-                    box_align_y: with_element_context(opt_default(dict, "box_align_y"), index)?,
+                    // A box is placed by its corner nearest the origin
+                    box_align_y: with_element_context(
+                        opt_or(dict, "box_align_y", match ctx.origin {
+                            Origin::TopLeft => BoxAlignY::Top,
+                            Origin::BottomLeft => BoxAlignY::Bottom,
+                        }),
+                        index,
+                    )?,
                     text_align_x: with_element_context(opt_default(dict, "text_align_x"), index)?,
                     text_align_y: with_element_context(opt_default(dict, "text_align_y"), index)?,
-                    text: with_element_context(req(dict, "text"), index)?,
-                    font: with_element_context(req(dict, "font"), index)?,
+                    text,
+                    inline_images,
+                    font,
                     font_fallback: with_element_context(opt_or(dict, "font_fallback", Vec::new()), index)?,
                     missing_glyph_policy: with_element_context(opt_default(dict, "missing_glyph_policy"), index)?,
+                    emoji: EmojiOptions {
+                        font: with_element_context(opt(dict, "emoji_font"), index)?,
+                        replacement: with_element_context(opt(dict, "emoji_replacement"), index)?,
+                    },
                     size,
                     line_height,
                     color: with_element_context(opt_or(dict, "color", Color::black()), index)?,
+                    columns,
+                    column_gap,
+                    balance: with_element_context(opt_or(dict, "balance", false), index)?,
+                    styles,
+                }))
+            }
+
+            "rect" => {
+                let corner_radius = with_element_context(opt_corner_radius(dict, ctx), index)?;
+                let sides: Option<RectSides> = with_element_context(opt(dict, "sides"), index)?;
+                if sides.is_some() && corner_radius.any() {
+                    return Err(RupdfError::InvalidDocument(format!(
+                        "Element {}: sides cannot be combined with corner_radius",
+                        index
+                    )));
+                }
+                let (stroke, hairline) = with_element_context(opt_stroke(dict, 1.0, ctx), index)?;
+                Ok(Element::Rect(RectElement {
+                    id: id.clone(),
+                    tagging,
+                    z,
+                    visible,
+                    x: with_element_context(req_len(dict, "x", ctx), index)?,
+                    y: with_element_context(req_len(dict, "y", ctx), index)?,
+                    w: with_element_context(req_len(dict, "w", ctx), index)?,
+                    h: with_element_context(req_len(dict, "h", ctx), index)?,
+                    stroke,
+                    stroke_color: with_element_context(opt_or(dict, "stroke_color", Color::black()), index)?,
+                    fill_color: with_element_context(opt(dict, "fill_color"), index)?,
+                    fill_pattern: with_element_context(opt_fill_pattern(dict, ctx), index)?,
+                    gradient: with_element_context(opt_gradient(dict, ctx), index)?,
+                    corner_radius,
+                    corner_style: with_element_context(opt_default(dict, "corner_style"), index)?,
+                    sides,
+                    dash: with_element_context(opt_dash(dict, stroke, ctx), index)?,
+                    miter_limit: with_element_context(opt_miter_limit(dict), index)?,
+                    stroke_align: with_element_context(opt_default(dict, "stroke_align"), index)?,
+                    hairline,
+                    skew: with_element_context(opt_skew(dict), index)?,
+                    rotation: with_element_context(opt_or(dict, "rotation", 0.0), index)?,
+                    rotation_origin: with_element_context(opt_rotation_origin(dict, ctx), index)?,
+                    cap: with_element_context(opt(dict, "cap"), index)?,
+                    join: with_element_context(opt(dict, "join"), index)?,
+                }))
+            }
+
+            "check" | "checkbox" => {
+                let size: f32 = with_element_context(req_len(dict, "size", ctx), index)?;
+                let stroke_color: Color = with_element_context(opt_or(dict, "stroke_color", Color::black()), index)?;
+                let mark_color: Color = with_element_context(opt_or(dict, "mark_color", stroke_color.clone()), index)?;
+                Ok(Element::Check(CheckElement {
+                    id: id.clone(),
+                    tagging,
+                    z,
+                    visible,
+                    x: with_element_context(req_len(dict, "x", ctx), index)?,
+                    y: with_element_context(req_len(dict, "y", ctx), index)?,
+                    size,
+                    state: with_element_context(opt_default(dict, "state"), index)?,
+                    stroke: with_element_context(opt_len_or(dict, "stroke", 1.0, ctx), index)?,
+                    stroke_color,
+                    fill_color: with_element_context(opt(dict, "fill_color"), index)?,
+                    corner_radius: with_element_context(opt_len_or(dict, "corner_radius", 0.0, ctx), index)?,
+                    mark_color,
+                    mark_stroke: with_element_context(opt_len_or(dict, "mark_stroke", size * 0.12, ctx), index)?,
                 }))
             }
 
-            "rect" => Ok(Element::Rect(RectElement {
-                x: with_element_context(req(dict, "x"), index)?,
-                y: with_element_context(req(dict, "y"), index)?,
-                w: with_element_context(req(dict, "w"), index)?,
-                h: with_element_context(req(dict, "h"), index)?,
-                stroke: with_element_context(opt_or(dict, "stroke", 1.0), index)?,
-                stroke_color: with_element_context(opt_or(dict, "stroke_color", Color::black()), index)?,
-                fill_color: with_element_context(opt(dict, "fill_color"), index)?,
-                corner_radius: with_element_context(opt_or(dict, "corner_radius", 0.0), index)?,
+            "note" => Ok(Element::Note(NoteElement {
+                id: id.clone(),
+                tagging,
+                z,
+                visible,
+                x: with_element_context(req_len(dict, "x", ctx), index)?,
+                y: with_element_context(req_len(dict, "y", ctx), index)?,
+                contents: with_element_context(req(dict, "contents"), index)?,
+                title: with_element_context(opt(dict, "title"), index)?,
+                color: with_element_context(opt_or(dict, "color", Color::rgba(255, 235, 0, 255)), index)?,
+                icon: with_element_context(opt_default(dict, "icon"), index)?,
+                open: with_element_context(opt_or(dict, "open", false), index)?,
             })),
 
-            "line" => Ok(Element::Line(LineElement {
-                x1: with_element_context(req(dict, "x1"), index)?,
-                y1: with_element_context(req(dict, "y1"), index)?,
-                x2: with_element_context(req(dict, "x2"), index)?,
-                y2: with_element_context(req(dict, "y2"), index)?,
-                stroke: with_element_context(opt_or(dict, "stroke", 1.0), index)?,
+            "signature_line" => Ok(Element::SignatureLine(SignatureLineElement {
+                id: id.clone(),
+                tagging,
+                z,
+                visible,
+                x: with_element_context(req_len(dict, "x", ctx), index)?,
+                y: with_element_context(req_len(dict, "y", ctx), index)?,
+                w: with_element_context(req_len(dict, "w", ctx), index)?,
+                label: with_element_context(opt::<String>(dict, "label"), index)?.map(|label| ctx.text(label)),
+                sublabels: with_element_context(opt_or(dict, "sublabels", Vec::<String>::new()), index)?
+                    .into_iter()
+                    .map(|sublabel| ctx.text(sublabel))
+                    .collect(),
+                font: with_element_context(req(dict, "font"), index)?,
+                size: with_element_context(opt_len_or(dict, "size", 9.0, ctx), index)?,
                 color: with_element_context(opt_or(dict, "color", Color::black()), index)?,
+                stroke: with_element_context(opt_len_or(dict, "stroke", 0.75, ctx), index)?,
+                mark: with_element_context(opt_or(dict, "mark", false), index)?,
             })),
 
+            "image_placeholder" => {
+                // Without a source image, `aspect` (width / height) stands in
+                // for the image's own proportions when only w or h is given
+                let w: Option<f32> = with_element_context(opt_len(dict, "w", ctx), index)?;
+                let h: Option<f32> = with_element_context(opt_len(dict, "h", ctx), index)?;
+                let aspect: Option<f32> = with_element_context(opt(dict, "aspect"), index)?;
+                if let Some(aspect) = aspect {
+                    if aspect.is_nan() || aspect <= 0.0 {
+                        return Err(RupdfError::InvalidDocument(format!(
+                            "Element {}: aspect must be positive, got {}", index, aspect
+                        )));
+                    }
+                }
+                let (w, h) = match (w, h, aspect) {
+                    (Some(w), Some(h), _) => (w, h),
+                    (Some(w), None, Some(aspect)) => (w, w / aspect),
+                    (None, Some(h), Some(aspect)) => (h * aspect, h),
+                    _ => {
+                        return Err(RupdfError::InvalidDocument(format!(
+                            "Element {}: image_placeholder needs w and h, or one of them with an aspect", index
+                        )));
+                    }
+                };
+                let align_str: String = with_element_context(opt_or(dict, "align", "left".to_string()), index)?;
+                let align = match align_str.as_str() {
+                    "center" => TextAlign::Center,
+                    "right" => TextAlign::Right,
+                    _ => TextAlign::Left,
+                };
+                Ok(Element::ImagePlaceholder(ImagePlaceholderElement {
+                    id: id.clone(),
+                    tagging,
+                    z,
+                    visible,
+                    x: with_element_context(req_len(dict, "x", ctx), index)?,
+                    y: with_element_context(req_len(dict, "y", ctx), index)?,
+                    w,
+                    h,
+                    align,
+                    label: with_element_context(opt::<String>(dict, "label"), index)?.map(|label| ctx.text(label)),
+                    note: with_element_context(opt::<String>(dict, "note"), index)?.map(|note| ctx.text(note)),
+                    font: with_element_context(req(dict, "font"), index)?,
+                    size: with_element_context(opt_len_or(dict, "size", 9.0, ctx), index)?,
+                    color: with_element_context(opt_or(dict, "color", Color::rgba(128, 128, 128, 255)), index)?,
+                    fill_color: with_element_context(opt_or(dict, "fill_color", Color::rgba(230, 230, 230, 255)), index)?,
+                }))
+            }
+
+            "grid" => {
+                let spacing: f32 = with_element_context(req_len(dict, "spacing", ctx), index)?;
+                if spacing <= 0.0 {
+                    return Err(RupdfError::InvalidDocument(format!(
+                        "Element {}: grid spacing must be positive, got {}", index, spacing
+                    )));
+                }
+                let major_every: u32 = with_element_context(opt_or(dict, "major_every", 5), index)?;
+                if major_every == 0 {
+                    return Err(RupdfError::InvalidDocument(format!(
+                        "Element {}: grid major_every must be at least 1", index
+                    )));
+                }
+                Ok(Element::Grid(GridElement {
+                    id: id.clone(),
+                    tagging,
+                    z,
+                    visible,
+                    spacing,
+                    major_every,
+                    color: with_element_context(opt_or(dict, "color", Color::rgba(190, 210, 235, 255)), index)?,
+                    stroke: with_element_context(opt_len_or(dict, "stroke", 0.25, ctx), index)?,
+                    major_stroke: with_element_context(opt_len_or(dict, "major_stroke", 0.75, ctx), index)?,
+                    font: with_element_context(opt(dict, "font"), index)?,
+                    size: with_element_context(opt_len_or(dict, "size", 6.0, ctx), index)?,
+                    label_color: with_element_context(opt_or(dict, "label_color", Color::rgba(90, 90, 90, 255)), index)?,
+                    unit: ctx.units.points_per_unit(),
+                    from_bottom: false,
+                }))
+            }
+
+            "group" => {
+                let list: Bound<'py, PyList> = with_element_context(req(dict, "elements"), index)?;
+                let mut elements = Vec::with_capacity(list.len());
+                for (i, item) in list.iter().enumerate() {
+                    let child = item.cast::<PyDict>().map_err(|_| {
+                        RupdfError::InvalidDocument(format!("Element {}: child {} must be a dict", index, i))
+                    })?;
+                    // Keep the child's own message under the group's index
+                    let parsed = Element::from_py_indexed(child, i, ctx).map_err(|e| match e {
+                        RupdfError::InvalidDocument(msg) => {
+                            RupdfError::InvalidDocument(format!("Element {}: {}", index, msg))
+                        }
+                        other => RupdfError::InvalidDocument(format!("Element {}: {}", index, other)),
+                    })?;
+                    elements.push(parsed);
+                }
+                let transparency_group: bool = with_element_context(opt_or(dict, "transparency_group", false), index)?;
+                let opacity: f32 = with_element_context(opt_or(dict, "opacity", 1.0), index)?;
+                if !(0.0..=1.0).contains(&opacity) {
+                    return Err(RupdfError::InvalidDocument(format!(
+                        "Element {}: opacity must be between 0 and 1, got {}", index, opacity
+                    )));
+                }
+                let x: f32 = with_element_context(opt_len_or(dict, "x", 0.0, ctx), index)?;
+                let y: f32 = with_element_context(opt_len_or(dict, "y", 0.0, ctx), index)?;
+                let rotation: f32 = with_element_context(opt_or(dict, "rotation", 0.0), index)?;
+                let scale: f32 = with_element_context(opt_or(dict, "scale", 1.0), index)?;
+                if !(scale > 0.0 && scale.is_finite()) {
+                    return Err(RupdfError::InvalidDocument(format!(
+                        "Element {}: group scale must be positive, got {}", index, scale
+                    )));
+                }
+                Ok(Element::Group(GroupElement {
+                    id: id.clone(),
+                    tagging,
+                    z,
+                    visible,
+                    elements,
+                    x,
+                    y,
+                    rotation,
+                    scale,
+                    // Opacity applies to the group as a whole, so it
+                    // always composites as one unit
+                    transparency_group: transparency_group || opacity < 1.0,
+                    opacity,
+                }))
+            }
+
+            "fragment" => {
+                let name: String = with_element_context(req(dict, "fragment_ref"), index)?;
+                let Some(fragment) = ctx.fragments.get(&name) else {
+                    return Err(RupdfError::InvalidDocument(format!(
+                        "Element {}: unknown fragment '{}'; a fragment can only place fragments defined before it",
+                        index, name
+                    )));
+                };
+                let scale: f32 = with_element_context(opt_or(dict, "scale", 1.0), index)?;
+                if !(scale > 0.0 && scale.is_finite()) {
+                    return Err(RupdfError::InvalidDocument(format!(
+                        "Element {}: fragment scale must be positive, got {}", index, scale
+                    )));
+                }
+                Ok(Element::Fragment(FragmentElement {
+                    id: id.clone(),
+                    tagging,
+                    z,
+                    visible,
+                    fragment: Arc::clone(fragment),
+                    x: with_element_context(req_len(dict, "x", ctx), index)?,
+                    y: with_element_context(req_len(dict, "y", ctx), index)?,
+                    scale,
+                }))
+            }
+
+            "raw_content" => {
+                let operators: String = with_element_context(req(dict, "operators"), index)?;
+                let mut resources = Vec::new();
+                if let Some(declared) = with_element_context(opt::<Bound<'py, PyDict>>(dict, "raw_resources"), index)? {
+                    for (key, value) in declared.iter() {
+                        let name: String = key.extract().map_err(|e| RupdfError::InvalidDocument(format!(
+                            "Element {}: raw_resources key must be a string: {}", index, e
+                        )))?;
+                        let resource: RawResource = value.extract().map_err(|e| RupdfError::InvalidDocument(format!(
+                            "Element {}: raw_resources.{}: {}", index, name, e
+                        )))?;
+                        resources.push((name, resource));
+                    }
+                }
+                let uses = with_element_context(raw::check(&operators, &resources), index)?;
+                Ok(Element::RawContent(RawContentElement { id: id.clone(), tagging, z, visible, operators, resources, uses }))
+            }
+
+            "line" => {
+                let (stroke, hairline) = with_element_context(opt_stroke(dict, 1.0, ctx), index)?;
+                Ok(Element::Line(LineElement {
+                    id: id.clone(),
+                    tagging,
+                    z,
+                    visible,
+                    x1: with_element_context(req_len(dict, "x1", ctx), index)?,
+                    y1: with_element_context(req_len(dict, "y1", ctx), index)?,
+                    x2: with_element_context(req_len(dict, "x2", ctx), index)?,
+                    y2: with_element_context(req_len(dict, "y2", ctx), index)?,
+                    stroke,
+                    hairline,
+                    color: with_element_context(opt_or(dict, "color", Color::black()), index)?,
+                    dash: with_element_context(opt_dash(dict, stroke, ctx), index)?,
+                    cap: with_element_context(opt(dict, "cap"), index)?,
+                    join: with_element_context(opt(dict, "join"), index)?,
+                    miter_limit: with_element_context(opt_miter_limit(dict), index)?,
+                }))
+            }
+
+            "polygon" => {
+                let points: Vec<(PageLength, PageLength)> = with_element_context(req(dict, "points"), index)?;
+                if points.len() < 3 {
+                    return Err(RupdfError::InvalidDocument(format!(
+                        "Element {}: polygon needs at least 3 points, got {}", index, points.len()
+                    )));
+                }
+                let points = points
+                    .into_iter()
+                    .map(|(x, y)| Ok((ctx.to_points(x, "x")?, ctx.to_points(y, "y")?)))
+                    .collect::<Result<Vec<_>>>();
+                let (stroke, hairline) = with_element_context(opt_stroke(dict, 1.0, ctx), index)?;
+                Ok(Element::Polygon(PolygonElement {
+                    id: id.clone(),
+                    tagging,
+                    z,
+                    visible,
+                    points: with_element_context(points, index)?,
+                    stroke,
+                    hairline,
+                    stroke_color: with_element_context(opt_or(dict, "stroke_color", Color::black()), index)?,
+                    fill_color: with_element_context(opt(dict, "fill_color"), index)?,
+                    close: with_element_context(opt_or(dict, "close", true), index)?,
+                    fill_rule: with_element_context(opt_default(dict, "fill_rule"), index)?,
+                    cap: with_element_context(opt(dict, "cap"), index)?,
+                    join: with_element_context(opt(dict, "join"), index)?,
+                    miter_limit: with_element_context(opt_miter_limit(dict), index)?,
+                }))
+            }
+
+            "leader" => {
+                let x1: f32 = with_element_context(req_len(dict, "x1", ctx), index)?;
+                let x2: f32 = with_element_context(req_len(dict, "x2", ctx), index)?;
+                if x2 < x1 {
+                    return Err(RupdfError::InvalidDocument(format!(
+                        "Element {}: leader x2 ({}) must not be less than x1 ({})", index, x2, x1
+                    )));
+                }
+                let dot_size: Option<f32> = with_element_context(opt_len(dict, "dot_size", ctx), index)?;
+                if let Some(dot_size) = dot_size.filter(|&d| d <= 0.0) {
+                    return Err(RupdfError::InvalidDocument(format!(
+                        "Element {}: dot_size must be positive, got {}", index, dot_size
+                    )));
+                }
+                let gap: Option<f32> = with_element_context(opt_len(dict, "gap", ctx), index)?;
+                if let Some(gap) = gap.filter(|&g| g < 0.0) {
+                    return Err(RupdfError::InvalidDocument(format!(
+                        "Element {}: gap must not be negative, got {}", index, gap
+                    )));
+                }
+                let font: Option<String> = with_element_context(opt(dict, "font"), index)?;
+                let size = match font {
+                    Some(_) => with_element_context(req_len(dict, "size", ctx), index)?,
+                    None => 0.0,
+                };
+                Ok(Element::Leader(LeaderElement {
+                    id: id.clone(),
+                    tagging,
+                    z,
+                    visible,
+                    x1,
+                    x2,
+                    y: with_element_context(req_len(dict, "y", ctx), index)?,
+                    style: with_element_context(opt_default(dict, "style"), index)?,
+                    dot_size,
+                    gap,
+                    color: with_element_context(opt_or(dict, "color", Color::black()), index)?,
+                    font,
+                    size,
+                }))
+            }
+
+            "progress_ring" => {
+                let radius: f32 = with_element_context(req_len(dict, "radius", ctx), index)?;
+                let thickness: f32 = with_element_context(req_len(dict, "thickness", ctx), index)?;
+                for (key, v) in [("radius", radius), ("thickness", thickness)] {
+                    if v <= 0.0 {
+                        return Err(RupdfError::InvalidDocument(format!(
+                            "Element {}: progress_ring {} must be positive, got {}", index, key, v
+                        )));
+                    }
+                }
+                let value: f32 = with_element_context(req(dict, "value"), index)?;
+                if !(0.0..=1.0).contains(&value) {
+                    return Err(RupdfError::InvalidDocument(format!(
+                        "Element {}: progress_ring value must be between 0 and 1, got {}", index, value
+                    )));
+                }
+                Ok(Element::ProgressRing(ProgressRingElement {
+                    id: id.clone(),
+                    tagging,
+                    z,
+                    visible,
+                    cx: with_element_context(req_len(dict, "cx", ctx), index)?,
+                    cy: with_element_context(req_len(dict, "cy", ctx), index)?,
+                    radius,
+                    thickness,
+                    value,
+                    start_angle: with_element_context(opt_or(dict, "start_angle", 0.0), index)?,
+                    track_color: with_element_context(opt(dict, "track_color"), index)?,
+                    color: with_element_context(opt_or(dict, "color", Color::black()), index)?,
+                }))
+            }
+
             "image" => {
                 let align_str: String = with_element_context(opt_or(dict, "align", "left".to_string()), index)?;
                 let align = match align_str.as_str() {
@@ -577,13 +4704,50 @@ impl Element {
                     "right" => TextAlign::Right,
                     _ => TextAlign::Left,
                 };
+                let image_ref = with_element_context(req(dict, "image_ref"), index)?;
+                let fade = with_element_context(opt(dict, "fade"), index)?;
+                let clip = with_element_context(opt_image_clip(dict, ctx), index)?;
+                let border_width = with_element_context(opt_len_or(dict, "border_width", 0.0, ctx), index)?;
+                let border_color = with_element_context(opt_or(dict, "border_color", Color::black()), index)?;
+                // A quad places the image by itself
+                if let Some(quad) = with_element_context(opt_quad(dict, ctx), index)? {
+                    let (w, h) = quad.size();
+                    return Ok(Element::Image(ImageElement {
+                        id: id.clone(),
+                        tagging,
+                        z,
+                        visible,
+                        x: quad.origin.0,
+                        y: quad.origin.1,
+                        w: Some(w),
+                        h: Some(h),
+                        image_ref,
+                        align: TextAlign::Left,
+                        fade,
+                        rotation: 0.0,
+                        quad: Some(quad),
+                        clip,
+                        border_width,
+                        border_color,
+                    }));
+                }
                 Ok(Element::Image(ImageElement {
-                    x: with_element_context(req(dict, "x"), index)?,
-                    y: with_element_context(req(dict, "y"), index)?,
-                    w: with_element_context(opt(dict, "w"), index)?,
-                    h: with_element_context(opt(dict, "h"), index)?,
-                    image_ref: with_element_context(req(dict, "image_ref"), index)?,
+                    id: id.clone(),
+                    tagging,
+                    z,
+                    visible,
+                    x: with_element_context(req_len(dict, "x", ctx), index)?,
+                    y: with_element_context(req_len(dict, "y", ctx), index)?,
+                    w: with_element_context(opt_len(dict, "w", ctx), index)?,
+                    h: with_element_context(opt_len(dict, "h", ctx), index)?,
+                    image_ref,
                     align,
+                    fade,
+                    rotation: with_element_context(opt_or(dict, "rotation", 0.0), index)?,
+                    quad: None,
+                    clip,
+                    border_width,
+                    border_color,
                 }))
             }
 
@@ -592,27 +4756,74 @@ impl Element {
                     "gs1_128" | "gs1-128" | "gs1" => BarcodeKind::Gs1_128,
                     _ => BarcodeKind::Code128,
                 };
+                let human_readable = with_element_context(opt_or(dict, "human_readable", false), index)?;
+                let rotation: f32 = with_element_context(opt_or(dict, "rotation", 0.0), index)?;
+                if ![0.0, 90.0, 180.0, 270.0].contains(&rotation) {
+                    return Err(RupdfError::InvalidDocument(format!(
+                        "Element {}: barcode rotation must be 0, 90, 180 or 270, got {}", index, rotation
+                    )));
+                }
+                let font_size = with_element_context(opt_len_or(dict, "font_size", 10.0, ctx), index)?;
+                let text_min_size = with_element_context(opt_len_or(dict, "text_min_size", font_size / 2.0, ctx), index)?;
+                if human_readable && (text_min_size.is_nan() || text_min_size <= 0.0 || text_min_size > font_size) {
+                    return Err(RupdfError::InvalidDocument(format!(
+                        "Element {}: text_min_size must be positive and at most font_size {}, got {}",
+                        index, font_size, text_min_size
+                    )));
+                }
                 Ok(Element::Barcode(BarcodeElement {
+                    id: id.clone(),
+                    tagging,
+                    z,
+                    visible,
                     kind,
-                    x: with_element_context(req(dict, "x"), index)?,
-                    y: with_element_context(req(dict, "y"), index)?,
-                    w: with_element_context(req(dict, "w"), index)?,
-                    h: with_element_context(req(dict, "h"), index)?,
+                    x: with_element_context(req_len(dict, "x", ctx), index)?,
+                    y: with_element_context(req_len(dict, "y", ctx), index)?,
+                    w: with_element_context(req_len(dict, "w", ctx), index)?,
+                    h: with_element_context(req_len(dict, "h", ctx), index)?,
                     value: with_element_context(req(dict, "value"), index)?,
-                    human_readable: with_element_context(opt_or(dict, "human_readable", false), index)?,
-                    font: with_element_context(opt_or(dict, "font", "mono".to_string()), index)?,
-                    font_size: with_element_context(opt_or(dict, "font_size", 10.0), index)?,
+                    human_readable,
+                    font: with_element_context(barcode_font(dict, human_readable, ctx), index)?,
+                    font_size,
+                    text_overflow: with_element_context(opt_or(dict, "text_overflow", TextOverflow::Shrink), index)?,
+                    text_min_size,
+                    color: with_element_context(opt_or(dict, "color", Color::black()), index)?,
+                    rotation,
                 }))
             }
 
-            "qrcode" | "qr" => Ok(Element::QRCode(QRCodeElement {
-                x: with_element_context(req(dict, "x"), index)?,
-                y: with_element_context(req(dict, "y"), index)?,
-                size: with_element_context(req(dict, "size"), index)?,
-                value: with_element_context(req(dict, "value"), index)?,
-                color: with_element_context(opt_or(dict, "color", Color::black()), index)?,
-                background: with_element_context(opt_or(dict, "background", Color::white()), index)?,
-            })),
+            "qrcode" | "qr" => {
+                // Wide enough that any out-of-range int gets the range message
+                let version: Option<i64> = with_element_context(opt(dict, "version"), index)?;
+                let version = match version {
+                    Some(v @ 1..=40) => Some(v as u8),
+                    Some(v) => {
+                        return Err(RupdfError::InvalidDocument(format!(
+                            "Element {}: QR version must be between 1 and 40, got {}", index, v
+                        )));
+                    }
+                    None => None,
+                };
+                Ok(Element::QRCode(QRCodeElement {
+                    id: id.clone(),
+                    tagging,
+                    z,
+                    visible,
+                    x: with_element_context(req_len(dict, "x", ctx), index)?,
+                    y: with_element_context(req_len(dict, "y", ctx), index)?,
+                    size: with_element_context(req_len(dict, "size", ctx), index)?,
+                    value: with_element_context(
+                        req::<Bound<'py, PyAny>>(dict, "value").and_then(|v| QrPayload::from_py(&v)),
+                        index,
+                    )?,
+                    error_correction: with_element_context(opt_default(dict, "error_correction"), index)?,
+                    version,
+                    auto_version: with_element_context(opt_or(dict, "auto_version", true), index)?,
+                    min_module_size: with_element_context(opt_len(dict, "min_module_size", ctx), index)?,
+                    color: with_element_context(opt_or(dict, "color", Color::black()), index)?,
+                    background: with_element_context(opt_or(dict, "background", Color::white()), index)?,
+                }))
+            }
 
             "datamatrix" | "gs1_datamatrix" | "gs1-datamatrix" => {
                 let kind = match element_type.as_str() {
@@ -620,10 +4831,14 @@ impl Element {
                     _ => DataMatrixKind::Plain,
                 };
                 Ok(Element::DataMatrix(DataMatrixElement {
+                    id: id.clone(),
+                    tagging,
+                    z,
+                    visible,
                     kind,
-                    x: with_element_context(req(dict, "x"), index)?,
-                    y: with_element_context(req(dict, "y"), index)?,
-                    size: with_element_context(req(dict, "size"), index)?,
+                    x: with_element_context(req_len(dict, "x", ctx), index)?,
+                    y: with_element_context(req_len(dict, "y", ctx), index)?,
+                    size: with_element_context(req_len(dict, "size", ctx), index)?,
                     value: with_element_context(req(dict, "value"), index)?,
                     shape: with_element_context(opt_default(dict, "shape"), index)?,
                     color: with_element_context(opt_or(dict, "color", Color::black()), index)?,
@@ -631,14 +4846,143 @@ impl Element {
                 }))
             }
 
+            "table" => {
+                let widths: Vec<Length> = with_element_context(req(dict, "column_widths"), index)?;
+                let column_widths: Vec<f32> = widths.into_iter().map(|w| ctx.units.to_points(w)).collect();
+                if column_widths.is_empty() || column_widths.iter().any(|&w| w <= 0.0) {
+                    return Err(RupdfError::InvalidDocument(format!(
+                        "Element {}: column_widths must be a non-empty list of positive widths", index
+                    )));
+                }
+                let rows: Vec<Vec<String>> = with_element_context(req::<Vec<Vec<String>>>(dict, "rows"), index)?
+                    .into_iter()
+                    .map(|row| row.into_iter().map(|cell| ctx.text(cell)).collect())
+                    .collect();
+                if let Some((r, row)) = rows.iter().enumerate().find(|(_, row)| row.len() != column_widths.len()) {
+                    return Err(RupdfError::InvalidDocument(format!(
+                        "Element {}: row {} has {} cells, expected {}",
+                        index, r, row.len(), column_widths.len()
+                    )));
+                }
+                let header_rows: usize = with_element_context(opt_or(dict, "header_rows", 0), index)?;
+                if header_rows > rows.len() {
+                    return Err(RupdfError::InvalidDocument(format!(
+                        "Element {}: header_rows {} exceeds the {} rows in the table",
+                        index, header_rows, rows.len()
+                    )));
+                }
+                let size: f32 = with_element_context(req_len(dict, "size", ctx), index)?;
+                let line_height_ratio: f32 = with_element_context(opt_or(dict, "line_height_ratio", 1.2), index)?;
+                Ok(Element::Table(TableElement {
+                    id: id.clone(),
+                    tagging,
+                    z,
+                    visible,
+                    x: with_element_context(req_len(dict, "x", ctx), index)?,
+                    y: with_element_context(req_len(dict, "y", ctx), index)?,
+                    column_widths,
+                    rows,
+                    header_rows,
+                    font: with_element_context(req(dict, "font"), index)?,
+                    header_font: with_element_context(opt(dict, "header_font"), index)?,
+                    font_fallback: with_element_context(opt_or(dict, "font_fallback", Vec::new()), index)?,
+                    missing_glyph_policy: with_element_context(opt_default(dict, "missing_glyph_policy"), index)?,
+                    size,
+                    line_height: with_element_context(opt_len_or(dict, "line_height", size * line_height_ratio, ctx), index)?,
+                    color: with_element_context(opt_or(dict, "color", Color::black()), index)?,
+                    padding: with_element_context(opt_len_or(dict, "padding", 4.0, ctx), index)?,
+                    border: with_element_context(opt_len_or(dict, "border", 0.5, ctx), index)?,
+                    border_color: with_element_context(opt_or(dict, "border_color", Color::black()), index)?,
+                    header_fill: with_element_context(opt(dict, "header_fill"), index)?,
+                    max_height: with_element_context(opt_len(dict, "max_height", ctx), index)?,
+                    continued_y: with_element_context(opt_len(dict, "continued_y", ctx), index)?,
+                    page_margin_bottom: ctx.frame.map_or(0.0, |frame| frame.margins.bottom),
+                    continued_caption: with_element_context(opt(dict, "continued_caption"), index)?,
+                    continuation: false,
+                }))
+            }
+
             _ => Err(RupdfError::UnknownElementType(format!("Element {}: {}", index, element_type))),
         }
     }
 }
 
+/// Get a page's optional `margins`: one length for every side, or a dict
+/// of `top`, `right`, `bottom` and `left` (each 0 when unset).
+fn page_margins<'py>(dict: &Bound<'py, PyDict>, (width, height): (f32, f32), ctx: &ParseContext<'py>) -> Result<Margins> {
+    let Some(value) = opt::<Bound<'py, PyAny>>(dict, "margins")? else {
+        return Ok(Margins::default());
+    };
+    let margins = match value.cast::<PyDict>() {
+        Ok(sides) => {
+            let side = |key| -> Result<f32> {
+                Ok(opt::<Length>(sides, key)?.map(|l| ctx.units.to_points(l)).unwrap_or(0.0))
+            };
+            Margins { top: side("top")?, right: side("right")?, bottom: side("bottom")?, left: side("left")? }
+        }
+        Err(_) => {
+            let all = ctx.units.to_points(to_doc_err(value.extract())?);
+            Margins { top: all, right: all, bottom: all, left: all }
+        }
+    };
+    let Margins { top, right, bottom, left } = margins;
+    if [top, right, bottom, left].iter().any(|&m| m < 0.0) {
+        return Err(RupdfError::InvalidDocument("Page margins must not be negative".to_string()));
+    }
+    if left + right >= width || top + bottom >= height {
+        return Err(RupdfError::InvalidDocument(format!(
+            "Page margins (top {}, right {}, bottom {}, left {}) leave no content area on a {} x {} page",
+            top, right, bottom, left, width, height
+        )));
+    }
+    Ok(margins)
+}
+
+/// Keep going past `result`'s error when collecting: record it, located
+/// by `locate`, and return `None`. Fails fast otherwise.
+fn collect<T>(
+    result: Result<T>,
+    errors: &mut Option<&mut Vec<ParseError>>,
+    locate: impl FnOnce(String) -> ParseError,
+) -> Result<Option<T>> {
+    match (result, errors) {
+        (Ok(value), _) => Ok(Some(value)),
+        (Err(e), Some(errors)) => {
+            errors.push(locate(e.to_string()));
+            Ok(None)
+        }
+        (Err(e), None) => Err(e),
+    }
+}
+
+/// An element's `id` for an error entry, if it has a readable one
+fn error_id(item: &Bound<'_, PyAny>) -> Option<String> {
+    let dict = item.cast::<PyDict>().ok()?;
+    dict.get_item("id").ok()??.extract().ok()
+}
+
 impl Page {
-    pub fn from_py<'py>(dict: &Bound<'py, PyDict>) -> Result<Self> {
-        let size: (f32, f32) = req(dict, "size")?;
+    /// With `errors`, elements that fail are recorded there and left out.
+    /// The caller, which knows the page index, adds it.
+    pub fn from_py<'py>(
+        dict: &Bound<'py, PyDict>,
+        ctx: &ParseContext<'py>,
+        mut errors: Option<&mut Vec<ParseError>>,
+    ) -> Result<Self> {
+        if ctx.strict {
+            keys::check(dict, keys::PAGE, "pages")?;
+        }
+        let size_obj: Bound<'py, PyAny> = req(dict, "size")?;
+        let mut size = match size_obj.extract::<String>() {
+            Ok(name) => units::paper_size(&name)?,
+            Err(_) => {
+                let (w, h): (Length, Length) = to_doc_err(size_obj.extract())?;
+                (ctx.units.to_points(w), ctx.units.to_points(h))
+            }
+        };
+        if opt_or(dict, "landscape", false)? && size.0 < size.1 {
+            size = (size.1, size.0);
+        }
 
         if size.0 <= 0.0 || size.1 <= 0.0 {
             return Err(RupdfError::InvalidPageSize {
@@ -647,15 +4991,52 @@ impl Page {
             });
         }
 
-        let background = opt_or(dict, "background", Color::white())?;
+        // A page may restate the document's origin, but not change it
+        if let Some(origin) = opt::<Origin>(dict, "origin")? {
+            if origin != ctx.origin {
+                return Err(RupdfError::InvalidDocument(format!(
+                    "origin '{}' differs from the document's '{}'; every page uses the document's origin",
+                    origin.as_str(),
+                    ctx.origin.as_str()
+                )));
+            }
+        }
+
+        let background = opt_or(dict, "background", PageBackground::Color(Color::white()))?;
+        let repeat: usize = opt_or(dict, "repeat", 1)?;
+        if repeat == 0 {
+            return Err(RupdfError::InvalidDocument("Page repeat must be at least 1".to_string()));
+        }
         let elements_list: Option<Bound<'py, PyList>> = opt(dict, "elements")?;
 
+        // Elements resolve page-relative lengths against this page
+        let frame = PageFrame {
+            width: size.0,
+            height: size.1,
+            margins: page_margins(dict, size, ctx)?,
+            origin: ctx.origin,
+        };
+        let ctx = &ParseContext {
+            defaults: ctx.defaults.clone(),
+            units: ctx.units,
+            frame: Some(frame),
+            fragments: ctx.fragments.clone(),
+            styles: ctx.styles.clone(),
+            font_families: ctx.font_families.clone(),
+            strict: ctx.strict,
+            origin: ctx.origin,
+            fonts: ctx.fonts.clone(),
+            raw_text: ctx.raw_text,
+            font_names: ctx.font_names.clone(),
+        };
         let mut elements = Vec::new();
         if let Some(list) = elements_list {
             for (i, item) in list.iter().enumerate() {
-                let elem_dict = item.cast::<PyDict>()
-                    .map_err(|_| RupdfError::InvalidDocument(format!("Element {} must be a dict", i)))?;
-                elements.push(Element::from_py_indexed(elem_dict, i)?);
+                let parsed = item.cast::<PyDict>()
+                    .map_err(|_| RupdfError::InvalidDocument(format!("Element {} must be a dict", i)))
+                    .and_then(|elem_dict| Element::from_py_indexed(elem_dict, i, ctx));
+                let located = |message| ParseError { page: None, element: Some(i), id: error_id(&item), message };
+                elements.extend(collect(parsed, &mut errors, located)?);
             }
         }
 
@@ -663,13 +5044,18 @@ impl Page {
             width: size.0,
             height: size.1,
             background,
+            repeat,
             elements,
+            source: 0,
         })
     }
 }
 
 impl Metadata {
-    pub fn from_py<'py>(dict: &Bound<'py, PyDict>) -> Result<Self> {
+    pub fn from_py<'py>(dict: &Bound<'py, PyDict>, strict: bool) -> Result<Self> {
+        if strict {
+            keys::check(dict, keys::METADATA, "metadata")?;
+        }
         Ok(Self {
             title: opt(dict, "title")?,
             author: opt(dict, "author")?,
@@ -681,8 +5067,11 @@ impl Metadata {
 }
 
 impl Resources {
-    pub fn from_py<'py>(dict: &Bound<'py, PyDict>) -> Result<Self> {
+    pub fn from_py<'py>(dict: &Bound<'py, PyDict>, strict: bool) -> Result<Self> {
         let mut resources = Self::default();
+        if strict {
+            keys::check(dict, keys::RESOURCES, "resources")?;
+        }
 
         // Parse fonts
         if let Some(fonts_dict) = opt::<Bound<'py, PyDict>>(dict, "fonts")? {
@@ -692,24 +5081,11 @@ impl Resources {
                 let font_dict = value.cast::<PyDict>()
                     .map_err(|_| RupdfError::InvalidDocument("Font value must be a dict".to_string()))?;
 
-                let path: Option<String> = opt(font_dict, "path")?;
-                let bytes: Option<Bound<'py, PyBytes>> = opt(font_dict, "bytes")?;
-
-                let source = match (path, bytes) {
-                    (Some(p), None) => FontSource::Path(p),
-                    (None, Some(b)) => FontSource::Bytes(b.as_bytes().to_vec()),
-                    (Some(_), Some(_)) => {
-                        return Err(RupdfError::ResourceError(format!(
-                            "Font '{}' has both 'path' and 'bytes'; only one is allowed", name
-                        )));
-                    }
-                    (None, None) => {
-                        return Err(RupdfError::ResourceError(format!(
-                            "Font '{}' must have either 'path' or 'bytes'", name
-                        )));
-                    }
-                };
-                resources.fonts.insert(name, source);
+                if strict {
+                    keys::check(font_dict, keys::FONT_RESOURCE, &format!("font '{}'", name))?;
+                }
+                let font = FontResource::from_py(font_dict, &name)?;
+                resources.fonts.insert(name, font);
             }
         }
 
@@ -721,24 +5097,18 @@ impl Resources {
                 let image_dict = value.cast::<PyDict>()
                     .map_err(|_| RupdfError::InvalidDocument("Image value must be a dict".to_string()))?;
 
-                let path: Option<String> = opt(image_dict, "path")?;
-                let bytes: Option<Bound<'py, PyBytes>> = opt(image_dict, "bytes")?;
-
-                let source = match (path, bytes) {
-                    (Some(p), None) => ImageSource::Path(p),
-                    (None, Some(b)) => ImageSource::Bytes(b.as_bytes().to_vec()),
-                    (Some(_), Some(_)) => {
-                        return Err(RupdfError::ResourceError(format!(
-                            "Image '{}' has both 'path' and 'bytes'; only one is allowed", name
-                        )));
-                    }
-                    (None, None) => {
-                        return Err(RupdfError::ResourceError(format!(
-                            "Image '{}' must have either 'path' or 'bytes'", name
-                        )));
-                    }
+                if strict {
+                    keys::check(image_dict, keys::IMAGE_RESOURCE, &format!("image '{}'", name))?;
+                }
+                let source = match SourceKey::from_py(image_dict, "Image", &name, true)? {
+                    SourceKey::Path(p) => ImageSource::Path(p),
+                    SourceKey::Bytes(b) => ImageSource::Bytes(b),
+                    SourceKey::Url(u) => ImageSource::Url(u),
+                    SourceKey::Svg(s) => ImageSource::Svg(s),
                 };
-                resources.images.insert(name, source);
+                let frame: u32 = opt_or(image_dict, "frame", 0)?;
+                let profile = opt_default(image_dict, "profile")?;
+                resources.images.insert(name, ImageResource { source, frame, profile });
             }
         }
 
@@ -746,30 +5116,475 @@ impl Resources {
     }
 }
 
+impl Fragment {
+    fn from_py<'py>(name: &str, value: &Bound<'py, PyAny>, ctx: &ParseContext<'py>) -> Result<Self> {
+        let list = value.cast::<PyList>()
+            .map_err(|_| RupdfError::InvalidDocument("must be a list of elements".to_string()))?;
+        let mut elements = Vec::with_capacity(list.len());
+        for (i, item) in list.iter().enumerate() {
+            let elem_dict = item.cast::<PyDict>()
+                .map_err(|_| RupdfError::InvalidDocument(format!("Element {} must be a dict", i)))?;
+            let element = Element::from_py_indexed(elem_dict, i, ctx)?;
+            // Notes are page annotations, placed outside the content the
+            // fragment is drawn into
+            if contains_note(&element) {
+                return Err(RupdfError::InvalidDocument(format!(
+                    "Element {}: notes can't be used in fragments", element.label(i)
+                )));
+            }
+            elements.push(element);
+        }
+        Ok(Self { name: name.to_string(), elements })
+    }
+}
+
+fn contains_note(element: &Element) -> bool {
+    match element {
+        Element::Note(_) => true,
+        Element::Group(g) => g.elements.iter().any(contains_note),
+        _ => false,
+    }
+}
+
+/// Move page `index`'s elements flagged `every_page` off the page, for the
+/// repeating list. Only page 0 may flag them.
+fn take_every_page(page_dict: &Bound<'_, PyDict>, index: usize, page: &mut Page) -> Result<Vec<RepeatingElement>> {
+    let mut moved = Vec::new();
+    if let Some(list) = opt::<Bound<'_, PyList>>(page_dict, "elements")? {
+        let mut removed = 0;
+        for (j, item) in list.iter().enumerate() {
+            let elem_dict = item.cast::<PyDict>()
+                .map_err(|_| RupdfError::InvalidDocument(format!("Element {} must be a dict", j)))?;
+            if !with_element_context(opt_or(elem_dict, "every_page", false), page.elements[j - removed].label(j))? {
+                continue;
+            }
+            if index != 0 {
+                return Err(RupdfError::InvalidDocument(format!(
+                    "Page {}, element {}: every_page is only allowed on page 0; use repeating_elements",
+                    index, page.elements[j - removed].label(j)
+                )));
+            }
+            let element = page.elements.remove(j - removed);
+            removed += 1;
+            let id = element.id().map(str::to_string);
+            let label = ElementLabel { index: j, id: id.as_deref() };
+            moved.push(with_element_context(RepeatingElement::from_py(elem_dict, element), label)?);
+        }
+    }
+    Ok(moved)
+}
+
 impl Document {
     pub fn from_py<'py>(dict: &Bound<'py, PyDict>) -> Result<Self> {
+        Self::parse(dict, None)
+    }
+
+    /// Parse the whole document, carrying on past elements, pages,
+    /// fragments and repeating elements that fail, and fail with all their
+    /// errors at once as `RupdfError::Multiple`. An error elsewhere still
+    /// stops parsing, and is reported after those found before it.
+    pub fn from_py_collecting<'py>(dict: &Bound<'py, PyDict>) -> Result<Self> {
+        let mut errors = Vec::new();
+        match Self::parse(dict, Some(&mut errors)) {
+            Ok(doc) if errors.is_empty() => Ok(doc),
+            Ok(_) => Err(RupdfError::Multiple(errors)),
+            Err(e) => {
+                errors.push(ParseError { page: None, element: None, id: None, message: e.to_string() });
+                Err(RupdfError::Multiple(errors))
+            }
+        }
+    }
+
+    fn parse<'py>(dict: &Bound<'py, PyDict>, mut errors: Option<&mut Vec<ParseError>>) -> Result<Self> {
+        // With strict_keys, every dict is checked before it is read
+        let strict: bool = opt_or(dict, "strict_keys", false)?;
+        if strict {
+            keys::check(dict, keys::DOCUMENT, "the document")?;
+        }
+
+        // Palette names resolve in every color parsed from here on
+        let mut palette = HashMap::new();
+        if let Some(palette_dict) = opt::<Bound<'py, PyDict>>(dict, "palette")? {
+            for (key, value) in palette_dict.iter() {
+                let name: String = key.extract()
+                    .map_err(|e| RupdfError::InvalidDocument(format!("Palette key must be string: {}", e)))?;
+                let color: Color = value.extract()
+                    .map_err(|e| RupdfError::InvalidDocument(format!("palette.{}: {}", name, e)))?;
+                palette.insert(name, color);
+            }
+        }
+        let _palette = PaletteScope::install(palette);
+
         // Parse metadata (optional)
         let metadata = match opt::<Bound<'py, PyDict>>(dict, "metadata")? {
-            Some(meta_dict) => Metadata::from_py(&meta_dict)?,
+            Some(meta_dict) => Metadata::from_py(&meta_dict, strict)?,
             None => Metadata::default(),
         };
 
+        // Parse defaults and units (optional) before pages so elements can use them
+        let mut ctx = ParseContext {
+            defaults: match opt::<Bound<'py, PyDict>>(dict, "defaults")? {
+                Some(defaults_dict) => Defaults::from_py(&defaults_dict, strict)?,
+                None => Defaults::default(),
+            },
+            units: match opt::<String>(dict, "units")? {
+                Some(units) => Units::parse(&units)?,
+                None => Units::default(),
+            },
+            frame: None,
+            fragments: HashMap::new(),
+            styles: HashMap::new(),
+            font_families: HashMap::new(),
+            strict,
+            origin: opt_default(dict, "origin")?,
+            fonts: None,
+            raw_text: !opt_or(dict, "normalize_text", true)?,
+            font_names: Rc::default(),
+        };
+
+        // Font aliases, read ahead of the resources themselves so elements
+        // can check theirs; a malformed entry fails with the resources below
+        let mut fonts: Vec<String> = opt::<Bound<'py, PyDict>>(dict, "resources")?
+            .and_then(|res| opt::<Bound<'py, PyDict>>(&res, "fonts").ok().flatten())
+            .map(|fonts| fonts.keys().iter().filter_map(|key| key.extract().ok()).collect())
+            .unwrap_or_default();
+        fonts.sort();
+        ctx.fonts = Some(fonts);
+
+        // Parse font families (optional) before anything with text
+        if let Some(families) = opt::<Bound<'py, PyDict>>(dict, "font_families")? {
+            for (key, value) in families.iter() {
+                let name: String = key.extract()
+                    .map_err(|e| RupdfError::InvalidDocument(format!("font_families: key must be a string: {}", e)))?;
+                let family = value.cast::<PyDict>()
+                    .map_err(|_| RupdfError::InvalidDocument("a family must be a dict".to_string()))
+                    .and_then(|family| FontFamily::from_py(family))
+                    .map_err(|e| RupdfError::InvalidDocument(format!("font_families.{}: {}", name, e)))?;
+                ctx.font_families.insert(name, family);
+            }
+        }
+
+        // Parse styles (optional) before fragments, whose text can use them
+        if let Some(styles) = opt::<Bound<'py, PyDict>>(dict, "styles")? {
+            for (key, value) in styles.iter() {
+                let name: String = key.extract()
+                    .map_err(|e| RupdfError::InvalidDocument(format!("styles: key must be a string: {}", e)))?;
+                let style = value.cast::<PyDict>()
+                    .map_err(|_| RupdfError::InvalidDocument("a style must be a dict".to_string()))
+                    .and_then(|style| TextStyle::from_py(style))
+                    .map_err(|e| RupdfError::InvalidDocument(format!("styles.{}: {}", name, e)))?;
+                ctx.styles.insert(name, style);
+            }
+        }
+
+        // Parse fragments (optional) in order, each able to place those
+        // defined before it, which also rules out cycles
+        if let Some(fragments) = opt::<Bound<'py, PyDict>>(dict, "fragments")? {
+            for (key, value) in fragments.iter() {
+                let name: String = key.extract()
+                    .map_err(|e| RupdfError::InvalidDocument(format!("fragments: key must be a string: {}", e)))?;
+                let fragment = Fragment::from_py(&name, &value, &ctx)
+                    .map_err(|e| RupdfError::InvalidDocument(format!("fragments.{}: {}", name, e)));
+                let located = |message| ParseError { page: None, element: None, id: None, message };
+                if let Some(fragment) = collect(fragment, &mut errors, located)? {
+                    ctx.fragments.insert(name, Arc::new(fragment));
+                }
+            }
+        }
+
         // Parse pages (required)
         let pages_list: Bound<'py, PyList> = req(dict, "pages")?;
         let mut pages = Vec::with_capacity(pages_list.len());
+        let mut repeating = Vec::new();
         for (i, item) in pages_list.iter().enumerate() {
-            let page_dict = item.cast::<PyDict>()
-                .map_err(|_| RupdfError::InvalidDocument(format!("Page {} must be a dict", i)))?;
-            pages.push(Page::from_py(page_dict)?);
+            let found = errors.as_ref().map_or(0, |errors| errors.len());
+            let page = item.cast::<PyDict>()
+                .map_err(|_| RupdfError::InvalidDocument(format!("Page {} must be a dict", i)))
+                .and_then(|page_dict| {
+                    let page = Page::from_py(page_dict, &ctx, errors.as_deref_mut()).map(|page| Page { source: i, ..page });
+                    let page = match page {
+                        Err(e) if strict && errors.is_none() => {
+                            Err(RupdfError::InvalidDocument(format!("Page {}: {}", i, e)))
+                        }
+                        page => page,
+                    };
+                    page.map(|page| (page_dict, page))
+                });
+            let located = |message| ParseError {
+                page: Some(i),
+                element: None,
+                id: None,
+                message: format!("Page {}: {}", i, message),
+            };
+            let parsed = collect(page, &mut errors, located)?;
+            // The page's element errors were recorded without it
+            if let Some(errors) = errors.as_deref_mut() {
+                for error in &mut errors[found..] {
+                    if error.page.is_none() {
+                        error.page = Some(i);
+                        error.message = format!("Page {}: {}", i, error.message);
+                    }
+                }
+                if errors.len() > found {
+                    continue;
+                }
+            }
+            let Some((page_dict, mut page)) = parsed else { continue };
+
+            // Page 0 elements flagged `every_page` move to the repeating list
+            let moved = take_every_page(page_dict, i, &mut page);
+            // Its errors name the page already
+            let located = |message| ParseError { page: Some(i), element: None, id: None, message };
+            if let Some(moved) = collect(moved, &mut errors, located)? {
+                repeating.extend(moved);
+                pages.push(page);
+            }
+        }
+
+        // Parse repeating elements (optional)
+        if let Some(list) = opt::<Bound<'py, PyList>>(dict, "repeating_elements")? {
+            for (i, item) in list.iter().enumerate() {
+                let parsed = RepeatingElement::from_py_item(&item, i, &ctx)
+                    .map_err(|e| RupdfError::InvalidDocument(format!("repeating_elements: {}", e)));
+                let located = |message| ParseError { page: None, element: Some(i), id: error_id(&item), message };
+                repeating.extend(collect(parsed, &mut errors, located)?);
+            }
+        }
+
+        // Parse the watermark (optional)
+        let mut watermark = None;
+        if let Some(watermark_dict) = opt::<Bound<'py, PyDict>>(dict, "watermark")? {
+            let parsed = Watermark::from_py(&watermark_dict, &ctx)
+                .map_err(|e| RupdfError::InvalidDocument(format!("watermark: {}", e)));
+            let located = |message| ParseError { page: None, element: None, id: None, message };
+            watermark = collect(parsed, &mut errors, located)?;
+        }
+
+        // Parse the header and footer (optional)
+        let mut regions = [None, None];
+        for (key, region) in ["header", "footer"].into_iter().zip(&mut regions) {
+            if let Some(value) = opt::<Bound<'py, PyAny>>(dict, key)? {
+                let parsed = PageRegion::from_py(&value, &ctx)
+                    .map_err(|e| RupdfError::InvalidDocument(format!("{}: {}", key, e)));
+                let located = |message| ParseError { page: None, element: None, id: None, message };
+                *region = collect(parsed, &mut errors, located)?;
+            }
+        }
+        let [header, footer] = regions;
+
+        // Parse layers (optional). Every layer is checked here, selected
+        // or not, so a mistake in a proofing layer shows up in production
+        // runs too.
+        let mut layers = Vec::new();
+        if let Some(layers_dict) = opt::<Bound<'py, PyDict>>(dict, "layers")? {
+            for (key, value) in layers_dict.iter() {
+                let name: String = key.extract()
+                    .map_err(|e| RupdfError::InvalidDocument(format!("layers: key must be a string: {}", e)))?;
+                let list = value.cast::<PyList>()
+                    .map_err(|_| RupdfError::InvalidDocument(format!("layers.{}: a layer must be a list of elements", name)))?;
+                let mut elements = Vec::with_capacity(list.len());
+                for (i, item) in list.iter().enumerate() {
+                    let parsed = RepeatingElement::from_py_item(&item, i, &ctx)
+                        .map_err(|e| RupdfError::InvalidDocument(format!("layers.{}: {}", name, e)));
+                    let located = |message| ParseError { page: None, element: Some(i), id: error_id(&item), message };
+                    elements.extend(collect(parsed, &mut errors, located)?);
+                }
+                layers.push(Layer { name, elements });
+            }
         }
 
         // Parse resources (optional)
-        let resources = match opt::<Bound<'py, PyDict>>(dict, "resources")? {
-            Some(res_dict) => Resources::from_py(&res_dict)?,
+        let mut resources = match opt::<Bound<'py, PyDict>>(dict, "resources")? {
+            Some(res_dict) => Resources::from_py(&res_dict, strict)?,
             None => Resources::default(),
         };
+        if let Some(intent_dict) = opt::<Bound<'py, PyDict>>(dict, "output_intent")? {
+            resources.output_intent = Some(OutputIntent::from_py(&intent_dict)?);
+        }
+        resources.inherited_fonts = ctx.font_names.borrow().iter()
+            .filter(|&(_, &inherited)| inherited)
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        // Parse the open action (optional). Pagination only adds pages, so
+        // an index valid here stays valid.
+        let open_action = match opt::<Bound<'py, PyDict>>(dict, "open_action")? {
+            Some(action_dict) => {
+                let action = OpenAction::from_py(&action_dict, &ctx)?;
+                let count: usize = pages.iter().map(|page| page.repeat).sum();
+                if action.page >= count {
+                    return Err(RupdfError::InvalidDocument(format!(
+                        "open_action page {} is out of range; the document has {} pages",
+                        action.page, count
+                    )));
+                }
+                Some(action)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            metadata,
+            pages,
+            repeating,
+            layers,
+            resources,
+            open_action,
+            origin: ctx.origin,
+            units: ctx.units,
+            watermark,
+            header,
+            footer,
+        })
+    }
+
+    /// Add the watermark to `repeating`, placed on each page size in use:
+    /// after tables are paginated, so continuation pages get it too. A
+    /// watermark drawn behind goes beneath the other repeating elements,
+    /// and one drawn over them above.
+    pub fn place_watermark(&mut self) {
+        let Some(watermark) = self.watermark.take() else {
+            return;
+        };
+        let placed = self.pages_by_size().into_iter().map(|((width, height), pages)| RepeatingElement {
+            element: watermark.placed(width, height),
+            pages: PageFilter { only: Some(pages), except: Vec::new() },
+            under_content: watermark.behind,
+            page_numbers: false,
+        });
+        if watermark.behind {
+            self.repeating.splice(0..0, placed);
+        } else {
+            self.repeating.extend(placed);
+        }
+    }
+
+    /// Add the header and footer to `repeating`, over the other repeating
+    /// elements: the header `offset` below the top edge and the footer
+    /// `offset` above the bottom one, placed on each page height in use.
+    /// Run after tables are paginated, so continuation pages get them and
+    /// are counted.
+    pub fn place_regions(&mut self) {
+        let regions = [(self.header.take(), false), (self.footer.take(), true)];
+        for (region, from_bottom) in regions {
+            let Some(region) = region else { continue };
+            for ((_, height), pages) in self.pages_by_size() {
+                let y = if from_bottom { height - region.offset } else { region.offset };
+                let except = if region.skip_first { vec![0] } else { Vec::new() };
+                self.repeating.push(RepeatingElement {
+                    element: region.placed(y),
+                    pages: PageFilter { only: Some(pages), except },
+                    under_content: false,
+                    page_numbers: true,
+                });
+            }
+        }
+    }
 
-        Ok(Self { metadata, pages, resources })
+    /// Page indices grouped by page size, in order of first use
+    fn pages_by_size(&self) -> Vec<((f32, f32), Vec<usize>)> {
+        let mut by_size: Vec<((f32, f32), Vec<usize>)> = Vec::new();
+        for (i, page) in self.pages.iter().enumerate() {
+            match by_size.iter_mut().find(|(size, _)| *size == (page.width, page.height)) {
+                Some((_, pages)) => pages.push(i),
+                None => by_size.push(((page.width, page.height), vec![i])),
+            }
+        }
+        by_size
+    }
+
+    /// Draw the layers named in `include` and drop the others, before
+    /// anything looks at the document's elements: a dropped layer loads
+    /// no fonts or images and adds nothing to the output. Selected layers
+    /// go ahead of `repeating_elements`, beneath them at the same level.
+    pub fn select_layers(&mut self, include: &[String]) -> Result<()> {
+        if let Some(unknown) = include.iter().find(|name| !self.layers.iter().any(|layer| &layer.name == *name)) {
+            let known: Vec<String> = self.layers.iter().map(|layer| format!("'{}'", layer.name)).collect();
+            return Err(RupdfError::InvalidDocument(match known.is_empty() {
+                true => format!("Unknown layer '{}'; the document has no layers", unknown),
+                false => format!("Unknown layer '{}'; the document has {}", unknown, known.join(", ")),
+            }));
+        }
+        let selected = std::mem::take(&mut self.layers)
+            .into_iter()
+            .filter(|layer| include.contains(&layer.name))
+            .flat_map(|layer| layer.elements);
+        self.repeating.splice(0..0, selected);
+        Ok(())
+    }
+
+    /// Warn for each element reusing an id already seen earlier in the
+    /// document. Group children count, located at their group. Run before
+    /// tables are paginated, since continuation parts share their id.
+    pub fn warn_duplicate_ids(&self, warnings: &mut Warnings) {
+        fn ids<'e>(element: &'e Element, out: &mut Vec<&'e str>) {
+            out.extend(element.id());
+            if let Element::Group(g) = element {
+                for child in &g.elements {
+                    ids(child, out);
+                }
+            }
+        }
+        let page_elements = self.pages.iter().enumerate().flat_map(|(p, page)| {
+            page.elements.iter().enumerate().map(move |(i, e)| (e, Some(p), i))
+        });
+        let repeating = self.repeating.iter().enumerate().map(|(i, r)| (&r.element, None, i));
+
+        let mut first_use: HashMap<&str, String> = HashMap::new();
+        for (element, page, index) in page_elements.chain(repeating) {
+            let location = match page {
+                Some(p) => format!("page {}, element {}", p, index),
+                None => format!("repeating element {}", index),
+            };
+            let mut found = Vec::new();
+            ids(element, &mut found);
+            for id in found {
+                let Some(first) = first_use.get(id) else {
+                    first_use.insert(id, location.clone());
+                    continue;
+                };
+                let message = format!("duplicate id, first used by {}", first);
+                warnings.push(Warning {
+                    code: "duplicate_id",
+                    page,
+                    element: page.map(|_| index),
+                    id: Some(id.to_string()),
+                    message: match page {
+                        Some(_) => message,
+                        None => format!("repeating element {}: {}", ElementLabel { index, id: Some(id) }, message),
+                    },
+                });
+            }
+        }
+    }
+
+    /// Fail on the first `raw_content` element, in groups and fragments
+    /// too, unless raw content is `allowed`.
+    pub fn check_raw_allowed(&self, allowed: bool) -> Result<()> {
+        fn contains_raw(element: &Element) -> bool {
+            match element {
+                Element::RawContent(_) => true,
+                Element::Group(g) => g.elements.iter().any(contains_raw),
+                Element::Fragment(f) => f.fragment.elements.iter().any(contains_raw),
+                _ => false,
+            }
+        }
+        if allowed {
+            return Ok(());
+        }
+        let page_elements = self.pages.iter().enumerate().flat_map(|(p, page)| {
+            page.elements.iter().enumerate().map(move |(i, e)| (e, format!("Page {}, element {}", p, e.label(i))))
+        });
+        let repeating = self.repeating.iter().enumerate()
+            .map(|(i, r)| (&r.element, format!("Repeating element {}", r.element.label(i))));
+        for (element, location) in page_elements.chain(repeating) {
+            if contains_raw(element) {
+                return Err(RupdfError::InvalidDocument(format!(
+                    "{}: raw_content is only rendered with allow_raw=True", location
+                )));
+            }
+        }
+        Ok(())
     }
 }
 
@@ -795,9 +5610,32 @@ mod tests {
         assert_eq!(c.a, 255);
     }
 
+    #[test]
+    fn test_color_strings() {
+        assert_eq!(color_from_str("#1A2b3C").unwrap(), Color::rgba(0x1a, 0x2b, 0x3c, 255));
+        assert_eq!(color_from_str("#1a2b3c80").unwrap(), Color::rgba(0x1a, 0x2b, 0x3c, 0x80));
+        assert_eq!(color_from_str("Navy").unwrap(), Color::rgba(0, 0, 128, 255));
+        assert!(color_from_str("#1a2b3").is_err());
+        assert!(color_from_str("#gg0000").is_err());
+        assert_eq!(
+            color_from_str("brand").unwrap_err(),
+            "Unknown color 'brand'. Must be #RRGGBB, #RRGGBBAA, a CSS color name or a palette name"
+        );
+
+        let scope = PaletteScope::install(HashMap::from([
+            ("brand".to_string(), Color::rgba(1, 2, 3, 255)),
+            ("red".to_string(), Color::rgba(200, 0, 0, 255)),
+        ]));
+        assert_eq!(color_from_str("brand").unwrap(), Color::rgba(1, 2, 3, 255));
+        assert_eq!(color_from_str("red").unwrap(), Color::rgba(200, 0, 0, 255), "palette before CSS names");
+        assert_eq!(color_from_str("brnad").unwrap_err(), "Unknown color 'brnad'. Palette colors: brand, red");
+        drop(scope);
+        assert!(color_from_str("brand").is_err());
+    }
+
     #[test]
     fn test_color_to_rgb_floats() {
-        let c = Color { r: 255, g: 128, b: 0, a: 255 };
+        let c = Color::rgba(255, 128, 0, 255);
         let (r, g, b) = c.to_rgb_floats();
         assert!((r - 1.0).abs() < 0.001);
         assert!((g - 0.502).abs() < 0.01);
@@ -806,11 +5644,284 @@ mod tests {
 
     #[test]
     fn test_color_alpha() {
-        let c = Color { r: 0, g: 0, b: 0, a: 127 };
+        let c = Color::rgba(0, 0, 0, 127);
         let alpha = c.alpha();
         assert!((alpha - 0.498).abs() < 0.01);
     }
 
+    fn transformed(text: &str, transform: TextTransform) -> Vec<(String, f32)> {
+        TextElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 0.0,
+            y: 0.0,
+            text: text.to_string(),
+            font: "f".to_string(),
+            font_fallback: Vec::new(),
+            missing_glyph_policy: MissingGlyphPolicy::Drop,
+            emoji: EmojiOptions::default(),
+            size: 12.0,
+            color: Color::black(),
+            align: TextAlign::Left,
+            vertical_anchor: VerticalAnchor::Baseline,
+            highlight_color: None,
+            highlight_padding: 1.0,
+            transform,
+            fit_width: None,
+            max_width: None,
+            skew: Skew::default(),
+            rotation: 0.0,
+            outline: false,
+            styles: Vec::new(),
+        }
+        .pieces()
+    }
+
+    #[test]
+    fn test_signature_line_parts() {
+        let line = SignatureLineElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 72.0,
+            y: 100.0,
+            w: 200.0,
+            label: Some("Jane Doe".to_string()),
+            sublabels: vec!["Title".to_string(), "Date".to_string()],
+            font: "f".to_string(),
+            size: 10.0,
+            color: Color::black(),
+            stroke: 1.0,
+            mark: true,
+        };
+        let parts = line.parts();
+        assert_eq!(parts.len(), 5);
+        assert!(matches!(&parts[0], Element::Line(l) if (l.x1, l.x2, l.y1, l.y2) == (72.0, 272.0, 100.0, 100.0)));
+        let texts: Vec<_> = parts[1..]
+            .iter()
+            .map(|part| match part {
+                Element::Text(t) => (t.text.as_str(), t.x, t.y, t.vertical_anchor),
+                _ => panic!("expected text"),
+            })
+            .collect();
+        // The mark sits on the rule; captions hang 6 pt under it, 14 pt apart
+        assert!(matches!(texts[0], ("X", 72.0, 96.5, VerticalAnchor::Baseline)));
+        assert!(matches!(texts[1], ("Jane Doe", 72.0, 106.5, VerticalAnchor::Capline)));
+        assert!(matches!(texts[2], ("Title", 72.0, 120.5, VerticalAnchor::Capline)));
+        assert!(matches!(texts[3], ("Date", 172.0, 120.5, VerticalAnchor::Capline)));
+    }
+
+    #[test]
+    fn test_font_family_faces() {
+        let family = FontFamily {
+            regular: Some("r".to_string()),
+            bold: Some("b".to_string()),
+            italic: None,
+            bold_italic: Some("bi".to_string()),
+        };
+        assert_eq!(family.face(FontWeight::Regular, FontStyle::Normal), (Some("r"), "regular"));
+        assert_eq!(family.face(FontWeight::Bold, FontStyle::Normal), (Some("b"), "bold"));
+        assert_eq!(family.face(FontWeight::Regular, FontStyle::Italic), (None, "italic"));
+        assert_eq!(family.face(FontWeight::Bold, FontStyle::Italic), (Some("bi"), "bold_italic"));
+    }
+
+    #[test]
+    fn test_image_placeholder_parts() {
+        let placeholder = ImagePlaceholderElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            x: 300.0,
+            y: 100.0,
+            w: 160.0,
+            h: 90.0,
+            align: TextAlign::Center,
+            label: Some("Hero photo".to_string()),
+            note: Some("16:9".to_string()),
+            font: "f".to_string(),
+            size: 10.0,
+            color: Color::black(),
+            fill_color: Color::white(),
+        };
+        let parts = placeholder.parts();
+        assert_eq!(parts.len(), 5);
+        assert!(matches!(&parts[0], Element::Rect(r) if (r.x, r.y, r.w, r.h) == (220.0, 100.0, 160.0, 90.0)));
+        assert!(matches!(&parts[1], Element::Line(l) if (l.x1, l.y1, l.x2, l.y2) == (220.0, 100.0, 380.0, 190.0)));
+        assert!(matches!(&parts[2], Element::Line(l) if (l.x1, l.y1, l.x2, l.y2) == (220.0, 190.0, 380.0, 100.0)));
+        let texts: Vec<_> = parts[3..]
+            .iter()
+            .map(|part| match part {
+                Element::Text(t) => (t.text.as_str(), t.x, t.y, t.size),
+                _ => panic!("expected text"),
+            })
+            .collect();
+        // Label and note straddle the box's middle, 13 pt apart
+        assert_eq!(texts, [("Hero photo", 300.0, 138.5, 10.0), ("16:9", 300.0, 151.5, 8.0)]);
+    }
+
+    #[test]
+    fn test_grid_parts() {
+        let mut grid = GridElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            spacing: 10.0,
+            major_every: 5,
+            color: Color::black(),
+            stroke: 0.25,
+            major_stroke: 1.0,
+            font: Some("f".to_string()),
+            size: 10.0,
+            label_color: Color::black(),
+            unit: 2.0,
+            from_bottom: false,
+        };
+        let split = |parts: Vec<Element>| {
+            let mut lines = Vec::new();
+            let mut texts = Vec::new();
+            for part in parts {
+                match part {
+                    Element::Line(l) => lines.push((l.x1, l.y1, l.x2, l.y2, l.stroke)),
+                    Element::Text(t) => texts.push((t.text, t.x, t.y, t.vertical_anchor)),
+                    _ => panic!("expected lines and text"),
+                }
+            }
+            (lines, texts)
+        };
+        // 11 columns and 7 rows, the page edges included
+        let (lines, texts) = split(grid.parts(100.0, 60.0));
+        assert_eq!(lines.len(), 18);
+        assert_eq!(lines[0], (0.0, 0.0, 0.0, 60.0, 1.0));
+        assert_eq!(lines[1], (10.0, 0.0, 10.0, 60.0, 0.25));
+        assert_eq!(lines[16], (0.0, 50.0, 100.0, 50.0, 1.0));
+        // Major lines past the corner are labeled in document units
+        assert_eq!(texts.len(), 3);
+        assert!(matches!(&texts[0], (s, 53.0, 3.0, VerticalAnchor::Capline) if s == "25"));
+        assert!(matches!(&texts[1], (s, 103.0, 3.0, VerticalAnchor::Capline) if s == "50"));
+        assert!(matches!(&texts[2], (s, 3.0, 47.0, VerticalAnchor::Baseline) if s == "25"));
+
+        // Rows count up from the bottom edge
+        grid.from_bottom = true;
+        grid.unit = 1.0;
+        let (lines, texts) = split(grid.parts(100.0, 60.0));
+        assert_eq!(lines[11], (0.0, 60.0, 100.0, 60.0, 1.0));
+        assert!(matches!(&texts[2], (s, 3.0, 7.0, VerticalAnchor::Baseline) if s == "50"));
+    }
+
+    #[test]
+    fn test_watermark_placed_per_page_size() {
+        let page = |width, height| Page {
+            width,
+            height,
+            background: PageBackground::Color(Color::white()),
+            repeat: 1,
+            elements: Vec::new(),
+            source: 0,
+        };
+        let mark = GroupElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            elements: Vec::new(),
+            x: 0.0,
+            y: 0.0,
+            rotation: 0.0,
+            scale: 1.0,
+            transparency_group: false,
+            opacity: 1.0,
+        };
+        let under = RepeatingElement { element: Element::Group(mark.clone()), pages: PageFilter::default(), under_content: true, page_numbers: false };
+        let mut doc = Document {
+            metadata: Metadata::default(),
+            pages: vec![page(612.0, 792.0), page(792.0, 612.0), page(612.0, 792.0)],
+            repeating: vec![under],
+            layers: Vec::new(),
+            resources: Resources::default(),
+            open_action: None,
+            origin: Origin::TopLeft,
+            units: Units::default(),
+            watermark: Some(Watermark {
+                element: Element::Group(mark),
+                opacity: 0.25,
+                rotation: 45.0,
+                placement: (0.5, 0.25),
+                behind: true,
+            }),
+            header: None,
+            footer: None,
+        };
+        doc.place_watermark();
+
+        assert!(doc.watermark.is_none());
+        assert_eq!(doc.repeating.len(), 3);
+        let placed: Vec<_> = doc.repeating[..2]
+            .iter()
+            .map(|r| match &r.element {
+                Element::Group(g) => (g.x, g.y, g.rotation, g.transparency_group, r.pages.only.clone().unwrap(), r.under_content),
+                other => panic!("not a group: {:?}", other),
+            })
+            .collect();
+        // Behind goes beneath the document's own repeating elements
+        assert_eq!(placed[0], (306.0, 198.0, -45.0, true, vec![0, 2], true));
+        assert_eq!(placed[1], (396.0, 153.0, -45.0, true, vec![1], true));
+    }
+
+    #[test]
+    fn test_group_place_matches_matrix() {
+        let group = GroupElement {
+            id: None,
+            tagging: Tagging::default(),
+            z: 0,
+            visible: true,
+            elements: Vec::new(),
+            x: 200.0,
+            y: 300.0,
+            rotation: 30.0,
+            scale: 1.5,
+            transparency_group: false,
+            opacity: 1.0,
+        };
+        let h = 792.0;
+        let [a, b, c, d, e, f] = group.matrix(h);
+        for (x, y) in [(0.0, 0.0), (40.0, 0.0), (0.0, 25.0), (-10.0, 60.0)] {
+            // The child point in PDF coordinates, through `cm`, back to top-left
+            let (u, v) = (x, h - y);
+            let (px, py) = (a * u + c * v + e, h - (b * u + d * v + f));
+            let (qx, qy) = group.place((x, y));
+            assert!((px - qx).abs() < 1e-3 && (py - qy).abs() < 1e-3, "({}, {}): ({}, {}) vs ({}, {})", x, y, px, py, qx, qy);
+        }
+        assert_eq!(group.place((0.0, 0.0)), (200.0, 300.0));
+        assert!(!group.is_identity());
+    }
+
+    #[test]
+    fn test_text_transform_pieces() {
+        let one = |s: &str| vec![(s.to_string(), 12.0)];
+        assert_eq!(transformed("Straße 5", TextTransform::Uppercase), one("STRASSE 5"));
+        assert_eq!(transformed("MiXed", TextTransform::Lowercase), one("mixed"));
+        assert_eq!(transformed("hello big  world", TextTransform::Capitalize), one("Hello Big  World"));
+        assert_eq!(transformed("as is", TextTransform::None), one("as is"));
+        assert_eq!(
+            transformed("Rupdf 2", TextTransform::SmallCaps),
+            vec![("R".to_string(), 12.0), ("UPDF".to_string(), 9.0), (" 2".to_string(), 12.0)]
+        );
+    }
+
+    #[test]
+    fn test_cmyk_rgb_equivalent() {
+        let c = Color::cmyka(0.0, 0.5, 1.0, 0.2, 255);
+        assert_eq!((c.r, c.g, c.b), (204, 102, 0));
+        assert_eq!(c.model, ColorModel::Cmyk([0.0, 0.5, 1.0, 0.2]));
+        assert!(Color::cmyka(0.0, 0.0, 0.0, 0.0, 255).is_paper());
+        assert!(!Color::cmyka(0.0, 0.0, 0.0, 0.0, 128).is_paper());
+    }
+
     #[test]
     fn test_text_align_default() {
         let align = TextAlign::default();
@@ -0,0 +1,507 @@
+//! Length units for document coordinates.
+//!
+//! Bare numbers in a document are in the document's `units` (points by
+//! default). Any length may instead be a string with an explicit suffix,
+//! e.g. `"25mm"`, which is converted regardless of the document units.
+//!
+//! Element lengths may also be relative to the page they are on (see
+//! `PageLength`): a keyword such as `"content_width"`, a percentage of the
+//! page, or a fraction of the page or its content area. These resolve to
+//! points while the page's elements are parsed.
+
+use crate::error::{Result, RupdfError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use pyo3::Borrowed;
+
+const SUPPORTED: &str = "pt, mm, cm, in, px, px@<dpi>";
+
+const KEYWORDS: &str =
+    "margin_left, margin_right, margin_top, margin_bottom, content_width, content_height, page_width, page_height";
+
+/// Default resolution for `px` when no dpi is given (CSS reference pixel)
+const DEFAULT_PX_DPI: f32 = 96.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Unit {
+    Pt,
+    Mm,
+    Cm,
+    In,
+    /// Pixels at the given resolution (dots per inch)
+    Px(f32),
+}
+
+impl Unit {
+    /// Parse a unit name: "pt", "mm", "cm", "in", "px" or "px@<dpi>".
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "pt" => Some(Unit::Pt),
+            "mm" => Some(Unit::Mm),
+            "cm" => Some(Unit::Cm),
+            "in" => Some(Unit::In),
+            "px" => Some(Unit::Px(DEFAULT_PX_DPI)),
+            _ => {
+                let dpi: f32 = s.strip_prefix("px@")?.parse().ok()?;
+                (dpi.is_finite() && dpi > 0.0).then_some(Unit::Px(dpi))
+            }
+        }
+    }
+
+    pub fn points_per_unit(self) -> f32 {
+        match self {
+            Unit::Pt => 1.0,
+            Unit::Mm => 72.0 / 25.4,
+            Unit::Cm => 72.0 / 2.54,
+            Unit::In => 72.0,
+            Unit::Px(dpi) => 72.0 / dpi,
+        }
+    }
+}
+
+/// The document-level `units` option
+#[derive(Debug, Clone, Copy)]
+pub struct Units {
+    unit: Unit,
+}
+
+impl Default for Units {
+    fn default() -> Self {
+        Self { unit: Unit::Pt }
+    }
+}
+
+impl Units {
+    pub fn parse(s: &str) -> Result<Self> {
+        Unit::parse(s).map(|unit| Self { unit }).ok_or_else(|| {
+            RupdfError::InvalidDocument(format!(
+                "Invalid units: '{}'. Must be one of: {}",
+                s, SUPPORTED
+            ))
+        })
+    }
+
+    /// Points in one bare unit
+    pub fn points_per_unit(self) -> f32 {
+        self.unit.points_per_unit()
+    }
+
+    /// Convert a parsed length to points.
+    pub fn to_points(self, length: Length) -> f32 {
+        let unit = match length.unit {
+            // A bare `px` suffix follows the document's dpi when it is in pixels
+            Some(Unit::Px(_)) if matches!(self.unit, Unit::Px(_)) => self.unit,
+            Some(unit) => unit,
+            None => self.unit,
+        };
+        length.value * unit.points_per_unit()
+    }
+}
+
+/// Named paper sizes as (name, width, height, unit), portrait orientation.
+/// ISO sizes are defined in millimeters, North American sizes in inches.
+const PAPER_SIZES: &[(&str, f32, f32, Unit)] = &[
+    ("A3", 297.0, 420.0, Unit::Mm),
+    ("A4", 210.0, 297.0, Unit::Mm),
+    ("A5", 148.0, 210.0, Unit::Mm),
+    ("A6", 105.0, 148.0, Unit::Mm),
+    ("B5", 176.0, 250.0, Unit::Mm),
+    ("C4", 229.0, 324.0, Unit::Mm),
+    ("C5", 162.0, 229.0, Unit::Mm),
+    ("DL", 110.0, 220.0, Unit::Mm),
+    ("letter", 8.5, 11.0, Unit::In),
+    ("legal", 8.5, 14.0, Unit::In),
+    ("tabloid", 11.0, 17.0, Unit::In),
+    ("executive", 7.25, 10.5, Unit::In),
+];
+
+/// Portrait (width, height) in points for a named paper size.
+/// Names are matched case-insensitively.
+pub fn paper_size(name: &str) -> Result<(f32, f32)> {
+    PAPER_SIZES
+        .iter()
+        .find(|(n, ..)| n.eq_ignore_ascii_case(name))
+        .map(|&(_, w, h, unit)| (w * unit.points_per_unit(), h * unit.points_per_unit()))
+        .ok_or_else(|| {
+            let names: Vec<&str> = PAPER_SIZES.iter().map(|(n, ..)| *n).collect();
+            RupdfError::InvalidDocument(format!(
+                "Unknown page size '{}'. Supported presets: {}",
+                name,
+                names.join(", ")
+            ))
+        })
+}
+
+/// A length as written in the document: a number in document units, or a
+/// string with an explicit unit suffix.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Length {
+    pub value: f32,
+    pub unit: Option<Unit>,
+}
+
+impl Length {
+    /// Parse a suffixed length string such as "25mm", "0.5in", "12" or
+    /// "1.5e1mm". The number is the longest prefix that reads as one, so
+    /// an exponent's `e` isn't taken for the start of a unit.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let numeric = |c: char| c.is_ascii_digit() || matches!(c, '+' | '-' | '.' | 'e' | 'E');
+        let end = s.find(|c: char| !numeric(c)).unwrap_or(s.len());
+        (0..=end).rev().find_map(|split| {
+            let (number, suffix) = s.split_at(split);
+            let value: f32 = number.parse().ok()?;
+            let unit = match suffix.trim_start() {
+                "" => None,
+                // Suffixes carry no dpi; "px" is resolved against the document
+                suffix => Some(Unit::parse(suffix)?),
+            };
+            Some(Self { value, unit })
+        })
+    }
+}
+
+impl<'py> FromPyObject<'_, 'py> for Length {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        if let Ok(value) = ob.extract::<f32>() {
+            return Ok(Self { value, unit: None });
+        }
+        let s: String = ob.extract()?;
+        Self::parse(&s).ok_or_else(|| {
+            pyo3::exceptions::PyValueError::new_err(format!(
+                "Invalid length: '{}'. Expected a number or a string like '25mm' (units: {})",
+                s, SUPPORTED
+            ))
+        })
+    }
+}
+
+/// A page's margins in points, inset from each edge
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Margins {
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub left: f32,
+}
+
+/// Where a document's coordinates start, from its `origin`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Origin {
+    /// The page's top-left corner, y increasing downward
+    #[default]
+    TopLeft,
+    /// The page's bottom-left corner, y increasing upward, as in PDF
+    BottomLeft,
+}
+
+impl Origin {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Origin::TopLeft => "top_left",
+            Origin::BottomLeft => "bottom_left",
+        }
+    }
+}
+
+impl<'py> FromPyObject<'_, 'py> for Origin {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        let s: String = ob.extract()?;
+        match s.as_str() {
+            "top_left" => Ok(Origin::TopLeft),
+            "bottom_left" => Ok(Origin::BottomLeft),
+            _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Invalid origin: '{}'. Must be 'top_left' or 'bottom_left'",
+                s
+            ))),
+        }
+    }
+}
+
+/// The page whose elements are being parsed: its size and margins
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PageFrame {
+    pub width: f32,
+    pub height: f32,
+    pub margins: Margins,
+    /// The document's origin, which vertical positions are measured from
+    pub origin: Origin,
+}
+
+impl PageFrame {
+    /// The value of a length keyword. Margin keywords are the content
+    /// area's edges, as coordinates from the document's origin.
+    fn keyword(&self, name: &str) -> Option<f32> {
+        let m = &self.margins;
+        let up = self.origin == Origin::BottomLeft;
+        Some(match name {
+            "margin_left" => m.left,
+            "margin_right" => self.width - m.right,
+            "margin_top" if up => self.height - m.top,
+            "margin_top" => m.top,
+            "margin_bottom" if up => m.bottom,
+            "margin_bottom" => self.height - m.bottom,
+            "content_width" => self.width - m.left - m.right,
+            "content_height" => self.height - m.top - m.bottom,
+            "page_width" => self.width,
+            "page_height" => self.height,
+            _ => return None,
+        })
+    }
+
+    /// Start and extent of `area` along `axis`.
+    fn span(&self, area: Area, axis: Axis) -> (f32, f32) {
+        let m = &self.margins;
+        match (area, axis) {
+            (Area::Page, Axis::X) => (0.0, self.width),
+            (Area::Page, Axis::Y) => (0.0, self.height),
+            (Area::Content, Axis::X) => (m.left, self.width - m.left - m.right),
+            (Area::Content, Axis::Y) if self.origin == Origin::BottomLeft => {
+                (m.bottom, self.height - m.top - m.bottom)
+            }
+            (Area::Content, Axis::Y) => (m.top, self.height - m.top - m.bottom),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Area {
+    Page,
+    Content,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Axis {
+    X,
+    Y,
+}
+
+impl Axis {
+    /// The axis a field measures along, from its name: `x`, `x1`, `w`, ...
+    /// are horizontal and `y`, `y2`, `h`, ... vertical.
+    fn of(key: &str) -> Option<Self> {
+        match key.chars().next()? {
+            'x' | 'w' => Some(Axis::X),
+            'y' | 'h' => Some(Axis::Y),
+            _ => None,
+        }
+    }
+}
+
+/// An element length that may depend on its page
+#[derive(Debug, Clone, PartialEq)]
+pub enum PageLength {
+    Absolute(Length),
+    /// A page dimension by name, e.g. `"margin_left"`
+    Keyword(String),
+    /// Percentage of the page along the field's axis, e.g. `"50%"`
+    Percent(f32),
+    /// `{"rel": "page" | "content", "x" | "y" | "w" | "h": fraction}`: a
+    /// position (`x`, `y`) or extent (`w`, `h`) as a fraction of the area
+    Fraction { area: Area, axis: Axis, position: bool, fraction: f32 },
+}
+
+impl PageLength {
+    /// Resolve to points for the field `key`. Relative lengths need the
+    /// `frame` of the page being parsed.
+    pub fn to_points(&self, units: Units, frame: Option<&PageFrame>, key: &str) -> Result<f32> {
+        if let PageLength::Absolute(length) = self {
+            return Ok(units.to_points(*length));
+        }
+        let frame = frame.ok_or_else(|| {
+            RupdfError::InvalidDocument(format!(
+                "'{}' is relative to the page; only page elements can use page-relative lengths",
+                key
+            ))
+        })?;
+        match self {
+            PageLength::Absolute(_) => unreachable!("handled above"),
+            PageLength::Keyword(name) => Ok(frame.keyword(name).expect("keywords are checked when parsed")),
+            PageLength::Percent(percent) => {
+                let axis = Axis::of(key).ok_or_else(|| {
+                    RupdfError::InvalidDocument(format!(
+                        "'{}' can't be a percentage; only x, y, w and h lengths can",
+                        key
+                    ))
+                })?;
+                Ok(frame.span(Area::Page, axis).1 * percent / 100.0)
+            }
+            PageLength::Fraction { area, axis, position, fraction } => {
+                let (start, extent) = frame.span(*area, *axis);
+                Ok(if *position { start } else { 0.0 } + extent * fraction)
+            }
+        }
+    }
+}
+
+impl<'py> FromPyObject<'_, 'py> for PageLength {
+    type Error = PyErr;
+    fn extract(ob: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        let invalid = |message: String| pyo3::exceptions::PyValueError::new_err(message);
+        if let Ok(dict) = ob.cast::<PyDict>() {
+            let area = match dict.get_item("rel")?.map(|v| v.extract::<String>()).transpose()?.as_deref() {
+                Some("content") | None => Area::Content,
+                Some("page") => Area::Page,
+                Some(other) => {
+                    return Err(invalid(format!("Invalid rel: '{}'. Must be 'page' or 'content'", other)));
+                }
+            };
+            let one_of = || invalid("A relative length must have exactly one of x, y, w or h".to_string());
+            let mut found = None;
+            for (key, value) in dict.iter() {
+                let key: String = key.extract()?;
+                let (axis, position) = match key.as_str() {
+                    "rel" => continue,
+                    "x" => (Axis::X, true),
+                    "y" => (Axis::Y, true),
+                    "w" => (Axis::X, false),
+                    "h" => (Axis::Y, false),
+                    _ => {
+                        return Err(invalid(format!(
+                            "Unknown key '{}' in relative length. Must be one of: rel, x, y, w, h",
+                            key
+                        )));
+                    }
+                };
+                if found.is_some() {
+                    return Err(one_of());
+                }
+                found = Some(PageLength::Fraction { area, axis, position, fraction: value.extract()? });
+            }
+            return found.ok_or_else(one_of);
+        }
+        if let Ok(s) = ob.extract::<String>() {
+            if let Some(percent) = s.trim().strip_suffix('%') {
+                return percent
+                    .trim()
+                    .parse()
+                    .map(PageLength::Percent)
+                    .map_err(|_| invalid(format!("Invalid percentage: '{}'", s)));
+            }
+            if !s.is_empty() && s.chars().all(|c| c.is_ascii_lowercase() || c == '_') {
+                if !KEYWORDS.split(", ").any(|k| k == s) {
+                    return Err(invalid(format!("Unknown length keyword '{}'. Must be one of: {}", s, KEYWORDS)));
+                }
+                return Ok(PageLength::Keyword(s));
+            }
+        }
+        Ok(PageLength::Absolute(ob.extract()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    #[test]
+    fn parses_unit_names() {
+        assert_eq!(Unit::parse("mm"), Some(Unit::Mm));
+        assert_eq!(Unit::parse("px@300"), Some(Unit::Px(300.0)));
+        assert_eq!(Unit::parse("px"), Some(Unit::Px(96.0)));
+        assert_eq!(Unit::parse("px@0"), None);
+        assert_eq!(Unit::parse("furlong"), None);
+    }
+
+    #[test]
+    fn bare_numbers_use_document_units() {
+        let mm = Units::parse("mm").unwrap();
+        assert!(close(mm.to_points(Length { value: 25.4, unit: None }), 72.0));
+        assert!(close(Units::default().to_points(Length { value: 10.0, unit: None }), 10.0));
+    }
+
+    #[test]
+    fn suffix_overrides_document_units() {
+        let inches = Units::parse("in").unwrap();
+        let len = Length::parse("25.4mm").unwrap();
+        assert!(close(inches.to_points(len), 72.0));
+        assert!(close(inches.to_points(Length::parse("12pt").unwrap()), 12.0));
+    }
+
+    #[test]
+    fn exponents_are_part_of_the_number() {
+        assert_eq!(Length::parse("1e3"), Some(Length { value: 1000.0, unit: None }));
+        assert_eq!(Length::parse("1e2pt"), Some(Length { value: 100.0, unit: Some(Unit::Pt) }));
+        assert_eq!(Length::parse("1.5e1mm"), Some(Length { value: 15.0, unit: Some(Unit::Mm) }));
+        assert_eq!(Length::parse("2.5E-1 in"), Some(Length { value: 0.25, unit: Some(Unit::In) }));
+        assert!(Length::parse("1em").is_none());
+        assert!(Length::parse("1e").is_none());
+    }
+
+    #[test]
+    fn px_suffix_follows_document_dpi() {
+        let px300 = Units::parse("px@300").unwrap();
+        assert!(close(px300.to_points(Length::parse("300px").unwrap()), 72.0));
+        assert!(close(Units::default().to_points(Length::parse("96px").unwrap()), 72.0));
+    }
+
+    #[test]
+    fn paper_sizes_resolve_to_points() {
+        let (w, h) = paper_size("a4").unwrap();
+        assert!(close(w, 595.276) && close(h, 841.89));
+        assert_eq!(paper_size("Letter").unwrap(), (612.0, 792.0));
+        let (w, h) = paper_size("dl").unwrap();
+        assert!(close(w, 311.811) && close(h, 623.622));
+    }
+
+    #[test]
+    fn unknown_paper_size_lists_presets() {
+        let err = paper_size("A9").unwrap_err().to_string();
+        assert!(err.contains("Unknown page size 'A9'"), "{}", err);
+        assert!(err.contains("A4") && err.contains("letter"), "{}", err);
+    }
+
+    #[test]
+    fn page_lengths_resolve_against_the_frame() {
+        let frame = PageFrame {
+            width: 600.0,
+            height: 800.0,
+            margins: Margins { top: 50.0, right: 40.0, bottom: 60.0, left: 30.0 },
+            origin: Origin::TopLeft,
+        };
+        let at = |length: PageLength, key| length.to_points(Units::default(), Some(&frame), key).unwrap();
+        assert_eq!(at(PageLength::Keyword("margin_right".to_string()), "x"), 560.0);
+        assert_eq!(at(PageLength::Keyword("content_height".to_string()), "h"), 690.0);
+        assert_eq!(at(PageLength::Percent(25.0), "x2"), 150.0);
+        assert_eq!(at(PageLength::Percent(25.0), "h"), 200.0);
+        let half = |position| PageLength::Fraction { area: Area::Content, axis: Axis::X, position, fraction: 0.5 };
+        assert_eq!(at(half(true), "x"), 295.0);
+        assert_eq!(at(half(false), "w"), 265.0);
+
+        let err = PageLength::Percent(10.0).to_points(Units::default(), Some(&frame), "size").unwrap_err();
+        assert!(err.to_string().contains("'size' can't be a percentage"), "{}", err);
+        let err = PageLength::Keyword("margin_top".to_string()).to_points(Units::default(), None, "y").unwrap_err();
+        assert!(err.to_string().contains("only page elements"), "{}", err);
+    }
+
+    #[test]
+    fn bottom_left_positions_are_measured_from_the_bottom() {
+        let frame = PageFrame {
+            width: 600.0,
+            height: 800.0,
+            margins: Margins { top: 50.0, right: 40.0, bottom: 60.0, left: 30.0 },
+            origin: Origin::BottomLeft,
+        };
+        let at = |length: PageLength, key| length.to_points(Units::default(), Some(&frame), key).unwrap();
+        assert_eq!(at(PageLength::Keyword("margin_top".to_string()), "y"), 750.0);
+        assert_eq!(at(PageLength::Keyword("margin_bottom".to_string()), "y"), 60.0);
+        assert_eq!(at(PageLength::Keyword("content_height".to_string()), "h"), 690.0);
+        let fraction = |position| PageLength::Fraction { area: Area::Content, axis: Axis::Y, position, fraction: 0.5 };
+        assert_eq!(at(fraction(true), "y"), 405.0);
+        assert_eq!(at(fraction(false), "h"), 345.0);
+        // Horizontal positions don't change
+        assert_eq!(at(PageLength::Keyword("margin_left".to_string()), "x"), 30.0);
+    }
+
+    #[test]
+    fn rejects_malformed_lengths() {
+        assert!(Length::parse("mm").is_none());
+        assert!(Length::parse("10 furlongs").is_none());
+        assert!(Units::parse("meters").is_err());
+    }
+}
@@ -0,0 +1,114 @@
+//! Structured, non-fatal diagnostics collected while checking or rendering
+//! a document.
+//!
+//! `render_pdf` emits each warning as a Python `RupdfWarning` through the
+//! standard `warnings` module; `validate_document` returns them as dicts.
+
+use crate::types::ElementLabel;
+use pyo3::exceptions::PyUserWarning;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::ffi::CString;
+use std::fmt;
+
+pyo3::create_exception!(rupdf, PyRupdfWarning, PyUserWarning);
+
+/// A single diagnostic, located by page and element index where relevant
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    /// Stable machine-readable category, e.g. "out_of_bounds"
+    pub code: &'static str,
+    pub page: Option<usize>,
+    pub element: Option<usize>,
+    /// The element's `id`, if it has one
+    pub id: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.page, self.element) {
+            (Some(p), Some(index)) => {
+                let element = ElementLabel { index, id: self.id.as_deref() };
+                write!(f, "Page {}, element {}: {}", p, element, self.message)
+            }
+            (Some(p), None) => write!(f, "Page {}: {}", p, self.message),
+            _ => f.write_str(&self.message),
+        }
+    }
+}
+
+impl Warning {
+    pub fn to_py<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        dict.set_item("code", self.code)?;
+        dict.set_item("page", self.page)?;
+        dict.set_item("element", self.element)?;
+        dict.set_item("id", &self.id)?;
+        dict.set_item("message", self.to_string())?;
+        Ok(dict)
+    }
+}
+
+/// Warnings accumulated over one call
+#[derive(Debug, Default)]
+pub struct Warnings {
+    items: Vec<Warning>,
+}
+
+impl Warnings {
+    pub fn push(&mut self, warning: Warning) {
+        self.items.push(warning);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Warning> {
+        self.items.iter()
+    }
+
+    /// Emit every warning as a Python `RupdfWarning`. Raises if a warnings
+    /// filter turns them into errors.
+    pub fn emit(&self, py: Python<'_>) -> PyResult<()> {
+        let category = py.get_type::<PyRupdfWarning>();
+        for warning in &self.items {
+            let message = CString::new(warning.to_string().replace('\0', ""))
+                .expect("interior NULs were removed");
+            PyErr::warn(py, category.as_any(), &message, 1)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_includes_location() {
+        let w = Warning {
+            code: "out_of_bounds",
+            page: Some(1),
+            element: Some(4),
+            id: None,
+            message: "rect extends past the right edge by 3.0 pt".to_string(),
+        };
+        assert_eq!(
+            w.to_string(),
+            "Page 1, element 4: rect extends past the right edge by 3.0 pt"
+        );
+    }
+
+    #[test]
+    fn display_includes_id() {
+        let w = Warning {
+            code: "out_of_bounds",
+            page: Some(0),
+            element: Some(2),
+            id: Some("invoice.total".to_string()),
+            message: "text extends past the right edge by 1.5 pt".to_string(),
+        };
+        assert_eq!(
+            w.to_string(),
+            "Page 0, element 2 (id 'invoice.total'): text extends past the right edge by 1.5 pt"
+        );
+    }
+}
@@ -0,0 +1,224 @@
+//! Web font (WOFF and WOFF2) unwrapping.
+//!
+//! WOFF 1.0 is an SFNT (TrueType/OpenType) font whose tables are
+//! individually zlib-compressed behind a small header; WOFF2 is handled in
+//! `woff2`. Fonts are unwrapped to plain SFNT bytes in memory so the rest
+//! of the pipeline (parsing, subsetting, embedding) never sees the
+//! container.
+
+use miniz_oxide::inflate::{decompress_to_vec_zlib_with_limit, TINFLStatus};
+
+const WOFF_SIGNATURE: &[u8; 4] = b"wOFF";
+const WOFF2_SIGNATURE: &[u8; 4] = b"wOF2";
+const HEADER_LEN: usize = 44;
+const DIR_ENTRY_LEN: usize = 20;
+
+fn read_u16(data: &[u8], pos: usize) -> Option<u16> {
+    data.get(pos..pos + 2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+}
+
+fn read_u32(data: &[u8], pos: usize) -> Option<u32> {
+    data.get(pos..pos + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// Return SFNT font data, unwrapping WOFF or WOFF2 if `data` carries its
+/// signature.
+/// Data without a web font signature is returned unchanged.
+pub fn to_sfnt(data: Vec<u8>) -> Result<Vec<u8>, String> {
+    match data.get(..4) {
+        Some(sig) if sig == WOFF_SIGNATURE => decode_woff(&data),
+        Some(sig) if sig == WOFF2_SIGNATURE => crate::woff2::decode(&data),
+        _ => Ok(data),
+    }
+}
+
+pub(crate) struct TableEntry {
+    pub tag: [u8; 4],
+    pub checksum: u32,
+    pub data: Vec<u8>,
+}
+
+fn decode_woff(data: &[u8]) -> Result<Vec<u8>, String> {
+    let truncated = || "truncated WOFF header".to_string();
+    if data.len() < HEADER_LEN {
+        return Err(truncated());
+    }
+    let flavor = read_u32(data, 4).ok_or_else(truncated)?;
+    let declared_len = read_u32(data, 8).ok_or_else(truncated)? as usize;
+    let num_tables = read_u16(data, 12).ok_or_else(truncated)? as usize;
+    if declared_len != data.len() {
+        return Err(format!(
+            "WOFF length mismatch: header declares {} bytes, file has {}",
+            declared_len,
+            data.len()
+        ));
+    }
+
+    let mut tables = Vec::with_capacity(num_tables);
+    for i in 0..num_tables {
+        let entry = HEADER_LEN + i * DIR_ENTRY_LEN;
+        let field = |offset: usize| {
+            read_u32(data, entry + offset)
+                .ok_or_else(|| format!("truncated WOFF table directory at entry {}", i))
+        };
+        let tag_bytes = field(0)?.to_be_bytes();
+        let tag = String::from_utf8_lossy(&tag_bytes).into_owned();
+        let offset = field(4)? as usize;
+        let comp_len = field(8)? as usize;
+        let orig_len = field(12)? as usize;
+        let checksum = field(16)?;
+
+        let compressed = offset
+            .checked_add(comp_len)
+            .and_then(|end| data.get(offset..end))
+            .ok_or_else(|| format!("WOFF table '{}' extends past the end of the file", tag))?;
+        let table = if comp_len < orig_len {
+            // Inflate no further than the declared length, so a small table
+            // can't expand without bound
+            let inflated =
+                decompress_to_vec_zlib_with_limit(compressed, orig_len).map_err(|e| match e.status {
+                    TINFLStatus::HasMoreOutput => format!(
+                        "WOFF table '{}' decompresses to more than the {} bytes it declares",
+                        tag, orig_len
+                    ),
+                    status => format!("failed to decompress WOFF table '{}': {:?}", tag, status),
+                })?;
+            if inflated.len() != orig_len {
+                return Err(format!(
+                    "WOFF table '{}' decompressed to {} bytes, expected {}",
+                    tag,
+                    inflated.len(),
+                    orig_len
+                ));
+            }
+            inflated
+        } else if comp_len == orig_len {
+            compressed.to_vec()
+        } else {
+            return Err(format!(
+                "WOFF table '{}' is larger compressed ({}) than uncompressed ({})",
+                tag, comp_len, orig_len
+            ));
+        };
+        tables.push(TableEntry { tag: tag_bytes, checksum, data: table });
+    }
+
+    Ok(build_sfnt(flavor, &tables))
+}
+
+/// Lay out tables as an SFNT file: offset table, table records, then each
+/// table padded to a 4-byte boundary.
+pub(crate) fn build_sfnt(flavor: u32, tables: &[TableEntry]) -> Vec<u8> {
+    let num_tables = tables.len() as u16;
+    let entry_selector = if num_tables == 0 { 0 } else { 15 - num_tables.leading_zeros() as u16 };
+    let search_range = (1u16 << entry_selector) * 16;
+    let range_shift = num_tables * 16 - search_range.min(num_tables * 16);
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&flavor.to_be_bytes());
+    out.extend_from_slice(&num_tables.to_be_bytes());
+    out.extend_from_slice(&search_range.to_be_bytes());
+    out.extend_from_slice(&entry_selector.to_be_bytes());
+    out.extend_from_slice(&range_shift.to_be_bytes());
+
+    let mut offset = 12 + 16 * tables.len();
+    for table in tables {
+        out.extend_from_slice(&table.tag);
+        out.extend_from_slice(&table.checksum.to_be_bytes());
+        out.extend_from_slice(&(offset as u32).to_be_bytes());
+        out.extend_from_slice(&(table.data.len() as u32).to_be_bytes());
+        offset += table.data.len().next_multiple_of(4);
+    }
+    for table in tables {
+        out.extend_from_slice(&table.data);
+        out.resize(out.len().next_multiple_of(4), 0);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FONT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/IBMPlexSans-Regular.otf");
+
+    /// Wrap an SFNT font as WOFF, compressing every table that shrinks.
+    fn encode_woff(sfnt: &[u8]) -> Vec<u8> {
+        let num_tables = read_u16(sfnt, 4).unwrap() as usize;
+        let mut dir = Vec::new();
+        let mut body = Vec::new();
+        let data_start = HEADER_LEN + num_tables * DIR_ENTRY_LEN;
+        for i in 0..num_tables {
+            let rec = 12 + i * 16;
+            let offset = read_u32(sfnt, rec + 8).unwrap() as usize;
+            let len = read_u32(sfnt, rec + 12).unwrap() as usize;
+            let raw = &sfnt[offset..offset + len];
+            let mut compressed = miniz_oxide::deflate::compress_to_vec_zlib(raw, 6);
+            if compressed.len() >= len {
+                compressed = raw.to_vec();
+            }
+            dir.extend_from_slice(&sfnt[rec..rec + 4]);
+            dir.extend_from_slice(&((data_start + body.len()) as u32).to_be_bytes());
+            dir.extend_from_slice(&(compressed.len() as u32).to_be_bytes());
+            dir.extend_from_slice(&(len as u32).to_be_bytes());
+            dir.extend_from_slice(&sfnt[rec + 4..rec + 8]);
+            body.extend_from_slice(&compressed);
+            body.resize(body.len().next_multiple_of(4), 0);
+        }
+        let mut out = Vec::new();
+        out.extend_from_slice(WOFF_SIGNATURE);
+        out.extend_from_slice(&sfnt[0..4]);
+        out.extend_from_slice(&((data_start + body.len()) as u32).to_be_bytes());
+        out.extend_from_slice(&(num_tables as u16).to_be_bytes());
+        out.resize(HEADER_LEN, 0);
+        out.extend_from_slice(&dir);
+        out.extend_from_slice(&body);
+        out
+    }
+
+    #[test]
+    fn sfnt_passes_through() {
+        let sfnt = std::fs::read(FONT).unwrap();
+        assert_eq!(to_sfnt(sfnt.clone()).unwrap(), sfnt);
+    }
+
+    #[test]
+    fn woff_round_trips_to_parseable_sfnt() {
+        let sfnt = std::fs::read(FONT).unwrap();
+        let decoded = to_sfnt(encode_woff(&sfnt)).unwrap();
+        let original = ttf_parser::Face::parse(&sfnt, 0).unwrap();
+        let face = ttf_parser::Face::parse(&decoded, 0).unwrap();
+        assert_eq!(face.number_of_glyphs(), original.number_of_glyphs());
+        assert_eq!(face.glyph_index('A'), original.glyph_index('A'));
+    }
+
+    #[test]
+    fn truncated_woff_reports_length_mismatch() {
+        let mut woff = encode_woff(&std::fs::read(FONT).unwrap());
+        woff.truncate(woff.len() / 2);
+        let err = to_sfnt(woff).unwrap_err();
+        assert!(err.contains("length mismatch"), "{}", err);
+    }
+
+    #[test]
+    fn corrupt_table_reports_decompression_failure() {
+        let mut woff = encode_woff(&std::fs::read(FONT).unwrap());
+        let first_table = read_u32(&woff, HEADER_LEN + 4).unwrap() as usize;
+        woff[first_table + 2..first_table + 12].fill(0xFF);
+        let err = to_sfnt(woff).unwrap_err();
+        assert!(err.contains("failed to decompress WOFF table"), "{}", err);
+    }
+
+    #[test]
+    fn table_larger_than_declared_is_rejected() {
+        let mut woff = encode_woff(&std::fs::read(FONT).unwrap());
+        // Halve the first compressed table's declared length
+        let orig_len = read_u32(&woff, HEADER_LEN + 12).unwrap();
+        let comp_len = read_u32(&woff, HEADER_LEN + 8).unwrap();
+        let smaller = (orig_len / 2).max(comp_len + 1);
+        assert!(smaller < orig_len);
+        woff[HEADER_LEN + 12..HEADER_LEN + 16].copy_from_slice(&smaller.to_be_bytes());
+        let err = to_sfnt(woff).unwrap_err();
+        assert!(err.contains("more than the"), "{}", err);
+    }
+}
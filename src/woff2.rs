@@ -0,0 +1,986 @@
+//! WOFF2 unwrapping.
+//!
+//! WOFF2 packs every table into a single Brotli stream behind a compact
+//! table directory. `glyf` and `loca` are usually stored transformed: the
+//! glyph data is split into separate streams (contour counts, point counts,
+//! flags, coordinate triplets, composites, bounding boxes, instructions) and
+//! `loca` is left out entirely, so both are rebuilt here. `hmtx` may also
+//! drop left side bearings that equal each glyph's `xMin`. The result is
+//! the same plain SFNT bytes `woff::to_sfnt` returns for WOFF 1.0.
+
+use std::io::Read;
+
+use crate::woff::{build_sfnt, TableEntry};
+
+const HEADER_LEN: usize = 48;
+const COLLECTION_FLAVOR: &[u8; 4] = b"ttcf";
+
+/// Tags a table directory entry can name by index rather than spelling out
+const KNOWN_TAGS: [&[u8; 4]; 63] = [
+    b"cmap", b"head", b"hhea", b"hmtx", b"maxp", b"name", b"OS/2", b"post", b"cvt ", b"fpgm",
+    b"glyf", b"loca", b"prep", b"CFF ", b"VORG", b"EBDT", b"EBLC", b"gasp", b"hdmx", b"kern",
+    b"LTSH", b"PCLT", b"VDMX", b"vhea", b"vmtx", b"BASE", b"GDEF", b"GPOS", b"GSUB", b"EBSC",
+    b"JSTF", b"MATH", b"CBDT", b"CBLC", b"COLR", b"CPAL", b"SVG ", b"sbix", b"acnt", b"avar",
+    b"bdat", b"bloc", b"bsln", b"cvar", b"fdsc", b"feat", b"fmtx", b"fvar", b"gvar", b"hsty",
+    b"just", b"lcar", b"mort", b"morx", b"opbd", b"prop", b"trak", b"Zapf", b"Silf", b"Glat",
+    b"Gloc", b"Feat", b"Sill",
+];
+
+// Composite glyph component flags
+const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+const WE_HAVE_A_SCALE: u16 = 0x0008;
+const MORE_COMPONENTS: u16 = 0x0020;
+const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+const WE_HAVE_INSTRUCTIONS: u16 = 0x0100;
+
+// Simple glyph point flags
+const ON_CURVE_POINT: u8 = 0x01;
+const X_SHORT_VECTOR: u8 = 0x02;
+const Y_SHORT_VECTOR: u8 = 0x04;
+const X_IS_SAME_OR_POSITIVE: u8 = 0x10;
+const Y_IS_SAME_OR_POSITIVE: u8 = 0x20;
+const OVERLAP_SIMPLE: u8 = 0x40;
+
+/// A named cursor over one part of the file, so a short read says which
+/// part ran out.
+struct Stream<'a> {
+    name: &'static str,
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Stream<'a> {
+    fn new(name: &'static str, data: &'a [u8]) -> Self {
+        Self { name, data, pos: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.data.len())
+            .ok_or_else(|| format!("truncated WOFF2 {}", self.name))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn i16(&mut self) -> Result<i16, String> {
+        Ok(self.u16()? as i16)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    /// UIntBase128: 7 bits per byte, high bit set on all but the last
+    fn base128(&mut self) -> Result<u32, String> {
+        let mut value = 0u32;
+        for i in 0..5 {
+            let byte = self.u8()?;
+            if i == 0 && byte == 0x80 {
+                return Err(format!("WOFF2 {} has a UIntBase128 with leading zeros", self.name));
+            }
+            if value & 0xFE00_0000 != 0 {
+                return Err(format!("WOFF2 {} has a UIntBase128 past 32 bits", self.name));
+            }
+            value = (value << 7) | u32::from(byte & 0x7F);
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(format!("WOFF2 {} has a UIntBase128 longer than 5 bytes", self.name))
+    }
+
+    /// 255UInt16: one byte below 253, otherwise a code byte and more data
+    fn u255_16(&mut self) -> Result<u16, String> {
+        Ok(match self.u8()? {
+            253 => self.u16()?,
+            254 => u16::from(self.u8()?) + 506,
+            255 => u16::from(self.u8()?) + 253,
+            byte => u16::from(byte),
+        })
+    }
+}
+
+struct DirEntry {
+    tag: [u8; 4],
+    orig_len: usize,
+    /// Bytes the table takes in the decompressed stream
+    stream_len: usize,
+    transformed: bool,
+}
+
+fn tag_name(tag: &[u8; 4]) -> String {
+    String::from_utf8_lossy(tag).into_owned()
+}
+
+/// Unwrap WOFF2 `data` (already known to carry the `wOF2` signature).
+pub fn decode(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut header = Stream::new("header", data);
+    header.bytes(4)?; // signature
+    let flavor = header.u32()?;
+    let declared_len = header.u32()? as usize;
+    let num_tables = header.u16()? as usize;
+    header.bytes(6)?; // reserved, totalSfntSize
+    let compressed_len = header.u32()? as usize;
+    header.bytes(HEADER_LEN - header.pos)?; // versions, metadata and private blocks
+    if declared_len != data.len() {
+        return Err(format!(
+            "WOFF2 length mismatch: header declares {} bytes, file has {}",
+            declared_len,
+            data.len()
+        ));
+    }
+    if &flavor.to_be_bytes() == COLLECTION_FLAVOR {
+        return Err("WOFF2 font collections are not supported".to_string());
+    }
+
+    let mut dir = Stream::new("table directory", &data[HEADER_LEN..]);
+    let mut entries = Vec::with_capacity(num_tables);
+    for _ in 0..num_tables {
+        let flags = dir.u8()?;
+        let tag = match flags & 0x3F {
+            0x3F => dir.bytes(4)?.try_into().expect("four bytes"),
+            index => *KNOWN_TAGS[usize::from(index)],
+        };
+        let version = flags >> 6;
+        let orig_len = dir.base128()? as usize;
+        // glyf and loca use version 3 for "stored as is"; every other
+        // table uses version 0
+        let transformed = match &tag {
+            b"glyf" | b"loca" => version != 3,
+            _ => version != 0,
+        };
+        let valid = match &tag {
+            b"glyf" | b"loca" => version == 0 || version == 3,
+            b"hmtx" => version == 0 || version == 1,
+            _ => version == 0,
+        };
+        if !valid {
+            return Err(format!(
+                "WOFF2 table '{}' uses unknown transform version {}",
+                tag_name(&tag),
+                version
+            ));
+        }
+        let stream_len = if transformed { dir.base128()? as usize } else { orig_len };
+        entries.push(DirEntry { tag, orig_len, stream_len, transformed });
+    }
+
+    let find = |tag: &[u8; 4]| entries.iter().position(|e| &e.tag == tag);
+    let glyf = find(b"glyf");
+    let loca = find(b"loca");
+    let glyf_transformed = glyf.is_some_and(|i| entries[i].transformed);
+    if glyf_transformed != loca.is_some_and(|i| entries[i].transformed) {
+        return Err("WOFF2 glyf and loca must be transformed together".to_string());
+    }
+    if loca.is_some_and(|i| entries[i].transformed && entries[i].stream_len != 0) {
+        return Err("WOFF2 transformed loca must be empty".to_string());
+    }
+
+    let start = HEADER_LEN + dir.pos;
+    let compressed = start
+        .checked_add(compressed_len)
+        .and_then(|end| data.get(start..end))
+        .ok_or_else(|| "WOFF2 compressed data extends past the end of the file".to_string())?;
+    let expected: usize = entries.iter().map(|e| e.stream_len).sum();
+    let stream = decompress(compressed, expected)?;
+
+    let mut raw = Vec::with_capacity(entries.len());
+    let mut offset = 0;
+    for entry in &entries {
+        raw.push(&stream[offset..offset + entry.stream_len]);
+        offset += entry.stream_len;
+    }
+
+    let mut rebuilt = vec![None; entries.len()];
+    let mut x_mins = None;
+    if let (Some(glyf), Some(loca), true) = (glyf, loca, glyf_transformed) {
+        let glyphs = rebuild_glyf(raw[glyf])?;
+        if glyphs.loca.len() != entries[loca].orig_len {
+            return Err(format!(
+                "WOFF2 loca rebuilt to {} bytes, directory declares {}",
+                glyphs.loca.len(),
+                entries[loca].orig_len
+            ));
+        }
+        rebuilt[glyf] = Some(glyphs.glyf);
+        rebuilt[loca] = Some(glyphs.loca);
+        x_mins = Some(glyphs.x_mins);
+    }
+    if let Some(hmtx) = find(b"hmtx").filter(|&i| entries[i].transformed) {
+        let x_mins = x_mins
+            .as_deref()
+            .ok_or_else(|| "WOFF2 hmtx is transformed but glyf is not".to_string())?;
+        let num_glyphs = table_u16(&entries, &raw, b"maxp", 4)? as usize;
+        let num_hmetrics = table_u16(&entries, &raw, b"hhea", 34)? as usize;
+        if num_glyphs != x_mins.len() {
+            return Err(format!(
+                "WOFF2 maxp declares {} glyphs, glyf has {}",
+                num_glyphs,
+                x_mins.len()
+            ));
+        }
+        rebuilt[hmtx] = Some(rebuild_hmtx(raw[hmtx], num_hmetrics, x_mins)?);
+    }
+
+    let mut tables: Vec<TableEntry> = entries
+        .iter()
+        .zip(raw)
+        .zip(rebuilt)
+        .map(|((entry, raw), rebuilt)| {
+            let data = rebuilt.unwrap_or_else(|| raw.to_vec());
+            TableEntry { tag: entry.tag, checksum: checksum(&entry.tag, &data), data }
+        })
+        .collect();
+    // SFNT table records are sorted by tag; a WOFF2 directory needn't be
+    tables.sort_by_key(|table| table.tag);
+    Ok(build_sfnt(flavor, &tables))
+}
+
+/// Inflate no further than the tables' declared lengths, so a small file
+/// can't expand without bound.
+fn decompress(compressed: &[u8], expected: usize) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    brotli_decompressor::Decompressor::new(compressed, 4096)
+        .take(expected as u64 + 1)
+        .read_to_end(&mut out)
+        .map_err(|e| format!("failed to decompress WOFF2 data: {}", e))?;
+    if out.len() != expected {
+        return Err(format!(
+            "WOFF2 data decompresses to {} bytes, its tables declare {}",
+            if out.len() > expected { format!("more than {}", expected) } else { out.len().to_string() },
+            expected
+        ));
+    }
+    Ok(out)
+}
+
+/// A big-endian u16 at `offset` in an untransformed table
+fn table_u16(entries: &[DirEntry], raw: &[&[u8]], tag: &[u8; 4], offset: usize) -> Result<u16, String> {
+    let index = entries
+        .iter()
+        .position(|e| &e.tag == tag)
+        .ok_or_else(|| format!("WOFF2 font has transformed hmtx but no '{}' table", tag_name(tag)))?;
+    Stream::new("table", raw[index])
+        .bytes(offset + 2)
+        .map(|b| u16::from_be_bytes([b[offset], b[offset + 1]]))
+        .map_err(|_| format!("truncated WOFF2 '{}' table", tag_name(tag)))
+}
+
+/// The SFNT table checksum; `head`'s excludes its checkSumAdjustment.
+fn checksum(tag: &[u8; 4], data: &[u8]) -> u32 {
+    let mut sum = 0u32;
+    for (i, chunk) in data.chunks(4).enumerate() {
+        if tag == b"head" && i == 2 {
+            continue;
+        }
+        let mut word = [0u8; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum = sum.wrapping_add(u32::from_be_bytes(word));
+    }
+    sum
+}
+
+struct RebuiltGlyf {
+    glyf: Vec<u8>,
+    loca: Vec<u8>,
+    /// Each glyph's xMin, for side bearings a transformed hmtx leaves out
+    x_mins: Vec<i16>,
+}
+
+fn rebuild_glyf(data: &[u8]) -> Result<RebuiltGlyf, String> {
+    let mut header = Stream::new("glyf header", data);
+    header.u16()?; // reserved
+    let option_flags = header.u16()?;
+    let num_glyphs = header.u16()? as usize;
+    let index_format = header.u16()?;
+    let mut sizes = [0usize; 7];
+    for size in &mut sizes {
+        *size = header.u32()? as usize;
+    }
+
+    let mut rest = &data[header.pos..];
+    let mut substream = |name: &'static str, len: usize| {
+        if len > rest.len() {
+            return Err(format!("WOFF2 glyf {} extends past the table", name));
+        }
+        let (head, tail) = rest.split_at(len);
+        rest = tail;
+        Ok(Stream::new(name, head))
+    };
+    let mut contour_counts = substream("contour count stream", sizes[0])?;
+    let mut point_counts = substream("point count stream", sizes[1])?;
+    let mut flags = substream("flag stream", sizes[2])?;
+    let mut glyphs = substream("glyph stream", sizes[3])?;
+    let mut composites = substream("composite stream", sizes[4])?;
+    let mut bboxes = substream("bbox stream", sizes[5])?;
+    let mut instructions = substream("instruction stream", sizes[6])?;
+    let bbox_bitmap = bboxes.bytes(num_glyphs.div_ceil(32) * 4)?;
+    let overlap_bitmap = match option_flags & 1 {
+        0 => None,
+        _ => Some(substream("overlap bitmap", num_glyphs.div_ceil(8))?.data),
+    };
+    let has_bit = |bitmap: &[u8], glyph: usize| bitmap[glyph >> 3] & (0x80 >> (glyph & 7)) != 0;
+
+    let mut glyf = Vec::new();
+    let mut offsets = Vec::with_capacity(num_glyphs + 1);
+    let mut x_mins = Vec::with_capacity(num_glyphs);
+    for glyph in 0..num_glyphs {
+        offsets.push(glyf.len());
+        let explicit_bbox = has_bit(bbox_bitmap, glyph);
+        match contour_counts.i16()? {
+            0 => {
+                if explicit_bbox {
+                    return Err(format!("WOFF2 empty glyph {} has a bounding box", glyph));
+                }
+                x_mins.push(0);
+            }
+            -1 => {
+                if !explicit_bbox {
+                    return Err(format!("WOFF2 composite glyph {} has no bounding box", glyph));
+                }
+                let bbox = bboxes.bytes(8)?;
+                let (components, has_instructions) = read_composite(&mut composites)?;
+                glyf.extend_from_slice(&(-1i16).to_be_bytes());
+                glyf.extend_from_slice(bbox);
+                glyf.extend_from_slice(components);
+                if has_instructions {
+                    let len = glyphs.u255_16()?;
+                    glyf.extend_from_slice(&len.to_be_bytes());
+                    glyf.extend_from_slice(instructions.bytes(usize::from(len))?);
+                }
+                x_mins.push(i16::from_be_bytes([bbox[0], bbox[1]]));
+            }
+            contours if contours > 0 => {
+                let mut end_points = Vec::with_capacity(contours as usize);
+                let mut total = 0usize;
+                for _ in 0..contours {
+                    total += usize::from(point_counts.u255_16()?);
+                    let end = total
+                        .checked_sub(1)
+                        .and_then(|end| u16::try_from(end).ok())
+                        .ok_or_else(|| format!("WOFF2 glyph {} has invalid contour point counts", glyph))?;
+                    end_points.push(end);
+                }
+
+                let mut points = Vec::with_capacity(total);
+                let (mut x, mut y) = (0i32, 0i32);
+                for _ in 0..total {
+                    let flag = flags.u8()?;
+                    let (dx, dy) = read_triplet(flag & 0x7F, &mut glyphs)?;
+                    x += dx;
+                    y += dy;
+                    if i16::try_from(x).is_err() || i16::try_from(y).is_err() {
+                        return Err(format!("WOFF2 glyph {} has a point outside the 16-bit range", glyph));
+                    }
+                    points.push((x, y, flag & 0x80 == 0));
+                }
+                let instruction_len = glyphs.u255_16()?;
+
+                let bbox = if explicit_bbox {
+                    let b = bboxes.bytes(8)?;
+                    [0, 2, 4, 6].map(|i| i16::from_be_bytes([b[i], b[i + 1]]))
+                } else {
+                    let xs = points.iter().map(|p| p.0 as i16);
+                    let ys = points.iter().map(|p| p.1 as i16);
+                    [
+                        xs.clone().min().unwrap_or(0),
+                        ys.clone().min().unwrap_or(0),
+                        xs.max().unwrap_or(0),
+                        ys.max().unwrap_or(0),
+                    ]
+                };
+                glyf.extend_from_slice(&contours.to_be_bytes());
+                for v in bbox {
+                    glyf.extend_from_slice(&v.to_be_bytes());
+                }
+                for end in end_points {
+                    glyf.extend_from_slice(&end.to_be_bytes());
+                }
+                glyf.extend_from_slice(&instruction_len.to_be_bytes());
+                glyf.extend_from_slice(instructions.bytes(usize::from(instruction_len))?);
+                let overlap = overlap_bitmap.is_some_and(|bitmap| has_bit(bitmap, glyph));
+                write_points(&mut glyf, &points, overlap);
+                x_mins.push(bbox[0]);
+            }
+            contours => {
+                return Err(format!("WOFF2 glyph {} has {} contours", glyph, contours));
+            }
+        }
+        glyf.resize(glyf.len().next_multiple_of(4), 0);
+    }
+    offsets.push(glyf.len());
+
+    let mut loca = Vec::with_capacity(offsets.len() * 4);
+    for offset in offsets {
+        if index_format == 0 {
+            let short = u16::try_from(offset / 2)
+                .map_err(|_| "WOFF2 glyf is too large for short loca offsets".to_string())?;
+            loca.extend_from_slice(&short.to_be_bytes());
+        } else {
+            loca.extend_from_slice(&(offset as u32).to_be_bytes());
+        }
+    }
+    Ok(RebuiltGlyf { glyf, loca, x_mins })
+}
+
+/// One point's coordinate deltas from the glyph stream, in the encoding
+/// the point's flag byte (less its on-curve bit) selects.
+fn read_triplet(flag: u8, glyphs: &mut Stream) -> Result<(i32, i32), String> {
+    let flag = i32::from(flag);
+    let with_sign = |flag: i32, base: i32| if flag & 1 != 0 { base } else { -base };
+    let b = |bytes: &[u8], i: usize| i32::from(bytes[i]);
+    Ok(match flag {
+        0..=9 => {
+            let d = glyphs.bytes(1)?;
+            (0, with_sign(flag, ((flag & 14) << 7) + b(d, 0)))
+        }
+        10..=19 => {
+            let d = glyphs.bytes(1)?;
+            (with_sign(flag, (((flag - 10) & 14) << 7) + b(d, 0)), 0)
+        }
+        20..=83 => {
+            let d = glyphs.bytes(1)?;
+            let b0 = flag - 20;
+            (
+                with_sign(flag, 1 + (b0 & 0x30) + (b(d, 0) >> 4)),
+                with_sign(flag >> 1, 1 + ((b0 & 0x0C) << 2) + (b(d, 0) & 0x0F)),
+            )
+        }
+        84..=119 => {
+            let d = glyphs.bytes(2)?;
+            let b0 = flag - 84;
+            (
+                with_sign(flag, 1 + ((b0 / 12) << 8) + b(d, 0)),
+                with_sign(flag >> 1, 1 + (((b0 % 12) >> 2) << 8) + b(d, 1)),
+            )
+        }
+        120..=123 => {
+            let d = glyphs.bytes(3)?;
+            (
+                with_sign(flag, (b(d, 0) << 4) + (b(d, 1) >> 4)),
+                with_sign(flag >> 1, ((b(d, 1) & 0x0F) << 8) + b(d, 2)),
+            )
+        }
+        _ => {
+            let d = glyphs.bytes(4)?;
+            (
+                with_sign(flag, (b(d, 0) << 8) + b(d, 1)),
+                with_sign(flag >> 1, (b(d, 2) << 8) + b(d, 3)),
+            )
+        }
+    })
+}
+
+/// Write a simple glyph's flags and coordinates in the standard `glyf`
+/// encoding, one flag per point.
+fn write_points(out: &mut Vec<u8>, points: &[(i32, i32, bool)], overlap: bool) {
+    let mut flags = Vec::with_capacity(points.len());
+    let mut xs = Vec::new();
+    let mut ys = Vec::new();
+    let (mut last_x, mut last_y) = (0, 0);
+    for (i, &(x, y, on_curve)) in points.iter().enumerate() {
+        let mut flag = if on_curve { ON_CURVE_POINT } else { 0 };
+        if i == 0 && overlap {
+            flag |= OVERLAP_SIMPLE;
+        }
+        flag |= write_delta(&mut xs, x - last_x, X_SHORT_VECTOR, X_IS_SAME_OR_POSITIVE);
+        flag |= write_delta(&mut ys, y - last_y, Y_SHORT_VECTOR, Y_IS_SAME_OR_POSITIVE);
+        flags.push(flag);
+        (last_x, last_y) = (x, y);
+    }
+    out.extend_from_slice(&flags);
+    out.extend_from_slice(&xs);
+    out.extend_from_slice(&ys);
+}
+
+fn write_delta(out: &mut Vec<u8>, delta: i32, short: u8, same_or_positive: u8) -> u8 {
+    if delta == 0 {
+        same_or_positive
+    } else if delta.abs() < 256 {
+        out.push(delta.unsigned_abs() as u8);
+        short | if delta > 0 { same_or_positive } else { 0 }
+    } else {
+        // Both ends are in i16 range, so the wrapped delta adds back exactly
+        out.extend_from_slice(&(delta as i16).to_be_bytes());
+        0
+    }
+}
+
+/// A composite glyph's component records, and whether they ask for
+/// instructions after them.
+fn read_composite<'a>(composites: &mut Stream<'a>) -> Result<(&'a [u8], bool), String> {
+    let start = composites.pos;
+    let mut has_instructions = false;
+    loop {
+        let flags = composites.u16()?;
+        has_instructions |= flags & WE_HAVE_INSTRUCTIONS != 0;
+        let mut len = 2 + if flags & ARG_1_AND_2_ARE_WORDS != 0 { 4 } else { 2 };
+        if flags & WE_HAVE_A_SCALE != 0 {
+            len += 2;
+        } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+            len += 4;
+        } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+            len += 8;
+        }
+        composites.bytes(len)?;
+        if flags & MORE_COMPONENTS == 0 {
+            return Ok((&composites.data[start..composites.pos], has_instructions));
+        }
+    }
+}
+
+fn rebuild_hmtx(data: &[u8], num_hmetrics: usize, x_mins: &[i16]) -> Result<Vec<u8>, String> {
+    let mut stream = Stream::new("hmtx", data);
+    let flags = stream.u8()?;
+    // Bit 0: proportional side bearings left out; bit 1: monospaced ones
+    if flags & 0xFC != 0 || flags & 0x03 == 0 {
+        return Err(format!("WOFF2 hmtx has invalid transform flags {:#04x}", flags));
+    }
+    if num_hmetrics == 0 || num_hmetrics > x_mins.len() {
+        return Err(format!(
+            "WOFF2 hhea declares {} horizontal metrics for {} glyphs",
+            num_hmetrics,
+            x_mins.len()
+        ));
+    }
+    let advances = (0..num_hmetrics).map(|_| stream.u16()).collect::<Result<Vec<_>, _>>()?;
+    let mut out = Vec::with_capacity(num_hmetrics * 4 + (x_mins.len() - num_hmetrics) * 2);
+    for (glyph, &x_min) in x_mins.iter().enumerate() {
+        let omitted = if glyph < num_hmetrics { flags & 1 } else { flags & 2 };
+        let lsb = if omitted != 0 { x_min } else { stream.i16()? };
+        if let Some(advance) = advances.get(glyph) {
+            out.extend_from_slice(&advance.to_be_bytes());
+        }
+        out.extend_from_slice(&lsb.to_be_bytes());
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CFF_FONT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/IBMPlexSans-Regular.otf");
+    const GLYF_FONT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/TestCJK.ttf");
+
+    fn u16_at(data: &[u8], pos: usize) -> u16 {
+        u16::from_be_bytes([data[pos], data[pos + 1]])
+    }
+
+    fn u32_at(data: &[u8], pos: usize) -> u32 {
+        u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+    }
+
+    fn sfnt_tables(sfnt: &[u8]) -> Vec<([u8; 4], &[u8])> {
+        (0..u16_at(sfnt, 4) as usize)
+            .map(|i| {
+                let rec = 12 + i * 16;
+                let offset = u32_at(sfnt, rec + 8) as usize;
+                let len = u32_at(sfnt, rec + 12) as usize;
+                (sfnt[rec..rec + 4].try_into().unwrap(), &sfnt[offset..offset + len])
+            })
+            .collect()
+    }
+
+    fn write_base128(out: &mut Vec<u8>, value: u32) {
+        let mut groups = vec![(value & 0x7F) as u8];
+        let mut rest = value >> 7;
+        while rest > 0 {
+            groups.push((rest & 0x7F) as u8 | 0x80);
+            rest >>= 7;
+        }
+        out.extend(groups.iter().rev());
+    }
+
+    fn write_255_u16(out: &mut Vec<u8>, value: u16) {
+        match value {
+            0..=252 => out.push(value as u8),
+            253..=505 => out.extend([255, (value - 253) as u8]),
+            506..=761 => out.extend([254, (value - 506) as u8]),
+            _ => {
+                out.push(253);
+                out.extend(value.to_be_bytes());
+            }
+        }
+    }
+
+    /// The reference encoder's triplet choice for one point delta
+    fn write_triplet(flags: &mut Vec<u8>, glyphs: &mut Vec<u8>, on_curve: bool, x: i32, y: i32) {
+        let (ax, ay) = (x.abs(), y.abs());
+        let on = if on_curve { 0 } else { 128 };
+        let x_sign = i32::from(x >= 0);
+        let y_sign = i32::from(y >= 0);
+        let xy_signs = x_sign + 2 * y_sign;
+        let flag = if x == 0 && ay < 1280 {
+            glyphs.push((ay & 0xFF) as u8);
+            ((ay & 0xF00) >> 7) + y_sign
+        } else if y == 0 && ax < 1280 {
+            glyphs.push((ax & 0xFF) as u8);
+            10 + ((ax & 0xF00) >> 7) + x_sign
+        } else if ax < 65 && ay < 65 {
+            glyphs.push(((((ax - 1) & 0xF) << 4) | ((ay - 1) & 0xF)) as u8);
+            20 + ((ax - 1) & 0x30) + (((ay - 1) & 0x30) >> 2) + xy_signs
+        } else if ax < 769 && ay < 769 {
+            glyphs.extend([((ax - 1) & 0xFF) as u8, ((ay - 1) & 0xFF) as u8]);
+            84 + 12 * (((ax - 1) & 0x300) >> 8) + (((ay - 1) & 0x300) >> 6) + xy_signs
+        } else if ax < 4096 && ay < 4096 {
+            glyphs.extend([(ax >> 4) as u8, (((ax & 0xF) << 4) | (ay >> 8)) as u8, (ay & 0xFF) as u8]);
+            120 + xy_signs
+        } else {
+            glyphs.extend([(ax >> 8) as u8, (ax & 0xFF) as u8, (ay >> 8) as u8, (ay & 0xFF) as u8]);
+            124 + xy_signs
+        };
+        flags.push(on + flag as u8);
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Glyph {
+        Empty,
+        Simple { bbox: Vec<u8>, end_points: Vec<u16>, instructions: Vec<u8>, points: Vec<(i32, i32, bool)> },
+        Composite { bbox: Vec<u8>, components: Vec<u8>, instructions: Vec<u8> },
+    }
+
+    fn parse_glyph(data: &[u8]) -> Glyph {
+        if data.is_empty() {
+            return Glyph::Empty;
+        }
+        let contours = u16_at(data, 0) as i16;
+        let bbox = data[2..10].to_vec();
+        if contours < 0 {
+            let mut stream = Stream::new("composite", &data[10..]);
+            let (components, has_instructions) = read_composite(&mut stream).unwrap();
+            let instructions = if has_instructions {
+                let len = stream.u16().unwrap() as usize;
+                stream.bytes(len).unwrap().to_vec()
+            } else {
+                Vec::new()
+            };
+            return Glyph::Composite { bbox, components: components.to_vec(), instructions };
+        }
+        let end_points: Vec<u16> = (0..contours as usize).map(|i| u16_at(data, 10 + 2 * i)).collect();
+        let mut pos = 10 + 2 * contours as usize;
+        let instruction_len = u16_at(data, pos) as usize;
+        let instructions = data[pos + 2..pos + 2 + instruction_len].to_vec();
+        pos += 2 + instruction_len;
+        let total = end_points.last().map_or(0, |&end| end as usize + 1);
+        let mut flags = Vec::new();
+        while flags.len() < total {
+            let flag = data[pos];
+            pos += 1;
+            flags.push(flag);
+            if flag & 0x08 != 0 {
+                for _ in 0..data[pos] {
+                    flags.push(flag);
+                }
+                pos += 1;
+            }
+        }
+        let mut coordinate = |flag: u8, short: u8, same_or_positive: u8| -> i32 {
+            if flag & short != 0 {
+                pos += 1;
+                let v = i32::from(data[pos - 1]);
+                if flag & same_or_positive != 0 { v } else { -v }
+            } else if flag & same_or_positive != 0 {
+                0
+            } else {
+                pos += 2;
+                i32::from(u16_at(data, pos - 2) as i16)
+            }
+        };
+        let mut points = Vec::new();
+        let mut x = 0;
+        for &flag in &flags {
+            x += coordinate(flag, X_SHORT_VECTOR, X_IS_SAME_OR_POSITIVE);
+            points.push((x, 0, flag & ON_CURVE_POINT != 0));
+        }
+        let mut y = 0;
+        for (point, &flag) in points.iter_mut().zip(&flags) {
+            y += coordinate(flag, Y_SHORT_VECTOR, Y_IS_SAME_OR_POSITIVE);
+            point.1 = y;
+        }
+        Glyph::Simple { bbox, end_points, instructions, points }
+    }
+
+    fn computed_bbox(points: &[(i32, i32, bool)]) -> Vec<u8> {
+        let min_x = points.iter().map(|p| p.0).min().unwrap();
+        let min_y = points.iter().map(|p| p.1).min().unwrap();
+        let max_x = points.iter().map(|p| p.0).max().unwrap();
+        let max_y = points.iter().map(|p| p.1).max().unwrap();
+        [min_x, min_y, max_x, max_y].iter().flat_map(|&v| (v as i16).to_be_bytes()).collect()
+    }
+
+    /// The transformed form of a `glyf` table whose glyphs start at `offsets`
+    fn transform_glyf(glyf: &[u8], offsets: &[usize], index_format: u16) -> Vec<u8> {
+        let num_glyphs = offsets.len() - 1;
+        let mut streams: [Vec<u8>; 7] = Default::default();
+        let [contour_counts, point_counts, flags, glyphs, composites, bboxes, instructions] = &mut streams;
+        let mut bbox_bitmap = vec![0u8; num_glyphs.div_ceil(32) * 4];
+        for glyph in 0..num_glyphs {
+            match parse_glyph(&glyf[offsets[glyph]..offsets[glyph + 1]]) {
+                Glyph::Empty => contour_counts.extend(0i16.to_be_bytes()),
+                Glyph::Simple { bbox, end_points, instructions: code, points } => {
+                    contour_counts.extend((end_points.len() as i16).to_be_bytes());
+                    let mut start = 0;
+                    for &end in &end_points {
+                        write_255_u16(point_counts, end + 1 - start);
+                        start = end + 1;
+                    }
+                    let (mut x, mut y) = (0, 0);
+                    for &(px, py, on_curve) in &points {
+                        write_triplet(flags, glyphs, on_curve, px - x, py - y);
+                        (x, y) = (px, py);
+                    }
+                    write_255_u16(glyphs, code.len() as u16);
+                    instructions.extend(&code);
+                    if bbox != computed_bbox(&points) {
+                        bbox_bitmap[glyph >> 3] |= 0x80 >> (glyph & 7);
+                        bboxes.extend(&bbox);
+                    }
+                }
+                Glyph::Composite { bbox, components, instructions: code } => {
+                    contour_counts.extend((-1i16).to_be_bytes());
+                    composites.extend(&components);
+                    if !code.is_empty() {
+                        write_255_u16(glyphs, code.len() as u16);
+                        instructions.extend(&code);
+                    }
+                    bbox_bitmap[glyph >> 3] |= 0x80 >> (glyph & 7);
+                    bboxes.extend(&bbox);
+                }
+            }
+        }
+        bbox_bitmap.extend(std::mem::take(bboxes));
+        *bboxes = bbox_bitmap;
+
+        let mut out = Vec::new();
+        out.extend(0u16.to_be_bytes());
+        out.extend(0u16.to_be_bytes());
+        out.extend((num_glyphs as u16).to_be_bytes());
+        out.extend(index_format.to_be_bytes());
+        for stream in &streams {
+            out.extend((stream.len() as u32).to_be_bytes());
+        }
+        for stream in &streams {
+            out.extend(stream);
+        }
+        out
+    }
+
+    fn loca_offsets(loca: &[u8], index_format: u16) -> Vec<usize> {
+        if index_format == 0 {
+            (0..loca.len() / 2).map(|i| u16_at(loca, 2 * i) as usize * 2).collect()
+        } else {
+            (0..loca.len() / 4).map(|i| u32_at(loca, 4 * i) as usize).collect()
+        }
+    }
+
+    /// Wrap an SFNT font as WOFF2, transforming glyf, loca and hmtx when
+    /// `transform` is set.
+    fn encode_woff2(sfnt: &[u8], transform: bool) -> Vec<u8> {
+        let tables = sfnt_tables(sfnt);
+        let table = |tag: &[u8; 4]| tables.iter().find(|(t, _)| t == tag).map(|(_, data)| *data);
+        let mut dir = Vec::new();
+        let mut stream = Vec::new();
+        for &(tag, data) in &tables {
+            let index = KNOWN_TAGS.iter().position(|&known| known == &tag);
+            let transformed = transform && matches!(&tag, b"glyf" | b"loca" | b"hmtx");
+            let version = match (&tag, transformed) {
+                (b"glyf" | b"loca", false) => 3,
+                (b"hmtx", true) => 1,
+                _ => 0,
+            };
+            dir.push((version << 6) | index.map_or(0x3F, |i| i as u8));
+            if index.is_none() {
+                dir.extend(tag);
+            }
+            write_base128(&mut dir, data.len() as u32);
+            let stored = match (&tag, transformed) {
+                (b"glyf", true) => {
+                    let index_format = u16_at(table(b"head").unwrap(), 50);
+                    let offsets = loca_offsets(table(b"loca").unwrap(), index_format);
+                    transform_glyf(data, &offsets, index_format)
+                }
+                (b"loca", true) => Vec::new(),
+                (b"hmtx", true) => {
+                    let num_hmetrics = u16_at(table(b"hhea").unwrap(), 34) as usize;
+                    let index_format = u16_at(table(b"head").unwrap(), 50);
+                    let glyf = table(b"glyf").unwrap();
+                    let x_mins: Vec<i16> = loca_offsets(table(b"loca").unwrap(), index_format)
+                        .windows(2)
+                        .map(|w| if w[0] == w[1] { 0 } else { u16_at(glyf, w[0] + 2) as i16 })
+                        .collect();
+                    let lsbs: Vec<i16> = (0..x_mins.len())
+                        .map(|i| if i < num_hmetrics { 4 * i + 2 } else { 2 * i + 2 * num_hmetrics })
+                        .map(|pos| u16_at(data, pos) as i16)
+                        .collect();
+                    // Leave out each run of side bearings that all equal xMin
+                    let omit = |run: std::ops::Range<usize>| run.into_iter().all(|i| lsbs[i] == x_mins[i]);
+                    let flags = u8::from(omit(0..num_hmetrics)) | u8::from(omit(num_hmetrics..x_mins.len())) << 1;
+                    let mut out = vec![flags];
+                    out.extend((0..num_hmetrics).flat_map(|i| data[4 * i..4 * i + 2].to_vec()));
+                    for (i, lsb) in lsbs.iter().enumerate() {
+                        let omitted = if i < num_hmetrics { flags & 1 } else { flags & 2 };
+                        if omitted == 0 {
+                            out.extend(lsb.to_be_bytes());
+                        }
+                    }
+                    out
+                }
+                _ => data.to_vec(),
+            };
+            if transformed {
+                write_base128(&mut dir, stored.len() as u32);
+            }
+            stream.extend(stored);
+        }
+        let mut compressed = Vec::new();
+        let params = brotli::enc::BrotliEncoderParams::default();
+        brotli::BrotliCompress(&mut &stream[..], &mut compressed, &params).unwrap();
+
+        let mut out = Vec::new();
+        out.extend(b"wOF2");
+        out.extend(&sfnt[0..4]);
+        let total_len = HEADER_LEN + dir.len() + compressed.len();
+        out.extend((total_len as u32).to_be_bytes());
+        out.extend((tables.len() as u16).to_be_bytes());
+        out.extend([0, 0]);
+        out.extend((sfnt.len() as u32).to_be_bytes());
+        out.extend((compressed.len() as u32).to_be_bytes());
+        out.resize(HEADER_LEN, 0);
+        out.extend(dir);
+        out.extend(compressed);
+        out
+    }
+
+    #[test]
+    fn untransformed_font_round_trips() {
+        let sfnt = std::fs::read(CFF_FONT).unwrap();
+        let decoded = crate::woff::to_sfnt(encode_woff2(&sfnt, false)).unwrap();
+        let original = ttf_parser::Face::parse(&sfnt, 0).unwrap();
+        let face = ttf_parser::Face::parse(&decoded, 0).unwrap();
+        assert_eq!(face.number_of_glyphs(), original.number_of_glyphs());
+        assert_eq!(face.glyph_index('A'), original.glyph_index('A'));
+        for (tag, data) in sfnt_tables(&sfnt) {
+            let (_, decoded) = sfnt_tables(&decoded).into_iter().find(|(t, _)| *t == tag).unwrap();
+            assert_eq!(decoded, data, "{}", tag_name(&tag));
+        }
+    }
+
+    #[test]
+    fn transformed_glyf_and_hmtx_round_trip() {
+        let sfnt = std::fs::read(GLYF_FONT).unwrap();
+        let decoded = crate::woff::to_sfnt(encode_woff2(&sfnt, true)).unwrap();
+        let original = sfnt_tables(&sfnt);
+        let rebuilt = sfnt_tables(&decoded);
+        let table = |tables: &[([u8; 4], &[u8])], tag: &[u8; 4]| tables.iter().find(|(t, _)| t == tag).unwrap().1.to_vec();
+        assert_eq!(table(&rebuilt, b"hmtx"), table(&original, b"hmtx"));
+
+        let index_format = u16_at(&table(&original, b"head"), 50);
+        let glyphs = |tables: &[([u8; 4], &[u8])]| {
+            let glyf = table(tables, b"glyf");
+            let offsets = loca_offsets(&table(tables, b"loca"), index_format);
+            offsets.windows(2).map(|w| parse_glyph(&glyf[w[0]..w[1]])).collect::<Vec<_>>()
+        };
+        assert_eq!(glyphs(&rebuilt), glyphs(&original));
+
+        let face = ttf_parser::Face::parse(&decoded, 0).unwrap();
+        let original = ttf_parser::Face::parse(&sfnt, 0).unwrap();
+        for id in 0..face.number_of_glyphs() {
+            let id = ttf_parser::GlyphId(id);
+            assert_eq!(face.glyph_bounding_box(id), original.glyph_bounding_box(id));
+            assert_eq!(face.glyph_hor_advance(id), original.glyph_hor_advance(id));
+        }
+    }
+
+    #[test]
+    fn glyph_transform_covers_every_encoding() {
+        // A simple glyph whose deltas hit each triplet range, with an
+        // explicit bbox, followed by an empty glyph and a composite with
+        // instructions
+        let points = [(0, 0), (0, -900), (-1100, 0), (40, -50), (700, 300), (-3000, 4000), (20000, -30000)];
+        let mut points_abs = Vec::new();
+        let (mut x, mut y) = (0, 0);
+        for (i, (dx, dy)) in points.into_iter().enumerate() {
+            x += dx;
+            y += dy;
+            points_abs.push((x, y, i % 3 != 1));
+        }
+        let mut simple = Vec::new();
+        simple.extend(2i16.to_be_bytes());
+        simple.extend([-20000i16, -30000, 20000, 30000].iter().flat_map(|v| v.to_be_bytes()));
+        simple.extend(2u16.to_be_bytes());
+        simple.extend(6u16.to_be_bytes());
+        simple.extend(3u16.to_be_bytes());
+        simple.extend([0xB0, 0x01, 0x2C]);
+        write_points(&mut simple, &points_abs, false);
+        simple.resize(simple.len().next_multiple_of(4), 0);
+
+        let mut composite = Vec::new();
+        composite.extend((-1i16).to_be_bytes());
+        composite.extend([0i16, 0, 100, 100].iter().flat_map(|v| v.to_be_bytes()));
+        let first = ARG_1_AND_2_ARE_WORDS | MORE_COMPONENTS | WE_HAVE_A_SCALE;
+        composite.extend(first.to_be_bytes());
+        composite.extend([0, 0, 0x01, 0x00, 0xFF, 0x00, 0x40, 0x00]);
+        composite.extend((WE_HAVE_A_TWO_BY_TWO | WE_HAVE_INSTRUCTIONS).to_be_bytes());
+        composite.extend([0, 0, 5, 6]);
+        composite.extend([0x40, 0, 0, 0, 0, 0, 0x40, 0]);
+        composite.extend(2u16.to_be_bytes());
+        composite.extend([0xB0, 0x00]);
+        composite.resize(composite.len().next_multiple_of(4), 0);
+
+        let glyf = [simple.clone(), composite.clone()].concat();
+        let offsets = [0, simple.len(), simple.len(), glyf.len()];
+        let rebuilt = rebuild_glyf(&transform_glyf(&glyf, &offsets, 0)).unwrap();
+        let rebuilt_offsets = loca_offsets(&rebuilt.loca, 0);
+        let parse = |glyf: &[u8], offsets: &[usize]| {
+            offsets.windows(2).map(|w| parse_glyph(&glyf[w[0]..w[1]])).collect::<Vec<_>>()
+        };
+        assert_eq!(parse(&rebuilt.glyf, &rebuilt_offsets), parse(&glyf, &offsets));
+        assert_eq!(rebuilt.x_mins, [-20000, 0, 0]);
+    }
+
+    #[test]
+    fn corrupt_stream_reports_decompression_failure() {
+        let mut woff2 = encode_woff2(&std::fs::read(CFF_FONT).unwrap(), false);
+        let len = woff2.len();
+        woff2[len - 64..].fill(0xFF);
+        let err = crate::woff::to_sfnt(woff2).unwrap_err();
+        assert!(err.contains("WOFF2 data"), "{}", err);
+    }
+
+    #[test]
+    fn truncated_glyph_stream_names_the_stream() {
+        let sfnt = std::fs::read(GLYF_FONT).unwrap();
+        let tables = sfnt_tables(&sfnt);
+        let table = |tag: &[u8; 4]| tables.iter().find(|(t, _)| t == tag).unwrap().1;
+        let index_format = u16_at(table(b"head"), 50);
+        let mut transformed = transform_glyf(table(b"glyf"), &loca_offsets(table(b"loca"), index_format), index_format);
+        // Claim one fewer byte of glyph stream, shifting it onto the next
+        let glyph_stream_len = u32_at(&transformed, 20);
+        transformed[20..24].copy_from_slice(&(glyph_stream_len - 1).to_be_bytes());
+        let err = rebuild_glyf(&transformed).err().unwrap();
+        assert!(err.contains("WOFF2 glyph stream"), "{}", err);
+    }
+
+    #[test]
+    fn collections_are_rejected() {
+        let mut woff2 = encode_woff2(&std::fs::read(CFF_FONT).unwrap(), false);
+        woff2[4..8].copy_from_slice(COLLECTION_FLAVOR);
+        let err = crate::woff::to_sfnt(woff2).unwrap_err();
+        assert!(err.contains("collections"), "{}", err);
+    }
+}